@@ -0,0 +1,3 @@
+ALTER TABLE graphs
+    DROP COLUMN IF EXISTS largest_component_fraction,
+    DROP COLUMN IF EXISTS num_components;
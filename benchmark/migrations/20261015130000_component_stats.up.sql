@@ -0,0 +1,5 @@
+-- Connectivity of the raw GIRG before it is reduced to its largest component
+ALTER TABLE graphs
+    ADD COLUMN num_components INTEGER CHECK (num_components > 0),
+    ADD COLUMN largest_component_fraction DOUBLE PRECISION
+        CHECK (largest_component_fraction > 0 AND largest_component_fraction <= 1);
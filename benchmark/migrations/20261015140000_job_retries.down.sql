@@ -0,0 +1,27 @@
+DROP FUNCTION IF EXISTS cleanup_stale_jobs(INTEGER, INTEGER);
+CREATE OR REPLACE FUNCTION cleanup_stale_jobs(timeout_hours INTEGER DEFAULT 2)
+RETURNS INTEGER AS $$
+DECLARE
+    cleaned_count INTEGER;
+BEGIN
+    UPDATE position_jobs 
+    SET status = 'pending', claimed_at = NULL, claimed_by_hostname = NULL,
+        error_message = COALESCE(error_message, '') || ' [Reset due to timeout]'
+    WHERE status = 'running' AND claimed_at < NOW() - INTERVAL '1 hour' * timeout_hours;
+    
+    GET DIAGNOSTICS cleaned_count = ROW_COUNT;
+    RETURN cleaned_count;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP INDEX IF EXISTS idx_position_jobs_retry;
+
+UPDATE position_jobs SET status = 'failed' WHERE status = 'failed-permanent';
+ALTER TABLE position_jobs DROP CONSTRAINT position_jobs_status_check;
+ALTER TABLE position_jobs ADD CONSTRAINT position_jobs_status_check
+    CHECK (status IN ('pending', 'running', 'completed', 'failed'));
+
+ALTER TABLE position_jobs
+    DROP COLUMN IF EXISTS error_history,
+    DROP COLUMN IF EXISTS next_eligible_at,
+    DROP COLUMN IF EXISTS attempts;
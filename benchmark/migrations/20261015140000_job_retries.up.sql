@@ -0,0 +1,32 @@
+-- Attempt tracking for position jobs. Failed jobs are re-claimed after an
+-- exponential backoff until they run out of attempts and become
+-- 'failed-permanent'.
+ALTER TABLE position_jobs
+    ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN next_eligible_at TIMESTAMPTZ,
+    ADD COLUMN error_history TEXT[] NOT NULL DEFAULT '{}';
+
+ALTER TABLE position_jobs DROP CONSTRAINT position_jobs_status_check;
+ALTER TABLE position_jobs ADD CONSTRAINT position_jobs_status_check
+    CHECK (status IN ('pending', 'running', 'completed', 'failed', 'failed-permanent'));
+
+CREATE INDEX idx_position_jobs_retry ON position_jobs (next_eligible_at) WHERE status = 'failed';
+
+-- Jobs whose worker died count as failed attempts as well
+DROP FUNCTION IF EXISTS cleanup_stale_jobs(INTEGER);
+CREATE FUNCTION cleanup_stale_jobs(timeout_hours INTEGER DEFAULT 2, max_attempts INTEGER DEFAULT 5)
+RETURNS INTEGER AS $$
+DECLARE
+    cleaned_count INTEGER;
+BEGIN
+    UPDATE position_jobs 
+    SET status = CASE WHEN attempts >= max_attempts THEN 'failed-permanent' ELSE 'pending' END,
+        claimed_at = NULL, claimed_by_hostname = NULL,
+        error_message = COALESCE(error_message, '') || ' [Reset due to timeout]',
+        error_history = array_append(error_history, 'Timed out on ' || COALESCE(claimed_by_hostname, 'unknown host'))
+    WHERE status = 'running' AND claimed_at < NOW() - INTERVAL '1 hour' * timeout_hours;
+    
+    GET DIAGNOSTICS cleaned_count = ROW_COUNT;
+    RETURN cleaned_count;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,9 @@
+-- Only the radius 1 test files fit the old one-file-per-result schema
+DELETE FROM tests WHERE radius != 1.0;
+
+ALTER TABLE tests
+    DROP CONSTRAINT unique_test_result;
+ALTER TABLE tests
+    DROP COLUMN radius;
+ALTER TABLE tests
+    ADD CONSTRAINT unique_test_result PRIMARY KEY (result_id);
@@ -0,0 +1,9 @@
+-- Radius factor the ground truth of a test file was computed with. A result
+-- can have test files for several radii; existing files are for radius 1.
+ALTER TABLE tests
+    ADD COLUMN radius DOUBLE PRECISION NOT NULL DEFAULT 1.0;
+
+ALTER TABLE tests
+    DROP CONSTRAINT unique_test_result;
+ALTER TABLE tests
+    ADD CONSTRAINT unique_test_result PRIMARY KEY (result_id, radius);
@@ -0,0 +1,2 @@
+ALTER TABLE measurements
+    DROP COLUMN IF EXISTS adaptive_samples;
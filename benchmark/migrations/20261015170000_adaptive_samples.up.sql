@@ -0,0 +1,4 @@
+-- Whether the fixed sample count of a measurement was extended because its
+-- samples varied too much
+ALTER TABLE measurements
+    ADD COLUMN adaptive_samples BOOLEAN NOT NULL DEFAULT FALSE;
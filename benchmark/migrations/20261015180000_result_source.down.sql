@@ -0,0 +1,2 @@
+ALTER TABLE position_results
+    DROP COLUMN IF EXISTS source;
@@ -0,0 +1,5 @@
+-- Where a position result was computed: 'daemon' for results of position
+-- jobs, 'external' for files registered with `register-result`
+ALTER TABLE position_results
+    ADD COLUMN source TEXT NOT NULL DEFAULT 'daemon'
+    CHECK (source IN ('daemon', 'external'));
@@ -0,0 +1,2 @@
+ALTER TABLE measurements
+    DROP COLUMN IF EXISTS wall_time_histogram;
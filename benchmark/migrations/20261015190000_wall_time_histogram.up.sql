@@ -0,0 +1,4 @@
+-- Query counts per log-spaced bucket of their own wall time, see
+-- perf_measurement::histogram_bucket. Only stored with --histograms
+ALTER TABLE measurements
+    ADD COLUMN wall_time_histogram BIGINT[];
@@ -0,0 +1 @@
+ALTER TABLE position_results DROP COLUMN optimizer_options;
@@ -0,0 +1,4 @@
+-- Optimizer settings a position result was computed with, as written by
+-- `EmbedderOptions::optimizer_json`. NULL for earlier results, which used
+-- Adam with beta1 = 0.9, beta2 = 0.999, epsilon = 1e-8 and no step clamp.
+ALTER TABLE position_results ADD COLUMN optimizer_options JSONB;
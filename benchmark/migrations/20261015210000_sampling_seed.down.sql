@@ -0,0 +1,10 @@
+-- Measurements of seeded queries would violate the old constraint
+DELETE FROM measurements WHERE sampling_seed IS NOT NULL;
+
+ALTER TABLE measurements
+    DROP CONSTRAINT unique_measurement;
+ALTER TABLE measurements
+    ADD CONSTRAINT unique_measurement
+    UNIQUE (code_state_id, result_id, iteration_number, benchmark_type, query_radius, hostname);
+
+ALTER TABLE measurements DROP COLUMN sampling_seed;
@@ -0,0 +1,11 @@
+-- Seed the queries of a measurement were drawn with, see `--sampling-seed`.
+-- NULL for evenly spaced queries, which all earlier measurements used.
+ALTER TABLE measurements ADD COLUMN sampling_seed BIGINT;
+
+-- Runs with different seeds, or with and without one, measure different
+-- queries. NULLS NOT DISTINCT keeps evenly spaced measurements unique.
+ALTER TABLE measurements
+    DROP CONSTRAINT unique_measurement;
+ALTER TABLE measurements
+    ADD CONSTRAINT unique_measurement
+    UNIQUE NULLS NOT DISTINCT (code_state_id, result_id, iteration_number, benchmark_type, query_radius, hostname, sampling_seed);
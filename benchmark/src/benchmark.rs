@@ -1,11 +1,14 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     ops::Deref,
     sync::Arc,
+    time::Duration,
 };
 
 use criterion::Criterion;
-use rembed::{Embedding, NodeId, dvec::DVec, graph::Graph, parsing::Iterations};
+use crossbeam::channel::Sender;
+use rand::{SeedableRng, rngs::SmallRng};
+use rembed::{Embedding, NodeId, dvec::DVec, graph::Graph, parsing::Iterations, query::IndexClone};
 use sqlx::{Pool, Postgres, Row};
 
 pub mod perf_measurement;
@@ -14,7 +17,13 @@ pub mod runner;
 
 pub mod distribution_bench;
 
+pub mod dashboard;
+
+pub mod graph_cache;
+
 use crate::{code_state::RepoCodeStateManager, pull_files};
+use dashboard::{Dashboard, ProgressReporter, QuietOutput};
+use graph_cache::{GRAPH_CACHE_BYTES, GraphCache};
 use runner::{BenchmarkResult, BenchmarkType, MeasurementResult};
 
 pub struct Testcase<'a, const D: usize> {
@@ -28,6 +37,18 @@ pub struct LoadData {
     pub repo_code_manager: RepoCodeStateManager,
     pub store: bool,
     pub allow_dirty: bool,
+    /// Store the wall time histogram of each measurement
+    pub histograms: bool,
+    /// Compare the neighbor sets of the exact structures before measuring,
+    /// see [`check_neighbor_sets`]
+    pub verify_consistency: bool,
+    /// Send criterion's console output to [`dashboard::CRITERION_LOG`]
+    pub quiet_criterion: bool,
+    /// Skip the graphs with more than this many nodes after preprocessing
+    pub max_nodes: Option<usize>,
+    /// Draw the queries at random with this seed instead of spacing them
+    /// evenly, see [`query_list_for_type`]
+    pub sampling_seed: Option<u64>,
 }
 
 impl LoadData {
@@ -41,9 +62,16 @@ impl LoadData {
             repo_code_manager,
             store: false,
             allow_dirty: false,
+            histograms: false,
+            verify_consistency: false,
+            quiet_criterion: false,
+            max_nodes: None,
+            sampling_seed: None,
         }
     }
 
+    /// Benchmarks the position results matching the filters and returns
+    /// every measurement, which is also stored in the database with `store`.
     #[allow(clippy::too_many_arguments)]
     pub async fn run_benchmarks(
         &self,
@@ -64,8 +92,9 @@ impl LoadData {
         structures: Option<Vec<String>>,
         dynamic_download: bool,
         fast: bool,
+        precision: Option<f64>,
         export_only: bool,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> Result<Vec<BenchmarkResult>, Box<dyn std::error::Error>> {
         let mut tx = self.pool.begin().await?;
 
         let query =
@@ -81,6 +110,7 @@ impl LoadData {
                 || deg_range.1 > 0
                 || ple_range.1 > 0.0
                 || alpha_range.1 > 0.0
+                || self.max_nodes.is_some()
             {
                 let mut conditions = vec![];
                 if dim_range.1 > 0 && dim_selection.is_some() {
@@ -150,6 +180,10 @@ impl LoadData {
                         alpha_range.0, alpha_range.1
                     ));
                 }
+                if let Some(max_nodes) = self.max_nodes {
+                    println!("Skipping graphs with more than {max_nodes} nodes (--max-nodes)");
+                    conditions.push(node_cap_condition(max_nodes));
+                }
 
                 let condition_str = conditions.join(" AND ");
                 let full_query = format!("{} WHERE {}", query, condition_str);
@@ -227,8 +261,16 @@ impl LoadData {
             position_results.len()
         );
         if position_results.is_empty() {
-            return Ok(());
+            return Ok(Vec::new());
         }
+        // Consecutive results of the same graph find it in the graph cache
+        let mut position_results = position_results;
+        position_results.sort_by_cached_key(|result| {
+            let graph_path: String = result.get("graph_path");
+            let embedding_dim: i32 = result.get("embedding_dim");
+            (graph_path, embedding_dim)
+        });
+        let graphs = Arc::new(GraphCache::new(GRAPH_CACHE_BYTES));
         let queue = crossbeam::queue::ArrayQueue::new(position_results.len());
         for result in position_results {
             queue.push(result).unwrap();
@@ -239,18 +281,30 @@ impl LoadData {
         } else {
             concurrency
         };
-        let prog = crate::create_progress_bar(queue.len());
+        let live = std::io::IsTerminal::is_terminal(&std::io::stdout());
+        let quiet = if self.quiet_criterion {
+            Some(QuietOutput::redirect(std::path::Path::new(
+                dashboard::CRITERION_LOG,
+            ))?)
+        } else {
+            None
+        };
+        let terminal = quiet.as_ref().map(QuietOutput::terminal).transpose()?;
+        let dashboard = Dashboard::spawn(queue.len(), concurrency, live, terminal);
 
         let queue = Arc::new(queue);
+        let (sender, receiver) = crossbeam::channel::unbounded();
         let mut handles = Vec::new();
 
-        for _ in 0..concurrency {
+        for worker in 0..concurrency {
             let queue = queue.clone();
             let benchmarks = benchmarks.clone();
             let structures = structures.clone();
             let data_directory = data_directory.clone();
             let load_data = self.clone();
-            let prog = prog.clone();
+            let progress = dashboard.reporter(worker);
+            let sender = sender.clone();
+            let graphs = graphs.clone();
 
             let handle = tokio::task::spawn_blocking(move || {
                 let handle = tokio::runtime::Handle::current();
@@ -258,6 +312,8 @@ impl LoadData {
 
                 handle.block_on(local.run_until(async move {
                     while let Some(result) = queue.pop() {
+                        let result_id: i64 = result.get("result_id");
+                        progress.started(result_id);
                         if let Err(e) = load_data
                             .bench_embedding(
                                 only_last_iteration,
@@ -265,14 +321,18 @@ impl LoadData {
                                 &structures,
                                 &data_directory,
                                 result,
+                                &graphs,
                                 fast,
+                                precision,
                                 export_only,
+                                &progress,
+                                &sender,
                             )
                             .await
                         {
                             println!("error while benchmarking {e}");
                         }
-                        prog.inc(1);
+                        progress.finished(result_id);
                     }
                 }));
             });
@@ -281,9 +341,21 @@ impl LoadData {
         }
 
         // Wait for all threads to complete
+        drop(sender);
         futures::future::join_all(handles).await;
+        dashboard.finish();
+        println!(
+            "Parsed {} graph files, reused parsed graphs {} times",
+            graphs.parsed(),
+            graphs.reused()
+        );
+        if let Some(quiet) = quiet {
+            // Restores stdout
+            drop(quiet);
+            println!("Criterion output written to {}", dashboard::CRITERION_LOG);
+        }
 
-        Ok(())
+        Ok(receiver.into_iter().collect())
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -294,8 +366,12 @@ impl LoadData {
         structures: &Option<Vec<String>>,
         data_directory: &str,
         result: sqlx::postgres::PgRow,
+        graphs: &GraphCache,
         fast: bool,
+        precision: Option<f64>,
         export_only: bool,
+        progress: &ProgressReporter,
+        results: &Sender<BenchmarkResult>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let mut c = Criterion::default().with_output_color(true).without_plots();
         let pos_path: String = result.get::<String, _>("pos_path");
@@ -304,12 +380,9 @@ impl LoadData {
         let graph_path = format!("{data_directory}/{}", graph_path);
         let embedding_dim: i32 = result.get("embedding_dim");
         let dim_hint: i32 = result.get("dim_hint");
-        let graph = rembed::graph::Graph::parse_from_edge_list_file(
-            &graph_path,
-            embedding_dim as usize,
-            dim_hint as usize,
-        )
-        .map_err(|e| format!("Failed to load graph from {}: {}", graph_path, e))?;
+        let graph = graphs
+            .get(&graph_path, embedding_dim as usize, dim_hint as usize)
+            .map_err(|e| format!("Failed to load graph from {}: {}", graph_path, e))?;
 
         load_and_run_dynamic(
             embedding_dim as u8,
@@ -322,7 +395,10 @@ impl LoadData {
                 structures,
                 load_data: self,
                 fast,
+                precision,
                 export_only,
+                progress,
+                results,
             },
             &mut c,
         )
@@ -330,9 +406,92 @@ impl LoadData {
         Ok(())
     }
 
+    /// Builds every structure on the embeddings of `graph_id` at each of
+    /// `dims` to show how construction time grows with the dimension. Uses
+    /// the last iteration of one embedding per dimension; the files have to
+    /// be pulled already.
+    pub async fn construction_scaling(
+        &self,
+        graph_id: i64,
+        dims: &[usize],
+        structures: &Option<Vec<String>>,
+        fast: bool,
+        precision: Option<f64>,
+    ) -> Result<ConstructionScaling, Box<dyn std::error::Error>> {
+        if let Some(dim) = dims.iter().find(|dim| !BENCHMARK_DIMS.contains(dim)) {
+            return Err(format!("dim {dim} is not compiled into the benchmarks").into());
+        }
+        let dims: Vec<i32> = dims.iter().map(|&dim| dim as i32).collect();
+        let results = sqlx::query!(
+            r#"
+            SELECT DISTINCT ON (embedding_dim)
+                embedding_dim, dim_hint, position_results.file_path as pos_path, graphs.file_path as graph_path
+            FROM position_results
+            JOIN graphs USING (graph_id)
+            WHERE graph_id = $1 AND embedding_dim = ANY($2)
+            ORDER BY embedding_dim, result_id
+            "#,
+            graph_id,
+            &dims
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        if results.is_empty() {
+            return Err(format!("no position results for graph {graph_id}").into());
+        }
+
+        let data_directory = std::env::var("DATA_DIRECTORY").unwrap_or(String::from("../data/"));
+        let mut c = Criterion::default().with_output_color(true).without_plots();
+        let mut scaling = ConstructionScaling {
+            graph_id,
+            ..Default::default()
+        };
+        for result in results {
+            let graph_path = format!("{data_directory}/{}", result.graph_path);
+            let graph = rembed::graph::Graph::parse_from_edge_list_file(
+                &graph_path,
+                result.embedding_dim as usize,
+                result.dim_hint as usize,
+            )
+            .map_err(|e| format!("Failed to load graph from {}: {}", graph_path, e))?;
+            let pos_path = format!("{data_directory}/{}", result.pos_path);
+            if !std::path::Path::new(&pos_path).exists() {
+                return Err(format!(
+                    "File not found: {} \n Please trigger Pull via Command",
+                    pos_path
+                )
+                .into());
+            }
+
+            let measurements = construction_times_dynamic(
+                result.embedding_dim as u8,
+                ConstructionArgs {
+                    graph: &graph,
+                    embedding_path: &pos_path,
+                    structures,
+                    fast,
+                    precision,
+                },
+                &mut c,
+            )
+            .await;
+            for measurement in measurements {
+                scaling
+                    .times
+                    .entry(measurement.data_structure_name)
+                    .or_default()
+                    .insert(
+                        result.embedding_dim as usize,
+                        measurement.measurement.wall_time_mean,
+                    );
+            }
+        }
+        Ok(scaling)
+    }
+
     async fn store_benchmark_result(
         &self,
-        result: BenchmarkResult,
+        result: &BenchmarkResult,
         checksum: &str,
     ) -> Result<(), Box<dyn std::error::Error>> {
         if !self.allow_dirty && RepoCodeStateManager::git_dirty()? {
@@ -348,15 +507,20 @@ impl LoadData {
             .get_or_create_code_state(&result.data_structure_name, checksum)
             .await?;
 
+        let wall_time_histogram = self
+            .histograms
+            .then(|| result.measurement.wall_time_histogram.clone());
+
         // Store measurement result
         sqlx::query!(
             r#"
                 INSERT INTO measurements (
                     code_state_id, result_id, iteration_number, sample_count,
-                    hostname, architecture, benchmark_type,
-                    wall_time_mean, wall_time_stddev, 
-                    instruction_count_mean, instruction_count_stddev, cycles_mean, cycles_stddev, ref_cycles_mean, ref_cycles_stddev
-                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+                    hostname, architecture, benchmark_type, query_radius,
+                    wall_time_mean, wall_time_stddev, wall_time_stderr,
+                    instruction_count_mean, instruction_count_stddev, cycles_mean, cycles_stddev, ref_cycles_mean, ref_cycles_stddev,
+                    adaptive_samples, wall_time_histogram, sampling_seed
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)
                 "#,
             code_state.code_state_id,
             result.result_id,
@@ -365,21 +529,27 @@ impl LoadData {
             self.hostname,
             std::env::consts::ARCH,
             result.benchmark_type.as_str(),
+            result.query_radius,
             result.measurement.wall_time_mean.as_nanos() as i64,
             result.measurement.wall_time_stddev.as_nanos() as i64,
+            result.measurement.wall_time_stderr.as_nanos() as i64,
             result.measurement.instructions_mean,
             result.measurement.instructions_stddev,
             result.measurement.cycles_mean,
             result.measurement.cycles_stddev,
             result.measurement.ref_cycles_mean as Option<f64>,
             result.measurement.ref_cycles_stddev as Option<f64>,
+            result.adaptive_samples,
+            wall_time_histogram.as_deref(),
+            result.sampling_seed.map(|seed| seed as i64),
         )
         .execute(&self.pool)
         .await?;
 
         println!(
-            "Stored benchmark result: {} for result_id: {} iteration: {}",
+            "Stored benchmark result: {} (radius {}) for result_id: {} iteration: {}",
             result.benchmark_type.as_str(),
+            result.query_radius,
             result.result_id,
             result.iteration_number
         );
@@ -391,20 +561,26 @@ impl LoadData {
         result_id: i64,
         code_state_id: i64,
     ) -> Result<HashSet<Measurement>, sqlx::Error> {
-        // Store measurement result
-        sqlx::query_as!(
-            Measurement,
+        sqlx::query!(
             r#"
-                SELECT benchmark_type, iteration_number as iteration FROM measurements
+                SELECT benchmark_type, iteration_number, query_radius FROM measurements
                 WHERE code_state_id = $1 AND result_id = $2 AND hostname = $3
+                    AND sampling_seed IS NOT DISTINCT FROM $4
                 "#,
             code_state_id,
             result_id,
             self.hostname,
+            self.sampling_seed.map(|seed| seed as i64),
         )
         .fetch_all(&self.pool)
         .await
-        .map(|x| x.into_iter().collect())
+        .map(|rows| {
+            rows.into_iter()
+                .map(|row| {
+                    Measurement::new(row.benchmark_type, row.iteration_number, row.query_radius)
+                })
+                .collect()
+        })
     }
 }
 
@@ -412,6 +588,18 @@ impl LoadData {
 struct Measurement {
     benchmark_type: String,
     iteration: i32,
+    /// Bit pattern of the query radius, so measurements can be hashed
+    query_radius: u64,
+}
+
+impl Measurement {
+    fn new(benchmark_type: String, iteration: i32, query_radius: f64) -> Self {
+        Measurement {
+            benchmark_type,
+            iteration,
+            query_radius: query_radius.to_bits(),
+        }
+    }
 }
 struct BenchmarkArgs<'a> {
     graph: &'a Graph,
@@ -422,13 +610,20 @@ struct BenchmarkArgs<'a> {
     structures: &'a Option<Vec<String>>,
     load_data: &'a LoadData,
     fast: bool,
+    precision: Option<f64>,
     export_only: bool,
+    progress: &'a ProgressReporter,
+    /// Receives every measurement, whether it is stored or not
+    results: &'a Sender<BenchmarkResult>,
 }
 
+/// Dimensions the benchmarks are compiled for
+pub const BENCHMARK_DIMS: [usize; 16] = [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 32];
+
 macro_rules! dispatch_dim {
-    ($dim:ident, $args:ident, $c:ident, $($c_dim:literal,)*) => {
+    ($dim:ident, $f:ident $args:tt; $($c_dim:literal,)*) => {
         match  $dim {
-            $($c_dim => load_and_run::<$c_dim>($args, $c).await,)*
+            $($c_dim => $f::<$c_dim> $args.await,)*
             _ => panic!("dim {} not covered",$dim),
         }
     };
@@ -436,10 +631,142 @@ macro_rules! dispatch_dim {
 
 async fn load_and_run_dynamic(dim: u8, args: BenchmarkArgs<'_>, c: &mut Criterion) {
     dispatch_dim!(
-        dim, args, c, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 32,
+        dim, load_and_run(args, c);
+        2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 32,
     )
 }
 
+async fn construction_times_dynamic(
+    dim: u8,
+    args: ConstructionArgs<'_>,
+    c: &mut Criterion,
+) -> Vec<MeasurementResult> {
+    dispatch_dim!(
+        dim, construction_times(args, c);
+        2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 32,
+    )
+}
+
+struct ConstructionArgs<'a> {
+    graph: &'a Graph,
+    embedding_path: &'a str,
+    structures: &'a Option<Vec<String>>,
+    fast: bool,
+    precision: Option<f64>,
+}
+
+/// Measures the construction of every selected structure on the last
+/// iteration of an embedding, like the `construction` benchmark type
+async fn construction_times<const D: usize>(
+    args: ConstructionArgs<'_>,
+    c: &mut Criterion,
+) -> Vec<MeasurementResult> {
+    let ConstructionArgs {
+        graph,
+        embedding_path,
+        structures,
+        fast,
+        precision,
+    } = args;
+    let iterations: Iterations<D> = rembed::parsing::parse_positions_file(embedding_path).unwrap();
+    if let Err(e) = iterations.validate_graph_compatibility(graph) {
+        println!("Skipping {embedding_path}: {e}");
+        return Vec::new();
+    }
+    let Some(last) = iterations.iterations().last() else {
+        println!("Empty embedding {embedding_path}, skipping");
+        return Vec::new();
+    };
+    let embedding = Embedding::<D> {
+        positions: last.positions.deref().clone(),
+        graph,
+    };
+
+    let mut group = c.benchmark_group(format!("construction_scaling_dim-{D}"));
+    rembed::data_structures(&embedding, structures.as_deref())
+        .map(|structure| {
+            runner::profile_datastructure_query(
+                &embedding,
+                None,
+                &mut group,
+                &[],
+                None,
+                None,
+                None,
+                BenchmarkType::PositionUpdate,
+                structure.as_ref(),
+                fast,
+                precision,
+            )
+        })
+        .collect()
+}
+
+/// Construction time of each structure per embedding dimension for one
+/// graph, see [`LoadData::construction_scaling`]
+#[derive(Debug, Clone, Default)]
+pub struct ConstructionScaling {
+    pub graph_id: i64,
+    /// Mean construction time per structure and dimension
+    pub times: BTreeMap<String, BTreeMap<usize, Duration>>,
+}
+
+impl ConstructionScaling {
+    pub fn dims(&self) -> Vec<usize> {
+        let dims: BTreeSet<usize> = self
+            .times
+            .values()
+            .flat_map(|times| times.keys().copied())
+            .collect();
+        dims.into_iter().collect()
+    }
+
+    /// Construction time at the largest dimension relative to the smallest
+    /// one a structure was measured at
+    pub fn growth(&self, structure: &str) -> Option<f64> {
+        let times = self.times.get(structure)?;
+        let (_, first) = times.first_key_value()?;
+        let (_, last) = times.last_key_value()?;
+        Some(last.as_secs_f64() / first.as_secs_f64())
+    }
+
+    /// One row per structure with its construction time in ms per dimension
+    pub fn table(&self) -> String {
+        use std::fmt::Write;
+        let dims = self.dims();
+        let mut table = format!("{:<32}", "structure");
+        for dim in &dims {
+            write!(table, " {:>10}", format!("D={dim}")).unwrap();
+        }
+        writeln!(table, " {:>8}", "growth").unwrap();
+        for (structure, times) in &self.times {
+            write!(table, "{structure:<32}").unwrap();
+            for dim in &dims {
+                match times.get(dim) {
+                    Some(time) => write!(table, " {:>10.3}", time.as_secs_f64() * 1e3).unwrap(),
+                    None => write!(table, " {:>10}", "-").unwrap(),
+                }
+            }
+            match self.growth(structure) {
+                Some(growth) => writeln!(table, " {growth:>7.1}x").unwrap(),
+                None => writeln!(table, " {:>8}", "-").unwrap(),
+            }
+        }
+        table
+    }
+
+    /// Long format for plotting: `structure,dim,wall_time_ns`
+    pub fn csv(&self) -> String {
+        let mut csv = String::from("structure,dim,wall_time_ns\n");
+        for (structure, times) in &self.times {
+            for (dim, time) in times {
+                csv.push_str(&format!("{structure},{dim},{}\n", time.as_nanos()));
+            }
+        }
+        csv
+    }
+}
+
 async fn load_and_run<const D: usize>(args: BenchmarkArgs<'_>, c: &mut Criterion) {
     let BenchmarkArgs {
         graph,
@@ -450,9 +777,16 @@ async fn load_and_run<const D: usize>(args: BenchmarkArgs<'_>, c: &mut Criterion
         structures,
         load_data,
         fast,
+        precision,
         export_only,
+        progress,
+        results,
     } = args;
     let iterations: Iterations<D> = rembed::parsing::parse_positions_file(embedding_path).unwrap();
+    if let Err(e) = iterations.validate_graph_compatibility(graph) {
+        println!("Skipping result {result_id}: {e}");
+        return;
+    }
 
     // Load the embeddings from the file
     let embeddings = || {
@@ -467,27 +801,23 @@ async fn load_and_run<const D: usize>(args: BenchmarkArgs<'_>, c: &mut Criterion
         })
     };
 
-    let embeddings: Vec<_> = embeddings().collect();
+    let all_embeddings: Vec<_> = embeddings().collect();
 
-    let embeddings = if only_last_iteration {
-        match embeddings.last() {
-            Some(last) => std::slice::from_ref(last),
-            None => &[],
-        }
+    // Position updates are measured from the iteration before, so keep it
+    // even if only the last iteration is benchmarked
+    let first = if only_last_iteration {
+        all_embeddings.len().saturating_sub(1)
     } else {
-        embeddings.as_slice()
+        0
     };
+    let embeddings = &all_embeddings[first..];
     if embeddings.is_empty() {
         println!("Empty embedding, skipping");
         return;
     }
 
-    let mut data_structures = if let Some(structures) = structures {
-        rembed::data_structures(&embeddings[0].1)
-            .filter(|s| structures.contains(&s.name()) || structures.is_empty())
-            .collect()
-    } else if !export_only {
-        rembed::data_structures(&embeddings[0].1).collect::<Vec<_>>()
+    let mut data_structures = if structures.is_some() || !export_only {
+        rembed::query_data_structures(&embeddings[0].1, structures.as_deref()).collect()
     } else {
         vec![]
     };
@@ -511,63 +841,94 @@ async fn load_and_run<const D: usize>(args: BenchmarkArgs<'_>, c: &mut Criterion
         }
     }
 
-    for &(iteration, ref embedding) in embeddings {
+    for (k, &(iteration, ref embedding)) in embeddings.iter().enumerate() {
+        let previous_positions = (first + k)
+            .checked_sub(1)
+            .map(|previous| all_embeddings[previous].1.positions.as_slice());
         for structure in &mut data_structures {
             structure.update_positions(&embedding.positions, None);
         }
         let identifier = format!("result_{result_id}@{iteration}_dim-{D}");
         let mut group = c.benchmark_group(&identifier);
 
-        let process_results = |m: MeasurementResult, ty: &BenchmarkType| BenchmarkResult {
-            benchmark_type: ty.clone(),
-            data_structure_name: m.data_structure_name,
-            result_id,
-            iteration_number: iteration,
-            sample_count: m.sample_count,
-            measurement: m.measurement,
-        };
+        let process_results =
+            |m: MeasurementResult, ty: &BenchmarkType, query_radius| BenchmarkResult {
+                benchmark_type: ty.clone(),
+                data_structure_name: m.data_structure_name,
+                result_id,
+                iteration_number: iteration,
+                query_radius,
+                sample_count: m.sample_count,
+                adaptive_samples: m.adaptive_samples,
+                sampling_seed: load_data.sampling_seed,
+                measurement: m.measurement,
+            };
 
         let mut run_benchmark_with_query_list =
-            async |query_list: Vec<_>, benchmark_type: &BenchmarkType| {
+            async |query_list: &[NodeId], benchmark_type: &BenchmarkType, query_radius: f64| {
                 let radius_hint = match benchmark_type {
                     BenchmarkType::Radius(r, _) => *r as f64,
-                    _ => 1.0,
+                    _ => query_radius,
                 };
                 for structure in &mut data_structures {
                     structure.set_radius_hint(radius_hint);
                 }
+                if load_data.verify_consistency {
+                    check_neighbor_sets(&data_structures, embedding, query_list, query_radius);
+                }
                 for structure in &data_structures {
                     if load_data.store
                         && let Some((_, skiplist)) = code_states.get(&structure.name())
-                        && skiplist.contains(&Measurement {
-                            benchmark_type: benchmark_type.as_str().to_owned(),
-                            iteration: iteration as i32,
-                        })
+                        && skiplist.contains(&Measurement::new(
+                            benchmark_type.as_str().to_owned(),
+                            iteration as i32,
+                            query_radius,
+                        ))
                     {
                         continue;
                     }
+                    let column = match benchmark_type {
+                        BenchmarkType::RadiusSweep => {
+                            format!("{}@{query_radius}", benchmark_type.as_str())
+                        }
+                        _ => benchmark_type.as_str().to_owned(),
+                    };
+                    progress.measuring(result_id, iteration, &structure.name(), &column);
                     let result = process_results(
                         runner::profile_datastructure_query(
                             embedding,
+                            previous_positions,
                             &mut group,
-                            &query_list,
-                            None,
+                            query_list,
                             None,
+                            Some(query_radius),
                             None,
                             benchmark_type.clone(),
                             structure.as_ref(),
                             fast,
+                            precision,
                         ),
                         benchmark_type,
+                        query_radius,
+                    );
+                    progress.measured(
+                        result_id,
+                        &result.data_structure_name,
+                        &column,
+                        result.measurement.wall_time_mean,
                     );
                     if load_data.store {
-                        let result = load_data
-                            .store_benchmark_result(result, &structure.checksum())
+                        let stored = load_data
+                            .store_benchmark_result(&result, &structure.checksum())
                             .await;
-                        if let Err(e) = result {
+                        if let Err(e) = stored {
                             println!("encontered error while storing results {e}");
                         }
                     }
+                    let _ = results.send(result);
+                }
+                if let BenchmarkType::RadiusSweep = benchmark_type {
+                    check_result_counts(&data_structures, embedding, query_list, query_radius);
                 }
             };
 
@@ -576,7 +937,8 @@ async fn load_and_run<const D: usize>(args: BenchmarkArgs<'_>, c: &mut Criterion
             .map(|x| x.as_slice())
             .unwrap_or(BenchmarkType::all());
         for benchmark in benchmarks {
-            let query_list = query_list_for_type(benchmark.clone(), embedding);
+            let query_list =
+                query_list_for_type(benchmark.clone(), embedding, load_data.sampling_seed);
             if export_only {
                 use std::io::Write;
                 std::fs::create_dir_all("embedding_export").unwrap();
@@ -605,22 +967,31 @@ async fn load_and_run<const D: usize>(args: BenchmarkArgs<'_>, c: &mut Criterion
                     let radius = embedding.graph.nodes[query].weight.powi(2);
                     writeln!(file, "{radius}").unwrap();
                 }
+            } else if let BenchmarkType::RadiusSweep = benchmark {
+                for radius in runner::SWEEP_RADII {
+                    run_benchmark_with_query_list(&query_list, benchmark, radius).await;
+                }
             } else {
-                run_benchmark_with_query_list(query_list, benchmark).await;
+                run_benchmark_with_query_list(&query_list, benchmark, 1.0).await;
             }
         }
     }
 }
 
+/// Nodes queried by a benchmark of type `ty`, drawn at random with `seed`
+/// and evenly spaced without one
 fn query_list_for_type<'a, const D: usize>(
     ty: BenchmarkType,
     embedding: &Embedding<'a, D>,
+    seed: Option<u64>,
 ) -> Vec<NodeId> {
     match ty {
-        BenchmarkType::MixedNodes => query_sparse(embedding, 10000),
-        BenchmarkType::LightNodes => query_light(embedding, 10000),
-        BenchmarkType::AllNodes => query_sparse(embedding, embedding.positions.len()),
-        BenchmarkType::HeavyNodes => query_heavy(embedding, 10000),
+        BenchmarkType::MixedNodes | BenchmarkType::RadiusSweep => {
+            query_sparse(embedding, 10000, seed)
+        }
+        BenchmarkType::LightNodes => query_light(embedding, 10000, seed),
+        BenchmarkType::AllNodes => query_sparse(embedding, embedding.positions.len(), seed),
+        BenchmarkType::HeavyNodes => query_heavy(embedding, 10000, seed),
         BenchmarkType::PositionUpdate => (0..embedding.positions.len()).collect(),
         BenchmarkType::Radius(radius, _) => unimplemented!(
             "Radius-based query list generation not implemented yet, radius: {radius}"
@@ -628,37 +999,289 @@ fn query_list_for_type<'a, const D: usize>(
     }
 }
 
-fn query_sparse<'a, const D: usize>(embedding: &Embedding<'a, D>, n: usize) -> Vec<NodeId> {
-    let total = embedding.positions.len();
-    (0..total).step_by(total / n.min(total)).collect()
-}
-fn query_light<'a, const D: usize>(embedding: &Embedding<'a, D>, n: usize) -> Vec<NodeId> {
-    let light_nodes: Vec<_> = embedding
-        .graph
-        .nodes
+/// Warns about exact structures whose in-radius result count for the queries
+/// differs from the count most of them agree on.
+fn check_result_counts<'a, const D: usize>(
+    data_structures: &[Box<dyn IndexClone<D> + 'a>],
+    embedding: &Embedding<'a, D>,
+    query_list: &[NodeId],
+    radius: f64,
+) {
+    let counts: Vec<_> = data_structures
         .iter()
-        .enumerate()
-        .filter(|(_, n)| n.weight < 1.)
-        .map(|(i, _)| i)
+        .filter(|s| s.is_exact())
+        .map(|s| {
+            let count = runner::in_radius_count(s.as_ref(), embedding, query_list, radius);
+            (s.name(), count)
+        })
         .collect();
-    let total = light_nodes.len();
-    light_nodes
-        .into_iter()
-        .step_by(total / n.min(total))
-        .collect()
+    let Some((expected, outliers)) = count_outliers(&counts) else {
+        return;
+    };
+    for (name, count) in outliers {
+        eprintln!(
+            "Warning: {name} returned {count} points within radius {radius}, other exact structures returned {expected}"
+        );
+    }
 }
-fn query_heavy<'a, const D: usize>(embedding: &Embedding<'a, D>, n: usize) -> Vec<NodeId> {
-    let light_nodes: Vec<_> = embedding
-        .graph
-        .nodes
+
+/// Warns about exact structures whose in-radius neighbor sets differ from the
+/// ones of the first exact structure. Approximate structures may legitimately
+/// miss neighbors and are not compared.
+fn check_neighbor_sets<'a, const D: usize>(
+    data_structures: &[Box<dyn IndexClone<D> + 'a>],
+    embedding: &Embedding<'a, D>,
+    query_list: &[NodeId],
+    radius: f64,
+) {
+    let mut exact = data_structures.iter().filter(|s| s.is_exact());
+    let Some(reference) = exact.next() else {
+        return;
+    };
+    let expected: Vec<_> = query_list
         .iter()
-        .enumerate()
-        .filter(|(_, n)| n.weight >= 1.)
-        .map(|(i, _)| i)
+        .map(|&i| runner::in_radius_neighbors(reference.as_ref(), embedding, i, radius))
         .collect();
-    let total = light_nodes.len();
-    light_nodes
-        .into_iter()
-        .step_by(total / n.min(total))
+    for structure in exact {
+        let diverging = diverging_queries(query_list, &expected, |i| {
+            runner::in_radius_neighbors(structure.as_ref(), embedding, i, radius)
+        });
+        if let Some(first) = diverging.first() {
+            eprintln!(
+                "Warning: {} returned different neighbors than {} for {} of {} queries (e.g. node {first}) within radius {radius}",
+                structure.name(),
+                reference.name(),
+                diverging.len(),
+                query_list.len()
+            );
+        }
+    }
+}
+
+/// The queries whose `neighbors` differ from the `expected` neighbors at the
+/// same position of `query_list`.
+fn diverging_queries(
+    query_list: &[NodeId],
+    expected: &[Vec<NodeId>],
+    neighbors: impl Fn(NodeId) -> Vec<NodeId>,
+) -> Vec<NodeId> {
+    query_list
+        .iter()
+        .zip(expected)
+        .filter(|&(&i, expected)| neighbors(i) != *expected)
+        .map(|(&i, _)| i)
         .collect()
 }
+
+/// Condition on the graphs of the position results that excludes those with
+/// more than `max_nodes` nodes after preprocessing
+fn node_cap_condition(max_nodes: usize) -> String {
+    format!("processed_n <= {max_nodes}")
+}
+
+/// The most common count and the entries that deviate from it.
+fn count_outliers(counts: &[(String, usize)]) -> Option<(usize, Vec<&(String, usize)>)> {
+    let mut frequency: HashMap<usize, usize> = HashMap::new();
+    for &(_, count) in counts {
+        *frequency.entry(count).or_default() += 1;
+    }
+    let (&expected, _) = frequency
+        .iter()
+        .max_by_key(|&(&count, &frequency)| (frequency, count))?;
+    let outliers = counts.iter().filter(|(_, c)| *c != expected).collect();
+    Some((expected, outliers))
+}
+
+fn query_sparse<'a, const D: usize>(
+    embedding: &Embedding<'a, D>,
+    n: usize,
+    seed: Option<u64>,
+) -> Vec<NodeId> {
+    spread((0..embedding.positions.len()).collect(), n, seed)
+}
+fn query_light<'a, const D: usize>(
+    embedding: &Embedding<'a, D>,
+    n: usize,
+    seed: Option<u64>,
+) -> Vec<NodeId> {
+    let light_nodes = embedding
+        .nodes()
+        .map(|(id, ..)| id)
+        .filter(|&id| embedding.graph.nodes[id].weight < 1.);
+    spread(light_nodes.collect(), n, seed)
+}
+fn query_heavy<'a, const D: usize>(
+    embedding: &Embedding<'a, D>,
+    n: usize,
+    seed: Option<u64>,
+) -> Vec<NodeId> {
+    let heavy_nodes = embedding.heavy_nodes().map(|(id, ..)| id);
+    spread(heavy_nodes.collect(), n, seed)
+}
+/// About `n` of the `nodes`, evenly spaced, or with a `seed` exactly `n` of
+/// them drawn at random. The drawn nodes keep the order of `nodes`.
+fn spread(nodes: Vec<NodeId>, n: usize, seed: Option<u64>) -> Vec<NodeId> {
+    let total = nodes.len();
+    let Some(seed) = seed else {
+        return nodes.into_iter().step_by(total / n.min(total)).collect();
+    };
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let mut picked = rand::seq::index::sample(&mut rng, total, n.min(total)).into_vec();
+    picked.sort_unstable();
+    picked.into_iter().map(|i| nodes[i]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        BenchmarkArgs, ConstructionScaling, LoadData, count_outliers, dashboard::Dashboard,
+        diverging_queries, load_and_run_dynamic, node_cap_condition, query_list_for_type,
+        runner::BenchmarkType,
+    };
+
+    #[test]
+    fn construction_scaling_table() {
+        let mut scaling = ConstructionScaling::default();
+        for (dim, ms) in [(2, 1), (8, 4), (32, 40)] {
+            scaling
+                .times
+                .entry(String::from("snn"))
+                .or_default()
+                .insert(dim, Duration::from_millis(ms));
+        }
+        scaling
+            .times
+            .entry(String::from("kiddo"))
+            .or_default()
+            .insert(8, Duration::from_millis(2));
+
+        assert_eq!(scaling.dims(), vec![2, 8, 32]);
+        assert_eq!(scaling.growth("snn"), Some(40.));
+        assert_eq!(scaling.growth("kiddo"), Some(1.));
+
+        let table = scaling.table();
+        let snn = table.lines().find(|l| l.starts_with("snn")).unwrap();
+        assert!(snn.ends_with("40.0x"), "{snn}");
+        let kiddo = table.lines().find(|l| l.starts_with("kiddo")).unwrap();
+        assert_eq!(
+            kiddo.split_whitespace().collect::<Vec<_>>(),
+            ["kiddo", "-", "2.000", "-", "1.0x"]
+        );
+        assert!(scaling.csv().contains("snn,32,40000000\n"));
+    }
+
+    #[test]
+    fn count_outliers_reports_deviating_structures() {
+        let counts: Vec<_> = [("atree", 12), ("kiddo", 12), ("snn", 13), ("grid", 12)]
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count))
+            .collect();
+        let (expected, outliers) = count_outliers(&counts).unwrap();
+        assert_eq!(expected, 12);
+        assert_eq!(outliers, vec![&("snn".to_string(), 13)]);
+        assert!(count_outliers(&[]).is_none());
+    }
+
+    #[test]
+    fn node_cap_excludes_larger_graphs() {
+        assert_eq!(node_cap_condition(5000), "processed_n <= 5000");
+    }
+
+    #[test]
+    fn diverging_queries_compares_whole_neighbor_sets() {
+        let queries = [3, 5, 8];
+        let expected = vec![vec![1, 2], vec![4], vec![]];
+        let agreeing = |i| match i {
+            3 => vec![1, 2],
+            5 => vec![4],
+            _ => vec![],
+        };
+        assert!(diverging_queries(&queries, &expected, agreeing).is_empty());
+
+        // Same number of neighbors, but a different one for node 3
+        let diverging = |i| match i {
+            3 => vec![1, 7],
+            5 => vec![4],
+            _ => vec![],
+        };
+        assert_eq!(diverging_queries(&queries, &expected, diverging), vec![3]);
+    }
+
+    #[test]
+    fn same_sampling_seed_selects_the_same_queries() {
+        let n = 25_000;
+        let graph =
+            rembed::graph::Graph::from_edge_list((0..n - 1).map(|i| (i, i + 1)).collect(), 2, 2)
+                .unwrap();
+        let positions = (0..n)
+            .map(|i| rembed::dvec::DVec::new([i as f32, 0.]))
+            .collect();
+        let embedding = rembed::Embedding {
+            positions,
+            graph: &graph,
+        };
+        let queries = |seed| query_list_for_type(BenchmarkType::MixedNodes, &embedding, seed);
+
+        let first = queries(Some(7));
+        assert_eq!(first.len(), 10000);
+        assert!(first.is_sorted());
+        assert_eq!(first, queries(Some(7)));
+        assert_ne!(first, queries(Some(8)));
+        // Without a seed the queries stay evenly spaced
+        assert_eq!(queries(None), (0..n).step_by(2).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn results_are_sent_without_storing() {
+        let graph =
+            rembed::graph::Graph::from_edge_list((0..99).map(|i| (i, i + 1)).collect(), 2, 2)
+                .unwrap();
+        let mut positions = String::from("# iteration 0\n");
+        for i in 0..100 {
+            positions.push_str(&format!("{i}, {}, {}\n", i % 10, i / 10));
+        }
+        let path = std::env::temp_dir().join(format!("results-test-{}.txt", std::process::id()));
+        std::fs::write(&path, positions).unwrap();
+
+        // Never connects as long as nothing is stored
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        let load_data = LoadData::new(pool);
+        let dashboard = Dashboard::spawn(1, 1, false, None);
+        let progress = dashboard.reporter(0);
+        let (sender, receiver) = crossbeam::channel::unbounded();
+        let structures = Some(vec![String::from("grid")]);
+        load_and_run_dynamic(
+            2,
+            BenchmarkArgs {
+                graph: &graph,
+                result_id: 7,
+                embedding_path: path.to_str().unwrap(),
+                only_last_iteration: true,
+                benchmarks: &Some(vec![BenchmarkType::AllNodes]),
+                structures: &structures,
+                load_data: &load_data,
+                fast: true,
+                precision: Some(0.5),
+                export_only: false,
+                progress: &progress,
+                results: &sender,
+            },
+            &mut criterion::Criterion::default().without_plots(),
+        )
+        .await;
+        drop(sender);
+        dashboard.finish();
+        std::fs::remove_file(&path).unwrap();
+
+        let results: Vec<_> = receiver.into_iter().collect();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].data_structure_name, "grid");
+        assert_eq!(results[0].result_id, 7);
+        assert_eq!(results[0].benchmark_type.as_str(), "all_nodes");
+        assert!(results[0].sample_count > 0);
+    }
+}
@@ -0,0 +1,401 @@
+//! Progress display for benchmark sweeps. The workers send [`ProgressEvent`]s
+//! over a channel to a display thread, which draws a live dashboard when
+//! stdout is a terminal and prints plain lines otherwise.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{self, Write},
+    os::fd::{AsFd, AsRawFd, OwnedFd},
+    path::Path,
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crossbeam::channel::{Receiver, RecvTimeoutError, Sender};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// File criterion's console output goes to with `--quiet-criterion`
+pub const CRITERION_LOG: &str = "criterion.log";
+
+/// Interval at which the live summary table is redrawn
+const TABLE_REFRESH: Duration = Duration::from_millis(500);
+
+pub enum ProgressEvent {
+    /// A worker picked up a position result
+    Started { worker: usize, result_id: i64 },
+    /// A worker starts measuring one structure
+    Measuring {
+        worker: usize,
+        result_id: i64,
+        iteration: usize,
+        structure: String,
+        benchmark: String,
+    },
+    /// A measurement completed
+    Measured {
+        result_id: i64,
+        structure: String,
+        benchmark: String,
+        wall_time_mean: Duration,
+    },
+    /// A worker is done with a position result, successfully or not
+    Finished { worker: usize, result_id: i64 },
+}
+
+/// Handle of one worker to send its progress to the display thread. Events
+/// are dropped once the display is gone.
+#[derive(Clone)]
+pub struct ProgressReporter {
+    worker: usize,
+    sender: Sender<ProgressEvent>,
+}
+
+impl ProgressReporter {
+    pub fn started(&self, result_id: i64) {
+        self.send(ProgressEvent::Started {
+            worker: self.worker,
+            result_id,
+        });
+    }
+
+    pub fn measuring(&self, result_id: i64, iteration: usize, structure: &str, benchmark: &str) {
+        self.send(ProgressEvent::Measuring {
+            worker: self.worker,
+            result_id,
+            iteration,
+            structure: structure.to_string(),
+            benchmark: benchmark.to_string(),
+        });
+    }
+
+    pub fn measured(&self, result_id: i64, structure: &str, benchmark: &str, mean: Duration) {
+        self.send(ProgressEvent::Measured {
+            result_id,
+            structure: structure.to_string(),
+            benchmark: benchmark.to_string(),
+            wall_time_mean: mean,
+        });
+    }
+
+    pub fn finished(&self, result_id: i64) {
+        self.send(ProgressEvent::Finished {
+            worker: self.worker,
+            result_id,
+        });
+    }
+
+    fn send(&self, event: ProgressEvent) {
+        let _ = self.sender.send(event);
+    }
+}
+
+/// Sum and count of the mean wall times per structure and benchmark
+type ResultCells = BTreeMap<(String, String), (Duration, u32)>;
+
+/// Mean wall time per structure and benchmark of the completed measurements,
+/// averaged over iterations
+#[derive(Debug, Default)]
+pub struct SummaryTable {
+    results: BTreeMap<i64, ResultCells>,
+    latest: Option<i64>,
+}
+
+impl SummaryTable {
+    pub fn record(&mut self, result_id: i64, structure: &str, benchmark: &str, mean: Duration) {
+        let cell = self
+            .results
+            .entry(result_id)
+            .or_default()
+            .entry((structure.to_string(), benchmark.to_string()))
+            .or_default();
+        cell.0 += mean;
+        cell.1 += 1;
+        self.latest = Some(result_id);
+    }
+
+    pub fn mean(&self, result_id: i64, structure: &str, benchmark: &str) -> Option<Duration> {
+        let (sum, count) = self
+            .results
+            .get(&result_id)?
+            .get(&(structure.to_string(), benchmark.to_string()))?;
+        Some(*sum / *count)
+    }
+
+    /// Result with the most recent measurement
+    pub fn latest(&self) -> Option<i64> {
+        self.latest
+    }
+
+    /// The table of `result_id` with a row per structure and a column per
+    /// benchmark
+    pub fn render(&self, result_id: i64) -> Option<String> {
+        let cells = self.results.get(&result_id)?;
+        let structures: BTreeSet<_> = cells.keys().map(|(s, _)| s.as_str()).collect();
+        let benchmarks: BTreeSet<_> = cells.keys().map(|(_, b)| b.as_str()).collect();
+
+        let mut rows = vec![
+            std::iter::once(format!("result {result_id}"))
+                .chain(benchmarks.iter().map(|b| b.to_string()))
+                .collect::<Vec<_>>(),
+        ];
+        for structure in &structures {
+            let mut row = vec![structure.to_string()];
+            for benchmark in &benchmarks {
+                row.push(match self.mean(result_id, structure, benchmark) {
+                    Some(mean) => format!("{mean:.2?}"),
+                    None => String::from("-"),
+                });
+            }
+            rows.push(row);
+        }
+
+        // Widths in characters, durations contain a µ
+        let widths: Vec<usize> = (0..rows[0].len())
+            .map(|column| {
+                let width = |row: &Vec<String>| row[column].chars().count();
+                rows.iter().map(width).max().unwrap_or(0)
+            })
+            .collect();
+        let lines: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                let cells: Vec<_> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, &width)| format!("{cell:>width$}"))
+                    .collect();
+                cells.join("  ").trim_end().to_string()
+            })
+            .collect();
+        Some(lines.join("\n"))
+    }
+}
+
+/// Display thread for the progress of a benchmark sweep
+pub struct Dashboard {
+    sender: Sender<ProgressEvent>,
+    thread: JoinHandle<()>,
+}
+
+impl Dashboard {
+    /// Starts the display of `total` results worked on by `workers` workers.
+    /// Draws to `terminal` if given, else to stdout; live only if `live`.
+    pub fn spawn(total: usize, workers: usize, live: bool, terminal: Option<File>) -> Self {
+        let (sender, receiver) = crossbeam::channel::unbounded();
+        let thread = std::thread::spawn(move || {
+            if live {
+                let target = match terminal {
+                    Some(terminal) => match terminal.try_clone() {
+                        Ok(read) => ProgressDrawTarget::term(
+                            console::Term::read_write_pair(read, terminal),
+                            10,
+                        ),
+                        Err(_) => ProgressDrawTarget::stdout(),
+                    },
+                    None => ProgressDrawTarget::stdout(),
+                };
+                display_live(receiver, total, workers, target);
+            } else {
+                let output: Box<dyn Write> = match terminal {
+                    Some(terminal) => Box::new(terminal),
+                    None => Box::new(io::stdout()),
+                };
+                display_lines(receiver, total, output);
+            }
+        });
+        Dashboard { sender, thread }
+    }
+
+    pub fn reporter(&self, worker: usize) -> ProgressReporter {
+        ProgressReporter {
+            worker,
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Waits until the display has shown all events. The reporters have to
+    /// be dropped first.
+    pub fn finish(self) {
+        drop(self.sender);
+        let _ = self.thread.join();
+    }
+}
+
+fn display_live(
+    receiver: Receiver<ProgressEvent>,
+    total: usize,
+    workers: usize,
+    target: ProgressDrawTarget,
+) {
+    let multi = MultiProgress::with_draw_target(target);
+    let overall = multi.add(crate::create_progress_bar(total));
+    let worker_style = ProgressStyle::default_spinner()
+        .template("{spinner:.green} {prefix} {msg}")
+        .unwrap();
+    let lines: Vec<_> = (0..workers)
+        .map(|worker| {
+            let line = multi.add(ProgressBar::new_spinner());
+            line.set_style(worker_style.clone());
+            line.set_prefix(format!("worker {worker}:"));
+            line.set_message("idle");
+            line
+        })
+        .collect();
+    let table = multi.add(ProgressBar::new_spinner());
+    table.set_style(ProgressStyle::default_spinner().template("{msg}").unwrap());
+
+    let mut summary = SummaryTable::default();
+    let mut changed = false;
+    loop {
+        match receiver.recv_timeout(TABLE_REFRESH) {
+            Ok(ProgressEvent::Started { worker, result_id }) => {
+                lines[worker].set_message(format!("result {result_id}"));
+            }
+            Ok(ProgressEvent::Measuring {
+                worker,
+                result_id,
+                iteration,
+                structure,
+                benchmark,
+            }) => {
+                lines[worker].set_message(format!(
+                    "result {result_id} iteration {iteration}: {structure} {benchmark}"
+                ));
+                lines[worker].tick();
+            }
+            Ok(ProgressEvent::Measured {
+                result_id,
+                structure,
+                benchmark,
+                wall_time_mean,
+            }) => {
+                summary.record(result_id, &structure, &benchmark, wall_time_mean);
+                changed = true;
+            }
+            Ok(ProgressEvent::Finished { worker, .. }) => {
+                lines[worker].set_message("idle");
+                overall.inc(1);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if changed && let Some(rendered) = summary.latest().and_then(|r| summary.render(r)) {
+            table.set_message(rendered);
+            changed = false;
+        }
+    }
+    for line in lines {
+        line.finish_and_clear();
+    }
+    overall.finish();
+    table.finish();
+}
+
+fn display_lines(receiver: Receiver<ProgressEvent>, total: usize, mut output: Box<dyn Write>) {
+    let mut done = 0;
+    for event in receiver {
+        let line = match event {
+            ProgressEvent::Started { worker, result_id } => {
+                format!("worker {worker}: started result {result_id}")
+            }
+            ProgressEvent::Measuring {
+                worker,
+                result_id,
+                iteration,
+                structure,
+                benchmark,
+            } => format!(
+                "worker {worker}: result {result_id} iteration {iteration}: {structure} {benchmark}"
+            ),
+            ProgressEvent::Measured {
+                result_id,
+                structure,
+                benchmark,
+                wall_time_mean,
+            } => format!("result {result_id}: {structure} {benchmark} {wall_time_mean:.2?}"),
+            ProgressEvent::Finished { worker, result_id } => {
+                done += 1;
+                format!("worker {worker}: finished result {result_id} [{done}/{total}]")
+            }
+        };
+        let _ = writeln!(output, "{line}");
+    }
+}
+
+/// Sends everything written to stdout and stderr to a log file until
+/// dropped, to keep criterion's console output away from the dashboard
+pub struct QuietOutput {
+    stdout: OwnedFd,
+    stderr: OwnedFd,
+}
+
+impl QuietOutput {
+    pub fn redirect(log: &Path) -> io::Result<Self> {
+        let file = File::create(log)?;
+        let quiet = QuietOutput {
+            stdout: io::stdout().as_fd().try_clone_to_owned()?,
+            stderr: io::stderr().as_fd().try_clone_to_owned()?,
+        };
+        io::stdout().flush()?;
+        for fd in [io::stdout().as_raw_fd(), io::stderr().as_raw_fd()] {
+            if unsafe { libc::dup2(file.as_raw_fd(), fd) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(quiet)
+    }
+
+    /// The stdout from before the redirection
+    pub fn terminal(&self) -> io::Result<File> {
+        Ok(File::from(self.stdout.try_clone()?))
+    }
+}
+
+impl Drop for QuietOutput {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        unsafe {
+            libc::dup2(self.stdout.as_raw_fd(), io::stdout().as_raw_fd());
+            libc::dup2(self.stderr.as_raw_fd(), io::stderr().as_raw_fd());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SummaryTable;
+    use std::time::Duration;
+
+    #[test]
+    fn summary_table_averages_over_iterations() {
+        let mut table = SummaryTable::default();
+        let micros = Duration::from_micros;
+        table.record(3, "atree", "mixed_nodes", micros(10));
+        table.record(3, "atree", "mixed_nodes", micros(20));
+        table.record(3, "kiddo", "light_nodes", micros(4));
+        table.record(5, "atree", "mixed_nodes", micros(100));
+
+        assert_eq!(table.mean(3, "atree", "mixed_nodes"), Some(micros(15)));
+        assert_eq!(table.mean(3, "kiddo", "light_nodes"), Some(micros(4)));
+        assert_eq!(table.mean(3, "kiddo", "mixed_nodes"), None);
+        assert_eq!(table.mean(5, "atree", "mixed_nodes"), Some(micros(100)));
+        assert_eq!(table.latest(), Some(5));
+        assert_eq!(table.render(4), None);
+    }
+
+    #[test]
+    fn summary_table_renders_structures_by_benchmarks() {
+        let mut table = SummaryTable::default();
+        table.record(3, "kiddo", "light_nodes", Duration::from_micros(4));
+        table.record(3, "atree", "mixed_nodes", Duration::from_micros(15));
+
+        let expected = [
+            "result 3  light_nodes  mixed_nodes",
+            "   atree            -      15.00µs",
+            "   kiddo       4.00µs            -",
+        ];
+        assert_eq!(table.render(3).unwrap(), expected.join("\n"));
+    }
+}
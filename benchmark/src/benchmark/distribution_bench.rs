@@ -375,13 +375,8 @@ impl DistributionBenchRunner {
         };
 
         // Get data structures
-        let mut data_structures: Vec<_> = if !self.config.structures.is_empty() {
-            rembed::data_structures(&embedding)
-                .filter(|s| self.config.structures.contains(&s.name()))
-                .collect()
-        } else {
-            rembed::data_structures(&embedding).collect()
-        };
+        let mut data_structures: Vec<_> =
+            rembed::data_structures(&embedding, Some(&self.config.structures)).collect();
         for structure in &mut data_structures {
             structure.set_radius_hint(radius);
         }
@@ -497,6 +492,7 @@ impl DistributionBenchRunner {
         for structure in &data_structures {
             let measurement = runner::profile_datastructure_query(
                 &embedding,
+                None,
                 &mut group,
                 &query_indices,
                 query_pos_list.clone(),
@@ -505,6 +501,7 @@ impl DistributionBenchRunner {
                 runner::BenchmarkType::Radius(radius as f32, format!("radius_{}", radius)),
                 structure.as_ref(),
                 fast,
+                None,
             );
 
             results.push(BenchmarkRecord {
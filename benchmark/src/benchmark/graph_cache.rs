@@ -0,0 +1,242 @@
+//! Graphs of the position results, parsed once per file and shared by the
+//! benchmark workers. The results of one graph differ in embedding dimension
+//! and seed; a graph parsed for another dimension is reweighted with
+//! [`Graph::reweighted`] instead of read again.
+
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use rembed::graph::Graph;
+
+/// Heap usage, see [`Graph::memory_bytes`], above which the least recently
+/// used graphs are dropped
+pub const GRAPH_CACHE_BYTES: usize = 4 << 30;
+
+/// Graph file, embedding dimension and dimension hint a graph was weighted for
+type GraphKey = (String, usize, usize);
+
+/// Graph of one key, locked while it is parsed so that workers asking for the
+/// same key wait for it instead of parsing it again. Empty if parsing failed.
+type Slot = Arc<Mutex<Option<Arc<Graph>>>>;
+
+/// Least recently used cache of parsed graphs, bounded by their heap usage.
+/// The graph used last is kept even if it alone exceeds the bound. Graphs
+/// are parsed outside of the lock of the cache, so workers loading other
+/// graphs are not held up.
+pub struct GraphCache {
+    max_bytes: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    bytes: usize,
+    /// Graph and tick of its last use
+    graphs: HashMap<GraphKey, (Slot, u64)>,
+    tick: u64,
+    /// Files read, for the summary after a run
+    parsed: usize,
+    /// Graphs taken from the cache or reweighted
+    reused: usize,
+}
+
+impl GraphCache {
+    pub fn new(max_bytes: usize) -> Self {
+        GraphCache {
+            max_bytes,
+            state: Mutex::default(),
+        }
+    }
+
+    /// The graph at `path` weighted for `embedding_dim` and `dim_hint`, see
+    /// [`Graph::parse_from_edge_list_file`]
+    pub fn get(&self, path: &str, embedding_dim: usize, dim_hint: usize) -> io::Result<Arc<Graph>> {
+        let key = (path.to_string(), embedding_dim, dim_hint);
+        let (slot, same_file) = {
+            let mut state = self.state.lock().unwrap();
+            state.tick += 1;
+            let tick = state.tick;
+            match state.graphs.get_mut(&key) {
+                Some((slot, used)) => {
+                    *used = tick;
+                    (slot.clone(), None)
+                }
+                None => {
+                    // Preferably a graph of the file that is already parsed.
+                    // Otherwise one that is being parsed, which is waited for
+                    // below; slots only ever wait for older ones.
+                    let mut same_file: Vec<_> = state
+                        .graphs
+                        .iter()
+                        .filter(|((file, ..), _)| file == path)
+                        .map(|(_, (slot, _))| slot.clone())
+                        .collect();
+                    same_file
+                        .sort_by_key(|slot| !slot.try_lock().is_ok_and(|graph| graph.is_some()));
+                    let same_file = same_file.into_iter().next();
+                    let slot = Slot::default();
+                    state.graphs.insert(key.clone(), (slot.clone(), tick));
+                    (slot, same_file)
+                }
+            }
+        };
+
+        let mut graph = slot.lock().unwrap();
+        if let Some(graph) = &*graph {
+            self.state.lock().unwrap().reused += 1;
+            return Ok(graph.clone());
+        }
+        let same_file = same_file.and_then(|slot| slot.lock().unwrap().clone());
+        let reused = same_file.is_some();
+        let parsed = match same_file {
+            Some(same_file) => same_file.reweighted(embedding_dim, dim_hint),
+            None => Graph::parse_from_edge_list_file(path, embedding_dim, dim_hint)?,
+        };
+        let parsed = Arc::new(parsed);
+        *graph = Some(parsed.clone());
+        drop(graph);
+
+        let mut state = self.state.lock().unwrap();
+        if reused {
+            state.reused += 1;
+        } else {
+            state.parsed += 1;
+        }
+        // The slot may have been evicted while the graph was parsed
+        if state
+            .graphs
+            .get(&key)
+            .is_some_and(|(cached, _)| Arc::ptr_eq(cached, &slot))
+        {
+            state.bytes += parsed.memory_bytes();
+            self.evict(&mut state, &key);
+        }
+        Ok(parsed)
+    }
+
+    /// Drops the least recently used graphs other than `keep` until the rest
+    /// fit the bound. Graphs that are being parsed stay.
+    fn evict(&self, state: &mut CacheState, keep: &GraphKey) {
+        while state.bytes > self.max_bytes {
+            let oldest = state
+                .graphs
+                .iter()
+                .filter(|(key, _)| *key != keep)
+                .filter_map(|(key, (slot, used))| {
+                    let bytes = slot.try_lock().ok()?.as_ref()?.memory_bytes();
+                    Some((key, *used, bytes))
+                })
+                .min_by_key(|(_, used, _)| *used)
+                .map(|(key, _, bytes)| (key.clone(), bytes));
+            let Some((oldest, bytes)) = oldest else {
+                break;
+            };
+            state.graphs.remove(&oldest);
+            state.bytes -= bytes;
+        }
+    }
+
+    /// Number of graph files read
+    pub fn parsed(&self) -> usize {
+        self.state.lock().unwrap().parsed
+    }
+
+    /// Number of graphs taken from the cache or reweighted
+    pub fn reused(&self) -> usize {
+        self.state.lock().unwrap().reused
+    }
+
+    /// Number of cached graphs, including the ones being parsed
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().graphs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Edge list of a `side` x `side` grid in a temporary file
+    fn grid_file(name: &str, side: usize) -> String {
+        let mut edges = String::new();
+        for u in 0..side * side {
+            if u % side + 1 < side {
+                edges.push_str(&format!("{u} {}\n", u + 1));
+            }
+            if u + side < side * side {
+                edges.push_str(&format!("{u} {}\n", u + side));
+            }
+        }
+        let path = std::env::temp_dir().join(format!("{name}-{}.txt", std::process::id()));
+        std::fs::write(&path, edges).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn graphs_are_parsed_once_per_file() {
+        let path = grid_file("graph-cache-reuse", 10);
+        let cache = GraphCache::new(GRAPH_CACHE_BYTES);
+
+        let first = cache.get(&path, 2, 2).unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.get(&path, 2, 2).unwrap()));
+        let other_dim = cache.get(&path, 8, 2).unwrap();
+        let parsed = Graph::parse_from_edge_list_file(&path, 8, 2).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((cache.parsed(), cache.reused()), (1, 2));
+        assert_eq!(cache.len(), 2);
+        for (a, b) in other_dim.nodes.iter().zip(&parsed.nodes) {
+            assert_eq!(a.weight, b.weight);
+        }
+    }
+
+    #[test]
+    fn least_recently_used_graphs_are_dropped() {
+        let paths: Vec<_> = (0..3)
+            .map(|i| grid_file(&format!("graph-cache-evict-{i}"), 10))
+            .collect();
+        let bytes = Graph::parse_from_edge_list_file(&paths[0], 2, 2)
+            .unwrap()
+            .memory_bytes();
+        // Room for two of the graphs
+        let cache = GraphCache::new(2 * bytes + bytes / 2);
+
+        cache.get(&paths[0], 2, 2).unwrap();
+        cache.get(&paths[1], 2, 2).unwrap();
+        cache.get(&paths[0], 2, 2).unwrap();
+        cache.get(&paths[2], 2, 2).unwrap();
+        assert_eq!(cache.len(), 2);
+        // The second graph was used least recently and has to be read again
+        cache.get(&paths[0], 2, 2).unwrap();
+        cache.get(&paths[1], 2, 2).unwrap();
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert_eq!((cache.parsed(), cache.reused()), (4, 2));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn concurrent_workers_parse_a_graph_once() {
+        let path = grid_file("graph-cache-concurrent", 30);
+        let cache = GraphCache::new(GRAPH_CACHE_BYTES);
+        let graphs: Vec<_> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| cache.get(&path, 2, 2).unwrap()))
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((cache.parsed(), cache.reused()), (1, 3));
+        assert!(graphs.iter().all(|graph| Arc::ptr_eq(graph, &graphs[0])));
+    }
+}
@@ -135,12 +135,21 @@ impl PerfMeasurements {
         self.samples.push(elapsed);
         elapsed.wall_time
     }
-    /// Get statistics from all collected measurements
-    pub fn get_statistics(&self, queries: usize, warmup: Duration) -> PerfStatistics {
+    /// Number of leading samples that were taken while the caches and branch
+    /// predictors were still warming up, see [`detect_warmup`].
+    pub fn detect_warmup_samples(&self) -> usize {
+        detect_warmup(&self.wall_times_ns())
+    }
+
+    /// Get statistics from the collected measurements, skipping the warm-up
+    /// samples. The samples time whole batches of queries, so the
+    /// [`PerfStatistics::wall_time_histogram`] is left empty.
+    pub fn get_statistics(&self, queries: usize) -> PerfStatistics {
         let samples = queries as u64;
-        let mut measurements: Vec<_> = self
+        let measurements: Vec<_> = self
             .samples
             .iter()
+            .skip(self.detect_warmup_samples())
             .map(|x| {
                 let divisor = samples * x.iterations;
                 PerfMeasurement {
@@ -153,12 +162,6 @@ impl PerfMeasurements {
             })
             .collect();
 
-        let mut acc = Duration::default();
-        measurements.retain(|x| {
-            acc += x.wall_time;
-            acc > warmup / (samples * x.iterations) as u32
-        });
-
         let count = measurements.len() as f64;
 
         if measurements.is_empty() {
@@ -220,6 +223,8 @@ impl PerfMeasurements {
             .sum::<f64>()
             / count;
 
+        let wall_time_stderr = (wall_time_variance / count).sqrt();
+
         let ref_cycles_stddev = ref_cycles_mean.map(|mean| {
             let variance = measurements
                 .iter()
@@ -235,30 +240,193 @@ impl PerfMeasurements {
         PerfStatistics {
             wall_time_mean: Duration::from_nanos(wall_time_mean as u64),
             wall_time_stddev: Duration::from_nanos(wall_time_variance.sqrt() as u64),
+            wall_time_stderr: Duration::from_nanos(wall_time_stderr as u64),
             instructions_mean,
             instructions_stddev: instructions_variance.sqrt(),
             cycles_mean,
             cycles_stddev: cycles_variance.sqrt(),
             ref_cycles_mean,
             ref_cycles_stddev,
+            wall_time_histogram: Vec::new(),
         }
     }
 
     pub(crate) fn num_samples(&self) -> usize {
         self.samples.len()
     }
+
+    /// Wall time per iteration of every collected sample in nanoseconds
+    pub fn wall_times_ns(&self) -> Vec<f64> {
+        self.samples
+            .iter()
+            .map(|x| x.wall_time.as_nanos() as f64 / x.iterations.max(1) as f64)
+            .collect()
+    }
+
+    /// Like [`PerfMeasurements::wall_times_ns`], without the warm-up samples
+    pub fn steady_wall_times_ns(&self) -> Vec<f64> {
+        let mut wall_times = self.wall_times_ns();
+        wall_times.drain(..self.detect_warmup_samples());
+        wall_times
+    }
+}
+
+/// Slowdown relative to the steady state up to which a sample no longer counts
+/// as warm-up
+const WARMUP_TOLERANCE: f64 = 0.1;
+
+/// Detects how many leading samples belong to the warm-up phase.
+///
+/// The steady state is the median of the second half of the samples. The
+/// warm-up ends once the wall time has dropped to within [`WARMUP_TOLERANCE`]
+/// of it. At most half of the samples are discarded.
+pub fn detect_warmup(wall_times: &[f64]) -> usize {
+    if wall_times.len() < 4 {
+        return 0;
+    }
+    let mut steady = wall_times[wall_times.len() / 2..].to_vec();
+    steady.sort_unstable_by(f64::total_cmp);
+    let threshold = steady[steady.len() / 2] * (1.0 + WARMUP_TOLERANCE);
+    wall_times
+        .iter()
+        .position(|&time| time <= threshold)
+        .unwrap_or(0)
+        .min(wall_times.len() / 2)
+}
+
+/// Number of latency histogram buckets per factor of ten
+pub const HISTOGRAM_BUCKETS_PER_DECADE: usize = 10;
+/// Lower bound of the first latency histogram bucket in nanoseconds
+pub const HISTOGRAM_MIN_NS: f64 = 1.0;
+/// Number of latency histogram buckets, spanning 1 ns to 1 s
+pub const HISTOGRAM_BUCKETS: usize = 9 * HISTOGRAM_BUCKETS_PER_DECADE;
+
+/// Bucket of a wall time in nanoseconds. Bucket `i` covers
+/// `[HISTOGRAM_MIN_NS * 10^(i / HISTOGRAM_BUCKETS_PER_DECADE), ..)`, times
+/// outside of the covered range fall into the first or last bucket.
+pub fn histogram_bucket(wall_time_ns: f64) -> usize {
+    let bucket = (wall_time_ns / HISTOGRAM_MIN_NS).log10() * HISTOGRAM_BUCKETS_PER_DECADE as f64;
+    // `as` saturates, so zero and NaN end up in the first bucket
+    (bucket.floor() as usize).min(HISTOGRAM_BUCKETS - 1)
+}
+
+/// Lower bound of `bucket` in nanoseconds, see [`histogram_bucket`]
+pub fn histogram_bucket_start_ns(bucket: usize) -> f64 {
+    HISTOGRAM_MIN_NS * 10f64.powf(bucket as f64 / HISTOGRAM_BUCKETS_PER_DECADE as f64)
+}
+
+/// Counts of `wall_times` in nanoseconds per histogram bucket
+pub fn latency_histogram(wall_times: &[f64]) -> Vec<i64> {
+    let mut counts = vec![0; HISTOGRAM_BUCKETS];
+    for &time in wall_times {
+        counts[histogram_bucket(time)] += 1;
+    }
+    counts
+}
+
+/// Coefficient of variation, the standard deviation over the mean, of
+/// `samples`. `None` for fewer than two samples or a zero mean.
+pub fn coefficient_of_variation(samples: &[f64]) -> Option<f64> {
+    if samples.len() < 2 {
+        return None;
+    }
+    let count = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / count;
+    if mean == 0.0 {
+        return None;
+    }
+    let variance = samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / count;
+    Some(variance.sqrt() / mean.abs())
+}
+
+/// Relative standard error of the mean of `samples`, `None` for fewer than two
+/// samples or a zero mean.
+pub fn relative_standard_error(samples: &[f64]) -> Option<f64> {
+    coefficient_of_variation(samples).map(|cv| cv / (samples.len() as f64).sqrt())
+}
+
+/// Stopping rule for adaptive measurements: keep sampling until `statistic`
+/// of the wall times, by default the relative standard error of their mean,
+/// drops below `target`, or until either `max_samples` or `max_time` is
+/// reached.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveStop {
+    pub target: f64,
+    pub statistic: fn(&[f64]) -> Option<f64>,
+    pub min_samples: usize,
+    pub max_samples: usize,
+    pub max_time: Duration,
+}
+
+impl AdaptiveStop {
+    pub fn new(target: f64, max_time: Duration) -> Self {
+        Self {
+            target,
+            statistic: relative_standard_error,
+            min_samples: 5,
+            max_samples: 1000,
+            max_time,
+        }
+    }
+
+    pub fn should_stop(&self, wall_times: &[f64], elapsed: Duration) -> bool {
+        if wall_times.len() >= self.max_samples || elapsed >= self.max_time {
+            return true;
+        }
+        if wall_times.len() < self.min_samples {
+            return false;
+        }
+        (self.statistic)(wall_times).is_some_and(|value| value <= self.target)
+    }
+}
+
+/// Extension of a fixed size measurement: if the coefficient of variation of
+/// the wall times exceeds `start_cv`, keep sampling until `stop` is met. By
+/// default that is a coefficient of variation of 5% or 100 samples.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveSampling {
+    pub start_cv: f64,
+    pub stop: AdaptiveStop,
+}
+
+impl Default for AdaptiveSampling {
+    fn default() -> Self {
+        Self {
+            start_cv: 0.1,
+            stop: AdaptiveStop {
+                target: 0.05,
+                statistic: coefficient_of_variation,
+                min_samples: 0,
+                max_samples: 100,
+                max_time: Duration::MAX,
+            },
+        }
+    }
+}
+
+impl AdaptiveSampling {
+    /// Whether the fixed samples are too noisy and more should be taken
+    pub fn should_extend(&self, wall_times: &[f64]) -> bool {
+        coefficient_of_variation(wall_times).is_some_and(|cv| cv > self.start_cv)
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct PerfStatistics {
     pub wall_time_mean: Duration,
     pub wall_time_stddev: Duration,
+    /// Standard error of `wall_time_mean`
+    pub wall_time_stderr: Duration,
     pub instructions_mean: f64,
     pub instructions_stddev: f64,
     pub cycles_mean: f64,
     pub cycles_stddev: f64,
     pub ref_cycles_mean: Option<f64>,
     pub ref_cycles_stddev: Option<f64>,
+    /// Number of queries per [`histogram_bucket`] of their own wall time, or
+    /// of runs for position updates. Filled in by the runner, which times
+    /// the queries one by one, see [`latency_histogram`].
+    pub wall_time_histogram: Vec<i64>,
 }
 
 #[cfg(test)]
@@ -282,4 +450,114 @@ mod tests {
         assert!(result.instructions > 0);
         // Cycles might be 0 in some virtualized environments, so we don't assert on it
     }
+
+    /// Feeds `stream` into `rule` and returns the number of samples taken
+    /// before it stopped, treating each sample as its own duration in ns.
+    fn samples_until_stop(rule: &AdaptiveStop, stream: impl Iterator<Item = f64>) -> usize {
+        let mut samples = Vec::new();
+        let mut elapsed = Duration::ZERO;
+        for sample in stream {
+            samples.push(sample);
+            elapsed += Duration::from_nanos(sample as u64);
+            if rule.should_stop(&samples, elapsed) {
+                break;
+            }
+        }
+        samples.len()
+    }
+
+    #[test]
+    fn adaptive_stop_on_target_error() {
+        let rule = AdaptiveStop::new(0.03, Duration::from_secs(3600));
+        // σ = 10 around a mean of 100, so the relative error is 0.1 / sqrt(n)
+        let stream = (0..).map(|i| if i % 2 == 0 { 90.0 } else { 110.0 });
+        assert_eq!(samples_until_stop(&rule, stream), 12);
+
+        let rule = AdaptiveStop::new(0.015, Duration::from_secs(3600));
+        let stream = (0..).map(|i| if i % 2 == 0 { 90.0 } else { 110.0 });
+        assert_eq!(samples_until_stop(&rule, stream), 45);
+    }
+
+    #[test]
+    fn adaptive_stop_respects_min_samples() {
+        let rule = AdaptiveStop::new(0.02, Duration::from_secs(3600));
+        let stream = std::iter::repeat(100.0);
+        assert_eq!(samples_until_stop(&rule, stream), rule.min_samples);
+    }
+
+    #[test]
+    fn warmup_detection() {
+        // Three slow samples decaying towards a noisy steady state of 100
+        let mut samples = vec![400.0, 220.0, 130.0];
+        samples.extend((0..20).map(|i| if i % 2 == 0 { 97.0 } else { 104.0 }));
+        assert_eq!(detect_warmup(&samples), 3);
+
+        let steady: Vec<f64> = (0..20).map(|i| 100.0 + (i % 3) as f64).collect();
+        assert_eq!(detect_warmup(&steady), 0);
+
+        // A run that keeps speeding up never discards more than half
+        let decaying: Vec<f64> = (1..=10).map(|i| 1000.0 / i as f64).collect();
+        assert_eq!(detect_warmup(&decaying), 5);
+        assert_eq!(detect_warmup(&[500.0, 100.0]), 0);
+    }
+
+    #[test]
+    fn latency_histogram_buckets() {
+        assert_eq!(histogram_bucket(0.0), 0);
+        assert_eq!(histogram_bucket(1.0), 0);
+        assert_eq!(histogram_bucket(10.0), HISTOGRAM_BUCKETS_PER_DECADE);
+        assert_eq!(histogram_bucket(1e12), HISTOGRAM_BUCKETS - 1);
+        for bucket in 1..HISTOGRAM_BUCKETS {
+            let start = histogram_bucket_start_ns(bucket);
+            assert_eq!(histogram_bucket(start * 1.001), bucket);
+            assert_eq!(histogram_bucket(start * 0.999), bucket - 1);
+        }
+
+        let wall_times: Vec<f64> = (0..1000).map(|i| (i * i) as f64 * 0.37).collect();
+        let histogram = latency_histogram(&wall_times);
+        assert_eq!(histogram.len(), HISTOGRAM_BUCKETS);
+        assert_eq!(histogram.iter().sum::<i64>(), wall_times.len() as i64);
+    }
+
+    #[test]
+    fn adaptive_stop_caps() {
+        let mut rule = AdaptiveStop::new(0.0001, Duration::from_secs(3600));
+        rule.max_samples = 50;
+        let stream = (0..).map(|i| if i % 2 == 0 { 10.0 } else { 1000.0 });
+        assert_eq!(samples_until_stop(&rule, stream), 50);
+
+        let rule = AdaptiveStop::new(0.0001, Duration::from_nanos(1000));
+        let stream = (0..).map(|i| if i % 2 == 0 { 90.0 } else { 110.0 });
+        assert_eq!(samples_until_stop(&rule, stream), 10);
+    }
+
+    #[test]
+    fn adaptive_sampling_extends_noisy_runs() {
+        let rule = AdaptiveSampling::default();
+        let stop = |samples: &[f64]| rule.stop.should_stop(samples, Duration::ZERO);
+        // σ = 5 around a mean of 100 is a coefficient of variation of 0.05
+        let steady: Vec<f64> = (0..10)
+            .map(|i| if i % 2 == 0 { 95.0 } else { 105.0 })
+            .collect();
+        assert!(!rule.should_extend(&steady));
+        // A single slow sample out of ten
+        let mut noisy = vec![100.0; 9];
+        noisy.push(140.0);
+        assert!(rule.should_extend(&noisy));
+
+        // Quiet samples dilute the outlier until the target is reached
+        let mut samples = noisy;
+        while !stop(&samples) {
+            samples.push(100.0);
+        }
+        assert!(samples.len() < rule.stop.max_samples);
+        assert!(coefficient_of_variation(&samples).unwrap() <= rule.stop.target);
+
+        // Samples that stay noisy run into the cap
+        let mut samples = vec![80.0, 120.0];
+        while !stop(&samples) {
+            samples.push(if samples.len() % 2 == 0 { 80.0 } else { 120.0 });
+        }
+        assert_eq!(samples.len(), rule.stop.max_samples);
+    }
 }
@@ -1,9 +1,15 @@
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use super::perf_measurement::{PerfMeasurements, PerfStatistics};
+use super::perf_measurement::{
+    AdaptiveSampling, AdaptiveStop, PerfMeasurements, PerfStatistics, latency_histogram,
+};
 use criterion::{BenchmarkGroup, measurement::WallTime};
-use rembed::{Embedding, NodeId, query::IndexClone};
+use rembed::{
+    Embedding, NodeId,
+    dvec::DVec,
+    query::{IndexClone, SpatialIndex},
+};
 
 #[derive(Debug, Clone)]
 pub enum BenchmarkType {
@@ -13,8 +19,13 @@ pub enum BenchmarkType {
     HeavyNodes,
     AllNodes,
     Radius(f32, String),
+    /// The mixed node queries at each of [`SWEEP_RADII`]
+    RadiusSweep,
 }
 
+/// Radius factors measured by [`BenchmarkType::RadiusSweep`]
+pub const SWEEP_RADII: [f64; 5] = [0.5, 1.0, 1.5, 2.0, 4.0];
+
 impl BenchmarkType {
     pub fn as_str(&self) -> &str {
         match self {
@@ -24,6 +35,7 @@ impl BenchmarkType {
             BenchmarkType::HeavyNodes => "heavy_nodes",
             BenchmarkType::AllNodes => "all_nodes",
             BenchmarkType::Radius(_, reference) => reference.as_str(),
+            BenchmarkType::RadiusSweep => "radius_sweep",
         }
     }
 
@@ -48,6 +60,7 @@ impl FromStr for BenchmarkType {
             "light_nodes" => BenchmarkType::LightNodes,
             "heavy_nodes" => BenchmarkType::HeavyNodes,
             "all_nodes" => BenchmarkType::AllNodes,
+            "radius_sweep" => BenchmarkType::RadiusSweep,
             s if s.starts_with("radius_") => {
                 let parts: Vec<&str> = s.splitn(2, '_').collect();
                 if parts.len() != 2 {
@@ -68,16 +81,24 @@ pub struct BenchmarkResult {
     pub data_structure_name: String,
     pub result_id: i64,
     pub iteration_number: usize,
+    pub query_radius: f64,
     pub sample_count: usize,
+    pub adaptive_samples: bool,
+    /// Seed the queries were drawn with, `None` if they were evenly spaced
+    pub sampling_seed: Option<u64>,
     pub measurement: PerfStatistics,
 }
 pub struct MeasurementResult {
     pub data_structure_name: String,
     pub sample_count: usize,
+    /// Whether the fixed sample count was extended because the samples
+    /// varied too much, see [`AdaptiveSampling`]
+    pub adaptive_samples: bool,
     pub measurement: PerfStatistics,
     pub avg_returned_points: f64,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn profile_datastructures<'a, const D: usize>(
     embedding: &Embedding<'a, D>,
     c: &mut BenchmarkGroup<WallTime>,
@@ -85,11 +106,13 @@ pub fn profile_datastructures<'a, const D: usize>(
     query_list: &[NodeId],
     benchmark_type: BenchmarkType,
     fast: bool,
+    precision: Option<f64>,
 ) -> Vec<MeasurementResult> {
     let mut results = Vec::with_capacity(data_structures.len());
     for structure in data_structures {
         results.push(profile_datastructure_query(
             embedding,
+            None,
             c,
             query_list,
             None,
@@ -98,22 +121,38 @@ pub fn profile_datastructures<'a, const D: usize>(
             benchmark_type.clone(),
             structure.as_ref(),
             fast,
+            precision,
         ));
     }
     results
 }
 
+/// Profiles `structure` on the given queries.
+///
+/// A [`BenchmarkType::PositionUpdate`] rebuilds a clone of the structure
+/// from scratch on every run. With `previous_positions`, the positions of
+/// the iteration before `embedding`, it instead alternates between the two
+/// iterations, so each run is one warm started update by the delta of an
+/// iteration, see [`rembed::query::Update::moved_in_last_update`].
+///
+/// Without a `precision` the measurement is driven by criterion with fixed
+/// warmup and measurement times, and extended by [`AdaptiveSampling`] if the
+/// samples vary too much. With a `precision` the queries are sampled
+/// directly until the relative standard error of the mean wall time drops
+/// below it, capped by the measurement time.
 #[allow(clippy::too_many_arguments)]
 pub fn profile_datastructure_query<'a, const D: usize>(
     embedding: &Embedding<'a, D>,
+    previous_positions: Option<&[DVec<D>]>,
     c: &mut BenchmarkGroup<WallTime>,
     query_list: &[usize],
-    query_pos_list: Option<Vec<rembed::dvec::DVec<D>>>,
+    query_pos_list: Option<Vec<DVec<D>>>,
     radius: Option<f64>,
     query_radii: Option<Vec<f64>>,
     benchmark_type: BenchmarkType,
     structure: &(dyn IndexClone<D> + 'a),
     fast: bool,
+    precision: Option<f64>,
 ) -> MeasurementResult {
     let mut samples = PerfMeasurements::new(1000);
     let mut warmup = Duration::from_secs(3);
@@ -135,81 +174,154 @@ pub fn profile_datastructure_query<'a, const D: usize>(
     } else {
         query_list.len()
     };
-    let mut result_counts = Vec::new();
-    if let Some(query_pos_list) = query_pos_list {
-        if let Some(ref radii) = query_radii {
-            assert_eq!(
-                radii.len(),
-                query_pos_list.len(),
-                "query_radii length must match the number of query points"
-            );
+    if let Some(ref query_pos_list) = query_pos_list
+        && let Some(ref radii) = query_radii
+    {
+        assert_eq!(
+            radii.len(),
+            query_pos_list.len(),
+            "query_radii length must match the number of query points"
+        );
+    }
+    // Queries only read the structure. Position updates change it, so they
+    // run on a clone, made once since on big graphs cloning can take longer
+    // than the measurement itself.
+    let mut updated = matches!(benchmark_type, BenchmarkType::PositionUpdate).then(|| {
+        let start = Instant::now();
+        let clone = structure.clone_box();
+        println!(
+            "Cloning {} for '{}' took {:?}",
+            structure.name(),
+            benchmark_id,
+            start.elapsed()
+        );
+        clone
+    });
+    let mut at_previous = false;
+    let mut run = |results: &mut Vec<NodeId>| match updated.as_deref_mut() {
+        Some(updated) => {
+            match previous_positions {
+                Some(previous) => {
+                    let target = if at_previous {
+                        &embedding.positions
+                    } else {
+                        previous
+                    };
+                    updated.update_positions(target, None);
+                    at_previous = !at_previous;
+                }
+                None => {
+                    updated.discard_warm_start();
+                    updated.update_positions(&embedding.positions, None);
+                }
+            }
+            0
         }
+        None => run_queries(
+            structure,
+            query_list,
+            query_pos_list.as_deref(),
+            radius,
+            query_radii.as_deref(),
+            results,
+            None,
+        ),
+    };
+    let mut result_counts = Vec::new();
+    let mut adaptive_samples = false;
+    if let Some(precision) = precision {
+        let rule = AdaptiveStop::new(precision, measure);
         println!(
-            "Running benchmark '{}' with {} queries",
+            "Running benchmark '{}' with {} queries until a relative error of {:.1}%",
             benchmark_id,
-            query_pos_list.len()
+            queries,
+            precision * 100.
         );
-        c.bench_with_input(benchmark_id, &structure.name(), |b, _| {
+        let mut results = Vec::with_capacity(structure.num_nodes());
+        let warmup_start = Instant::now();
+        while warmup_start.elapsed() < warmup {
+            run(&mut results);
+        }
+
+        let measure_start = Instant::now();
+        loop {
+            samples.start();
+            let num_results = run(&mut results);
+            samples.stop(1);
+            result_counts.push(num_results as f64 / queries as f64);
+            if rule.should_stop(&samples.wall_times_ns(), measure_start.elapsed()) {
+                break;
+            }
+        }
+    } else {
+        if query_pos_list.is_some() {
+            println!(
+                "Running benchmark '{}' with {} queries",
+                benchmark_id, queries
+            );
+        }
+        c.bench_with_input(&benchmark_id, &structure.name(), |b, _| {
             b.iter_custom(|iters| {
-                // let data_structures: Vec<_> = (0..iters).map(|_| structure.clone_box()).collect();
-                let mut structure = structure.clone_box();
                 let mut results = Vec::with_capacity(structure.num_nodes());
                 let mut num_results = 0;
                 samples.start();
                 for _ in 0..iters {
-                    // for mut structure in data_structures {
-                    match benchmark_type {
-                        BenchmarkType::PositionUpdate => {
-                            structure.update_positions(&embedding.positions, None);
-                        }
-                        _ => {
-                            for (i, &pos) in query_pos_list.iter().enumerate() {
-                                let query_radius = match query_radii {
-                                    Some(ref radii) => radii[i],
-                                    None => radius.expect(
-                                        "Radius must be provided for queryset benchmarks",
-                                    ),
-                                };
-                                results.clear();
-                                structure.query_radius(pos, query_radius, &mut results);
-                                num_results += results.len();
-                                std::hint::black_box(&results);
-                            }
-                        }
-                    }
+                    num_results += run(&mut results);
                 }
                 result_counts.push(num_results as f64 / (queries as u64 * iters) as f64);
                 samples.stop(iters) / queries as u32
             });
         });
-    } else {
-        c.bench_with_input(benchmark_id, &structure.name(), |b, _| {
-            b.iter_custom(|iters| {
-                // let data_structures: Vec<_> = (0..iters).map(|_| structure.clone_box()).collect();
-                let mut structure = structure.clone_box();
-                let mut results = Vec::with_capacity(structure.num_nodes());
+
+        let rule = AdaptiveSampling::default();
+        if rule.should_extend(&samples.steady_wall_times_ns()) {
+            adaptive_samples = true;
+            println!(
+                "High variance for '{}', sampling until a coefficient of variation of {:.0}%",
+                benchmark_id,
+                rule.stop.target * 100.
+            );
+            let mut results = Vec::with_capacity(structure.num_nodes());
+            let extend_start = Instant::now();
+            while !rule
+                .stop
+                .should_stop(&samples.steady_wall_times_ns(), extend_start.elapsed())
+            {
                 samples.start();
-                for _ in 0..iters {
-                    // for mut structure in data_structures {
-                    match benchmark_type {
-                        BenchmarkType::PositionUpdate => {
-                            structure.update_positions(&embedding.positions, None);
-                        }
-                        _ => {
-                            for &i in query_list {
-                                results.clear();
-                                structure.nearest_neighbors(i, 1., &mut results);
-                                std::hint::black_box(&results);
-                            }
-                        }
-                    }
-                }
-                samples.stop(iters) / queries as u32
-            });
-        });
+                let num_results = run(&mut results);
+                samples.stop(1);
+                result_counts.push(num_results as f64 / queries as f64);
+            }
+        }
     }
 
-    let statistics = samples.get_statistics(queries, warmup);
+    let mut statistics = samples.get_statistics(queries);
+    // The samples average over all queries of a run, which hides how fast
+    // light and slow hub queries are on their own, so the queries are timed
+    // one by one in a pass of their own
+    statistics.wall_time_histogram = if updated.is_some() {
+        latency_histogram(&samples.steady_wall_times_ns())
+    } else {
+        let mut latencies = Vec::with_capacity(queries);
+        run_queries(
+            structure,
+            query_list,
+            query_pos_list.as_deref(),
+            radius,
+            query_radii.as_deref(),
+            &mut Vec::with_capacity(structure.num_nodes()),
+            Some(&mut latencies),
+        );
+        latency_histogram(&latencies)
+    };
+    if previous_positions.is_some()
+        && let Some(moved) = updated.as_ref().and_then(|s| s.moved_in_last_update())
+    {
+        println!(
+            "{benchmark_id}: {moved} of {} nodes changed places in the last update",
+            embedding.positions.len()
+        );
+    }
 
     let mean_results = result_counts.iter().sum::<f64>() / result_counts.len() as f64;
 
@@ -230,14 +342,108 @@ pub fn profile_datastructure_query<'a, const D: usize>(
             format_number(stddev)
         );
     }
+    if precision.is_some() {
+        eprintln!(
+            "\tWall time: {:?} ± {:?} ({} samples)\n",
+            statistics.wall_time_mean,
+            statistics.wall_time_stderr,
+            samples.num_samples()
+        );
+    }
     MeasurementResult {
         data_structure_name: structure.name(),
         sample_count: samples.num_samples(),
+        adaptive_samples,
         measurement: statistics,
         avg_returned_points: mean_results,
     }
 }
 
+/// Runs every query of the benchmark once and returns the total number of
+/// returned points. With `latencies`, also pushes the wall time of every
+/// query in nanoseconds, which includes reading the clock.
+fn run_queries<const D: usize>(
+    structure: &dyn SpatialIndex<D>,
+    query_list: &[NodeId],
+    query_pos_list: Option<&[DVec<D>]>,
+    radius: Option<f64>,
+    query_radii: Option<&[f64]>,
+    results: &mut Vec<NodeId>,
+    mut latencies: Option<&mut Vec<f64>>,
+) -> usize {
+    let mut num_results = 0;
+    let mut timed = |query: &mut dyn FnMut(&mut Vec<NodeId>)| {
+        results.clear();
+        let start = latencies.is_some().then(Instant::now);
+        query(results);
+        if let (Some(latencies), Some(start)) = (latencies.as_deref_mut(), start) {
+            latencies.push(start.elapsed().as_nanos() as f64);
+        }
+        num_results += results.len();
+        std::hint::black_box(&results);
+    };
+    match query_pos_list {
+        Some(query_pos_list) => {
+            for (i, &pos) in query_pos_list.iter().enumerate() {
+                let query_radius = match query_radii {
+                    Some(radii) => radii[i],
+                    None => radius.expect("Radius must be provided for queryset benchmarks"),
+                };
+                timed(&mut |results: &mut Vec<NodeId>| {
+                    structure.query_radius(pos, query_radius, results)
+                });
+            }
+        }
+        None => {
+            for &i in query_list {
+                timed(&mut |results: &mut Vec<NodeId>| {
+                    structure.nearest_neighbors(i, radius.unwrap_or(1.), results)
+                });
+            }
+        }
+    }
+    num_results
+}
+
+/// Number of distinct nodes `structure` returns for the queries that lie
+/// within the queried radius of the pair, i.e. at most
+/// `radius * w_i * w_j` away from the query node `i`.
+///
+/// Exact structures may return different candidate supersets, but should
+/// agree on this count.
+pub fn in_radius_count<const D: usize>(
+    structure: &dyn SpatialIndex<D>,
+    embedding: &Embedding<'_, D>,
+    query_list: &[NodeId],
+    radius: f64,
+) -> usize {
+    query_list
+        .iter()
+        .map(|&i| in_radius_neighbors(structure, embedding, i, radius).len())
+        .sum()
+}
+
+/// Sorted neighbors of `index` that `structure` returns and that actually lie
+/// within `radius`, without `index` itself, see [`in_radius_count`]
+pub fn in_radius_neighbors<const D: usize>(
+    structure: &dyn SpatialIndex<D>,
+    embedding: &Embedding<'_, D>,
+    index: NodeId,
+    radius: f64,
+) -> Vec<NodeId> {
+    let mut results = Vec::new();
+    structure.nearest_neighbors(index, radius, &mut results);
+    results.sort_unstable();
+    results.dedup();
+    let weight = embedding.graph.nodes[index].weight;
+    let position = embedding.positions[index];
+    results.retain(|&j| {
+        let limit = (radius * weight * embedding.graph.nodes[j].weight) as f32;
+        j != index && position.distance_squared(&embedding.positions[j]) <= limit * limit
+    });
+    results
+}
+
 pub fn format_number(num: f64) -> String {
     if num < 1000. {
         format!("{:.2}", num)
@@ -249,3 +455,35 @@ pub fn format_number(num: f64) -> String {
         format!("{:.1}G", num / 1_000_000_000.)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rembed::{Embedding, dvec::DVec, graph::Graph};
+
+    use super::run_queries;
+
+    #[test]
+    fn every_query_is_timed_on_its_own() {
+        let edges = (0..99).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let embedding = Embedding {
+            positions: (0..100).map(|i| DVec::new([i as f32, 0.])).collect(),
+            graph: &graph,
+        };
+        let queries = [0, 5, 50, 99];
+        let mut results = Vec::new();
+        let mut latencies = Vec::new();
+        let found = run_queries(
+            &embedding,
+            &queries,
+            None,
+            None,
+            None,
+            &mut results,
+            Some(&mut latencies),
+        );
+        assert_eq!(latencies.len(), queries.len());
+        let untimed = run_queries(&embedding, &queries, None, None, None, &mut results, None);
+        assert_eq!(found, untimed);
+    }
+}
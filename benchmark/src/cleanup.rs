@@ -2,6 +2,8 @@ use std::{collections::HashSet, env, path::Path};
 
 use sqlx::PgPool;
 
+use crate::generate_positions::calculate_file_checksum;
+
 // Add this function to main.rs
 pub async fn cleanup_orphaned_files(
     pool: &PgPool,
@@ -159,3 +161,163 @@ fn scan_directory(
     }
     Ok(())
 }
+
+/// Problem found for a single database row while verifying files.
+enum FileIssue {
+    Missing,
+    ChecksumMismatch { expected: String, actual: String },
+}
+
+/// Checks that every file referenced in the database exists on disk and that
+/// its stored checksum matches. With `fix`, broken position results are
+/// flagged by clearing their checksum and their jobs reset to pending, so
+/// the next run rewrites the file while the measurements computed from it
+/// stay. Broken test rows are removed so that `generate-test` recreates them.
+pub async fn verify_files(pool: &PgPool, fix: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let data_directory = env::var("DATA_DIRECTORY").unwrap_or("../data/".to_string());
+    let data_path = Path::new(&data_directory);
+
+    if !data_path.exists() {
+        return Err(format!("Data directory does not exist: {}", data_directory).into());
+    }
+
+    let check = |file_path: &str, checksum: Option<&str>| -> Option<FileIssue> {
+        let full_path = data_path.join(file_path);
+        if !full_path.exists() {
+            return Some(FileIssue::Missing);
+        }
+        let checksum = checksum?;
+        match calculate_file_checksum(&full_path.to_string_lossy()) {
+            Ok(actual) if actual == checksum.trim() => None,
+            Ok(actual) => Some(FileIssue::ChecksumMismatch {
+                expected: checksum.trim().to_string(),
+                actual,
+            }),
+            Err(_) => Some(FileIssue::Missing),
+        }
+    };
+    let report = |kind: &str, id: i64, file_path: &str, issue: &FileIssue| match issue {
+        FileIssue::Missing => println!("  {kind} {id}: missing file {file_path}"),
+        FileIssue::ChecksumMismatch { expected, actual } => println!(
+            "  {kind} {id}: checksum mismatch for {file_path} (expected {expected}, found {actual})"
+        ),
+    };
+
+    let graphs =
+        sqlx::query!("SELECT graph_id, file_path, checksum FROM graphs WHERE file_path != ''")
+            .fetch_all(pool)
+            .await?;
+    println!("Verifying {} graph files...", graphs.len());
+    let mut broken_graphs = 0;
+    for graph in &graphs {
+        if let Some(issue) = check(&graph.file_path, Some(&graph.checksum)) {
+            report("graph", graph.graph_id, &graph.file_path, &issue);
+            broken_graphs += 1;
+        }
+    }
+
+    let positions = sqlx::query!(
+        "SELECT result_id, graph_id, embedding_dim, dim_hint, max_iterations, seed, file_path, checksum
+         FROM position_results WHERE file_path != ''"
+    )
+    .fetch_all(pool)
+    .await?;
+    // Results flagged by an earlier --fix wait for their job to rewrite them
+    let (flagged, positions): (Vec<_>, Vec<_>) = positions
+        .into_iter()
+        .partition(|result| result.checksum.trim().is_empty());
+    if !flagged.is_empty() {
+        println!(
+            "Skipping {} position results awaiting re-generation",
+            flagged.len()
+        );
+    }
+    println!("Verifying {} position files...", positions.len());
+    let mut broken_positions = Vec::new();
+    for result in &positions {
+        if let Some(issue) = check(&result.file_path, Some(&result.checksum)) {
+            report(
+                "position result",
+                result.result_id,
+                &result.file_path,
+                &issue,
+            );
+            broken_positions.push(result);
+        }
+    }
+
+    let tests = sqlx::query!("SELECT result_id, file_path FROM tests WHERE file_path != ''")
+        .fetch_all(pool)
+        .await?;
+    println!("Verifying {} test files...", tests.len());
+    let mut broken_tests = Vec::new();
+    for test in &tests {
+        if let Some(issue) = check(&test.file_path, None) {
+            report("test", test.result_id, &test.file_path, &issue);
+            broken_tests.push(&test.file_path);
+        }
+    }
+
+    let total_broken = broken_graphs + broken_positions.len() + broken_tests.len();
+    if total_broken == 0 {
+        println!("All referenced files are present and match their checksums");
+        return Ok(());
+    }
+
+    println!(
+        "\nFound {} broken graph files, {} broken position files, {} broken test files",
+        broken_graphs,
+        broken_positions.len(),
+        broken_tests.len()
+    );
+
+    if !fix {
+        println!("Run with --fix to mark broken rows for re-generation");
+        return Ok(());
+    }
+
+    if broken_graphs > 0 {
+        println!("Graph files can not be regenerated automatically, please pull them again");
+    }
+
+    let mut tx = pool.begin().await?;
+    for result in &broken_positions {
+        // Deleting the result would cascade to the measurements and tests
+        // computed from it. The cleared checksum flags it instead, and
+        // completing the job again overwrites the row in place.
+        sqlx::query!(
+            "UPDATE position_results SET checksum = '' WHERE result_id = $1",
+            result.result_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE position_jobs
+             SET status = 'pending', claimed_at = NULL, claimed_by_hostname = NULL, completed_at = NULL,
+                 error_message = COALESCE(error_message, '') || ' [Reset due to broken file]'
+             WHERE graph_id = $1 AND embedding_dim = $2 AND dim_hint = $3 AND max_iterations = $4 AND seed = $5",
+            result.graph_id,
+            result.embedding_dim,
+            result.dim_hint,
+            result.max_iterations,
+            result.seed
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    // A result can have test files for several radii, only drop the broken ones
+    for file_path in &broken_tests {
+        sqlx::query!("DELETE FROM tests WHERE file_path = $1", file_path)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    println!(
+        "Reset {} position jobs, flagging their results, and removed {} test entries",
+        broken_positions.len(),
+        broken_tests.len()
+    );
+
+    Ok(())
+}
@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use sqlx::{Pool, Postgres};
+use std::collections::BTreeMap;
 use std::process::Command;
 
 #[derive(Debug, Clone)]
@@ -191,4 +192,300 @@ impl RepoCodeStateManager {
         .fetch_all(&self.pool)
         .await
     }
+
+    /// Speedup of `comparison_commit` over `baseline_commit` for a data
+    /// structure, per embedding dimension.
+    ///
+    /// Measurements are paired by result, iteration, benchmark type, query
+    /// radius and host,
+    /// and the speedup of a dimension is the geometric mean of the paired
+    /// wall time ratios. Commits may be given as hash prefixes. A structure
+    /// whose code did not change between the commits has no code state for
+    /// the later one, so the result is empty.
+    pub async fn compare_structures_across_dims(
+        &self,
+        structure_name: &str,
+        baseline_commit: &str,
+        comparison_commit: &str,
+    ) -> Result<Vec<(usize, f64)>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT pr.embedding_dim AS "dim!",
+                   EXP(AVG(LN(b.wall_time_mean::float8 / c.wall_time_mean::float8))) AS "speedup!"
+            FROM measurements b
+            JOIN code_states bcs ON b.code_state_id = bcs.code_state_id
+            JOIN repository_states brs ON bcs.repo_state_id = brs.repo_state_id
+            JOIN measurements c ON c.result_id = b.result_id
+                AND c.iteration_number = b.iteration_number
+                AND c.benchmark_type = b.benchmark_type
+                AND c.query_radius = b.query_radius
+                AND c.hostname = b.hostname
+            JOIN code_states ccs ON c.code_state_id = ccs.code_state_id
+            JOIN repository_states crs ON ccs.repo_state_id = crs.repo_state_id
+            JOIN position_results pr ON b.result_id = pr.result_id
+            WHERE bcs.data_structure_name = $1 AND ccs.data_structure_name = $1
+              AND brs.commit_hash LIKE $2 || '%' AND crs.commit_hash LIKE $3 || '%'
+              AND b.wall_time_mean > 0 AND c.wall_time_mean > 0
+            GROUP BY pr.embedding_dim
+            ORDER BY pr.embedding_dim
+            "#,
+            structure_name,
+            baseline_commit,
+            comparison_commit,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.dim as usize, row.speedup))
+            .collect())
+    }
+
+    /// Mean wall time per query of each data structure in the radius sweep
+    /// of a result, as `(radius, wall time in ns)` points sorted by radius.
+    ///
+    /// Only the newest code state of each structure and the last measured
+    /// iteration are used.
+    pub async fn radius_costs(
+        &self,
+        result_id: i64,
+    ) -> Result<BTreeMap<String, Vec<(f64, f64)>>, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT data_structure_name AS "name!",
+                   query_radius AS "radius!",
+                   AVG(wall_time_mean)::float8 AS "wall_time!"
+            FROM measurement_results_view
+            WHERE result_id = $1 AND benchmark_type = 'radius_sweep'
+              AND is_newest_code_state AND is_last_iteration
+            GROUP BY data_structure_name, query_radius
+            ORDER BY data_structure_name, query_radius
+            "#,
+            result_id,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut costs: BTreeMap<String, Vec<(f64, f64)>> = BTreeMap::new();
+        for row in rows {
+            costs
+                .entry(row.name)
+                .or_default()
+                .push((row.radius, row.wall_time));
+        }
+        Ok(costs)
+    }
+}
+
+/// Renders per-dimension speedups as a standalone HTML page with a bar chart.
+pub fn speedup_report_html(
+    structure_name: &str,
+    baseline_commit: &str,
+    comparison_commit: &str,
+    speedups: &[(usize, f64)],
+) -> String {
+    const BAR_WIDTH: f64 = 40.0;
+    const GAP: f64 = 20.0;
+    const HEIGHT: f64 = 300.0;
+    const MARGIN: f64 = 40.0;
+
+    let max = speedups
+        .iter()
+        .map(|&(_, speedup)| speedup)
+        .fold(1.0, f64::max);
+    let scale = HEIGHT / max;
+    let width = MARGIN * 2.0 + speedups.len() as f64 * (BAR_WIDTH + GAP);
+
+    let mut bars = String::new();
+    for (i, &(dim, speedup)) in speedups.iter().enumerate() {
+        let x = MARGIN + i as f64 * (BAR_WIDTH + GAP);
+        let height = speedup * scale;
+        let y = MARGIN + HEIGHT - height;
+        let color = if speedup >= 1.0 { "#4c9a2a" } else { "#c0392b" };
+        bars.push_str(&format!(
+            "<rect x=\"{x}\" y=\"{y}\" width=\"{BAR_WIDTH}\" height=\"{height}\" fill=\"{color}\"/>\n\
+             <text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{speedup:.2}x</text>\n\
+             <text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{dim}D</text>\n",
+            x + BAR_WIDTH / 2.0,
+            y - 5.0,
+            x + BAR_WIDTH / 2.0,
+            MARGIN + HEIGHT + 20.0,
+        ));
+    }
+    // Line marking "no change"
+    let baseline_y = MARGIN + HEIGHT - scale;
+    let structure_name = escape_html(structure_name);
+    let (baseline_commit, comparison_commit) =
+        (escape_html(baseline_commit), escape_html(comparison_commit));
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{structure_name}: speedup per dimension</title></head>\n\
+         <body>\n<h1>{structure_name}</h1>\n<p>Speedup of {comparison_commit} over {baseline_commit}</p>\n\
+         <svg width=\"{width}\" height=\"{}\" font-family=\"sans-serif\" font-size=\"12\">\n{bars}\
+         <line x1=\"{MARGIN}\" x2=\"{}\" y1=\"{baseline_y}\" y2=\"{baseline_y}\" stroke=\"black\" stroke-dasharray=\"4\"/>\n\
+         </svg>\n</body>\n</html>\n",
+        HEIGHT + MARGIN * 2.0,
+        width - MARGIN,
+    )
+}
+
+/// Renders the query cost of each structure over the query radius as a
+/// standalone HTML page with a line chart. The cost axis is logarithmic.
+pub fn radius_cost_report_html(
+    result_id: i64,
+    costs: &BTreeMap<String, Vec<(f64, f64)>>,
+) -> String {
+    const WIDTH: f64 = 600.0;
+    const HEIGHT: f64 = 300.0;
+    const MARGIN: f64 = 60.0;
+    const LEGEND_WIDTH: f64 = 200.0;
+    const COLORS: [&str; 8] = [
+        "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+    ];
+
+    let points = || costs.values().flatten().filter(|&&(_, cost)| cost > 0.0);
+    let max_radius = points().map(|&(radius, _)| radius).fold(0.0, f64::max);
+    let (min_cost, max_cost) = points().fold((f64::INFINITY, 0.0f64), |(lo, hi), &(_, cost)| {
+        (lo.min(cost), hi.max(cost))
+    });
+    if max_radius <= 0.0 {
+        return format!(
+            "<!DOCTYPE html>\n<html>\n<body>\n<p>No radius sweep measurements for result {result_id}</p>\n</body>\n</html>\n"
+        );
+    }
+    let (log_min, log_max) = (
+        min_cost.log10().floor(),
+        max_cost.log10().ceil().max(min_cost.log10().floor() + 1.0),
+    );
+    let x = |radius: f64| MARGIN + radius / max_radius * WIDTH;
+    let y = |cost: f64| MARGIN + HEIGHT - (cost.log10() - log_min) / (log_max - log_min) * HEIGHT;
+
+    let mut axes = format!(
+        "<line x1=\"{MARGIN}\" x2=\"{}\" y1=\"{}\" y2=\"{}\" stroke=\"black\"/>\n\
+         <line x1=\"{MARGIN}\" x2=\"{MARGIN}\" y1=\"{MARGIN}\" y2=\"{}\" stroke=\"black\"/>\n",
+        MARGIN + WIDTH,
+        MARGIN + HEIGHT,
+        MARGIN + HEIGHT,
+        MARGIN + HEIGHT,
+    );
+    let mut radii: Vec<_> = costs
+        .values()
+        .flatten()
+        .map(|&(radius, _)| radius)
+        .collect();
+    radii.sort_by(f64::total_cmp);
+    radii.dedup();
+    for radius in radii {
+        axes.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{radius}</text>\n",
+            x(radius),
+            MARGIN + HEIGHT + 20.0,
+        ));
+    }
+    for exponent in log_min as i32..=log_max as i32 {
+        let cost = 10f64.powi(exponent);
+        axes.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n",
+            MARGIN - 5.0,
+            y(cost) + 4.0,
+            format_nanos(cost),
+        ));
+    }
+
+    let mut lines = String::new();
+    for (i, (name, points)) in costs.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let path = points
+            .iter()
+            .filter(|&&(_, cost)| cost > 0.0)
+            .map(|&(radius, cost)| format!("{},{}", x(radius), y(cost)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let legend_y = MARGIN + i as f64 * 16.0;
+        lines.push_str(&format!(
+            "<polyline points=\"{path}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"2\"/>\n\
+             <text x=\"{}\" y=\"{legend_y}\" fill=\"{color}\">{}</text>\n",
+            MARGIN * 1.5 + WIDTH,
+            escape_html(name),
+        ));
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Query cost per radius of result {result_id}</title></head>\n\
+         <body>\n<h1>Query cost per radius of result {result_id}</h1>\n<p>Mean wall time per query over the radius factor</p>\n\
+         <svg width=\"{}\" height=\"{}\" font-family=\"sans-serif\" font-size=\"12\">\n{axes}{lines}\
+         </svg>\n</body>\n</html>\n",
+        WIDTH + MARGIN * 2.0 + LEGEND_WIDTH,
+        HEIGHT + MARGIN * 2.0,
+    )
+}
+
+/// Escapes `text` for use in HTML text and attribute values, as structure
+/// names and commits come from the database or the command line.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_nanos(nanos: f64) -> String {
+    if nanos < 1e3 {
+        format!("{nanos}ns")
+    } else if nanos < 1e6 {
+        format!("{}µs", nanos / 1e3)
+    } else if nanos < 1e9 {
+        format!("{}ms", nanos / 1e6)
+    } else {
+        format!("{}s", nanos / 1e9)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{radius_cost_report_html, speedup_report_html};
+
+    #[test]
+    fn radius_cost_report_has_a_line_per_structure() {
+        let mut costs = BTreeMap::new();
+        costs.insert(
+            String::from("atree"),
+            vec![(0.5, 100.0), (1.0, 400.0), (4.0, 9000.0)],
+        );
+        costs.insert(
+            String::from("kiddo"),
+            vec![(0.5, 200.0), (1.0, 500.0), (4.0, 20000.0)],
+        );
+
+        let html = radius_cost_report_html(7, &costs);
+        assert_eq!(html.matches("<polyline").count(), 2);
+        assert!(html.contains(">atree</text>"));
+        assert!(html.contains(">10µs</text>"));
+        // Radii are placed linearly, the highest one at the end of the axis
+        assert!(html.contains("points=\"135,"));
+        assert!(html.contains(" 660,"));
+
+        assert!(radius_cost_report_html(7, &BTreeMap::new()).contains("No radius sweep"));
+    }
+
+    #[test]
+    fn reports_escape_names() {
+        let html = speedup_report_html("<Sprk & \"co\">", "a", "b", &[(2, 1.5)]);
+        assert!(html.contains("<h1>&lt;Sprk &amp; &quot;co&quot;&gt;</h1>"));
+        assert!(!html.contains("<Sprk"));
+
+        let mut costs = BTreeMap::new();
+        costs.insert(String::from("a<b>"), vec![(1.0, 100.0)]);
+        assert!(radius_cost_report_html(7, &costs).contains(">a&lt;b&gt;</text>"));
+    }
 }
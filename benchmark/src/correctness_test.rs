@@ -1,7 +1,7 @@
 use crate::pull_files;
 use chrono::{DateTime, Utc};
 use rembed::query::SpatialIndex;
-use rembed::{NodeId, Query, convert_to_embeddings, data_structures};
+use rembed::{NodeId, Query, RandomProjectionLsh, convert_to_embeddings, data_structures};
 use sqlx::{Pool, Postgres};
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
@@ -16,14 +16,42 @@ pub struct TestRecord {
     pub created_at: DateTime<Utc>,
 }
 
+/// Recall an approximate index (see [`SpatialIndex::is_exact`]) has to reach
+/// to pass the correctness tests.
+pub const DEFAULT_MIN_RECALL: f64 = 0.9;
+
+/// Radius factor of the ground truth unless another one is asked for
+pub const DEFAULT_TEST_RADIUS: f64 = 1.0;
+
+/// First bytes of a test file with a header. Files without it predate the
+/// header and hold ground truth for radius 1.
+const TEST_FILE_MAGIC: [u8; 8] = *b"rembedgt";
+
+/// Version of the rule the ground truth of a test file was computed with.
+/// Version 1: node `v` lists every node `u < v` with
+/// `|p_u - p_v| <= radius * w_u * w_v`. Bump it whenever the rule changes, so
+/// that files computed with the old rule are rejected instead of silently
+/// disagreeing with the structures.
+pub const RADIUS_CONVENTION_VERSION: u32 = 1;
+
+/// A position result with a test file, see
+/// [`CorrectnessTestManager::test_results`]
+pub struct TestResult {
+    pub result_id: i64,
+    pub embedding_dim: i32,
+    pub graph_id: i64,
+    pub processed_n: i64,
+}
+
 pub struct CorrectnessTestManager {
     pool: Pool<Postgres>,
     data_directory: String,
+    min_recall: f64,
 }
 macro_rules! dispatch_dim {
-    ($self:ident, $dim:expr, $graph:ident, $pos_path:ident, dims: [ $($c_dim:literal,)* ]) => {
+    ($self:ident, $dim:expr, $graph:ident, $pos_path:ident, $radius:ident, dims: [ $($c_dim:literal,)* ]) => {
         match  $dim {
-            $($c_dim => $self.generate_test_dynamic::<$c_dim>(&$graph, &$pos_path).await?,)*
+            $($c_dim => $self.generate_test_dynamic::<$c_dim>(&$graph, &$pos_path, $radius).await?,)*
             _ => {
                 return Err(
                     format!("Unsupported embedding dimension: {}", $dim).into(),
@@ -39,11 +67,27 @@ impl CorrectnessTestManager {
         Self {
             pool,
             data_directory,
+            min_recall: DEFAULT_MIN_RECALL,
         }
     }
 
-    /// Generate test file for a specific result_id
-    pub async fn generate_test(&self, result_id: i64) -> Result<(), Box<dyn std::error::Error>> {
+    /// Sets the recall threshold for approximate indices
+    pub fn with_min_recall(mut self, min_recall: f64) -> Self {
+        self.min_recall = min_recall;
+        self
+    }
+
+    /// Generate the test file with the ground truth at `radius` for a
+    /// specific result_id. A result can have test files for several radii.
+    pub async fn generate_test(
+        &self,
+        result_id: i64,
+        radius: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !(radius.is_finite() && radius > 0.0) {
+            return Err(format!("Invalid ground truth radius: {radius}").into());
+        }
+
         // Get result info from database
         let result = sqlx::query!(
             "SELECT pr.*, g.file_path as graph_path FROM position_results pr 
@@ -68,11 +112,12 @@ impl CorrectnessTestManager {
             result.embedding_dim,
             graph,
             pos_path,
+            radius,
             dims: [2, 3,4,5,6,7,8,9,10,11,12,13,14,15,16,32,]
         );
 
         // Generate test file path
-        let test_filename = format!("test_result_{}.bin", result_id);
+        let test_filename = test_file_name(result_id, radius);
         let test_file_path = format!("generated/tests/{}", test_filename);
         let full_test_path = format!("{}/{}", self.data_directory, test_file_path);
 
@@ -82,21 +127,22 @@ impl CorrectnessTestManager {
         }
 
         // Write binary test file
-        self.write_test_file(&full_test_path, &iterations)?;
+        write_test_file(&full_test_path, radius, &iterations)?;
 
         // Store in database
         sqlx::query!(
-            "INSERT INTO tests (result_id, file_path) VALUES ($1, $2) 
-             ON CONFLICT (result_id) DO UPDATE SET file_path = $2",
+            "INSERT INTO tests (result_id, radius, file_path) VALUES ($1, $2, $3)
+             ON CONFLICT (result_id, radius) DO UPDATE SET file_path = $3",
             result_id,
+            radius,
             test_file_path
         )
         .execute(&self.pool)
         .await?;
 
         println!(
-            "Generated test file for result_id {}: {}",
-            result_id, test_file_path
+            "Generated test file for result_id {} at radius {}: {}",
+            result_id, radius, test_file_path
         );
         Ok(())
     }
@@ -105,6 +151,7 @@ impl CorrectnessTestManager {
         &self,
         graph: &rembed::graph::Graph,
         pos_path: &str,
+        radius: f64,
     ) -> Result<Vec<Vec<Vec<NodeId>>>, Box<dyn std::error::Error>> {
         let iterations: rembed::parsing::Iterations<D> =
             rembed::parsing::parse_positions_file(pos_path)?;
@@ -113,113 +160,37 @@ impl CorrectnessTestManager {
         let embeddings = convert_to_embeddings(&iterations, graph);
 
         for embedding in embeddings {
-            let mut iteration_results = Vec::new();
-            for node_id in 0..embedding.positions.len() {
-                let neighbors = embedding.nearest_neighbors_owned(node_id, 1.0);
-                iteration_results.push(neighbors);
-            }
-            all_results.push(iteration_results);
+            let pairs = embedding.nearest_neighbor_pairs(radius);
+            all_results.push(earlier_neighbor_lists(&pairs, embedding.positions.len()));
         }
 
         Ok(all_results)
     }
 
-    fn write_test_file(
-        &self,
-        file_path: &str,
-        iterations: &[Vec<Vec<NodeId>>],
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let file = File::create(file_path)?;
-        let mut writer = BufWriter::new(file);
-
-        // Write number of nodes
-        if let Some(first_iteration) = iterations.first() {
-            let num_nodes = first_iteration.len() as u64;
-            writer.write_all(&num_nodes.to_le_bytes())?;
-        } else {
-            return Err("No iterations found".into());
-        }
-
-        // Write iterations
-        for iteration in iterations {
-            for node_neighbors in iteration {
-                // Write length of neighbor list as u32
-                let length = node_neighbors.len() as u32;
-                writer.write_all(&length.to_le_bytes())?;
-
-                // Write neighbor IDs as u32
-                for &neighbor in node_neighbors {
-                    writer.write_all(&(neighbor as u32).to_le_bytes())?;
-                }
-            }
-        }
-
-        writer.flush()?;
-        Ok(())
-    }
-
-    fn read_test_file(
+    /// The position results with a test file for `radius`. Unless
+    /// `all_graphs` is set, only those of graphs with fewer than 5000 nodes,
+    /// and with `max_nodes` none of a graph with more nodes than that.
+    pub async fn test_results(
         &self,
-        file_path: &str,
-    ) -> Result<Vec<Vec<Vec<NodeId>>>, Box<dyn std::error::Error>> {
-        let file = File::open(file_path)?;
-        let mut reader = BufReader::new(file);
-
-        // Read number of nodes
-        let mut num_nodes_bytes = [0u8; 8];
-        reader.read_exact(&mut num_nodes_bytes)?;
-        let num_nodes = u64::from_le_bytes(num_nodes_bytes) as usize;
-
-        let mut buffer = Vec::new();
-        reader.read_to_end(&mut buffer)?;
-
-        let mut iterations = Vec::new();
-        let mut pos = 0;
-
-        while pos < buffer.len() {
-            let mut iteration = Vec::new();
-
-            for _ in 0..num_nodes {
-                // Read length of neighbor list
-                if pos + 4 > buffer.len() {
-                    break;
-                }
-                let length_bytes = [
-                    buffer[pos],
-                    buffer[pos + 1],
-                    buffer[pos + 2],
-                    buffer[pos + 3],
-                ];
-                let length = u32::from_le_bytes(length_bytes) as usize;
-                pos += 4;
-
-                let mut neighbors = Vec::new();
-
-                // Read neighbor IDs
-                for _ in 0..length {
-                    if pos + 4 > buffer.len() {
-                        break;
-                    }
-                    let neighbor_bytes = [
-                        buffer[pos],
-                        buffer[pos + 1],
-                        buffer[pos + 2],
-                        buffer[pos + 3],
-                    ];
-                    let neighbor = u32::from_le_bytes(neighbor_bytes) as NodeId;
-                    neighbors.push(neighbor);
-                    pos += 4;
-                }
-
-                iteration.push(neighbors);
-            }
-
-            if !iteration.is_empty() {
-                iterations.push(iteration);
-            }
-        }
-
-        Ok(iterations)
+        all_graphs: bool,
+        radius: f64,
+        max_nodes: Option<usize>,
+    ) -> Result<Vec<TestResult>, sqlx::Error> {
+        sqlx::query_as!(
+            TestResult,
+            "SELECT result_id, pr.embedding_dim, g.graph_id, g.processed_n
+            FROM tests t
+            JOIN position_results pr USING (result_id)
+            JOIN graphs g USING (graph_id)
+            WHERE ($1 OR g.processed_n < 5000) AND t.radius = $2
+                AND ($3::BIGINT IS NULL OR g.processed_n <= $3)
+            ",
+            all_graphs,
+            radius,
+            max_nodes.map(|max_nodes| max_nodes as i64),
+        )
+        .fetch_all(&self.pool)
+        .await
     }
 
     /// Run correctness tests with configurable options
@@ -235,6 +206,8 @@ impl CorrectnessTestManager {
         structures: Vec<String>,
         dynamic_download: bool,
         check_over_query: bool,
+        radius: f64,
+        max_nodes: Option<usize>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         if run_unit_tests {
             println!("Running unit tests from main crate...");
@@ -256,26 +229,11 @@ impl CorrectnessTestManager {
                 "Running quick correctness tests on graphs with n < 5000 use --all-graphs to allow larger graphs..."
             );
         }
-
-        struct TestResult {
-            result_id: i64,
-            embedding_dim: i32,
-            graph_id: i64,
-            processed_n: i64,
+        if let Some(max_nodes) = max_nodes {
+            println!("Skipping results of graphs with more than {max_nodes} nodes (--max-nodes)");
         }
 
-        let test_results = sqlx::query_as!(
-            TestResult,
-            "SELECT result_id, pr.embedding_dim, g.graph_id, g.processed_n
-            FROM tests t
-            JOIN position_results pr USING (result_id)
-            JOIN graphs g USING (graph_id)
-            WHERE $1 OR g.processed_n < 5000
-            ",
-            all_graphs,
-        )
-        .fetch_all(&self.pool)
-        .await?;
+        let test_results = self.test_results(all_graphs, radius, max_nodes).await?;
 
         // Apply filters in Rust (simpler than dynamic SQL)
         let filtered_results: Vec<_> = test_results
@@ -301,7 +259,9 @@ impl CorrectnessTestManager {
             .collect();
 
         if filtered_results.is_empty() {
-            println!("No test files found matching criteria. Run 'generate-test' first.");
+            println!(
+                "No test files found matching criteria for radius {radius}. Run 'generate-test' first."
+            );
             return Ok(());
         }
 
@@ -344,6 +304,7 @@ impl CorrectnessTestManager {
                         structures,
                         dynamic_download,
                         check_over_query,
+                        radius,
                     )
                     .await?
                 }
@@ -354,6 +315,7 @@ impl CorrectnessTestManager {
                         structures,
                         dynamic_download,
                         check_over_query,
+                        radius,
                     )
                     .await?
                 }
@@ -364,6 +326,7 @@ impl CorrectnessTestManager {
                         structures,
                         dynamic_download,
                         check_over_query,
+                        radius,
                     )
                     .await?
                 }
@@ -374,6 +337,7 @@ impl CorrectnessTestManager {
                         structures,
                         dynamic_download,
                         check_over_query,
+                        radius,
                     )
                     .await?
                 }
@@ -384,6 +348,7 @@ impl CorrectnessTestManager {
                         structures,
                         dynamic_download,
                         check_over_query,
+                        radius,
                     )
                     .await?
                 }
@@ -401,12 +366,14 @@ impl CorrectnessTestManager {
         structure_selection: &[String],
         dynamic_download: bool,
         check_over_query: bool,
+        radius: f64,
     ) -> Result<(), Box<dyn std::error::Error>> {
         // Get test file info
         let test_record = sqlx::query_as!(
             TestRecord,
-            "SELECT result_id, file_path, created_at FROM tests WHERE result_id = $1",
-            result_id
+            "SELECT result_id, file_path, created_at FROM tests WHERE result_id = $1 AND radius = $2",
+            result_id,
+            radius
         )
         .fetch_one(&self.pool)
         .await?;
@@ -449,9 +416,10 @@ impl CorrectnessTestManager {
 
         let iterations: rembed::parsing::Iterations<D> =
             rembed::parsing::parse_positions_file(&pos_path)?;
+        iterations.validate_graph_compatibility(&graph)?;
 
         // Load ground truth
-        let ground_truth = self.read_test_file(&test_file_path)?;
+        let ground_truth = read_test_file(&test_file_path, radius)?;
 
         let mut embeddings = convert_to_embeddings(&iterations, &graph);
         // Test each iteration (or just the last one for quick tests)
@@ -478,19 +446,15 @@ impl CorrectnessTestManager {
                 continue;
             }
 
-            let data_structures = data_structures(embedding);
+            let data_structures = data_structures(embedding, Some(structure_selection));
 
             for structure in data_structures {
-                if !structure_selection.is_empty()
-                    && !structure_selection.contains(&structure.name())
-                {
-                    continue; // Skip structures not in selection
-                }
                 let errors = self.test_structure(
                     structure.as_ref() as &dyn SpatialIndex<D>,
                     &ground_truth[iteration_idx],
                     iteration_idx,
                     check_over_query,
+                    radius,
                 );
                 total_errors += errors;
             }
@@ -508,27 +472,86 @@ impl CorrectnessTestManager {
         Ok(())
     }
 
+    /// Traces the query of `node_a` for `node_b` and the other way round on
+    /// the last iteration of `result_id`, through every selected structure
+    /// that supports [`SpatialIndex::trace_query`]
+    pub async fn explain(
+        &self,
+        result_id: i64,
+        node_a: NodeId,
+        node_b: NodeId,
+        structures: Vec<String>,
+        dynamic_download: bool,
+        radius: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let result = sqlx::query!(
+            "SELECT pr.*, g.file_path as graph_path FROM position_results pr 
+             JOIN graphs g USING (graph_id) WHERE pr.result_id = $1",
+            result_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let pos_path = format!("{}/{}", self.data_directory, result.file_path);
+        let graph_path = format!("{}/{}", self.data_directory, result.graph_path);
+        if dynamic_download {
+            if !Path::new(&graph_path).exists() {
+                println!("Graph file not found locally. Downloading...");
+                pull_files(false, Some(result.graph_path.as_str()), None, None).await?;
+            }
+            if !Path::new(&pos_path).exists() {
+                println!("Positions file not found locally. Downloading...");
+                pull_files(false, Some(result.file_path.as_str()), None, None).await?;
+            }
+        }
+
+        let graph = rembed::graph::Graph::parse_from_edge_list_file(
+            &graph_path,
+            result.embedding_dim as usize,
+            result.dim_hint as usize,
+        )?;
+        let nodes = (node_a, node_b);
+        match result.embedding_dim {
+            2 => explain_last_iteration::<2>(&graph, &pos_path, nodes, &structures, radius),
+            4 => explain_last_iteration::<4>(&graph, &pos_path, nodes, &structures, radius),
+            8 => explain_last_iteration::<8>(&graph, &pos_path, nodes, &structures, radius),
+            16 => explain_last_iteration::<16>(&graph, &pos_path, nodes, &structures, radius),
+            32 => explain_last_iteration::<32>(&graph, &pos_path, nodes, &structures, radius),
+            dim => Err(format!("Unsupported embedding dimension: {dim}").into()),
+        }
+    }
+
     fn test_structure<'a, const D: usize>(
         &'a self,
         structure: &'a (dyn rembed::query::SpatialIndex<D> + 'a),
         ground_truth: &'a [Vec<NodeId>],
         iteration: usize,
         check_over_query: bool,
+        radius: f64,
     ) -> usize {
+        let exact = structure.is_exact();
         let mut errors = 0;
+        let mut expected_total = 0;
+        let mut found_total = 0;
         let mut over_queried_nodes = 0;
         let mut avg_distance_pruning_error = 0.0;
         let mut distance_pruning_error_count = 0;
 
-        let results =
-            structure.nearest_neighbors_batched(&(0..ground_truth.len()).collect::<Vec<_>>());
+        let queries: Vec<_> = (0..ground_truth.len()).map(|i| (i, radius)).collect();
+        let results = structure.nearest_neighbors_batched_with(&queries);
         for (node_id, list) in results.into_iter().enumerate() {
             let mut actual: HashSet<NodeId> = list.into_iter().collect();
             let expected: HashSet<NodeId> = ground_truth[node_id].iter().cloned().collect();
             actual.retain(|x| x != &node_id);
             let node_weight = structure.weight(node_id);
+            let found = expected.iter().filter(|n| actual.contains(n)).count();
+            expected_total += expected.len();
+            found_total += found;
 
-            if expected.iter().any(|n| !actual.contains(n)) {
+            // Approximate indices are judged by their overall recall below
+            if found < expected.len() && !exact {
+                errors += 1;
+            } else if found < expected.len() {
                 if errors == 0 {
                     println!(
                         "\nError for node {node_id}\n  node_weight: {:.2} pos: {:?}",
@@ -566,14 +589,39 @@ impl CorrectnessTestManager {
                         .distance_squared(structure.position(node_id))
                         as f64
                         / (node_weight * node_weight).powi(2);
-                    if approx_dist > 1.0 + 1e-6 {
-                        avg_distance_pruning_error += approx_dist - 1.0;
+                    if approx_dist > radius * radius + 1e-6 {
+                        avg_distance_pruning_error += approx_dist - radius * radius;
                         distance_pruning_error_count += 1;
                     }
                 }
             }
         }
 
+        if !exact {
+            let recall = if expected_total == 0 {
+                1.0
+            } else {
+                found_total as f64 / expected_total as f64
+            };
+            if recall >= self.min_recall {
+                println!(
+                    "  ✓ {} (approximate) reached recall {:.4} (minimum {:.2})",
+                    structure.name(),
+                    recall,
+                    self.min_recall
+                );
+                return 0;
+            }
+            println!(
+                "  ✗ {} (approximate) reached recall {:.4}, below minimum {:.2} ({} nodes with misses)",
+                structure.name(),
+                recall,
+                self.min_recall,
+                errors
+            );
+            return errors;
+        }
+
         if errors == 0 && over_queried_nodes == 0 {
             println!("  ✓ {} passed", structure.name());
         } else if errors == 0 && check_over_query {
@@ -626,3 +674,305 @@ impl CorrectnessTestManager {
         }
     }
 }
+
+/// Prints the traces of [`CorrectnessTestManager::explain`] for the last
+/// iteration of the positions at `pos_path`
+fn explain_last_iteration<const D: usize>(
+    graph: &rembed::graph::Graph,
+    pos_path: &str,
+    (node_a, node_b): (NodeId, NodeId),
+    structures: &[String],
+    radius: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let iterations: rembed::parsing::Iterations<D> =
+        rembed::parsing::parse_positions_file(pos_path)?;
+    iterations.validate_graph_compatibility(graph)?;
+    let embedding = convert_to_embeddings(&iterations, graph)
+        .next_back()
+        .ok_or("positions file has no iterations")?;
+    for node in [node_a, node_b] {
+        if node >= embedding.positions.len() {
+            return Err(format!(
+                "node {node} is not in the graph with {} nodes",
+                embedding.positions.len()
+            )
+            .into());
+        }
+    }
+
+    // Same rule as the ground truth of the test files
+    let distance = embedding.positions[node_a].distance(&embedding.positions[node_b]) as f64;
+    let limit = radius * graph.nodes[node_a].weight * graph.nodes[node_b].weight;
+    println!(
+        "Nodes {node_a} and {node_b} are {distance:.6} apart, the ground truth limit is {limit:.6}: {}",
+        if distance <= limit {
+            "neighbours"
+        } else {
+            "not neighbours"
+        }
+    );
+
+    for structure in data_structures(&embedding, Some(structures)) {
+        print_traces(structure.as_ref(), node_a, node_b, radius);
+    }
+    // The LSH has no factory, as it is not exact
+    if structures.is_empty() || structures.iter().any(|s| s.starts_with("rp-lsh")) {
+        print_traces(
+            &RandomProjectionLsh::new(embedding.view()),
+            node_a,
+            node_b,
+            radius,
+        );
+    }
+    Ok(())
+}
+
+fn print_traces<const D: usize>(
+    structure: &dyn SpatialIndex<D>,
+    node_a: NodeId,
+    node_b: NodeId,
+    radius: f64,
+) {
+    let name = structure.name();
+    // The tests symmetrise the results, a pair is only missed if both
+    // queries miss it
+    for (index, target) in [(node_a, node_b), (node_b, node_a)] {
+        match structure.trace_query(index, radius, target) {
+            Some(trace) => println!("\n{name}, query {index} for {target}:\n{trace}"),
+            None => {
+                println!("\n{name}: query tracing not supported");
+                return;
+            }
+        }
+    }
+}
+
+/// Name of the test file of `result_id` at `radius`. Radius 1 keeps the
+/// name test files had before other radii were possible.
+fn test_file_name(result_id: i64, radius: f64) -> String {
+    if radius == DEFAULT_TEST_RADIUS {
+        format!("test_result_{}.bin", result_id)
+    } else {
+        format!("test_result_{}_r{}.bin", result_id, radius)
+    }
+}
+
+/// Ground truth of `num_nodes` nodes in the layout of the test files from
+/// the `pairs` of [`Query::nearest_neighbor_pairs`]: node `v` lists every
+/// `u < v` it forms a pair with, in increasing order. The files hence store
+/// each pair once already.
+fn earlier_neighbor_lists(pairs: &[(NodeId, NodeId)], num_nodes: usize) -> Vec<Vec<NodeId>> {
+    let mut lists = vec![Vec::new(); num_nodes];
+    for &(u, v) in pairs {
+        lists[v].push(u);
+    }
+    lists
+}
+
+fn write_test_file(
+    file_path: &str,
+    radius: f64,
+    iterations: &[Vec<Vec<NodeId>>],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = File::create(file_path)?;
+    let mut writer = BufWriter::new(file);
+
+    // Write the header: which radius, and under which rule, the ground truth is for
+    writer.write_all(&TEST_FILE_MAGIC)?;
+    writer.write_all(&RADIUS_CONVENTION_VERSION.to_le_bytes())?;
+    writer.write_all(&radius.to_le_bytes())?;
+
+    // Write number of nodes
+    if let Some(first_iteration) = iterations.first() {
+        let num_nodes = first_iteration.len() as u64;
+        writer.write_all(&num_nodes.to_le_bytes())?;
+    } else {
+        return Err("No iterations found".into());
+    }
+
+    // Write iterations
+    for iteration in iterations {
+        for node_neighbors in iteration {
+            // Write length of neighbor list as u32
+            let length = node_neighbors.len() as u32;
+            writer.write_all(&length.to_le_bytes())?;
+
+            // Write neighbor IDs as u32
+            for &neighbor in node_neighbors {
+                writer.write_all(&(neighbor as u32).to_le_bytes())?;
+            }
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the ground truth of a test file and checks that it was computed
+/// for `radius` under the current [`RADIUS_CONVENTION_VERSION`]. Files
+/// without a header are taken to be for radius 1.
+fn read_test_file(
+    file_path: &str,
+    radius: f64,
+) -> Result<Vec<Vec<Vec<NodeId>>>, Box<dyn std::error::Error>> {
+    let file = File::open(file_path)?;
+    let mut reader = BufReader::new(file);
+
+    let mut first_bytes = [0u8; 8];
+    reader.read_exact(&mut first_bytes)?;
+    let num_nodes_bytes = if first_bytes == TEST_FILE_MAGIC {
+        let mut version_bytes = [0u8; 4];
+        reader.read_exact(&mut version_bytes)?;
+        let version = u32::from_le_bytes(version_bytes);
+        if version != RADIUS_CONVENTION_VERSION {
+            return Err(format!(
+                "{file_path} uses radius convention version {version}, expected {RADIUS_CONVENTION_VERSION}; regenerate it"
+            )
+            .into());
+        }
+        let mut radius_bytes = [0u8; 8];
+        reader.read_exact(&mut radius_bytes)?;
+        let file_radius = f64::from_le_bytes(radius_bytes);
+        if file_radius != radius {
+            return Err(format!(
+                "{file_path} holds ground truth for radius {file_radius}, not {radius}"
+            )
+            .into());
+        }
+
+        let mut num_nodes_bytes = [0u8; 8];
+        reader.read_exact(&mut num_nodes_bytes)?;
+        num_nodes_bytes
+    } else {
+        println!("Warning: {file_path} has no header, assuming ground truth for radius 1");
+        if radius != DEFAULT_TEST_RADIUS {
+            return Err(
+                format!("{file_path} holds ground truth for radius 1, not {radius}").into(),
+            );
+        }
+        first_bytes
+    };
+    let num_nodes = u64::from_le_bytes(num_nodes_bytes) as usize;
+
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+
+    let mut iterations = Vec::new();
+    let mut pos = 0;
+
+    while pos < buffer.len() {
+        let mut iteration = Vec::new();
+
+        for _ in 0..num_nodes {
+            // Read length of neighbor list
+            if pos + 4 > buffer.len() {
+                break;
+            }
+            let length_bytes = [
+                buffer[pos],
+                buffer[pos + 1],
+                buffer[pos + 2],
+                buffer[pos + 3],
+            ];
+            let length = u32::from_le_bytes(length_bytes) as usize;
+            pos += 4;
+
+            let mut neighbors = Vec::new();
+
+            // Read neighbor IDs
+            for _ in 0..length {
+                if pos + 4 > buffer.len() {
+                    break;
+                }
+                let neighbor_bytes = [
+                    buffer[pos],
+                    buffer[pos + 1],
+                    buffer[pos + 2],
+                    buffer[pos + 3],
+                ];
+                let neighbor = u32::from_le_bytes(neighbor_bytes) as NodeId;
+                neighbors.push(neighbor);
+                pos += 4;
+            }
+
+            iteration.push(neighbors);
+        }
+
+        if !iteration.is_empty() {
+            iterations.push(iteration);
+        }
+    }
+
+    Ok(iterations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("{name}-{}.bin", std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_file_header_round_trip() {
+        let path = temp_path("test-file-round-trip");
+        let iterations = vec![
+            vec![vec![], vec![0], vec![0, 1]],
+            vec![vec![], vec![], vec![1]],
+        ];
+        write_test_file(&path, 0.5, &iterations).unwrap();
+
+        let read = read_test_file(&path, 0.5);
+        let mismatch = read_test_file(&path, 1.0).map(|_| ());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.unwrap(), iterations);
+        let error = mismatch.unwrap_err().to_string();
+        assert!(error.contains("radius 0.5, not 1"), "{error}");
+    }
+
+    #[test]
+    fn ground_truth_from_pairs_matches_the_per_node_queries() {
+        // A hub at node 0, so the weights differ
+        let edges = (0..199).map(|i| (i, i + 1)).chain([(0, 50), (0, 100)]);
+        let graph = rembed::graph::Graph::from_edge_list(edges.collect(), 2, 2).unwrap();
+        let embedding = rembed::Embedding::<2> {
+            positions: (0..200)
+                .map(|i| rembed::dvec::DVec::new([(i % 20) as f32, (i / 20) as f32]))
+                .collect(),
+            graph: &graph,
+        };
+        let per_node: Vec<_> = (0..200)
+            .map(|i| embedding.nearest_neighbors_owned(i, 1.5))
+            .collect();
+
+        let pairs = embedding.nearest_neighbor_pairs(1.5);
+        assert_eq!(earlier_neighbor_lists(&pairs, 200), per_node);
+        assert_eq!(rembed::query::lists_to_pairs(&per_node), pairs);
+    }
+
+    #[test]
+    fn headerless_test_files_are_for_radius_one() {
+        let path = temp_path("test-file-legacy");
+        let mut legacy = Vec::new();
+        legacy.extend(2u64.to_le_bytes());
+        for list in [&[][..], &[0]] {
+            legacy.extend((list.len() as u32).to_le_bytes());
+            for &neighbor in list {
+                legacy.extend((neighbor as u32).to_le_bytes());
+            }
+        }
+        std::fs::write(&path, legacy).unwrap();
+
+        let read = read_test_file(&path, 1.0);
+        let mismatch = read_test_file(&path, 2.0).map(|_| ());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read.unwrap(), vec![vec![vec![], vec![0]]]);
+        assert!(mismatch.is_err());
+    }
+}
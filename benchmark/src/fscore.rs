@@ -156,7 +156,7 @@ fn compute_fscore<const D: usize>(
             positions: last_iteration.positions.iter().cloned().collect(),
             graph: &graph,
         };
-        let sprk = rembed::Sprk::new(&embedding);
+        let sprk = rembed::Sprk::new(embedding.view());
         let (percision, recall) = rembed::query::Embedder::graph_statistics(&sprk);
         vec![(
             last_iteration.number,
@@ -173,7 +173,7 @@ fn compute_fscore<const D: usize>(
                     positions: iteration.positions.iter().cloned().collect(),
                     graph: &graph,
                 };
-                let sprk = rembed::Sprk::new(&embedding);
+                let sprk = rembed::Sprk::new(embedding.view());
                 let (percision, recall) = rembed::query::Embedder::graph_statistics(&sprk);
                 (
                     iteration.number,
@@ -1,4 +1,3 @@
-use sha2::{Digest, Sha256};
 use sqlx::Postgres;
 use std::collections::{HashMap, HashSet};
 use std::io::Read;
@@ -7,7 +6,8 @@ use std::str::FromStr;
 use tracing::info;
 
 use crate::create_progress_bar;
-use crate::job_manager::JobManager;
+use crate::generate_positions::calculate_file_checksum;
+use crate::job_manager::{DEFAULT_JOB_DIMS, JobManager};
 
 struct Seed {
     wseed: i32,
@@ -34,6 +34,10 @@ impl Graph {
             adjacency_list[u as usize].push(v);
             adjacency_list[v as usize].push(u);
         }
+        // Isolated nodes at the end of the id range don't show up in the edges
+        if adjacency_list.len() < n {
+            adjacency_list.resize(n, Vec::new());
+        }
         Self {
             adjacency_list,
             n,
@@ -41,9 +45,11 @@ impl Graph {
         }
     }
 
-    pub fn reduce_to_largest_component(&self) -> Self {
+    /// The connected components, each in the order a depth-first search
+    /// visits its nodes
+    fn components(&self) -> Vec<Vec<i32>> {
         let mut visited = vec![false; self.adjacency_list.len()];
-        let mut component = Vec::new();
+        let mut components = Vec::new();
 
         for start in 0..self.adjacency_list.len() {
             if !visited[start] {
@@ -62,9 +68,25 @@ impl Graph {
                     }
                 }
 
-                if current_component.len() > component.len() {
-                    component = current_component;
-                }
+                components.push(current_component);
+            }
+        }
+        components
+    }
+
+    /// Sizes of the connected components, largest first
+    pub fn component_sizes(&self) -> Vec<usize> {
+        let mut sizes: Vec<usize> = self.components().iter().map(Vec::len).collect();
+        sizes.sort_unstable_by(|a, b| b.cmp(a));
+        sizes
+    }
+
+    pub fn reduce_to_largest_component(&self) -> Self {
+        // The first of the largest components, so ties keep the lowest node
+        let mut component = Vec::new();
+        for current_component in self.components() {
+            if current_component.len() > component.len() {
+                component = current_component;
             }
         }
 
@@ -127,6 +149,10 @@ impl Graph {
 pub struct GraphGenerator {
     pub girgs_path: String,
     pub output_path: String,
+    /// Embedding dimensions position jobs are created for
+    pub job_dims: Vec<i32>,
+    /// Keep the files genhrg writes instead of removing them
+    pub keep_temp: bool,
 }
 
 impl GraphGenerator {
@@ -134,9 +160,21 @@ impl GraphGenerator {
         Self {
             girgs_path,
             output_path,
+            job_dims: DEFAULT_JOB_DIMS.to_vec(),
+            keep_temp: false,
         }
     }
 
+    pub fn with_job_dims(mut self, job_dims: Vec<i32>) -> Self {
+        self.job_dims = job_dims;
+        self
+    }
+
+    pub fn with_keep_temp(mut self, keep_temp: bool) -> Self {
+        self.keep_temp = keep_temp;
+        self
+    }
+
     pub async fn generate(&self) -> Result<(), Box<dyn std::error::Error>> {
         let database_url = std::env::var("DATABASE_URL")
             .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
@@ -202,6 +240,12 @@ impl GraphGenerator {
                                 );
                                 let temp_file_path =
                                     format!("{}/{}", self.output_path, temp_filename);
+                                let mut temp_files = TempFiles {
+                                    dir: &self.output_path,
+                                    prefix: &temp_filename,
+                                    keep: self.keep_temp,
+                                    failed: true,
+                                };
 
                                 let status = self.run_girgs(
                                     &seed,
@@ -222,7 +266,8 @@ impl GraphGenerator {
                                     .into());
                                 }
 
-                                let processed_graph = self.process_raw_graph(&raw_file_path)?;
+                                let (processed_graph, component_sizes) =
+                                    self.process_raw_graph(&raw_file_path, n as usize)?;
 
                                 let graph_id = insert_graph_with_metrics(
                                     &mut tx,
@@ -234,6 +279,7 @@ impl GraphGenerator {
                                     &seed,
                                     processed_graph.n as i32,
                                     processed_graph.avg_degree,
+                                    &component_sizes,
                                 )
                                 .await?;
 
@@ -272,10 +318,11 @@ impl GraphGenerator {
 
                                 // Create position generation jobs using JobManager
                                 let job_manager = JobManager::new(pool.clone());
-                                let jobs_created =
-                                    job_manager.create_jobs_for_graph(graph_id).await?;
+                                let jobs_created = job_manager
+                                    .create_jobs_for_graph(graph_id, &self.job_dims)
+                                    .await?;
 
-                                std::fs::remove_file(raw_file_path).ok();
+                                temp_files.failed = false;
 
                                 info!(
                                     "Created graph {} with {} position generation jobs",
@@ -294,7 +341,13 @@ impl GraphGenerator {
         Ok(())
     }
 
-    fn process_raw_graph(&self, file_path: &str) -> Result<Graph, Box<dyn std::error::Error>> {
+    /// Reads a GIRG edge list and reduces it to its largest component. Also
+    /// returns the component sizes of the raw graph with `n` nodes.
+    fn process_raw_graph(
+        &self,
+        file_path: &str,
+        n: usize,
+    ) -> Result<(Graph, Vec<usize>), Box<dyn std::error::Error>> {
         let mut file = std::fs::File::open(file_path)?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
@@ -314,9 +367,11 @@ impl GraphGenerator {
             })
             .collect();
 
-        let mut graph = Graph::new(edges, 0).reduce_to_largest_component();
+        let raw_graph = Graph::new(edges, n);
+        let component_sizes = raw_graph.component_sizes();
+        let mut graph = raw_graph.reduce_to_largest_component();
         graph.compute_avg_degree();
-        Ok(graph)
+        Ok((graph, component_sizes))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -356,6 +411,39 @@ impl GraphGenerator {
     }
 }
 
+/// The files genhrg writes for a graph, i.e. those in `dir` named `prefix`
+/// plus an extension. Removed when dropped unless `keep` is set, in which
+/// case their paths are printed if the graph `failed`.
+struct TempFiles<'a> {
+    dir: &'a str,
+    prefix: &'a str,
+    keep: bool,
+    failed: bool,
+}
+
+impl Drop for TempFiles<'_> {
+    fn drop(&mut self) {
+        let Ok(entries) = std::fs::read_dir(self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let extension = name.to_string_lossy();
+            let Some(extension) = extension.strip_prefix(self.prefix) else {
+                continue;
+            };
+            if !(extension.is_empty() || extension.starts_with('.')) {
+                continue;
+            }
+            if !self.keep {
+                std::fs::remove_file(entry.path()).ok();
+            } else if self.failed {
+                eprintln!("Kept {} of the failed graph", entry.path().display());
+            }
+        }
+    }
+}
+
 async fn check_existing_graph(
     tx: &mut sqlx::Transaction<'static, Postgres>,
     n: i32,
@@ -393,14 +481,21 @@ async fn insert_graph_with_metrics(
     seed: &Seed,
     processed_n: i32,
     processed_avg_degree: f64,
+    component_sizes: &[usize],
 ) -> Result<i64, sqlx::Error> {
+    let num_components = component_sizes.len() as i32;
+    let total: usize = component_sizes.iter().sum();
+    // An empty graph has no largest component
+    let largest_component_fraction = component_sizes
+        .first()
+        .map_or(0.0, |&largest| largest as f64 / total as f64);
     sqlx::query_scalar!(
         r#"
-        INSERT INTO graphs (n, deg, dim, ple, alpha, wseed, pseed, sseed, processed_n, processed_avg_degree, file_path, checksum)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        INSERT INTO graphs (n, deg, dim, ple, alpha, wseed, pseed, sseed, processed_n, processed_avg_degree, num_components, largest_component_fraction, file_path, checksum)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
         RETURNING graph_id
         "#,
-        original_n, original_deg, dim, ple, alpha, seed.wseed, seed.pseed, seed.sseed, processed_n, processed_avg_degree, "", ""
+        original_n, original_deg, dim, ple, alpha, seed.wseed, seed.pseed, seed.sseed, processed_n, processed_avg_degree, num_components, largest_component_fraction, "", ""
     ).fetch_one(&mut **tx).await
 }
 
@@ -421,13 +516,6 @@ async fn update_file_path_and_checksum(
     Ok(())
 }
 
-fn calculate_file_checksum(file_path: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let contents = std::fs::read(file_path)?;
-    let mut hasher = Sha256::new();
-    hasher.update(&contents);
-    Ok(format!("{:x}", hasher.finalize()))
-}
-
 fn quarter_log10(start: f64, end: f64) -> Vec<i32> {
     let log_start = start.log10();
     let log_end = end.log10();
@@ -463,3 +551,24 @@ fn log_scale() {
     let n_s = quarter_log10(1000.0, 1_000_005.0);
     panic!("ns: {:?}", n_s);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn component_sizes_of_two_components() {
+        // A path on 0..4, a triangle on 4..7 and an isolated node 7
+        let edges = vec![(0, 1), (1, 2), (2, 3), (4, 5), (5, 6), (6, 4)];
+        let graph = Graph::new(edges, 8);
+        assert_eq!(graph.component_sizes(), vec![4, 3, 1]);
+
+        let reduced = graph.reduce_to_largest_component();
+        assert_eq!(reduced.n, 4);
+        assert_eq!(reduced.component_sizes(), vec![4]);
+
+        let empty = Graph::new(Vec::new(), 0);
+        assert!(empty.component_sizes().is_empty());
+        assert_eq!(empty.reduce_to_largest_component().n, 0);
+    }
+}
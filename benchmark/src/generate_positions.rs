@@ -1,12 +1,141 @@
 use crate::job_manager::{JobManager, PositionJob};
+use crate::sync::PushBatcher;
 use rembed::Embedding;
 use rembed::sprk::Sprk;
 use rembed::embedder::{EmbedderOptions, WEmbedder};
 use rembed::query::{Embedder, SpatialIndex};
 use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::{Arc, mpsc};
 use std::time::Duration;
 use tokio::time::sleep;
 
+/// How often [`monitor_resource_usage`] logs the resource usage
+pub const MONITOR_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Snapshot of the resources used by the daemon
+#[derive(Debug, Clone, Default)]
+pub struct ResourceUsage {
+    /// Resident memory of this process
+    pub memory_mb: Option<u64>,
+    /// One minute load average and number of CPUs
+    pub load_average: Option<f64>,
+    pub num_cpus: Option<u32>,
+    /// Used and total space of the file system holding `DATA_DIRECTORY`
+    pub disk_used_mb: Option<u64>,
+    pub disk_total_mb: Option<u64>,
+}
+
+impl std::fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn or_unknown<T: ToString>(value: Option<T>) -> String {
+            value.map_or(String::from("?"), |v| v.to_string())
+        }
+        write!(
+            f,
+            "memory: {} MB, load: {} on {} cpus, disk: {}/{} MB",
+            or_unknown(self.memory_mb),
+            or_unknown(self.load_average.map(|l| format!("{l:.2}"))),
+            or_unknown(self.num_cpus),
+            or_unknown(self.disk_used_mb),
+            or_unknown(self.disk_total_mb),
+        )
+    }
+}
+
+/// Samples memory, CPU and disk usage of the daemon and enforces the memory
+/// limit from `REMBED_MAX_MEMORY_MB`
+#[derive(Debug, Clone)]
+pub struct ResourceMonitor {
+    pub data_directory: PathBuf,
+    pub max_memory_mb: Option<u64>,
+}
+
+impl ResourceMonitor {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let max_memory_mb = match std::env::var("REMBED_MAX_MEMORY_MB") {
+            Ok(value) => Some(
+                value
+                    .parse()
+                    .map_err(|e| format!("invalid REMBED_MAX_MEMORY_MB '{value}': {e}"))?,
+            ),
+            Err(_) => None,
+        };
+        Ok(Self {
+            data_directory: std::env::var("DATA_DIRECTORY")
+                .unwrap_or(String::from("../data/"))
+                .into(),
+            max_memory_mb,
+        })
+    }
+
+    pub fn sample(&self) -> ResourceUsage {
+        let (disk_used_mb, disk_total_mb) = match disk_usage_mb(&self.data_directory) {
+            Some((used, total)) => (Some(used), Some(total)),
+            None => (None, None),
+        };
+        ResourceUsage {
+            memory_mb: memory_usage_mb(),
+            load_average: sys_info::loadavg().ok().map(|load| load.one),
+            num_cpus: sys_info::cpu_num().ok(),
+            disk_used_mb,
+            disk_total_mb,
+        }
+    }
+
+    /// Whether the process currently uses more memory than allowed. Always
+    /// false without a limit or where the memory usage is unknown.
+    pub fn memory_exceeded(&self) -> bool {
+        match (self.max_memory_mb, memory_usage_mb()) {
+            (Some(max), Some(used)) => used > max,
+            _ => false,
+        }
+    }
+}
+
+/// Logs the resource usage every [`MONITOR_INTERVAL`] until the task is
+/// dropped
+pub async fn monitor_resource_usage(monitor: Arc<ResourceMonitor>) {
+    loop {
+        let usage = monitor.sample();
+        match monitor.max_memory_mb {
+            Some(max) => println!("[resources] {usage}, memory limit: {max} MB"),
+            None => println!("[resources] {usage}"),
+        }
+        sleep(MONITOR_INTERVAL).await;
+    }
+}
+
+/// Resident memory of this process, read from `/proc/self/status`
+fn memory_usage_mb() -> Option<u64> {
+    parse_vm_rss_kb(&std::fs::read_to_string("/proc/self/status").ok()?).map(|kb| kb / 1024)
+}
+
+fn parse_vm_rss_kb(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Used and total space in MB of the file system containing `path`
+fn disk_usage_mb(path: &std::path::Path) -> Option<(u64, u64)> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stats: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stats) } != 0 {
+        return None;
+    }
+    let block_size = stats.f_frsize as u64;
+    let total = stats.f_blocks as u64 * block_size;
+    let free = stats.f_bfree as u64 * block_size;
+    Some(((total - free) / (1024 * 1024), total / (1024 * 1024)))
+}
+
 pub struct PositionGenerator {
     pub wembed_path: String,
     pub output_path: String,
@@ -27,24 +156,31 @@ impl PositionGenerator {
         std::fs::create_dir_all(&self.output_path)?;
         crate::pull_files(true, None, None, None).await?;
 
+        let monitor = Arc::new(ResourceMonitor::from_env()?);
+        let monitor_task = tokio::spawn(monitor_resource_usage(monitor.clone()));
+        let mut batcher = PushBatcher::from_env()?;
+
         loop {
-            match self.job_manager.claim_next_job().await {
-                Ok(Some(job)) => {
-                    println!(
-                        "Processing job {} - Graph {} Dim {}",
-                        job.job_id, job.graph_id, job.embedding_dim
-                    );
-                    if let Err(e) = self.process_job(job.clone()).await {
-                        eprintln!("Job {} failed: {}", job.job_id, e);
-                        let _ = self.job_manager.fail_job(job.job_id, &e.to_string()).await;
-                    }
-                    if let Err(e) = crate::push_files().await {
+            // Jobs run one at a time, so the last job has finished here
+            if monitor.memory_exceeded() {
+                println!(
+                    "Memory usage {} exceeds the limit, not claiming further jobs",
+                    monitor.sample()
+                );
+                monitor_task.abort();
+                batcher.flush().await?;
+                return Ok(());
+            }
+            match self.process_next_job().await {
+                Ok(Some(_)) => {
+                    batcher.record(1);
+                    if let Err(e) = batcher.push_if_due().await {
                         eprintln!("Failed to sync files: {e}");
                     }
                 }
                 Ok(None) => {
                     sleep(Duration::from_secs(5)).await;
-                    crate::push_files().await?;
+                    batcher.push_if_due().await?;
                 }
                 Err(e) => {
                     eprintln!("Error claiming job: {}", e);
@@ -54,12 +190,27 @@ impl PositionGenerator {
         }
     }
 
-    async fn process_job(&self, job: PositionJob) -> Result<(), Box<dyn std::error::Error>> {
-        let output_filename = format!(
-            "graph-{}_dim-{}_dim-hint-{}_seed-{}.log",
-            job.graph_id, job.embedding_dim, job.dim_hint, job.seed
+    /// Claims the next job and runs it, one step of [`Self::run_daemon`]. A
+    /// failure of the job itself is recorded with [`JobManager::fail_job`].
+    /// Returns the claimed job, `None` if no job is eligible.
+    pub async fn process_next_job(&self) -> Result<Option<PositionJob>, sqlx::Error> {
+        let Some(job) = self.job_manager.claim_next_job().await? else {
+            return Ok(None);
+        };
+        println!(
+            "Processing job {} - Graph {} Dim {}",
+            job.job_id, job.graph_id, job.embedding_dim
         );
-        let output_path_without_prefix = format!("generated/positions/{}", output_filename);
+        if let Err(e) = self.process_job(job.clone()).await {
+            eprintln!("Job {} failed: {}", job.job_id, e);
+            let _ = self.job_manager.fail_job(job.job_id, &e.to_string()).await;
+        }
+        Ok(Some(job))
+    }
+
+    async fn process_job(&self, job: PositionJob) -> Result<(), Box<dyn std::error::Error>> {
+        let output_path_without_prefix =
+            positions_file_path(job.graph_id, job.embedding_dim, job.dim_hint, job.seed);
         let output_path = format!("{}/{}", self.output_path, output_path_without_prefix);
         let graph_path = format!("{}/{}", self.output_path, job.graph_file_path);
         let graph = rembed::graph::Graph::parse_from_edge_list_file(
@@ -72,7 +223,8 @@ impl PositionGenerator {
             max_iterations: job.max_iterations as usize,
             ..Default::default()
         };
-        run_embedding_dynamic(
+        let optimizer_options = options.optimizer_json();
+        let actual_iterations = run_embedding_dynamic(
             job.seed as u64,
             &graph,
             options,
@@ -86,27 +238,69 @@ impl PositionGenerator {
 
         let checksum = calculate_file_checksum(&output_path)?;
 
-        // Parse actual iterations from log file if needed
-        let actual_iterations = parse_actual_iterations(&output_path).unwrap_or(None);
-
         self.job_manager
             .complete_job(
                 job.job_id,
                 &output_path_without_prefix,
                 &checksum,
-                actual_iterations,
+                Some(actual_iterations as i32),
+                &optimizer_options,
             )
             .await?;
-        println!("Completed job {} - {}", job.job_id, output_filename);
+        println!(
+            "Completed job {} - {}",
+            job.job_id, output_path_without_prefix
+        );
         Ok(())
     }
 
-    pub async fn show_summary_status(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let (pending, running, completed, failed) = self.job_manager.get_job_stats().await?;
+    /// Prints the job counts, and the completion and arrival rates over the
+    /// last `window_hours` with the time the queue needs to clear at them
+    pub async fn show_summary_status(
+        &self,
+        window_hours: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (pending, running, completed, failed, failed_permanent) =
+            self.job_manager.get_job_stats().await?;
+        println!(
+            "Jobs: {} pending, {} running, {} completed, {} failed, {} failed permanently",
+            pending, running, completed, failed, failed_permanent
+        );
+
+        let (completion_rate, arrival_rate) = self.job_manager.queue_trend(window_hours).await?;
         println!(
-            "Jobs: {} pending, {} running, {} completed, {} failed",
-            pending, running, completed, failed
+            "Last {window_hours}h: {completion_rate:.2} jobs/h completed, {arrival_rate:.2} jobs/h created"
         );
+        // Failed jobs are retried, so they are still part of the queue
+        let remaining = pending + running + failed;
+        match crate::job_manager::queue_clearance_eta(remaining, completion_rate, arrival_rate) {
+            Some(eta) => println!(
+                "ETA for queue clearance: {}h {}m",
+                eta.num_hours(),
+                eta.num_minutes() % 60
+            ),
+            None => println!("ETA for queue clearance: never, the queue is not shrinking"),
+        }
+
+        let failed_jobs = self.job_manager.get_permanently_failed_jobs().await?;
+        if !failed_jobs.is_empty() {
+            println!();
+            println!(
+                "!! {} jobs failed {} times and will not be retried, fix them and run `retry-job <id>`:",
+                failed_jobs.len(),
+                crate::job_manager::MAX_ATTEMPTS
+            );
+            for job in failed_jobs {
+                println!(
+                    "Job {}: graph_id: {}, dim: {}, attempts: {}, last error: {}",
+                    job.job_id,
+                    job.graph_id,
+                    job.embedding_dim,
+                    job.attempts,
+                    job.last_error.as_deref().unwrap_or("-")
+                );
+            }
+        }
         Ok(())
     }
 
@@ -124,29 +318,75 @@ impl PositionGenerator {
     }
 }
 
-fn calculate_file_checksum(file_path: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let contents = std::fs::read(file_path)?;
-    let mut hasher = Sha256::new();
-    hasher.update(&contents);
-    Ok(format!("{:x}", hasher.finalize()))
+/// Path of a position result relative to the data directory, as stored in
+/// `position_results.file_path`
+pub fn positions_file_path(graph_id: i64, embedding_dim: i32, dim_hint: i32, seed: i32) -> String {
+    format!(
+        "generated/positions/graph-{graph_id}_dim-{embedding_dim}_dim-hint-{dim_hint}_seed-{seed}.log"
+    )
+}
+
+/// Size of the chunks in which [`calculate_file_checksum`] reads a file
+const CHECKSUM_CHUNK_SIZE: usize = 8 << 20;
+
+/// Hex SHA-256 of a file, as stored in the `checksum` columns.
+///
+/// The file is streamed in chunks instead of being read into memory at once,
+/// and the next chunk is read on a separate thread while the current one is
+/// hashed. The digest is the plain SHA-256 of the contents, so checksums stay
+/// comparable to the ones already in the database.
+pub(crate) fn calculate_file_checksum(
+    file_path: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let file = std::fs::File::open(file_path)?;
+    Ok(streaming_checksum(file, CHECKSUM_CHUNK_SIZE)?)
 }
 
-fn parse_actual_iterations(file_path: &str) -> Result<Option<i32>, Box<dyn std::error::Error>> {
-    let mut max_iteration = 0;
-    for line in std::fs::read_to_string(file_path)?.lines() {
-        if line.starts_with("ITERATION") {
-            max_iteration = line.split_whitespace().last().unwrap().parse()?;
+fn streaming_checksum(mut reader: impl Read + Send, chunk_size: usize) -> io::Result<String> {
+    std::thread::scope(|scope| {
+        // At most two chunks wait for the hasher, bounding memory usage
+        let (sender, receiver) = mpsc::sync_channel(2);
+        scope.spawn(move || {
+            loop {
+                let mut chunk = Vec::with_capacity(chunk_size);
+                match (&mut reader)
+                    .take(chunk_size as u64)
+                    .read_to_end(&mut chunk)
+                {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        if sender.send(Ok(chunk)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let _ = sender.send(Err(e));
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut hasher = Sha256::new();
+        for chunk in receiver {
+            hasher.update(chunk?);
         }
-    }
-    Ok(Some(max_iteration))
+        Ok(format!("{:x}", hasher.finalize()))
+    })
 }
+
+/// Embedding dimensions [`run_embedding_dynamic`] is compiled for
+pub const SUPPORTED_DIMS: [usize; 16] = [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 32];
+
+/// Embeds `graph` and writes every tenth iteration to `output_path`. Returns
+/// the number of iterations the embedder ran.
 fn run_embedding_dynamic(
     seed: u64,
     graph: &rembed::graph::Graph,
     options: EmbedderOptions,
     dim: usize,
     output_path: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<usize, Box<dyn std::error::Error>> {
     match dim {
         2 => run_embedding::<2, Sprk<2>>(seed, graph, options, output_path),
         3 => run_embedding::<3, Sprk<3>>(seed, graph, options, output_path),
@@ -172,7 +412,7 @@ fn run_embedding<'a, const D: usize, SI: SpatialIndex<D> + Clone + Sync + Embedd
     graph: &'a rembed::graph::Graph,
     options: EmbedderOptions,
     output_path: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<usize, Box<dyn std::error::Error>> {
     let max_iterations = options.max_iterations;
     let mut embedder: WEmbedder<SI> = WEmbedder::random(seed, graph, options);
     let progress_bar = crate::create_progress_bar(max_iterations);
@@ -182,5 +422,46 @@ fn run_embedding<'a, const D: usize, SI: SpatialIndex<D> + Clone + Sync + Embedd
     });
     let sparse_iterations: Vec<_> = embedder.history().iter().step_by(10).cloned().collect();
 
-    rembed::parsing::write_test_file(output_path, sparse_iterations.as_slice())
+    rembed::parsing::write_test_file(
+        output_path,
+        sparse_iterations.as_slice(),
+        graph.original_ids.as_deref(),
+    )?;
+    Ok(embedder.iteration())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_resident_memory_from_proc_status() {
+        let status = "Name:\tbenchmark\nVmPeak:\t  900000 kB\nVmRSS:\t  524288 kB\nThreads:\t8\n";
+        assert_eq!(parse_vm_rss_kb(status), Some(524288));
+        assert_eq!(parse_vm_rss_kb("Name:\tbenchmark\n"), None);
+        // Linux only, but that is where the daemon runs
+        assert!(memory_usage_mb().is_some_and(|mb| mb > 0));
+    }
+
+    #[test]
+    fn streaming_checksum_matches_one_shot_digest() {
+        let contents: Vec<u8> = (0..10_000u32).map(|i| (i * 31 % 251) as u8).collect();
+        let expected = format!("{:x}", Sha256::digest(&contents));
+        for chunk_size in [1, 1000, 4096, 10_000, 20_000] {
+            assert_eq!(
+                streaming_checksum(contents.as_slice(), chunk_size).unwrap(),
+                expected
+            );
+        }
+        assert_eq!(
+            streaming_checksum(&[][..], 1000).unwrap(),
+            format!("{:x}", Sha256::digest(b""))
+        );
+
+        let path = std::env::temp_dir().join(format!("checksum-test-{}", std::process::id()));
+        std::fs::write(&path, &contents).unwrap();
+        let checksum = calculate_file_checksum(&path.to_string_lossy()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(checksum, expected);
+    }
 }
@@ -1,6 +1,69 @@
 use chrono::{DateTime, Utc};
 use sqlx::{Pool, Postgres};
 
+/// Number of times a job is run before it is marked `failed-permanent`
+pub const MAX_ATTEMPTS: i32 = 5;
+/// Delay before the first retry of a failed job, doubled for every further
+/// attempt up to [`MAX_BACKOFF`]
+pub const BASE_BACKOFF: chrono::Duration = chrono::Duration::minutes(10);
+pub const MAX_BACKOFF: chrono::Duration = chrono::Duration::hours(12);
+
+/// Embedding dimensions jobs are created for unless `--dims` is given
+pub const DEFAULT_JOB_DIMS: [i32; 15] = [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+
+/// Checks that the daemon can embed in all of `dims`, so no job is created
+/// that can only fail
+pub fn validate_job_dims(dims: &[i32]) -> Result<(), String> {
+    if dims.is_empty() {
+        return Err("no embedding dimensions given".to_string());
+    }
+    let supported = crate::generate_positions::SUPPORTED_DIMS;
+    match dims
+        .iter()
+        .find(|&&dim| !supported.contains(&(dim as usize)) || dim < 0)
+    {
+        Some(dim) => Err(format!(
+            "the daemon cannot embed in {dim} dimensions, supported are {supported:?}"
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Time until `remaining` jobs are done if jobs keep completing at
+/// `completion_rate` and arriving at `arrival_rate` (both per hour). `None`
+/// if the queue does not shrink.
+pub fn queue_clearance_eta(
+    remaining: i64,
+    completion_rate: f64,
+    arrival_rate: f64,
+) -> Option<chrono::Duration> {
+    if remaining <= 0 {
+        return Some(chrono::Duration::zero());
+    }
+    let net_rate = completion_rate - arrival_rate;
+    if net_rate <= 0.0 {
+        return None;
+    }
+    chrono::Duration::try_seconds((remaining as f64 / net_rate * 3600.0).round() as i64)
+}
+
+/// Time to wait before a job that failed its `attempts`-th run is retried
+pub fn backoff_delay(attempts: i32) -> chrono::Duration {
+    let doublings = (attempts - 1).clamp(0, 30) as u32;
+    BASE_BACKOFF
+        .checked_mul(2i32.pow(doublings))
+        .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF))
+}
+
+#[derive(Debug, Clone)]
+pub struct FailedJob {
+    pub job_id: i64,
+    pub graph_id: i64,
+    pub embedding_dim: i32,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct PositionJob {
     pub job_id: i64,
@@ -46,10 +109,12 @@ impl JobManager {
         let job = sqlx::query!(
             r#"
             UPDATE position_jobs 
-            SET status = 'running', claimed_at = NOW(), claimed_by_hostname = $1
+            SET status = 'running', claimed_at = NOW(), claimed_by_hostname = $1,
+                attempts = attempts + 1, next_eligible_at = NULL
             WHERE job_id = (
                 SELECT job_id FROM position_jobs 
-                WHERE status = 'pending' 
+                WHERE status = 'pending'
+                    OR (status = 'failed' AND next_eligible_at <= NOW())
                 ORDER BY embedding_dim,created_at ASC LIMIT 1 FOR UPDATE SKIP LOCKED
             )
             RETURNING job_id, graph_id, embedding_dim, dim_hint, max_iterations, seed
@@ -90,6 +155,7 @@ impl JobManager {
         file_path: &str,
         checksum: &str,
         actual_iterations: Option<i32>,
+        optimizer_options: &str,
     ) -> Result<(), sqlx::Error> {
         let mut tx = self.pool.begin().await?;
 
@@ -99,13 +165,16 @@ impl JobManager {
             job_id
         ).fetch_one(&mut *tx).await?;
 
-        // Insert result
+        // Insert result, or replace the file of one flagged by verify-files
+        // so that its result_id and measurements stay
         sqlx::query!(
             r#"
-            INSERT INTO position_results (graph_id, embedding_dim, dim_hint, max_iterations, actual_iterations, seed, file_path, checksum)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            INSERT INTO position_results (graph_id, embedding_dim, dim_hint, max_iterations, actual_iterations, seed, file_path, checksum, optimizer_options)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::text::jsonb)
+            ON CONFLICT ON CONSTRAINT unique_result_params DO UPDATE
+            SET actual_iterations = EXCLUDED.actual_iterations, file_path = EXCLUDED.file_path, checksum = EXCLUDED.checksum, optimizer_options = EXCLUDED.optimizer_options
             "#,
-            job.graph_id, job.embedding_dim, job.dim_hint, job.max_iterations, actual_iterations, job.seed, file_path, checksum
+            job.graph_id, job.embedding_dim, job.dim_hint, job.max_iterations, actual_iterations, job.seed, file_path, checksum, optimizer_options
         ).execute(&mut *tx).await?;
 
         // Mark job complete
@@ -120,28 +189,71 @@ impl JobManager {
         Ok(())
     }
 
+    /// Marks a job as failed. It is claimed again after [`backoff_delay`]
+    /// unless it used up its [`MAX_ATTEMPTS`], in which case it stays
+    /// `failed-permanent` until [`Self::retry_job`] is called.
     pub async fn fail_job(&self, job_id: i64, error: &str) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let attempts = sqlx::query_scalar!(
+            "SELECT attempts FROM position_jobs WHERE job_id = $1 FOR UPDATE",
+            job_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let (status, next_eligible_at) = if attempts >= MAX_ATTEMPTS {
+            ("failed-permanent", None)
+        } else {
+            ("failed", Some(Utc::now() + backoff_delay(attempts)))
+        };
         sqlx::query!(
-            "UPDATE position_jobs SET status = 'failed', error_message = $1 WHERE job_id = $2",
+            r#"
+            UPDATE position_jobs
+            SET status = $1, error_message = $2, error_history = array_append(error_history, $2),
+                next_eligible_at = $3
+            WHERE job_id = $4
+            "#,
+            status,
             error,
+            next_eligible_at,
             job_id
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
+
+        tx.commit().await?;
         Ok(())
     }
 
-    pub async fn create_jobs_for_graph(&self, graph_id: i64) -> Result<i32, sqlx::Error> {
-        // let dimensions = [2, 4, 8, 16, 32];
-        // let dimensions = [2, 4, 8];
-        // let dimensions = [2, 4, 8, 16];
-        // let dimensions = [2, 4, 8, 16, 32];
-        let dimensions = [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+    /// Resets a failed job so that it is claimed again right away with a
+    /// fresh attempt budget. Returns false if the job is not failed.
+    pub async fn retry_job(&self, job_id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE position_jobs
+            SET status = 'pending', attempts = 0, next_eligible_at = NULL,
+                claimed_at = NULL, claimed_by_hostname = NULL
+            WHERE job_id = $1 AND status IN ('failed', 'failed-permanent')
+            "#,
+            job_id
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Creates a job per dimension in `dimensions` that the graph has no job
+    /// for yet. Returns the number of jobs created.
+    pub async fn create_jobs_for_graph(
+        &self,
+        graph_id: i64,
+        dimensions: &[i32],
+    ) -> Result<i32, sqlx::Error> {
         let max_iterations = 1000;
         let seed = 42; // Fixed seed for reproducibility
         let mut job_count = 0;
 
-        for embedding_dim in dimensions {
+        for &embedding_dim in dimensions {
             let result = sqlx::query!(
                 r#"
                 INSERT INTO position_jobs (graph_id, embedding_dim, dim_hint, max_iterations, seed)
@@ -165,7 +277,7 @@ impl JobManager {
         Ok(job_count)
     }
 
-    pub async fn create_missing_jobs(&self) -> Result<i32, sqlx::Error> {
+    pub async fn create_missing_jobs(&self, dimensions: &[i32]) -> Result<i32, sqlx::Error> {
         let graphs = sqlx::query!("SELECT graph_id FROM graphs where  deg = 15")
             .fetch_all(&self.pool)
             .await?;
@@ -173,7 +285,9 @@ impl JobManager {
         let pb = crate::create_progress_bar(graphs.len());
         for graph in graphs {
             pb.inc(1);
-            total_created += self.create_jobs_for_graph(graph.graph_id).await?;
+            total_created += self
+                .create_jobs_for_graph(graph.graph_id, dimensions)
+                .await?;
         }
         Ok(total_created)
     }
@@ -207,14 +321,16 @@ impl JobManager {
             .collect())
     }
 
-    pub async fn get_job_stats(&self) -> Result<(i64, i64, i64, i64), sqlx::Error> {
+    // pending, running, completed, failed, failed permanently
+    pub async fn get_job_stats(&self) -> Result<(i64, i64, i64, i64, i64), sqlx::Error> {
         let result = sqlx::query!(
             r#"
             SELECT 
                 COUNT(*) FILTER (WHERE status = 'pending') as pending,
                 COUNT(*) FILTER (WHERE status = 'running') as running,
                 COUNT(*) FILTER (WHERE status = 'completed') as completed,
-                COUNT(*) FILTER (WHERE status = 'failed') as failed
+                COUNT(*) FILTER (WHERE status = 'failed') as failed,
+                COUNT(*) FILTER (WHERE status = 'failed-permanent') as failed_permanent
             FROM position_jobs
             "#
         )
@@ -226,9 +342,56 @@ impl JobManager {
             result.running.unwrap_or(0),
             result.completed.unwrap_or(0),
             result.failed.unwrap_or(0),
+            result.failed_permanent.unwrap_or(0),
         ))
     }
 
+    /// Jobs completed and jobs created per hour over the last
+    /// `window_hours`, i.e. `(completion_rate, arrival_rate)`. The queue
+    /// grows while the arrival rate is higher.
+    pub async fn queue_trend(&self, window_hours: f64) -> Result<(f64, f64), sqlx::Error> {
+        let result = sqlx::query!(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE completed_at >= NOW() - make_interval(secs => $1)) as completed,
+                COUNT(*) FILTER (WHERE created_at >= NOW() - make_interval(secs => $1)) as created
+            FROM position_jobs
+            "#,
+            window_hours * 3600.0
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((
+            result.completed.unwrap_or(0) as f64 / window_hours,
+            result.created.unwrap_or(0) as f64 / window_hours,
+        ))
+    }
+
+    pub async fn get_permanently_failed_jobs(&self) -> Result<Vec<FailedJob>, sqlx::Error> {
+        let results = sqlx::query!(
+            r#"
+            SELECT job_id, graph_id, embedding_dim, attempts, error_message
+            FROM position_jobs
+            WHERE status = 'failed-permanent'
+            ORDER BY job_id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(results
+            .into_iter()
+            .map(|row| FailedJob {
+                job_id: row.job_id,
+                graph_id: row.graph_id,
+                embedding_dim: row.embedding_dim,
+                attempts: row.attempts,
+                last_error: row.error_message,
+            })
+            .collect())
+    }
+
     // For all running jobs: hostname, duration_claimed, embedding_dim, n, graph_id
     pub async fn get_running_jobs(
         &self,
@@ -279,3 +442,42 @@ impl JobManager {
         Ok(running_jobs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        assert_eq!(backoff_delay(0), BASE_BACKOFF);
+        assert_eq!(backoff_delay(1), BASE_BACKOFF);
+        assert_eq!(backoff_delay(2), BASE_BACKOFF * 2);
+        assert_eq!(backoff_delay(4), BASE_BACKOFF * 8);
+        assert_eq!(backoff_delay(7), BASE_BACKOFF * 64);
+        assert_eq!(backoff_delay(8), MAX_BACKOFF);
+        assert_eq!(backoff_delay(i32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn queue_clears_only_while_it_shrinks() {
+        assert_eq!(
+            queue_clearance_eta(30, 12.0, 2.0),
+            Some(chrono::Duration::hours(3))
+        );
+        assert_eq!(
+            queue_clearance_eta(0, 0.0, 5.0),
+            Some(chrono::Duration::zero())
+        );
+        assert_eq!(queue_clearance_eta(30, 2.0, 2.0), None);
+        assert_eq!(queue_clearance_eta(30, 0.0, 0.0), None);
+    }
+
+    #[test]
+    fn job_dims_must_be_supported_by_the_daemon() {
+        assert!(validate_job_dims(&DEFAULT_JOB_DIMS).is_ok());
+        assert!(validate_job_dims(&[2, 4, 8, 16, 32]).is_ok());
+        assert!(validate_job_dims(&[]).is_err());
+        assert!(validate_job_dims(&[2, 17]).is_err());
+        assert!(validate_job_dims(&[-2]).is_err());
+    }
+}
@@ -7,12 +7,39 @@ mod generate_graphs;
 pub mod generate_positions;
 pub mod intrinsic_dim;
 pub mod job_manager;
+pub mod neighbor_stability;
+pub mod object_storage;
+pub mod quality;
+pub mod register_result;
 pub mod statistics;
+pub mod sync;
 pub mod synthetic_data;
 
 pub use generate_graphs::GraphGenerator;
 pub use generate_positions::PositionGenerator;
 use indicatif::{ProgressBar, ProgressStyle};
+use sqlx::{PgPool, Postgres, migrate::MigrateDatabase};
+
+/// The migrations in `migrations/`, embedded at compile time
+pub static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations");
+
+/// Applies all pending migrations
+pub async fn migrate(pool: &PgPool) -> Result<(), sqlx::migrate::MigrateError> {
+    MIGRATOR.run(pool).await
+}
+
+/// Creates the database behind `database_url` if it does not exist yet and
+/// applies all migrations
+pub async fn init_db(database_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !Postgres::database_exists(database_url).await? {
+        println!("Creating database {database_url}");
+        Postgres::create_database(database_url).await?;
+    }
+    let pool = PgPool::connect(database_url).await?;
+    migrate(&pool).await?;
+    println!("Database is up to date");
+    Ok(())
+}
 
 fn create_progress_bar(total_graphs: usize) -> ProgressBar {
     let pb = ProgressBar::new(total_graphs as u64);
@@ -24,18 +51,92 @@ fn create_progress_bar(total_graphs: usize) -> ProgressBar {
     );
     pb
 }
+/// Pushes the files of `DATA_DIRECTORY` that changed since the last push,
+/// see [`sync`]. Only the changed files are passed to rsync, or uploaded with
+/// the S3 backend of [`object_storage`].
 pub async fn push_files() -> Result<(), Box<dyn std::error::Error>> {
-    let sync_destination =
-        std::env::var("RSYNC_DESTINATION").expect("Please set the RSYNC_DESTINATION env var");
+    let backend = object_storage::SyncBackend::from_env()?;
+    let sync_source =
+        std::env::var("DATA_DIRECTORY").expect("Please set the DATA_DIRECTORY env var");
+
+    let root = std::path::Path::new(&sync_source);
+    let manifest_path = root.join(sync::MANIFEST_FILE);
+    let mut manifest = sync::Manifest::load(&manifest_path)?;
+    let (num_files, delta) = sync::delta_on_disk(root, &manifest)?;
+
+    match &backend {
+        _ if delta.changed.is_empty() => {}
+        object_storage::SyncBackend::S3 { bucket, prefix } => {
+            println!(
+                "Uploading {} of {num_files} files to: s3://{bucket}/{prefix}",
+                delta.changed.len()
+            );
+            object_storage::push(bucket, prefix, root, &delta.changed).await?;
+        }
+        object_storage::SyncBackend::Rsync => {
+            let sync_destination = std::env::var("RSYNC_DESTINATION")
+                .expect("Please set the RSYNC_DESTINATION env var");
+            println!(
+                "Syncing {} of {num_files} files to: {sync_destination}",
+                delta.changed.len()
+            );
+            let list_path =
+                std::env::temp_dir().join(format!("rembed-push-{}", std::process::id()));
+            std::fs::write(&list_path, delta.changed.join("\n"))?;
+            // With --files-from, the paths in the list are relative to the source
+            let status = tokio::process::Command::new("rsync")
+                .arg("-rlvtz")
+                .arg(format!("--files-from={}", list_path.display()))
+                .arg(format!("{}/", sync_source.trim_end_matches('/')))
+                .arg(format!("{}/", sync_destination.trim_end_matches('/')))
+                .status()
+                .await;
+            let _ = std::fs::remove_file(&list_path);
+            if !status?.success() {
+                return Err("Rsync failed".into());
+            }
+        }
+    }
+    manifest.apply(delta);
+    manifest.save(&manifest_path)?;
+
+    println!("File sync completed successfully");
+    Ok(())
+}
+
+/// Pushes the whole `DATA_DIRECTORY` with a single rsync over the tree, or
+/// uploads every file with the S3 backend, then records every file in the
+/// manifest of [`push_files`]
+pub async fn push_all_files() -> Result<(), Box<dyn std::error::Error>> {
     let sync_source =
         std::env::var("DATA_DIRECTORY").expect("Please set the DATA_DIRECTORY env var");
+    let root = std::path::Path::new(&sync_source);
 
+    if let object_storage::SyncBackend::S3 { bucket, prefix } =
+        object_storage::SyncBackend::from_env()?
+    {
+        let mut manifest = sync::Manifest::default();
+        let (_, delta) = sync::delta_on_disk(root, &manifest)?;
+        println!(
+            "Uploading {} files to: s3://{bucket}/{prefix}",
+            delta.changed.len()
+        );
+        object_storage::push(&bucket, &prefix, root, &delta.changed).await?;
+        manifest.apply(delta);
+        manifest.save(&root.join(sync::MANIFEST_FILE))?;
+        println!("File sync completed successfully");
+        return Ok(());
+    }
+
+    let sync_destination =
+        std::env::var("RSYNC_DESTINATION").expect("Please set the RSYNC_DESTINATION env var");
     println!("Syncing files to: {}", sync_destination);
 
     let status = tokio::process::Command::new("rsync")
         .arg("-rlvtz")
         .arg("--progress")
-        .arg(sync_source)
+        .arg(format!("--exclude=/{}", sync::MANIFEST_FILE))
+        .arg(&sync_source)
         .arg(&sync_destination)
         .status()
         .await?;
@@ -44,6 +145,11 @@ pub async fn push_files() -> Result<(), Box<dyn std::error::Error>> {
         return Err("Rsync failed".into());
     }
 
+    let mut manifest = sync::Manifest::default();
+    let (_, delta) = sync::delta_on_disk(root, &manifest)?;
+    manifest.apply(delta);
+    manifest.save(&root.join(sync::MANIFEST_FILE))?;
+
     println!("File sync completed successfully");
     Ok(())
 }
@@ -53,6 +159,27 @@ pub async fn pull_files(
     graph_id: Option<i64>,
     result_id: Option<i64>,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    if let object_storage::SyncBackend::S3 { bucket, prefix } =
+        object_storage::SyncBackend::from_env()?
+    {
+        let data_directory =
+            std::env::var("DATA_DIRECTORY").expect("Please set the DATA_DIRECTORY env var");
+        // The same selection as the rsync filters below
+        let path = path.map(|path| path.trim_start_matches('/'));
+        let id = graph_id.or(result_id).map(|id| id.to_string());
+        let filter = |relative: &str| {
+            let file_name = relative.rsplit('/').next().unwrap_or(relative);
+            (!only_graphs || relative.starts_with("generated/graphs/"))
+                && path.is_none_or(|path| relative.starts_with(path))
+                && id.as_ref().is_none_or(|id| file_name.contains(id.as_str()))
+        };
+        println!("Downloading files from: s3://{bucket}/{prefix}");
+        let root = std::path::Path::new(&data_directory);
+        let (count, current) = object_storage::pull(&bucket, &prefix, root, filter).await?;
+        println!("Downloaded {count} files, {current} were already up to date");
+        return Ok(());
+    }
+
     let mut sync_destination =
         std::env::var("RSYNC_DESTINATION").expect("Please set the RSYNC_DESTINATION env var");
     let mut sync_source =
@@ -1,5 +1,6 @@
 use benchmark::benchmark::LoadData;
 use benchmark::benchmark::runner::BenchmarkType;
+use benchmark::code_state::{RepoCodeStateManager, radius_cost_report_html, speedup_report_html};
 use benchmark::correctness_test::CorrectnessTestManager;
 use clap::{Parser, Subcommand};
 use dotenv::dotenv;
@@ -8,7 +9,8 @@ use std::env;
 use std::str::FromStr;
 
 use benchmark::generate_positions::PositionGenerator;
-use benchmark::job_manager::JobManager;
+use benchmark::job_manager::{DEFAULT_JOB_DIMS, JobManager, validate_job_dims};
+use benchmark::register_result::{ExternalResult, Registration, register_external_result};
 use benchmark::{GraphGenerator, push_files};
 
 #[derive(Parser)]
@@ -31,7 +33,16 @@ enum Commands {
         result_id: Option<i64>,
     },
     /// Push files to remote directory
-    Push,
+    Push {
+        /// Sync the whole data directory instead of the files that changed since the last push
+        #[arg(long)]
+        full: bool,
+    },
+    /// Apply pending database migrations to the database at DATABASE_URL
+    Migrate,
+    /// Set up a local database from scratch: create the database at DATABASE_URL
+    /// (default postgresql://localhost/rembed) if needed and apply all migrations
+    InitDb,
     /// Run Benchmarks matching the specified parameters
     Bench {
         /// Only run the last iteration of the benchmark (default: false)
@@ -97,12 +108,51 @@ enum Commands {
         /// Set benchmark to fast mode with shorter warmup and measurement times (for quick local testing)
         #[arg(long, default_value_t = false)]
         fast: bool,
+        /// Sample each benchmark until the relative standard error of the mean wall time drops below this value (e.g. 0.02) instead of using criterion's fixed measurement time
+        #[arg(long)]
+        precision: Option<f64>,
+        /// Store a histogram of the wall time per query over log-spaced buckets with each measurement
+        #[arg(long, default_value_t = false)]
+        histograms: bool,
+        /// Before measuring, check that all exact structures return the same neighbor sets for the queries and warn about the ones that differ
+        #[arg(long, default_value_t = false)]
+        verify_consistency: bool,
         /// Export datasets instead of running the benchmarks
         #[arg(long, default_value_t = false)]
         export_only: bool,
+        /// Store results even if the embedding quality check regressed against the baseline
+        #[arg(long, default_value_t = false)]
+        allow_quality_regression: bool,
+        /// Write criterion's console output to criterion.log instead of the terminal, leaving it to the progress dashboard
+        #[arg(long, default_value_t = false)]
+        quiet_criterion: bool,
+        /// Skip graphs with more than this many nodes, in the correctness tests as well
+        #[arg(long)]
+        max_nodes: Option<usize>,
+        /// Draw the queries at random with this seed instead of spacing them evenly, so the run can be repeated with the same queries. Stored with each measurement
+        #[arg(long)]
+        sampling_seed: Option<u64>,
+    },
+    /// Run the canonical embedding cases and compare their f1 against quality_baseline.tsv
+    QualityCheck {
+        /// Allowed drop in f1 per case
+        #[arg(long, default_value_t = benchmark::quality::DEFAULT_TOLERANCE)]
+        tolerance: f64,
+        /// Also write precision, recall and f1 per case and node weight class to this CSV file
+        #[arg(long)]
+        weight_classes: Option<String>,
     },
+    /// Rerun the canonical embedding cases and record them as the new quality baseline for the current commit
+    UpdateQualityBaseline,
     /// Generate graphs using GIRGs
-    GenerateGraphs,
+    GenerateGraphs {
+        /// Embedding dimensions to create position jobs for (e.g. "2,4,8,16,32"), default 2 to 16
+        #[arg(long, value_delimiter = ',')]
+        dims: Option<Vec<i32>>,
+        /// Keep the temp_genhrg_* files genhrg writes, and log their paths if a graph fails
+        #[arg(long, default_value_t = false)]
+        keep_temp: bool,
+    },
 
     /// Generate position embeddings (daemon mode)
     GeneratePositions,
@@ -122,23 +172,64 @@ enum Commands {
         /// Show detailed status information
         #[arg(short, action)]
         v: bool,
+        /// Hours over which the job completion and arrival rates are taken
+        #[arg(long, default_value_t = 24.0)]
+        window_hours: f64,
     },
 
     /// Create position generation jobs for a graph
     CreateJobs {
         /// Graph ID to create jobs for
         graph_id: i64,
+        /// Embedding dimensions to create position jobs for (e.g. "2,4,8,16,32"), default 2 to 16
+        #[arg(long, value_delimiter = ',')]
+        dims: Option<Vec<i32>>,
     },
 
     /// Create missing position jobs for all graphs
-    CreateMissingJobs,
+    CreateMissingJobs {
+        /// Embedding dimensions to create position jobs for (e.g. "2,4,8,16,32"), default 2 to 16
+        #[arg(long, value_delimiter = ',')]
+        dims: Option<Vec<i32>>,
+    },
+
+    /// Reset a failed or permanently failed job so that it is run again
+    RetryJob {
+        /// Job ID to retry
+        job_id: i64,
+    },
+
+    /// Register a positions file computed outside the daemon as a position result
+    RegisterResult {
+        #[arg(long)]
+        graph_id: i64,
+        /// Embedding dimension of the positions
+        #[arg(long)]
+        dim: i32,
+        /// Latent dimension hint the embedding used (defaults to --dim)
+        #[arg(long)]
+        dim_hint: Option<i32>,
+        #[arg(long)]
+        seed: i32,
+        #[arg(long)]
+        max_iterations: i32,
+        /// Positions file, binary or text
+        #[arg(long)]
+        file: String,
+        /// Move the file into the data directory instead of copying it
+        #[arg(long = "move")]
+        move_file: bool,
+        /// Also generate the correctness test of the result
+        #[arg(long)]
+        create_test: bool,
+    },
 
     /// Clean up stale jobs
     Cleanup {
         /// Timeout in hours for stale jobs (default: 2)
         #[arg(long, default_value = "2")]
         timeout_hours: i32,
-        /// Flag to clean up failed jobs
+        /// Release failed jobs to be retried after their backoff
         #[arg(long, action)]
         failed: bool,
     },
@@ -150,10 +241,77 @@ enum Commands {
         dry_run: bool,
     },
 
+    /// Check that all files referenced in the database exist and match their checksums
+    VerifyFiles {
+        /// Mark rows with missing or corrupted files for re-generation
+        #[arg(long)]
+        fix: bool,
+    },
+
+    /// Compare a data structure's query speed between two commits per embedding dimension
+    CompareDims {
+        /// Data structure name as stored in the code states
+        structure: String,
+        /// Commit (or hash prefix) to compare against
+        baseline_commit: String,
+        /// Commit (or hash prefix) to compare
+        comparison_commit: String,
+        /// Write an HTML report with a bar chart of the speedups to this path
+        #[arg(long)]
+        html: Option<String>,
+    },
+
+    /// Show the query cost of each data structure over the radius sweep of a result
+    RadiusCost {
+        /// Result ID whose radius_sweep measurements are shown
+        result_id: i64,
+        /// Write an HTML report with a chart of cost vs radius to this path
+        #[arg(long)]
+        html: Option<String>,
+    },
+
+    /// Measure construction time of each structure across embedding dimensions of one graph
+    ConstructionScaling {
+        /// Graph whose position results are used
+        graph_id: i64,
+        /// Embedding dimensions to build at
+        #[arg(long, value_delimiter = ',', default_value = "2,4,8,16,32")]
+        dims: Vec<usize>,
+        /// Data structures to measure (all if omitted)
+        #[arg(long, value_delimiter = ',')]
+        structures: Option<Vec<String>>,
+        /// Set benchmark to fast mode with shorter warmup and measurement times
+        #[arg(long, default_value_t = false)]
+        fast: bool,
+        /// Sample each build until the relative standard error of the mean wall time drops below this value
+        #[arg(long)]
+        precision: Option<f64>,
+        /// Also write the times as CSV (structure,dim,wall_time_ns) for plotting
+        #[arg(long)]
+        csv: Option<String>,
+    },
+
     /// Generate correctness test file for a specific result
     GenerateTest {
         /// Result ID to generate test for
         result_id: i64,
+        /// Radius factor to compute the ground truth at
+        #[arg(long, default_value_t = benchmark::correctness_test::DEFAULT_TEST_RADIUS)]
+        radius: f64,
+    },
+
+    /// Compare the predicted neighbourhoods of a graph's embeddings between
+    /// consecutive embedding dimensions to find where adding dimensions stops
+    /// changing them
+    NeighborStability {
+        /// Graph whose position results are compared
+        graph_id: i64,
+        /// Embedding seed of the position results
+        #[arg(long, default_value_t = 42)]
+        seed: i32,
+        /// Jaccard similarity from which two dimensions count as the same
+        #[arg(long, default_value_t = 0.9)]
+        threshold: f64,
     },
 
     /// Compute Missing Intrinsic Dimensions
@@ -192,6 +350,19 @@ enum Commands {
         /// Also check for over-queried nodes (nodes returned by the structure but not in the ground truth)
         #[arg(long, default_value_t = false)]
         check_over_query: bool,
+        /// Minimum recall for approximate data structures such as LSH
+        #[arg(long, default_value_t = benchmark::correctness_test::DEFAULT_MIN_RECALL)]
+        min_recall: f64,
+        /// Radius factor to query with, only results with ground truth at this radius are tested
+        #[arg(long, default_value_t = benchmark::correctness_test::DEFAULT_TEST_RADIUS)]
+        radius: f64,
+        /// Instead of testing, trace the queries between two nodes of the last
+        /// iteration of --result-id through every structure that supports it
+        #[arg(long, num_args = 2, value_names = ["NODE_A", "NODE_B"], requires = "result_id")]
+        explain: Option<Vec<usize>>,
+        /// Skip graphs with more than this many nodes, even with --all-graphs
+        #[arg(long)]
+        max_nodes: Option<usize>,
     },
 
     /// Benchmark data structures with synthetic distributions
@@ -285,8 +456,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             benchmark::pull_files(false, None, graph_id, result_id).await?;
         }
 
-        Commands::Push => {
-            benchmark::push_files().await?;
+        Commands::Push { full } => {
+            if full {
+                benchmark::push_all_files().await?;
+            } else {
+                benchmark::push_files().await?;
+            }
+        }
+
+        Commands::Migrate => {
+            let database_url = env::var("DATABASE_URL")
+                .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
+            let pool = PgPool::connect(&database_url).await?;
+            benchmark::migrate(&pool).await?;
+            println!("Applied all migrations");
+        }
+
+        Commands::InitDb => {
+            let database_url = env::var("DATABASE_URL")
+                .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
+            benchmark::init_db(&database_url).await?;
         }
 
         Commands::Bench {
@@ -311,8 +500,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             skip_tests,
             dynamic_download,
             fast,
+            precision,
+            histograms,
+            verify_consistency,
             export_only,
+            allow_quality_regression,
+            quiet_criterion,
+            max_nodes,
+            sampling_seed,
         } => {
+            if let Some(precision) = precision
+                && !(precision > 0.0 && precision < 1.0)
+            {
+                return Err(format!("--precision must be in (0, 1), got {precision}").into());
+            }
             let database_url = env::var("DATABASE_URL")
                 .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
             let pool = PgPool::connect(&database_url).await?;
@@ -330,10 +531,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         Vec::new(),
                         dynamic_download,
                         false,
+                        benchmark::correctness_test::DEFAULT_TEST_RADIUS,
+                        max_nodes,
                     )
                     .await?;
             }
 
+            if store && !allow_quality_regression {
+                let report = benchmark::quality::check_quality(
+                    benchmark::quality::BASELINE_PATH,
+                    benchmark::quality::DEFAULT_TOLERANCE,
+                )?;
+                report.print();
+                if !report.regressions().is_empty() {
+                    return Err("embedding quality regressed against the baseline, \
+                        pass --allow-quality-regression to store results anyway"
+                        .into());
+                }
+            }
+
             let n_range = match n {
                 Some(range) => parse_usize_range(&range).map_err(|e| e.to_string())?,
                 None => (0, 0),
@@ -377,6 +593,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let mut load_data = LoadData::new(pool);
             load_data.store = store;
             load_data.allow_dirty = allow_dirty;
+            load_data.histograms = histograms;
+            load_data.verify_consistency = verify_consistency;
+            load_data.quiet_criterion = quiet_criterion;
+            load_data.max_nodes = max_nodes;
+            load_data.sampling_seed = sampling_seed;
 
             let benchmarks: Option<Vec<_>> = benchmarks.map(|x| {
                 x.iter()
@@ -403,19 +624,41 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     structures,
                     dynamic_download,
                     fast,
+                    precision,
                     export_only,
                 )
                 .await?;
         }
 
-        Commands::GenerateGraphs => {
+        Commands::GenerateGraphs { dims, keep_temp } => {
             let generator = GraphGenerator::new(
                 env::var("GIRGS_PATH").unwrap_or("../../girgs/build/genhrg".to_string()),
                 env::var("DATA_DIRECTORY").unwrap_or("../data/".to_string()),
-            );
+            )
+            .with_job_dims(job_dims(dims)?)
+            .with_keep_temp(keep_temp);
             generator.generate().await?;
         }
 
+        Commands::NeighborStability {
+            graph_id,
+            seed,
+            threshold,
+        } => {
+            let database_url = env::var("DATABASE_URL")
+                .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
+            let pool = PgPool::connect(&database_url).await?;
+
+            let stability =
+                benchmark::neighbor_stability::neighbor_stability(pool, graph_id, seed).await?;
+            println!("Neighbour Jaccard between dimensions for graph {graph_id}, seed {seed}:");
+            print!("{}", stability.table());
+            match stability.plateau(threshold) {
+                Some(dim) => println!("Neighbourhoods are stable from dim {dim} on"),
+                None => println!("Neighbourhoods do not reach a Jaccard of {threshold}"),
+            }
+        }
+
         Commands::Intrinsic {
             only_last_iteration,
         } => {
@@ -464,7 +707,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
-        Commands::Status { v } => {
+        Commands::Status { v, window_hours } => {
+            if window_hours.is_nan() || window_hours <= 0.0 {
+                return Err(format!("--window-hours must be positive, got {window_hours}").into());
+            }
             let database_url = env::var("DATABASE_URL")
                 .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
             let pool = PgPool::connect(&database_url).await?;
@@ -476,34 +722,89 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 job_manager,
             );
 
-            generator.show_summary_status().await?;
+            generator.show_summary_status(window_hours).await?;
 
             if v {
                 generator.show_detailed_status().await?;
             }
         }
 
-        Commands::CreateJobs { graph_id } => {
+        Commands::CreateJobs { graph_id, dims } => {
+            let dims = job_dims(dims)?;
             let database_url = env::var("DATABASE_URL")
                 .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
             let pool = PgPool::connect(&database_url).await?;
             let job_manager = JobManager::new(pool);
 
-            let created = job_manager.create_jobs_for_graph(graph_id).await?;
+            let created = job_manager.create_jobs_for_graph(graph_id, &dims).await?;
             println!("Created {} jobs for graph {}", created, graph_id);
         }
 
-        Commands::CreateMissingJobs => {
+        Commands::CreateMissingJobs { dims } => {
+            let dims = job_dims(dims)?;
             let database_url = env::var("DATABASE_URL")
                 .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
             let pool = PgPool::connect(&database_url).await?;
             let job_manager = JobManager::new(pool);
 
             println!("Creating missing position jobs for all graphs...");
-            let created = job_manager.create_missing_jobs().await?;
+            let created = job_manager.create_missing_jobs(&dims).await?;
             println!("Created {} new jobs across all graphs", created);
         }
 
+        Commands::RetryJob { job_id } => {
+            let database_url = env::var("DATABASE_URL")
+                .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
+            let pool = PgPool::connect(&database_url).await?;
+            let job_manager = JobManager::new(pool);
+
+            if job_manager.retry_job(job_id).await? {
+                println!("Job {} is pending again", job_id);
+            } else {
+                println!("Job {} does not exist or has not failed", job_id);
+            }
+        }
+
+        Commands::RegisterResult {
+            graph_id,
+            dim,
+            dim_hint,
+            seed,
+            max_iterations,
+            file,
+            move_file,
+            create_test,
+        } => {
+            let database_url = env::var("DATABASE_URL")
+                .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
+            let pool = PgPool::connect(&database_url).await?;
+
+            let result = ExternalResult {
+                graph_id,
+                embedding_dim: dim,
+                dim_hint: dim_hint.unwrap_or(dim),
+                max_iterations,
+                seed,
+                file,
+                move_file,
+            };
+            let registration = register_external_result(&pool, &result).await?;
+            let result_id = registration.result_id();
+            match registration {
+                Registration::Created(_) => println!("Registered result {result_id}"),
+                Registration::Existing(_) => {
+                    println!("The file is already registered as result {result_id}")
+                }
+            }
+            if create_test {
+                let test_manager = CorrectnessTestManager::new(pool);
+                test_manager
+                    .generate_test(result_id, benchmark::correctness_test::DEFAULT_TEST_RADIUS)
+                    .await?;
+            }
+            push_files().await?;
+        }
+
         Commands::Cleanup {
             timeout_hours,
             failed,
@@ -513,12 +814,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let pool = PgPool::connect(&database_url).await?;
 
             if failed {
+                // Failed jobs stay failed and are claimed again once their
+                // backoff has passed, so they keep using up their attempts.
+                // Jobs failed before the backoff existed have no eligibility
+                // time and become eligible right away.
                 let cleaned = sqlx::query_scalar!(
                     "UPDATE position_jobs 
                      SET 
-                        status = 'pending', 
                         claimed_at = NULL, 
                         claimed_by_hostname = NULL, 
+                        next_eligible_at = COALESCE(next_eligible_at, NOW()), 
                         error_message = COALESCE(error_message, '') || ' [Reset due to timeout]'
                      WHERE status = 'failed' AND claimed_at < NOW() - INTERVAL '1 hour' * $1 RETURNING 1",
                     timeout_hours as i32
@@ -527,9 +832,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .await?;
                 println!("Cleaned up {} failed jobs", cleaned.len());
             } else {
-                let cleaned = sqlx::query_scalar!("SELECT cleanup_stale_jobs($1)", timeout_hours)
-                    .fetch_one(&pool)
-                    .await?;
+                let cleaned = sqlx::query_scalar!(
+                    "SELECT cleanup_stale_jobs($1, $2)",
+                    timeout_hours,
+                    benchmark::job_manager::MAX_ATTEMPTS
+                )
+                .fetch_one(&pool)
+                .await?;
                 println!("Cleaned up {} stale jobs", cleaned.unwrap_or(0));
             }
         }
@@ -542,13 +851,106 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             benchmark::cleanup::cleanup_orphaned_files(&pool, dry_run).await?;
         }
 
-        Commands::GenerateTest { result_id } => {
+        Commands::VerifyFiles { fix } => {
+            let database_url = env::var("DATABASE_URL")
+                .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
+            let pool = PgPool::connect(&database_url).await?;
+
+            benchmark::cleanup::verify_files(&pool, fix).await?;
+        }
+
+        Commands::CompareDims {
+            structure,
+            baseline_commit,
+            comparison_commit,
+            html,
+        } => {
+            let database_url = env::var("DATABASE_URL")
+                .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
+            let pool = PgPool::connect(&database_url).await?;
+            let code_state_manager = RepoCodeStateManager::new(pool);
+
+            let speedups = code_state_manager
+                .compare_structures_across_dims(&structure, &baseline_commit, &comparison_commit)
+                .await?;
+            if speedups.is_empty() {
+                println!(
+                    "No paired measurements of {structure} for {baseline_commit} and {comparison_commit}"
+                );
+            }
+            for (dim, speedup) in &speedups {
+                println!("dim {dim:>3}: {speedup:.3}x");
+            }
+
+            if let Some(path) = html {
+                std::fs::write(
+                    &path,
+                    speedup_report_html(
+                        &structure,
+                        &baseline_commit,
+                        &comparison_commit,
+                        &speedups,
+                    ),
+                )?;
+                println!("Wrote report to {path}");
+            }
+        }
+
+        Commands::RadiusCost { result_id, html } => {
+            let database_url = env::var("DATABASE_URL")
+                .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
+            let pool = PgPool::connect(&database_url).await?;
+            let code_state_manager = RepoCodeStateManager::new(pool);
+
+            let costs = code_state_manager.radius_costs(result_id).await?;
+            if costs.is_empty() {
+                println!("No radius_sweep measurements for result {result_id}");
+            }
+            for (structure, points) in &costs {
+                let points: Vec<_> = points
+                    .iter()
+                    .map(|(radius, nanos)| format!("{radius}: {:.0}ns", nanos))
+                    .collect();
+                println!("{structure:>24} {}", points.join("  "));
+            }
+
+            if let Some(path) = html {
+                std::fs::write(&path, radius_cost_report_html(result_id, &costs))?;
+                println!("Wrote report to {path}");
+            }
+        }
+
+        Commands::ConstructionScaling {
+            graph_id,
+            dims,
+            structures,
+            fast,
+            precision,
+            csv,
+        } => {
+            let database_url = env::var("DATABASE_URL")
+                .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
+            let pool = PgPool::connect(&database_url).await?;
+            let load_data = LoadData::new(pool);
+
+            let scaling = load_data
+                .construction_scaling(graph_id, &dims, &structures, fast, precision)
+                .await?;
+            println!("Construction time in ms for graph {graph_id}:");
+            print!("{}", scaling.table());
+            if let Some(path) = csv {
+                std::fs::write(&path, scaling.csv())?;
+                println!("Wrote {path}");
+            }
+        }
+
+        Commands::GenerateTest { result_id, radius } => {
             let database_url = env::var("DATABASE_URL")
                 .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
             let pool = PgPool::connect(&database_url).await?;
 
             let test_manager = CorrectnessTestManager::new(pool);
-            test_manager.generate_test(result_id).await?;
+            test_manager.generate_test(result_id, radius).await?;
             push_files().await?;
         }
 
@@ -562,26 +964,70 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             structures,
             dynamic_download,
             check_over_query,
+            min_recall,
+            radius,
+            explain,
+            max_nodes,
         } => {
             // pull_files().await?;
             let database_url = env::var("DATABASE_URL")
                 .unwrap_or_else(|_| "postgresql://localhost/rembed".to_string());
             let pool = PgPool::connect(&database_url).await?;
 
-            let test_manager = CorrectnessTestManager::new(pool);
-            test_manager
-                .run_tests(
-                    all_iterations,
-                    all_graphs,
-                    result_id,
-                    graph_id,
-                    dim,
-                    run_unit_tests,
-                    structures.unwrap_or_default(),
-                    dynamic_download,
-                    check_over_query,
+            let test_manager = CorrectnessTestManager::new(pool).with_min_recall(min_recall);
+            if let (Some(nodes), Some(result_id)) = (explain, result_id) {
+                test_manager
+                    .explain(
+                        result_id,
+                        nodes[0],
+                        nodes[1],
+                        structures.unwrap_or_default(),
+                        dynamic_download,
+                        radius,
+                    )
+                    .await?;
+            } else {
+                test_manager
+                    .run_tests(
+                        all_iterations,
+                        all_graphs,
+                        result_id,
+                        graph_id,
+                        dim,
+                        run_unit_tests,
+                        structures.unwrap_or_default(),
+                        dynamic_download,
+                        check_over_query,
+                        radius,
+                        max_nodes,
+                    )
+                    .await?;
+            }
+        }
+
+        Commands::QualityCheck {
+            tolerance,
+            weight_classes,
+        } => {
+            let report =
+                benchmark::quality::check_quality(benchmark::quality::BASELINE_PATH, tolerance)?;
+            report.print();
+            if let Some(path) = weight_classes {
+                std::fs::write(&path, report.weight_class_csv())?;
+                println!("Wrote weight class statistics to {path}");
+            }
+            let regressions = report.regressions();
+            if !regressions.is_empty() {
+                return Err(format!(
+                    "{} quality case(s) regressed by more than {tolerance}",
+                    regressions.len()
                 )
-                .await?;
+                .into());
+            }
+        }
+
+        Commands::UpdateQualityBaseline => {
+            benchmark::quality::update_baseline(benchmark::quality::BASELINE_PATH)?;
         }
 
         Commands::BenchDistributions {
@@ -714,6 +1160,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Dimensions given with `--dims`, or the default ones
+fn job_dims(dims: Option<Vec<i32>>) -> Result<Vec<i32>, String> {
+    let dims = dims.unwrap_or_else(|| DEFAULT_JOB_DIMS.to_vec());
+    validate_job_dims(&dims)?;
+    Ok(dims)
+}
+
 fn parse_usize_range(s: &str) -> Result<(usize, usize), String> {
     let s = s.replace("_", ""); // Remove underscores for easier parsing
 
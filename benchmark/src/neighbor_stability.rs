@@ -0,0 +1,244 @@
+use rembed::{Embedding, NodeId, Query, Sprk, query::pairs_to_lists};
+use sqlx::{Pool, Postgres, Row};
+
+use crate::benchmark::BENCHMARK_DIMS;
+
+macro_rules! dispatch_dim {
+    ($dim:ident, $file_path:ident, $graph_file_path:ident, $dim_hint:ident, $($c_dim:literal,)*) => {
+        match $dim {
+            $($c_dim => predicted_neighbors::<$c_dim>($file_path, $graph_file_path, $dim_hint),)*
+            _ => panic!("dim {} not covered", $dim),
+        }
+    };
+}
+
+/// Mean Jaccard similarity of the neighbour sets of every node in two
+/// embeddings of the same graph. Nodes without neighbours in both count as
+/// identical. The lists do not need to be sorted.
+pub fn neighbor_jaccard(a: &[Vec<NodeId>], b: &[Vec<NodeId>]) -> f64 {
+    assert_eq!(a.len(), b.len(), "neighbour sets of different graphs");
+    if a.is_empty() {
+        return 1.0;
+    }
+    let sum: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| {
+            let mut a = a.clone();
+            let mut b = b.clone();
+            a.sort_unstable();
+            a.dedup();
+            b.sort_unstable();
+            b.dedup();
+            let shared = a.iter().filter(|x| b.binary_search(x).is_ok()).count();
+            let union = a.len() + b.len() - shared;
+            if union == 0 {
+                1.0
+            } else {
+                shared as f64 / union as f64
+            }
+        })
+        .sum();
+    sum / a.len() as f64
+}
+
+/// Neighbour Jaccard between two consecutive available embedding
+/// dimensions of one graph
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DimPair {
+    pub from_dim: usize,
+    pub to_dim: usize,
+    pub jaccard: f64,
+}
+
+/// How much the predicted neighbourhoods change from one embedding
+/// dimension to the next, see [`neighbor_stability`]
+#[derive(Debug, Clone, Default)]
+pub struct NeighborStability {
+    pub graph_id: i64,
+    pub seed: i32,
+    /// Ordered by dimension. Dimensions without a usable embedding are
+    /// skipped, so `to_dim` can be more than one above `from_dim`.
+    pub pairs: Vec<DimPair>,
+}
+
+impl NeighborStability {
+    /// Smallest dimension from which every later step keeps a Jaccard of at
+    /// least `threshold`, i.e. where adding dimensions stops changing the
+    /// neighbourhoods
+    pub fn plateau(&self, threshold: f64) -> Option<usize> {
+        let stable_from = self
+            .pairs
+            .iter()
+            .rposition(|pair| pair.jaccard < threshold)
+            .map_or(0, |last_unstable| last_unstable + 1);
+        self.pairs.get(stable_from).map(|pair| pair.from_dim)
+    }
+
+    pub fn table(&self) -> String {
+        use std::fmt::Write;
+        let mut table = format!("{:>6} {:>6} {:>8}\n", "from", "to", "jaccard");
+        for pair in &self.pairs {
+            let gap = if pair.to_dim > pair.from_dim + 1 {
+                " (gap)"
+            } else {
+                ""
+            };
+            writeln!(
+                table,
+                "{:>6} {:>6} {:>8.4}{gap}",
+                pair.from_dim, pair.to_dim, pair.jaccard
+            )
+            .unwrap();
+        }
+        table
+    }
+}
+
+/// Compares the last iteration of one embedding per dimension of
+/// `graph_id` with `seed`, in increasing dimension. Dimensions whose files
+/// are missing or that are not compiled into the benchmarks are skipped, and
+/// the next available dimension is compared instead. The files have to be
+/// pulled already.
+pub async fn neighbor_stability(
+    pool: Pool<Postgres>,
+    graph_id: i64,
+    seed: i32,
+) -> Result<NeighborStability, Box<dyn std::error::Error>> {
+    let query = "
+        SELECT DISTINCT ON (embedding_dim)
+            embedding_dim, dim_hint, position_results.file_path, graphs.file_path as graph_file_path
+        FROM position_results
+        JOIN graphs USING (graph_id)
+        WHERE graph_id = $1 AND seed = $2
+        ORDER BY embedding_dim, result_id
+        ";
+    let rows = sqlx::query(query)
+        .bind(graph_id)
+        .bind(seed)
+        .fetch_all(&pool)
+        .await?;
+    if rows.is_empty() {
+        return Err(format!("no position results for graph {graph_id} with seed {seed}").into());
+    }
+
+    let data_directory = std::env::var("DATA_DIRECTORY").unwrap_or(String::from("../data/"));
+    let mut stability = NeighborStability {
+        graph_id,
+        seed,
+        ..Default::default()
+    };
+    let mut previous: Option<(usize, Vec<Vec<NodeId>>)> = None;
+    for row in rows {
+        let dim = row.get::<i32, _>("embedding_dim") as usize;
+        let dim_hint = row.get::<i32, _>("dim_hint") as usize;
+        let file_path = format!("{data_directory}/{}", row.get::<String, _>("file_path"));
+        let graph_file_path = format!(
+            "{data_directory}/{}",
+            row.get::<String, _>("graph_file_path")
+        );
+        if !BENCHMARK_DIMS.contains(&dim) {
+            eprintln!("Skipping dim {dim}: not compiled into the benchmarks");
+            continue;
+        }
+        if !std::path::Path::new(&file_path).exists() {
+            eprintln!("Skipping dim {dim}: file does not exist: {file_path}");
+            continue;
+        }
+        let (file_path, graph_file_path) = (&file_path, &graph_file_path);
+        let neighbors = match dispatch_dim!(
+            dim,
+            file_path,
+            graph_file_path,
+            dim_hint,
+            2,
+            3,
+            4,
+            5,
+            6,
+            7,
+            8,
+            9,
+            10,
+            11,
+            12,
+            13,
+            14,
+            15,
+            16,
+            32,
+        ) {
+            Ok(neighbors) => neighbors,
+            Err(e) => {
+                eprintln!("Skipping dim {dim}: {e}");
+                continue;
+            }
+        };
+        if let Some((from_dim, previous)) = &previous {
+            stability.pairs.push(DimPair {
+                from_dim: *from_dim,
+                to_dim: dim,
+                jaccard: neighbor_jaccard(previous, &neighbors),
+            });
+        }
+        previous = Some((dim, neighbors));
+    }
+    Ok(stability)
+}
+
+/// Nodes within weighted distance 1 of every node in the last iteration of
+/// the embedding, without the node itself
+fn predicted_neighbors<const D: usize>(
+    file_path: &str,
+    graph_file_path: &str,
+    dim_hint: usize,
+) -> Result<Vec<Vec<NodeId>>, Box<dyn std::error::Error>> {
+    let graph = rembed::graph::Graph::parse_from_edge_list_file(graph_file_path, D, dim_hint)?;
+    let iterations: rembed::parsing::Iterations<D> =
+        rembed::parsing::parse_positions_file(file_path)?;
+    iterations.validate_graph_compatibility(&graph)?;
+    let last = iterations
+        .iterations()
+        .last()
+        .ok_or_else(|| format!("empty embedding {file_path}"))?;
+    let embedding = Embedding::<D> {
+        positions: last.positions.to_vec(),
+        graph: &graph,
+    };
+    let index = Sprk::new(embedding.view());
+    let pairs = index.nearest_neighbor_pairs(1.);
+    Ok(pairs_to_lists(&pairs, graph.nodes.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DimPair, NeighborStability, neighbor_jaccard};
+
+    #[test]
+    fn jaccard_and_plateau() {
+        let a = vec![vec![1, 2], vec![0], vec![0], vec![]];
+        let b = vec![vec![2, 3, 1], vec![], vec![0], vec![]];
+        // 2/3, 0, 1, 1
+        assert!((neighbor_jaccard(&a, &b) - (2.0 / 3.0 + 2.0) / 4.0).abs() < 1e-12);
+        assert_eq!(neighbor_jaccard(&a, &a), 1.0);
+
+        let pair = |from_dim, to_dim, jaccard| DimPair {
+            from_dim,
+            to_dim,
+            jaccard,
+        };
+        let stability = NeighborStability {
+            pairs: vec![
+                pair(2, 3, 0.5),
+                pair(3, 4, 0.95),
+                pair(4, 6, 0.8),
+                pair(6, 7, 0.92),
+                pair(7, 8, 0.97),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(stability.plateau(0.9), Some(6));
+        assert_eq!(stability.plateau(0.5), Some(2));
+        assert_eq!(stability.plateau(0.99), None);
+    }
+}
@@ -0,0 +1,444 @@
+//! S3 backend of [`crate::push_files`] and [`crate::pull_files`].
+//!
+//! `SYNC_BACKEND=s3` stores the data directory in the bucket `S3_BUCKET`,
+//! each file under its path relative to `DATA_DIRECTORY`, behind the
+//! optional key prefix `S3_PREFIX`. Credentials, region and endpoint come
+//! from the usual AWS environment, e.g. `AWS_ENDPOINT_URL` for MinIO. The
+//! client needs the `s3` feature; rsync stays the default backend.
+//!
+//! Files are streamed from and to disk, never held in memory as a whole.
+//! Pulls skip files that already match the object, see [`is_current`].
+
+use std::future::Future;
+use std::path::Path;
+
+use futures::{StreamExt, TryStreamExt};
+use md5::{Digest, Md5};
+use tokio::io::AsyncReadExt;
+
+/// Files transferred at the same time
+const CONCURRENT_TRANSFERS: usize = 16;
+
+#[cfg(not(feature = "s3"))]
+const NO_S3_FEATURE: &str = "SYNC_BACKEND=s3 needs the benchmark built with --features s3";
+
+/// Where [`crate::push_files`] and [`crate::pull_files`] sync to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyncBackend {
+    /// rsync to `RSYNC_DESTINATION`
+    Rsync,
+    S3 {
+        bucket: String,
+        prefix: String,
+    },
+}
+
+impl SyncBackend {
+    /// Reads `SYNC_BACKEND`, which is `rsync` if unset, and for S3 also
+    /// `S3_BUCKET` and `S3_PREFIX`
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let var = |name| std::env::var(name).ok();
+        Self::from_vars(var("SYNC_BACKEND"), var("S3_BUCKET"), var("S3_PREFIX"))
+    }
+
+    fn from_vars(
+        backend: Option<String>,
+        bucket: Option<String>,
+        prefix: Option<String>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        match backend.as_deref() {
+            None | Some("rsync") => Ok(SyncBackend::Rsync),
+            Some("s3") => Ok(SyncBackend::S3 {
+                bucket: bucket.ok_or("SYNC_BACKEND=s3 needs the S3_BUCKET env var")?,
+                prefix: prefix.unwrap_or_default(),
+            }),
+            Some(other) => Err(format!("unknown SYNC_BACKEND '{other}', use rsync or s3").into()),
+        }
+    }
+}
+
+/// An object as listed by [`ObjectStore::list`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: u64,
+    /// Entity tag without quotes. For objects uploaded in one part, like
+    /// all objects of [`upload`], it is the hex MD5 of the content.
+    pub e_tag: Option<String>,
+}
+
+/// The operations the sync needs from an object store
+pub trait ObjectStore {
+    /// Uploads the file at `path`, streamed from disk
+    fn put_file(
+        &self,
+        key: &str,
+        path: &Path,
+    ) -> impl Future<Output = Result<(), Box<dyn std::error::Error>>>;
+
+    /// Downloads the object into the file at `path`, streamed to disk
+    fn get_file(
+        &self,
+        key: &str,
+        path: &Path,
+    ) -> impl Future<Output = Result<(), Box<dyn std::error::Error>>>;
+
+    /// All objects whose key starts with `prefix`
+    fn list(
+        &self,
+        prefix: &str,
+    ) -> impl Future<Output = Result<Vec<ObjectInfo>, Box<dyn std::error::Error>>>;
+}
+
+/// Key of the file at `relative` below the data directory
+fn object_key(prefix: &str, relative: &str) -> String {
+    let (prefix, relative) = (prefix.trim_matches('/'), relative.trim_start_matches('/'));
+    if prefix.is_empty() {
+        relative.to_string()
+    } else {
+        format!("{prefix}/{relative}")
+    }
+}
+
+/// Uploads the `files` below `root`, given relative to it like the changed
+/// files of a [`crate::sync::Delta`]
+pub async fn upload(
+    store: &impl ObjectStore,
+    root: &Path,
+    prefix: &str,
+    files: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    futures::stream::iter(files)
+        .map(|relative| async move {
+            let path = root.join(relative);
+            store.put_file(&object_key(prefix, relative), &path).await
+        })
+        .buffer_unordered(CONCURRENT_TRANSFERS)
+        .try_collect::<()>()
+        .await
+}
+
+/// Whether the file at `path` already holds `object`: it has the same size
+/// and, if the ETag is a plain MD5, the same MD5. Multipart ETags are not a
+/// hash of the content, for them the size has to do.
+pub async fn is_current(path: &Path, object: &ObjectInfo) -> std::io::Result<bool> {
+    let size = match tokio::fs::metadata(path).await {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    if size != object.size {
+        return Ok(false);
+    }
+    let Some(e_tag) = object.e_tag.as_deref().filter(|tag| !tag.contains('-')) else {
+        return Ok(true);
+    };
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()).eq_ignore_ascii_case(e_tag))
+}
+
+/// Downloads the objects below `prefix` whose path relative to the data
+/// directory passes `filter` into `root`, skipping files that are already
+/// current. Returns the number of downloaded and of skipped files.
+pub async fn download(
+    store: &impl ObjectStore,
+    root: &Path,
+    prefix: &str,
+    filter: impl Fn(&str) -> bool,
+) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    let base = object_key(prefix, "");
+    let objects: Vec<(String, ObjectInfo)> = store
+        .list(&base)
+        .await?
+        .into_iter()
+        .filter_map(|object| {
+            let relative = object.key.strip_prefix(&base)?.to_string();
+            (!relative.is_empty() && filter(&relative)).then_some((relative, object))
+        })
+        .collect();
+    let downloaded: Vec<bool> = futures::stream::iter(&objects)
+        .map(|(relative, object)| async move {
+            let path = root.join(relative);
+            if is_current(&path, object).await? {
+                return Ok(false);
+            }
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            store.get_file(&object.key, &path).await?;
+            Ok::<_, Box<dyn std::error::Error>>(true)
+        })
+        .buffer_unordered(CONCURRENT_TRANSFERS)
+        .try_collect()
+        .await?;
+    let count = downloaded.iter().filter(|&&downloaded| downloaded).count();
+    Ok((count, objects.len() - count))
+}
+
+/// Pushes the `files` below `root` to `bucket`
+#[cfg(feature = "s3")]
+pub async fn push(
+    bucket: &str,
+    prefix: &str,
+    root: &Path,
+    files: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    upload(&S3Store::from_env(bucket).await, root, prefix, files).await
+}
+
+#[cfg(not(feature = "s3"))]
+pub async fn push(
+    _bucket: &str,
+    _prefix: &str,
+    _root: &Path,
+    _files: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err(NO_S3_FEATURE.into())
+}
+
+/// Pulls the files of `bucket` that pass `filter` into `root`, see
+/// [`download`]
+#[cfg(feature = "s3")]
+pub async fn pull(
+    bucket: &str,
+    prefix: &str,
+    root: &Path,
+    filter: impl Fn(&str) -> bool,
+) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    download(&S3Store::from_env(bucket).await, root, prefix, filter).await
+}
+
+#[cfg(not(feature = "s3"))]
+pub async fn pull(
+    _bucket: &str,
+    _prefix: &str,
+    _root: &Path,
+    _filter: impl Fn(&str) -> bool,
+) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+    Err(NO_S3_FEATURE.into())
+}
+
+/// [`ObjectStore`] backed by a bucket of S3 or a compatible service
+#[cfg(feature = "s3")]
+pub struct S3Store {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+}
+
+#[cfg(feature = "s3")]
+impl S3Store {
+    /// Client configured from the AWS environment. Path-style addressing
+    /// keeps custom endpoints like MinIO working.
+    pub async fn from_env(bucket: &str) -> Self {
+        let config = aws_config::load_from_env().await;
+        let config = aws_sdk_s3::config::Builder::from(&config)
+            .force_path_style(true)
+            .build();
+        S3Store {
+            client: aws_sdk_s3::Client::from_conf(config),
+            bucket: bucket.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "s3")]
+impl ObjectStore for S3Store {
+    async fn put_file(&self, key: &str, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let body = aws_sdk_s3::primitives::ByteStream::from_path(path).await?;
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .body(body)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn get_file(&self, key: &str, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await?;
+        let mut body = std::pin::pin!(object.body.into_async_read());
+        let mut file = tokio::fs::File::create(path).await?;
+        tokio::io::copy_buf(&mut body, &mut file).await?;
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, Box<dyn std::error::Error>> {
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(prefix)
+            .into_paginator()
+            .send();
+        let mut objects = Vec::new();
+        while let Some(page) = pages.next().await {
+            objects.extend(page?.contents().iter().filter_map(|o| {
+                Some(ObjectInfo {
+                    key: o.key()?.to_string(),
+                    size: o.size().unwrap_or_default().try_into().ok()?,
+                    e_tag: o.e_tag().map(|tag| tag.trim_matches('"').to_string()),
+                })
+            }));
+        }
+        Ok(objects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// In-memory stand-in for a bucket
+    #[derive(Default)]
+    struct MemoryStore {
+        objects: Mutex<BTreeMap<String, Vec<u8>>>,
+    }
+
+    impl MemoryStore {
+        fn keys(&self) -> Vec<String> {
+            self.objects.lock().unwrap().keys().cloned().collect()
+        }
+    }
+
+    impl ObjectStore for MemoryStore {
+        async fn put_file(&self, key: &str, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+            let body = std::fs::read(path)?;
+            self.objects.lock().unwrap().insert(key.to_string(), body);
+            Ok(())
+        }
+
+        async fn get_file(&self, key: &str, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+            let body = self.objects.lock().unwrap().get(key).cloned();
+            std::fs::write(path, body.ok_or("no such key")?)?;
+            Ok(())
+        }
+
+        async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>, Box<dyn std::error::Error>> {
+            let objects = self.objects.lock().unwrap();
+            Ok(objects
+                .iter()
+                .filter(|(key, _)| key.starts_with(prefix))
+                .map(|(key, body)| ObjectInfo {
+                    key: key.clone(),
+                    size: body.len() as u64,
+                    e_tag: Some(format!("{:x}", Md5::digest(body))),
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn position_file_round_trips_through_the_store() {
+        let dir = std::env::temp_dir().join(format!("object-storage-test-{}", std::process::id()));
+        let (local, pulled) = (dir.join("local"), dir.join("pulled"));
+        let position_file = "generated/positions/graph_7/dim_4_seed_1.log";
+        let graph_file = "generated/graphs/graph_7.txt";
+        std::fs::create_dir_all(local.join("generated/positions/graph_7")).unwrap();
+        std::fs::create_dir_all(local.join("generated/graphs")).unwrap();
+        let positions = b"0.5,1.5,2.5,3.5\n-1,0,1,2\n";
+        std::fs::write(local.join(position_file), positions).unwrap();
+        std::fs::write(local.join(graph_file), b"0 1\n").unwrap();
+
+        let store = MemoryStore::default();
+        let files = [position_file.to_string(), graph_file.to_string()];
+        upload(&store, &local, "runs/", &files).await.unwrap();
+        assert_eq!(
+            store.keys(),
+            [
+                format!("runs/{graph_file}"),
+                format!("runs/{position_file}")
+            ]
+        );
+
+        let only_positions = |path: &str| path.starts_with("generated/positions/");
+        let counts = download(&store, &pulled, "runs", only_positions)
+            .await
+            .unwrap();
+        assert_eq!(counts, (1, 0));
+        assert_eq!(
+            std::fs::read(pulled.join(position_file)).unwrap(),
+            positions
+        );
+        assert!(!pulled.join(graph_file).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn pull_skips_files_that_match_the_object() {
+        let dir = std::env::temp_dir().join(format!("object-storage-skip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = "generated/graphs/graph_7.txt";
+        let store = MemoryStore::default();
+        store
+            .objects
+            .lock()
+            .unwrap()
+            .insert(file.to_string(), b"0 1\n".to_vec());
+        let all = |_: &str| true;
+
+        assert_eq!(download(&store, &dir, "", all).await.unwrap(), (1, 0));
+        assert_eq!(download(&store, &dir, "", all).await.unwrap(), (0, 1));
+
+        // Same size, different content: only the ETag tells them apart
+        std::fs::write(dir.join(file), b"0 2\n").unwrap();
+        assert_eq!(download(&store, &dir, "", all).await.unwrap(), (1, 0));
+        assert_eq!(std::fs::read(dir.join(file)).unwrap(), b"0 1\n");
+
+        // Without an ETag the size decides
+        let object = ObjectInfo {
+            key: file.to_string(),
+            size: 4,
+            e_tag: None,
+        };
+        assert!(is_current(&dir.join(file), &object).await.unwrap());
+        let multipart = ObjectInfo {
+            e_tag: Some("0123-2".to_string()),
+            ..object.clone()
+        };
+        assert!(is_current(&dir.join(file), &multipart).await.unwrap());
+        let larger = ObjectInfo { size: 5, ..object };
+        assert!(!is_current(&dir.join(file), &larger).await.unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backend_defaults_to_rsync() {
+        let s = |value: &str| Some(value.to_string());
+        assert_eq!(
+            SyncBackend::from_vars(None, None, None).unwrap(),
+            SyncBackend::Rsync
+        );
+        assert_eq!(
+            SyncBackend::from_vars(s("s3"), s("bucket"), None).unwrap(),
+            SyncBackend::S3 {
+                bucket: "bucket".to_string(),
+                prefix: String::new()
+            }
+        );
+        assert!(SyncBackend::from_vars(s("s3"), None, None).is_err());
+        assert!(SyncBackend::from_vars(s("ftp"), None, None).is_err());
+        assert_eq!(object_key("", "/a/b"), "a/b");
+        assert_eq!(object_key("runs/", "a/b"), "runs/a/b");
+    }
+}
@@ -0,0 +1,455 @@
+//! Embedding quality regression guard.
+//!
+//! Runs a fixed set of small embedding cases (graph, seed, options and
+//! spatial index) and compares the final f1 score against the baseline in
+//! `quality_baseline.tsv`. Speed-ups that make an index subtly lossy change
+//! the repulsion sets and show up here as a drop in f1.
+//!
+//! The f1 score is always evaluated with the brute-force [`Embedding`], so a
+//! lossy index cannot hide its own regressions.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::time::Instant;
+
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+use rembed::dvec::DVec;
+use rembed::dyn_embed::EmbedIndex;
+use rembed::dynamic_queries::DynamicQuery;
+use rembed::embedder::{EmbedderOptions, WEmbedder};
+use rembed::graph::Graph;
+use rembed::graph_statistics::GraphStatisticsReport;
+use rembed::query::Embedder;
+use rembed::{Embedding, Sprk};
+
+use crate::code_state::RepoCodeStateManager;
+
+/// Location of the checked-in baseline
+pub const BASELINE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/quality_baseline.tsv");
+
+/// Default allowed drop in f1 before a case counts as regressed
+pub const DEFAULT_TOLERANCE: f64 = 0.01;
+
+#[derive(Clone, Copy, Debug)]
+enum CaseGraph {
+    /// `side x side` grid graph
+    Grid { side: usize },
+    /// Threshold GIRG in the unit cube of dimension `dim` with power-law
+    /// weights of exponent `ple`
+    Girg {
+        n: usize,
+        dim: usize,
+        ple: f64,
+        avg_degree: f64,
+        seed: u64,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+enum CaseIndex {
+    Sprk,
+    DynamicQuery,
+    BruteForce,
+}
+
+#[derive(Clone, Debug)]
+pub struct QualityCase {
+    pub name: &'static str,
+    graph: CaseGraph,
+    embedding_dim: usize,
+    index: CaseIndex,
+    seed: u64,
+    iterations: usize,
+}
+
+/// The canonical cases. Changing any of them invalidates the baseline.
+pub fn canonical_cases() -> Vec<QualityCase> {
+    let grid = CaseGraph::Grid { side: 20 };
+    let girg = |dim| CaseGraph::Girg {
+        n: 2000,
+        dim,
+        ple: 2.5,
+        avg_degree: 10.0,
+        seed: 7,
+    };
+    let case = |name, graph, embedding_dim, index| QualityCase {
+        name,
+        graph,
+        embedding_dim,
+        index,
+        seed: 42,
+        iterations: 1000,
+    };
+    vec![
+        case("grid-2d/sprk", grid, 2, CaseIndex::Sprk),
+        case("grid-2d/dynamic-query", grid, 2, CaseIndex::DynamicQuery),
+        case("grid-2d/brute-force", grid, 2, CaseIndex::BruteForce),
+        case("girg-2d/sprk", girg(2), 2, CaseIndex::Sprk),
+        case("girg-4d/sprk", girg(4), 4, CaseIndex::Sprk),
+        case("girg-4d/dynamic-query", girg(4), 4, CaseIndex::DynamicQuery),
+        case("girg-4d/brute-force", girg(4), 4, CaseIndex::BruteForce),
+        case("girg-8d-in-4d/sprk", girg(8), 4, CaseIndex::Sprk),
+    ]
+}
+
+impl CaseGraph {
+    fn build(&self, embedding_dim: usize) -> io::Result<Graph> {
+        let (edges, latent_dim) = match *self {
+            CaseGraph::Grid { side } => {
+                let mut edges = Vec::new();
+                for y in 0..side {
+                    for x in 0..side {
+                        let id = y * side + x;
+                        if x + 1 < side {
+                            edges.push((id, id + 1));
+                        }
+                        if y + 1 < side {
+                            edges.push((id, id + side));
+                        }
+                    }
+                }
+                (edges, 2)
+            }
+            CaseGraph::Girg {
+                n,
+                dim,
+                ple,
+                avg_degree,
+                seed,
+            } => (girg_edges(n, dim, ple, avg_degree, seed), dim),
+        };
+        Graph::from_edge_list(edges, embedding_dim, latent_dim)
+    }
+}
+
+/// Threshold GIRG: `u` and `v` are connected if the volume of the
+/// L∞ ball spanned by their distance is below `c * w_u * w_v / W`, with `c`
+/// chosen for the requested average degree. Isolated points are connected to
+/// their closest neighbour so every node has a weight.
+fn girg_edges(n: usize, dim: usize, ple: f64, avg_degree: f64, seed: u64) -> Vec<(usize, usize)> {
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let points: Vec<Vec<f64>> = (0..n)
+        .map(|_| (0..dim).map(|_| rng.random::<f64>()).collect())
+        .collect();
+    let weights: Vec<f64> = (0..n)
+        .map(|_| (1.0 - rng.random::<f64>()).powf(-1.0 / (ple - 1.0)))
+        .collect();
+    let total_weight: f64 = weights.iter().sum();
+    let c = avg_degree * n as f64 / total_weight;
+    let volume = |a: usize, b: usize| -> f64 {
+        let dist = points[a]
+            .iter()
+            .zip(&points[b])
+            .map(|(x, y)| (x - y).abs())
+            .fold(0.0, f64::max);
+        (2.0 * dist).powi(dim as i32)
+    };
+
+    let mut edges = Vec::new();
+    let mut degree = vec![0; n];
+    for a in 0..n {
+        for b in (a + 1)..n {
+            if volume(a, b) < c * weights[a] * weights[b] / total_weight {
+                edges.push((a, b));
+                degree[a] += 1;
+                degree[b] += 1;
+            }
+        }
+    }
+    for a in 0..n {
+        if degree[a] == 0 {
+            let closest = (0..n)
+                .filter(|&b| b != a)
+                .min_by(|&x, &y| volume(a, x).total_cmp(&volume(a, y)))
+                .unwrap();
+            edges.push((a.min(closest), a.max(closest)));
+            degree[a] += 1;
+            degree[closest] += 1;
+        }
+    }
+    edges
+}
+
+macro_rules! dispatch_index {
+    ($index:expr, $graph:expr, $seed:expr, $options:expr, dims: [ $($dim:literal,)* ], $embedding_dim:expr) => {
+        match $embedding_dim {
+            $($dim => match $index {
+                CaseIndex::Sprk => embed::<$dim, Sprk<$dim>>($graph, $seed, $options),
+                CaseIndex::DynamicQuery => {
+                    embed::<$dim, DynamicQuery<$dim, Sprk<$dim>>>($graph, $seed, $options)
+                }
+                CaseIndex::BruteForce => embed::<$dim, Embedding<$dim>>($graph, $seed, $options),
+            },)*
+            dim => panic!("no quality case support for dimension {dim}"),
+        }
+    };
+}
+
+impl QualityCase {
+    /// Embeds the case's graph and returns the statistics of the final
+    /// embedding, overall and per weight class
+    pub fn run(&self) -> io::Result<GraphStatisticsReport> {
+        let graph = self.graph.build(self.embedding_dim)?;
+        let options = EmbedderOptions {
+            max_iterations: self.iterations,
+            ..Default::default()
+        };
+        Ok(dispatch_index!(
+            self.index,
+            &graph,
+            self.seed,
+            options,
+            dims: [2, 4, 8,],
+            self.embedding_dim
+        ))
+    }
+}
+
+fn embed<'a, const D: usize, SI>(
+    graph: &'a Graph,
+    seed: u64,
+    options: EmbedderOptions,
+) -> GraphStatisticsReport
+where
+    SI: Embedder<'a, D> + EmbedIndex<Vec = DVec<D>>,
+{
+    let mut embedder: WEmbedder<SI> = WEmbedder::random(seed, graph, options);
+    let positions = embedder.embed();
+    Embedder::graph_statistics_report(&Embedding { positions, graph }, None, 0)
+}
+
+/// Expected f1 per case, together with the commit it was recorded at
+#[derive(Clone, Debug)]
+pub struct QualityBaseline {
+    pub commit: String,
+    pub f1: BTreeMap<String, f64>,
+}
+
+impl QualityBaseline {
+    /// Reads a baseline written by [`QualityBaseline::write`]
+    pub fn read(path: &str) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut commit = None;
+        let mut f1 = BTreeMap::new();
+        for line in contents.lines().map(str::trim) {
+            if let Some(hash) = line.strip_prefix("# commit ") {
+                commit = Some(hash.trim().to_string());
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line
+                .split_once('\t')
+                .ok_or_else(|| invalid(format!("expected `<case>\\t<f1>`, got `{line}`")))?;
+            let value = value
+                .trim()
+                .parse()
+                .map_err(|e| invalid(format!("invalid f1 for {name}: {e}")))?;
+            f1.insert(name.to_string(), value);
+        }
+        let commit = commit.ok_or_else(|| invalid(format!("{path} has no `# commit` line")))?;
+        Ok(Self { commit, f1 })
+    }
+
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let mut out = format!(
+            "# Embedding quality baseline, update with `benchmark update-quality-baseline`\n\
+             # commit {}\n",
+            self.commit
+        );
+        for (name, f1) in &self.f1 {
+            out.push_str(&format!("{name}\t{f1:.6}\n"));
+        }
+        fs::write(path, out)
+    }
+}
+
+/// One line of a [`QualityReport`]
+#[derive(Clone, Debug)]
+pub struct QualityResult {
+    pub name: String,
+    pub baseline: Option<f64>,
+    pub f1: f64,
+    pub statistics: GraphStatisticsReport,
+}
+
+#[derive(Clone, Debug)]
+pub struct QualityReport {
+    pub baseline_commit: String,
+    pub tolerance: f64,
+    pub results: Vec<QualityResult>,
+}
+
+impl QualityReport {
+    /// Cases whose f1 dropped by more than the tolerance
+    pub fn regressions(&self) -> Vec<&QualityResult> {
+        self.results
+            .iter()
+            .filter(|r| r.baseline.is_some_and(|b| r.f1 < b - self.tolerance))
+            .collect()
+    }
+
+    /// [`GraphStatisticsReport::csv`] of every case with the case name, and
+    /// so the structure, in front, for plotting f1 over the weight class
+    pub fn weight_class_csv(&self) -> String {
+        let mut csv = String::from("case,");
+        for (i, result) in self.results.iter().enumerate() {
+            let class_csv = result.statistics.csv();
+            let (header, rows) = class_csv.split_once('\n').unwrap_or_default();
+            if i == 0 {
+                csv.push_str(header);
+                csv.push('\n');
+            }
+            for row in rows.lines() {
+                csv.push_str(&format!("{},{row}\n", result.name));
+            }
+        }
+        csv
+    }
+
+    pub fn print(&self) {
+        println!(
+            "Embedding quality vs baseline {} (tolerance {:.3})",
+            self.baseline_commit, self.tolerance
+        );
+        println!(
+            "{:<24} {:>9} {:>9} {:>9}",
+            "case", "baseline", "f1", "delta"
+        );
+        for r in &self.results {
+            match r.baseline {
+                Some(b) => {
+                    let status = if r.f1 < b - self.tolerance {
+                        "  ✗ regressed"
+                    } else if r.f1 > b + self.tolerance {
+                        "  ↑ improved"
+                    } else {
+                        ""
+                    };
+                    println!(
+                        "{:<24} {:>9.4} {:>9.4} {:>+9.4}{status}",
+                        r.name,
+                        b,
+                        r.f1,
+                        r.f1 - b
+                    );
+                }
+                None => println!("{:<24} {:>9} {:>9.4} {:>9}", r.name, "-", r.f1, "new"),
+            }
+        }
+    }
+}
+
+fn run_cases() -> io::Result<BTreeMap<String, GraphStatisticsReport>> {
+    let mut results = BTreeMap::new();
+    for case in canonical_cases() {
+        let start = Instant::now();
+        let statistics = case.run()?;
+        println!(
+            "  {} f1 {:.4} ({:.1}s)",
+            case.name,
+            statistics.f1(),
+            start.elapsed().as_secs_f64()
+        );
+        results.insert(case.name.to_string(), statistics);
+    }
+    Ok(results)
+}
+
+/// Runs all canonical cases and compares them against the baseline at `path`
+pub fn check_quality(
+    path: &str,
+    tolerance: f64,
+) -> Result<QualityReport, Box<dyn std::error::Error>> {
+    let baseline = QualityBaseline::read(path).map_err(|e| {
+        format!("could not read quality baseline {path}: {e} (run `update-quality-baseline`)")
+    })?;
+    println!("Running embedding quality cases...");
+    let results = run_cases()?
+        .into_iter()
+        .map(|(name, statistics)| QualityResult {
+            baseline: baseline.f1.get(&name).copied(),
+            name,
+            f1: statistics.f1(),
+            statistics,
+        })
+        .collect();
+    Ok(QualityReport {
+        baseline_commit: baseline.commit,
+        tolerance,
+        results,
+    })
+}
+
+/// Runs all canonical cases and records them as the new baseline for the
+/// current commit
+pub fn update_baseline(path: &str) -> Result<QualityBaseline, Box<dyn std::error::Error>> {
+    let (mut commit, _) = RepoCodeStateManager::get_current_git_state()?;
+    if RepoCodeStateManager::git_dirty()? {
+        commit.push_str("-dirty");
+    }
+    println!("Running embedding quality cases...");
+    let baseline = QualityBaseline {
+        commit,
+        f1: run_cases()?
+            .into_iter()
+            .map(|(name, statistics)| (name, statistics.f1()))
+            .collect(),
+    };
+    baseline.write(path)?;
+    println!("Wrote quality baseline for {} to {path}", baseline.commit);
+    Ok(baseline)
+}
+
+#[cfg(test)]
+mod tests {
+    use rembed::graph_statistics::{GraphStatisticsReport, WeightClassStatistics};
+
+    use super::{QualityReport, QualityResult};
+
+    #[test]
+    fn weight_class_csv_has_a_row_per_case_and_class() {
+        let class = |min_weight, nodes| WeightClassStatistics {
+            min_weight,
+            max_weight: 2.0 * min_weight,
+            nodes,
+            sampled_nodes: nodes,
+            true_positives: 3,
+            false_positives: 1,
+            edges: 4,
+        };
+        let result = |name: &str, classes| {
+            let statistics = GraphStatisticsReport::from_classes(classes);
+            QualityResult {
+                name: name.to_string(),
+                baseline: None,
+                f1: statistics.f1(),
+                statistics,
+            }
+        };
+        let report = QualityReport {
+            baseline_commit: String::new(),
+            tolerance: 0.01,
+            results: vec![
+                result("grid-2d/sprk", vec![class(1.0, 10)]),
+                result(
+                    "girg-2d/sprk",
+                    vec![class(1.0, 5), class(2.0, 0), class(4.0, 2)],
+                ),
+            ],
+        };
+        let csv = report.weight_class_csv();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "case,class,min_weight,max_weight,nodes,sampled_nodes,precision,recall,f1"
+        );
+        assert_eq!(lines[1], "grid-2d/sprk,0,1,2,10,10,0.75,0.75,0.75");
+        assert!(lines[2].starts_with("girg-2d/sprk,0,1,2,5,"));
+        assert!(lines[3].starts_with("girg-2d/sprk,2,4,8,2,"));
+        assert_eq!(lines.len(), 4);
+    }
+}
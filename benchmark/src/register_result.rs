@@ -0,0 +1,284 @@
+//! Registration of position results that were computed outside the daemon,
+//! e.g. on a cluster without database access or with the C++ wembed binary.
+
+use std::path::Path;
+
+use sqlx::{Pool, Postgres};
+
+use crate::generate_positions::{SUPPORTED_DIMS, calculate_file_checksum, positions_file_path};
+
+macro_rules! dispatch_dim {
+    ($dim:ident, $file:ident, $graph:ident, $($c_dim:literal,)*) => {
+        match $dim {
+            $($c_dim => last_iteration::<$c_dim>($file, $graph),)*
+            _ => Err(format!("dim {} is not supported, supported are {SUPPORTED_DIMS:?}", $dim).into()),
+        }
+    };
+}
+
+/// A positions file and the parameters it was computed with
+#[derive(Debug, Clone)]
+pub struct ExternalResult {
+    pub graph_id: i64,
+    pub embedding_dim: i32,
+    pub dim_hint: i32,
+    pub max_iterations: i32,
+    pub seed: i32,
+    /// Path of the positions file, in any format `parse_positions_file` reads
+    pub file: String,
+    /// Move the file into the data directory instead of copying it
+    pub move_file: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registration {
+    /// A new result row with this id was inserted
+    Created(i64),
+    /// A result with the same parameters and checksum already exists
+    Existing(i64),
+}
+
+impl Registration {
+    pub fn result_id(&self) -> i64 {
+        match *self {
+            Registration::Created(id) | Registration::Existing(id) => id,
+        }
+    }
+}
+
+/// Checks that `file` holds positions of every node of `graph` in
+/// `embedding_dim` dimensions and returns the number of its last iteration.
+pub fn validate_positions_file(
+    file: &str,
+    embedding_dim: usize,
+    graph: &rembed::graph::Graph,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    dispatch_dim!(
+        embedding_dim,
+        file,
+        graph,
+        2,
+        3,
+        4,
+        5,
+        6,
+        7,
+        8,
+        9,
+        10,
+        11,
+        12,
+        13,
+        14,
+        15,
+        16,
+        32,
+    )
+}
+
+fn last_iteration<const D: usize>(
+    file: &str,
+    graph: &rembed::graph::Graph,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let iterations: rembed::parsing::Iterations<D> =
+        rembed::parsing::parse_positions_file(file).map_err(|e| format!("{file}: {e}"))?;
+    iterations.validate_graph_compatibility(graph)?;
+    let last = iterations
+        .iterations()
+        .last()
+        .ok_or_else(|| format!("{file} contains no iterations"))?;
+    Ok(last.number)
+}
+
+/// Result rows are unique per graph, dimension, dim hint, iterations and
+/// seed. Registering the same file again is a no-op, a different file for
+/// the same parameters is rejected.
+fn resolve_conflict(
+    existing: Option<(i64, &str)>,
+    checksum: &str,
+) -> Result<Option<Registration>, String> {
+    match existing {
+        None => Ok(None),
+        Some((result_id, existing)) if existing == checksum => {
+            Ok(Some(Registration::Existing(result_id)))
+        }
+        Some((result_id, _)) => Err(format!(
+            "result {result_id} already has these parameters but a different file"
+        )),
+    }
+}
+
+/// Copies `source` to `destination`, or moves it with `move_file`
+fn place_file(source: &str, destination: &Path, move_file: bool) -> std::io::Result<()> {
+    if !move_file {
+        std::fs::copy(source, destination)?;
+        return Ok(());
+    }
+    // Renaming fails across file systems, fall back to copying
+    if std::fs::rename(source, destination).is_err() {
+        std::fs::copy(source, destination)?;
+        std::fs::remove_file(source)?;
+    }
+    Ok(())
+}
+
+/// Undoes [`place_file`]: moves the file back with `move_file`, removes the
+/// copy otherwise
+fn restore_file(source: &str, destination: &Path, move_file: bool) -> std::io::Result<()> {
+    if move_file {
+        place_file(&destination.to_string_lossy(), Path::new(source), true)
+    } else {
+        std::fs::remove_file(destination)
+    }
+}
+
+/// Validates `result.file` against the graph, copies or moves it to the
+/// canonical path in the data directory and inserts its `position_results`
+/// row with source `external`. The row is inserted first and only committed
+/// once the file is in place, and the file operation is undone if the commit
+/// fails, so neither an orphaned file nor a row without file is left behind.
+pub async fn register_external_result(
+    pool: &Pool<Postgres>,
+    result: &ExternalResult,
+) -> Result<Registration, Box<dyn std::error::Error>> {
+    let data_directory = std::env::var("DATA_DIRECTORY").unwrap_or(String::from("../data/"));
+    let graph_file_path = sqlx::query_scalar!(
+        "SELECT file_path FROM graphs WHERE graph_id = $1",
+        result.graph_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| format!("graph {} does not exist", result.graph_id))?;
+    let graph = rembed::graph::Graph::parse_from_edge_list_file(
+        &format!("{data_directory}/{graph_file_path}"),
+        result.embedding_dim as usize,
+        result.dim_hint as usize,
+    )?;
+    let last_iteration =
+        validate_positions_file(&result.file, result.embedding_dim as usize, &graph)?;
+    let checksum = calculate_file_checksum(&result.file)?;
+
+    let existing = sqlx::query!(
+        r#"
+        SELECT result_id, checksum FROM position_results
+        WHERE graph_id = $1 AND embedding_dim = $2 AND dim_hint = $3 AND max_iterations = $4 AND seed = $5
+        "#,
+        result.graph_id,
+        result.embedding_dim,
+        result.dim_hint,
+        result.max_iterations,
+        result.seed
+    )
+    .fetch_optional(pool)
+    .await?;
+    if let Some(registration) = resolve_conflict(
+        existing
+            .as_ref()
+            .map(|row| (row.result_id, row.checksum.as_str())),
+        &checksum,
+    )? {
+        return Ok(registration);
+    }
+
+    let file_path = positions_file_path(
+        result.graph_id,
+        result.embedding_dim,
+        result.dim_hint,
+        result.seed,
+    );
+    let destination = std::path::Path::new(&data_directory).join(&file_path);
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut tx = pool.begin().await?;
+    let result_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO position_results (graph_id, embedding_dim, dim_hint, max_iterations, actual_iterations, seed, file_path, checksum, source)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'external')
+        RETURNING result_id
+        "#,
+        result.graph_id,
+        result.embedding_dim,
+        result.dim_hint,
+        result.max_iterations,
+        last_iteration as i32,
+        result.seed,
+        file_path,
+        checksum
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    place_file(&result.file, &destination, result.move_file)?;
+    if let Err(error) = tx.commit().await {
+        if let Err(restore_error) = restore_file(&result.file, &destination, result.move_file) {
+            return Err(format!(
+                "{error}, and restoring {} failed: {restore_error}",
+                result.file
+            )
+            .into());
+        }
+        return Err(error.into());
+    }
+    Ok(Registration::Created(result_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rembed::dvec::DVec;
+
+    #[test]
+    fn validation_rejects_files_of_another_dimension() {
+        let graph = rembed::graph::Graph::from_edge_list(vec![(0, 1), (1, 2)], 2, 2).unwrap();
+        let path = std::env::temp_dir().join(format!("register-test-{}.log", std::process::id()));
+        let path = path.to_string_lossy();
+
+        let positions_3d = vec![DVec::<3>::new([0.0, 1.0, 2.0]); 3];
+        rembed::parsing::write_test_file(
+            &path,
+            &[(0, positions_3d.clone()), (7, positions_3d)],
+            None,
+        )
+        .unwrap();
+        assert_eq!(validate_positions_file(&path, 3, &graph).unwrap(), 7);
+        let error = validate_positions_file(&path, 2, &graph).unwrap_err();
+        assert!(error.to_string().contains("dimension 3"), "{error}");
+
+        // One node short of the graph
+        let positions_2d = vec![DVec::<2>::new([0.0, 1.0]); 2];
+        rembed::parsing::write_test_file(&path, &[(0, positions_2d)], None).unwrap();
+        assert!(validate_positions_file(&path, 2, &graph).is_err());
+        std::fs::remove_file(path.as_ref()).unwrap();
+    }
+
+    #[test]
+    fn restoring_undoes_placing_the_file() {
+        let dir = std::env::temp_dir().join(format!("register-place-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("positions.log");
+        let source_str = source.to_string_lossy();
+        let destination = dir.join("placed.log");
+        std::fs::write(&source, "positions").unwrap();
+
+        for move_file in [false, true] {
+            place_file(&source_str, &destination, move_file).unwrap();
+            assert!(destination.exists());
+            assert_eq!(source.exists(), !move_file);
+            restore_file(&source_str, &destination, move_file).unwrap();
+            assert!(!destination.exists());
+            assert_eq!(std::fs::read_to_string(&source).unwrap(), "positions");
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn conflicts_are_deduplicated_by_checksum() {
+        assert_eq!(resolve_conflict(None, "abc"), Ok(None));
+        assert_eq!(
+            resolve_conflict(Some((4, "abc")), "abc"),
+            Ok(Some(Registration::Existing(4)))
+        );
+        assert!(resolve_conflict(Some((4, "abc")), "def").is_err());
+    }
+}
@@ -26,7 +26,7 @@ impl StatisticGenerator {
                 positions: iteration.positions.iter().cloned().collect(),
                 graph,
             };
-            let sprk = Sprk::new(&embedding);
+            let sprk = Sprk::new(embedding.view());
             stats.push(sprk.graph_statistics());
         }
         stats
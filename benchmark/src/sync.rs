@@ -0,0 +1,431 @@
+//! Incremental pushes of the data directory to `RSYNC_DESTINATION`.
+//!
+//! A manifest in the data directory remembers the size, modification time
+//! and checksum of every file of the last successful push. Files whose size
+//! and modification time still match are assumed to be synced without reading
+//! them, the others are checksummed in parallel and only pushed if their
+//! contents changed. rsync then gets the changed files as an explicit
+//! `--files-from` list instead of scanning the whole tree.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use rayon::prelude::*;
+
+/// Name of the manifest in the data directory, which is never pushed
+pub const MANIFEST_FILE: &str = ".sync_manifest";
+
+/// Default for `SYNC_INTERVAL_MINUTES`, see [`PushBatcher::from_env`]
+pub const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Default for `SYNC_BATCH_FILES`, see [`PushBatcher::from_env`]
+pub const DEFAULT_SYNC_BATCH_FILES: usize = 50;
+
+/// Size and modification time of a file, which are cheap to read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStat {
+    pub size: u64,
+    pub mtime_ns: u64,
+}
+
+/// State of a file at the time it was last pushed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileState {
+    pub stat: FileStat,
+    /// Hex SHA-256 of the contents
+    pub checksum: String,
+}
+
+/// Files known to be synced, by path relative to the data directory
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Manifest {
+    files: BTreeMap<String, FileState>,
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`, which is empty if it does not exist yet
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                Self::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the manifest to `path`. The file is replaced atomically, so an
+    /// interrupted save keeps the previous manifest.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        let mut file = io::BufWriter::new(std::fs::File::create(&tmp)?);
+        for (path, state) in &self.files {
+            writeln!(
+                file,
+                "{path}\t{}\t{}\t{}",
+                state.stat.size, state.stat.mtime_ns, state.checksum
+            )?;
+        }
+        file.into_inner()?.sync_all()?;
+        std::fs::rename(tmp, path)
+    }
+
+    /// Parses one `path\tsize\tmtime_ns\tchecksum` line per file
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut files = BTreeMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let invalid = || format!("invalid manifest line {}: '{line}'", number + 1);
+            let mut fields = line.split('\t');
+            let (Some(path), Some(size), Some(mtime_ns), Some(checksum), None) = (
+                fields.next(),
+                fields.next(),
+                fields.next(),
+                fields.next(),
+                fields.next(),
+            ) else {
+                return Err(invalid());
+            };
+            let stat = FileStat {
+                size: size.parse().map_err(|_| invalid())?,
+                mtime_ns: mtime_ns.parse().map_err(|_| invalid())?,
+            };
+            let state = FileState {
+                stat,
+                checksum: checksum.to_string(),
+            };
+            files.insert(path.to_string(), state);
+        }
+        Ok(Self { files })
+    }
+
+    pub fn get(&self, path: &str) -> Option<&FileState> {
+        self.files.get(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Records the files of `delta` as synced
+    pub fn apply(&mut self, delta: Delta) {
+        for path in &delta.removed {
+            self.files.remove(path);
+        }
+        self.files.extend(delta.states);
+    }
+}
+
+/// Difference between the files on disk and a [`Manifest`]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Delta {
+    /// Files that are new or whose contents changed, which need to be pushed
+    pub changed: Vec<String>,
+    /// New state of every file that was checksummed, including files that
+    /// were only touched
+    pub states: BTreeMap<String, FileState>,
+    /// Files of the manifest that no longer exist
+    pub removed: Vec<String>,
+}
+
+/// Lists the regular files below `root` with paths relative to it, without
+/// the manifest
+pub fn scan(root: &Path) -> io::Result<BTreeMap<String, FileStat>> {
+    let mut files = BTreeMap::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                directories.push(entry.path());
+                continue;
+            }
+            if !file_type.is_file() {
+                continue;
+            }
+            let path = entry.path();
+            let relative = path
+                .strip_prefix(root)
+                .expect("scanned paths lie below the root")
+                .to_string_lossy()
+                .into_owned();
+            if relative == MANIFEST_FILE {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            let mtime_ns = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as u64;
+            let stat = FileStat {
+                size: metadata.len(),
+                mtime_ns,
+            };
+            files.insert(relative, stat);
+        }
+    }
+    Ok(files)
+}
+
+/// Compares the `files` on disk to `manifest`. Files whose size and
+/// modification time match the manifest are not read, all others are
+/// checksummed in parallel with `checksum` and count as changed unless the
+/// checksum matches the manifest.
+pub fn compute_delta(
+    manifest: &Manifest,
+    files: &BTreeMap<String, FileStat>,
+    checksum: impl Fn(&str) -> io::Result<String> + Sync,
+) -> io::Result<Delta> {
+    let candidates: Vec<_> = files
+        .iter()
+        .filter(|(path, stat)| manifest.get(path).is_none_or(|known| known.stat != **stat))
+        .collect();
+    let states = candidates
+        .into_par_iter()
+        .map(|(path, &stat)| {
+            let checksum = checksum(path)?;
+            Ok((path.clone(), FileState { stat, checksum }))
+        })
+        .collect::<io::Result<BTreeMap<_, _>>>()?;
+    let changed = states
+        .iter()
+        .filter(|(path, state)| {
+            manifest
+                .get(path)
+                .is_none_or(|known| known.checksum != state.checksum)
+        })
+        .map(|(path, _)| path.clone())
+        .collect();
+    let removed = manifest
+        .files
+        .keys()
+        .filter(|path| !files.contains_key(*path))
+        .cloned()
+        .collect();
+    Ok(Delta {
+        changed,
+        states,
+        removed,
+    })
+}
+
+/// [`compute_delta`] for the files below `root`, checksummed like the
+/// `checksum` columns of the database. Also returns the number of files.
+pub fn delta_on_disk(root: &Path, manifest: &Manifest) -> io::Result<(usize, Delta)> {
+    let files = scan(root)?;
+    let delta = compute_delta(manifest, &files, |path| {
+        let path = root.join(path);
+        crate::generate_positions::calculate_file_checksum(&path.to_string_lossy())
+            .map_err(|e| io::Error::other(e.to_string()))
+    })?;
+    Ok((files.len(), delta))
+}
+
+/// Decides when the position daemon pushes: once `max_files` files are
+/// waiting or `max_interval` passed since the last push, instead of after
+/// every job.
+#[derive(Debug)]
+pub struct PushBatcher {
+    max_interval: Duration,
+    max_files: usize,
+    pending: usize,
+    last_push: Instant,
+}
+
+impl PushBatcher {
+    pub fn new(max_interval: Duration, max_files: usize) -> Self {
+        Self {
+            max_interval,
+            max_files,
+            pending: 0,
+            last_push: Instant::now(),
+        }
+    }
+
+    /// Reads the interval from `SYNC_INTERVAL_MINUTES` and the number of
+    /// files from `SYNC_BATCH_FILES`
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let max_interval = match std::env::var("SYNC_INTERVAL_MINUTES") {
+            Ok(value) => {
+                let minutes: f64 = value
+                    .parse()
+                    .map_err(|e| format!("invalid SYNC_INTERVAL_MINUTES '{value}': {e}"))?;
+                Duration::from_secs_f64(minutes * 60.)
+            }
+            Err(_) => DEFAULT_SYNC_INTERVAL,
+        };
+        let max_files = match std::env::var("SYNC_BATCH_FILES") {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("invalid SYNC_BATCH_FILES '{value}': {e}"))?,
+            Err(_) => DEFAULT_SYNC_BATCH_FILES,
+        };
+        Ok(Self::new(max_interval, max_files))
+    }
+
+    /// Notes `files` new files that wait for the next push
+    pub fn record(&mut self, files: usize) {
+        self.pending += files;
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Whether files are waiting and either enough of them or enough time
+    /// since the last push came together by `now`
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.pending > 0
+            && (self.pending >= self.max_files
+                || now.duration_since(self.last_push) >= self.max_interval)
+    }
+
+    /// Notes a successful push at `now`
+    pub fn pushed(&mut self, now: Instant) {
+        self.pending = 0;
+        self.last_push = now;
+    }
+
+    /// Pushes if [`PushBatcher::is_due`]
+    pub async fn push_if_due(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.is_due(Instant::now()) {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Pushes the waiting files, if any
+    pub async fn flush(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.pending > 0 {
+            crate::push_files().await?;
+            self.pushed(Instant::now());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    use super::{Delta, FileStat, FileState, MANIFEST_FILE, Manifest, PushBatcher, compute_delta};
+
+    fn stat(size: u64, mtime_ns: u64) -> FileStat {
+        FileStat { size, mtime_ns }
+    }
+
+    fn manifest(files: &[(&str, FileStat, &str)]) -> Manifest {
+        let mut manifest = Manifest::default();
+        manifest.apply(Delta {
+            states: files
+                .iter()
+                .map(|&(path, stat, checksum)| {
+                    let checksum = checksum.to_string();
+                    (path.to_string(), FileState { stat, checksum })
+                })
+                .collect(),
+            ..Default::default()
+        });
+        manifest
+    }
+
+    #[test]
+    fn delta_only_reads_files_with_new_stats() {
+        let known = manifest(&[
+            ("graphs/1.txt", stat(10, 100), "a"),
+            ("graphs/2.txt", stat(20, 200), "b"),
+            ("positions/3.txt", stat(30, 300), "c"),
+            ("positions/4.txt", stat(40, 400), "d"),
+        ]);
+        let files = BTreeMap::from([
+            // Unchanged
+            ("graphs/1.txt".to_string(), stat(10, 100)),
+            // Touched, same contents
+            ("graphs/2.txt".to_string(), stat(20, 250)),
+            // Rewritten
+            ("positions/3.txt".to_string(), stat(31, 350)),
+            // New
+            ("positions/5.txt".to_string(), stat(50, 500)),
+        ]);
+        let reads = AtomicUsize::new(0);
+        let delta = compute_delta(&known, &files, |path| {
+            reads.fetch_add(1, Ordering::Relaxed);
+            Ok(match path {
+                "graphs/2.txt" => "b",
+                _ => "new",
+            }
+            .to_string())
+        })
+        .unwrap();
+
+        assert_eq!(reads.into_inner(), 3);
+        assert_eq!(delta.changed, ["positions/3.txt", "positions/5.txt"]);
+        assert_eq!(delta.removed, ["positions/4.txt"]);
+
+        let mut updated = known.clone();
+        updated.apply(delta);
+        assert_eq!(updated.len(), 4);
+        assert_eq!(updated.get("graphs/2.txt").unwrap().stat, stat(20, 250));
+        assert!(updated.get("positions/4.txt").is_none());
+        // Nothing left to push afterwards
+        let delta = compute_delta(&updated, &files, |_| unreachable!()).unwrap();
+        assert_eq!(delta, Delta::default());
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let dir = std::env::temp_dir().join(format!("sync-manifest-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("graphs")).unwrap();
+        std::fs::write(dir.join("graphs/1.txt"), "0 1\n").unwrap();
+        std::fs::write(dir.join("2.txt"), "").unwrap();
+
+        let path = dir.join(MANIFEST_FILE);
+        assert!(Manifest::load(&path).unwrap().is_empty());
+        let files = super::scan(&dir).unwrap();
+        assert_eq!(files.keys().collect::<Vec<_>>(), ["2.txt", "graphs/1.txt"]);
+        assert_eq!(files["graphs/1.txt"].size, 4);
+
+        let mut manifest = Manifest::default();
+        let delta = compute_delta(&manifest, &files, |path| Ok(format!("sum of {path}")));
+        manifest.apply(delta.unwrap());
+        manifest.save(&path).unwrap();
+        // The manifest itself is not part of the data
+        assert_eq!(super::scan(&dir).unwrap(), files);
+        let loaded = Manifest::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded, manifest);
+        assert_eq!(loaded.get("2.txt").unwrap().checksum, "sum of 2.txt");
+
+        assert!(Manifest::parse("graphs/1.txt\t4\tnot a time\tsum\n").is_err());
+        assert!(Manifest::parse("graphs/1.txt\t4\n").is_err());
+    }
+
+    #[test]
+    fn batcher_waits_for_files_or_time() {
+        let start = Instant::now();
+        let mut batcher = PushBatcher::new(Duration::from_secs(600), 3);
+        batcher.last_push = start;
+        // Nothing to push, no matter how long ago the last push was
+        assert!(!batcher.is_due(start + Duration::from_secs(3600)));
+
+        batcher.record(2);
+        assert!(!batcher.is_due(start + Duration::from_secs(60)));
+        assert!(batcher.is_due(start + Duration::from_secs(600)));
+        batcher.record(1);
+        assert!(batcher.is_due(start + Duration::from_secs(60)));
+
+        batcher.pushed(start + Duration::from_secs(60));
+        assert_eq!(batcher.pending(), 0);
+        batcher.record(1);
+        assert!(!batcher.is_due(start + Duration::from_secs(600)));
+        assert!(batcher.is_due(start + Duration::from_secs(660)));
+    }
+}
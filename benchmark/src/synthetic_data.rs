@@ -3,7 +3,6 @@ use rand::rngs::StdRng;
 use rand_distr::{Distribution, StandardNormal, Uniform};
 use rembed::dvec::DVec;
 use rembed::graph::{Graph, Node};
-use std::collections::HashSet;
 
 #[derive(Debug, Clone)]
 pub enum PointDistribution {
@@ -87,7 +86,6 @@ pub fn create_minimal_graph(node_count: usize, weight: f64) -> Graph {
         .map(|_| Node {
             weight,
             neighbors: Vec::new(),
-            neighbors_set: HashSet::new(),
         })
         .collect();
 
@@ -133,7 +131,6 @@ mod tests {
     //     for node in &graph.nodes {
     //         assert_eq!(node.weight, 1.0);
     //         assert_eq!(node.neighbors.len(), 0);
-    //         assert_eq!(node.neighbors_set.len(), 0);
     //     }
     // }
 
@@ -0,0 +1,65 @@
+//! Throwaway databases for the tests that need a Postgres server.
+//! `TEST_DATABASE_URL` is the URL of a database on it, next to which a fresh
+//! database is created for every test, e.g.
+//!
+//! ```sh
+//! TEST_DATABASE_URL=postgresql://localhost/rembed_test cargo test -- --ignored
+//! ```
+
+use sqlx::migrate::MigrateDatabase;
+use sqlx::{PgPool, Postgres};
+use std::path::PathBuf;
+
+/// Fresh database with all migrations applied and an empty data directory,
+/// both named after `name`
+pub struct TestEnvironment {
+    url: String,
+    pub pool: PgPool,
+    pub data_directory: PathBuf,
+}
+
+impl TestEnvironment {
+    pub async fn new(name: &str) -> Self {
+        let base = std::env::var("TEST_DATABASE_URL")
+            .expect("set TEST_DATABASE_URL to run the database tests");
+        let url = format!("{base}_{name}_{}", std::process::id());
+        if Postgres::database_exists(&url).await.unwrap() {
+            Postgres::force_drop_database(&url).await.unwrap();
+        }
+        Postgres::create_database(&url).await.unwrap();
+        let pool = PgPool::connect(&url).await.unwrap();
+        benchmark::migrate(&pool).await.unwrap();
+
+        let data_directory =
+            std::env::temp_dir().join(format!("rembed-db-test-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(data_directory.join("generated/graphs")).unwrap();
+        std::fs::create_dir_all(data_directory.join("generated/positions")).unwrap();
+        TestEnvironment {
+            url,
+            pool,
+            data_directory,
+        }
+    }
+
+    /// Registers a graph stored at `file_path` below the data directory
+    pub async fn insert_graph(&self, file_path: &str, n: i32) -> i64 {
+        sqlx::query_scalar(
+            "INSERT INTO graphs (n, deg, ple, dim, alpha, wseed, pseed, sseed,
+                processed_n, processed_avg_degree, file_path, checksum)
+            VALUES ($1, 4, 2.5, 2, 'infinity', 1, 1, 1, $1, 4, $2, $3)
+            RETURNING graph_id",
+        )
+        .bind(n)
+        .bind(file_path)
+        .bind("0".repeat(64))
+        .fetch_one(&self.pool)
+        .await
+        .unwrap()
+    }
+
+    pub async fn finish(self) {
+        self.pool.close().await;
+        Postgres::force_drop_database(&self.url).await.unwrap();
+        std::fs::remove_dir_all(&self.data_directory).unwrap();
+    }
+}
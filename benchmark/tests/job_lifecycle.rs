@@ -0,0 +1,143 @@
+//! Claim, process and complete of the position job daemon against a real
+//! database. Needs a Postgres server: `TEST_DATABASE_URL` is the URL of a
+//! database on it, next to which a throwaway database is created, e.g.
+//!
+//! ```sh
+//! TEST_DATABASE_URL=postgresql://localhost/rembed_test cargo test --test job_lifecycle -- --ignored
+//! ```
+
+mod common;
+
+use benchmark::generate_positions::PositionGenerator;
+use benchmark::job_manager::JobManager;
+use common::TestEnvironment;
+use sha2::{Digest, Sha256};
+use sqlx::Row;
+use std::path::Path;
+
+impl TestEnvironment {
+    fn generator(&self) -> PositionGenerator {
+        PositionGenerator::new(
+            String::new(),
+            self.data_directory.to_string_lossy().into_owned(),
+            JobManager::new(self.pool.clone()),
+        )
+    }
+}
+
+/// Edge list of a `side` x `side` grid
+fn write_grid(path: &Path, side: usize) {
+    let mut edges = String::new();
+    for u in 0..side * side {
+        if u % side + 1 < side {
+            edges.push_str(&format!("{u} {}\n", u + 1));
+        }
+        if u + side < side * side {
+            edges.push_str(&format!("{u} {}\n", u + side));
+        }
+    }
+    std::fs::write(path, edges).unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server, see the module docs"]
+async fn completed_job_records_its_result() {
+    let env = TestEnvironment::new("complete").await;
+    let graph_file = "generated/graphs/grid.txt";
+    write_grid(&env.data_directory.join(graph_file), 10);
+    let graph_id = env.insert_graph(graph_file, 100).await;
+    let generator = env.generator();
+    assert_eq!(
+        generator
+            .job_manager
+            .create_jobs_for_graph(graph_id, &[2])
+            .await
+            .unwrap(),
+        1
+    );
+
+    let job = generator.process_next_job().await.unwrap().unwrap();
+    assert_eq!(job.graph_id, graph_id);
+    assert!(generator.process_next_job().await.unwrap().is_none());
+
+    let status: String = sqlx::query_scalar("SELECT status FROM position_jobs WHERE job_id = $1")
+        .bind(job.job_id)
+        .fetch_one(&env.pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "completed");
+
+    let result = sqlx::query(
+        "SELECT file_path, checksum, max_iterations, actual_iterations, source,
+            optimizer_options::text AS optimizer_options
+        FROM position_results WHERE graph_id = $1",
+    )
+    .bind(graph_id)
+    .fetch_one(&env.pool)
+    .await
+    .unwrap();
+    let file_path: String = result.get("file_path");
+    let path = env.data_directory.join(&file_path);
+    let contents = std::fs::read(&path).unwrap();
+    assert_eq!(
+        result.get::<String, _>("checksum"),
+        format!("{:x}", Sha256::digest(&contents))
+    );
+    assert_eq!(result.get::<String, _>("source"), "daemon");
+    assert!(
+        result
+            .get::<Option<String>, _>("optimizer_options")
+            .is_some()
+    );
+
+    let iterations: rembed::parsing::Iterations<2> =
+        rembed::parsing::parse_positions_file(&path).unwrap();
+    let last = iterations.iterations().last().unwrap();
+    assert_eq!(iterations.num_nodes(), 100);
+    let actual_iterations: i32 = result.get::<Option<i32>, _>("actual_iterations").unwrap();
+    assert!(actual_iterations > 0);
+    assert!(actual_iterations <= result.get::<i32, _>("max_iterations"));
+    assert!(last.number <= actual_iterations as usize);
+
+    env.finish().await;
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server, see the module docs"]
+async fn missing_graph_file_fails_the_job() {
+    let env = TestEnvironment::new("fail").await;
+    let graph_id = env
+        .insert_graph("generated/graphs/does-not-exist.txt", 100)
+        .await;
+    let generator = env.generator();
+    generator
+        .job_manager
+        .create_jobs_for_graph(graph_id, &[2])
+        .await
+        .unwrap();
+
+    let job = generator.process_next_job().await.unwrap().unwrap();
+    let failed = sqlx::query(
+        "SELECT status, attempts, error_message, next_eligible_at IS NOT NULL AS retried
+        FROM position_jobs WHERE job_id = $1",
+    )
+    .bind(job.job_id)
+    .fetch_one(&env.pool)
+    .await
+    .unwrap();
+    assert_eq!(failed.get::<String, _>("status"), "failed");
+    assert_eq!(failed.get::<i32, _>("attempts"), 1);
+    assert!(failed.get::<bool, _>("retried"));
+    let message: String = failed.get("error_message");
+    assert!(message.contains("does-not-exist.txt"), "{message}");
+
+    let results: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM position_results")
+        .fetch_one(&env.pool)
+        .await
+        .unwrap();
+    assert_eq!(results, 0);
+    // Not eligible again before the backoff
+    assert!(generator.process_next_job().await.unwrap().is_none());
+
+    env.finish().await;
+}
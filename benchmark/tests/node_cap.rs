@@ -0,0 +1,74 @@
+//! The `--max-nodes` guard of the correctness tests against a real database,
+//! see [`common`] for the Postgres server it needs:
+//!
+//! ```sh
+//! TEST_DATABASE_URL=postgresql://localhost/rembed_test cargo test --test node_cap -- --ignored
+//! ```
+
+mod common;
+
+use benchmark::correctness_test::{CorrectnessTestManager, TestResult};
+use common::TestEnvironment;
+
+impl TestEnvironment {
+    /// Registers a position result of `graph_id` with a test file for radius 1
+    async fn insert_tested_result(&self, graph_id: i64) {
+        let result_id: i64 = sqlx::query_scalar(
+            "INSERT INTO position_results (graph_id, embedding_dim, dim_hint, max_iterations,
+                seed, file_path, checksum)
+            VALUES ($1, 2, 2, 100, 1, $2, $3)
+            RETURNING result_id",
+        )
+        .bind(graph_id)
+        .bind(format!("generated/positions/{graph_id}.bin"))
+        .bind("0".repeat(64))
+        .fetch_one(&self.pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO tests (result_id, file_path) VALUES ($1, $2)")
+            .bind(result_id)
+            .bind(format!("generated/tests/{result_id}.bin"))
+            .execute(&self.pool)
+            .await
+            .unwrap();
+    }
+}
+
+fn graph_ids(results: Vec<TestResult>) -> Vec<i64> {
+    let mut ids: Vec<i64> = results.into_iter().map(|r| r.graph_id).collect();
+    ids.sort_unstable();
+    ids
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres server, see the module docs"]
+async fn max_nodes_excludes_larger_graphs() {
+    let env = TestEnvironment::new("max_nodes").await;
+    let small = env.insert_graph("generated/graphs/small.txt", 100).await;
+    let at_cap = env.insert_graph("generated/graphs/at_cap.txt", 1000).await;
+    let large = env.insert_graph("generated/graphs/large.txt", 3000).await;
+    let huge = env.insert_graph("generated/graphs/huge.txt", 20000).await;
+    for graph_id in [small, at_cap, large, huge] {
+        env.insert_tested_result(graph_id).await;
+    }
+    let manager = CorrectnessTestManager::new(env.pool.clone());
+
+    let results = |all_graphs, max_nodes| manager.test_results(all_graphs, 1.0, max_nodes);
+    assert_eq!(
+        graph_ids(results(true, None).await.unwrap()),
+        [small, at_cap, large, huge]
+    );
+    assert_eq!(
+        graph_ids(results(true, Some(1000)).await.unwrap()),
+        [small, at_cap]
+    );
+    // The cap also applies on top of the quick run's limit of 5000 nodes
+    assert_eq!(
+        graph_ids(results(false, None).await.unwrap()),
+        [small, at_cap, large]
+    );
+    assert_eq!(graph_ids(results(false, Some(999)).await.unwrap()), [small]);
+    assert!(results(true, Some(10)).await.unwrap().is_empty());
+
+    env.finish().await;
+}
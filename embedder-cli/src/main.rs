@@ -6,8 +6,9 @@ use rembed::{
     DynamicQuery, Embedding, Sprk,
     dyn_embed::{DynDynamicQuery, DynVec, EmbedIndex},
     dvec::{DVec, Vector},
-    embedder::{EmbedderOptions, WEmbedder},
+    embedder::{EmbedderOptions, OptimizerKind, WEmbedder},
     graph,
+    select_index::AutoIndex,
 };
 
 // Re-use rand from rembed's re-export isn't available, so we use the
@@ -52,10 +53,43 @@ struct Args {
     #[arg(long)]
     learning_rate: Option<f64>,
 
+    /// Pick the learning rate with a range test before embedding, replacing
+    /// --learning-rate
+    #[arg(long)]
+    auto_lr: bool,
+
     /// Cooling factor per iteration
     #[arg(long)]
     cooling_factor: Option<f64>,
 
+    /// Reset the optimizer's moments when the positions oscillate
+    #[arg(long)]
+    oscillation_restart: bool,
+
+    /// Update rule of the optimizer
+    #[arg(long, value_enum, default_value = "adam")]
+    optimizer: OptimizerArg,
+
+    /// Decay of Adam's first-moment estimate
+    #[arg(long)]
+    adam_beta1: Option<f64>,
+
+    /// Decay of Adam's second-moment estimate
+    #[arg(long)]
+    adam_beta2: Option<f64>,
+
+    /// Epsilon added to the root of Adam's second moment
+    #[arg(long)]
+    adam_epsilon: Option<f64>,
+
+    /// Velocity decay of the momentum optimizer
+    #[arg(long)]
+    momentum: Option<f64>,
+
+    /// Longest distance a node moves in one update
+    #[arg(long)]
+    max_step: Option<f64>,
+
     /// Maximum number of iterations
     #[arg(long)]
     max_iterations: Option<usize>,
@@ -79,6 +113,11 @@ struct Args {
     /// Random seed for initial positions
     #[arg(long, default_value = "42")]
     seed: u64,
+
+    /// Lay out the graph through this many coarsening levels instead of
+    /// embedding it directly (2D only, ignores --index)
+    #[arg(long)]
+    multi_scale_levels: Option<usize>,
 }
 
 #[derive(Clone, ValueEnum)]
@@ -91,12 +130,23 @@ enum F1Mode {
     Every,
 }
 
+#[derive(Clone, ValueEnum)]
+enum OptimizerArg {
+    /// Adam (default)
+    Adam,
+    /// Gradient descent with momentum, for ablation studies
+    Momentum,
+}
+
 #[derive(Clone, ValueEnum)]
 enum IndexKind {
     /// Sprk tree (direct, no caching)
     Sprk,
     /// Sprk tree with dynamic query caching (default)
     SprkDynamic,
+    /// Whichever index is fastest on a sample of the graph, see
+    /// `rembed::select_index`
+    Auto,
     // Uncomment to enable additional indices (requires impl_embed_index! in rembed):
     // Kiddo,
     // VpTree,
@@ -144,7 +194,14 @@ macro_rules! dispatch_dim {
 
 fn main() -> io::Result<()> {
     let args = Args::parse();
+    build_options(&args).validate().map_err(io::Error::other)?;
 
+    if let Some(levels) = args.multi_scale_levels {
+        if args.dim != 2 {
+            return Err(io::Error::other("--multi-scale-levels requires --dim 2"));
+        }
+        return run_multi_scale(&args, levels);
+    }
     dispatch_dim!(args.dim, &args)
 }
 
@@ -154,29 +211,37 @@ fn main() -> io::Result<()> {
 
 fn run<const D: usize>(args: &Args) -> io::Result<()> {
     let dim_hint = args.dim_hint.unwrap_or(D);
-    let graph = graph::Graph::parse_from_edge_list_file(&args.input, D, dim_hint)?;
+    let graph = load_graph(&args.input, D, dim_hint)?;
     let options = build_options(args);
 
     eprintln!("n: {}, dim: {D}, dim_hint: {dim_hint}", graph.nodes.len());
 
     match args.index {
         IndexKind::SprkDynamic => {
-            run_embed::<DynamicQuery<_, Sprk<_>>, D>(&graph, options, args)
+            let embedder: WEmbedder<DynamicQuery<'_, D, Sprk<'_, D>>> =
+                WEmbedder::random(args.seed, &graph, options);
+            run_embed(&graph, embedder, args)
+        }
+        IndexKind::Sprk => {
+            let embedder: WEmbedder<Sprk<'_, D>> = WEmbedder::random(args.seed, &graph, options);
+            run_embed(&graph, embedder, args)
+        }
+        IndexKind::Auto => {
+            let embedder: WEmbedder<AutoIndex<'_, D>> =
+                WEmbedder::random_auto(args.seed, &graph, options);
+            run_embed(&graph, embedder, args)
         }
-        IndexKind::Sprk => run_embed::<Sprk<_>, D>(&graph, options, args),
     }
 }
 
-fn run_embed<'a, SI, const D: usize>(
-    graph: &'a graph::Graph,
-    options: EmbedderOptions,
+fn run_embed<SI, const D: usize>(
+    graph: &graph::Graph,
+    mut embedder: WEmbedder<SI>,
     args: &Args,
 ) -> io::Result<()>
 where
-    SI: rembed::query::Embedder<'a, D> + EmbedIndex<Vec = DVec<D>>,
+    SI: EmbedIndex<Vec = DVec<D>>,
 {
-    let mut embedder: WEmbedder<SI> = WEmbedder::random(args.seed, graph, options.clone());
-
     let start = Instant::now();
     let mut last_time = Instant::now();
     embedder.embed_with_callback(|e| {
@@ -201,7 +266,7 @@ where
             positions: embedder.positions().to_vec(),
             graph,
         };
-        let eval = WEmbedder::new(Sprk::new(&final_embedding), Default::default());
+        let eval = WEmbedder::new(Sprk::new(final_embedding.view()), Default::default());
         eval.print_stats();
     }
 
@@ -213,6 +278,40 @@ where
     Ok(())
 }
 
+// ---------------------------------------------------------------------------
+// Multi-scale path (D = 2)
+// ---------------------------------------------------------------------------
+
+fn run_multi_scale(args: &Args, levels: usize) -> io::Result<()> {
+    let dim_hint = args.dim_hint.unwrap_or(2);
+    let graph = load_graph(&args.input, 2, dim_hint)?;
+    let options = build_options(args);
+
+    eprintln!(
+        "n: {}, dim: 2, dim_hint: {dim_hint}, levels: {levels}",
+        graph.nodes.len()
+    );
+
+    let start = Instant::now();
+    let positions = graph.multi_scale_layout(args.seed, levels, options);
+    eprintln!("Embedding took {:.2}s", start.elapsed().as_secs_f32());
+
+    if !matches!(args.f1_mode, F1Mode::Never) {
+        let final_embedding: Embedding<'_, 2> = Embedding {
+            positions: positions.clone(),
+            graph: &graph,
+        };
+        let eval = WEmbedder::new(Sprk::new(final_embedding.view()), Default::default());
+        eval.print_stats();
+    }
+
+    if let Some(ref path) = args.output {
+        write_positions(&positions, path)?;
+    }
+
+    Ok(())
+}
+
 // ---------------------------------------------------------------------------
 // Dynamic path (D > 16)
 // ---------------------------------------------------------------------------
@@ -220,7 +319,7 @@ where
 fn run_dynamic(args: &Args) -> io::Result<()> {
     let dim = args.dim;
     let dim_hint = args.dim_hint.unwrap_or(dim);
-    let graph = graph::Graph::parse_from_edge_list_file(&args.input, dim, dim_hint)?;
+    let graph = load_graph(&args.input, dim, dim_hint)?;
     let options = build_options(args);
 
     eprintln!(
@@ -285,6 +384,25 @@ fn build_options(args: &Args) -> EmbedderOptions {
     if let Some(v) = args.cooling_factor {
         opts.cooling_factor = v;
     }
+    opts.optimizer = match args.optimizer {
+        OptimizerArg::Adam => OptimizerKind::Adam,
+        OptimizerArg::Momentum => OptimizerKind::Momentum,
+    };
+    if let Some(v) = args.adam_beta1 {
+        opts.adam_beta1 = v;
+    }
+    if let Some(v) = args.adam_beta2 {
+        opts.adam_beta2 = v;
+    }
+    if let Some(v) = args.adam_epsilon {
+        opts.adam_epsilon = v;
+    }
+    if let Some(v) = args.momentum {
+        opts.momentum = v;
+    }
+    opts.max_step = args.max_step;
+    opts.auto_lr = args.auto_lr;
+    opts.oscillation_restart = args.oscillation_restart;
     if let Some(v) = args.max_iterations {
         opts.max_iterations = v;
     }
@@ -301,6 +419,17 @@ fn build_options(args: &Args) -> EmbedderOptions {
     opts
 }
 
+/// Parses the edge list and reports remapped node ids. The output rows
+/// follow the remapped ids, i.e. the original ids in ascending order.
+fn load_graph(path: &str, dim: usize, dim_hint: usize) -> io::Result<graph::Graph> {
+    let (graph, warnings) =
+        graph::Graph::parse_edge_list_file_with_warnings(path, dim, dim_hint, true)?;
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+    Ok(graph)
+}
+
 fn write_positions<const D: usize>(positions: &[DVec<D>], path: &str) -> io::Result<()> {
     use std::fmt::Write as _;
     let mut out = String::new();
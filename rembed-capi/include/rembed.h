@@ -0,0 +1,120 @@
+#ifndef REMBED_H
+#define REMBED_H
+
+/* Generated with cbindgen. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define REMBED_OK 0
+
+#define REMBED_ERR_NULL_POINTER 1
+
+#define REMBED_ERR_INVALID_ARGUMENT 2
+
+#define REMBED_ERR_PANIC 3
+
+/**
+ * Smallest embedding dimension the API supports
+ */
+#define REMBED_MIN_DIM 2
+
+/**
+ * Largest embedding dimension the API supports
+ */
+#define REMBED_MAX_DIM 16
+
+/**
+ * Opaque graph handle created by [`rembed_graph_parse`].
+ */
+typedef struct RembedGraph RembedGraph;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Returns the message of the last error on this thread, or null if there was none.
+ *
+ * The pointer stays valid until the next failing call on the same thread.
+ */
+const char *rembed_last_error_message(void);
+
+/**
+ * Parses an edge list file for embedding in `dim` dimensions, which has to
+ * lie between [`REMBED_MIN_DIM`] and [`REMBED_MAX_DIM`]. Returns null on
+ * failure.
+ *
+ * # Safety
+ * `path` must be a valid nul-terminated string.
+ */
+RembedGraph *rembed_graph_parse(const char *path, size_t dim, size_t dim_hint);
+
+/**
+ * Number of nodes of a parsed graph, 0 if `graph` is null.
+ *
+ * # Safety
+ * `graph` must be null or a handle returned by [`rembed_graph_parse`].
+ */
+size_t rembed_graph_num_nodes(const RembedGraph *graph);
+
+/**
+ * Frees a graph handle. Passing null is a no-op.
+ *
+ * # Safety
+ * `graph` must be null or a handle returned by [`rembed_graph_parse`] that
+ * has not been freed yet.
+ */
+void rembed_graph_free(RembedGraph *graph);
+
+/**
+ * Embeds the graph from random initial positions and writes the final
+ * positions row-major into `out_positions`.
+ *
+ * # Safety
+ * `graph` must be a valid handle and `out_positions` must point to at least
+ * `rembed_graph_num_nodes(graph) * dim` writable floats.
+ */
+int32_t rembed_embed(const RembedGraph *graph,
+                     size_t dim,
+                     uint64_t seed,
+                     size_t max_iterations,
+                     float *out_positions);
+
+/**
+ * Returns all nodes within `radius` of `node`, using an index freshly built
+ * over `positions` (`n * dim` floats, row-major). `dim` has to be the
+ * dimension the graph was parsed for.
+ *
+ * The result is written to `out_ids`/`out_len` and must be released with
+ * [`rembed_ids_free`].
+ *
+ * # Safety
+ * `positions` must point to `n * dim` readable floats, `graph` must be a
+ * valid handle and `out_ids`/`out_len` must be writable.
+ */
+int32_t rembed_query_radius(const float *positions,
+                            size_t n,
+                            size_t dim,
+                            const RembedGraph *graph,
+                            size_t node,
+                            double radius,
+                            size_t **out_ids,
+                            size_t *out_len);
+
+/**
+ * Frees an id list returned by [`rembed_query_radius`]. Passing null is a no-op.
+ *
+ * # Safety
+ * `ids` and `len` must come from the same successful [`rembed_query_radius`] call.
+ */
+void rembed_ids_free(size_t *ids, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* REMBED_H */
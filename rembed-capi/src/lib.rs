@@ -0,0 +1,320 @@
+//! Minimal C API for driving the embedder from the C++ wembed tooling.
+//!
+//! Graphs are handed out as opaque [`RembedGraph`] handles. All fallible
+//! functions return one of the `REMBED_*` status codes; the message for the
+//! most recent failure on the calling thread can be read with
+//! [`rembed_last_error_message`]. Panics are caught at every boundary and
+//! reported as [`REMBED_ERR_PANIC`].
+//!
+//! The embedder and the radius queries run on [`Sprk`] for every dimension
+//! from [`REMBED_MIN_DIM`] to [`REMBED_MAX_DIM`]; other dimensions are
+//! rejected with [`REMBED_ERR_INVALID_ARGUMENT`].
+//!
+//! This is a separate workspace crate rather than a `capi` feature of
+//! `rembed`: cargo cannot switch a crate type on with a feature, so the
+//! feature would have turned every build of `rembed` into a cdylib build.
+//!
+//! The header in `include/rembed.h` is generated with
+//! `cbindgen --config cbindgen.toml --output include/rembed.h`.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+use rembed::{
+    Embedding, NodeId,
+    dvec::DVec,
+    embedder::{EmbedderOptions, WEmbedder},
+    graph::Graph,
+    sprk::Sprk,
+};
+
+pub const REMBED_OK: i32 = 0;
+pub const REMBED_ERR_NULL_POINTER: i32 = 1;
+pub const REMBED_ERR_INVALID_ARGUMENT: i32 = 2;
+pub const REMBED_ERR_PANIC: i32 = 3;
+
+/// Smallest embedding dimension the API supports
+pub const REMBED_MIN_DIM: usize = 2;
+/// Largest embedding dimension the API supports
+pub const REMBED_MAX_DIM: usize = 16;
+
+/// Opaque graph handle created by [`rembed_graph_parse`].
+pub struct RembedGraph {
+    graph: Graph,
+    dim: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl Into<String>) {
+    let message = message.into().replace('\0', " ");
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(message).ok());
+}
+
+/// Runs `f`, converting errors and panics into status codes.
+fn guard(f: impl FnOnce() -> Result<(), (i32, String)>) -> i32 {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => REMBED_OK,
+        Ok(Err((code, message))) => {
+            set_last_error(message);
+            code
+        }
+        Err(payload) => {
+            set_last_error(panic_message(payload));
+            REMBED_ERR_PANIC
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        format!("panic: {s}")
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        format!("panic: {s}")
+    } else {
+        String::from("panic: unknown payload")
+    }
+}
+
+fn null_error(name: &str) -> (i32, String) {
+    (REMBED_ERR_NULL_POINTER, format!("{name} must not be null"))
+}
+
+macro_rules! dispatch_dim {
+    ($dim:expr, $func:ident, $($args:expr),*) => {
+        match $dim {
+            2 => $func::<2, Sprk<2>>($($args),*),
+            3 => $func::<3, Sprk<3>>($($args),*),
+            4 => $func::<4, Sprk<4>>($($args),*),
+            5 => $func::<5, Sprk<5>>($($args),*),
+            6 => $func::<6, Sprk<6>>($($args),*),
+            7 => $func::<7, Sprk<7>>($($args),*),
+            8 => $func::<8, Sprk<8>>($($args),*),
+            9 => $func::<9, Sprk<9>>($($args),*),
+            10 => $func::<10, Sprk<10>>($($args),*),
+            11 => $func::<11, Sprk<11>>($($args),*),
+            12 => $func::<12, Sprk<12>>($($args),*),
+            13 => $func::<13, Sprk<13>>($($args),*),
+            14 => $func::<14, Sprk<14>>($($args),*),
+            15 => $func::<15, Sprk<15>>($($args),*),
+            16 => $func::<16, Sprk<16>>($($args),*),
+            _ => Err((
+                REMBED_ERR_INVALID_ARGUMENT,
+                format!("unsupported embedding dimension {}", $dim),
+            )),
+        }
+    };
+}
+
+/// Returns the message of the last error on this thread, or null if there was none.
+///
+/// The pointer stays valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn rembed_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|e| {
+        e.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Parses an edge list file for embedding in `dim` dimensions, which has to
+/// lie between [`REMBED_MIN_DIM`] and [`REMBED_MAX_DIM`]. Returns null on
+/// failure.
+///
+/// # Safety
+/// `path` must be a valid nul-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rembed_graph_parse(
+    path: *const c_char,
+    dim: usize,
+    dim_hint: usize,
+) -> *mut RembedGraph {
+    let mut handle = std::ptr::null_mut();
+    guard(|| {
+        if path.is_null() {
+            return Err(null_error("path"));
+        }
+        if !(REMBED_MIN_DIM..=REMBED_MAX_DIM).contains(&dim) {
+            return Err((
+                REMBED_ERR_INVALID_ARGUMENT,
+                format!("unsupported embedding dimension {dim}"),
+            ));
+        }
+        let path = unsafe { CStr::from_ptr(path) }
+            .to_str()
+            .map_err(|e| (REMBED_ERR_INVALID_ARGUMENT, format!("invalid path: {e}")))?;
+        let graph = Graph::parse_from_edge_list_file(path, dim, dim_hint)
+            .map_err(|e| (REMBED_ERR_INVALID_ARGUMENT, e.to_string()))?;
+        handle = Box::into_raw(Box::new(RembedGraph { graph, dim }));
+        Ok(())
+    });
+    handle
+}
+
+/// Number of nodes of a parsed graph, 0 if `graph` is null.
+///
+/// # Safety
+/// `graph` must be null or a handle returned by [`rembed_graph_parse`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rembed_graph_num_nodes(graph: *const RembedGraph) -> usize {
+    unsafe { graph.as_ref() }.map_or(0, |g| g.graph.nodes.len())
+}
+
+/// Frees a graph handle. Passing null is a no-op.
+///
+/// # Safety
+/// `graph` must be null or a handle returned by [`rembed_graph_parse`] that
+/// has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rembed_graph_free(graph: *mut RembedGraph) {
+    if !graph.is_null() {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(graph) })));
+    }
+}
+
+/// Embeds the graph from random initial positions and writes the final
+/// positions row-major into `out_positions`.
+///
+/// # Safety
+/// `graph` must be a valid handle and `out_positions` must point to at least
+/// `rembed_graph_num_nodes(graph) * dim` writable floats.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rembed_embed(
+    graph: *const RembedGraph,
+    dim: usize,
+    seed: u64,
+    max_iterations: usize,
+    out_positions: *mut f32,
+) -> i32 {
+    guard(|| {
+        let graph = unsafe { graph.as_ref() }.ok_or_else(|| null_error("graph"))?;
+        if out_positions.is_null() {
+            return Err(null_error("out_positions"));
+        }
+        if dim != graph.dim {
+            return Err((
+                REMBED_ERR_INVALID_ARGUMENT,
+                format!("graph was parsed for dimension {}, not {dim}", graph.dim),
+            ));
+        }
+        let n = graph.graph.nodes.len();
+        let out = unsafe { std::slice::from_raw_parts_mut(out_positions, n * dim) };
+        let options = EmbedderOptions {
+            max_iterations,
+            ..Default::default()
+        };
+        dispatch_dim!(dim, embed, &graph.graph, seed, options, out)
+    })
+}
+
+fn embed<'a, const D: usize, SI>(
+    graph: &'a Graph,
+    seed: u64,
+    options: EmbedderOptions,
+    out: &mut [f32],
+) -> Result<(), (i32, String)>
+where
+    SI: rembed::query::Embedder<'a, D> + rembed::dyn_embed::EmbedIndex<Vec = DVec<D>>,
+{
+    let mut embedder: WEmbedder<SI> = WEmbedder::random(seed, graph, options);
+    let positions = embedder.embed();
+    for (chunk, pos) in out.chunks_exact_mut(D).zip(positions) {
+        chunk.copy_from_slice(&pos.components);
+    }
+    Ok(())
+}
+
+/// Returns all nodes within `radius` of `node`, using an index freshly built
+/// over `positions` (`n * dim` floats, row-major). `dim` has to be the
+/// dimension the graph was parsed for.
+///
+/// The result is written to `out_ids`/`out_len` and must be released with
+/// [`rembed_ids_free`].
+///
+/// # Safety
+/// `positions` must point to `n * dim` readable floats, `graph` must be a
+/// valid handle and `out_ids`/`out_len` must be writable.
+#[unsafe(no_mangle)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn rembed_query_radius(
+    positions: *const f32,
+    n: usize,
+    dim: usize,
+    graph: *const RembedGraph,
+    node: usize,
+    radius: f64,
+    out_ids: *mut *mut usize,
+    out_len: *mut usize,
+) -> i32 {
+    guard(|| {
+        let graph = unsafe { graph.as_ref() }.ok_or_else(|| null_error("graph"))?;
+        if positions.is_null() {
+            return Err(null_error("positions"));
+        }
+        if out_ids.is_null() || out_len.is_null() {
+            return Err(null_error("out_ids/out_len"));
+        }
+        if dim != graph.dim {
+            return Err((
+                REMBED_ERR_INVALID_ARGUMENT,
+                format!("graph was parsed for dimension {}, not {dim}", graph.dim),
+            ));
+        }
+        if n != graph.graph.nodes.len() {
+            return Err((
+                REMBED_ERR_INVALID_ARGUMENT,
+                format!("expected {} positions, got {n}", graph.graph.nodes.len()),
+            ));
+        }
+        if node >= n {
+            return Err((
+                REMBED_ERR_INVALID_ARGUMENT,
+                format!("node {node} out of range for {n} nodes"),
+            ));
+        }
+        let positions = unsafe { std::slice::from_raw_parts(positions, n * dim) };
+        let ids = dispatch_dim!(dim, query, &graph.graph, positions, node, radius)?;
+        let ids = ids.into_boxed_slice();
+        unsafe {
+            *out_len = ids.len();
+            *out_ids = Box::into_raw(ids) as *mut usize;
+        }
+        Ok(())
+    })
+}
+
+fn query<'a, const D: usize, SI>(
+    graph: &'a Graph,
+    positions: &[f32],
+    node: NodeId,
+    radius: f64,
+) -> Result<Vec<NodeId>, (i32, String)>
+where
+    SI: rembed::query::Embedder<'a, D>,
+{
+    let positions = positions
+        .chunks_exact(D)
+        .map(|chunk| DVec::from_fn(|i| chunk[i]))
+        .collect();
+    let embedding = Embedding { positions, graph };
+    let index = SI::new(embedding.view());
+    let mut results = Vec::new();
+    index.query_radius(*index.position(node), radius, &mut results);
+    Ok(results)
+}
+
+/// Frees an id list returned by [`rembed_query_radius`]. Passing null is a no-op.
+///
+/// # Safety
+/// `ids` and `len` must come from the same successful [`rembed_query_radius`] call.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rembed_ids_free(ids: *mut usize, len: usize) {
+    if !ids.is_null() {
+        let slice = std::ptr::slice_from_raw_parts_mut(ids, len);
+        drop(unsafe { Box::from_raw(slice) });
+    }
+}
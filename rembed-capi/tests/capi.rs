@@ -0,0 +1,107 @@
+//! Loads the built cdylib and drives the C API end to end.
+
+use std::ffi::{CStr, CString, c_char};
+
+use libloading::{Library, Symbol};
+
+type GraphParse = unsafe extern "C" fn(*const c_char, usize, usize) -> *mut u8;
+type GraphNumNodes = unsafe extern "C" fn(*const u8) -> usize;
+type GraphFree = unsafe extern "C" fn(*mut u8);
+type Embed = unsafe extern "C" fn(*const u8, usize, u64, usize, *mut f32) -> i32;
+type QueryRadius = unsafe extern "C" fn(
+    *const f32,
+    usize,
+    usize,
+    *const u8,
+    usize,
+    f64,
+    *mut *mut usize,
+    *mut usize,
+) -> i32;
+type IdsFree = unsafe extern "C" fn(*mut usize, usize);
+type LastError = unsafe extern "C" fn() -> *const c_char;
+
+fn library() -> Library {
+    // Integration tests live in target/<profile>/deps, the cdylib of this
+    // crate next to them or one directory up
+    let exe = std::env::current_exe().unwrap();
+    let deps = exe.parent().unwrap();
+    let name = libloading::library_filename("rembed_capi");
+    let path = [deps.join(&name), deps.parent().unwrap().join(&name)]
+        .into_iter()
+        .find(|p| p.exists())
+        .expect("librembed_capi not found next to the test binary");
+    unsafe { Library::new(path).unwrap() }
+}
+
+#[test]
+fn embed_and_query() {
+    let lib = library();
+    unsafe {
+        let parse: Symbol<GraphParse> = lib.get(b"rembed_graph_parse").unwrap();
+        let num_nodes: Symbol<GraphNumNodes> = lib.get(b"rembed_graph_num_nodes").unwrap();
+        let graph_free: Symbol<GraphFree> = lib.get(b"rembed_graph_free").unwrap();
+        let embed: Symbol<Embed> = lib.get(b"rembed_embed").unwrap();
+        let query: Symbol<QueryRadius> = lib.get(b"rembed_query_radius").unwrap();
+        let ids_free: Symbol<IdsFree> = lib.get(b"rembed_ids_free").unwrap();
+        let last_error: Symbol<LastError> = lib.get(b"rembed_last_error_message").unwrap();
+
+        let path = std::env::temp_dir().join(format!("rembed_capi_{}.edges", std::process::id()));
+        let edges: String = (0..20)
+            .map(|i| format!("{} {}\n", i, (i + 1) % 20))
+            .collect();
+        std::fs::write(&path, edges).unwrap();
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+
+        let dim = 2;
+        let graph = parse(c_path.as_ptr(), dim, dim);
+        std::fs::remove_file(&path).unwrap();
+        assert!(!graph.is_null());
+        let n = num_nodes(graph);
+        assert_eq!(n, 20);
+
+        let mut positions = vec![f32::NAN; n * dim];
+        assert_eq!(embed(graph, dim, 42, 50, positions.as_mut_ptr()), 0);
+        assert!(positions.iter().all(|x| x.is_finite()));
+
+        let mut ids = std::ptr::null_mut();
+        let mut len = 0;
+        let code = query(
+            positions.as_ptr(),
+            n,
+            dim,
+            graph,
+            0,
+            f64::MAX,
+            &mut ids,
+            &mut len,
+        );
+        assert_eq!(code, 0);
+        let mut found = std::slice::from_raw_parts(ids, len).to_vec();
+        found.sort_unstable();
+        assert_eq!(found, (0..n).collect::<Vec<_>>());
+        ids_free(ids, len);
+
+        // Mismatched dimensions are reported instead of crashing
+        assert_eq!(embed(graph, 3, 42, 50, positions.as_mut_ptr()), 2);
+        let message = CStr::from_ptr(last_error()).to_str().unwrap();
+        assert!(message.contains("dimension"), "{message}");
+        // The positions of a 2D graph read as 3D would be misaligned
+        let code = query(positions.as_ptr(), n, 3, graph, 0, 1.0, &mut ids, &mut len);
+        assert_eq!(code, 2);
+        let message = CStr::from_ptr(last_error()).to_str().unwrap();
+        assert!(message.contains("not 3"), "{message}");
+
+        graph_free(graph);
+
+        // Missing files surface as errors
+        let missing = CString::new("/nonexistent/rembed/graph").unwrap();
+        assert!(parse(missing.as_ptr(), dim, dim).is_null());
+        assert!(!last_error().is_null());
+
+        // Dimensions without a Sprk instantiation are rejected up front
+        assert!(parse(c_path.as_ptr(), 32, 32).is_null());
+        let message = CStr::from_ptr(last_error()).to_str().unwrap();
+        assert!(message.contains("dimension 32"), "{message}");
+    }
+}
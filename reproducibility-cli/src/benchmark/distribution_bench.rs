@@ -147,19 +147,14 @@ impl DistributionBenchRunner {
         };
 
         // Get data structures
-        let mut data_structures: Vec<_> = if !self.config.structures.is_empty() {
-            rembed::data_structures(&embedding)
-                .filter(|s| self.config.structures.contains(&s.name()))
-                .collect()
-        } else {
-            rembed::data_structures(&embedding).collect()
-        };
+        let mut data_structures: Vec<_> =
+            rembed::data_structures(&embedding, Some(&self.config.structures)).collect();
         for structure in &mut data_structures {
             structure.set_radius_hint(radius);
         }
 
         println!("Structures to benchmark: {:?}", data_structures.iter().map(|s| s.name()).collect::<Vec<_>>());
-        println!("Available structures: {:?}", rembed::data_structures(&embedding).map(|s| s.name()).collect::<Vec<_>>());
+        println!("Available structures: {:?}", rembed::factories::<D>().iter().filter_map(|f| f.name().map(String::from)).collect::<Vec<_>>());
 
         // Update positions for all structures
         for structure in &mut data_structures {
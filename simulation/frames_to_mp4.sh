@@ -0,0 +1,14 @@
+#!/usr/bin/env bash
+# Combines the frames written by `WEmbedder::export_animation_frames`
+# (e.g. via `cargo run -p simulation -- animate --graph <edge list>`) into an MP4.
+#
+# Usage: ./frames_to_mp4.sh <frame_dir> [output.mp4]
+set -euo pipefail
+
+frame_dir=${1:?usage: $0 <frame_dir> [output.mp4]}
+output=${2:-$frame_dir/animation.mp4}
+
+ffmpeg -y -f concat -i "$frame_dir/frames.ffconcat" \
+    -c:v libx264 -pix_fmt yuv420p -vf "pad=ceil(iw/2)*2:ceil(ih/2)*2" \
+    "$output"
+echo "Wrote $output"
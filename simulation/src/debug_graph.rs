@@ -0,0 +1,76 @@
+//! CSV export of the force pairs of one embedder step, see
+//! [`rembed::WEmbedder::calculate_step_debug_graph`].
+
+use std::io::{self, Write};
+
+use rembed::{NodeId, dvec::DVec, graph::Graph};
+
+/// Writes one row per force pair. Nodes appear with their id in `graph` and,
+/// in the `original_` columns, with their id in the input the graph was
+/// read from, so rows can be joined back to the input file.
+pub fn write_force_pairs<W: Write>(
+    out: &mut W,
+    graph: &Graph,
+    pairs: &[(NodeId, NodeId, DVec<2>)],
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "node,other,original_node,original_other,edge,force_x,force_y,magnitude"
+    )?;
+    for (node, other, force) in pairs {
+        writeln!(
+            out,
+            "{node},{other},{},{},{},{},{},{}",
+            graph.original_id(*node),
+            graph.original_id(*other),
+            rembed::query::Graph::is_connected(graph, *node, *other),
+            force[0],
+            force[1],
+            force.magnitude()
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rembed::{Sprk, embedder::EmbedderOptions, embedder::WEmbedder, graph::Graph};
+
+    use super::write_force_pairs;
+
+    #[test]
+    fn rows_join_back_to_the_input_edges() {
+        // Reducing to the largest component and relabeling move every node
+        let input = [(0, 1), (1, 2), (2, 0), (2, 3), (3, 4), (4, 0), (5, 6)];
+        let (component, _) = Graph::from_edge_list(input.to_vec(), 2, 4)
+            .unwrap()
+            .reduce_to_largest_component();
+        let graph = component.relabel(&[3, 0, 4, 1, 2]);
+
+        let options = EmbedderOptions {
+            max_iterations: 10,
+            ..Default::default()
+        };
+        let mut embedder = WEmbedder::<Sprk<2>>::random(1, &graph, options);
+        embedder.embed();
+        let pairs = embedder.calculate_step_debug_graph();
+        let mut out = Vec::new();
+        write_force_pairs(&mut out, &graph, &pairs).unwrap();
+
+        let csv = String::from_utf8(out).unwrap();
+        let mut edges = 0;
+        for row in csv.lines().skip(1) {
+            let fields: Vec<&str> = row.split(',').collect();
+            let id = |i: usize| fields[i].parse::<usize>().unwrap();
+            assert_eq!(graph.original_id(id(0)), id(2) as u64);
+            assert_eq!(graph.original_id(id(1)), id(3) as u64);
+            let original = (id(2), id(3));
+            let in_input = input
+                .iter()
+                .any(|&(u, v)| original == (u, v) || original == (v, u));
+            assert_eq!(fields[4] == "true", in_input, "{row}");
+            edges += usize::from(in_input);
+        }
+        assert!(edges > 0);
+    }
+}
@@ -1 +1,3 @@
+pub mod debug_graph;
 pub mod radius_reduction;
+pub mod study;
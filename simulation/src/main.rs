@@ -1,118 +1,272 @@
-use rembed::{embedder::EmbedderOptions, *};
-use simulation::radius_reduction::{QueryParams, Statistics};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
-fn main() -> io::Result<()> {
-    let dim = 8;
-    let dim_hint = 8;
+use clap::{Parser, Subcommand, ValueEnum};
+use rembed::{Sprk, embedder::EmbedderOptions, embedder::WEmbedder, graph::Graph};
+use simulation::debug_graph::write_force_pairs;
+use simulation::radius_reduction::SplitParams;
+use simulation::study::{StudyConfig, run_study};
 
-    // let graph = "../data/generated/graphs/19_girg_n-1000_deg-15_dim-4_ple-2.2_alpha-inf_wseed-12_pseed-130_sseed-1400";
-    // let graph = "../data/generated/graphs/55_girg_n-10000_deg-15_dim-2_ple-2.2_alpha-inf_wseed-12_pseed-130_sseed-1400";
-    let graph = "../data/generated/graphs/109_girg_n-100000_deg-15_dim-2_ple-2.2_alpha-inf_wseed-12_pseed-130_sseed-1400";
-    // let graph = "../data/generated/graphs/1217_girg_n-1000000_deg-15_dim-4_ple-2.5_alpha-inf_wseed-12_pseed-130_sseed-1400";
-    // let graph = "../data/generated/graphs/2263_girg_n-1000000_deg-15_dim-2_ple-2.2_alpha-inf_wseed-12_pseed-130_sseed-1400";
-    let graph = graph::Graph::parse_from_edge_list_file(graph, dim, dim_hint)?;
+/// Number of weight classes in the graph summary printed on startup
+const WEIGHT_CLASSES: usize = 8;
 
+/// Number of worst embedded nodes printed after embedding
+const WORST_NODES: usize = 10;
+
+#[derive(Parser)]
+#[command(name = "simulation", about = "Pruning experiments on embedded graphs")]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Count distance calculations of the pruning variants per dimension and
+    /// split configuration
+    Study(StudyArgs),
+    /// Embed a graph in 2D and write one PNG frame per logged iteration. Turn
+    /// them into a video with `./frames_to_mp4.sh <output>`.
+    Animate {
+        /// Edge list of the graph
+        #[arg(long)]
+        graph: PathBuf,
+        /// Directory for the frames
+        #[arg(long, default_value = "animation")]
+        output: String,
+        #[arg(long, default_value = "30")]
+        fps: usize,
+        #[arg(long, default_value = "500")]
+        iterations: usize,
+        #[arg(long, default_value = "42")]
+        seed: u64,
+        /// After embedding, re-seed the worst embedded nodes and continue for
+        /// another `iterations` steps this many times
+        #[arg(long, default_value = "0")]
+        resample_rounds: usize,
+        /// Number of worst embedded nodes re-seeded per round
+        #[arg(long, default_value = "10")]
+        resample_nodes: usize,
+        /// Finish with one more step that writes the force of every
+        /// attracting and repelling pair to `<output>/debug_graph.csv`
+        #[arg(long)]
+        debug_graph: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Split {
+    Element,
+    Spatial,
+}
+
+#[derive(clap::Args)]
+struct StudyArgs {
+    /// Edge list files of the graphs to study
+    #[arg(long, num_args = 1..)]
+    graph: Vec<PathBuf>,
+    /// Ids of generated graphs, resolved to `<graph-dir>/<id>_*`
+    #[arg(long, value_delimiter = ',')]
+    graph_id: Vec<i64>,
+    #[arg(long, default_value = "../data/generated/graphs")]
+    graph_dir: PathBuf,
+    /// Embedding dimensions to test
+    #[arg(long, value_delimiter = ',', default_value = "2,4,6,8,10,12,14,16")]
+    dims: Vec<usize>,
+    /// Dimension and latent dimension hint the node weights are computed for
+    #[arg(long, default_value = "8")]
+    weight_dim: usize,
+    /// Split strategies of the pruning tree
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "element")]
+    split: Vec<Split>,
+    /// Bucket counts for element splits
+    #[arg(long, value_delimiter = ',', default_value = "2")]
+    buckets: Vec<usize>,
+    /// Bucket resolutions (buckets per unit) for spatial splits
+    #[arg(long, value_delimiter = ',', default_value = "2")]
+    resolutions: Vec<f32>,
+    /// Number of sampled query nodes per graph, all nodes if omitted
+    #[arg(long)]
+    queries: Option<usize>,
+    /// Seed for the initial embedding and the query sample
+    #[arg(long, default_value = "42")]
+    seed: u64,
+    #[arg(long, default_value = "500")]
+    iterations: usize,
+    /// CSV file for the results
+    #[arg(long, short, default_value = "study.csv")]
+    output: PathBuf,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    match Cli::parse().command {
+        Commands::Study(args) => study(args),
+        Commands::Animate {
+            graph,
+            output,
+            fps,
+            iterations,
+            seed,
+            resample_rounds,
+            resample_nodes,
+            debug_graph,
+        } => Ok(animate(
+            &graph,
+            &output,
+            fps,
+            iterations,
+            seed,
+            (resample_rounds, resample_nodes),
+            debug_graph,
+        )?),
+    }
+}
+
+fn study(args: StudyArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut paths = args.graph;
+    for id in args.graph_id {
+        paths.push(resolve_graph_id(&args.graph_dir, id)?);
+    }
+    if paths.is_empty() {
+        return Err("no graphs given, use --graph or --graph-id".into());
+    }
+
+    let mut graphs = Vec::new();
+    for path in paths {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let graph = Graph::parse_from_edge_list_file(
+            &path.to_string_lossy(),
+            args.weight_dim,
+            args.weight_dim,
+        )?;
+        print_graph_summary(&name, &graph);
+        graphs.push((name, graph));
+    }
+
+    let mut splits = Vec::new();
+    for split in args.split {
+        match split {
+            Split::Element => splits.extend(
+                args.buckets
+                    .iter()
+                    .map(|&num_buckets| SplitParams::Element { num_buckets }),
+            ),
+            Split::Spatial => splits.extend(
+                args.resolutions
+                    .iter()
+                    .map(|&resolution| SplitParams::Spatial { resolution }),
+            ),
+        }
+    }
+
+    let config = StudyConfig {
+        dims: args.dims,
+        splits,
+        queries: args.queries,
+        seed: args.seed,
+        max_iterations: args.iterations,
+        weight_dim: args.weight_dim,
+    };
+    let mut out = BufWriter::new(File::create(&args.output)?);
+    run_study(&graphs, &config, &mut out)?;
+    println!("Wrote {}", args.output.display());
+    Ok(())
+}
+
+/// Prints the size of `graph` and its edges per weight class
+fn print_graph_summary(name: &str, graph: &Graph) {
     println!(
-        "D, normal, reduction_radius, reduction_snn, reduction_both, snn_best, both_best, snn_with_radius_reduction, snn_with_radius_reduction_best_dim, pruned_subtrees"
+        "{name}: {} nodes, {} edges",
+        graph.nodes.len(),
+        graph.edges.len()
     );
-    embedd_and_calc_stats::<2>(&graph);
-    // embedd_and_calc_stats::<3>(&graph);
-    embedd_and_calc_stats::<4>(&graph);
-    // embedd_and_calc_stats::<5>(&graph);
-    embedd_and_calc_stats::<6>(&graph);
-    // embedd_and_calc_stats::<7>(&graph);
-    embedd_and_calc_stats::<8>(&graph);
-    // embedd_and_calc_stats::<9>(&graph);
-    embedd_and_calc_stats::<10>(&graph);
-    // embedd_and_calc_stats::<11>(&graph);
-    embedd_and_calc_stats::<12>(&graph);
-    // embedd_and_calc_stats::<13>(&graph);
-    embedd_and_calc_stats::<14>(&graph);
-    // embedd_and_calc_stats::<15>(&graph);
-    embedd_and_calc_stats::<16>(&graph);
-    // embedd_and_calc_stats::<17>(&graph);
-    // embedd_and_calc_stats::<18>(&graph);
-    // embedd_and_calc_stats::<19>(&graph);
-    // embedd_and_calc_stats::<20>(&graph);
-    // embedd_and_calc_stats::<21>(&graph);
-    // embedd_and_calc_stats::<22>(&graph);
-    // embedd_and_calc_stats::<23>(&graph);
-    // embedd_and_calc_stats::<24>(&graph);
+    println!("{}", graph.weight_class_edge_counts(WEIGHT_CLASSES));
+}
 
-    Ok(())
+/// Finds the generated graph file `<dir>/<id>_...`
+fn resolve_graph_id(dir: &Path, id: i64) -> io::Result<PathBuf> {
+    let prefix = format!("{id}_");
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+        {
+            return Ok(path);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no graph with id {id} in {}", dir.display()),
+    ))
+}
+
+/// Prints the nodes with the lowest local f1 score
+fn print_worst_nodes(embedder: &WEmbedder<Sprk<2>>, graph: &Graph) {
+    let per_node = embedder.per_node_f1();
+    let mean = per_node.iter().sum::<f64>() / per_node.len().max(1) as f64;
+    println!("mean node f1 {mean:.4}, worst nodes:");
+    for (node, f1) in embedder.worst_embedded_nodes(WORST_NODES) {
+        println!(
+            "  node {node}: f1 {f1:.4}, weight {:.3}, degree {}",
+            graph.nodes[node].weight,
+            rembed::query::Graph::neighbors(graph, node).len()
+        );
+    }
 }
 
-fn embedd_and_calc_stats<const D: usize>(graph: &graph::Graph) {
+fn animate(
+    graph: &Path,
+    output: &str,
+    fps: usize,
+    iterations: usize,
+    seed: u64,
+    (resample_rounds, resample_nodes): (usize, usize),
+    debug_graph: bool,
+) -> io::Result<()> {
+    let name = graph.file_name().unwrap_or_default().to_string_lossy();
+    let graph = Graph::parse_from_edge_list_file(&graph.to_string_lossy(), 2, 4)?;
+    print_graph_summary(&name, &graph);
     let options = EmbedderOptions {
-        max_iterations: 500,
+        max_iterations: iterations,
         ..Default::default()
     };
-    // let mut embedder: embedder::WEmbedder<Sprk<_>, D> =
-    //     embedder::WEmbedder::random(42, graph, options);
-    let mut embedder = embedder::WEmbedder::<DynamicQuery<D, Sprk<D>>>::random(42, graph, options);
-
-    embedder.embed_with_callback(|e| {
-        let i = e.iteration();
-        if i % 10 == 0 && i > 0 {
-            // eprintln!("Iteration {i}");
+    let mut embedder = WEmbedder::<Sprk<2>>::random(seed, &graph, options);
+    embedder.embed();
+    print_worst_nodes(&embedder, &graph);
+    for round in 1..=resample_rounds {
+        let reseeded = embedder.reseed_worst_nodes(resample_nodes);
+        println!("round {round}: re-seeded {reseeded} nodes");
+        for _ in 0..iterations {
+            embedder.calculate_step();
         }
-    });
-    let pos: &[rembed::dvec::DVec<D>] = embedder.positions();
-
-    let analysis = simulation::radius_reduction::DimReduction::new(pos.to_vec());
-
-    let mut stats_normal = Statistics::default();
-    let mut stats_reduced_radius = Statistics::default();
-    let mut stats_snn = Statistics::default();
-    let mut stats_both = Statistics::default();
-    let mut stats_snn_best_dim = Statistics::default();
-    let mut stats_both_best_dim = Statistics::default();
-    let mut stats_snn_with_radius_reduction = Statistics::default();
-    let mut stats_snn_with_radius_reduction_best_dim = Statistics::default();
-
-    for i in 0..pos.len() {
-        let mut results = Vec::new();
-        let params = QueryParams::new(false, false, false, false, false);
-        analysis.query(i, 1., &mut results, &mut stats_normal, &params);
-        let params = QueryParams::new(true, false, false, false, false);
-        analysis.query(i, 1., &mut results, &mut stats_reduced_radius, &params);
-        let params = QueryParams::new(false, true, false, false, false);
-        analysis.query(i, 1., &mut results, &mut stats_snn, &params);
-        let params = QueryParams::new(true, true, false, false, false);
-        analysis.query(i, 1., &mut results, &mut stats_both, &params);
-        let params = QueryParams::new(false, true, true, false, false);
-        analysis.query(i, 1., &mut results, &mut stats_snn_best_dim, &params);
-        let params = QueryParams::new(true, true, true, false, false);
-        analysis.query(i, 1., &mut results, &mut stats_both_best_dim, &params);
-        let params = QueryParams::new(true, true, false, false, true);
-        analysis.query(
-            i,
-            1.,
-            &mut results,
-            &mut stats_snn_with_radius_reduction,
-            &params,
-        );
-        let params = QueryParams::new(true, true, true, false, true);
-        analysis.query(
-            i,
-            1.,
-            &mut results,
-            &mut stats_snn_with_radius_reduction_best_dim,
-            &params,
-        );
+        print_worst_nodes(&embedder, &graph);
     }
-    println!(
-        "{D}, {normal}, {reduction_radius}, {reduction_snn}, {reduction_both}, {snn_best}, {both_best}, {snn_with_radius_reduction}, {snn_with_radius_reduction_best_dim}, {pruned_trees}",
-        D = D,
-        normal = stats_normal.num_comparionsons,
-        reduction_radius = stats_reduced_radius.num_comparionsons,
-        reduction_snn = stats_snn.num_comparionsons,
-        reduction_both = stats_both.num_comparionsons,
-        snn_best = stats_snn_best_dim.num_comparionsons,
-        both_best = stats_both_best_dim.num_comparionsons,
-        snn_with_radius_reduction = stats_snn_with_radius_reduction.num_comparionsons,
-        snn_with_radius_reduction_best_dim =
-            stats_snn_with_radius_reduction_best_dim.num_comparionsons,
-        pruned_trees = stats_normal.pruned_trees,
-    );
+    if debug_graph {
+        fs::create_dir_all(output)?;
+        write_debug_graph(
+            &mut embedder,
+            &graph,
+            &Path::new(output).join("debug_graph.csv"),
+        )?;
+    }
+    embedder.export_animation_frames(output, fps)?;
+    println!("Wrote {} frames to {output}/", embedder.history().len());
+    Ok(())
+}
+
+/// Runs a step with [`WEmbedder::calculate_step_debug_graph`] and writes one
+/// row per force pair
+fn write_debug_graph(
+    embedder: &mut WEmbedder<Sprk<2>>,
+    graph: &Graph,
+    path: &Path,
+) -> io::Result<()> {
+    let pairs = embedder.calculate_step_debug_graph();
+    let mut out = BufWriter::new(File::create(path)?);
+    write_force_pairs(&mut out, graph, &pairs)?;
+    out.flush()?;
+    println!("Wrote {} force pairs to {}", pairs.len(), path.display());
+    Ok(())
 }
@@ -1,6 +1,24 @@
 use rembed::dvec::DVec;
 use std::ops::Mul;
 
+/// How a [`DimReduction`] splits a layer along its dimension
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitParams {
+    /// Into `num_buckets` buckets with the same number of points
+    Element { num_buckets: usize },
+    /// Into buckets of width `1 / resolution`
+    Spatial { resolution: f32 },
+}
+
+impl SplitParams {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SplitParams::Element { .. } => "element",
+            SplitParams::Spatial { .. } => "spatial",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum SplitStrategy {
     Element {
@@ -32,27 +50,27 @@ pub enum Layer<const D: usize> {
 }
 
 const LEAFSIZE: usize = 150;
+/// Spatial splits into fewer buckets are not worth it, the layer becomes a leaf
+const MIN_SPATIAL_BUCKETS: usize = 50;
 
 impl<const D: usize> Layer<D> {
-    fn new(mut positions: Vec<DVec<D>>, depth: usize) -> Self {
+    fn new(mut positions: Vec<DVec<D>>, depth: usize, split: SplitParams) -> Self {
         let dim = depth % D;
         if positions.len() <= LEAFSIZE {
             return Self::BruteForce { positions, dim };
         }
-        // dbg!(positions.len(), dim);
 
         // Sort by the specified dimension
         positions.sort_unstable_by(|a, b| a[dim].partial_cmp(&b[dim]).unwrap());
 
-        const NUM_BUCKETS: usize = 2;
-        const RESOLUTION: f32 = 2.;
-
-        let element_split = Self::element_split(positions.clone(), dim, NUM_BUCKETS, depth);
-
-        let _spatial_split = Self::spatial_split(positions, dim, RESOLUTION, depth);
-
-        element_split
-        // spatial_split
+        match split {
+            SplitParams::Element { num_buckets } => {
+                Self::element_split(positions, dim, num_buckets, depth, split)
+            }
+            SplitParams::Spatial { resolution } => {
+                Self::spatial_split(positions, dim, resolution, depth, split)
+            }
+        }
     }
 
     fn element_split(
@@ -60,6 +78,7 @@ impl<const D: usize> Layer<D> {
         dim: usize,
         num_buckets: usize,
         depth: usize,
+        split: SplitParams,
     ) -> Self {
         let mut children = Vec::new();
         let total_len = positions.len();
@@ -76,7 +95,7 @@ impl<const D: usize> Layer<D> {
             if start < end {
                 let bucket_positions = positions[start..end].to_vec();
 
-                let child = Layer::new(bucket_positions, depth + 1);
+                let child = Layer::new(bucket_positions, depth + 1, split);
                 bucket_starts.push((positions[start][dim], positions[end - 1][dim]));
                 children.push(child);
             }
@@ -90,16 +109,19 @@ impl<const D: usize> Layer<D> {
         }
     }
 
-    fn spatial_split(positions: Vec<DVec<D>>, dim: usize, resolution: f32, depth: usize) -> Self {
+    fn spatial_split(
+        positions: Vec<DVec<D>>,
+        dim: usize,
+        resolution: f32,
+        depth: usize,
+        split: SplitParams,
+    ) -> Self {
         let min = positions[0][dim].mul(resolution).floor();
         let max = positions.last().unwrap()[dim].mul(resolution).floor();
         let num_buckets = (max - min) as usize + 1;
 
-        if num_buckets <= 50 {
-            return Self::BruteForce {
-                positions,
-                dim: depth,
-            };
+        if num_buckets <= MIN_SPATIAL_BUCKETS {
+            return Self::BruteForce { positions, dim };
         }
 
         let mut buckets = vec![Vec::new(); num_buckets];
@@ -123,7 +145,7 @@ impl<const D: usize> Layer<D> {
         let mut children = Vec::new();
 
         for bucket in buckets.into_iter() {
-            let child = Layer::new(bucket, depth + 1);
+            let child = Layer::new(bucket, depth + 1, split);
             children.push(child);
         }
 
@@ -139,6 +161,7 @@ impl<const D: usize> Layer<D> {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
 pub struct QueryParams {
     use_radius_reduction: bool,
     use_snn: bool,
@@ -172,22 +195,19 @@ pub struct Statistics {
     pub num_reductions: usize,
     pub pruned_trees: usize,
     pub ground_truth_comparisons: usize,
+    /// Children that the reduced radius visits although the plain interval
+    /// check skips them. The reduced radius should always be tighter, so
+    /// anything but zero points at a bug in the bucket bounds.
+    pub missed_prunings: usize,
 }
 
 impl<const D: usize> DimReduction<D> {
-    pub fn new(positions: Vec<DVec<D>>) -> Self {
-        let root = Layer::new(positions.clone(), 0);
+    pub fn new(positions: Vec<DVec<D>>, split: SplitParams) -> Self {
+        let root = Layer::new(positions.clone(), 0, split);
 
         Self { positions, root }
     }
-    pub fn query(
-        &self,
-        id: usize,
-        radius: f32,
-        _results: &mut [usize],
-        stats: &mut Statistics,
-        params: &QueryParams,
-    ) {
+    pub fn query(&self, id: usize, radius: f32, stats: &mut Statistics, params: &QueryParams) {
         self.query_impl(
             self.positions[id],
             radius,
@@ -207,7 +227,6 @@ impl<const D: usize> DimReduction<D> {
         statistics: &mut Statistics,
         params: &QueryParams,
     ) {
-        // println!("final_radius: {}", 1. - spatial_offset.magnitude());
         if let Layer::BruteForce { positions, dim } = layer {
             if params.use_snn {
                 let parameter_dim = *dim;
@@ -231,11 +250,6 @@ impl<const D: usize> DimReduction<D> {
                         };
                         if projected_distance <= snn_radius {
                             num_comparisons += 1;
-                            // if dim == parameter_dim
-                            //     && (pos - *p).magnitude_squared() < radius.powi(2)
-                            // {
-                            //     statistics.ground_truth_comparisons += 1;
-                            // }
                         }
                     }
                     if min_checks > num_comparisons {
@@ -246,7 +260,6 @@ impl<const D: usize> DimReduction<D> {
             } else {
                 statistics.num_comparionsons += positions.len();
             }
-            // return;
         }
 
         if let Layer::Split {
@@ -257,9 +270,7 @@ impl<const D: usize> DimReduction<D> {
         } = layer
         {
             let p = pos[*dim];
-            // dbg!(p);
             for ((start, end), child) in bucket_starts.iter().zip(children.iter()) {
-                // dbg!(start, end);
                 let mut new_spatial_offset = spatial_offset;
                 if *end <= p {
                     new_spatial_offset[*dim] = p - *end;
@@ -272,14 +283,6 @@ impl<const D: usize> DimReduction<D> {
                 if new_spatial_offset != spatial_offset {
                     statistics.num_reductions += 1;
                 }
-                // let before_first_or_after_last =
-                //     (p < *start && i == 0) || (p > *end && i == bucket_starts.len() - 1);
-                // // We know that we can't be in this set
-                // if before_first_or_after_last {
-                //     return;
-                // }
-                // assert!(!before_first_or_after_last);
-
                 let should_recurse = (p + radius >= *start && p - radius <= *start)
                     || (p + radius >= *end && p - radius <= *end)
                     || (p >= *start && p <= *end);
@@ -288,20 +291,11 @@ impl<const D: usize> DimReduction<D> {
                     statistics.pruned_trees += 1;
                 }
                 if should_recurse_red && !should_recurse {
-                    // println!("skipped check at depth {depth}");
-                    dbg!(
-                        pos,
-                        dim,
-                        new_spatial_offset,
-                        pos - new_spatial_offset,
-                        start,
-                        end
-                    );
+                    statistics.missed_prunings += 1;
                 }
                 if should_recurse_red && params.use_radius_reduction
                     || should_recurse && !params.use_radius_reduction
                 {
-                    // if should_recurse {
                     self.query_impl(pos, radius, new_spatial_offset, child, statistics, params);
                 }
             }
@@ -0,0 +1,232 @@
+//! Dimensionality versus pruning study.
+//!
+//! Embeds each graph in every requested dimension, builds a [`DimReduction`]
+//! tree per split configuration and counts the distance calculations of a
+//! sample of neighbour queries for each pruning variant. The results are
+//! written as CSV, preceded by `#` lines that record every parameter, so a
+//! figure can be regenerated from the file alone.
+
+use std::io::{self, Write};
+
+use rand::SeedableRng;
+use rand::rngs::SmallRng;
+use rembed::{DynamicQuery, Sprk, dvec::DVec, embedder::EmbedderOptions, embedder::WEmbedder};
+
+use crate::radius_reduction::{DimReduction, QueryParams, SplitParams, Statistics};
+
+/// Dimensions the study is compiled for
+pub const SUPPORTED_DIMS: [usize; 11] = [2, 3, 4, 5, 6, 7, 8, 10, 12, 14, 16];
+
+#[derive(Clone, Debug)]
+pub struct StudyConfig {
+    pub dims: Vec<usize>,
+    pub splits: Vec<SplitParams>,
+    /// Number of query nodes per graph, all nodes if `None`
+    pub queries: Option<usize>,
+    pub seed: u64,
+    pub max_iterations: usize,
+    /// Dimension the node weights of the graphs were computed for. The graphs
+    /// are loaded by the caller, so this is only recorded in the header.
+    pub weight_dim: usize,
+}
+
+/// The pruning variants, named after the CSV columns of the original figures
+pub fn variants() -> [(&'static str, QueryParams); 8] {
+    [
+        (
+            "normal",
+            QueryParams::new(false, false, false, false, false),
+        ),
+        (
+            "reduction_radius",
+            QueryParams::new(true, false, false, false, false),
+        ),
+        (
+            "reduction_snn",
+            QueryParams::new(false, true, false, false, false),
+        ),
+        (
+            "reduction_both",
+            QueryParams::new(true, true, false, false, false),
+        ),
+        (
+            "snn_best",
+            QueryParams::new(false, true, true, false, false),
+        ),
+        (
+            "both_best",
+            QueryParams::new(true, true, true, false, false),
+        ),
+        (
+            "snn_with_radius_reduction",
+            QueryParams::new(true, true, false, false, true),
+        ),
+        (
+            "snn_with_radius_reduction_best_dim",
+            QueryParams::new(true, true, true, false, true),
+        ),
+    ]
+}
+
+/// Runs the study on `graphs` (name and graph) and writes the CSV to `out`.
+pub fn run_study<W: Write>(
+    graphs: &[(String, rembed::graph::Graph)],
+    config: &StudyConfig,
+    out: &mut W,
+) -> io::Result<()> {
+    if let Some(&dim) = config.dims.iter().find(|d| !SUPPORTED_DIMS.contains(d)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("dimension {dim} is not supported, use one of {SUPPORTED_DIMS:?}"),
+        ));
+    }
+
+    writeln!(out, "# rembed simulation study")?;
+    let names: Vec<&str> = graphs.iter().map(|(name, _)| name.as_str()).collect();
+    writeln!(out, "# graphs: {}", names.join(" "))?;
+    writeln!(out, "# dims: {:?}", config.dims)?;
+    writeln!(out, "# splits: {:?}", config.splits)?;
+    match config.queries {
+        Some(queries) => writeln!(out, "# queries: {queries}")?,
+        None => writeln!(out, "# queries: all")?,
+    }
+    writeln!(out, "# seed: {}", config.seed)?;
+    writeln!(out, "# max_iterations: {}", config.max_iterations)?;
+    writeln!(out, "# weight_dim: {}", config.weight_dim)?;
+    writeln!(
+        out,
+        "graph,nodes,dim,split,split_param,variant,distance_calculations,pruned_subtrees,missed_prunings"
+    )?;
+
+    for (name, graph) in graphs {
+        for &dim in &config.dims {
+            let rows = match dim {
+                2 => study_dim::<2>(graph, config),
+                3 => study_dim::<3>(graph, config),
+                4 => study_dim::<4>(graph, config),
+                5 => study_dim::<5>(graph, config),
+                6 => study_dim::<6>(graph, config),
+                7 => study_dim::<7>(graph, config),
+                8 => study_dim::<8>(graph, config),
+                10 => study_dim::<10>(graph, config),
+                12 => study_dim::<12>(graph, config),
+                14 => study_dim::<14>(graph, config),
+                16 => study_dim::<16>(graph, config),
+                _ => unreachable!("checked against SUPPORTED_DIMS"),
+            };
+            for (split, variant, stats) in rows {
+                let split_param = match split {
+                    SplitParams::Element { num_buckets } => num_buckets.to_string(),
+                    SplitParams::Spatial { resolution } => resolution.to_string(),
+                };
+                writeln!(
+                    out,
+                    "{name},{},{dim},{},{split_param},{variant},{},{},{}",
+                    graph.nodes.len(),
+                    split.name(),
+                    stats.num_comparionsons,
+                    stats.pruned_trees,
+                    stats.missed_prunings
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn study_dim<const D: usize>(
+    graph: &rembed::graph::Graph,
+    config: &StudyConfig,
+) -> Vec<(SplitParams, &'static str, Statistics)> {
+    let options = EmbedderOptions {
+        max_iterations: config.max_iterations,
+        ..Default::default()
+    };
+    let mut embedder = WEmbedder::<DynamicQuery<D, Sprk<D>>>::random(config.seed, graph, options);
+    embedder.embed();
+    let positions: Vec<DVec<D>> = embedder.positions().to_vec();
+
+    let n = positions.len();
+    let query_nodes: Vec<usize> = match config.queries {
+        Some(queries) if queries < n => {
+            let mut rng = SmallRng::seed_from_u64(config.seed);
+            rand::seq::index::sample(&mut rng, n, queries).into_vec()
+        }
+        _ => (0..n).collect(),
+    };
+
+    let mut rows = Vec::new();
+    for &split in &config.splits {
+        let analysis = DimReduction::new(positions.clone(), split);
+        for (variant, params) in variants() {
+            let mut stats = Statistics::default();
+            for &node in &query_nodes {
+                analysis.query(node, 1., &mut stats, &params);
+            }
+            rows.push((split, variant, stats));
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn study_on_random_geometric_graph() {
+        // 1000 uniform points in a square, connected below a fixed distance
+        let mut rng = SmallRng::seed_from_u64(1);
+        let points: Vec<[f64; 2]> = (0..1000)
+            .map(|_| [rng.random_range(0.0..1.0), rng.random_range(0.0..1.0)])
+            .collect();
+        let mut edges = Vec::new();
+        for u in 0..points.len() {
+            for v in u + 1..points.len() {
+                let (dx, dy) = (points[u][0] - points[v][0], points[u][1] - points[v][1]);
+                if dx * dx + dy * dy < 0.05 * 0.05 {
+                    edges.push((u, v));
+                }
+            }
+        }
+        let graph = rembed::graph::Graph::from_edge_list(edges, 2, 2).unwrap();
+        let n = graph.nodes.len();
+
+        let config = StudyConfig {
+            dims: vec![2, 4],
+            splits: vec![
+                SplitParams::Element { num_buckets: 2 },
+                SplitParams::Spatial { resolution: 2. },
+            ],
+            queries: Some(200),
+            seed: 42,
+            max_iterations: 50,
+            weight_dim: 2,
+        };
+        let mut out = Vec::new();
+        run_study(&[("rgg".to_string(), graph)], &config, &mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+
+        assert!(csv.contains("# seed: 42\n"));
+        let rows: Vec<Vec<&str>> = csv
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .skip(1)
+            .map(|line| line.split(',').collect())
+            .collect();
+        assert_eq!(rows.len(), 2 * 2 * variants().len());
+        for chunk in rows.chunks(variants().len()) {
+            let count = |variant: &str| -> usize {
+                chunk.iter().find(|row| row[5] == variant).unwrap()[6]
+                    .parse()
+                    .unwrap()
+            };
+            assert_eq!(chunk[0][1], n.to_string());
+            assert!(count("normal") > 0);
+            assert!(count("reduction_radius") <= count("normal"));
+            assert!(count("reduction_snn") <= count("normal"));
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
@@ -42,7 +43,7 @@ impl<'a, const D: usize> crate::query::Graph for AGrid<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -132,13 +133,13 @@ impl<const D: usize> Layer<D> {
     }
 }
 impl<'a, const D: usize> AGrid<'a, D> {
-    pub fn new(embedding: &Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let mut line_lsh = AGrid {
-            positions: embedding.positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             layer: Layer::Snn(Default::default()),
         };
-        line_lsh.update_positions(&embedding.positions, None);
+        line_lsh.update_positions(embedding.positions, None);
         line_lsh
     }
     fn light_nn(&self, index: usize, radius: f64, results: &mut Vec<NodeId>) {
@@ -230,7 +231,7 @@ impl<const D: usize> SpatialIndex<D> for AGrid<'_, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for AGrid<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         Self::new(embedding)
     }
 }
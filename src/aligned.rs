@@ -0,0 +1,134 @@
+//! Joint layout of two graphs, e.g. two snapshots of an evolving network, in
+//! which the nodes present in both land at comparable positions.
+
+use crate::{
+    DynamicQuery, NodeId, Sprk,
+    dvec::DVec,
+    embedder::{EmbedderOptions, WEmbedder},
+    graph::Graph,
+};
+
+/// Springs between the nodes of two graphs that are embedded as one disjoint
+/// union, the nodes of the second graph following those of the first. The
+/// springs are a force term of their own rather than edges, so they change
+/// neither the weights nor the neighbours the spatial index sees.
+#[derive(Clone, Debug)]
+pub(crate) struct Anchors {
+    /// Node of the first graph and node of the second, both in the union
+    pub(crate) pairs: Vec<(NodeId, NodeId)>,
+    /// First node of the second graph
+    pub(crate) split: NodeId,
+}
+
+impl Anchors {
+    /// Only nodes of the same graph repel each other, otherwise anchored
+    /// nodes could not meet
+    pub(crate) fn same_graph(&self, u: NodeId, v: NodeId) -> bool {
+        (u < self.split) == (v < self.split)
+    }
+}
+
+/// Result of [`embed_aligned`]
+#[derive(Clone, Debug)]
+pub struct AlignedEmbedding<const D: usize> {
+    pub positions_a: Vec<DVec<D>>,
+    pub positions_b: Vec<DVec<D>>,
+    /// Distance between the two nodes of every anchor, in the order of the
+    /// anchors
+    pub residuals: Vec<f64>,
+}
+
+/// Embeds `graph_a` and `graph_b` into the same space. Each graph keeps its
+/// own attraction and repulsion, the two do not repel each other, and every
+/// anchor `(a, b)` pulls node `a` of `graph_a` and node `b` of `graph_b`
+/// together with [`EmbedderOptions::anchor_strength`]. The initial positions
+/// are drawn from `seed` as in [`WEmbedder::random`].
+pub fn embed_aligned<const D: usize>(
+    seed: u64,
+    graph_a: &Graph,
+    graph_b: &Graph,
+    anchors: &[(NodeId, NodeId)],
+    options: EmbedderOptions,
+) -> AlignedEmbedding<D> {
+    let split = graph_a.nodes.len();
+    for &(a, b) in anchors {
+        assert!(
+            a < split && b < graph_b.nodes.len(),
+            "anchor ({a}, {b}) is not a pair of nodes of the two graphs"
+        );
+    }
+    let union = disjoint_union(graph_a, graph_b);
+    let springs = Anchors {
+        pairs: anchors.iter().map(|&(a, b)| (a, split + b)).collect(),
+        split,
+    };
+    let mut embedder: WEmbedder<DynamicQuery<D, Sprk<D>>> =
+        WEmbedder::random(seed, &union, options).with_anchors(springs);
+    let mut positions_a = embedder.embed();
+    let positions_b = positions_a.split_off(split);
+    let residuals = anchors
+        .iter()
+        .map(|&(a, b)| positions_a[a].distance(&positions_b[b]) as f64)
+        .collect();
+    AlignedEmbedding {
+        positions_a,
+        positions_b,
+        residuals,
+    }
+}
+
+/// `first` followed by `second`, both with the weights they had on their own
+fn disjoint_union(first: &Graph, second: &Graph) -> Graph {
+    let offset = first.nodes.len();
+    let (weights, neighbors) = first
+        .nodes
+        .iter()
+        .map(|node| (node.weight, node.neighbor_ids().to_vec()))
+        .chain(second.nodes.iter().map(|node| {
+            let neighbors = node.neighbor_ids().iter().map(|v| offset + v).collect();
+            (node.weight, neighbors)
+        }))
+        .unzip();
+    Graph::from_adjacency(weights, neighbors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Embedding, graph::grid, query::Embedder};
+
+    fn f1(positions: Vec<DVec<2>>, graph: &Graph) -> f64 {
+        Embedding { positions, graph }.f1()
+    }
+
+    /// F1 score of embedding `graph` on its own
+    fn direct_f1(graph: &Graph) -> f64 {
+        let mut embedder: WEmbedder<Sprk<2>> =
+            WEmbedder::random(0, graph, EmbedderOptions::default());
+        f1(embedder.embed(), graph)
+    }
+
+    #[test]
+    fn fully_anchored_copies_coincide() {
+        let graph = grid(15);
+        let anchors: Vec<_> = (0..graph.nodes.len()).map(|u| (u, u)).collect();
+        let aligned = embed_aligned::<2>(0, &graph, &graph, &anchors, EmbedderOptions::default());
+        let worst = aligned.residuals.iter().copied().fold(0.0, f64::max);
+        assert!(worst < 0.05, "largest residual {worst}");
+        assert!(f1(aligned.positions_a, &graph) > 0.9 * direct_f1(&graph));
+    }
+
+    #[test]
+    fn unanchored_halves_still_converge() {
+        let graph = grid(15);
+        let n = graph.nodes.len();
+        let anchors: Vec<_> = (0..n / 2).map(|u| (u, u)).collect();
+        let aligned = embed_aligned::<2>(0, &graph, &graph, &anchors, EmbedderOptions::default());
+        assert_eq!(aligned.positions_b.len(), n);
+        let worst = aligned.residuals.iter().copied().fold(0.0, f64::max);
+        assert!(worst < 0.05, "largest residual {worst}");
+        let direct = direct_f1(&graph);
+        assert!(f1(aligned.positions_a, &graph) > 0.9 * direct);
+        assert!(f1(aligned.positions_b, &graph) > 0.9 * direct);
+    }
+}
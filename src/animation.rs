@@ -0,0 +1,186 @@
+//! Renders the position history of a 2D embedding as PNG frames.
+//!
+//! Frames are written as `frame_{iteration:05}.png` together with a
+//! `frames.ffconcat` playlist; `simulation/frames_to_mp4.sh` turns them into
+//! a video with ffmpeg.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use image::{Rgb, RgbImage};
+
+use crate::{dvec::DVec, dyn_embed::EmbedIndex, embedder::WEmbedder};
+
+const FRAME_SIZE: u32 = 800;
+const MARGIN: f32 = 20.0;
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const EDGE_COLOR: Rgb<u8> = Rgb([200, 200, 200]);
+
+impl<SI> WEmbedder<SI>
+where
+    SI: EmbedIndex<Vec = DVec<2>>,
+{
+    /// Writes one frame per logged history entry to `output_dir`.
+    ///
+    /// All frames share the bounding box of the whole history so the camera
+    /// stays fixed. Nodes are coloured by weight, from blue (light) to red
+    /// (heavy). `fps` sets the frame duration in the ffconcat playlist.
+    pub fn export_animation_frames(&self, output_dir: &str, fps: usize) -> io::Result<()> {
+        if fps == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "fps must be positive",
+            ));
+        }
+        let history = self.history();
+        if history.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no history to export, run the embedder first",
+            ));
+        }
+        fs::create_dir_all(output_dir)?;
+
+        let index = &self.spatial_index;
+        let n = index.num_nodes();
+        let weights: Vec<f64> = (0..n).map(|i| index.weight(i)).collect();
+        let (min_w, max_w) = weights
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &w| {
+                (lo.min(w), hi.max(w))
+            });
+        let colors: Vec<Rgb<u8>> = weights
+            .iter()
+            .map(|&w| {
+                let t = if max_w > min_w {
+                    ((w.ln() - min_w.ln()) / (max_w.ln() - min_w.ln())) as f32
+                } else {
+                    0.5
+                };
+                Rgb([(255.0 * t) as u8, 60, (255.0 * (1.0 - t)) as u8])
+            })
+            .collect();
+
+        let transform = Transform::fit(history.iter().flat_map(|(_, p)| p.iter()));
+        let mut playlist = String::from("ffconcat version 1.0\n");
+        for (iteration, positions) in history {
+            let mut frame = RgbImage::from_pixel(FRAME_SIZE, FRAME_SIZE, BACKGROUND);
+            let pixels: Vec<(f32, f32)> = positions.iter().map(|p| transform.apply(p)).collect();
+            for (u, &from) in pixels.iter().enumerate() {
+                for v in index.neighbors(u).iter().filter(|&v| v > u) {
+                    draw_line(&mut frame, from, pixels[v], EDGE_COLOR);
+                }
+            }
+            for (i, &center) in pixels.iter().enumerate() {
+                draw_circle(&mut frame, center, 3.0, colors[i]);
+            }
+
+            let name = format!("frame_{iteration:05}.png");
+            frame
+                .save(Path::new(output_dir).join(&name))
+                .map_err(io::Error::other)?;
+            playlist.push_str(&format!("file '{name}'\nduration {}\n", 1.0 / fps as f64));
+        }
+        // ffmpeg drops the duration of the last entry unless it is repeated
+        let (last_iteration, _) = history[history.len() - 1];
+        playlist.push_str(&format!("file 'frame_{last_iteration:05}.png'\n"));
+        fs::File::create(Path::new(output_dir).join("frames.ffconcat"))?
+            .write_all(playlist.as_bytes())
+    }
+}
+
+/// Maps embedding coordinates to pixel coordinates
+struct Transform {
+    min: [f32; 2],
+    scale: f32,
+}
+
+impl Transform {
+    fn fit<'a>(points: impl Iterator<Item = &'a DVec<2>>) -> Self {
+        let mut min = [f32::INFINITY; 2];
+        let mut max = [f32::NEG_INFINITY; 2];
+        for p in points {
+            for d in 0..2 {
+                min[d] = min[d].min(p[d]);
+                max[d] = max[d].max(p[d]);
+            }
+        }
+        let extent = (max[0] - min[0]).max(max[1] - min[1]).max(f32::EPSILON);
+        Self {
+            min,
+            scale: (FRAME_SIZE as f32 - 2.0 * MARGIN) / extent,
+        }
+    }
+
+    fn apply(&self, p: &DVec<2>) -> (f32, f32) {
+        (
+            MARGIN + (p[0] - self.min[0]) * self.scale,
+            // Image rows grow downwards
+            FRAME_SIZE as f32 - MARGIN - (p[1] - self.min[1]) * self.scale,
+        )
+    }
+}
+
+fn put_pixel(frame: &mut RgbImage, x: i64, y: i64, color: Rgb<u8>) {
+    if (0..frame.width() as i64).contains(&x) && (0..frame.height() as i64).contains(&y) {
+        frame.put_pixel(x as u32, y as u32, color);
+    }
+}
+
+fn draw_line(frame: &mut RgbImage, from: (f32, f32), to: (f32, f32), color: Rgb<u8>) {
+    let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil() as usize;
+    for step in 0..=steps {
+        let t = if steps == 0 {
+            0.0
+        } else {
+            step as f32 / steps as f32
+        };
+        let x = from.0 + (to.0 - from.0) * t;
+        let y = from.1 + (to.1 - from.1) * t;
+        put_pixel(frame, x.round() as i64, y.round() as i64, color);
+    }
+}
+
+fn draw_circle(frame: &mut RgbImage, center: (f32, f32), radius: f32, color: Rgb<u8>) {
+    let r = radius.ceil() as i64;
+    let (cx, cy) = (center.0.round() as i64, center.1.round() as i64);
+    for dy in -r..=r {
+        for dx in -r..=r {
+            if ((dx * dx + dy * dy) as f32) <= radius * radius {
+                put_pixel(frame, cx + dx, cy + dy, color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{embedder::EmbedderOptions, embedding::Embedding, graph::Graph};
+
+    use super::*;
+
+    #[test]
+    fn writes_one_frame_per_history_entry() {
+        let edges = (0..49).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            max_iterations: 30,
+            ..Default::default()
+        };
+        let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(1, &graph, options);
+        embedder.embed();
+
+        let dir = std::env::temp_dir().join(format!("rembed-animation-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        embedder.export_animation_frames(dir, 10).unwrap();
+
+        for (iteration, _) in embedder.history() {
+            let frame = image::open(format!("{dir}/frame_{iteration:05}.png")).unwrap();
+            assert_eq!(frame.width(), FRAME_SIZE);
+        }
+        let playlist = fs::read_to_string(format!("{dir}/frames.ffconcat")).unwrap();
+        assert!(playlist.contains("duration 0.1"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
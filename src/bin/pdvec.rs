@@ -1,4 +1,4 @@
-use rand::random;
+use rand::{Rng, SeedableRng, rngs::SmallRng};
 use sprk::simd::PDVec;
 use std::io;
 use std::time::{Duration, Instant};
@@ -192,11 +192,12 @@ fn bench_snn_8<const D: usize>(query: [f32; D]) -> Duration {
 }
 
 fn generate_pvecs<const D: usize>() -> Vec<PDVec<D, W, f32, u32>> {
+    let mut rng = SmallRng::seed_from_u64(D as u64);
     (0..128)
         .step_by(W)
         .map(|i| {
-            // let random_arr: [f32; W] = std::array::from_fn(|_| random());
-            PDVec::new((0..W).map(|j| (std::array::from_fn(|_| random()), i + j)))
+            // let random_arr: [f32; W] = std::array::from_fn(|_| rng.random());
+            PDVec::new((0..W).map(|j| (std::array::from_fn(|_| rng.random()), i + j)))
         })
         .collect()
 }
@@ -134,11 +134,11 @@ fn main() -> io::Result<()> {
                 // Measure recall if requested (requires MeasuredLSH wrapper)
                 let p_value = if config.lsh_measure_recall {
                     let lsh = RandomProjectionLsh::<D>::new_with_params(
-                        embedding.clone(),
+                        embedding.view(),
                         Some(num_tables),
                         Some(num_projections),
                     );
-                    let ground_truth = Sprk::<D>::new(&embedding);
+                    let ground_truth = Sprk::<D>::new(embedding.view());
                     let spatial_index = MeasuredLSH::new(lsh, ground_truth);
                     let mut embedder = WEmbedder::new(spatial_index, options.clone());
                     embedder.embed();
@@ -150,7 +150,7 @@ fn main() -> io::Result<()> {
                 // Measure time if requested (run without wrapper for accuracy)
                 let time_ms = if config.lsh_measure_time {
                     let lsh = RandomProjectionLsh::<D>::new_with_params(
-                        embedding.clone(),
+                        embedding.view(),
                         Some(num_tables),
                         Some(num_projections),
                     );
@@ -165,7 +165,7 @@ fn main() -> io::Result<()> {
 
                 // Always compute final F1 with Sprk (non-approximate ground truth)
                 let lsh = RandomProjectionLsh::<D>::new_with_params(
-                    embedding.clone(),
+                    embedding.view(),
                     Some(num_tables),
                     Some(num_projections),
                 );
@@ -178,7 +178,7 @@ fn main() -> io::Result<()> {
                     positions: final_positions,
                     graph: &graph,
                 };
-                let ground_truth = Sprk::<D>::new(&final_embedding);
+                let ground_truth = Sprk::<D>::new(final_embedding.view());
                 let (precision, recall_final) = ground_truth.graph_statistics();
                 let f1 = 2. / (recall_final.recip() + precision.recip());
 
@@ -214,7 +214,8 @@ fn main() -> io::Result<()> {
                 eprintln!("Testing strategy: {:?}, p={}", strategy, p_target);
 
                 // Run embedding with LossyQuery
-                let spatial_index = LossyQuery::<_, Sprk<D>>::new(&embedding, p_target, strategy);
+                let spatial_index =
+                    LossyQuery::<_, Sprk<D>>::new(embedding.view(), p_target, strategy);
                 let mut embedder = WEmbedder::new(spatial_index, options.clone());
 
                 let start = Instant::now();
@@ -227,7 +228,7 @@ fn main() -> io::Result<()> {
                     positions: final_positions,
                     graph: &graph,
                 };
-                let ground_truth = Sprk::<D>::new(&final_embedding);
+                let ground_truth = Sprk::<D>::new(final_embedding.view());
                 let (precision, recall) = ground_truth.graph_statistics();
                 let f1 = 2. / (recall.recip() + precision.recip());
 
@@ -0,0 +1,390 @@
+//! Self-contained embedding bundles for serving queries from another process.
+//!
+//! A bundle holds the final positions together with the node weights and the
+//! adjacency, so it can be queried without the original graph file. All
+//! integers are little endian `u64`, weights are `f64` and coordinates `f32`.
+//! Every section starts at a multiple of 8 bytes so a reader can mmap the file
+//! and use the arrays in place:
+//!
+//! ```text
+//! "RMBUNDLE"  version  D  n  m  k  directed
+//! weights[n]  offsets[n + 1]  indices[m]  original_ids[k]  positions[n * D]
+//! ```
+//!
+//! The neighbours of node `i` are `indices[offsets[i]..offsets[i + 1]]`,
+//! sorted and without duplicates. `k` is `n` if the graph has
+//! [`Graph::original_ids`] and 0 otherwise. `directed` is 1 for a
+//! [directed](Graph::is_directed) graph, whose lists only hold the heads of
+//! the outgoing edges, and 0 otherwise.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use memmap::Mmap;
+
+use crate::{Embedding, NodeId, dvec::DVec, graph::Graph};
+
+const MAGIC: &[u8; 8] = b"RMBUNDLE";
+const VERSION: u64 = 2;
+const HEADER_LEN: usize = 56;
+
+/// An embedding loaded from a bundle, owning its graph
+pub struct Bundle<const D: usize> {
+    pub graph: Graph,
+    pub positions: Vec<DVec<D>>,
+}
+
+impl<const D: usize> Bundle<D> {
+    /// Brute-force index over the bundled positions
+    pub fn embedding(&self) -> Embedding<'_, D> {
+        Embedding {
+            positions: self.positions.clone(),
+            graph: &self.graph,
+        }
+    }
+}
+
+impl<const D: usize> Embedding<'_, D> {
+    /// Writes the positions, node weights and adjacency to a bundle at `path`,
+    /// see the [module docs](crate::bundle) for the layout.
+    pub fn export_bundle<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let nodes = &self.graph.nodes;
+        if nodes.len() != self.positions.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "embedding has {} positions for {} nodes",
+                    self.positions.len(),
+                    nodes.len()
+                ),
+            ));
+        }
+        let num_indices: usize = nodes.iter().map(|node| node.neighbors.len()).sum();
+        let original_ids = self.graph.original_ids.as_deref().unwrap_or_default();
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        for value in [
+            VERSION,
+            D as u64,
+            nodes.len() as u64,
+            num_indices as u64,
+            original_ids.len() as u64,
+            self.graph.is_directed() as u64,
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        for node in nodes {
+            writer.write_all(&node.weight.to_le_bytes())?;
+        }
+        let mut offset = 0u64;
+        writer.write_all(&offset.to_le_bytes())?;
+        for node in nodes {
+            offset += node.neighbors.len() as u64;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        for node in nodes {
+            for &neighbor in &node.neighbors {
+                writer.write_all(&(neighbor as u64).to_le_bytes())?;
+            }
+        }
+        for id in original_ids {
+            writer.write_all(&id.to_le_bytes())?;
+        }
+        for position in &self.positions {
+            for d in 0..D {
+                writer.write_all(&position[d].to_le_bytes())?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+/// Loads a bundle written by [`Embedding::export_bundle`].
+pub fn load<P: AsRef<Path>, const D: usize>(path: P) -> io::Result<Bundle<D>> {
+    let file = File::open(path)?;
+    let mmap = unsafe { Mmap::map(&file)? };
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    if mmap.len() < HEADER_LEN || &mmap[..8] != MAGIC {
+        return Err(invalid("not a rembed bundle".into()));
+    }
+    let mut reader = Reader { bytes: &mmap[8..] };
+    let version = reader.u64()?;
+    if version != VERSION {
+        return Err(invalid(format!("unsupported bundle version {version}")));
+    }
+    let dim = reader.u64()? as usize;
+    if dim != D {
+        return Err(invalid(format!("bundle has dimension {dim}, expected {D}")));
+    }
+    let n = reader.u64()? as usize;
+    let num_indices = reader.u64()? as usize;
+    let num_original_ids = reader.u64()? as usize;
+    let directed = match reader.u64()? {
+        0 => false,
+        1 => true,
+        flag => return Err(invalid(format!("invalid directed flag {flag}"))),
+    };
+    if num_original_ids != 0 && num_original_ids != n {
+        return Err(invalid(format!(
+            "bundle has {num_original_ids} original ids for {n} nodes"
+        )));
+    }
+    // The counts come from the file, so a crafted header must not overflow
+    let expected_len = (|| {
+        let words = n
+            .checked_mul(2)?
+            .checked_add(1)?
+            .checked_add(num_indices)?
+            .checked_add(num_original_ids)?;
+        HEADER_LEN
+            .checked_add(words.checked_mul(8)?)?
+            .checked_add(n.checked_mul(D)?.checked_mul(4)?)
+    })();
+    if expected_len != Some(mmap.len()) {
+        return Err(invalid(format!(
+            "bundle has {} bytes, which does not match its header",
+            mmap.len()
+        )));
+    }
+
+    let weights = (0..n)
+        .map(|_| reader.f64())
+        .collect::<io::Result<Vec<_>>>()?;
+    let offsets = (0..=n)
+        .map(|_| reader.u64().map(|o| o as usize))
+        .collect::<io::Result<Vec<_>>>()?;
+    if offsets[0] != 0 || offsets[n] != num_indices || offsets.windows(2).any(|w| w[0] > w[1]) {
+        return Err(invalid("adjacency offsets are not monotonic".into()));
+    }
+    let indices = (0..num_indices)
+        .map(|_| reader.u64().map(|i| i as NodeId))
+        .collect::<io::Result<Vec<_>>>()?;
+    let original_ids = (0..num_original_ids)
+        .map(|_| reader.u64())
+        .collect::<io::Result<Vec<_>>>()?;
+    let positions = (0..n)
+        .map(|_| {
+            let mut position = DVec::zero();
+            for d in 0..D {
+                position[d] = reader.f32()?;
+            }
+            Ok(position)
+        })
+        .collect::<io::Result<Vec<DVec<D>>>>()?;
+
+    let neighbors: Vec<Vec<NodeId>> = offsets
+        .windows(2)
+        .map(|w| indices[w[0]..w[1]].to_vec())
+        .collect();
+    // The graph binary searches the neighbour lists and derives its edges
+    // from one side of them, so they have to be sorted and, unless the graph
+    // is directed, symmetric
+    for (u, adjacent) in neighbors.iter().enumerate() {
+        if let Some(&v) = adjacent.iter().find(|&&v| v >= n || v == u) {
+            return Err(invalid(format!("neighbour {v} of node {u} out of range")));
+        }
+        if adjacent.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(invalid(format!(
+                "neighbours of node {u} are not sorted and unique"
+            )));
+        }
+        if let Some(&v) = adjacent
+            .iter()
+            .find(|&&v| !directed && neighbors[v].binary_search(&u).is_err())
+        {
+            return Err(invalid(format!("edge {u}-{v} is only stored at node {u}")));
+        }
+    }
+    let mut graph = if directed {
+        Graph::from_directed_adjacency(weights, neighbors)
+    } else {
+        Graph::from_adjacency(weights, neighbors)
+    };
+    graph.original_ids = (num_original_ids > 0).then_some(original_ids);
+    Ok(Bundle { graph, positions })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let Some((head, rest)) = self.bytes.split_first_chunk::<N>() else {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+        self.bytes = rest;
+        Ok(*head)
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    fn f64(&mut self) -> io::Result<f64> {
+        self.take().map(f64::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        self.take().map(f32::from_le_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+    use super::*;
+    use crate::embedder::{EmbedderOptions, WEmbedder};
+    use crate::query::{Graph as _, Query};
+
+    #[test]
+    fn round_trip_answers_the_same_queries() {
+        let edges = (0..99)
+            .map(|i| (i, i + 1))
+            .chain([(0, 50), (10, 90)])
+            .collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let mut rng = SmallRng::seed_from_u64(3);
+        let embedding: Embedding<2> = Embedding {
+            positions: (0..graph.nodes.len())
+                .map(|_| DVec::from_fn(|_| rng.random_range(0.0..5.0)))
+                .collect(),
+            graph: &graph,
+        };
+
+        let path = std::env::temp_dir().join(format!("rembed-bundle-{}", std::process::id()));
+        embedding.export_bundle(&path).unwrap();
+        let bundle = load::<_, 2>(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(load::<_, 3>(&path).is_err());
+
+        let loaded = bundle.embedding();
+        assert_eq!(loaded.positions, embedding.positions);
+        for v in 0..graph.nodes.len() {
+            assert_eq!(loaded.graph.nodes[v].weight, graph.nodes[v].weight);
+            assert_eq!(loaded.graph.nodes[v].neighbors, graph.nodes[v].neighbors);
+            assert_eq!(
+                loaded.nearest_neighbors_owned(v, 1.),
+                embedding.nearest_neighbors_owned(v, 1.)
+            );
+        }
+        assert_eq!(loaded.graph.edges.len(), graph.edges.len());
+    }
+
+    #[test]
+    fn directed_round_trip() {
+        // A cycle entered from node 3, so 0 has an edge it doesn't list
+        let graph =
+            Graph::from_directed_edge_list(vec![(0, 1), (1, 2), (2, 0), (3, 0)], 2, 2).unwrap();
+        let embedding: Embedding<2> = Embedding {
+            positions: (0..4).map(|i| DVec::from_fn(|d| (i + d) as f32)).collect(),
+            graph: &graph,
+        };
+
+        let path = std::env::temp_dir().join(format!("rembed-bundle-dir-{}", std::process::id()));
+        embedding.export_bundle(&path).unwrap();
+        let bundle = load::<_, 2>(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let loaded = &bundle.graph;
+        assert!(loaded.is_directed());
+        assert_eq!(bundle.positions, embedding.positions);
+        assert_eq!(loaded.edges.len(), graph.edges.len());
+        for v in 0..graph.nodes.len() {
+            assert_eq!(loaded.nodes[v].weight, graph.nodes[v].weight);
+            assert_eq!(loaded.nodes[v].neighbors, graph.nodes[v].neighbors);
+        }
+        assert!(loaded.is_connected(0, 3) && loaded.is_connected(3, 0));
+        assert!(!loaded.is_connected(1, 3));
+    }
+
+    #[test]
+    fn embedding_joins_back_to_the_input_by_original_id() {
+        // A wheel on 0..=5 and a separate edge, so reducing to the largest
+        // component and relabeling both move nodes
+        let edges = vec![
+            (0, 1),
+            (1, 2),
+            (2, 3),
+            (3, 4),
+            (4, 0),
+            (5, 0),
+            (5, 1),
+            (5, 2),
+            (5, 3),
+            (5, 4),
+            (6, 7),
+        ];
+        let input = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let (component, _) = input.reduce_to_largest_component();
+        let graph = component.relabel(&[5, 3, 1, 4, 0, 2]);
+        let options = EmbedderOptions {
+            max_iterations: 20,
+            ..Default::default()
+        };
+        let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(1, &graph, options);
+        embedder.embed();
+        let embedding = Embedding {
+            positions: embedder.positions().to_vec(),
+            graph: &graph,
+        };
+
+        let path = std::env::temp_dir().join(format!("rembed-bundle-ids-{}", std::process::id()));
+        embedding.export_bundle(&path).unwrap();
+        let bundle = load::<_, 2>(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let loaded = &bundle.graph;
+        let mut joined: Vec<u64> = (0..loaded.nodes.len())
+            .map(|v| loaded.original_id(v))
+            .collect();
+        for (v, &original) in joined.iter().enumerate() {
+            let original = original as usize;
+            assert_eq!(loaded.nodes[v].weight, input.nodes[original].weight);
+            assert_eq!(bundle.positions[v], embedder.positions()[v]);
+            let mut neighbors: Vec<_> = loaded.nodes[v]
+                .neighbors
+                .iter()
+                .map(|&u| loaded.original_id(u as NodeId) as u32)
+                .collect();
+            neighbors.sort_unstable();
+            assert_eq!(neighbors, input.nodes[original].neighbors, "node {v}");
+        }
+        joined.sort_unstable();
+        assert_eq!(joined, [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn rejects_inconsistent_bundles() {
+        let graph = Graph::from_edge_list(vec![(0, 1), (1, 2)], 2, 2).unwrap();
+        let embedding: Embedding<2> = Embedding {
+            positions: vec![DVec::zero(); 3],
+            graph: &graph,
+        };
+        let path = std::env::temp_dir().join(format!("rembed-bundle-bad-{}", std::process::id()));
+        embedding.export_bundle(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        let word = |i: usize| 8 + 8 * i;
+        // indices start after the header, 3 weights and 4 offsets
+        let indices = HEADER_LEN + 8 * 3 + 8 * 4;
+
+        let mut huge = bytes.clone();
+        huge[word(2)..word(3)].copy_from_slice(&(u64::MAX / 4).to_le_bytes());
+        // Node 1 lists node 2 before node 0
+        let mut unsorted = bytes.clone();
+        unsorted[indices + 8..indices + 16].copy_from_slice(&2u64.to_le_bytes());
+        unsorted[indices + 16..indices + 24].copy_from_slice(&0u64.to_le_bytes());
+        // Node 0 lists node 2, which does not list node 0
+        let mut asymmetric = bytes.clone();
+        asymmetric[indices..indices + 8].copy_from_slice(&2u64.to_le_bytes());
+        for corrupted in [huge, unsorted, asymmetric] {
+            std::fs::write(&path, corrupted).unwrap();
+            assert!(load::<_, 2>(&path).is_err());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -2,8 +2,9 @@ use std::marker::PhantomData;
 use std::ptr;
 
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 use cgal::*;
@@ -17,22 +18,22 @@ pub struct CgalKdTreeWrapper<'a, const D: usize> {
 
 impl<'a, const D: usize> Clone for CgalKdTreeWrapper<'a, D> {
     fn clone(&self) -> Self {
-        Self::new(&Embedding {
-            positions: self.positions.clone(),
+        Self::new(EmbeddingRef {
+            positions: &self.positions,
             graph: self.graph,
         })
     }
 }
 
 impl<'a, const D: usize> CgalKdTreeWrapper<'a, D> {
-    pub fn new(embedding: &Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let mut wrapper = Self {
-            positions: embedding.positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             index: ptr::null_mut(),
             _phantom: PhantomData,
         };
-        wrapper.update_positions(&embedding.positions, None);
+        wrapper.update_positions(embedding.positions, None);
         wrapper
     }
 
@@ -90,7 +91,7 @@ impl<'a, const D: usize> Graph for CgalKdTreeWrapper<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -175,7 +176,7 @@ impl<'a, const D: usize> SpatialIndex<D> for CgalKdTreeWrapper<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for CgalKdTreeWrapper<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         Self::new(embedding)
     }
 }
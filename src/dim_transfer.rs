@@ -0,0 +1,197 @@
+//! Transfer of embeddings between dimensions, e.g. to initialise a 2-D
+//! drawing from a converged 16-D embedding of the same graph.
+
+use std::fmt;
+
+use rand::Rng;
+
+use crate::{
+    dvec::DVec,
+    pca::Pca,
+    rng::{Purpose, RngSource},
+};
+
+/// Largest offset [`jitter`] moves a coordinate by. The initialisation cube
+/// holds about one node per unit volume, so this is a small fraction of the
+/// distance between nodes.
+pub const PROJECTION_JITTER: f32 = 0.05;
+
+/// Positions of an existing embedding of any supported dimension.
+#[derive(Clone, Copy, Debug)]
+pub struct SourceEmbedding<'s> {
+    /// Row-major positions, `dim` floats per node
+    pub positions: &'s [f32],
+    pub dim: usize,
+}
+
+/// Projects `positions` onto their top `TO` principal components. If `TO`
+/// exceeds `FROM`, the remaining coordinates are zero.
+pub fn project<const FROM: usize, const TO: usize>(positions: &[DVec<FROM>]) -> Vec<DVec<TO>> {
+    let raw_positions: Vec<[f32; FROM]> = positions.iter().map(|p| p.components).collect();
+    let mut pca = Pca::new();
+    pca.fit(&raw_positions);
+    pca.project_all(&raw_positions)
+        .into_iter()
+        .map(|p| DVec::from_fn(|i| if i < FROM { p[i] } else { 0.0 }))
+        .collect()
+}
+
+/// Rescales every axis so the positions span `[0, n^(1/D)]`, the cube used for
+/// random initialisation in [`crate::WEmbedder::random`].
+pub fn rescale_to_cube_side<const D: usize>(positions: &mut [DVec<D>]) {
+    if positions.is_empty() {
+        return;
+    }
+    let cube_side = (positions.len() as f64).powf(1.0 / D as f64) as f32;
+    for axis in 0..D {
+        let (min, max) = positions
+            .iter()
+            .map(|p| p[axis])
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), x| {
+                (min.min(x), max.max(x))
+            });
+        let scale = if max > min {
+            cube_side / (max - min)
+        } else {
+            0.0
+        };
+        for p in positions.iter_mut() {
+            p[axis] = (p[axis] - min) * scale;
+        }
+    }
+}
+
+/// Moves every coordinate by a random offset of at most
+/// [`PROJECTION_JITTER`], drawn from `rng`. Separates nodes that the
+/// projection put on top of each other, and makes the initialisation depend
+/// on the seed.
+pub fn jitter<const D: usize>(positions: &mut [DVec<D>], rng: RngSource) {
+    for (node, position) in positions.iter_mut().enumerate() {
+        let mut rng = rng.stream(Purpose::ProjectionJitter, &[node as u64]);
+        *position += DVec::from_fn(|_| rng.random_range(-PROJECTION_JITTER..PROJECTION_JITTER));
+    }
+}
+
+/// The source embedding has a dimension [`project_source`] is not compiled
+/// for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedDimension(pub usize);
+
+impl fmt::Display for UnsupportedDimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "source dimension {} is not supported", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedDimension {}
+
+macro_rules! dispatch_source_dim {
+    ($source:ident, $($dim:literal),*) => {
+        match $source.dim {
+            $($dim => Ok(project::<$dim, TO>(&to_dvecs::<$dim>($source.positions))),)*
+            dim => Err(UnsupportedDimension(dim)),
+        }
+    };
+}
+
+/// Projects a type-erased source embedding to `TO` dimensions, see [`project`].
+pub fn project_source<const TO: usize>(
+    source: SourceEmbedding,
+) -> Result<Vec<DVec<TO>>, UnsupportedDimension> {
+    assert_eq!(
+        source.positions.len() % source.dim.max(1),
+        0,
+        "source positions are not a multiple of the source dimension"
+    );
+    dispatch_source_dim!(
+        source, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 32
+    )
+}
+
+fn to_dvecs<const D: usize>(positions: &[f32]) -> Vec<DVec<D>> {
+    positions
+        .chunks_exact(D)
+        .map(|chunk| DVec::from_fn(|i| chunk[i]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SourceEmbedding, UnsupportedDimension, project_source};
+    use crate::{
+        Embedding,
+        embedder::{EmbedderOptions, WEmbedder},
+        graph::{Graph, grid},
+        query::Embedder,
+    };
+
+    fn f1_after<const D: usize>(
+        graph: &Graph,
+        max_iterations: usize,
+        source: Option<SourceEmbedding>,
+    ) -> (f64, Vec<f32>) {
+        let options = EmbedderOptions {
+            max_iterations,
+            // A lower rate keeps the first steps from scrambling the initialisation
+            learning_rate: 1.0,
+            ..Default::default()
+        };
+        let mut embedder: WEmbedder<Embedding<D>> =
+            WEmbedder::random_or_projected(1, graph, options, source).unwrap();
+        let positions = embedder.embed();
+        let flat = positions.iter().flat_map(|p| p.components).collect();
+        let f1 = Embedding { positions, graph }.f1();
+        (f1, flat)
+    }
+
+    #[test]
+    fn projected_initialisation_beats_random() {
+        let graph_2d = grid(15);
+        let graph_8d = graph_2d.reweighted(8, 2);
+
+        let (_, source) = f1_after::<8>(&graph_8d, 300, None);
+        let source = SourceEmbedding {
+            positions: &source,
+            dim: 8,
+        };
+
+        let budget = 50;
+        let (projected_f1, _) = f1_after::<2>(&graph_2d, budget, Some(source));
+        let (random_f1, _) = f1_after::<2>(&graph_2d, budget, None);
+        assert!(
+            projected_f1 > random_f1,
+            "projected: {projected_f1}, random: {random_f1}"
+        );
+    }
+
+    #[test]
+    fn projected_initialisation_depends_on_the_seed() {
+        let graph = grid(4);
+        let flat: Vec<f32> = (0..graph.nodes.len() * 3).map(|i| i as f32).collect();
+        let source = SourceEmbedding {
+            positions: &flat,
+            dim: 3,
+        };
+        let initial = |seed| {
+            let embedder: WEmbedder<Embedding<2>> = WEmbedder::random_or_projected(
+                seed,
+                &graph,
+                EmbedderOptions::default(),
+                Some(source),
+            )
+            .unwrap();
+            embedder.positions().to_vec()
+        };
+        assert_eq!(initial(1), initial(1));
+        assert_ne!(initial(1), initial(2));
+
+        let source = SourceEmbedding {
+            positions: &flat,
+            dim: 1,
+        };
+        assert_eq!(
+            project_source::<2>(source).unwrap_err(),
+            UnsupportedDimension(1)
+        );
+    }
+}
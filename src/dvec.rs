@@ -4,6 +4,13 @@ use std::ops::{
     Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
 };
 
+/// How often [`DVec::distance_squared_bounded`] compares its partial sum
+/// with the bound. Has to be a multiple of 4, the accumulator width. Checking
+/// every 8 components is twice as slow as no check at all when most pairs are
+/// only slightly out of range, as the branch becomes unpredictable.
+pub const BOUND_CHECK_COMPONENTS: usize = 16;
+const _: () = assert!(BOUND_CHECK_COMPONENTS > 0 && BOUND_CHECK_COMPONENTS.is_multiple_of(4));
+
 /// Trait abstracting over fixed-size `DVec<D>` and heap-allocated `DynVec`.
 ///
 /// Enables the embedder and optimizer to be generic over the vector
@@ -13,6 +20,7 @@ pub trait Vector:
     Clone
     + Send
     + Sync
+    + 'static
     + Add<Output = Self>
     + AddAssign
     + Sub<Output = Self>
@@ -21,6 +29,7 @@ pub trait Vector:
     + Div<Self, Output = Self>
     + Mul<Self, Output = Self>
     + Neg<Output = Self>
+    + Index<usize, Output = f32>
     + fmt::Debug
 {
     fn zero(dim: usize) -> Self;
@@ -30,6 +39,10 @@ pub trait Vector:
     fn distance_squared(&self, other: &Self) -> f32;
     fn map(&self, f: impl FnMut(f32) -> f32) -> Self;
     fn dim(&self) -> usize;
+    /// Bytes the vector owns on the heap, on top of `size_of::<Self>()`
+    fn heap_bytes(&self) -> usize {
+        0
+    }
 }
 
 impl<const D: usize> Vector for DVec<D> {
@@ -64,12 +77,20 @@ impl<const D: usize> Vector for DVec<D> {
     }
 }
 
+/// With the `simd-align` feature, every `DVec` starts on a 32 byte boundary
+/// so AVX2 can use aligned 256 bit loads. Its size is then rounded up to a
+/// multiple of 8 floats, e.g. `DVec<3>` takes 32 bytes instead of 12.
+/// Without the feature it has the layout of `[f32; D]`.
 #[derive(Clone, Copy, Debug, PartialOrd)]
-#[repr(transparent)]
+#[cfg_attr(not(feature = "simd-align"), repr(transparent))]
+#[cfg_attr(feature = "simd-align", repr(C, align(32)))]
 pub struct DVec<const D: usize> {
     pub components: [f32; D],
 }
 
+#[cfg(feature = "simd-align")]
+const _: () = assert!(std::mem::size_of::<DVec<8>>() == 32);
+
 impl<const D: usize> std::hash::Hash for DVec<D> {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         for &component in &self.components {
@@ -241,13 +262,53 @@ impl<const D: usize> DVec<D> {
         } else {
             panic!()
         };
-        if d.is_multiple_of(2) {
+        // `d` is always even, the last component of an odd `D` is left
+        if D.is_multiple_of(2) {
             dist
         } else {
             dist + (a[D - 1] - b[D - 1]).powi(2)
         }
     }
 
+    /// [`DVec::distance_squared`], or `None` as soon as a partial sum
+    /// exceeds `bound`. The partial sums are checked every
+    /// [`BOUND_CHECK_COMPONENTS`] components and the summation order is the
+    /// same as in `distance_squared`, so a returned distance is bit-identical
+    /// and `None` means the full distance is larger than `bound` as well.
+    pub fn distance_squared_bounded(&self, other: &Self, bound: f32) -> Option<f32> {
+        let a = &self.components;
+        let b = &other.components;
+        let mut acc = [0.0f32; 4];
+        let chunks = D / 4;
+        for i in 0..chunks {
+            let base = i * 4;
+            let d0 = a[base] - b[base];
+            let d1 = a[base + 1] - b[base + 1];
+            let d2 = a[base + 2] - b[base + 2];
+            let d3 = a[base + 3] - b[base + 3];
+            acc[0] += d0 * d0;
+            acc[1] += d1 * d1;
+            acc[2] += d2 * d2;
+            acc[3] += d3 * d3;
+            if (i + 1) % (BOUND_CHECK_COMPONENTS / 4) == 0
+                && (acc[0] + acc[1]) + (acc[2] + acc[3]) > bound
+            {
+                return None;
+            }
+        }
+        let mut dist = (acc[0] + acc[1]) + (acc[2] + acc[3]);
+        if D % 4 >= 2 {
+            let tail = chunks * 4;
+            let d0 = a[tail] - b[tail];
+            let d1 = a[tail + 1] - b[tail + 1];
+            dist += d0 * d0 + d1 * d1;
+        }
+        if !D.is_multiple_of(2) {
+            dist += (a[D - 1] - b[D - 1]).powi(2);
+        }
+        (dist <= bound).then_some(dist)
+    }
+
     pub fn manhattan_distance(&self, other: &Self) -> f32 {
         (*self - *other).manhattan_norm()
     }
@@ -317,6 +378,43 @@ impl<const D: usize> DVec<D> {
             components: [value; D],
         }
     }
+
+    /// Component-wise comparison with tolerance `epsilon`. Two components
+    /// match if they differ by at most `epsilon` in absolute terms, or by at
+    /// most `epsilon` times the larger magnitude, so the same tolerance works
+    /// near zero and for large coordinates. NaN never matches.
+    ///
+    /// `==` requires the exact same values, which is too strict for positions
+    /// computed by different code paths, whose rounding differs.
+    pub fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        self.components
+            .iter()
+            .zip(other.components.iter())
+            .all(|(&a, &b)| {
+                let diff = (a - b).abs();
+                a == b || diff <= epsilon || diff <= epsilon * a.abs().max(b.abs())
+            })
+    }
+}
+
+/// Centroid of `positions` where every position is weighted by the matching
+/// entry of `weights`. Returns the zero vector if the total weight is zero.
+pub fn weighted_centroid<const D: usize>(positions: &[DVec<D>], weights: &[f64]) -> DVec<D> {
+    assert_eq!(
+        positions.len(),
+        weights.len(),
+        "Every position needs a weight"
+    );
+    let total_weight: f64 = weights.iter().sum();
+    if total_weight == 0.0 {
+        return DVec::zero();
+    }
+    let sum: DVec<D> = positions
+        .iter()
+        .zip(weights)
+        .map(|(&pos, &weight)| pos * weight as f32)
+        .sum();
+    sum / total_weight as f32
 }
 
 // From implementations
@@ -502,3 +600,97 @@ impl<const D: usize> Sum for DVec<D> {
         iter.fold(Self::zero(), |acc, x| acc + x)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{DVec, weighted_centroid};
+
+    #[test]
+    fn approx_eq_tolerates_small_differences() {
+        let a = DVec::new([1.0, -2.0, 0.0]);
+        assert!(a.approx_eq(&DVec::new([1.0 + 1e-7, -2.0, 5e-7]), 1e-6));
+        assert!(!a.approx_eq(&DVec::new([1.0 + 1e-5, -2.0, 0.0]), 1e-6));
+        assert!(!a.approx_eq(&DVec::new([1.0, -2.0, 2e-6]), 1e-6));
+
+        // Relative tolerance for large coordinates
+        let far = DVec::new([1e6, 0.0, 0.0]);
+        assert!(far.approx_eq(&DVec::new([1e6 + 0.5, 0.0, 0.0]), 1e-6));
+        assert!(!far.approx_eq(&DVec::new([1e6 + 2.0, 0.0, 0.0]), 1e-6));
+
+        let nan = DVec::new([f32::NAN, 0.0, 0.0]);
+        assert!(!nan.approx_eq(&nan, 1.0));
+    }
+
+    #[test]
+    fn weighted_centroid_weighs_every_position() {
+        let positions = [
+            DVec::new([0.0, 0.0]),
+            DVec::new([4.0, 0.0]),
+            DVec::new([0.0, 8.0]),
+        ];
+        let centroid = weighted_centroid(&positions, &[2.0, 1.0, 1.0]);
+        assert!(
+            centroid.approx_eq(&DVec::new([1.0, 2.0]), 1e-6),
+            "{centroid}"
+        );
+        // Nodes without weight do not pull the centroid
+        let centroid = weighted_centroid(&positions, &[0.0, 3.0, 0.0]);
+        assert!(centroid.approx_eq(&positions[1], 1e-6), "{centroid}");
+
+        assert_eq!(weighted_centroid(&positions, &[0.0; 3]), DVec::zero());
+        assert_eq!(weighted_centroid::<2>(&[], &[]), DVec::zero());
+    }
+
+    #[test]
+    fn distance_squared_matches_a_naive_sum() {
+        fn check<const D: usize>() {
+            let a = DVec::<D>::from_fn(|i| (i as f32 * 0.37).sin());
+            let b = DVec::<D>::from_fn(|i| (i as f32 * 1.3).cos() * 2.0);
+            let naive: f32 = (0..D).map(|i| (a[i] - b[i]).powi(2)).sum();
+            let distance = a.distance_squared(&b);
+            assert!(
+                (distance - naive).abs() <= 1e-5 * naive,
+                "D = {D}: {distance} vs {naive}"
+            );
+        }
+        check::<1>();
+        check::<2>();
+        check::<3>();
+        check::<4>();
+        check::<5>();
+        check::<6>();
+        check::<7>();
+        check::<8>();
+        check::<9>();
+        check::<16>();
+        check::<17>();
+        check::<32>();
+        check::<33>();
+    }
+
+    #[test]
+    fn bounded_distance_matches_distance_squared() {
+        fn check<const D: usize>() {
+            let a = DVec::<D>::from_fn(|i| (i as f32 * 0.37).sin());
+            let b = DVec::<D>::from_fn(|i| (i as f32 * 1.3).cos() * 2.0);
+            let exact = a.distance_squared(&b);
+            assert_eq!(
+                a.distance_squared_bounded(&b, exact),
+                Some(exact),
+                "D = {D}"
+            );
+            assert_eq!(a.distance_squared_bounded(&b, f32::INFINITY), Some(exact));
+            assert_eq!(a.distance_squared_bounded(&b, exact.next_down()), None);
+            assert_eq!(a.distance_squared_bounded(&b, 0.0), None);
+            assert_eq!(a.distance_squared_bounded(&a, 0.0), Some(0.0));
+        }
+        check::<1>();
+        check::<2>();
+        check::<3>();
+        check::<5>();
+        check::<8>();
+        check::<11>();
+        check::<14>();
+        check::<32>();
+    }
+}
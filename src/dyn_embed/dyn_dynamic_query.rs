@@ -3,7 +3,7 @@ use std::sync::Mutex;
 use rayon::prelude::*;
 
 use crate::dvec::Vector;
-use crate::graph::Graph;
+use crate::graph::{Graph, Neighbors};
 use crate::query::Graph as _;
 use crate::NodeId;
 
@@ -155,14 +155,14 @@ impl<'a> DynDynamicQuery<'a> {
         }
     }
 
-    fn nearest_neighbors_batched(&self, indices: &[usize]) -> Vec<Vec<usize>> {
-        let n = indices.len();
+    fn nearest_neighbors_batched_with(&self, queries: &[(usize, f64)]) -> Vec<Vec<usize>> {
+        let n = queries.len();
         // Run all NN queries in parallel
-        let per_node: Vec<Vec<usize>> = indices
+        let per_node: Vec<Vec<usize>> = queries
             .par_iter()
-            .map(|&index| {
+            .map(|&(index, radius)| {
                 let mut owned = Vec::new();
-                self.structure.nearest_neighbors(index, 1., &mut owned);
+                self.structure.nearest_neighbors(index, radius, &mut owned);
                 owned
             })
             .collect();
@@ -202,7 +202,7 @@ impl EmbedIndex for DynDynamicQuery<'_> {
         self.structure.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.structure.graph.neighbors(index)
     }
 
@@ -215,8 +215,8 @@ impl EmbedIndex for DynDynamicQuery<'_> {
     }
 
     fn graph_statistics(&self) -> (f64, f64) {
-        let ids: Vec<_> = (0..self.num_nodes()).collect();
-        let results = self.nearest_neighbors_batched(&ids);
+        let queries: Vec<_> = (0..self.num_nodes()).map(|i| (i, 1.)).collect();
+        let results = self.nearest_neighbors_batched_with(&queries);
 
         // Count total edges in graph
         let total_edges: usize =
@@ -256,4 +256,18 @@ impl EmbedIndex for DynDynamicQuery<'_> {
             found_edges as f64 / total_edges as f64,
         )
     }
+
+    fn memory_usage_bytes(&self) -> usize {
+        let positions: usize = self.positions.iter().map(Vector::heap_bytes).sum();
+        let cached: usize = self
+            .query_cache
+            .iter()
+            .map(|cache| cache.lock().unwrap().capacity() * size_of::<usize>())
+            .sum();
+        self.positions.capacity() * size_of::<DynVec>()
+            + positions
+            + self.query_cache.capacity() * size_of::<Mutex<Vec<usize>>>()
+            + cached
+            + self.structure.memory_usage_bytes()
+    }
 }
@@ -1,6 +1,7 @@
 use crate::graph::Graph;
 use crate::query::Graph as _;
 use crate::NodeId;
+use crate::dvec::Vector;
 
 use super::dyn_vec::DynVec;
 
@@ -54,4 +55,12 @@ impl<'a> DynDynSprk<'a> {
         let scaled_radius = radius * self.graph.weight(index).powi(2);
         self.query_radius(pos, scaled_radius, results);
     }
+
+    /// See [`crate::query::SpatialIndex::memory_usage_bytes`]
+    pub fn memory_usage_bytes(&self) -> usize {
+        let positions: usize = self.positions.iter().map(Vector::heap_bytes).sum();
+        self.positions.capacity() * size_of::<DynVec>()
+            + positions
+            + crate::sprk::tree_bytes(self.tree.len(), self.tree.dim())
+    }
 }
@@ -59,6 +59,10 @@ impl Vector for DynVec {
     fn dim(&self) -> usize {
         self.components.len()
     }
+
+    fn heap_bytes(&self) -> usize {
+        self.components.capacity() * size_of::<f32>()
+    }
 }
 
 impl Add for DynVec {
@@ -17,6 +17,8 @@ pub use dyn_vec::DynVec;
 
 use crate::NodeId;
 use crate::dvec::Vector;
+use crate::graph::Neighbors;
+use crate::query::PreparedUpdate;
 
 /// Trait unifying const-generic spatial indices and the dynamic variant.
 ///
@@ -29,13 +31,29 @@ pub trait EmbedIndex: Clone + Sync {
     fn num_nodes(&self) -> usize;
     fn weight(&self, index: NodeId) -> f64;
     fn is_connected(&self, first: NodeId, second: NodeId) -> bool;
-    fn neighbors(&self, index: NodeId) -> &[NodeId];
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_>;
     fn update_positions(&mut self, positions: &[Self::Vec], last_delta: Option<f64>);
+    /// See [`crate::query::Update::prepare_update`]
+    fn prepare_update(
+        &self,
+        positions: &[Self::Vec],
+        last_delta: Option<f64>,
+    ) -> PreparedUpdate<Self::Vec> {
+        PreparedUpdate::deferred(positions, last_delta)
+    }
+    /// See [`crate::query::Update::commit_update`]
+    fn commit_update(&mut self, prepared: PreparedUpdate<Self::Vec>) {
+        let (positions, last_delta) = prepared.into_deferred();
+        self.update_positions(&positions, last_delta);
+    }
     fn repelling_nodes(&self, index: usize, result: &mut Vec<NodeId>);
     fn graph_statistics(&self) -> (f64, f64);
+    /// See [`crate::query::SpatialIndex::memory_usage_bytes`]
+    fn memory_usage_bytes(&self) -> usize;
 }
 
-/// Implements [`EmbedIndex`] for a const-generic type that implements `Embedder<'a, D>`.
+/// Implements [`EmbedIndex`] for a const-generic type that implements `Embedder<'a, D>`
+/// and [`crate::query::SpatialIndex`].
 ///
 /// Usage: `impl_embed_index!(Sprk<'a, D>);`
 #[macro_export]
@@ -64,7 +82,7 @@ macro_rules! impl_embed_index {
                 $crate::query::Graph::is_connected(self, first, second)
             }
 
-            fn neighbors(&self, index: $crate::NodeId) -> &[$crate::NodeId] {
+            fn neighbors(&self, index: $crate::NodeId) -> $crate::graph::Neighbors<'_> {
                 $crate::query::Graph::neighbors(self, index)
             }
 
@@ -76,6 +94,21 @@ macro_rules! impl_embed_index {
                 $crate::query::Update::update_positions(self, positions, last_delta);
             }
 
+            fn prepare_update(
+                &self,
+                positions: &[$crate::dvec::DVec<D>],
+                last_delta: Option<f64>,
+            ) -> $crate::query::PreparedUpdate<$crate::dvec::DVec<D>> {
+                $crate::query::Update::prepare_update(self, positions, last_delta)
+            }
+
+            fn commit_update(
+                &mut self,
+                prepared: $crate::query::PreparedUpdate<$crate::dvec::DVec<D>>,
+            ) {
+                $crate::query::Update::commit_update(self, prepared);
+            }
+
             fn repelling_nodes(&self, index: usize, result: &mut Vec<$crate::NodeId>) {
                 $crate::query::Embedder::repelling_nodes(self, index, result);
             }
@@ -83,6 +116,10 @@ macro_rules! impl_embed_index {
             fn graph_statistics(&self) -> (f64, f64) {
                 $crate::query::Embedder::graph_statistics(self)
             }
+
+            fn memory_usage_bytes(&self) -> usize {
+                $crate::query::SpatialIndex::memory_usage_bytes(self)
+            }
         }
     };
 }
@@ -92,6 +129,7 @@ macro_rules! impl_embed_index {
 impl_embed_index!(crate::sprk::Sprk<'a, D>);
 impl_embed_index!(crate::dynamic_queries::DynamicQuery<'a, D, crate::sprk::Sprk<'a, D>>);
 impl_embed_index!(crate::embedding::Embedding<'a, D>);
+impl_embed_index!(crate::embedding::OwnedEmbedding<D>);
 impl_embed_index!(crate::measured_lsh::MeasuredLSH<'a, D>);
 impl_embed_index!(crate::random_projection_lsh::RandomProjectionLsh<'a, D>);
 impl_embed_index!(crate::lossy_queries::LossyQuery<'a, D, crate::sprk::Sprk<'a, D>>);
@@ -1,6 +1,7 @@
 use crate::{
-    Embedding, NodeId,
+    EmbeddingRef, NodeId,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, SpatialIndex},
 };
 
@@ -16,7 +17,7 @@ impl<const D: usize> crate::query::Graph for DynSprk<'_, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -68,7 +69,7 @@ impl<const D: usize> SpatialIndex<D> for DynSprk<'_, D> {
 }
 
 impl<'a, const D: usize> DynSprk<'a, D> {
-    pub fn new(embedding: &Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let flat: Vec<f32> = embedding
             .positions
             .iter()
@@ -76,14 +77,14 @@ impl<'a, const D: usize> DynSprk<'a, D> {
             .collect();
         DynSprk {
             tree: sprk::DynSprk::new(D, &flat),
-            positions: embedding.positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
         }
     }
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for DynSprk<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         Self::new(embedding)
     }
 }
@@ -1,17 +1,66 @@
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use crate::{
     NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Embedder, Graph, Position, SpatialIndex, Update},
 };
 
+/// Over-query radius unless it is adapted, see [`AdaptiveRadius`].
+pub const DEFAULT_OVER_QUERY_RADIUS: f64 = 1.1;
+
+/// Weight of the previous average in the moving average of the deltas.
+const DELTA_SMOOTHING: f64 = 0.8;
+
+/// Counters collected while answering queries.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DynamicQueryStats {
+    /// Candidates dropped because a node's cache exceeded the size cap.
+    pub dropped_candidates: usize,
+    /// Times the underlying index was updated and the caches refilled.
+    pub recomputes: usize,
+    /// Candidates returned by queries to the underlying index, the bulk of
+    /// the query work.
+    pub queried_candidates: usize,
+}
+
+/// Steers the over-query radius towards reusing the candidates of one query
+/// for `target_interval` updates. A buffer of `r - 1` lasts for about
+/// `(r - 1) / delta` updates moving the nodes by `delta`, so on every
+/// recompute the radius becomes `1 + target_interval * delta` for the moving
+/// average of the deltas, clamped to `min_radius..=max_radius`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveRadius {
+    pub target_interval: f64,
+    pub min_radius: f64,
+    pub max_radius: f64,
+}
+
+impl Default for AdaptiveRadius {
+    /// Fewest queried candidates on a random geometric graph with 20k nodes
+    fn default() -> Self {
+        AdaptiveRadius {
+            target_interval: 4.,
+            min_radius: 1.01,
+            max_radius: 1.5,
+        }
+    }
+}
+
 pub struct DynamicQuery<'a, const D: usize, ID: Embedder<'a, D>> {
     query_cache: Vec<Mutex<Vec<usize>>>,
+    max_cache_size: usize,
+    dropped_candidates: AtomicUsize,
+    queried_candidates: AtomicUsize,
+    recomputes: usize,
     structure: ID,
     positions: Vec<DVec<D>>,
     query_buffer: f64,
     over_query_radius: f64,
+    adaptive: Option<AdaptiveRadius>,
+    average_delta: Option<f64>,
     overquery: bool,
     cache_empty: bool,
     _phantom: std::marker::PhantomData<&'a ()>,
@@ -20,12 +69,22 @@ pub struct DynamicQuery<'a, const D: usize, ID: Embedder<'a, D>> {
 impl<'a, const D: usize, ID: Embedder<'a, D> + Clone> Clone for DynamicQuery<'a, D, ID> {
     fn clone(&self) -> Self {
         Self {
-            query_cache: empty_cache(self.query_cache.len()),
+            query_cache: self
+                .query_cache
+                .iter()
+                .map(|cache| Mutex::new(cache.lock().unwrap().clone()))
+                .collect(),
+            max_cache_size: self.max_cache_size,
+            dropped_candidates: AtomicUsize::new(0),
+            queried_candidates: AtomicUsize::new(0),
+            recomputes: 0,
             structure: self.structure.clone(),
             positions: self.positions.clone(),
             query_buffer: self.query_buffer,
             over_query_radius: self.over_query_radius,
-            cache_empty: false,
+            adaptive: self.adaptive,
+            average_delta: self.average_delta,
+            cache_empty: self.cache_empty,
             overquery: self.overquery,
             _phantom: std::marker::PhantomData,
         }
@@ -36,12 +95,88 @@ fn empty_cache(len: usize) -> Vec<Mutex<Vec<usize>>> {
     (0..len).map(|_| Mutex::new(Vec::new())).collect()
 }
 
+impl<'a, const D: usize, ID: Embedder<'a, D>> DynamicQuery<'a, D, ID> {
+    /// Like [`Embedder::new`], but adapts the over-query radius to the deltas
+    /// passed to [`Update::update_positions`] as configured by `adaptive`.
+    pub fn with_adaptive_radius(
+        embedding: crate::EmbeddingRef<'_, 'a, D>,
+        adaptive: AdaptiveRadius,
+    ) -> Self {
+        assert!(
+            1. < adaptive.min_radius && adaptive.min_radius <= adaptive.max_radius,
+            "over-query radius bounds must satisfy 1 < min <= max, got {}..={}",
+            adaptive.min_radius,
+            adaptive.max_radius
+        );
+        assert!(
+            adaptive.target_interval > 0.,
+            "target_interval must be positive"
+        );
+        let mut query = Self::new(embedding);
+        query.adaptive = Some(adaptive);
+        query
+    }
+
+    pub fn over_query_radius(&self) -> f64 {
+        self.over_query_radius
+    }
+
+    /// Limits the number of cached candidates per node. When a refreshed cache
+    /// exceeds the cap, the candidates with the largest weighted distance are
+    /// dropped, as they are the least likely to become neighbors. Unlimited by
+    /// default; with a cap, queries may miss neighbors and the index is no
+    /// longer [`SpatialIndex::is_exact`].
+    pub fn set_max_cache_size(&mut self, max_cache_size: usize) {
+        assert!(max_cache_size > 0, "max_cache_size must be positive");
+        self.max_cache_size = max_cache_size;
+    }
+
+    pub fn max_cache_size(&self) -> usize {
+        self.max_cache_size
+    }
+
+    pub fn stats(&self) -> DynamicQueryStats {
+        DynamicQueryStats {
+            dropped_candidates: self.dropped_candidates.load(Ordering::Relaxed),
+            recomputes: self.recomputes,
+            queried_candidates: self.queried_candidates.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Sets the radius of the next recompute from the moving average of the
+    /// deltas, see [`AdaptiveRadius`]
+    fn adapt_radius(&mut self) {
+        let (Some(adaptive), Some(delta)) = (self.adaptive, self.average_delta) else {
+            return;
+        };
+        self.over_query_radius =
+            (1. + adaptive.target_interval * delta).clamp(adaptive.min_radius, adaptive.max_radius);
+    }
+
+    fn truncate_cache(&self, index: NodeId, cache: &mut Vec<usize>) {
+        if cache.len() <= self.max_cache_size {
+            return;
+        }
+        let pos = self.position(index);
+        let weight = self.weight(index);
+        let weighted_distance = |id: usize| {
+            self.position(id).distance_squared(pos) as f64 / (weight * self.weight(id)).powi(2)
+        };
+        cache.select_nth_unstable_by(self.max_cache_size, |&a, &b| {
+            weighted_distance(a).total_cmp(&weighted_distance(b))
+        });
+        self.dropped_candidates
+            .fetch_add(cache.len() - self.max_cache_size, Ordering::Relaxed);
+        cache.truncate(self.max_cache_size);
+    }
+}
+
 impl<'a, const D: usize, ID: Embedder<'a, D>> crate::query::Graph for DynamicQuery<'a, D, ID> {
     fn is_connected(&self, first: NodeId, second: NodeId) -> bool {
         self.structure.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.structure.neighbors(index)
     }
 
@@ -67,6 +202,15 @@ impl<'a, const D: usize, ID: Embedder<'a, D>> query::Update<D> for DynamicQuery<
         // let max_deviation = last_delta.unwrap_or(10.) * 2.;
         // let max_deviation = last_delta.unwrap_or(10.) * 2.;
         let max_deviation = last_delta.unwrap_or(10.);
+        if let Some(delta) = last_delta {
+            self.average_delta = Some(self.average_delta.map_or(delta, |average| {
+                DELTA_SMOOTHING * average + (1. - DELTA_SMOOTHING) * delta
+            }));
+        }
+        let recompute = self.query_buffer - max_deviation < 1.;
+        if recompute {
+            self.adapt_radius();
+        }
 
         if self.positions.len() != positions.len() {
             self.positions = positions.to_vec();
@@ -87,8 +231,9 @@ impl<'a, const D: usize, ID: Embedder<'a, D>> query::Update<D> for DynamicQuery<
             // return;
         }
         // return;
-        if self.query_buffer - max_deviation < 1. {
+        if recompute {
             self.structure.update_positions(positions, last_delta);
+            self.recomputes += 1;
             // println!("recomputing after pos diff {}", max_deviation);
             self.cache_empty = true;
             for cache in self.query_cache.iter_mut() {
@@ -108,7 +253,11 @@ impl<'a, const D: usize, ID: Embedder<'a, D>> Query<D> for DynamicQuery<'a, D, I
         if !self.overquery {
             // TODO find out why this assert fails
             // assert!(self.query_cache[index].lock().unwrap().is_empty());
-            return self.structure.nearest_neighbors(index, radius, results);
+            let before = results.len();
+            self.structure.nearest_neighbors(index, radius, results);
+            self.queried_candidates
+                .fetch_add(results.len() - before, Ordering::Relaxed);
+            return;
         }
         assert!(
             radius <= self.query_buffer,
@@ -142,33 +291,60 @@ impl<'a, const D: usize, ID: Embedder<'a, D>> Query<D> for DynamicQuery<'a, D, I
             assert!(guard.is_empty());
             self.structure
                 .nearest_neighbors(index, self.over_query_radius, &mut guard);
+            self.queried_candidates
+                .fetch_add(guard.len(), Ordering::Relaxed);
             guard.retain(filter);
+            self.truncate_cache(index, &mut guard);
             results.extend(guard.iter().filter(|x| radius_one(x)).cloned());
         }
     }
 }
-impl<'a, const D: usize, ID: Embedder<'a, D> + Sync> SpatialIndex<D> for DynamicQuery<'a, D, ID> {
+impl<'a, const D: usize, ID: Embedder<'a, D> + SpatialIndex<D>> SpatialIndex<D>
+    for DynamicQuery<'a, D, ID>
+{
     fn name(&self) -> String {
         String::from("dynamic queries")
     }
     fn implementation_string(&self) -> &'static str {
         include_str!("dynamic_queries.rs")
     }
+    /// Dropped candidates are missing from the results
+    fn is_exact(&self) -> bool {
+        self.max_cache_size == usize::MAX && self.structure.is_exact()
+    }
+    /// Also counts the cached candidates
+    fn memory_usage_bytes(&self) -> usize {
+        let cached: usize = self
+            .query_cache
+            .iter()
+            .map(|cache| cache.lock().unwrap().capacity() * size_of::<usize>())
+            .sum();
+        self.positions.capacity() * size_of::<DVec<D>>()
+            + self.query_cache.capacity() * size_of::<Mutex<Vec<usize>>>()
+            + cached
+            + self.structure.memory_usage_bytes()
+    }
 }
 
 impl<'a, const D: usize, ID: Embedder<'a, D>> query::Embedder<'a, D> for DynamicQuery<'a, D, ID> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         let mut query = DynamicQuery {
             query_cache: empty_cache(embedding.positions.len()),
+            max_cache_size: usize::MAX,
+            dropped_candidates: AtomicUsize::new(0),
+            queried_candidates: AtomicUsize::new(0),
+            recomputes: 0,
             structure: ID::new(embedding),
             positions: vec![],
             query_buffer: 0.,
-            over_query_radius: 1.1,
+            over_query_radius: DEFAULT_OVER_QUERY_RADIUS,
+            adaptive: None,
+            average_delta: None,
             overquery: false,
             cache_empty: true,
             _phantom: std::marker::PhantomData,
         };
-        query.update_positions(&embedding.positions, None);
+        query.update_positions(embedding.positions, None);
         // assert_ne!(query.query_cache.len(), 0);
         query
     }
@@ -195,3 +371,123 @@ impl<'a, const D: usize, ID: Embedder<'a, D>> query::Embedder<'a, D> for Dynamic
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+    use super::{AdaptiveRadius, DEFAULT_OVER_QUERY_RADIUS, DynamicQuery};
+    use crate::{
+        Embedding,
+        dvec::DVec,
+        graph::Graph,
+        query::{Embedder, Query, SpatialIndex, Update},
+    };
+
+    #[test]
+    fn hub_cache_stays_bounded() {
+        // Nodes 0..200 form a path that is not connected to the hub 200 with
+        // its 50 leaves, so all path nodes are repulsion candidates of the hub.
+        let mut edges: Vec<_> = (0..199).map(|i| (i, i + 1)).collect();
+        edges.extend((201..=250).map(|i| (200, i)));
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+
+        let mut rng = SmallRng::seed_from_u64(0);
+        let mut positions: Vec<DVec<2>> = (0..graph.nodes.len())
+            .map(|_| DVec::from_fn(|_| rng.random_range(0.0..1.0)))
+            .collect();
+        let embedding = Embedding {
+            positions: positions.clone(),
+            graph: &graph,
+        };
+        let mut query = DynamicQuery::<_, Embedding<2>>::new(embedding.view());
+        let cap = 16;
+        assert!(query.is_exact());
+        query.set_max_cache_size(cap);
+        assert!(!query.is_exact());
+
+        let mut results = Vec::new();
+        for _ in 0..200 {
+            for pos in &mut positions {
+                *pos += DVec::from_fn(|_| rng.random_range(-0.005..0.005));
+            }
+            query.update_positions(&positions, Some(0.02));
+            results.clear();
+            query.nearest_neighbors(200, 1., &mut results);
+            assert!(query.query_cache[200].lock().unwrap().len() <= cap);
+        }
+        assert!(query.stats().dropped_candidates > 0);
+    }
+
+    #[test]
+    fn clone_keeps_the_query_cache() {
+        let graph = Graph::from_edge_list((0..299).map(|i| (i, i + 1)).collect(), 2, 2).unwrap();
+        let mut rng = SmallRng::seed_from_u64(1);
+        let mut positions: Vec<DVec<2>> = (0..graph.nodes.len())
+            .map(|_| DVec::from_fn(|_| rng.random_range(0.0..3.0)))
+            .collect();
+        let embedding = Embedding {
+            positions: positions.clone(),
+            graph: &graph,
+        };
+        let mut query = DynamicQuery::<_, Embedding<2>>::new(embedding.view());
+        let nodes: Vec<_> = (0..positions.len()).collect();
+        let mut results = Vec::new();
+        // Enough updates to pass a recompute with overquerying enabled
+        for _ in 0..15 {
+            for pos in &mut positions {
+                *pos += DVec::from_fn(|_| rng.random_range(-0.001..0.001));
+            }
+            query.update_positions(&positions, Some(0.01));
+            for &node in &nodes {
+                query.nearest_neighbors(node, 1., &mut results);
+            }
+        }
+        assert!(query.overquery && !query.cache_empty);
+
+        let clone = query.clone();
+        let (mut expected, mut cloned) = (Vec::new(), Vec::new());
+        for &node in &nodes {
+            query.nearest_neighbors(node, 1., &mut expected);
+            clone.nearest_neighbors(node, 1., &mut cloned);
+        }
+        assert!(!expected.is_empty());
+        assert_eq!(cloned, expected);
+    }
+
+    #[test]
+    fn radius_adapts_to_the_deltas() {
+        let graph = Graph::from_edge_list((0..99).map(|i| (i, i + 1)).collect(), 2, 2).unwrap();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let positions: Vec<DVec<2>> = (0..graph.nodes.len())
+            .map(|_| DVec::from_fn(|_| rng.random_range(0.0..10.0)))
+            .collect();
+        let embedding = Embedding {
+            positions: positions.clone(),
+            graph: &graph,
+        };
+        let adaptive = AdaptiveRadius {
+            target_interval: 5.,
+            min_radius: 1.05,
+            max_radius: 3.,
+        };
+        let mut fixed = DynamicQuery::<_, Embedding<2>>::new(embedding.view());
+        let mut query =
+            DynamicQuery::<_, Embedding<2>>::with_adaptive_radius(embedding.view(), adaptive);
+        assert_eq!(query.over_query_radius(), DEFAULT_OVER_QUERY_RADIUS);
+
+        for _ in 0..30 {
+            fixed.update_positions(&positions, Some(0.2));
+            query.update_positions(&positions, Some(0.2));
+        }
+        assert!((query.over_query_radius() - 2.).abs() < 1e-3);
+        // The fixed buffer of 0.1 is used up by every update
+        assert_eq!(fixed.stats().recomputes, 31);
+        assert!(query.stats().recomputes < 10, "{:?}", query.stats());
+
+        for _ in 0..60 {
+            query.update_positions(&positions, Some(1.));
+        }
+        assert_eq!(query.over_query_radius(), 3.);
+    }
+}
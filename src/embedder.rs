@@ -1,13 +1,18 @@
-use std::sync::Mutex;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
 
 use crate::{
     NodeId,
+    aligned::Anchors,
     dvec::Vector,
     dyn_embed::EmbedIndex,
     graph::Graph,
     query::Embedder,
+    rng::{Purpose, RngSource},
 };
-use rand::{Rng, rngs::SmallRng};
+use rand::Rng;
 use rayon::prelude::*;
 
 /// Configuration options for the embedder
@@ -20,6 +25,83 @@ pub struct EmbedderOptions {
     pub attraction_scale: f64,
     pub repulsion_scale: f64,
     pub print_timings: bool,
+    /// Every this many iterations, move stuck nodes back next to their graph
+    /// neighbours. `None` disables re-seeding.
+    pub reseed_stuck_every: Option<usize>,
+    /// A node counts as stuck if its net force, averaged over the re-seeding
+    /// interval, is below this value ...
+    pub stuck_force_threshold: f64,
+    /// ... while its local f1 score is below this value.
+    pub stuck_f1_threshold: f64,
+    /// Largest force magnitude [`WEmbedder::validate_forces`] accepts.
+    pub max_force: f64,
+    /// Call [`WEmbedder::validate_forces`] on every step in debug builds.
+    /// Off by default, as it panics on the first inconsistent step.
+    pub validate_forces: bool,
+    /// A relative position change above this multiple of the rolling median
+    /// counts as an explosion and backs off the learning rate. `None`, the
+    /// default, disables explosion detection.
+    pub explosion_factor: Option<f64>,
+    /// Number of past iterations the rolling median is taken over. Nothing
+    /// is detected before the window is full.
+    pub explosion_window: usize,
+    /// The learning rate is multiplied by this on every explosion.
+    pub explosion_backoff: f64,
+    /// Also forget the optimizer's second-moment estimates on an explosion.
+    pub explosion_reset_moments: bool,
+    /// Restore the last snapshot from [`WEmbedder::history`] if the relative
+    /// change exceeds this multiple of the rolling median. `None` never
+    /// rolls back.
+    pub explosion_rollback_factor: Option<f64>,
+    /// Number of steps at the start of [`WEmbedder::embed`] that only apply
+    /// attraction, see [`WEmbedder::calculate_step_attraction_only`].
+    pub attraction_only_warmup_steps: usize,
+    /// Build the next spatial index state on a separate rayon task while the
+    /// attraction forces, which only need the graph, are computed. The index
+    /// contents, and so the embedding, are the same either way.
+    pub overlap_index_build: bool,
+    /// Skip the attraction forces, to debug the repulsion on its own
+    pub disable_attraction: bool,
+    /// Skip the repulsion forces, to debug the attraction on its own
+    pub disable_repulsion: bool,
+    /// Every this many iterations, compare the repulsion candidates from the
+    /// spatial index to the exact repelling pairs, found by brute force. See
+    /// [`WEmbedder::repulsion_diagnostics`]. `None` disables the check.
+    pub repulsion_diagnostics_every: Option<usize>,
+    /// Also append each [`RepulsionDiagnostics`] report to this file
+    pub repulsion_diagnostics_file: Option<std::path::PathBuf>,
+    /// Every this many iterations, record
+    /// [`WEmbedder::memory_estimate_bytes`] in [`WEmbedder::memory_history`].
+    /// `None` records nothing.
+    pub memory_profile_every: Option<usize>,
+    /// Keep every position inside this box. [`WEmbedder::random`] then
+    /// draws the initial positions from it instead of the cube heuristic.
+    pub bounds: Option<Bounds>,
+    /// Update rule of the optimizer, Adam unless ablating it
+    pub optimizer: OptimizerKind,
+    /// Decay of Adam's first-moment estimate, in `0..1`
+    pub adam_beta1: f64,
+    /// Decay of Adam's second-moment estimate, in `0..1`. Lower values
+    /// recover faster from a spike in the forces.
+    pub adam_beta2: f64,
+    /// Added to the root of Adam's second moment, must be positive
+    pub adam_epsilon: f64,
+    /// Decay of the velocity of [`OptimizerKind::Momentum`], in `0..1`
+    pub momentum: f64,
+    /// Longest distance a node moves in one update. `None` does not clamp.
+    pub max_step: Option<f64>,
+    /// Replace `learning_rate` by the result of
+    /// [`WEmbedder::find_learning_rate`] over [`AUTO_LR_RANGE`] before
+    /// embedding
+    pub auto_lr: bool,
+    /// Pull between the two nodes of an anchor of
+    /// [`crate::aligned::embed_aligned`]. It grows linearly with their
+    /// distance up to this value at distance 1 and stays there.
+    pub anchor_strength: f64,
+    /// Forget the optimizer's moments when the positions oscillate, i.e.
+    /// end up close to where they were [`OSCILLATION_LAG`] iterations ago
+    /// after moving much further in between
+    pub oscillation_restart: bool,
 }
 
 impl Default for EmbedderOptions {
@@ -32,71 +114,450 @@ impl Default for EmbedderOptions {
             attraction_scale: 1.0,
             repulsion_scale: 1.0,
             print_timings: false,
+            reseed_stuck_every: None,
+            stuck_force_threshold: 0.1,
+            stuck_f1_threshold: 0.5,
+            max_force: 1e6,
+            validate_forces: false,
+            explosion_factor: None,
+            explosion_window: 20,
+            explosion_backoff: 0.5,
+            explosion_reset_moments: false,
+            explosion_rollback_factor: None,
+            attraction_only_warmup_steps: 0,
+            overlap_index_build: false,
+            disable_attraction: false,
+            disable_repulsion: false,
+            repulsion_diagnostics_every: None,
+            repulsion_diagnostics_file: None,
+            memory_profile_every: None,
+            bounds: None,
+            optimizer: OptimizerKind::Adam,
+            adam_beta1: 0.9,
+            adam_beta2: 0.999,
+            adam_epsilon: 1e-8,
+            momentum: 0.9,
+            max_step: None,
+            auto_lr: false,
+            anchor_strength: 1.0,
+            oscillation_restart: false,
+        }
+    }
+}
+
+impl EmbedderOptions {
+    /// Checks that the optimizer settings are in range. [`WEmbedder::new`]
+    /// panics with this message otherwise.
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = |name: &str, value: f64| {
+            if value > 0.0 && value.is_finite() {
+                Ok(())
+            } else {
+                Err(format!("{name} must be positive and finite, got {value}"))
+            }
+        };
+        let decay = |name: &str, value: f64| {
+            if (0.0..1.0).contains(&value) {
+                Ok(())
+            } else {
+                Err(format!("{name} must be in [0, 1), got {value}"))
+            }
+        };
+        positive("learning_rate", self.learning_rate)?;
+        if !(self.cooling_factor > 0.0 && self.cooling_factor <= 1.0) {
+            return Err(format!(
+                "cooling_factor must be in (0, 1], got {}",
+                self.cooling_factor
+            ));
+        }
+        decay("adam_beta1", self.adam_beta1)?;
+        decay("adam_beta2", self.adam_beta2)?;
+        positive("adam_epsilon", self.adam_epsilon)?;
+        decay("momentum", self.momentum)?;
+        if let Some(max_step) = self.max_step {
+            positive("max_step", max_step)?;
+        }
+        positive("anchor_strength", self.anchor_strength)?;
+        Ok(())
+    }
+
+    /// The optimizer settings as a JSON object, stored with the results so
+    /// that runs stay interpretable when the defaults change
+    pub fn optimizer_json(&self) -> String {
+        let optimizer = match self.optimizer {
+            OptimizerKind::Adam => "adam",
+            OptimizerKind::Momentum => "momentum",
+        };
+        let max_step = self
+            .max_step
+            .map_or_else(|| "null".to_string(), |max_step| max_step.to_string());
+        format!(
+            "{{\"optimizer\":\"{optimizer}\",\"learning_rate\":{},\"cooling_factor\":{},\
+             \"adam_beta1\":{},\"adam_beta2\":{},\"adam_epsilon\":{},\"momentum\":{},\
+             \"max_step\":{max_step}}}",
+            self.learning_rate,
+            self.cooling_factor,
+            self.adam_beta1,
+            self.adam_beta2,
+            self.adam_epsilon,
+            self.momentum,
+        )
+    }
+}
+
+/// How the optimizer turns the forces into position updates, see
+/// [`EmbedderOptions::optimizer`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OptimizerKind {
+    #[default]
+    Adam,
+    /// Gradient descent with heavy-ball momentum. The forces are not
+    /// normalised, so it needs a much smaller learning rate than Adam.
+    Momentum,
+}
+
+/// What happens to a coordinate that an update moves past the
+/// [`Bounds`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BoundaryPolicy {
+    /// Stop the coordinate at the wall it would cross. The other
+    /// coordinates still move, so a node on a wall slides along it.
+    #[default]
+    Clamp,
+    /// Mirror the overshoot back into the box. The node also forgets its
+    /// momentum, so it is not pushed straight back out.
+    Reflect,
+}
+
+/// Axis-aligned box the positions stay in, see [`EmbedderOptions::bounds`]
+#[derive(Clone, Debug)]
+pub struct Bounds {
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+    pub policy: BoundaryPolicy,
+}
+
+impl Bounds {
+    pub fn new<const D: usize>(
+        min: crate::dvec::DVec<D>,
+        max: crate::dvec::DVec<D>,
+        policy: BoundaryPolicy,
+    ) -> Self {
+        assert!(
+            (0..D).all(|d| min[d] < max[d]),
+            "the minimum corner must lie below the maximum corner"
+        );
+        Bounds {
+            min: min.components.to_vec(),
+            max: max.components.to_vec(),
+            policy,
+        }
+    }
+
+    pub fn contains<V: Vector>(&self, position: &V) -> bool {
+        (0..position.dim()).all(|d| self.min[d] <= position[d] && position[d] <= self.max[d])
+    }
+
+    /// Moves a node that an update took to `new` back into the box according
+    /// to the policy. Returns `None` if `new` is inside.
+    fn constrain<V: Vector>(&self, new: &V) -> Option<V> {
+        if self.contains(new) {
+            return None;
+        }
+        if self.policy == BoundaryPolicy::Reflect {
+            let reflected = V::from_fn(new.dim(), |d| {
+                let (min, max, x) = (self.min[d], self.max[d], new[d]);
+                if x < min {
+                    2.0 * min - x
+                } else if x > max {
+                    2.0 * max - x
+                } else {
+                    x
+                }
+            });
+            // Overshoots wider than the box are clamped instead
+            if self.contains(&reflected) {
+                return Some(reflected);
+            }
         }
+        Some(self.clamp(new))
+    }
+
+    /// Clamps each coordinate on its own. Scaling the whole step down
+    /// instead would freeze a node on a wall in every coordinate, including
+    /// those moving along or away from it.
+    fn clamp<V: Vector>(&self, new: &V) -> V {
+        V::from_fn(new.dim(), |d| new[d].clamp(self.min[d], self.max[d]))
     }
 }
 
-/// Adam optimizer for gradient descent, generic over vector type.
+/// Largest net force [`WEmbedder::validate_forces`] accepts, relative to the
+/// sum of all force magnitudes. Coincident nodes get a random, unbalanced
+/// displacement, so the forces never cancel exactly.
+const FORCE_BALANCE_TOLERANCE: f64 = 1e-3;
+
+/// Learning rates [`EmbedderOptions::auto_lr`] searches between, in
+/// [`AUTO_LR_STEPS`] steps
+pub const AUTO_LR_RANGE: (f64, f64) = (1e-2, 1e4);
+pub const AUTO_LR_STEPS: usize = 100;
+/// The range test of [`WEmbedder::find_learning_rate`] stops once the
+/// smoothed energy exceeds its minimum by this factor
+const LR_DIVERGENCE_FACTOR: f64 = 2.0;
+/// Weight of the previous value in the energy average of the range test
+const LR_ENERGY_SMOOTHING: f64 = 0.9;
+
+/// Iterations between the positions that
+/// [`EmbedderOptions::oscillation_restart`] compares
+pub const OSCILLATION_LAG: usize = 5;
+/// The positions oscillate if they ended up closer than this fraction of the
+/// distance travelled to where they were [`OSCILLATION_LAG`] iterations ago
+const OSCILLATION_RATIO: f64 = 0.25;
+
+/// Adam optimizer for gradient descent, generic over vector type. With
+/// [`OptimizerKind::Momentum`] it takes plain momentum steps instead, keeping
+/// the velocity in the first moment.
 pub struct AdamOptimizer<V: Vector> {
     m: Vec<V>,    // First moment estimates
     v: Vec<V>,    // Second moment estimates
     t: usize,     // Time step
+    // Steps since the moments were reset, for the bias correction
+    moment_t: usize,
     dim: usize,
 
+    kind: OptimizerKind,
     learning_rate: f64,
     cooling_factor: f64,
     beta1: f64,
     beta2: f64,
     epsilon: f64,
+    momentum: f64,
+    max_step: Option<f64>,
 }
 
 impl<V: Vector> AdamOptimizer<V> {
-    pub fn new(num_nodes: usize, dim: usize, learning_rate: f64, cooling_factor: f64) -> Self {
+    /// Optimizer with the settings of `options`, see
+    /// [`EmbedderOptions::validate`]
+    pub fn new(num_nodes: usize, dim: usize, options: &EmbedderOptions) -> Self {
         Self {
             m: vec![V::zero(dim); num_nodes],
             v: vec![V::zero(dim); num_nodes],
             t: 0,
+            moment_t: 0,
             dim,
-            learning_rate,
-            cooling_factor,
-            beta1: 0.9,
-            beta2: 0.999,
-            epsilon: 1e-8,
+            kind: options.optimizer,
+            learning_rate: options.learning_rate,
+            cooling_factor: options.cooling_factor,
+            beta1: options.adam_beta1,
+            beta2: options.adam_beta2,
+            epsilon: options.adam_epsilon,
+            momentum: options.momentum,
+            max_step: options.max_step,
         }
     }
 
     pub fn update(&mut self, positions: &mut [V], forces: &[V]) {
         self.t += 1;
+        self.moment_t += 1;
         let cooling = self.cooling_factor.powi(self.t as i32) as f32;
 
         for i in 0..positions.len() {
-            // Update biased first moment estimate
-            self.m[i] =
-                self.m[i].clone() * (self.beta1 as f32) + forces[i].clone() * ((1.0 - self.beta1) as f32);
-
-            // Update biased second moment estimate
-            let force_squared = forces[i].map(|x| x * x);
-            self.v[i] =
-                self.v[i].clone() * (self.beta2 as f32) + force_squared * ((1.0 - self.beta2) as f32);
-
-            // Compute bias-corrected moments
-            let m_hat = self.m[i].clone() / ((1.0 - self.beta1.powi(self.t as i32)) as f32);
-            let v_hat = self.v[i].clone() / ((1.0 - self.beta2.powi(self.t as i32)) as f32);
-
-            // Update parameters
-            let update = m_hat * (cooling * self.learning_rate as f32)
-                / v_hat.map(|v| v.sqrt() + self.epsilon as f32);
+            let mut update = match self.kind {
+                OptimizerKind::Adam => self.adam_step(i, &forces[i], cooling),
+                OptimizerKind::Momentum => {
+                    self.m[i] = self.m[i].clone() * (self.momentum as f32) + forces[i].clone();
+                    self.m[i].clone() * (cooling * self.learning_rate as f32)
+                }
+            };
+            if let Some(max_step) = self.max_step {
+                let length = update.magnitude();
+                if length > max_step as f32 {
+                    update = update * (max_step as f32 / length);
+                }
+            }
             positions[i] += update;
         }
     }
 
-    pub fn reset(&mut self) {
-        self.t = 0;
+    fn adam_step(&mut self, i: usize, force: &V, cooling: f32) -> V {
+        // Update biased first moment estimate
+        self.m[i] =
+            self.m[i].clone() * (self.beta1 as f32) + force.clone() * ((1.0 - self.beta1) as f32);
+
+        // Update biased second moment estimate
+        let force_squared = force.map(|x| x * x);
+        self.v[i] =
+            self.v[i].clone() * (self.beta2 as f32) + force_squared * ((1.0 - self.beta2) as f32);
+
+        // Compute bias-corrected moments
+        let m_hat = self.m[i].clone() / ((1.0 - self.beta1.powi(self.moment_t as i32)) as f32);
+        let v_hat = self.v[i].clone() / ((1.0 - self.beta2.powi(self.moment_t as i32)) as f32);
+
+        // Update parameters
+        m_hat * (cooling * self.learning_rate as f32)
+            / v_hat.map(|v| v.sqrt() + self.epsilon as f32)
+    }
+
+    pub fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    pub fn scale_learning_rate(&mut self, factor: f64) {
+        self.learning_rate *= factor;
+    }
+
+    pub fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
+    /// Forgets the second-moment estimates, so the step size adapts to the
+    /// current gradients again
+    pub fn reset_second_moments(&mut self) {
+        for v in &mut self.v {
+            *v = V::zero(self.dim);
+        }
+    }
+
+    /// Forgets the momentum of a single node
+    pub fn reset_node(&mut self, i: usize) {
+        self.m[i] = V::zero(self.dim);
+        self.v[i] = V::zero(self.dim);
+    }
+
+    /// Forgets the moments of all nodes but keeps the cooling
+    pub fn reset_moments(&mut self) {
+        self.moment_t = 0;
         for i in 0..self.m.len() {
             self.m[i] = V::zero(self.dim);
             self.v[i] = V::zero(self.dim);
         }
     }
+
+    pub fn reset(&mut self) {
+        self.t = 0;
+        self.reset_moments();
+    }
+}
+
+/// An iteration whose relative position change spiked, see
+/// [`EmbedderOptions::explosion_factor`].
+#[derive(Clone, Debug)]
+pub struct Explosion {
+    pub iteration: usize,
+    pub relative_change: f64,
+    /// Rolling median of the relative change before this iteration
+    pub median: f64,
+    /// Learning rate after the backoff
+    pub learning_rate: f64,
+    /// Iteration of the snapshot the positions were restored from
+    pub rolled_back_to: Option<u64>,
+}
+
+/// Number of missed and extra pairs kept in a [`RepulsionDiagnostics`]
+const REPULSION_DIAGNOSTICS_SAMPLE: usize = 20;
+
+/// A pair of nodes in a [`RepulsionDiagnostics`] report
+#[derive(Clone, Debug)]
+pub struct RepulsionPair {
+    pub u: NodeId,
+    pub v: NodeId,
+    pub distance: f64,
+    pub weight_u: f64,
+    pub weight_v: f64,
+}
+
+/// Difference between the repulsion candidates the spatial index found in
+/// one step and the exact set of repelling pairs, see
+/// [`EmbedderOptions::repulsion_diagnostics_every`]. Pairs are unordered and
+/// counted once.
+#[derive(Clone, Debug)]
+pub struct RepulsionDiagnostics {
+    pub iteration: usize,
+    /// Number of pairs that should repel
+    pub exact_pairs: usize,
+    /// Repelling pairs the index did not report
+    pub missed_pairs: usize,
+    /// Reported pairs that do not repel
+    pub extra_pairs: usize,
+    /// The first missed pairs, ordered by node id
+    pub missed_sample: Vec<RepulsionPair>,
+    /// The first extra pairs, ordered by node id
+    pub extra_sample: Vec<RepulsionPair>,
+}
+
+impl RepulsionDiagnostics {
+    /// Fraction of the repelling pairs the index missed
+    pub fn missed_rate(&self) -> f64 {
+        if self.exact_pairs == 0 {
+            return 0.0;
+        }
+        self.missed_pairs as f64 / self.exact_pairs as f64
+    }
+}
+
+impl std::fmt::Display for RepulsionDiagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "iteration {}: {} repelling pairs, {} missed ({:.3}%), {} extra",
+            self.iteration,
+            self.exact_pairs,
+            self.missed_pairs,
+            self.missed_rate() * 100.0,
+            self.extra_pairs
+        )?;
+        for (kind, sample) in [
+            ("missed", &self.missed_sample),
+            ("extra", &self.extra_sample),
+        ] {
+            for pair in sample {
+                writeln!(
+                    f,
+                    "  {kind} {}-{}: distance {:.4}, weights {:.4} {:.4}",
+                    pair.u, pair.v, pair.distance, pair.weight_u, pair.weight_v
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Capacity of the channel to the position logging thread. Snapshots are
+/// logged synchronously while it is full.
+const POSITION_LOG_QUEUE: usize = 10;
+
+/// Logged positions [`WEmbedder::position_uncertainty`] takes the spread of
+/// the nodes over, together with the current ones
+pub const UNCERTAINTY_SNAPSHOTS: usize = 10;
+
+/// Background thread that collects the snapshots of [`WEmbedder::history`]
+/// during [`WEmbedder::embed`], so that logging does not stall the iteration.
+/// It is handed the shared old positions and copies them itself, the
+/// embedding thread only replaces its buffer if the copy is still pending at
+/// the next step.
+struct PositionLogger<V> {
+    sender: crossbeam::channel::Sender<(u64, Arc<Vec<V>>)>,
+    thread: std::thread::JoinHandle<Vec<(u64, Vec<V>)>>,
+    // Snapshots sent to the thread, which keeps all of them until the flush
+    sent: usize,
+}
+
+impl<V: Vector> PositionLogger<V> {
+    fn spawn() -> Self {
+        let (sender, receiver) = crossbeam::channel::bounded(POSITION_LOG_QUEUE);
+        let thread = std::thread::spawn(move || {
+            receiver
+                .iter()
+                .map(|(iteration, positions): (u64, Arc<Vec<V>>)| {
+                    (iteration, positions.as_ref().clone())
+                })
+                .collect()
+        });
+        PositionLogger {
+            sender,
+            thread,
+            sent: 0,
+        }
+    }
 }
 
 /// Main weighted embedder, generic over the spatial index via [`EmbedIndex`].
@@ -105,8 +566,10 @@ pub struct WEmbedder<SI: EmbedIndex> {
     positions: Vec<SI::Vec>,
     weights: Vec<f64>,
     forces: Vec<SI::Vec>,
-    old_positions: Vec<SI::Vec>,
+    // Shared with the logging thread until it copied a snapshot
+    old_positions: Arc<Vec<SI::Vec>>,
     positions_log: Vec<(u64, Vec<SI::Vec>)>,
+    position_logger: Option<PositionLogger<SI::Vec>>,
 
     // Helpers for symmetrification
     query_cache: Vec<Vec<NodeId>>,
@@ -115,53 +578,255 @@ pub struct WEmbedder<SI: EmbedIndex> {
     // Spatial index
     pub spatial_index: SI,
 
+    // Springs between two graphs embedded side by side
+    anchors: Option<Anchors>,
+    // Whether some edge only pulls its tail, as in a directed graph
+    one_way_edges: bool,
+
     // Optimizer
     optimizer: AdamOptimizer<SI::Vec>,
 
+    // Sum of the forces per node since the last re-seeding check
+    recent_forces: Vec<SI::Vec>,
+    reseeded_nodes: usize,
+
+    // Relative changes of the last iterations, for explosion detection
+    recent_changes: VecDeque<f64>,
+    explosions: Vec<Explosion>,
+
+    // Positions of the last iterations and the length of the steps between
+    // them, for oscillation detection
+    recent_positions: VecDeque<Vec<SI::Vec>>,
+    recent_steps: VecDeque<f64>,
+    oscillation_restarts: usize,
+
+    repulsion_diagnostics: Vec<RepulsionDiagnostics>,
+    // Iteration and estimated heap usage, see `memory_profile_every`
+    memory_history: Vec<(usize, usize)>,
+
     dim: usize,
 
     // Configuration
     options: EmbedderOptions,
+    rng: RngSource,
     iteration: usize,
     last_relative_change: Option<f64>,
     print_timings: bool,
 }
 
+/// Box that [`WEmbedder::random`] draws the initial positions of `n` nodes
+/// from: [`EmbedderOptions::bounds`] if set, otherwise a cube of volume `n`
+pub(crate) fn initial_box<const D: usize>(
+    n: usize,
+    options: &EmbedderOptions,
+) -> ([f64; D], [f64; D]) {
+    if let Some(bounds) = &options.bounds {
+        let min = std::array::from_fn(|d| bounds.min[d] as f64);
+        let max = std::array::from_fn(|d| bounds.max[d] as f64);
+        return (min, max);
+    }
+    let cube_side = (n as f64).powf(1.0 / D as f64);
+    ([0.0; D], [cube_side; D])
+}
+
+/// `n` positions drawn uniformly from `min[i]..max[i]` on axis `i`
+pub(crate) fn random_positions<const D: usize>(
+    rng: RngSource,
+    n: usize,
+    min: [f64; D],
+    max: [f64; D],
+) -> Vec<crate::dvec::DVec<D>> {
+    let mut initial = rng.initial_positions();
+    (0..n)
+        .map(|_| {
+            let components: [f32; D] =
+                std::array::from_fn(|i| initial.random_range(min[i]..max[i]) as f32);
+            crate::dvec::DVec::new(components)
+        })
+        .collect()
+}
+
 /// Constructor for const-generic spatial indices that implement `Embedder<'a, D>`.
 impl<'a, SI, const D: usize> WEmbedder<SI>
 where
     SI: Embedder<'a, D> + EmbedIndex<Vec = crate::dvec::DVec<D>>,
 {
     pub fn random(seed: u64, graph: &'a Graph, options: EmbedderOptions) -> Self {
-        use crate::dvec::DVec;
-        let n = graph.nodes.len();
-
-        // Initialize random positions
-        let mut rng: SmallRng = rand::SeedableRng::seed_from_u64(seed);
-        let cube_side = (n as f64).powf(1.0 / D as f64);
-        let positions: Vec<DVec<D>> = (0..n)
-            .map(|_| {
-                let components: [f32; D] =
-                    std::array::from_fn(|_| rng.random_range(0.0..cube_side) as f32);
-                DVec::new(components)
-            })
-            .collect();
-        let spatial_index = SI::new(&crate::Embedding { positions, graph });
+        let (min, max) = initial_box::<D>(graph.nodes.len(), &options);
+        Self::random_in_box(seed, graph, options, min, max)
+    }
+
+    /// Like [`WEmbedder::random`], but draws axis `i` uniformly from
+    /// `0..ranges[i]` instead of using the same cube side for every axis.
+    pub fn random_with_ranges(
+        seed: u64,
+        graph: &'a Graph,
+        options: EmbedderOptions,
+        ranges: [f32; D],
+    ) -> Self {
+        assert!(
+            ranges.iter().all(|&r| r > 0.0),
+            "initial position ranges must be positive"
+        );
+        Self::random_in_box(seed, graph, options, [0.0; D], ranges.map(f64::from))
+    }
+
+    fn random_in_box(
+        seed: u64,
+        graph: &'a Graph,
+        options: EmbedderOptions,
+        min: [f64; D],
+        max: [f64; D],
+    ) -> Self {
+        let rng = RngSource::new(seed);
+        let positions = random_positions(rng, graph.nodes.len(), min, max);
+        let spatial_index = SI::new(crate::EmbeddingRef {
+            positions: &positions,
+            graph,
+        });
+
+        Self::new(spatial_index, options).with_rng(rng)
+    }
+
+    /// Like [`WEmbedder::random`], but starts from the PCA projection of
+    /// `source` if given, rescaled to the random initialisation cube and
+    /// jittered with `seed`, see [`crate::dim_transfer::jitter`].
+    pub fn random_or_projected(
+        seed: u64,
+        graph: &'a Graph,
+        options: EmbedderOptions,
+        source: Option<crate::dim_transfer::SourceEmbedding>,
+    ) -> Result<Self, crate::dim_transfer::UnsupportedDimension> {
+        use crate::dim_transfer::{jitter, project_source, rescale_to_cube_side};
+        let Some(source) = source else {
+            return Ok(Self::random(seed, graph, options));
+        };
+        let rng = RngSource::new(seed);
+        let mut positions = project_source::<D>(source)?;
+        assert_eq!(
+            positions.len(),
+            graph.nodes.len(),
+            "source embedding has a different number of nodes than the graph"
+        );
+        rescale_to_cube_side(&mut positions);
+        jitter(&mut positions, rng);
+        let spatial_index = SI::new(crate::EmbeddingRef {
+            positions: &positions,
+            graph,
+        });
+
+        Ok(Self::new(spatial_index, options).with_rng(rng))
+    }
+}
+
+/// Snapshot of the forces of the last step, see [`WEmbedder::force_diagnostics`].
+#[derive(Clone, Debug)]
+pub struct ForceDiagnostics<const D: usize> {
+    /// Sum of all forces. Attraction and repulsion are symmetric, so this
+    /// should stay close to zero.
+    pub net_force: crate::dvec::DVec<D>,
+    pub max_force: f64,
+    pub mean_force: f64,
+    pub weighted_centroid: crate::dvec::DVec<D>,
+    /// Distance the weighted centroid moved during the last step.
+    pub centroid_drift: f64,
+}
+
+impl<SI, const D: usize> WEmbedder<SI>
+where
+    SI: EmbedIndex<Vec = crate::dvec::DVec<D>>,
+{
+    /// Summarises the forces of the last step. A weighted centroid that keeps
+    /// drifting indicates a global bias force.
+    pub fn force_diagnostics(&self) -> ForceDiagnostics<D> {
+        use crate::dvec::{DVec, weighted_centroid};
+        let net_force: DVec<D> = self.forces.iter().copied().sum();
+        let max_force = self
+            .forces
+            .iter()
+            .map(|f| f.magnitude() as f64)
+            .fold(0.0, f64::max);
+        let mean_force = self
+            .forces
+            .iter()
+            .map(|f| f.magnitude() as f64)
+            .sum::<f64>()
+            / self.forces.len().max(1) as f64;
+
+        let centroid = weighted_centroid(&self.positions, &self.weights);
+        let old_centroid = weighted_centroid(&self.old_positions, &self.weights);
+
+        ForceDiagnostics {
+            net_force,
+            max_force,
+            mean_force,
+            weighted_centroid: centroid,
+            centroid_drift: centroid.distance(&old_centroid) as f64,
+        }
+    }
+
+    /// Like [`WEmbedder::calculate_step`], but also returns every force pair
+    /// of the step as `(node, other, force other exerts on node)`, attraction
+    /// and repulsion alike. Allocates an entry per pair, only meant for
+    /// debugging edges that do not converge.
+    pub fn calculate_step_debug_graph(&mut self) -> Vec<(NodeId, NodeId, crate::dvec::DVec<D>)> {
+        let mut pairs = Vec::new();
+        self.step(true, Some(&mut pairs));
+        pairs
+    }
+}
 
-        Self::new(spatial_index, options)
+impl<SI, const D: usize> WEmbedder<SI>
+where
+    SI: EmbedIndex<Vec = crate::dvec::DVec<D>>,
+{
+    /// Positions of the latest snapshot in [`WEmbedder::history`] taken at
+    /// or before `iteration`, bound to `graph`, so an index can be built over
+    /// an intermediate state. `graph` must be the graph being embedded.
+    /// Returns `None` if no snapshot is that old.
+    ///
+    /// Panics while [`WEmbedder::embed`] runs, e.g. in its callback, as the
+    /// history is incomplete and unordered until the logging thread is
+    /// flushed, see [`WEmbedder::flush_position_log`].
+    pub fn embedding_at<'g>(
+        &self,
+        iteration: usize,
+        graph: &'g Graph,
+    ) -> Option<crate::EmbeddingRef<'_, 'g, D>> {
+        assert_eq!(
+            graph.nodes.len(),
+            self.positions.len(),
+            "graph does not match the embedding"
+        );
+        assert!(
+            self.position_logger.is_none(),
+            "the position log is incomplete while embedding, flush it first"
+        );
+        let logged = self
+            .positions_log
+            .partition_point(|(logged, _)| *logged <= iteration as u64);
+        let (_, positions) = self.positions_log.get(logged.checked_sub(1)?)?;
+        Some(crate::EmbeddingRef { positions, graph })
     }
 }
 
 impl<SI: EmbedIndex> WEmbedder<SI> {
     pub fn new(spatial_index: SI, options: EmbedderOptions) -> Self {
+        if let Err(message) = options.validate() {
+            panic!("invalid embedder options: {message}");
+        }
         let n = spatial_index.num_nodes();
-        let learning_rate = options.learning_rate;
-        let cooling_factor = options.cooling_factor;
         let dim = if n > 0 {
             spatial_index.position(0).dim()
         } else {
             0
         };
+        if let Some(bounds) = &options.bounds {
+            assert!(
+                n == 0 || (bounds.min.len() == dim && bounds.max.len() == dim),
+                "bounds have a different dimension than the embedding"
+            );
+        }
 
         let positions: Vec<_> = (0..n)
             .map(|node| spatial_index.position(node).clone())
@@ -169,55 +834,256 @@ impl<SI: EmbedIndex> WEmbedder<SI> {
 
         // Extract weights from graph
         let weights: Vec<f64> = (0..n).map(|node| spatial_index.weight(node)).collect();
+        let one_way_edges = (0..n).any(|v| {
+            spatial_index
+                .neighbors(v)
+                .iter()
+                .any(|u| !spatial_index.neighbors(u).contains(v))
+        });
 
         Self {
             positions,
             weights,
             forces: vec![SI::Vec::zero(dim); n],
-            old_positions: vec![SI::Vec::zero(dim); n],
+            old_positions: Arc::new(vec![SI::Vec::zero(dim); n]),
             positions_log: Vec::new(),
+            position_logger: None,
             query_cache: vec![Vec::with_capacity(10); n],
             repulsion_mutexes: (0..n).map(|_| Mutex::new(Vec::with_capacity(10))).collect(),
             spatial_index,
-            optimizer: AdamOptimizer::new(n, dim, learning_rate, cooling_factor),
+            anchors: None,
+            one_way_edges,
+            optimizer: AdamOptimizer::new(n, dim, &options),
+            recent_forces: vec![SI::Vec::zero(dim); n],
+            reseeded_nodes: 0,
+            recent_changes: VecDeque::new(),
+            explosions: Vec::new(),
+            recent_positions: VecDeque::new(),
+            recent_steps: VecDeque::new(),
+            oscillation_restarts: 0,
+            repulsion_diagnostics: Vec::new(),
+            memory_history: Vec::new(),
             print_timings: options.print_timings,
             dim,
             options,
+            rng: RngSource::default(),
             iteration: 0,
             last_relative_change: None,
         }
     }
 
+    /// Draws all randomness of the embedding from `rng`, see [`crate::rng`].
+    /// [`WEmbedder::new`] uses the default seed 0.
+    pub fn with_rng(mut self, rng: RngSource) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    pub fn rng(&self) -> RngSource {
+        self.rng
+    }
+
+    /// Adds the springs of `anchors` to the forces and stops the repulsion
+    /// between the two graphs, see [`crate::aligned`]
+    pub(crate) fn with_anchors(mut self, anchors: Anchors) -> Self {
+        self.anchors = Some(anchors);
+        self
+    }
+
+    /// Re-reads the weights of `nodes` from the spatial index after the graph
+    /// changed
+    pub(crate) fn refresh_weights(&mut self, nodes: &[NodeId]) {
+        for &node in nodes {
+            self.weights[node] = self.spatial_index.weight(node);
+        }
+    }
+
     /// Run the embedding algorithm until convergence or max iterations
     pub fn embed(&mut self) -> Vec<SI::Vec> {
         self.embed_with_callback(|_| {})
     }
     /// Run the embedding algorithm until convergence or max iterations
     pub fn embed_with_callback(&mut self, mut callback: impl FnMut(&Self)) -> Vec<SI::Vec> {
+        if self.options.auto_lr {
+            let learning_rate =
+                self.find_learning_rate(AUTO_LR_RANGE.0, AUTO_LR_RANGE.1, AUTO_LR_STEPS);
+            // Later calls keep the rate found here
+            self.options.auto_lr = false;
+            self.options.learning_rate = learning_rate;
+            self.optimizer.set_learning_rate(learning_rate);
+        }
         self.optimizer.reset();
+        self.position_logger = Some(PositionLogger::spawn());
 
         loop {
             callback(self);
             self.iteration += 1;
 
-            self.calculate_step();
+            let warmup = self.iteration <= self.options.attraction_only_warmup_steps;
+            if warmup {
+                self.calculate_step_attraction_only();
+            } else {
+                if self.iteration == self.options.attraction_only_warmup_steps + 1 {
+                    // Enabling repulsion moves the nodes far more than the
+                    // warm-up did, which is not an explosion
+                    self.recent_changes.clear();
+                }
+                self.calculate_step();
+            }
 
-            // Check convergence
-            if self.check_convergence() || self.iteration >= self.options.max_iterations {
+            // Check convergence. The warm-up converges as soon as all
+            // neighbours are close, long before the embedding does.
+            let converged = self.check_convergence() && !warmup;
+            if converged || self.iteration >= self.options.max_iterations {
                 break;
             }
         }
+        self.flush_position_log();
 
         self.positions.clone()
     }
 
+    /// Learning rate range test: takes `steps` steps with learning rates
+    /// growing geometrically from `min_lr` to `max_lr` and returns the rate of
+    /// the last step before the [`WEmbedder::potential_energy`] diverged,
+    /// `max_lr` if it never did. Afterwards the positions and the optimizer
+    /// are as before, and the next step rebuilds the spatial index.
+    pub fn find_learning_rate(&mut self, min_lr: f64, max_lr: f64, steps: usize) -> f64 {
+        assert!(
+            min_lr > 0.0 && min_lr < max_lr && steps >= 2,
+            "invalid learning rate range {min_lr}..{max_lr} in {steps} steps"
+        );
+        self.flush_position_log();
+        let positions = self.positions.clone();
+        let (iteration, logged) = (self.iteration, self.positions_log.len());
+        let options = self.options.clone();
+        // Re-seeding and diagnostics would act on the trial positions
+        self.options.reseed_stuck_every = None;
+        self.options.repulsion_diagnostics_every = None;
+        self.options.memory_profile_every = None;
+        // Without cooling, every step moves at the rate it is taken with
+        let trial = EmbedderOptions {
+            learning_rate: min_lr,
+            cooling_factor: 1.0,
+            ..options.clone()
+        };
+        let optimizer = std::mem::replace(
+            &mut self.optimizer,
+            AdamOptimizer::new(self.positions.len(), self.dim, &trial),
+        );
+        // The nodes move too far for an index that relies on small changes
+        self.last_relative_change = None;
+
+        let growth = (max_lr / min_lr).powf(1.0 / (steps - 1) as f64);
+        let (mut average, mut lowest) = (0.0, f64::INFINITY);
+        let mut found = max_lr;
+        for i in 0..steps {
+            let learning_rate = min_lr * growth.powi(i as i32);
+            self.optimizer.set_learning_rate(learning_rate);
+            self.iteration += 1;
+            self.step(true, None);
+            average = LR_ENERGY_SMOOTHING * average
+                + (1.0 - LR_ENERGY_SMOOTHING) * self.potential_energy();
+            let smoothed = average / (1.0 - LR_ENERGY_SMOOTHING.powi(i as i32 + 1));
+            if !smoothed.is_finite() || smoothed > LR_DIVERGENCE_FACTOR * lowest {
+                found = learning_rate / growth;
+                break;
+            }
+            lowest = lowest.min(smoothed);
+        }
+
+        self.flush_position_log();
+        self.positions_log.truncate(logged);
+        self.positions = positions;
+        self.iteration = iteration;
+        self.optimizer = optimizer;
+        self.options = options;
+        found
+    }
+
+    /// Energy whose negative gradient the forces of
+    /// [`WEmbedder::calculate_step`] are: each edge costs `attraction_scale`
+    /// per unit of weighted distance beyond 1, each repelling pair
+    /// `repulsion_scale` per unit below 1. The repelling pairs are taken from
+    /// the candidates of the last step. Anchors add the energy of their
+    /// springs.
+    pub fn potential_energy(&self) -> f64 {
+        let weighted_distance = |u: NodeId, v: NodeId| {
+            let distance = self.positions[u]
+                .distance_squared(&self.positions[v])
+                .sqrt();
+            distance as f64 / (self.weights[u] * self.weights[v])
+        };
+        (0..self.positions.len())
+            .into_par_iter()
+            .map(|v| {
+                let mut energy = 0.0;
+                if !self.options.disable_attraction {
+                    // Edges of both directions are counted once, one-way
+                    // edges of a directed graph from their tail
+                    let once = |&u: &NodeId| u > v || !self.spatial_index.neighbors(u).contains(v);
+                    for u in self.spatial_index.neighbors(v).into_iter().filter(once) {
+                        energy += self.options.attraction_scale
+                            * (weighted_distance(u, v) - 1.0).max(0.0);
+                    }
+                }
+                if !self.options.disable_repulsion {
+                    let mut repelling: Vec<NodeId> = self.query_cache[v]
+                        .iter()
+                        .copied()
+                        .filter(|&u| u > v)
+                        .collect();
+                    repelling.sort_unstable();
+                    repelling.dedup();
+                    for u in repelling {
+                        energy +=
+                            self.options.repulsion_scale * (1.0 - weighted_distance(u, v)).max(0.0);
+                    }
+                }
+                energy
+            })
+            .sum::<f64>()
+            + self.anchor_energy()
+    }
+
+    /// Energy of the springs of [`WEmbedder::anchor_force`]
+    fn anchor_energy(&self) -> f64 {
+        let Some(anchors) = &self.anchors else {
+            return 0.0;
+        };
+        anchors
+            .pairs
+            .iter()
+            .map(|&(u, v)| {
+                let distance = self.positions[u]
+                    .distance_squared(&self.positions[v])
+                    .sqrt() as f64;
+                let stretch = distance.min(1.0);
+                self.options.anchor_strength * (stretch * stretch / 2.0 + distance - stretch)
+            })
+            .sum()
+    }
+
     pub fn calculate_step(&mut self) {
+        self.step(true, None);
+    }
+
+    /// Like [`WEmbedder::calculate_step`], but without repulsion forces, to
+    /// pull the graph together cheaply before the actual embedding.
+    pub fn calculate_step_attraction_only(&mut self) {
+        self.step(false, None);
+    }
+
+    fn step(&mut self, repulsion: bool, debug_graph: Option<&mut Vec<(NodeId, NodeId, SI::Vec)>>) {
         let update_start = std::time::Instant::now();
         // Save old positions
-        self.old_positions.clone_from(&self.positions);
+        match Arc::get_mut(&mut self.old_positions) {
+            Some(old_positions) => old_positions.clone_from(&self.positions),
+            // The logging thread has yet to copy the last snapshot
+            None => self.old_positions = Arc::new(self.positions.clone()),
+        }
         if self.iteration.is_multiple_of(10) {
-            self.positions_log
-                .push((self.iteration as u64, self.old_positions.clone()));
+            self.log_positions();
         }
 
         // Clear forces
@@ -226,27 +1092,77 @@ impl<SI: EmbedIndex> WEmbedder<SI> {
             .for_each(|f| *f = SI::Vec::zero(self.dim));
         let reset = update_start.elapsed();
 
-        // Update spatial index
-        self.update_spatial_index();
+        // Update spatial index and calculate attraction forces
+        let overlapped = self
+            .options
+            .overlap_index_build
+            .then(|| self.update_index_during_attraction());
+        if overlapped.is_none() {
+            self.update_spatial_index();
+        }
         let update_end = update_start.elapsed();
-
-        // Calculate forces
-        self.calculate_attraction_forces();
+        if overlapped.is_none() {
+            self.calculate_attraction_forces();
+        }
         let attraction_end = update_start.elapsed();
-        self.calculate_repulsion_forces();
+        let repulsion = repulsion && !self.options.disable_repulsion;
+        if repulsion {
+            self.calculate_repulsion_forces();
+        }
+        self.add_anchor_forces();
         let repulsion_end = update_start.elapsed();
+        if let Some(pairs) = debug_graph {
+            *pairs = self.force_pairs(repulsion);
+        }
+        if let Some(every) = self.options.repulsion_diagnostics_every
+            && repulsion
+            && every > 0
+            && self.iteration.is_multiple_of(every)
+        {
+            self.record_repulsion_diagnostics();
+        }
+        if let Some(every) = self.options.memory_profile_every
+            && every > 0
+            && self.iteration.is_multiple_of(every)
+        {
+            let bytes = self.memory_estimate_bytes();
+            self.memory_history.push((self.iteration, bytes));
+        }
+
+        if cfg!(debug_assertions) && self.options.validate_forces {
+            self.validate_forces();
+        }
 
         // Update positions
         self.optimizer.update(&mut self.positions, &self.forces);
         let optimizer_update = update_start.elapsed();
 
+        if let Some(every) = self.options.reseed_stuck_every {
+            for (sum, force) in self.recent_forces.iter_mut().zip(&self.forces) {
+                *sum += force.clone();
+            }
+            if every > 0 && self.iteration.is_multiple_of(every) {
+                self.reseed_stuck_nodes(every);
+            }
+        }
+        self.apply_bounds();
+
         if self.iteration.is_multiple_of(100) && self.print_timings {
             println!("reset: {}μs", reset.as_micros());
-            println!("update index: {}ms", (update_end - reset).as_millis());
-            println!(
-                "attraction: {}ms",
-                (attraction_end - update_end).as_millis()
-            );
+            if let Some((index_build, attraction)) = overlapped {
+                println!(
+                    "update index: {}ms, attraction: {}ms, overlapped: {}ms",
+                    index_build.as_millis(),
+                    attraction.as_millis(),
+                    (update_end - reset).as_millis()
+                );
+            } else {
+                println!("update index: {}ms", (update_end - reset).as_millis());
+                println!(
+                    "attraction: {}ms",
+                    (attraction_end - update_end).as_millis()
+                );
+            }
             println!(
                 "repulsion: {}ms",
                 (repulsion_end - attraction_end).as_millis()
@@ -256,15 +1172,132 @@ impl<SI: EmbedIndex> WEmbedder<SI> {
         }
     }
 
+    /// Moves the nodes that left [`EmbedderOptions::bounds`] back inside.
+    /// Nodes resting on the boundary do not move, so they do not keep the
+    /// embedding from converging.
+    fn apply_bounds(&mut self) {
+        let Some(bounds) = &self.options.bounds else {
+            return;
+        };
+        for (i, position) in self.positions.iter_mut().enumerate() {
+            if let Some(constrained) = bounds.constrain(position) {
+                *position = constrained;
+                if bounds.policy == BoundaryPolicy::Reflect {
+                    self.optimizer.reset_node(i);
+                }
+            }
+        }
+    }
+
+    /// Logs a snapshot of the positions before this step. During
+    /// [`WEmbedder::embed`] the logging thread copies it, unless that fell
+    /// behind.
+    fn log_positions(&mut self) {
+        let iteration = self.iteration as u64;
+        let Some(logger) = &mut self.position_logger else {
+            self.positions_log
+                .push((iteration, self.old_positions.as_ref().clone()));
+            return;
+        };
+        let snapshot = (iteration, Arc::clone(&self.old_positions));
+        match logger.sender.try_send(snapshot) {
+            Ok(()) => logger.sent += 1,
+            Err(error) => {
+                let (iteration, positions) = error.into_inner();
+                self.positions_log
+                    .push((iteration, positions.as_ref().clone()));
+            }
+        }
+    }
+
+    /// Waits for the thread that logs snapshots during [`WEmbedder::embed`]
+    /// and moves the snapshots it collected into [`WEmbedder::history`],
+    /// ordered by iteration. Afterwards the history is complete and later
+    /// snapshots are logged directly. [`WEmbedder::embed`] flushes before it
+    /// returns; without a logging thread this does nothing.
+    pub fn flush_position_log(&mut self) {
+        let Some(logger) = self.position_logger.take() else {
+            return;
+        };
+        drop(logger.sender);
+        let queued = logger
+            .thread
+            .join()
+            .expect("position logging thread panicked");
+        self.positions_log.extend(queued);
+        // Snapshots logged directly overtake the queued ones
+        self.positions_log.sort_by_key(|(iteration, _)| *iteration);
+    }
+
+    /// Panics unless the forces of the current step are consistent: no
+    /// component is NaN or infinite, no magnitude exceeds
+    /// [`EmbedderOptions::max_force`] and, as attraction and repulsion act in
+    /// pairs, all forces sum to about zero. The last check is skipped for
+    /// directed graphs, whose edges only pull their tail. With
+    /// [`EmbedderOptions::validate_forces`] it runs on every step in debug
+    /// builds to catch broken force implementations early.
+    pub fn validate_forces(&self) {
+        let mut net_force = SI::Vec::zero(self.dim);
+        let mut total = 0.0;
+        for (node, force) in self.forces.iter().enumerate() {
+            let magnitude = force.magnitude() as f64;
+            assert!(
+                magnitude.is_finite(),
+                "force on node {node} is not finite: {force:?}"
+            );
+            assert!(
+                magnitude <= self.options.max_force,
+                "force on node {node} exceeds the maximum of {}: {magnitude}",
+                self.options.max_force
+            );
+            net_force += force.clone();
+            total += magnitude;
+        }
+        let net = net_force.magnitude() as f64;
+        assert!(
+            self.one_way_edges || net <= FORCE_BALANCE_TOLERANCE * total,
+            "forces do not cancel out: net force {net} for a total of {total}"
+        );
+    }
+
     fn update_spatial_index(&mut self) {
         self.spatial_index
             .update_positions(&self.positions, self.last_relative_change);
     }
 
+    /// Prepares the index update on one rayon task while the attraction
+    /// forces are computed on the others, then commits it. Returns how long
+    /// each of the two took.
+    fn update_index_during_attraction(&mut self) -> (std::time::Duration, std::time::Duration) {
+        let ((prepared, index_build), (forces, attraction)) = rayon::join(
+            || {
+                let start = std::time::Instant::now();
+                let prepared = self
+                    .spatial_index
+                    .prepare_update(&self.positions, self.last_relative_change);
+                (prepared, start.elapsed())
+            },
+            || {
+                let start = std::time::Instant::now();
+                (self.attraction_forces(), start.elapsed())
+            },
+        );
+        self.forces = forces;
+        self.spatial_index.commit_update(prepared);
+        (index_build, attraction)
+    }
+
     fn calculate_attraction_forces(&mut self) {
+        self.forces = self.attraction_forces();
+    }
+
+    fn attraction_forces(&self) -> Vec<SI::Vec> {
         let dim = self.dim;
+        if self.options.disable_attraction {
+            return vec![SI::Vec::zero(dim); self.positions.len()];
+        }
         // Calculate forces for each node in parallel using neighbor lists
-        let forces: Vec<SI::Vec> = (0..self.positions.len())
+        (0..self.positions.len())
             .into_par_iter()
             .map(|v| {
                 let mut force = SI::Vec::zero(dim);
@@ -273,17 +1306,14 @@ impl<SI: EmbedIndex> WEmbedder<SI> {
                 let neighbors = self.spatial_index.neighbors(v);
 
                 // Calculate attraction force for each neighbor
-                for &u in neighbors {
+                for u in neighbors {
                     let f = self.attraction_force(v, u);
                     force += f;
                 }
 
                 force
             })
-            .collect();
-
-        // Update the forces
-        self.forces = forces;
+            .collect()
     }
 
     fn attraction_force(&self, u: NodeId, v: NodeId) -> SI::Vec {
@@ -295,8 +1325,7 @@ impl<SI: EmbedIndex> WEmbedder<SI> {
 
         if distance == 0.0 {
             // Random displacement if positions are identical
-            let mut rng = rand::rng();
-            return SI::Vec::from_fn(self.dim, |_| rng.random_range(-0.01..0.01));
+            return self.kick(Purpose::AttractionKick, u, v);
         }
 
         let weight_factor = self.weights[u] * self.weights[v];
@@ -311,6 +1340,63 @@ impl<SI: EmbedIndex> WEmbedder<SI> {
         }
     }
 
+    fn add_anchor_forces(&mut self) {
+        let Some(anchors) = &self.anchors else {
+            return;
+        };
+        for &(u, v) in &anchors.pairs {
+            let (to_v, to_u) = (self.anchor_force(u, v), self.anchor_force(v, u));
+            self.forces[u] += to_v;
+            self.forces[v] += to_u;
+        }
+    }
+
+    /// Spring force on `u` towards `v`, see [`EmbedderOptions::anchor_strength`].
+    /// Unlike an edge it has no dead zone, so anchored nodes end up on top of
+    /// each other.
+    fn anchor_force(&self, u: NodeId, v: NodeId) -> SI::Vec {
+        let direction = self.positions[v].clone() - self.positions[u].clone();
+        let distance = direction.magnitude() as f64;
+        direction * (self.options.anchor_strength / distance.max(1.0)) as f32
+    }
+
+    /// Recomputes the single forces that were summed up in this step from the
+    /// neighbors and the query cache. The kicks of coinciding nodes are drawn
+    /// from the same streams, so the pairs add up to the forces of the step.
+    fn force_pairs(&self, repulsion: bool) -> Vec<(NodeId, NodeId, SI::Vec)> {
+        let mut pairs = Vec::new();
+        for v in 0..self.positions.len() {
+            if !self.options.disable_attraction {
+                for u in self.spatial_index.neighbors(v) {
+                    pairs.push((v, u, self.attraction_force(v, u)));
+                }
+            }
+            if repulsion {
+                for &u in &self.query_cache[v] {
+                    pairs.push((v, u, self.repulsion_force(v, u)));
+                }
+            }
+        }
+        for &(u, v) in self.anchors.iter().flat_map(|anchors| &anchors.pairs) {
+            pairs.push((u, v, self.anchor_force(u, v)));
+            pairs.push((v, u, self.anchor_force(v, u)));
+        }
+        pairs
+    }
+
+    /// Displacement of `u` from `v` at the same position in this iteration.
+    /// Both directions draw from one stream and `v` is kicked the opposite
+    /// way, so the kicks cancel out like the forces they stand in for, even
+    /// when clamping piles many nodes onto one corner.
+    fn kick(&self, purpose: Purpose, u: NodeId, v: NodeId) -> SI::Vec {
+        let (low, high) = (u.min(v), u.max(v));
+        let mut rng = self
+            .rng
+            .stream(purpose, &[self.iteration as u64, low as u64, high as u64]);
+        let kick = SI::Vec::from_fn(self.dim, |_| rng.random_range(-0.01..0.01));
+        if u < v { kick } else { kick * -1.0 }
+    }
+
     pub fn print_stats(&self) {
         let (percision, recall) = self.spatial_index.graph_statistics();
         let f1 = 2. / (recall.recip() + percision.recip());
@@ -330,6 +1416,9 @@ impl<SI: EmbedIndex> WEmbedder<SI> {
                 cache.clear();
                 // Find nearby nodes that might repel
                 self.spatial_index.repelling_nodes(v, cache);
+                if let Some(anchors) = &self.anchors {
+                    cache.retain(|&u| anchors.same_graph(u, v));
+                }
 
                 for candidate in cache {
                     self.repulsion_mutexes[*candidate].lock().unwrap().push(v);
@@ -340,7 +1429,11 @@ impl<SI: EmbedIndex> WEmbedder<SI> {
             .par_iter()
             .zip(self.query_cache.par_iter_mut())
             .for_each(|(candidates, cache)| {
-                cache.extend(candidates.lock().unwrap().drain(..));
+                // Threads push in any order, sorting keeps the summation
+                // order of the forces independent of the thread count
+                let mut candidates = candidates.lock().unwrap();
+                candidates.sort_unstable();
+                cache.extend(candidates.drain(..));
             });
 
         // Stage 2: Calculate repulsion forces in parallel
@@ -374,8 +1467,7 @@ impl<SI: EmbedIndex> WEmbedder<SI> {
 
         if distance == 0.0 {
             // Random displacement if positions are identical
-            let mut rng = rand::rng();
-            return SI::Vec::from_fn(self.dim, |_| rng.random_range(-0.01..0.01));
+            return self.kick(Purpose::RepulsionKick, v, u);
         }
 
         let weight_factor = self.weights[v] * self.weights[u];
@@ -390,45 +1482,340 @@ impl<SI: EmbedIndex> WEmbedder<SI> {
         }
     }
 
-    fn check_convergence(&mut self) -> bool {
-        let (sum_norm_squared, sum_diff_squared, max_squared) = self
-            .positions
-            .iter()
-            .zip(&self.old_positions)
-            .map(|(new_pos, old_pos)| {
-                let diff = new_pos.clone() - old_pos.clone();
-                (old_pos.magnitude_squared(), diff.magnitude_squared())
+    /// Brute forces the exact repelling pairs and compares them to the
+    /// symmetrised candidates of the last [`WEmbedder::calculate_repulsion_forces`].
+    fn diagnose_repulsion(&self) -> RepulsionDiagnostics {
+        let n = self.positions.len();
+        let pair = |(u, v): (NodeId, NodeId)| RepulsionPair {
+            u,
+            v,
+            distance: self.positions[u]
+                .distance_squared(&self.positions[v])
+                .sqrt() as f64,
+            weight_u: self.weights[u],
+            weight_v: self.weights[v],
+        };
+        let (exact_pairs, missed, extra) = (0..n)
+            .into_par_iter()
+            .map(|v| {
+                let repels = |u: NodeId| {
+                    !self.spatial_index.is_connected(v, u)
+                        && (self.positions[v].distance_squared(&self.positions[u]) as f64)
+                            < (self.weights[v] * self.weights[u]).powi(2)
+                };
+                let exact: Vec<NodeId> = (v + 1..n).filter(|&u| repels(u)).collect();
+                // Every pair is in the cache of both nodes, keep it at the smaller one
+                let mut found: Vec<NodeId> = self.query_cache[v]
+                    .iter()
+                    .copied()
+                    .filter(|&u| u > v)
+                    .collect();
+                found.sort_unstable();
+                found.dedup();
+
+                let missed = exact.iter().filter(|u| found.binary_search(u).is_err());
+                let extra = found.iter().filter(|u| exact.binary_search(u).is_err());
+                let missed: Vec<_> = missed.map(|&u| (v, u)).collect();
+                let extra: Vec<_> = extra.map(|&u| (v, u)).collect();
+                (exact.len(), missed, extra)
             })
-            .fold(
-                (0.0, 0.0, 0f64),
-                |(sum_norm, sum_diff, max), (norm, diff)| {
-                    (
-                        sum_norm + norm as f64,
-                        sum_diff + diff as f64,
-                        max.max(diff as f64),
-                    )
+            .reduce(
+                || (0, Vec::new(), Vec::new()),
+                |(count, mut missed, mut extra), (other_count, other_missed, other_extra)| {
+                    missed.extend(other_missed);
+                    extra.extend(other_extra);
+                    (count + other_count, missed, extra)
                 },
             );
+        let sample = |pairs: &[(NodeId, NodeId)]| {
+            pairs
+                .iter()
+                .take(REPULSION_DIAGNOSTICS_SAMPLE)
+                .copied()
+                .map(pair)
+                .collect()
+        };
 
-        if sum_norm_squared == 0.0 {
-            return false;
+        RepulsionDiagnostics {
+            iteration: self.iteration,
+            exact_pairs,
+            missed_pairs: missed.len(),
+            extra_pairs: extra.len(),
+            missed_sample: sample(&missed),
+            extra_sample: sample(&extra),
         }
-
-        let relative_change = sum_diff_squared / sum_norm_squared;
-        self.last_relative_change = Some(max_squared.sqrt());
-        relative_change < self.options.min_position_change
     }
 
-    /// Get the current positions
+    fn record_repulsion_diagnostics(&mut self) {
+        let diagnostics = self.diagnose_repulsion();
+        if let Some(path) = &self.options.repulsion_diagnostics_file {
+            let written = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| {
+                    std::io::Write::write_all(&mut file, diagnostics.to_string().as_bytes())
+                });
+            if let Err(e) = written {
+                eprintln!(
+                    "Failed to write repulsion diagnostics to {}: {e}",
+                    path.display()
+                );
+            }
+        }
+        self.repulsion_diagnostics.push(diagnostics);
+    }
+
+    /// Local f1 score of `v`: how well the nodes within its weighted
+    /// distance 1 match its graph neighbours. Non-neighbours are taken from
+    /// the repulsion candidates of the last step.
+    fn local_f1(&self, v: NodeId) -> f64 {
+        let is_close = |u: NodeId| {
+            let distance = self.positions[v]
+                .distance_squared(&self.positions[u])
+                .sqrt() as f64;
+            distance <= self.weights[v] * self.weights[u]
+        };
+        let neighbors = self.spatial_index.neighbors(v);
+        let true_positives = neighbors.iter().filter(|&u| is_close(u)).count();
+        let false_negatives = neighbors.len() - true_positives;
+        let false_positives = self.query_cache[v]
+            .iter()
+            .filter(|&&u| u != v && !self.spatial_index.is_connected(v, u) && is_close(u))
+            .count();
+        let denominator = 2 * true_positives + false_positives + false_negatives;
+        if denominator == 0 {
+            return 1.0;
+        }
+        (2 * true_positives) as f64 / denominator as f64
+    }
+
+    /// Moves nodes that are badly placed but whose forces cancelled out over
+    /// the last `interval` iterations to a random point next to the centroid
+    /// of their neighbours.
+    fn reseed_stuck_nodes(&mut self, interval: usize) {
+        let stuck: Vec<NodeId> = (0..self.positions.len())
+            .filter(|&v| {
+                let mean_force = self.recent_forces[v].magnitude() as f64 / interval as f64;
+                !self.spatial_index.neighbors(v).is_empty()
+                    && mean_force < self.options.stuck_force_threshold
+                    && self.local_f1(v) < self.options.stuck_f1_threshold
+            })
+            .collect();
+
+        self.reseed_nodes(&stuck);
+        self.recent_forces
+            .iter_mut()
+            .for_each(|f| *f = SI::Vec::zero(self.dim));
+    }
+
+    /// Moves `nodes` to a random point next to the centroid of their
+    /// neighbours and forgets their momentum. Nodes without neighbours stay.
+    fn reseed_nodes(&mut self, nodes: &[NodeId]) {
+        for &v in nodes {
+            let mut rng = self
+                .rng
+                .stream(Purpose::Reseed, &[self.iteration as u64, v as u64]);
+            let neighbors = self.spatial_index.neighbors(v);
+            if neighbors.is_empty() {
+                continue;
+            }
+            let mut centroid = SI::Vec::zero(self.dim);
+            let mut mean_weight = 0.0;
+            for u in neighbors {
+                centroid += self.positions[u].clone();
+                mean_weight += self.weights[u];
+            }
+            let count = neighbors.len() as f32;
+            mean_weight /= neighbors.len() as f64;
+            // Stay within the distance at which v and an average neighbour connect
+            let spread = (self.weights[v] * mean_weight) as f32 / 2.0;
+            self.positions[v] = centroid / count
+                + SI::Vec::from_fn(self.dim, |_| rng.random_range(-spread..spread));
+            self.optimizer.reset_node(v);
+            self.reseeded_nodes += 1;
+        }
+    }
+
+    fn check_convergence(&mut self) -> bool {
+        let (sum_norm_squared, sum_diff_squared, max_squared) = self
+            .positions
+            .iter()
+            .zip(self.old_positions.iter())
+            .map(|(new_pos, old_pos)| {
+                let diff = new_pos.clone() - old_pos.clone();
+                (old_pos.magnitude_squared(), diff.magnitude_squared())
+            })
+            .fold(
+                (0.0, 0.0, 0f64),
+                |(sum_norm, sum_diff, max), (norm, diff)| {
+                    (
+                        sum_norm + norm as f64,
+                        sum_diff + diff as f64,
+                        max.max(diff as f64),
+                    )
+                },
+            );
+
+        if sum_norm_squared == 0.0 {
+            return false;
+        }
+
+        let relative_change = sum_diff_squared / sum_norm_squared;
+        self.last_relative_change = Some(max_squared.sqrt());
+        if self.detect_explosion(relative_change) {
+            return false;
+        }
+        let converged = relative_change < self.options.min_position_change;
+        if !converged && self.options.oscillation_restart {
+            self.detect_oscillation(sum_diff_squared.sqrt());
+        }
+        converged
+    }
+
+    /// Resets the optimizer's moments if the positions ended up close to
+    /// where they were [`OSCILLATION_LAG`] iterations ago, measured against
+    /// the `step_length`s in between, see
+    /// [`EmbedderOptions::oscillation_restart`].
+    fn detect_oscillation(&mut self, step_length: f64) {
+        self.recent_positions.push_back(self.positions.clone());
+        self.recent_steps.push_back(step_length);
+        if self.recent_steps.len() > OSCILLATION_LAG {
+            self.recent_steps.pop_front();
+        }
+        if self.recent_positions.len() <= OSCILLATION_LAG {
+            return;
+        }
+        let lagged = self.recent_positions.pop_front().unwrap();
+        let net = lagged
+            .iter()
+            .zip(&self.positions)
+            .map(|(old, new)| old.distance_squared(new) as f64)
+            .sum::<f64>()
+            .sqrt();
+        let travelled: f64 = self.recent_steps.iter().sum();
+        if net < OSCILLATION_RATIO * travelled {
+            self.optimizer.reset_moments();
+            self.recent_positions.clear();
+            self.recent_steps.clear();
+            self.oscillation_restarts += 1;
+        }
+    }
+
+    /// Compares `relative_change` to the rolling median of the last
+    /// [`EmbedderOptions::explosion_window`] iterations and backs off the
+    /// learning rate if it spiked. Returns whether this was an explosion.
+    fn detect_explosion(&mut self, relative_change: f64) -> bool {
+        let Some(factor) = self.options.explosion_factor else {
+            return false;
+        };
+        let window = self.options.explosion_window;
+        let median = (self.recent_changes.len() >= window.max(1)).then(|| {
+            let mut sorted: Vec<f64> = self.recent_changes.iter().copied().collect();
+            sorted.sort_by(f64::total_cmp);
+            sorted[sorted.len() / 2]
+        });
+        let Some(median) = median.filter(|&m| m > 0.0 && relative_change > factor * m) else {
+            self.remember_change(relative_change);
+            return false;
+        };
+
+        self.optimizer
+            .scale_learning_rate(self.options.explosion_backoff);
+        if self.options.explosion_reset_moments {
+            self.optimizer.reset_second_moments();
+        }
+        let roll_back = self
+            .options
+            .explosion_rollback_factor
+            .is_some_and(|hard| relative_change > hard * median);
+        let mut rolled_back_to = None;
+        if roll_back {
+            // The rest of the run logs directly, rollbacks are rare
+            self.flush_position_log();
+            if let Some((iteration, snapshot)) = self.positions_log.last() {
+                self.positions.clone_from(snapshot);
+                rolled_back_to = Some(*iteration);
+            }
+        }
+        if rolled_back_to.is_some() {
+            self.optimizer.reset();
+        } else {
+            // Explosive iterations that are kept stay in the window, so a run
+            // that settles in a new regime stops triggering once they make up
+            // half of it
+            self.remember_change(relative_change);
+        }
+
+        self.explosions.push(Explosion {
+            iteration: self.iteration,
+            relative_change,
+            median,
+            learning_rate: self.optimizer.learning_rate(),
+            rolled_back_to,
+        });
+        true
+    }
+
+    fn remember_change(&mut self, relative_change: f64) {
+        self.recent_changes.push_back(relative_change);
+        while self.recent_changes.len() > self.options.explosion_window {
+            self.recent_changes.pop_front();
+        }
+    }
+
+    /// Get the current positions
     pub fn positions(&self) -> &[SI::Vec] {
         &self.positions
     }
 
-    /// Get the history of positions
+    /// Get the history of positions. While [`WEmbedder::embed`] runs, e.g.
+    /// in its callback, snapshots queued for the logging thread are missing.
     pub fn history(&self) -> &[(u64, Vec<SI::Vec>)] {
         &self.positions_log
     }
 
+    /// How far each node still wanders: the root mean squared distance of
+    /// its current and last [`UNCERTAINTY_SNAPSHOTS`] logged positions from
+    /// their mean, in units of its weight, so that it compares to the radius
+    /// of its neighbourhood. Nodes held in place by many edges score low,
+    /// loosely attached ones high, so downstream consumers can downweight
+    /// them. Before anything is logged, the magnitude of the last force on
+    /// the node, also divided by its weight, stands in. Like
+    /// [`WEmbedder::history`], misses the snapshots still queued while
+    /// [`WEmbedder::embed`] runs.
+    pub fn position_uncertainty(&self) -> Vec<f32> {
+        let log = &self.positions_log;
+        let snapshots: Vec<&[SI::Vec]> = log[log.len().saturating_sub(UNCERTAINTY_SNAPSHOTS)..]
+            .iter()
+            .map(|(_, positions)| positions.as_slice())
+            .chain([self.positions.as_slice()])
+            .collect();
+        if snapshots.len() < 2 {
+            return (self.forces.iter().zip(&self.weights))
+                .map(|(force, &weight)| force.magnitude() / weight as f32)
+                .collect();
+        }
+        let count = snapshots.len() as f32;
+        (0..self.positions.len())
+            .into_par_iter()
+            .map(|v| {
+                let mean = snapshots
+                    .iter()
+                    .fold(SI::Vec::zero(self.dim), |sum, positions| {
+                        sum + positions[v].clone()
+                    })
+                    / count;
+                let variance = snapshots
+                    .iter()
+                    .map(|positions| positions[v].distance_squared(&mean))
+                    .sum::<f32>()
+                    / count;
+                variance.sqrt() / self.weights[v] as f32
+            })
+            .collect()
+    }
+
     /// Get the current iteration count
     pub fn iteration(&self) -> usize {
         self.iteration
@@ -443,6 +1830,117 @@ impl<SI: EmbedIndex> WEmbedder<SI> {
     pub fn last_pos_delta(&self) -> &Option<f64> {
         &self.last_relative_change
     }
+
+    /// Total number of times a stuck node was re-seeded, see
+    /// [`EmbedderOptions::reseed_stuck_every`]
+    pub fn reseeded_nodes(&self) -> usize {
+        self.reseeded_nodes
+    }
+
+    /// Local f1 score of every node: how well the nodes within its weighted
+    /// distance match its graph neighbours. Non-neighbours are taken from the
+    /// repulsion candidates of the last step, so call this after at least one
+    /// step.
+    pub fn per_node_f1(&self) -> Vec<f64> {
+        (0..self.positions.len())
+            .map(|v| self.local_f1(v))
+            .collect()
+    }
+
+    /// The `k` nodes with the lowest [`WEmbedder::per_node_f1`], worst first
+    pub fn worst_embedded_nodes(&self, k: usize) -> Vec<(NodeId, f64)> {
+        let mut nodes: Vec<_> = self.per_node_f1().into_iter().enumerate().collect();
+        nodes.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        nodes.truncate(k);
+        nodes
+    }
+
+    /// Re-seeds the `k` worst embedded nodes next to their neighbours, like
+    /// [`EmbedderOptions::reseed_stuck_every`] does for stuck nodes, and
+    /// returns how many were moved. Nodes whose f1 is already 1 stay.
+    pub fn reseed_worst_nodes(&mut self, k: usize) -> usize {
+        let worst: Vec<NodeId> = self
+            .worst_embedded_nodes(k)
+            .into_iter()
+            .filter(|&(_, f1)| f1 < 1.0)
+            .map(|(v, _)| v)
+            .collect();
+        let before = self.reseeded_nodes;
+        self.reseed_nodes(&worst);
+        self.reseeded_nodes - before
+    }
+
+    /// Number of times the optimizer was restarted, see
+    /// [`EmbedderOptions::oscillation_restart`]
+    pub fn oscillation_restarts(&self) -> usize {
+        self.oscillation_restarts
+    }
+
+    /// All explosions so far, in order. Check the last entry from the
+    /// callback of [`WEmbedder::embed_with_callback`] to react to new ones.
+    pub fn explosions(&self) -> &[Explosion] {
+        &self.explosions
+    }
+
+    /// All repulsion reports so far, in order, see
+    /// [`EmbedderOptions::repulsion_diagnostics_every`]. Like
+    /// [`WEmbedder::explosions`], check the last entry from the callback of
+    /// [`WEmbedder::embed_with_callback`].
+    pub fn repulsion_diagnostics(&self) -> &[RepulsionDiagnostics] {
+        &self.repulsion_diagnostics
+    }
+
+    /// Estimated heap usage in bytes of the per-node buffers (positions,
+    /// forces, weights, the optimizer's moments and the re-seeding and
+    /// oscillation history), the logged positions, including the snapshots
+    /// still held by the logging thread, the repulsion candidates and the
+    /// spatial index, see [`EmbedIndex::memory_usage_bytes`]. The graph is
+    /// not counted.
+    pub fn memory_estimate_bytes(&self) -> usize {
+        fn vectors<V: Vector>(vectors: &Vec<V>) -> usize {
+            vectors.capacity() * size_of::<V>()
+                + vectors.iter().map(Vector::heap_bytes).sum::<usize>()
+        }
+        fn lists<L>(lists: &Vec<L>, capacity: impl Fn(&L) -> usize) -> usize {
+            lists.capacity() * size_of::<L>()
+                + lists.iter().map(capacity).sum::<usize>() * size_of::<NodeId>()
+        }
+        let snapshot = size_of::<(u64, Vec<SI::Vec>)>();
+        let log = self.positions_log.capacity() * snapshot
+            + self
+                .positions_log
+                .iter()
+                .map(|(_, positions)| vectors(positions))
+                .sum::<usize>();
+        // Queued snapshots are clones of the old positions
+        let sent = self
+            .position_logger
+            .as_ref()
+            .map_or(0, |logger| logger.sent);
+        let queued = sent * (snapshot + vectors(self.old_positions.as_ref()));
+        let recent_positions = self.recent_positions.capacity() * size_of::<Vec<SI::Vec>>()
+            + self.recent_positions.iter().map(vectors).sum::<usize>();
+        vectors(&self.positions)
+            + vectors(&self.forces)
+            + vectors(self.old_positions.as_ref())
+            + self.weights.capacity() * size_of::<f64>()
+            + vectors(&self.optimizer.m)
+            + vectors(&self.optimizer.v)
+            + vectors(&self.recent_forces)
+            + recent_positions
+            + log
+            + queued
+            + lists(&self.query_cache, Vec::capacity)
+            + lists(&self.repulsion_mutexes, |m| m.lock().unwrap().capacity())
+            + self.spatial_index.memory_usage_bytes()
+    }
+
+    /// Iterations and their [`WEmbedder::memory_estimate_bytes`], see
+    /// [`EmbedderOptions::memory_profile_every`]. Steady growth over a long
+    /// run points to a leak.
+    pub fn memory_history(&self) -> &[(usize, usize)] {
+        &self.memory_history
+    }
 }
 
 #[cfg(test)]
@@ -450,11 +1948,13 @@ mod tests {
     use crate::{
         Embedding,
         dvec::DVec,
-        graph::Graph,
-        query::{Embedder, Graph as _},
+        graph::{Graph, grid},
+        lossy_queries::{LossyQuery, LossyStrategy},
+        query::{Embedder, Graph as _, SpatialIndex},
+        rng::RngSource,
     };
 
-    use super::{EmbedderOptions, WEmbedder};
+    use super::{BoundaryPolicy, Bounds, EmbedderOptions, OptimizerKind, WEmbedder};
 
     #[test]
     fn check_convergence() {
@@ -501,7 +2001,514 @@ mod tests {
                 break;
             }
         }
+
+        // Once converged, a further step barely moves the nodes
+        let converged = embedder.embed();
+        embedder.calculate_step();
+        for (before, after) in converged.iter().zip(embedder.positions()) {
+            assert!(before.approx_eq(after, 1e-3), "{before} moved to {after}");
+        }
+    }
+
+    #[test]
+    fn force_diagnostics_report_the_centroid_drift_of_a_step() {
+        let edges = (0..49).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let mut embedder: WEmbedder<Embedding<2>> =
+            WEmbedder::random(4, &graph, EmbedderOptions::default());
+        let before = embedder.positions().to_vec();
+        embedder.calculate_step();
+
+        let diagnostics = embedder.force_diagnostics();
+        let old = crate::dvec::weighted_centroid(&before, &embedder.weights);
+        let new = crate::dvec::weighted_centroid(embedder.positions(), &embedder.weights);
+        assert_eq!(diagnostics.weighted_centroid, new);
+        assert_ne!(new, old);
+        assert_eq!(diagnostics.centroid_drift, new.distance(&old) as f64);
+        assert!(diagnostics.centroid_drift > 0.0);
+        assert!(diagnostics.max_force >= diagnostics.mean_force);
+        assert!(diagnostics.mean_force > 0.0);
+    }
+
+    #[test]
+    fn forces_of_an_embedding_step_are_valid() {
+        let edges = (0..199).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            max_iterations: 20,
+            validate_forces: true,
+            ..Default::default()
+        };
+        let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(7, &graph, options);
+        for _ in 0..20 {
+            embedder.calculate_step();
+            embedder.validate_forces();
+        }
+        assert!(embedder.force_diagnostics().max_force > 0.0);
+    }
+
+    #[test]
+    fn debug_graph_pairs_add_up_to_the_forces() {
+        let edges = (0..199).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let mut embedder: WEmbedder<Embedding<2>> =
+            WEmbedder::random(7, &graph, EmbedderOptions::default());
+        for _ in 0..5 {
+            embedder.calculate_step();
+        }
+        let pairs = embedder.calculate_step_debug_graph();
+        assert!(pairs.iter().any(|&(v, u, _)| graph.is_connected(v, u)));
+        assert!(pairs.iter().any(|&(v, u, _)| !graph.is_connected(v, u)));
+
+        let mut sums = vec![DVec::<2>::zero(); graph.nodes.len()];
+        for (v, _, force) in pairs {
+            sums[v] += force;
+        }
+        for (v, (sum, force)) in sums.iter().zip(&embedder.forces).enumerate() {
+            assert!(
+                sum.distance(force) <= 1e-4 * force.magnitude().max(1.0),
+                "node {v}: pairs sum to {sum:?}, force {force:?}"
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds the maximum")]
+    fn validate_forces_rejects_large_forces() {
+        let edges = (0..199).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            max_force: 0.0,
+            ..Default::default()
+        };
+        let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(7, &graph, options);
+        embedder.calculate_step();
+        embedder.validate_forces();
+    }
+
+    #[test]
+    fn explosion_backs_off_learning_rate() {
+        let edges = (0..199).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            explosion_factor: Some(1e3),
+            explosion_rollback_factor: Some(1e4),
+            ..Default::default()
+        };
+        let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(3, &graph, options);
+        for i in 1..=300 {
+            embedder.iteration = i;
+            if i == 100 {
+                // An absurd learning rate throws every node far away
+                embedder.optimizer.scale_learning_rate(1e5);
+            }
+            embedder.calculate_step();
+            embedder.check_convergence();
+        }
+
+        let explosions = embedder.explosions();
+        assert_eq!(explosions[0].iteration, 100);
+        assert!(explosions.iter().any(|e| e.rolled_back_to.is_some()));
+        assert!(embedder.optimizer.learning_rate() < 100.0);
+        assert!(
+            embedder
+                .positions()
+                .iter()
+                .all(|p| p.components.iter().all(|c| c.is_finite()))
+        );
+        let last_change = *embedder.recent_changes.back().unwrap();
+        assert!(last_change < 1e-2, "run did not settle: {last_change}");
+    }
+
+    #[test]
+    fn embedding_at_returns_logged_snapshots() {
+        let edges = (0..99).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            max_iterations: 60,
+            min_position_change: 0.0,
+            ..Default::default()
+        };
+        let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(9, &graph, options);
+        embedder.embed();
+
+        assert!(embedder.embedding_at(5, &graph).is_none());
+        for (iteration, logged) in [(10, 10), (50, 50), (59, 50)] {
+            let snapshot = embedder.embedding_at(iteration, &graph).unwrap();
+            let (_, expected) = embedder
+                .history()
+                .iter()
+                .find(|(i, _)| *i == logged)
+                .unwrap();
+            assert_eq!(snapshot.positions, expected.as_slice());
+
+            let index = Embedding::new(snapshot);
+            assert_eq!(index.positions, *expected);
+        }
+        assert_ne!(
+            embedder.embedding_at(10, &graph).unwrap().positions,
+            embedder.embedding_at(50, &graph).unwrap().positions
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "position log is incomplete")]
+    fn embedding_at_is_rejected_while_embedding() {
+        let edges = (0..99).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            max_iterations: 30,
+            min_position_change: 0.0,
+            ..Default::default()
+        };
+        let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(9, &graph, options);
+        embedder.embed_with_callback(|embedder| {
+            if embedder.iteration() == 20 {
+                embedder.embedding_at(10, &graph);
+            }
+        });
+    }
+
+    #[test]
+    fn single_force_modes_contract_or_spread_the_graph() {
+        fn mean_distance(positions: &[DVec<2>]) -> f64 {
+            let mut sum = 0.0;
+            for (i, a) in positions.iter().enumerate() {
+                for b in &positions[..i] {
+                    sum += a.distance(b) as f64;
+                }
+            }
+            let n = positions.len();
+            sum / (n * (n - 1) / 2) as f64
+        }
+
+        let n = 50;
+        let edges = (0..n)
+            .flat_map(|i| [(i, (i + 1) % n), (i, (i * 7 + 3) % n)])
+            .filter(|(a, b)| a != b)
+            .collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let run = |options: EmbedderOptions| {
+            let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(5, &graph, options);
+            let start = mean_distance(&embedder.positions);
+            embedder.embed();
+            (start, mean_distance(&embedder.positions))
+        };
+
+        let (start, attraction_only) = run(EmbedderOptions {
+            max_iterations: 100,
+            disable_repulsion: true,
+            ..Default::default()
+        });
+        assert!(attraction_only < start, "{attraction_only} >= {start}");
+
+        let (start, repulsion_only) = run(EmbedderOptions {
+            max_iterations: 100,
+            disable_attraction: true,
+            ..Default::default()
+        });
+        assert!(repulsion_only > start, "{repulsion_only} <= {start}");
+    }
+
+    #[test]
+    fn repulsion_diagnostics_find_the_dropped_pairs() {
+        let edges = (0..2999).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            max_iterations: 4,
+            repulsion_diagnostics_every: Some(2),
+            ..Default::default()
+        };
+
+        let mut exact: WEmbedder<Embedding<2>> = WEmbedder::random(3, &graph, options.clone());
+        exact.embed();
+        let reports = exact.repulsion_diagnostics();
+        let iterations: Vec<_> = reports.iter().map(|r| r.iteration).collect();
+        assert_eq!(iterations, [2, 4]);
+        for report in reports {
+            assert_eq!((report.missed_pairs, report.extra_pairs), (0, 0));
+        }
+
+        // Crowd the nodes so every repelling list is long enough for the
+        // recall to drop about 1% of it, and stop before they spread out
+        let positions: Vec<_> = (0..graph.nodes.len())
+            .map(|i| DVec::new([(i % 50) as f32 * 0.01, (i / 50) as f32 * 0.01]))
+            .collect();
+        let lossy = LossyQuery::<2, crate::Sprk<2>>::with_rng(
+            crate::EmbeddingRef {
+                positions: &positions,
+                graph: &graph,
+            },
+            0.99,
+            LossyStrategy::Random,
+            RngSource::new(9),
+        );
+        let options = EmbedderOptions {
+            max_iterations: 2,
+            ..options
+        };
+        let mut lossy = WEmbedder::new(lossy, options);
+        lossy.embed();
+        assert_eq!(lossy.repulsion_diagnostics().len(), 1);
+        for report in lossy.repulsion_diagnostics() {
+            assert!(report.exact_pairs > 2000, "{}", report.exact_pairs);
+            assert_eq!(report.extra_pairs, 0);
+            let rate = report.missed_rate();
+            assert!((0.005..0.015).contains(&rate), "{report}");
+            assert_eq!(
+                report.missed_sample.len(),
+                report.missed_pairs.min(super::REPULSION_DIAGNOSTICS_SAMPLE)
+            );
+        }
+    }
+
+    #[test]
+    fn leaves_are_less_certain_than_nodes_of_a_clique() {
+        // A clique of ten nodes with a path of ten nodes hanging off it
+        let mut edges: Vec<_> = (0..10)
+            .flat_map(|u| (u + 1..10).map(move |v| (u, v)))
+            .collect();
+        edges.extend((9..19).map(|u| (u, u + 1)));
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            max_iterations: 200,
+            min_position_change: 0.0,
+            ..Default::default()
+        };
+        let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(3, &graph, options);
+        let before = embedder.position_uncertainty();
+        assert_eq!(before.len(), 20);
+        assert!(before.iter().all(|&u| u == 0.0));
+
+        embedder.embed();
+        let uncertainty = embedder.position_uncertainty();
+        let clique = uncertainty[..9].iter().copied().fold(0.0, f32::max);
+        assert!(clique < uncertainty[19], "{uncertainty:?}");
+    }
+
+    #[test]
+    fn memory_profile_counts_the_buffers_and_the_index() {
+        let n = 1000;
+        let edges = (0..n - 1).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            max_iterations: 30,
+            min_position_change: 0.0,
+            memory_profile_every: Some(5),
+            ..Default::default()
+        };
+
+        let mut brute_force: WEmbedder<Embedding<2>> =
+            WEmbedder::random(3, &graph, options.clone());
+        // Positions, forces, old positions, the optimizer's two moments and
+        // the copy of the index
+        let buffers = 6 * n * size_of::<DVec<2>>();
+        assert!(brute_force.memory_estimate_bytes() >= buffers);
+        brute_force.embed();
+        let history = brute_force.memory_history();
+        let iterations: Vec<_> = history.iter().map(|&(i, _)| i).collect();
+        assert_eq!(iterations, [5, 10, 15, 20, 25, 30]);
+        let bytes: Vec<_> = history.iter().map(|&(_, bytes)| bytes).collect();
+        assert!(bytes[0] >= buffers);
+        // Every tenth iteration the logging thread takes another snapshot,
+        // nothing else accumulates
+        let snapshot = n * size_of::<DVec<2>>();
+        for pair in bytes.windows(2) {
+            assert!(pair[1] >= pair[0], "{history:?}");
+        }
+        for pair in [1, 3, 5].map(|logged| &bytes[logged - 1..=logged]) {
+            let growth = pair[1] - pair[0];
+            assert!((snapshot..2 * snapshot).contains(&growth), "{history:?}");
+        }
+        for pair in [2, 4].map(|unlogged| &bytes[unlogged - 1..=unlogged]) {
+            assert!(pair[1] - pair[0] < snapshot, "{history:?}");
+        }
+
+        // The tree keeps more than the positions
+        let tree: WEmbedder<crate::Sprk<2>> = WEmbedder::random(3, &graph, options);
+        assert!(
+            tree.spatial_index.memory_usage_bytes()
+                > brute_force.spatial_index.memory_usage_bytes()
+        );
+    }
+
+    #[test]
+    fn position_log_is_complete_and_ordered() {
+        let edges = (0..99).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            max_iterations: 500,
+            min_position_change: 0.0,
+            ..Default::default()
+        };
+        let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(4, &graph, options);
+        // Positions the snapshot of the next step is taken of
+        let mut seen = Vec::new();
+        embedder.embed_with_callback(|embedder| {
+            if (embedder.iteration + 1).is_multiple_of(10) {
+                seen.push(embedder.positions().to_vec());
+            }
+        });
+
+        let logged: Vec<u64> = embedder.history().iter().map(|(i, _)| *i).collect();
+        let expected: Vec<u64> = (10..=500).step_by(10).collect();
+        assert_eq!(logged, expected);
+        assert!(embedder.position_logger.is_none());
+        // The logging thread copied the old positions before they changed
+        let snapshots: Vec<_> = embedder.history().iter().map(|(_, p)| p.clone()).collect();
+        assert_eq!(snapshots, seen);
+        embedder.flush_position_log();
+        assert_eq!(embedder.history().len(), expected.len());
+
+        // Steps driven by hand are logged right away
+        for i in 501..=530 {
+            embedder.iteration = i;
+            embedder.calculate_step();
+        }
+        let logged: Vec<u64> = embedder.history().iter().map(|(i, _)| *i).collect();
+        assert_eq!(logged[expected.len()..], [510, 520, 530]);
+    }
+
+    #[test]
+    fn random_with_ranges_scales_each_axis() {
+        let edges = (0..999).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 3, 3).unwrap();
+        let ranges = [100.0, 10.0, 0.5];
+        let embedder: WEmbedder<Embedding<3>> =
+            WEmbedder::random_with_ranges(42, &graph, EmbedderOptions::default(), ranges);
+
+        for (axis, range) in ranges.into_iter().enumerate() {
+            let values = embedder.positions().iter().map(|p| p[axis]);
+            let min = values.clone().fold(f32::INFINITY, f32::min);
+            let max = values.fold(f32::NEG_INFINITY, f32::max);
+            assert!(min >= 0.0 && max < range, "axis {axis} outside 0..{range}");
+            // 1000 uniform samples cover all but a sliver of the range
+            assert!(max - min > 0.95 * range, "axis {axis} spread {}", max - min);
+        }
+    }
+
+    #[test]
+    fn reseeding_frees_trapped_node() {
+        // A path on the x-axis with a pendant node attached to its middle but
+        // placed beyond its left end. All forces act along the axis, so the
+        // pendant cannot get past the path nodes in between on its own.
+        let n = 10;
+        let pendant = n;
+        let mut edges: Vec<_> = (0..n - 1).map(|i| (i, i + 1)).collect();
+        edges.push((n / 2, pendant));
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let spacing = 0.9 * graph.nodes[1].weight.powi(2) as f32;
+        let mut positions: Vec<_> = (0..n)
+            .map(|i| DVec::new([i as f32 * spacing, 0.0]))
+            .collect();
+        positions.push(DVec::new([-2.0 * spacing, 0.0]));
+
+        let run = |reseed_stuck_every| {
+            let options = EmbedderOptions {
+                learning_rate: 0.1,
+                max_iterations: 300,
+                reseed_stuck_every,
+                ..Default::default()
+            };
+            let embedding = Embedding {
+                positions: positions.clone(),
+                graph: &graph,
+            };
+            let mut embedder = WEmbedder::new(embedding, options);
+            embedder.embed();
+            let (precision, recall) = Embedding {
+                positions: embedder.positions().to_vec(),
+                graph: &graph,
+            }
+            .graph_statistics();
+            let f1 = 2. / (recall.recip() + precision.recip());
+            (f1, embedder.reseeded_nodes(), embedder.local_f1(pendant))
+        };
+
+        let (stuck_f1, reseeded, pendant_f1) = run(None);
+        assert_eq!(reseeded, 0);
+        assert!(pendant_f1 < 0.5, "pendant escaped on its own");
+
+        let (f1, reseeded, pendant_f1) = run(Some(20));
+        println!("f1 without re-seeding {stuck_f1}, with {f1}, re-seeded {reseeded}");
+        assert!(f1 > stuck_f1);
+        assert_eq!(pendant_f1, 1.0);
+        // Only the pendant is ever moved
+        assert!((1..=3).contains(&reseeded));
+    }
+
+    #[test]
+    fn worst_embedded_nodes_finds_the_trapped_node() {
+        // Same trap as in reseeding_frees_trapped_node
+        let n = 10;
+        let pendant = n;
+        let mut edges: Vec<_> = (0..n - 1).map(|i| (i, i + 1)).collect();
+        edges.push((n / 2, pendant));
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let spacing = 0.9 * graph.nodes[1].weight.powi(2) as f32;
+        let mut positions: Vec<_> = (0..n)
+            .map(|i| DVec::new([i as f32 * spacing, 0.0]))
+            .collect();
+        positions.push(DVec::new([-2.0 * spacing, 0.0]));
+        let options = EmbedderOptions {
+            learning_rate: 0.1,
+            ..Default::default()
+        };
+        let embedding = Embedding {
+            positions,
+            graph: &graph,
+        };
+        let mut embedder = WEmbedder::new(embedding, options);
+        for _ in 0..100 {
+            embedder.calculate_step();
+        }
+
+        let per_node = embedder.per_node_f1();
+        assert_eq!(per_node.len(), n + 1);
+        let worst = embedder.worst_embedded_nodes(1);
+        assert_eq!(worst, vec![(pendant, per_node[pendant])]);
+        assert!(worst[0].1 < 0.5);
+
+        assert_eq!(embedder.reseed_worst_nodes(1), 1);
+        for _ in 0..100 {
+            embedder.calculate_step();
+        }
+        assert_eq!(embedder.per_node_f1()[pendant], 1.0);
     }
+
+    const KNOWLEDGE_GRAPH_EDGES: [(usize, usize); 30] = [
+        (0, 1),
+        (1, 2),
+        (1, 3),
+        (1, 4),
+        (0, 5),
+        (5, 6),
+        (5, 7),
+        (5, 8),
+        (0, 9),
+        (9, 10),
+        (9, 11),
+        (9, 12),
+        (9, 13),
+        (9, 14),
+        (9, 15),
+        (9, 16),
+        (16, 17),
+        (16, 18),
+        (0, 19),
+        (19, 20),
+        (20, 21),
+        (20, 22),
+        (20, 23),
+        (20, 24),
+        (20, 25),
+        (19, 26),
+        (26, 27),
+        (26, 28),
+        (26, 29),
+        (26, 30),
+    ];
+
     #[test]
     fn knowledge_graph() {
         let nodes = [
@@ -538,38 +2545,7 @@ mod tests {
             ((-3, 3), "Plotting"),
         ];
 
-        let edges = vec![
-            (0, 1),
-            (1, 2),
-            (1, 3),
-            (1, 4),
-            (0, 5),
-            (5, 6),
-            (5, 7),
-            (5, 8),
-            (0, 9),
-            (9, 10),
-            (9, 11),
-            (9, 12),
-            (9, 13),
-            (9, 14),
-            (9, 15),
-            (9, 16),
-            (16, 17),
-            (16, 18),
-            (0, 19),
-            (19, 20),
-            (20, 21),
-            (20, 22),
-            (20, 23),
-            (20, 24),
-            (20, 25),
-            (19, 26),
-            (26, 27),
-            (26, 28),
-            (26, 29),
-            (26, 30),
-        ];
+        let edges = KNOWLEDGE_GRAPH_EDGES.to_vec();
 
         let graph = Graph::from_edge_list(edges.clone(), 2, 5).unwrap();
 
@@ -613,4 +2589,397 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn bounded_positions_stay_inside() {
+        let n = 200;
+        let edges = (0..n)
+            .flat_map(|i| [(i, (i + 1) % n), (i, (i * 7 + 3) % n)])
+            .filter(|(a, b)| a != b)
+            .collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+
+        for policy in [BoundaryPolicy::Clamp, BoundaryPolicy::Reflect] {
+            // Smaller than the ~14 wide cube the nodes would spread over
+            let bounds = Bounds::new(DVec::new([-2.0, 1.0]), DVec::new([6.0, 9.0]), policy);
+            let options = EmbedderOptions {
+                max_iterations: 100,
+                bounds: Some(bounds.clone()),
+                ..Default::default()
+            };
+            let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(5, &graph, options);
+            assert!(embedder.positions().iter().all(|p| bounds.contains(p)));
+            embedder.embed_with_callback(|embedder| {
+                let outside = embedder.positions().iter().find(|p| !bounds.contains(*p));
+                assert!(outside.is_none(), "{policy:?} left the bounds: {outside:?}");
+            });
+            assert!(embedder.positions().iter().all(|p| bounds.contains(p)));
+        }
+    }
+
+    #[test]
+    fn clamped_nodes_slide_along_the_wall() {
+        let bounds = Bounds::new(
+            DVec::new([0.0, 0.0]),
+            DVec::new([4.0, 4.0]),
+            BoundaryPolicy::Clamp,
+        );
+        // On the right wall, pushed out of it and up along it
+        let clamped = bounds.constrain(&DVec::new([5.0, 3.0])).unwrap();
+        assert_eq!(clamped, DVec::new([4.0, 3.0]));
+        // Moving back inside is not held up
+        assert!(bounds.constrain(&DVec::new([3.0, 2.0])).is_none());
+    }
+
+    #[test]
+    fn knowledge_graph_embeds_within_bounds() {
+        let graph = Graph::from_edge_list(KNOWLEDGE_GRAPH_EDGES.to_vec(), 2, 5).unwrap();
+        let bounds = Bounds::new(
+            DVec::new([-10.0, -10.0]),
+            DVec::new([10.0, 10.0]),
+            BoundaryPolicy::Clamp,
+        );
+        let options = EmbedderOptions {
+            learning_rate: 0.8,
+            bounds: Some(bounds.clone()),
+            ..Default::default()
+        };
+        let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(1, &graph, options);
+        assert!(embedder.positions().iter().all(|p| bounds.contains(p)));
+
+        let mut f1 = 0.0;
+        for _ in 0..1000 {
+            embedder.calculate_step();
+            assert!(embedder.positions().iter().all(|p| bounds.contains(p)));
+            let (precision, recall) = embedder.spatial_index.graph_statistics();
+            f1 = 2. / (recall.recip() + precision.recip());
+            if f1 == 1. {
+                break;
+            }
+        }
+        assert_eq!(f1, 1.0);
+    }
+
+    /// Kicks apart coincident nodes and re-seeding draw from seeded streams,
+    /// so the result must not depend on the number of threads
+    #[test]
+    fn embedding_is_reproducible_across_thread_counts() {
+        let n = 60;
+        let edges = (0..n)
+            .flat_map(|i| [(i, (i + 1) % n), (i, (i * 7 + 3) % n)])
+            .filter(|(a, b)| a != b)
+            .collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            max_iterations: 60,
+            reseed_stuck_every: Some(10),
+            stuck_force_threshold: f64::INFINITY,
+            stuck_f1_threshold: 1.0,
+            ..Default::default()
+        };
+        let run = |seed, threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                let mut embedder: WEmbedder<Embedding<2>> =
+                    WEmbedder::random(seed, &graph, options.clone());
+                // Neighbours 0 and 1 attract, 2 and 40 repel at the same spot
+                embedder.positions[1] = embedder.positions[0];
+                embedder.positions[40] = embedder.positions[2];
+                embedder.embed();
+                assert!(embedder.reseeded_nodes > 0);
+                embedder.positions
+            })
+        };
+
+        let single = run(7, 1);
+        assert_eq!(single, run(7, 4));
+        assert_eq!(single, run(7, 4));
+        assert_ne!(single, run(8, 4));
+    }
+
+    #[test]
+    fn overlapped_index_build_gives_the_same_embedding() {
+        fn embed<'a, SI>(graph: &'a Graph, overlap_index_build: bool) -> Vec<DVec<2>>
+        where
+            SI: Embedder<'a, 2> + crate::dyn_embed::EmbedIndex<Vec = DVec<2>>,
+        {
+            let options = EmbedderOptions {
+                max_iterations: 40,
+                overlap_index_build,
+                ..Default::default()
+            };
+            WEmbedder::<SI>::random(3, graph, options).embed()
+        }
+
+        let n = 200;
+        let edges = (0..n)
+            .flat_map(|i| [(i, (i + 1) % n), (i, (i * 7 + 3) % n)])
+            .filter(|(a, b)| a != b)
+            .collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+
+        assert_eq!(
+            embed::<crate::Sprk<2>>(&graph, false),
+            embed::<crate::Sprk<2>>(&graph, true)
+        );
+        assert_eq!(
+            embed::<crate::RandomProjectionLsh<2>>(&graph, false),
+            embed::<crate::RandomProjectionLsh<2>>(&graph, true)
+        );
+    }
+
+    #[test]
+    fn attraction_only_warmup_converges() {
+        // A binary tree, which embeds perfectly in 2D
+        let edges = (1..31).map(|i| ((i - 1) / 2, i)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            attraction_only_warmup_steps: 50,
+            ..Default::default()
+        };
+        let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(3, &graph, options);
+
+        let mut warmup_f1 = None;
+        embedder.embed_with_callback(|e| {
+            if e.iteration == 50 {
+                warmup_f1 = Some(e.spatial_index.f1());
+            }
+        });
+        // Without repulsion non-neighbours end up close together as well
+        assert!(warmup_f1.unwrap() < 1.0);
+        assert!(embedder.iteration() > 50);
+        assert_eq!(embedder.spatial_index.f1(), 1.0);
+    }
+
+    #[test]
+    fn directed_edges_only_pull_their_tail() {
+        let graph = Graph::from_directed_edge_list(vec![(0, 1)], 2, 2).unwrap();
+        let embedding = Embedding {
+            positions: vec![DVec::new([0.0, 0.0]), DVec::new([10.0, 0.0])],
+            graph: &graph,
+        };
+        let mut embedder = WEmbedder::new(embedding, EmbedderOptions::default());
+        for _ in 0..10 {
+            embedder.calculate_step();
+        }
+        assert_eq!(embedder.positions()[1], DVec::new([10.0, 0.0]));
+        assert!(embedder.positions()[0].components[0] > 0.0);
+
+        // Children only follow their parents, but the tree still embeds
+        let edges = (1..31).map(|i| (i, (i - 1) / 2)).collect();
+        let graph = Graph::from_directed_edge_list(edges, 2, 2).unwrap();
+        let mut embedder: WEmbedder<Embedding<2>> =
+            WEmbedder::random(3, &graph, EmbedderOptions::default());
+        embedder.embed();
+        assert!(embedder.spatial_index.f1() > 0.95);
+    }
+
+    /// The default optimizer settings must keep producing the embeddings
+    /// of the plain Adam update they replaced, down to the last bit
+    #[test]
+    fn default_optimizer_reproduces_the_adam_trajectory() {
+        let n = 60;
+        let edges = (0..n)
+            .flat_map(|i| [(i, (i + 1) % n), (i, (i * 7 + 3) % n)])
+            .filter(|(a, b)| a != b)
+            .collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            max_iterations: 60,
+            ..Default::default()
+        };
+        let positions = WEmbedder::<Embedding<2>>::random(7, &graph, options).embed();
+        // FNV-1a over the bits of all coordinates
+        let hash = positions
+            .iter()
+            .flat_map(|p| p.components)
+            .fold(0xcbf2_9ce4_8422_2325u64, |hash, x| {
+                (hash ^ x.to_bits() as u64).wrapping_mul(0x100_0000_01b3)
+            });
+        assert_eq!(hash, 0x3d69_8e30_c3c3_a786);
+    }
+
+    #[test]
+    fn options_validation_rejects_out_of_range_optimizer_settings() {
+        assert_eq!(EmbedderOptions::default().validate(), Ok(()));
+        let invalid = [
+            EmbedderOptions {
+                learning_rate: 0.0,
+                ..Default::default()
+            },
+            EmbedderOptions {
+                cooling_factor: 1.5,
+                ..Default::default()
+            },
+            EmbedderOptions {
+                adam_beta1: 1.0,
+                ..Default::default()
+            },
+            EmbedderOptions {
+                adam_beta2: -0.1,
+                ..Default::default()
+            },
+            EmbedderOptions {
+                adam_epsilon: 0.0,
+                ..Default::default()
+            },
+            EmbedderOptions {
+                momentum: f64::NAN,
+                ..Default::default()
+            },
+            EmbedderOptions {
+                max_step: Some(f64::INFINITY),
+                ..Default::default()
+            },
+        ];
+        for options in invalid {
+            assert!(options.validate().is_err(), "{options:?}");
+        }
+
+        let graph = Graph::from_edge_list(vec![(0, 1)], 2, 2).unwrap();
+        let options = EmbedderOptions {
+            adam_beta2: 1.0,
+            ..Default::default()
+        };
+        let result = std::panic::catch_unwind(|| {
+            WEmbedder::<Embedding<2>>::random(0, &graph, options);
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn oscillation_restart_settles_an_oscillating_pair() {
+        // Adam's first steps carry the two nodes past each other, after
+        // which they swing back and forth until the cooling stops them
+        let graph = Graph::from_edge_list(vec![(0, 1)], 2, 2).unwrap();
+        let embed = |oscillation_restart| {
+            let embedding = Embedding {
+                positions: vec![DVec::new([0., 0.]), DVec::new([3., 0.])],
+                graph: &graph,
+            };
+            let options = EmbedderOptions {
+                oscillation_restart,
+                ..Default::default()
+            };
+            let mut embedder = WEmbedder::new(embedding, options);
+            let positions = embedder.embed();
+            let distance = positions[0].distance(&positions[1]) as f64;
+            (
+                embedder.iteration(),
+                embedder.oscillation_restarts(),
+                distance,
+            )
+        };
+        let (plain, _, _) = embed(false);
+        let (restarted, restarts, distance) = embed(true);
+        assert!(restarts > 0);
+        assert!(restarted < plain / 2, "{restarted} vs {plain} iterations");
+        assert!(distance <= graph.nodes[0].weight * graph.nodes[1].weight);
+    }
+
+    #[test]
+    fn optimizer_json_records_every_setting() {
+        assert_eq!(
+            EmbedderOptions::default().optimizer_json(),
+            "{\"optimizer\":\"adam\",\"learning_rate\":10,\"cooling_factor\":0.99,\
+             \"adam_beta1\":0.9,\"adam_beta2\":0.999,\"adam_epsilon\":0.00000001,\
+             \"momentum\":0.9,\"max_step\":null}"
+        );
+        let options = EmbedderOptions {
+            optimizer: OptimizerKind::Momentum,
+            max_step: Some(0.5),
+            ..Default::default()
+        };
+        let json = options.optimizer_json();
+        assert!(json.contains("\"optimizer\":\"momentum\""));
+        assert!(json.ends_with("\"max_step\":0.5}"));
+    }
+
+    #[test]
+    fn optimizer_settings_change_the_steps() {
+        let edges = (0..49).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let first_step = |options: EmbedderOptions| {
+            let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(5, &graph, options);
+            let before = embedder.positions.clone();
+            embedder.calculate_step();
+            before
+                .iter()
+                .zip(&embedder.positions)
+                .map(|(a, b)| a.distance(b))
+                .fold(0.0f32, f32::max)
+        };
+
+        let default_step = first_step(EmbedderOptions::default());
+        let clamped = first_step(EmbedderOptions {
+            max_step: Some(0.01),
+            ..Default::default()
+        });
+        assert!(default_step > 0.01);
+        assert!(clamped <= 0.01 * (1.0 + 1e-5));
+
+        let momentum = first_step(EmbedderOptions {
+            optimizer: OptimizerKind::Momentum,
+            learning_rate: 1e-3,
+            ..Default::default()
+        });
+        assert!(momentum > 0.0 && momentum != default_step);
+    }
+
+    #[test]
+    fn found_learning_rate_converges_faster_than_the_default() {
+        // Large enough that the default rate cools down before the grid has
+        // unfolded
+        let graph = grid(20);
+        // Iteration at which the energy first drops below a thousandth of
+        // the energy after the first step
+        let converged_at = |auto_lr| {
+            let options = EmbedderOptions {
+                auto_lr,
+                ..Default::default()
+            };
+            let mut embedder: WEmbedder<Embedding<2>> = WEmbedder::random(3, &graph, options);
+            let mut initial = None;
+            let mut converged_at = None;
+            embedder.embed_with_callback(|embedder| {
+                if embedder.iteration() == 1 {
+                    initial = Some(embedder.potential_energy());
+                }
+                if let Some(initial) = initial
+                    && converged_at.is_none()
+                    && embedder.potential_energy() < 1e-3 * initial
+                {
+                    converged_at = Some(embedder.iteration());
+                }
+            });
+            (converged_at, embedder.options.learning_rate)
+        };
+        let (default, default_lr) = converged_at(false);
+        let (found, found_lr) = converged_at(true);
+        assert_ne!(found_lr, default_lr);
+        let found = found.expect("the found learning rate does not converge");
+        assert!(
+            default.is_none_or(|default| found < default),
+            "{found} vs {default:?}"
+        );
+    }
+
+    #[test]
+    fn finding_the_learning_rate_leaves_the_embedder_unchanged() {
+        let graph = grid(8);
+        let options = EmbedderOptions {
+            max_iterations: 30,
+            ..Default::default()
+        };
+        let mut searched: WEmbedder<Embedding<2>> = WEmbedder::random(5, &graph, options.clone());
+        let mut fresh: WEmbedder<Embedding<2>> = WEmbedder::random(5, &graph, options);
+        let learning_rate = searched.find_learning_rate(1e-2, 1e4, 40);
+        assert!((1e-2..=1e4).contains(&learning_rate));
+        assert_eq!(searched.iteration(), 0);
+        assert_eq!(searched.embed(), fresh.embed());
+        assert_eq!(searched.history().len(), fresh.history().len());
+    }
 }
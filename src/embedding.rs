@@ -1,7 +1,12 @@
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
 use crate::{
     NodeId, Query,
     dvec::DVec,
-    query::{self, Graph, Position, SpatialIndex},
+    graph::Neighbors,
+    query::{self, Graph, SpatialIndex},
 };
 
 #[derive(Clone)]
@@ -10,12 +15,205 @@ pub struct Embedding<'a, const D: usize> {
     pub graph: &'a crate::graph::Graph,
 }
 
+/// Borrowed positions and graph of an embedding. Spatial indices are built
+/// from this view and copy only what they keep, in their own layout.
+#[derive(Clone, Copy)]
+pub struct EmbeddingRef<'e, 'a, const D: usize> {
+    pub positions: &'e [DVec<D>],
+    pub graph: &'a crate::graph::Graph,
+}
+
+impl<'a, const D: usize> EmbeddingRef<'_, 'a, D> {
+    /// Copies the positions into an owned [`Embedding`].
+    pub fn to_embedding(&self) -> Embedding<'a, D> {
+        Embedding {
+            positions: self.positions.to_vec(),
+            graph: self.graph,
+        }
+    }
+}
+
+impl<'e, 'a, const D: usize> From<&'e Embedding<'a, D>> for EmbeddingRef<'e, 'a, D> {
+    fn from(embedding: &'e Embedding<'a, D>) -> Self {
+        embedding.view()
+    }
+}
+
+/// An [`Embedding`] that shares ownership of its graph, so it is `'static`
+/// and can be moved to other threads or async tasks.
+#[derive(Clone)]
+pub struct OwnedEmbedding<const D: usize> {
+    pub positions: Vec<DVec<D>>,
+    pub graph: Arc<crate::graph::Graph>,
+}
+
+impl<'a, const D: usize> Embedding<'a, D> {
+    /// Borrowed view of the positions and the graph
+    pub fn view(&self) -> EmbeddingRef<'_, 'a, D> {
+        EmbeddingRef {
+            positions: &self.positions,
+            graph: self.graph,
+        }
+    }
+
+    /// Centroid of all positions weighted by the node weights.
+    pub fn weighted_centroid(&self) -> DVec<D> {
+        let weights: Vec<f64> = (0..self.positions.len()).map(|i| self.weight(i)).collect();
+        crate::dvec::weighted_centroid(&self.positions, &weights)
+    }
+
+    /// The graph the embedding predicts: an edge between every pair of nodes
+    /// within weighted distance 1, found with a [`crate::Sprk`] through
+    /// [`Query::nearest_neighbor_pairs`]. The nodes keep the weights of
+    /// [`Embedding::graph`] instead of deriving new ones from their degrees,
+    /// so a perfect embedding predicts its input graph. They also keep its
+    /// [`crate::graph::Graph::original_ids`].
+    pub fn predicted_graph(&self) -> crate::graph::Graph {
+        let index: crate::Sprk<D> = query::Embedder::new(self.view());
+        let pairs = index.nearest_neighbor_pairs(1.);
+        let neighbors = query::pairs_to_lists(&pairs, self.positions.len());
+        let weights = (0..self.positions.len()).map(|i| self.weight(i)).collect();
+        let mut graph = crate::graph::Graph::from_adjacency(weights, neighbors);
+        graph.original_ids = self.graph.original_ids.clone();
+        graph
+    }
+
+    /// Estimates the parameters of a GIRG that would generate a graph with
+    /// similar statistics as this embedding, see [`crate::girg::estimate`].
+    pub fn estimate_girg_parameters(&self) -> crate::girg::GirgParams {
+        crate::girg::estimate(&self.positions, self.graph)
+    }
+
+    /// Copies the positions and the graph into an [`OwnedEmbedding`].
+    pub fn to_owned_embedding(&self) -> OwnedEmbedding<D> {
+        OwnedEmbedding {
+            positions: self.positions.clone(),
+            graph: Arc::new(self.graph.clone()),
+        }
+    }
+
+    /// Id, position and graph neighbours of every node. The neighbours are
+    /// [`Neighbors`], which iterates over [`NodeId`]s, as the graph stores
+    /// them compactly.
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &DVec<D>, Neighbors<'a>)> + '_ {
+        self.positions
+            .iter()
+            .zip(&self.graph.nodes)
+            .enumerate()
+            .map(|(id, (position, node))| (id, position, node.neighbor_ids()))
+    }
+
+    /// Like [`Embedding::nodes`], but only the nodes with weight at least 1
+    pub fn heavy_nodes(&self) -> impl Iterator<Item = (NodeId, &DVec<D>, Neighbors<'a>)> + '_ {
+        self.nodes()
+            .filter(|&(id, ..)| self.graph.nodes[id].weight >= 1.)
+    }
+}
+
+impl<const D: usize> OwnedEmbedding<D> {
+    /// Embedding at `positions` that shares `graph` with the caller.
+    /// [`query::Embedder::new`] only gets a borrowed graph and copies it.
+    pub fn with_shared_graph(positions: Vec<DVec<D>>, graph: Arc<crate::graph::Graph>) -> Self {
+        assert_eq!(
+            positions.len(),
+            graph.nodes.len(),
+            "graph does not match the positions"
+        );
+        OwnedEmbedding { positions, graph }
+    }
+
+    /// Borrowed view of the positions and the graph
+    pub fn view(&self) -> EmbeddingRef<'_, '_, D> {
+        EmbeddingRef {
+            positions: &self.positions,
+            graph: &self.graph,
+        }
+    }
+
+    /// Copy of the embedding that borrows the graph
+    pub fn as_embedding(&self) -> Embedding<'_, D> {
+        Embedding {
+            positions: self.positions.clone(),
+            graph: &self.graph,
+        }
+    }
+}
+
+fn within_distance_squared<const D: usize>(a: &DVec<D>, b: &DVec<D>, bound: f64) -> bool {
+    // Without a check before the last component there is nothing to skip
+    if D <= crate::dvec::BOUND_CHECK_COMPONENTS {
+        return (a.distance_squared(b) as f64) <= bound;
+    }
+    // No f32 distance lies between the bound and its f32 rounding, so the
+    // result is the same as without the early exit
+    a.distance_squared_bounded(b, bound as f32)
+        .is_some_and(|distance| distance as f64 <= bound)
+}
+
+fn brute_force_neighbors<const D: usize>(
+    graph: &crate::graph::Graph,
+    positions: &[DVec<D>],
+    index: usize,
+    radius: f64,
+    results: &mut Vec<NodeId>,
+) {
+    let own_weight = graph.weight(index);
+    let own_position = &positions[index];
+
+    for (i, (node, position)) in graph
+        .nodes
+        .iter()
+        .zip(positions.iter())
+        .enumerate()
+        .take(index)
+    {
+        let weight = own_weight * node.weight;
+        if within_distance_squared(own_position, position, (weight * radius).powi(2)) {
+            results.push(i);
+        }
+    }
+}
+
+/// The pairs of [`brute_force_neighbors`], which only looks at the nodes
+/// before the query node and so finds every pair from one end
+fn brute_force_pairs<const D: usize>(
+    graph: &crate::graph::Graph,
+    positions: &[DVec<D>],
+    radius: f64,
+) -> Vec<(NodeId, NodeId)> {
+    let mut pairs: Vec<_> = (0..positions.len())
+        .into_par_iter()
+        .flat_map_iter(|v| {
+            let mut earlier = Vec::new();
+            brute_force_neighbors(graph, positions, v, radius, &mut earlier);
+            earlier.into_iter().map(move |u| (u, v))
+        })
+        .collect();
+    pairs.par_sort_unstable();
+    pairs
+}
+
+fn brute_force_radius<const D: usize>(
+    positions: &[DVec<D>],
+    pos: DVec<D>,
+    radius: f64,
+    results: &mut Vec<NodeId>,
+) {
+    let radius_squared = radius.powi(2);
+
+    for (i, position) in positions.iter().enumerate() {
+        if within_distance_squared(&pos, position, radius_squared) {
+            results.push(i);
+        }
+    }
+}
+
 impl<'a, const D: usize> crate::query::Graph for Embedding<'a, D> {
     fn is_connected(&self, first: NodeId, second: NodeId) -> bool {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -39,34 +237,15 @@ impl<'a, const D: usize> query::Update<D> for Embedding<'a, D> {
 
 impl<const D: usize> Query<D> for Embedding<'_, D> {
     fn nearest_neighbors(&self, index: usize, radius: f64, results: &mut Vec<NodeId>) {
-        let graph = self.graph;
-        let own_weight = self.weight(index);
-        let own_position = self.position(index);
+        brute_force_neighbors(self.graph, &self.positions, index, radius, results);
+    }
 
-        for (i, (node, position)) in graph
-            .nodes
-            .iter()
-            .zip(self.positions.iter())
-            .enumerate()
-            .take(index)
-        {
-            let weight = own_weight * node.weight;
-            let distance = own_position.distance_squared(position);
-            if (distance as f64) <= (weight * radius).powi(2) {
-                results.push(i);
-            }
-        }
+    fn nearest_neighbor_pairs(&self, radius: f64) -> Vec<(NodeId, NodeId)> {
+        brute_force_pairs(self.graph, &self.positions, radius)
     }
 
     fn query_radius(&self, pos: DVec<D>, radius: f64, results: &mut Vec<NodeId>) {
-        let radius_squared = radius.powi(2);
-
-        for (i, position) in self.positions.iter().enumerate() {
-            let distance = pos.distance_squared(position);
-            if (distance as f64) <= radius_squared {
-                results.push(i);
-            }
-        }
+        brute_force_radius(&self.positions, pos, radius, results);
     }
 }
 impl<const D: usize> SpatialIndex<D> for Embedding<'_, D> {
@@ -79,8 +258,8 @@ impl<const D: usize> SpatialIndex<D> for Embedding<'_, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for Embedding<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
-        embedding.clone()
+    fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
+        embedding.to_embedding()
     }
 
     fn repelling_nodes(&self, index: usize, result: &mut Vec<NodeId>) {
@@ -89,3 +268,224 @@ impl<'a, const D: usize> query::Embedder<'a, D> for Embedding<'a, D> {
         result.retain(|&x| !self.is_connected(index, x));
     }
 }
+
+impl<const D: usize> crate::query::Graph for OwnedEmbedding<D> {
+    fn is_connected(&self, first: NodeId, second: NodeId) -> bool {
+        self.graph.is_connected(first, second)
+    }
+
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
+        self.graph.neighbors(index)
+    }
+
+    fn weight(&self, index: NodeId) -> f64 {
+        self.graph.weight(index)
+    }
+}
+impl<const D: usize> query::Position<D> for OwnedEmbedding<D> {
+    fn position(&self, index: NodeId) -> &DVec<D> {
+        &self.positions[index]
+    }
+    fn num_nodes(&self) -> usize {
+        self.positions.len()
+    }
+}
+impl<const D: usize> query::Update<D> for OwnedEmbedding<D> {
+    fn update_positions(&mut self, postions: &[DVec<D>], _: Option<f64>) {
+        self.positions = postions.to_vec();
+    }
+}
+
+impl<const D: usize> Query<D> for OwnedEmbedding<D> {
+    fn nearest_neighbors(&self, index: usize, radius: f64, results: &mut Vec<NodeId>) {
+        brute_force_neighbors(&self.graph, &self.positions, index, radius, results);
+    }
+
+    fn nearest_neighbor_pairs(&self, radius: f64) -> Vec<(NodeId, NodeId)> {
+        brute_force_pairs(&self.graph, &self.positions, radius)
+    }
+
+    fn query_radius(&self, pos: DVec<D>, radius: f64, results: &mut Vec<NodeId>) {
+        brute_force_radius(&self.positions, pos, radius, results);
+    }
+}
+impl<const D: usize> SpatialIndex<D> for OwnedEmbedding<D> {
+    fn name(&self) -> String {
+        String::from("brute-force")
+    }
+    fn implementation_string(&self) -> &'static str {
+        include_str!("embedding.rs")
+    }
+}
+
+impl<'a, const D: usize> query::Embedder<'a, D> for OwnedEmbedding<D> {
+    /// Copies the graph, see [`OwnedEmbedding::with_shared_graph`] to share it
+    fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
+        OwnedEmbedding {
+            positions: embedding.positions.to_vec(),
+            graph: Arc::new(embedding.graph.clone()),
+        }
+    }
+
+    fn repelling_nodes(&self, index: usize, result: &mut Vec<NodeId>) {
+        self.nearest_neighbors(index, 1., result);
+
+        result.retain(|&x| !self.is_connected(index, x));
+    }
+}
+
+impl<const D: usize> crate::embedder::WEmbedder<OwnedEmbedding<D>> {
+    /// Like [`crate::embedder::WEmbedder::random`], but shares `graph`
+    /// instead of copying it
+    pub fn random_shared(
+        seed: u64,
+        graph: Arc<crate::graph::Graph>,
+        options: crate::embedder::EmbedderOptions,
+    ) -> Self {
+        let n = graph.nodes.len();
+        let (min, max) = crate::embedder::initial_box::<D>(n, &options);
+        let rng = crate::rng::RngSource::new(seed);
+        let positions = crate::embedder::random_positions(rng, n, min, max);
+        Self::new(OwnedEmbedding::with_shared_graph(positions, graph), options).with_rng(rng)
+    }
+
+    /// Adds the edge `(u, v)` to the embedded graph, updating the weights of
+    /// both endpoints. The graph is copied first if it is shared.
+    pub fn add_edge(&mut self, u: NodeId, v: NodeId) -> bool {
+        let added = Arc::make_mut(&mut self.spatial_index.graph).add_edge(u, v);
+        self.refresh_weights(&[u, v]);
+        added
+    }
+
+    /// Removes the edge `(u, v)` from the embedded graph, updating the
+    /// weights of both endpoints. The graph is copied first if it is shared.
+    pub fn remove_edge(&mut self, u: NodeId, v: NodeId) -> bool {
+        let removed = Arc::make_mut(&mut self.spatial_index.graph).remove_edge(u, v);
+        self.refresh_weights(&[u, v]);
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        embedder::{EmbedderOptions, WEmbedder},
+        graph::Graph as InputGraph,
+    };
+
+    #[test]
+    fn owned_embedding_runs_on_another_thread() {
+        let edges = (0..99).map(|i| (i, i + 1)).collect();
+        let graph = InputGraph::from_edge_list(edges, 2, 2).unwrap();
+        let options = EmbedderOptions {
+            max_iterations: 20,
+            ..Default::default()
+        };
+        let mut borrowed: WEmbedder<Embedding<2>> = WEmbedder::random(5, &graph, options.clone());
+        let shared = Arc::new(graph.clone());
+        let mut owned = WEmbedder::<OwnedEmbedding<2>>::random_shared(5, shared.clone(), options);
+        assert!(Arc::ptr_eq(&owned.spatial_index.graph, &shared));
+
+        let owned_positions = std::thread::spawn(move || owned.embed()).join().unwrap();
+        assert_eq!(owned_positions, borrowed.embed());
+    }
+
+    #[test]
+    fn nodes_come_with_position_and_neighbors() {
+        let graph =
+            InputGraph::from_adjacency(vec![0.5, 2., 1.], vec![vec![1], vec![0, 2], vec![1]]);
+        let embedding = Embedding {
+            positions: vec![
+                DVec::new([0., 0.]),
+                DVec::new([1., 0.]),
+                DVec::new([2., 0.]),
+            ],
+            graph: &graph,
+        };
+        let nodes: Vec<_> = embedding
+            .nodes()
+            .map(|(id, position, neighbors)| (id, *position, neighbors.to_vec()))
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                (0, DVec::new([0., 0.]), vec![1]),
+                (1, DVec::new([1., 0.]), vec![0, 2]),
+                (2, DVec::new([2., 0.]), vec![1]),
+            ]
+        );
+        let heavy: Vec<_> = embedding.heavy_nodes().map(|(id, ..)| id).collect();
+        assert_eq!(heavy, [1, 2]);
+        // (0.5 * 0 + 2 * 1 + 1 * 2) / 3.5
+        let centroid = embedding.weighted_centroid();
+        assert!(
+            centroid.approx_eq(&DVec::new([4. / 3.5, 0.]), 1e-6),
+            "{centroid}"
+        );
+    }
+
+    #[test]
+    fn added_edges_raise_weight_in_queries() {
+        let graph = InputGraph::from_edge_list(vec![(0, 1), (1, 2), (2, 3), (3, 4)], 2, 2).unwrap();
+        let embedding = Embedding {
+            positions: (0..5).map(|i| DVec::new([i as f32, 0.])).collect(),
+            graph: &graph,
+        };
+        let mut embedder = WEmbedder::new(embedding.to_owned_embedding(), Default::default());
+        let old_weight = embedder.spatial_index.weight(4);
+        let reach = |embedder: &WEmbedder<OwnedEmbedding<2>>| {
+            embedder.spatial_index.nearest_neighbors_owned(4, 1.).len()
+        };
+        let old_reach = reach(&embedder);
+
+        assert!(embedder.add_edge(4, 0));
+        assert!(embedder.add_edge(4, 1));
+        assert!(embedder.spatial_index.weight(4) > old_weight);
+        assert!(reach(&embedder) > old_reach);
+        // The borrowed input graph is untouched
+        assert_eq!(graph.nodes[4].weight, old_weight);
+    }
+
+    #[test]
+    fn predicted_graph_of_a_perfect_embedding_is_the_input() {
+        let mut graph =
+            InputGraph::from_edge_list((0..49).map(|i| (i, i + 1)).collect(), 2, 2).unwrap();
+        graph.original_ids = Some((0..50).map(|i| 100 + 2 * i).collect());
+        // Consecutive nodes at 0.9 of their weighted radius, so every node
+        // reaches its neighbors on the path and nothing beyond them
+        let mut x = 0.;
+        let mut positions = vec![DVec::new([0., 0.])];
+        for i in 1..50 {
+            x += 0.9 * graph.weight(i - 1) * graph.weight(i);
+            positions.push(DVec::new([x as f32, 0.]));
+        }
+        let mut embedding = Embedding {
+            positions,
+            graph: &graph,
+        };
+        let sorted_edges = |graph: &InputGraph| {
+            let mut edges: Vec<_> = graph
+                .edges
+                .iter()
+                .map(|&(u, v)| (u.min(v), u.max(v)))
+                .collect();
+            edges.sort_unstable();
+            edges
+        };
+
+        let predicted = embedding.predicted_graph();
+        assert_eq!(sorted_edges(&predicted), sorted_edges(&graph));
+        assert_eq!(predicted.original_ids, graph.original_ids);
+        for (node, (predicted, input)) in predicted.nodes.iter().zip(&graph.nodes).enumerate() {
+            assert_eq!(predicted.weight, input.weight, "node {node}");
+            assert_eq!(predicted.neighbors, input.neighbors, "node {node}");
+        }
+
+        // A node moved away from the path loses its edges
+        embedding.positions[20] = DVec::new([0., 100.]);
+        let predicted = embedding.predicted_graph();
+        assert!(predicted.neighbors(20).is_empty());
+        assert_eq!(predicted.edges.len(), graph.edges.len() - 2);
+    }
+}
@@ -0,0 +1,240 @@
+//! Estimates of the parameters of the geometric inhomogeneous random graph
+//! (GIRG) model that would generate a graph like the one of an embedding,
+//! see [`crate::embedding::Embedding::estimate_girg_parameters`].
+
+use rand::Rng;
+
+use crate::{
+    NodeId,
+    dvec::DVec,
+    graph::Graph,
+    rng::{Purpose, RngSource},
+};
+
+/// Pairs of nodes sampled for the distance distribution
+const SAMPLED_PAIRS: usize = 200_000;
+/// Fractions of pairs between which the correlation integral is fitted
+const CORRELATION_RANGE: (f64, f64) = (1e-3, 1e-1);
+const CORRELATION_POINTS: usize = 12;
+/// Logarithmic bins of the weighted distance per factor `e`
+const RATIO_BINS_PER_E: f64 = 4.;
+/// Fewest edges a weighted distance bin needs to enter the fit for alpha
+const MIN_BIN_EDGES: usize = 5;
+
+/// Parameters of the GIRG model
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GirgParams {
+    /// Dimension of the ground space
+    pub dim: usize,
+    /// Power law exponent of the degree distribution
+    pub ple: f64,
+    /// Temperature parameter, `f64::INFINITY` for the threshold model
+    pub alpha: f64,
+}
+
+/// Method of moments estimate of the GIRG parameters of `graph` with the
+/// nodes placed at `positions`. The dimension is the correlation dimension
+/// of the positions, the power law exponent a fit of the degree
+/// distribution and alpha the slope at which the edge probability falls off
+/// with the weighted distance `|x_u - x_v| / (w_u * w_v)`.
+pub fn estimate<const D: usize>(positions: &[DVec<D>], graph: &Graph) -> GirgParams {
+    let pairs = sample_pairs(positions.len());
+    let dim = correlation_dimension(positions, &pairs)
+        .round()
+        .clamp(1., D as f64) as usize;
+    let degrees: Vec<usize> = graph
+        .nodes
+        .iter()
+        .map(|node| node.neighbors.len())
+        .collect();
+    let edges: Vec<(NodeId, NodeId)> = (0..graph.nodes.len())
+        .flat_map(|u| {
+            graph.nodes[u]
+                .neighbor_ids()
+                .iter()
+                .filter(move |&v| u < v)
+                .map(move |v| (u, v))
+        })
+        .collect();
+    let weighted_distance = |(u, v): (NodeId, NodeId)| {
+        positions[u].distance(&positions[v]) as f64
+            / (graph.nodes[u].weight * graph.nodes[v].weight)
+    };
+    let edge_ratios: Vec<f64> = edges.into_iter().map(weighted_distance).collect();
+    let pair_ratios: Vec<f64> = pairs.iter().copied().map(weighted_distance).collect();
+    let n = positions.len() as f64;
+    let slope = edge_probability_slope(&edge_ratios, &pair_ratios, n * (n - 1.) / 2.);
+    GirgParams {
+        dim,
+        ple: power_law_exponent(&degrees),
+        alpha: -slope / dim as f64,
+    }
+}
+
+/// Up to [`SAMPLED_PAIRS`] distinct-node pairs, all pairs if there are fewer.
+/// The sample is seeded, so estimates are reproducible.
+fn sample_pairs(n: usize) -> Vec<(NodeId, NodeId)> {
+    if n < 2 {
+        return Vec::new();
+    }
+    if n * (n - 1) / 2 <= SAMPLED_PAIRS {
+        return (0..n)
+            .flat_map(|u| (u + 1..n).map(move |v| (u, v)))
+            .collect();
+    }
+    let mut rng = RngSource::default().stream(Purpose::GirgEstimate, &[n as u64]);
+    (0..SAMPLED_PAIRS)
+        .map(|_| {
+            let u = rng.random_range(0..n);
+            let v = rng.random_range(0..n - 1);
+            (u, if v >= u { v + 1 } else { v })
+        })
+        .collect()
+}
+
+/// Grassberger-Procaccia correlation dimension: the slope of `ln C(r)` over
+/// `ln r`, where `C(r)` is the fraction of `pairs` closer than `r`.
+fn correlation_dimension<const D: usize>(positions: &[DVec<D>], pairs: &[(NodeId, NodeId)]) -> f64 {
+    let mut distances: Vec<f64> = pairs
+        .iter()
+        .map(|&(u, v)| positions[u].distance(&positions[v]) as f64)
+        .filter(|&distance| distance > 0.)
+        .collect();
+    if distances.len() < CORRELATION_POINTS {
+        return 1.;
+    }
+    distances.sort_by(f64::total_cmp);
+    let (low, high) = (CORRELATION_RANGE.0.ln(), CORRELATION_RANGE.1.ln());
+    let (log_r, log_c): (Vec<f64>, Vec<f64>) = (0..CORRELATION_POINTS)
+        .map(|i| {
+            let fraction = (low + (high - low) * i as f64 / (CORRELATION_POINTS - 1) as f64).exp();
+            let rank = ((fraction * distances.len() as f64) as usize).min(distances.len() - 1);
+            (distances[rank].ln(), fraction.ln())
+        })
+        .unzip();
+    linreg::linear_regression::<f64, f64, f64>(&log_r, &log_c).map_or(1., |(slope, _)| slope)
+}
+
+/// Maximum likelihood power law exponent of the degrees from the median
+/// degree on, with the continuity correction for discrete values.
+fn power_law_exponent(degrees: &[usize]) -> f64 {
+    let mut degrees: Vec<usize> = degrees.iter().copied().filter(|&d| d > 0).collect();
+    if degrees.is_empty() {
+        return f64::NAN;
+    }
+    degrees.sort_unstable();
+    let min_degree = degrees[degrees.len() / 2] as f64 - 0.5;
+    let tail = &degrees[degrees.partition_point(|&d| (d as f64) < min_degree)..];
+    let log_sum: f64 = tail.iter().map(|&d| (d as f64 / min_degree).ln()).sum();
+    1. + tail.len() as f64 / log_sum
+}
+
+/// Slope of the log edge probability over the log weighted distance where the
+/// probability falls off, i.e. in the bins behind the most likely one whose
+/// probability is at most half of its. The probability of a bin is the number
+/// of edges in it over the number of node pairs, extrapolated from the
+/// sampled `pair_ratios` to `total_pairs`. `-inf` if the falloff is too steep
+/// to leave two bins, as in the threshold model.
+fn edge_probability_slope(edge_ratios: &[f64], pair_ratios: &[f64], total_pairs: f64) -> f64 {
+    let bin = |ratio: f64| (ratio.ln() * RATIO_BINS_PER_E).floor() as i64;
+    let finite = |ratios: &[f64]| -> Vec<i64> {
+        ratios
+            .iter()
+            .filter(|ratio| ratio.is_finite() && **ratio > 0.)
+            .map(|&ratio| bin(ratio))
+            .collect()
+    };
+    let (edge_bins, pair_bins) = (finite(edge_ratios), finite(pair_ratios));
+    let (Some(&first), Some(&last)) = (
+        edge_bins.iter().chain(&pair_bins).min(),
+        edge_bins.iter().chain(&pair_bins).max(),
+    ) else {
+        return f64::NEG_INFINITY;
+    };
+    let mut edges = vec![0usize; (last - first + 1) as usize];
+    let mut pairs = vec![0usize; edges.len()];
+    edge_bins
+        .iter()
+        .for_each(|&b| edges[(b - first) as usize] += 1);
+    pair_bins
+        .iter()
+        .for_each(|&b| pairs[(b - first) as usize] += 1);
+    let scale = total_pairs / pair_bins.len() as f64;
+    let probabilities: Vec<Option<f64>> = edges
+        .iter()
+        .zip(&pairs)
+        .map(|(&e, &p)| {
+            (e >= MIN_BIN_EDGES && p > 0).then(|| (e as f64 / (p as f64 * scale)).min(1.))
+        })
+        .collect();
+    let Some((peak, max)) = probabilities
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| p.map(|p| (i, p)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+    else {
+        return f64::NEG_INFINITY;
+    };
+    let (log_ratio, log_probability): (Vec<f64>, Vec<f64>) = probabilities[peak..]
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| {
+            p.filter(|&p| p <= max / 2.).map(|p| {
+                let center = ((first + (peak + i) as i64) as f64 + 0.5) / RATIO_BINS_PER_E;
+                (center, p.ln())
+            })
+        })
+        .unzip();
+    if log_ratio.len() < 2 {
+        return f64::NEG_INFINITY;
+    }
+    linreg::linear_regression::<f64, f64, f64>(&log_ratio, &log_probability)
+        .map_or(f64::NEG_INFINITY, |(slope, _)| slope)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+    use super::*;
+
+    /// Uniform positions in the unit square and power law weights with
+    /// exponent 2.5, connected if `|x_u - x_v|^2 < c * w_u * w_v / n`
+    fn threshold_girg(n: usize) -> (Vec<DVec<2>>, Graph) {
+        let mut rng = SmallRng::seed_from_u64(3);
+        let positions: Vec<DVec<2>> = (0..n)
+            .map(|_| DVec::new([rng.random::<f32>(), rng.random::<f32>()]))
+            .collect();
+        let weights: Vec<f64> = (0..n)
+            .map(|_| (1. - rng.random::<f64>()).powf(-1. / 1.5))
+            .collect();
+        let mut edges = Vec::new();
+        for u in 0..n {
+            for v in u + 1..n {
+                let distance = positions[u].distance(&positions[v]) as f64;
+                if distance * distance < 4. * weights[u] * weights[v] / n as f64 {
+                    edges.push((u, v));
+                }
+            }
+        }
+        (positions, Graph::from_edge_list(edges, 2, 2).unwrap())
+    }
+
+    #[test]
+    fn recovers_the_parameters_of_a_threshold_girg() {
+        let (positions, graph) = threshold_girg(3000);
+        let params = estimate(&positions, &graph);
+        assert_eq!(params.dim, 2);
+        assert!((params.ple - 2.5).abs() < 0.5, "{params:?}");
+        assert!(params.alpha > 2.5, "{params:?}");
+    }
+
+    #[test]
+    fn power_law_exponent_of_a_zipf_sample() {
+        // d_k = k^(-1 / (ple - 1)) scaled, the quantiles of a power law
+        let degrees: Vec<usize> = (1..=5000)
+            .map(|k| (1000. * (k as f64 / 5000.).powf(-1. / 1.5)) as usize)
+            .collect();
+        assert!((power_law_exponent(&degrees) - 2.5).abs() < 0.1);
+    }
+}
@@ -1,5 +1,6 @@
-use std::cmp::max;
-use std::collections::HashSet;
+use std::cmp::{Ordering, Reverse, max};
+use std::collections::{BinaryHeap, HashMap, VecDeque, hash_map::Entry};
+use std::fmt::Write;
 use std::fs::read_to_string;
 use std::hash::Hasher;
 use std::io;
@@ -14,7 +15,13 @@ impl EdgeKey {
     #[inline(always)]
     fn new(a: usize, b: usize) -> Self {
         let (lo, hi) = if a < b { (a, b) } else { (b, a) };
-        Self((lo as u64) << 32 | hi as u64)
+        Self::directed(lo, hi)
+    }
+
+    /// Key of the edge from `a` to `b`, distinct from the one back
+    #[inline(always)]
+    fn directed(a: usize, b: usize) -> Self {
+        Self((a as u64) << 32 | b as u64)
     }
 }
 
@@ -27,20 +34,165 @@ impl std::hash::Hash for EdgeKey {
 
 // A node in the graph
 // Each node has a weight, which is degree ^ (d/8)
+// Neighbours are stored as u32, the edge keys limit node ids to 32 bits anyway.
+// The list is sorted, so membership is a binary search rather than a lookup in
+// a per-node hash set, which would take more memory than the list itself.
 #[derive(Clone, Debug, Default)]
 pub struct Node {
     pub weight: f64,
-    pub neighbors: Vec<usize>,
-    pub neighbors_set: HashSet<usize>,
+    pub neighbors: Vec<u32>,
+}
+
+impl Node {
+    /// Node with the sorted neighbour list `neighbors`
+    pub fn with_neighbors(weight: f64, neighbors: Vec<NodeId>) -> Self {
+        Node {
+            weight,
+            neighbors: neighbors.into_iter().map(compact_id).collect(),
+        }
+    }
+
+    pub fn neighbor_ids(&self) -> Neighbors<'_> {
+        Neighbors(&self.neighbors)
+    }
+}
+
+#[inline(always)]
+fn compact_id(id: NodeId) -> u32 {
+    u32::try_from(id).expect("node ids have to fit into 32 bits")
+}
+
+/// Neighbour list of a node as [`NodeId`]s, see [`crate::query::Graph::neighbors`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Neighbors<'a>(&'a [u32]);
+
+impl<'a> Neighbors<'a> {
+    pub const EMPTY: Self = Neighbors(&[]);
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> NeighborIter<'a> {
+        self.into_iter()
+    }
+
+    /// Uses a binary search, neighbour lists are sorted
+    pub fn contains(&self, node: NodeId) -> bool {
+        u32::try_from(node).is_ok_and(|node| self.0.binary_search(&node).is_ok())
+    }
+
+    pub fn to_vec(&self) -> Vec<NodeId> {
+        self.iter().collect()
+    }
+
+    /// The ids as they are stored
+    pub fn as_compact(&self) -> &'a [u32] {
+        self.0
+    }
+}
+
+pub type NeighborIter<'a> =
+    std::iter::Map<std::iter::Copied<std::slice::Iter<'a, u32>>, fn(u32) -> NodeId>;
+
+impl<'a> IntoIterator for Neighbors<'a> {
+    type Item = NodeId;
+    type IntoIter = NeighborIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter().copied().map(|id| id as NodeId)
+    }
+}
+
+/// Parameters of the degree-based node weights, see [`Graph::from_edge_list`]
+#[derive(Clone, Copy, Debug)]
+struct WeightParams {
+    embedding_dim: usize,
+    dim_ratio: f64,
+    weight_norm: f64,
+}
+
+impl WeightParams {
+    fn weight(&self, degree: usize) -> f64 {
+        // weight = degree ^ (d/8)
+        ((degree as f64).powf(self.dim_ratio) * self.weight_norm)
+            .powf(1. / self.embedding_dim as f64)
+    }
+}
+
+/// Something [`Graph::parse_edge_list_file_with_warnings`] noticed about the
+/// input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// The node ids were not `0..n` and were mapped to it
+    NodeIdsRemapped { nodes: usize, max_id: usize },
+    /// The node ids were not `0..n` and were kept, so every unused id below
+    /// `max_id` is an isolated node
+    NonConsecutiveNodeIds { nodes: usize, max_id: usize },
+}
+
+impl std::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseWarning::NodeIdsRemapped { nodes, max_id } => write!(
+                f,
+                "node ids are not consecutive from 0 ({nodes} nodes with ids up to {max_id}), \
+                 remapped them to 0..{nodes}"
+            ),
+            ParseWarning::NonConsecutiveNodeIds { nodes, max_id } => write!(
+                f,
+                "node ids are not consecutive from 0 ({nodes} nodes with ids up to {max_id}), \
+                 every unused id is an isolated node"
+            ),
+        }
+    }
+}
+
+/// The distinct node ids of `edges` in ascending order
+fn distinct_node_ids(edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut ids: Vec<usize> = edges.iter().flat_map(|&(u, v)| [u, v]).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// Maps the node ids of `edges` to their index in `ids`
+fn remap_node_ids(edges: &mut [(usize, usize)], ids: &[usize]) {
+    let new_id: HashMap<usize, usize, FxBuildHasher> = ids
+        .iter()
+        .enumerate()
+        .map(|(new, &original)| (original, new))
+        .collect();
+    for (u, v) in edges.iter_mut() {
+        (*u, *v) = (new_id[u], new_id[v]);
+    }
 }
 
 // A graph structure
 // It contains the embedding dimension, nodes, and edges
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Graph {
     pub nodes: Vec<Node>,
     pub edges: Vec<(NodeId, NodeId)>,
-    edge_set: HashSet<EdgeKey, FxBuildHasher>,
+    /// Position of every edge in `edges`, see [`Graph::edge_key`]
+    edge_index: HashMap<EdgeKey, usize, FxBuildHasher>,
+    /// Id of every node in the input the graph was derived from. `None`
+    /// means node `i` is input node `i`. Set by transformations that drop or
+    /// permute nodes, see [`Graph::induced_subgraph`].
+    pub original_ids: Option<Vec<u64>>,
+    /// Set if the weights were derived from the degrees, so they can be
+    /// recomputed when edges change
+    weight_params: Option<WeightParams>,
+    /// Whether the neighbours of a node are only the heads of its outgoing
+    /// edges, see [`Graph::from_directed_edge_list`]
+    directed: bool,
+    /// Number of edges pointing at every node of a directed graph, empty
+    /// for undirected ones
+    in_degrees: Vec<u32>,
 }
 
 impl Default for Graph {
@@ -54,19 +206,478 @@ impl Graph {
         Graph {
             nodes: Vec::new(),
             edges: Vec::new(),
-            edge_set: HashSet::with_hasher(FxBuildHasher),
+            edge_index: HashMap::with_hasher(FxBuildHasher),
+            original_ids: None,
+            weight_params: None,
+            directed: false,
+            in_degrees: Vec::new(),
+        }
+    }
+
+    /// Whether the graph was built by [`Graph::from_directed_edge_list`]
+    pub fn is_directed(&self) -> bool {
+        self.directed
+    }
+
+    /// Key of the edge `(u, v)` in `edge_index`. Only a directed graph tells
+    /// `(u, v)` and `(v, u)` apart.
+    fn edge_key(&self, u: NodeId, v: NodeId) -> EdgeKey {
+        if self.directed {
+            EdgeKey::directed(u, v)
+        } else {
+            EdgeKey::new(u, v)
+        }
+    }
+
+    /// Whether there is an edge between `u` and `v` in either direction
+    fn has_edge_between(&self, u: NodeId, v: NodeId) -> bool {
+        self.edge_index.contains_key(&self.edge_key(u, v))
+            || self.directed && self.edge_index.contains_key(&self.edge_key(v, u))
+    }
+
+    /// Appends `(u, v)` to the edges unless it is already there
+    fn push_edge(&mut self, u: NodeId, v: NodeId) -> bool {
+        let Entry::Vacant(entry) = self.edge_index.entry(self.edge_key(u, v)) else {
+            return false;
+        };
+        entry.insert(self.edges.len());
+        self.edges.push((u, v));
+        true
+    }
+
+    /// Counts the in-degrees of a directed graph from the neighbour lists
+    fn count_in_degrees(&mut self) {
+        self.in_degrees.clear();
+        if self.directed {
+            self.in_degrees.resize(self.nodes.len(), 0);
+            for node in &self.nodes {
+                for &v in &node.neighbors {
+                    self.in_degrees[v as usize] += 1;
+                }
+            }
+        }
+    }
+
+    /// Number of distinct neighbours of `node`, counting both the heads and
+    /// the tails of its edges in a directed graph. The degree-based weights
+    /// are computed from it.
+    fn degree(&self, node: NodeId) -> usize {
+        let out = self.nodes[node].neighbors.len();
+        if self.directed {
+            out + self.in_degrees[node] as usize
+        } else {
+            out
+        }
+    }
+
+    /// For every node the sorted nodes with an edge to it. In an undirected
+    /// graph these are its neighbours.
+    fn in_neighbors(&self) -> Vec<Vec<NodeId>> {
+        let mut in_neighbors = vec![Vec::new(); self.nodes.len()];
+        for (u, node) in self.nodes.iter().enumerate() {
+            for v in node.neighbor_ids() {
+                in_neighbors[v].push(u);
+            }
+        }
+        in_neighbors
+    }
+
+    /// The neighbours of `u` followed by the nodes in `in_neighbors[u]`, so
+    /// passing [`Graph::in_neighbors`] of a directed graph ignores the
+    /// directions
+    fn adjacent<'a>(
+        &'a self,
+        u: NodeId,
+        in_neighbors: Option<&'a [Vec<NodeId>]>,
+    ) -> impl Iterator<Item = NodeId> + 'a {
+        let incoming = in_neighbors
+            .into_iter()
+            .flat_map(move |in_neighbors| &in_neighbors[u]);
+        self.nodes[u]
+            .neighbor_ids()
+            .into_iter()
+            .chain(incoming.copied())
+    }
+
+    /// Id of `node` in the input the graph was derived from
+    pub fn original_id(&self, node: NodeId) -> u64 {
+        match &self.original_ids {
+            Some(ids) => ids[node],
+            None => node as u64,
+        }
+    }
+
+    /// Chains two node mappings. A mapping lists for every node of a derived
+    /// graph the node it came from (`mapping[new] = old`). If `first` maps
+    /// `B` to `A` and `second` maps `C` to `B`, the result maps `C` to `A`.
+    pub fn compose_mapping(first: &[NodeId], second: &[NodeId]) -> Vec<NodeId> {
+        second.iter().map(|&node| first[node]).collect()
+    }
+
+    /// The subgraph induced by `nodes`, where node `i` of the result is
+    /// `nodes[i]` of `self`. Node weights are kept as they are rather than
+    /// recomputed from the reduced degrees, and the original ids are carried
+    /// over so results can be joined back to the input.
+    pub fn induced_subgraph(&self, nodes: &[NodeId]) -> Self {
+        let mut new_id = vec![None; self.nodes.len()];
+        for (new, &old) in nodes.iter().enumerate() {
+            assert!(new_id[old].is_none(), "node {old} selected twice");
+            new_id[old] = Some(new);
+        }
+
+        let mut graph = Graph::new();
+        graph.directed = self.directed;
+        for &(u, v) in &self.edges {
+            if let (Some(u), Some(v)) = (new_id[u], new_id[v]) {
+                graph.push_edge(u, v);
+            }
+        }
+        graph.nodes = nodes
+            .iter()
+            .map(|&old| {
+                let node = &self.nodes[old];
+                let mut neighbors: Vec<NodeId> = node
+                    .neighbor_ids()
+                    .iter()
+                    .filter_map(|u| new_id[u])
+                    .collect();
+                neighbors.sort_unstable();
+                Node::with_neighbors(node.weight, neighbors)
+            })
+            .collect();
+        graph.original_ids = Some(nodes.iter().map(|&old| self.original_id(old)).collect());
+        graph.weight_params = self.weight_params;
+        graph.count_in_degrees();
+        graph
+    }
+
+    /// Merges the adjacent nodes `u` and `v` into one node with the union of
+    /// their neighbours and the sum of their weights. The merged node gets
+    /// the smaller of the two ids and later ids shift down by one. The edge
+    /// between `u` and `v` is dropped instead of becoming a self-loop.
+    pub fn contract_edge(&self, u: NodeId, v: NodeId) -> Self {
+        assert!(
+            u != v && self.has_edge_between(u, v),
+            "({u}, {v}) is not an edge"
+        );
+        let (keep, removed) = (u.min(v), u.max(v));
+        let new_id = |x: NodeId| match x {
+            x if x == removed => keep,
+            x if x > removed => x - 1,
+            x => x,
+        };
+
+        let mut graph = Graph::new();
+        graph.directed = self.directed;
+        for &(a, b) in &self.edges {
+            let (a, b) = (new_id(a), new_id(b));
+            if a != b {
+                graph.push_edge(a, b);
+            }
+        }
+        graph.nodes = (0..self.nodes.len())
+            .filter(|&x| x != removed)
+            .map(|x| {
+                let mut weight = self.nodes[x].weight;
+                let mut neighbors: Vec<NodeId> =
+                    self.nodes[x].neighbor_ids().iter().map(new_id).collect();
+                if x == keep {
+                    weight += self.nodes[removed].weight;
+                    neighbors.extend(self.nodes[removed].neighbor_ids().iter().map(new_id));
+                }
+                let own = new_id(x);
+                neighbors.retain(|&y| y != own);
+                neighbors.sort_unstable();
+                neighbors.dedup();
+                Node::with_neighbors(weight, neighbors)
+            })
+            .collect();
+        graph.original_ids = Some(
+            (0..self.nodes.len())
+                .filter(|&x| x != removed)
+                .map(|x| self.original_id(x))
+                .collect(),
+        );
+        graph.count_in_degrees();
+        graph
+    }
+
+    /// Contracts all edges of `matching` at once. The result is the graph
+    /// [`Graph::contract_edge`] yields for the pairs one after another, but
+    /// takes one pass instead of one per pair. Also returns the coarse node
+    /// of every node of `self`.
+    pub fn contract_matching(&self, matching: &[(NodeId, NodeId)]) -> (Self, Vec<NodeId>) {
+        let mut partner = vec![None; self.nodes.len()];
+        for &(u, v) in matching {
+            assert!(
+                u != v && self.has_edge_between(u, v),
+                "({u}, {v}) is not an edge"
+            );
+            assert!(
+                partner[u].is_none() && partner[v].is_none(),
+                "({u}, {v}) shares a node with another pair"
+            );
+            partner[u] = Some(v);
+            partner[v] = Some(u);
+        }
+        // The merged node keeps the smaller id, like in contract_edge
+        let kept: Vec<NodeId> = (0..self.nodes.len())
+            .filter(|&x| partner[x].is_none_or(|p| x < p))
+            .collect();
+        let mut coarse = vec![0; self.nodes.len()];
+        for (new, &x) in kept.iter().enumerate() {
+            coarse[x] = new;
+            if let Some(p) = partner[x] {
+                coarse[p] = new;
+            }
+        }
+
+        let mut graph = Graph::new();
+        graph.directed = self.directed;
+        for &(a, b) in &self.edges {
+            let (a, b) = (coarse[a], coarse[b]);
+            if a != b {
+                graph.push_edge(a, b);
+            }
+        }
+        graph.nodes = kept
+            .iter()
+            .map(|&x| {
+                let mut weight = self.nodes[x].weight;
+                let mut neighbors: Vec<NodeId> = self.nodes[x]
+                    .neighbor_ids()
+                    .iter()
+                    .map(|y| coarse[y])
+                    .collect();
+                if let Some(p) = partner[x] {
+                    weight += self.nodes[p].weight;
+                    neighbors.extend(self.nodes[p].neighbor_ids().iter().map(|y| coarse[y]));
+                }
+                neighbors.retain(|&y| y != coarse[x]);
+                neighbors.sort_unstable();
+                neighbors.dedup();
+                Node::with_neighbors(weight, neighbors)
+            })
+            .collect();
+        graph.original_ids = Some(kept.iter().map(|&x| self.original_id(x)).collect());
+        graph.count_in_degrees();
+        (graph, coarse)
+    }
+
+    /// A maximal matching for coarsening: nodes are visited by increasing
+    /// degree and paired with their unmatched neighbour of lowest degree, so
+    /// hubs are merged last.
+    pub fn coarsening_matching(&self) -> Vec<(NodeId, NodeId)> {
+        let degree = |x: NodeId| self.nodes[x].neighbors.len();
+        let mut order: Vec<NodeId> = (0..self.nodes.len()).collect();
+        order.sort_by_key(|&x| (degree(x), x));
+        let mut matched = vec![false; self.nodes.len()];
+        let mut matching = Vec::new();
+        for u in order {
+            if matched[u] {
+                continue;
+            }
+            let partner = self.nodes[u]
+                .neighbor_ids()
+                .iter()
+                .filter(|&v| !matched[v] && v != u)
+                .min_by_key(|&v| (degree(v), v));
+            if let Some(v) = partner {
+                matched[u] = true;
+                matched[v] = true;
+                matching.push((u, v));
+            }
+        }
+        matching
+    }
+
+    /// Permutes the node ids, node `i` of the result is `order[i]` of `self`.
+    pub fn relabel(&self, order: &[NodeId]) -> Self {
+        assert_eq!(
+            order.len(),
+            self.nodes.len(),
+            "relabeling must keep every node"
+        );
+        self.induced_subgraph(order)
+    }
+
+    /// The largest connected component, keeping the relative node order.
+    /// Returns the component and the node mapping (`mapping[new] = old`).
+    /// Edges of a directed graph are followed both ways, so this is its
+    /// largest weakly connected component.
+    pub fn reduce_to_largest_component(&self) -> (Self, Vec<NodeId>) {
+        let in_neighbors = self.directed.then(|| self.in_neighbors());
+        let mut component = vec![usize::MAX; self.nodes.len()];
+        let mut sizes = Vec::new();
+        for start in 0..self.nodes.len() {
+            if component[start] != usize::MAX {
+                continue;
+            }
+            let id = sizes.len();
+            let mut size = 0;
+            let mut stack = vec![start];
+            component[start] = id;
+            while let Some(u) = stack.pop() {
+                size += 1;
+                for v in self.adjacent(u, in_neighbors.as_deref()) {
+                    if component[v] == usize::MAX {
+                        component[v] = id;
+                        stack.push(v);
+                    }
+                }
+            }
+            sizes.push(size);
+        }
+        let Some(largest) = (0..sizes.len()).max_by_key(|&c| (sizes[c], Reverse(c))) else {
+            return (self.induced_subgraph(&[]), Vec::new());
+        };
+        let nodes: Vec<NodeId> = (0..self.nodes.len())
+            .filter(|&v| component[v] == largest)
+            .collect();
+        (self.induced_subgraph(&nodes), nodes)
+    }
+
+    /// Whether the nodes can be split into two parts without an edge inside
+    /// a part, see [`Graph::bipartition`].
+    pub fn is_bipartite(&self) -> bool {
+        self.bipartition().is_some()
+    }
+
+    /// Two-colouring of the nodes by breadth-first search, `None` if the
+    /// graph has an odd cycle. The first node of every component goes into
+    /// the first part, so isolated nodes all end up there. Both parts are
+    /// sorted. Edges of a directed graph are followed both ways.
+    pub fn bipartition(&self) -> Option<(Vec<NodeId>, Vec<NodeId>)> {
+        let in_neighbors = self.directed.then(|| self.in_neighbors());
+        let mut side: Vec<Option<bool>> = vec![None; self.nodes.len()];
+        let mut queue = VecDeque::new();
+        for start in 0..self.nodes.len() {
+            if side[start].is_some() {
+                continue;
+            }
+            side[start] = Some(false);
+            queue.push_back(start);
+            while let Some(u) = queue.pop_front() {
+                let other = side[u] == Some(false);
+                for v in self.adjacent(u, in_neighbors.as_deref()) {
+                    match side[v] {
+                        None => {
+                            side[v] = Some(other);
+                            queue.push_back(v);
+                        }
+                        Some(s) if s != other => return None,
+                        Some(_) => {}
+                    }
+                }
+            }
+        }
+        let (first, second): (Vec<NodeId>, Vec<NodeId>) =
+            (0..self.nodes.len()).partition(|&v| side[v] == Some(false));
+        Some((first, second))
+    }
+
+    /// Whether every node can reach every other one along the edges, see
+    /// [`Graph::strongly_connected_components`]. For an undirected graph
+    /// this is whether it is connected.
+    pub fn is_strongly_connected(&self) -> bool {
+        self.strongly_connected_components().len() <= 1
+    }
+
+    /// Strongly connected components by Tarjan's algorithm, without
+    /// recursion so long paths don't overflow the stack. Every component is
+    /// sorted and the components are ordered by their smallest node.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<NodeId>> {
+        let n = self.nodes.len();
+        let mut index = vec![usize::MAX; n];
+        let mut low_link = vec![0; n];
+        let mut on_stack = vec![false; n];
+        let mut stack = Vec::new();
+        let mut components = Vec::new();
+        let mut next_index = 0;
+        for start in 0..n {
+            if index[start] != usize::MAX {
+                continue;
+            }
+            // Node and position in its neighbour list
+            let mut calls = vec![(start, 0)];
+            index[start] = next_index;
+            low_link[start] = next_index;
+            next_index += 1;
+            stack.push(start);
+            on_stack[start] = true;
+            while let Some(&mut (u, ref mut next)) = calls.last_mut() {
+                if let Some(&v) = self.nodes[u].neighbors.get(*next) {
+                    let v = v as NodeId;
+                    *next += 1;
+                    if index[v] == usize::MAX {
+                        index[v] = next_index;
+                        low_link[v] = next_index;
+                        next_index += 1;
+                        stack.push(v);
+                        on_stack[v] = true;
+                        calls.push((v, 0));
+                    } else if on_stack[v] {
+                        low_link[u] = low_link[u].min(index[v]);
+                    }
+                    continue;
+                }
+                calls.pop();
+                if let Some(&(parent, _)) = calls.last() {
+                    low_link[parent] = low_link[parent].min(low_link[u]);
+                }
+                if low_link[u] == index[u] {
+                    let mut component = Vec::new();
+                    while let Some(v) = stack.pop() {
+                        on_stack[v] = false;
+                        component.push(v);
+                        if v == u {
+                            break;
+                        }
+                    }
+                    component.sort_unstable();
+                    components.push(component);
+                }
+            }
         }
+        components.sort_unstable_by_key(|component| component[0]);
+        components
     }
 
     /// Parses a graph from an edge list file.
-    /// The file should contain pairs of integers representing edges.
+    /// The file should contain pairs of integers representing edges. Node ids
+    /// that are not `0..n` are remapped, see
+    /// [`Graph::parse_edge_list_file_with_warnings`].
     pub fn parse_from_edge_list_file(
         file_path: &str,
         embedding_dim: usize,
         latent_dim_hint: usize,
     ) -> io::Result<Self> {
-        let edges = read_to_string(file_path)
-            .unwrap_or_else(|e| panic!("Tried to load file from {file_path}:\n {e:?}"))
+        let (graph, _) = Self::parse_edge_list_file_with_warnings(
+            file_path,
+            embedding_dim,
+            latent_dim_hint,
+            true,
+        )?;
+        Ok(graph)
+    }
+
+    /// Like [`Graph::parse_from_edge_list_file`], but also returns what it
+    /// noticed about the node ids. If they are not `0..n`, e.g. 1-indexed or
+    /// with gaps, and `remap_ids` is set, they are mapped to `0..n` in
+    /// ascending order and kept as [`Graph::original_node_ids`].
+    pub fn parse_edge_list_file_with_warnings(
+        file_path: &str,
+        embedding_dim: usize,
+        latent_dim_hint: usize,
+        remap_ids: bool,
+    ) -> io::Result<(Self, Vec<ParseWarning>)> {
+        let mut edges = read_to_string(file_path)
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Tried to load file from {file_path}: {e}"),
+                )
+            })?
             .lines()
             .map(|s| {
                 s.split_ascii_whitespace()
@@ -79,7 +690,49 @@ impl Graph {
                 (u, v)
             })
             .collect::<Vec<_>>();
-        Self::from_edge_list(edges, embedding_dim, latent_dim_hint)
+        let ids = distinct_node_ids(&edges);
+        let (nodes, max_id) = (ids.len(), ids.last().copied().unwrap_or_default());
+        if nodes == 0 || max_id + 1 == nodes {
+            let graph = Self::from_edge_list(edges, embedding_dim, latent_dim_hint)?;
+            return Ok((graph, Vec::new()));
+        }
+        if !remap_ids {
+            let graph = Self::from_edge_list(edges, embedding_dim, latent_dim_hint)?;
+            return Ok((
+                graph,
+                vec![ParseWarning::NonConsecutiveNodeIds { nodes, max_id }],
+            ));
+        }
+        remap_node_ids(&mut edges, &ids);
+        let mut graph = Self::from_edge_list(edges, embedding_dim, latent_dim_hint)?;
+        graph.original_ids = Some(ids.into_iter().map(|id| id as u64).collect());
+        Ok((graph, vec![ParseWarning::NodeIdsRemapped { nodes, max_id }]))
+    }
+
+    /// A copy with the weights [`Graph::from_edge_list`] derives for
+    /// `embedding_dim` and `latent_dim_hint`, without parsing the file or
+    /// building the adjacency again
+    pub fn reweighted(&self, embedding_dim: usize, latent_dim_hint: usize) -> Self {
+        let mut graph = self.clone();
+        let degrees: Vec<usize> = (0..graph.nodes.len()).map(|u| graph.degree(u)).collect();
+        let params = WeightParams {
+            embedding_dim,
+            dim_ratio: embedding_dim as f64 / latent_dim_hint as f64,
+            weight_norm: degrees.len() as f64 / degrees.iter().sum::<usize>() as f64,
+        };
+        for (node, &degree) in graph.nodes.iter_mut().zip(&degrees) {
+            node.weight = params.weight(degree);
+        }
+        graph.weight_params = Some(params);
+        graph
+    }
+
+    /// The id every node had in the input, `None` if node `i` is input node
+    /// `i`. See [`Graph::original_ids`].
+    pub fn original_node_ids(&self) -> Option<Vec<usize>> {
+        self.original_ids
+            .as_ref()
+            .map(|ids| ids.iter().map(|&id| id as usize).collect())
     }
 
     /// Parses a graph from an edge list.
@@ -88,55 +741,1069 @@ impl Graph {
         edges: Vec<(usize, usize)>,
         embedding_dim: usize,
         latent_dim_hint: usize,
+    ) -> io::Result<Self> {
+        Self::build_from_edge_list(edges, embedding_dim, latent_dim_hint, false)
+    }
+
+    /// Like [`Graph::from_edge_list`], but `(u, v)` only makes `v` a
+    /// neighbour of `u`. The weights still come from the total degree, where
+    /// `(u, v)` and `(v, u)` are two edges, and
+    /// [`crate::query::Graph::is_connected`] holds for both orders, so the
+    /// embedder pulls `u` towards `v` while neither end repels the other.
+    pub fn from_directed_edge_list(
+        edges: Vec<(usize, usize)>,
+        embedding_dim: usize,
+        latent_dim_hint: usize,
+    ) -> io::Result<Self> {
+        Self::build_from_edge_list(edges, embedding_dim, latent_dim_hint, true)
+    }
+
+    fn build_from_edge_list(
+        edges: Vec<(usize, usize)>,
+        embedding_dim: usize,
+        latent_dim_hint: usize,
+        directed: bool,
     ) -> io::Result<Self> {
         let mut graph = Graph::new();
-        graph.edges = edges;
-        let mut node_degree = Vec::new();
-        for (u, v) in graph.edges.iter() {
-            if node_degree.len() <= max(*u, *v) {
-                node_degree.resize(max(*u, *v) + 1, 0);
-            }
-            node_degree[*u] += 1;
-            node_degree[*v] += 1;
-        }
-        let total_weight: usize = node_degree.iter().sum();
-        let weight_norm = node_degree.len() as f64 / total_weight as f64;
-        let dim_ratio = embedding_dim as f64 / latent_dim_hint as f64;
-        (0..node_degree.len()).for_each(|i| {
-            graph.nodes.push(Node {
-                // weight = degree ^ (d/8)
-                weight: ((node_degree[i] as f64).powf(dim_ratio) * weight_norm)
-                    .powf(1. / embedding_dim as f64),
-                neighbors: Vec::new(),
-                neighbors_set: HashSet::new(),
-            });
-        });
-        graph.edge_set.reserve(graph.edges.len());
-        for (u, v) in graph.edges.iter() {
-            graph.nodes[*u].neighbors.push(*v);
-            graph.nodes[*u].neighbors_set.insert(*v);
-            graph.nodes[*v].neighbors.push(*u);
-            graph.nodes[*v].neighbors_set.insert(*u);
-            graph.edge_set.insert(EdgeKey::new(*u, *v));
+        graph.directed = directed;
+        let num_nodes = edges.iter().map(|&(u, v)| max(u, v) + 1).max().unwrap_or(0);
+        graph.nodes = vec![Node::default(); num_nodes];
+        graph.edge_index.reserve(edges.len());
+        // Repeated edges are dropped, so they don't count towards the
+        // degrees and `recompute_weight` agrees with the weights set here
+        for (u, v) in edges {
+            if !graph.push_edge(u, v) {
+                continue;
+            }
+            let (cu, cv) = (compact_id(u), compact_id(v));
+            graph.nodes[u].neighbors.push(cv);
+            if !directed {
+                graph.nodes[v].neighbors.push(cu);
+            }
         }
         for node in &mut graph.nodes {
             node.neighbors.sort_unstable();
             node.neighbors.dedup();
         }
+        graph.count_in_degrees();
+
+        let degrees: Vec<usize> = (0..num_nodes).map(|u| graph.degree(u)).collect();
+        let total_weight: usize = degrees.iter().sum();
+        let params = WeightParams {
+            embedding_dim,
+            dim_ratio: embedding_dim as f64 / latent_dim_hint as f64,
+            weight_norm: num_nodes as f64 / total_weight as f64,
+        };
+        for (node, &degree) in graph.nodes.iter_mut().zip(&degrees) {
+            node.weight = params.weight(degree);
+        }
+        graph.weight_params = Some(params);
 
         // TODO: Sort nodes by degree and reassign indices
         Ok(graph)
     }
+
+    /// Builds a graph from precomputed node weights and sorted neighbour
+    /// lists, e.g. when loading a [`crate::bundle`].
+    pub fn from_adjacency(weights: Vec<f64>, neighbors: Vec<Vec<NodeId>>) -> Self {
+        Self::build_from_adjacency(weights, neighbors, false)
+    }
+
+    /// Like [`Graph::from_adjacency`], but the lists only hold the heads of
+    /// the outgoing edges, see [`Graph::from_directed_edge_list`].
+    pub fn from_directed_adjacency(weights: Vec<f64>, neighbors: Vec<Vec<NodeId>>) -> Self {
+        Self::build_from_adjacency(weights, neighbors, true)
+    }
+
+    fn build_from_adjacency(
+        weights: Vec<f64>,
+        neighbors: Vec<Vec<NodeId>>,
+        directed: bool,
+    ) -> Self {
+        assert_eq!(weights.len(), neighbors.len());
+        let mut graph = Graph::new();
+        graph.directed = directed;
+        for (u, adjacent) in neighbors.iter().enumerate() {
+            for &v in adjacent.iter().filter(|&&v| directed || u < v) {
+                graph.push_edge(u, v);
+            }
+        }
+        graph.nodes = weights
+            .into_iter()
+            .zip(neighbors)
+            .map(|(weight, neighbors)| Node::with_neighbors(weight, neighbors))
+            .collect();
+        graph.count_in_degrees();
+        graph
+    }
+
+    /// Adds the edge `(u, v)` and updates the weights of both endpoints, see
+    /// [`Graph::recompute_weight`]. Returns false if the edge already exists.
+    /// In a directed graph `(v, u)` is a different edge.
+    pub fn add_edge(&mut self, u: NodeId, v: NodeId) -> bool {
+        assert!(u != v, "self-loops are not supported");
+        if !self.push_edge(u, v) {
+            return false;
+        }
+        for (a, b) in [(u, v), (v, u)] {
+            if !self.directed || a == u {
+                let node = &mut self.nodes[a];
+                let b = compact_id(b);
+                let position = node.neighbors.partition_point(|&x| x < b);
+                node.neighbors.insert(position, b);
+            }
+        }
+        if self.directed {
+            self.in_degrees[v] += 1;
+        }
+        self.recompute_weight(u);
+        self.recompute_weight(v);
+        true
+    }
+
+    /// Removes the edge `(u, v)` and updates the weights of both endpoints,
+    /// see [`Graph::recompute_weight`]. Returns false if there is no such edge.
+    /// In a directed graph `(v, u)` is kept. The last edge of
+    /// [`Graph::edges`] takes the place of the removed one.
+    pub fn remove_edge(&mut self, u: NodeId, v: NodeId) -> bool {
+        let Some(index) = self.edge_index.remove(&self.edge_key(u, v)) else {
+            return false;
+        };
+        self.edges.swap_remove(index);
+        if let Some(&(a, b)) = self.edges.get(index) {
+            let key = self.edge_key(a, b);
+            self.edge_index.insert(key, index);
+        }
+        for (a, b) in [(u, v), (v, u)] {
+            if !self.directed || a == u {
+                let node = &mut self.nodes[a];
+                let b = compact_id(b);
+                if let Ok(position) = node.neighbors.binary_search(&b) {
+                    node.neighbors.remove(position);
+                }
+            }
+        }
+        if self.directed {
+            self.in_degrees[v] -= 1;
+        }
+        self.recompute_weight(u);
+        self.recompute_weight(v);
+        true
+    }
+
+    /// Recomputes the weight of `node` from its current degree. The
+    /// normalisation by the average degree is kept from construction, so the
+    /// other weights stay valid. Does nothing for graphs whose weights were
+    /// not derived from the degrees, e.g. ones built by [`Graph::from_adjacency`].
+    /// In a directed graph the degree counts incoming edges too.
+    pub fn recompute_weight(&mut self, node: NodeId) {
+        let Some(params) = self.weight_params else {
+            return;
+        };
+        self.nodes[node].weight = params.weight(self.degree(node));
+    }
+
+    /// Estimated heap usage of the graph in bytes. Hash sets are counted
+    /// with one control byte per slot, like hashbrown lays them out.
+    pub fn memory_bytes(&self) -> usize {
+        fn set_bytes<T>(capacity: usize) -> usize {
+            capacity * (size_of::<T>() + 1)
+        }
+        let adjacency: usize = self
+            .nodes
+            .iter()
+            .map(|node| node.neighbors.capacity() * size_of::<u32>())
+            .sum();
+        self.nodes.capacity() * size_of::<Node>()
+            + adjacency
+            + self.edges.capacity() * size_of::<(NodeId, NodeId)>()
+            + set_bytes::<(EdgeKey, usize)>(self.edge_index.capacity())
+            + self.in_degrees.capacity() * size_of::<u32>()
+            + self
+                .original_ids
+                .as_ref()
+                .map_or(0, |ids| ids.capacity() * size_of::<u64>())
+    }
+
+    /// Serializes the graph as an iGraph edge list with a
+    /// `# Nodes: N Edges: M` header, followed by a `# Directed` line for
+    /// directed graphs and an `# Original ids:` line if the graph has
+    /// [`Graph::original_ids`].
+    pub fn to_igraph_string(&self) -> String {
+        let mut out = format!(
+            "# Nodes: {} Edges: {}\n",
+            self.nodes.len(),
+            self.edges.len()
+        );
+        if self.directed {
+            out.push_str("# Directed\n");
+        }
+        if let Some(ids) = &self.original_ids {
+            out.push_str("# Original ids:");
+            for id in ids {
+                write!(out, " {id}").unwrap();
+            }
+            out.push('\n');
+        }
+        for (u, v) in &self.edges {
+            writeln!(out, "{u} {v}").unwrap();
+        }
+        out
+    }
+
+    /// Writes the graph to `path` in the format of [`Graph::to_igraph_string`].
+    pub fn to_igraph_file(&self, path: &str) -> io::Result<()> {
+        std::fs::write(path, self.to_igraph_string())
+    }
+
+    /// Parses a graph from an iGraph edge list as written by
+    /// [`Graph::to_igraph_string`]. Further `#` comment lines are ignored.
+    /// With a `# Directed` line the graph is built by
+    /// [`Graph::from_directed_edge_list`].
+    ///
+    /// Node weights are recomputed from the degrees, so they only match the
+    /// serialized graph if it was not derived from a larger one.
+    pub fn from_igraph_string(s: &str, dim: usize, dim_hint: usize) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut lines = s.lines().map(str::trim).filter(|l| !l.is_empty());
+
+        let header = lines
+            .next()
+            .ok_or_else(|| invalid("missing iGraph header".to_string()))?;
+        let (num_nodes, num_edges) = match header.split_ascii_whitespace().collect::<Vec<_>>()[..] {
+            ["#", "Nodes:", n, "Edges:", m] => (
+                n.parse::<usize>()
+                    .map_err(|e| invalid(format!("invalid node count {n:?}: {e}")))?,
+                m.parse::<usize>()
+                    .map_err(|e| invalid(format!("invalid edge count {m:?}: {e}")))?,
+            ),
+            _ => return Err(invalid(format!("invalid iGraph header {header:?}"))),
+        };
+
+        let mut edges = Vec::with_capacity(num_edges);
+        let mut original_ids = None;
+        let mut directed = false;
+        for line in lines {
+            if line == "# Directed" {
+                directed = true;
+                continue;
+            }
+            if let Some(ids) = line.strip_prefix("# Original ids:") {
+                let ids = ids
+                    .split_ascii_whitespace()
+                    .map(|id| {
+                        id.parse::<u64>()
+                            .map_err(|e| invalid(format!("invalid original id {id:?}: {e}")))
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                if ids.len() != num_nodes {
+                    return Err(invalid(format!(
+                        "expected {num_nodes} original ids, found {}",
+                        ids.len()
+                    )));
+                }
+                original_ids = Some(ids);
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+            let mut ids = line.split_ascii_whitespace().map(|id| {
+                id.parse::<usize>()
+                    .map_err(|e| invalid(format!("invalid node id {id:?}: {e}")))
+            });
+            let (Some(u), Some(v), None) = (ids.next(), ids.next(), ids.next()) else {
+                return Err(invalid(format!("invalid edge line {line:?}")));
+            };
+            let (u, v) = (u?, v?);
+            if u >= num_nodes || v >= num_nodes {
+                return Err(invalid(format!(
+                    "edge ({u}, {v}) out of range for {num_nodes} nodes"
+                )));
+            }
+            edges.push((u, v));
+        }
+        if edges.len() != num_edges {
+            return Err(invalid(format!(
+                "header announces {num_edges} edges, found {}",
+                edges.len()
+            )));
+        }
+
+        let mut graph = Self::build_from_edge_list(edges, dim, dim_hint, directed)?;
+        graph.original_ids = original_ids;
+        if graph.nodes.len() != num_nodes {
+            return Err(invalid(format!(
+                "header announces {num_nodes} nodes, but only {} are connected",
+                graph.nodes.len()
+            )));
+        }
+        Ok(graph)
+    }
+
+    /// Weighted diameter, where adjacent nodes `u, v` are `1/(w_u * w_v)`
+    /// apart. The result is within a factor of `1 + epsilon` below the exact
+    /// diameter; `epsilon = 0.0` computes it exactly.
+    /// Returns infinity for disconnected graphs. Edges of a directed graph
+    /// are followed both ways, like the embedder's attraction acts on both
+    /// ends.
+    pub fn weighted_diameter(&self, epsilon: f64) -> f64 {
+        self.bounding_eccentricities(Extremum::Diameter, epsilon)
+    }
+
+    /// Weighted radius, the smallest weighted eccentricity of any node. Uses
+    /// the same distances as [`Graph::weighted_diameter`].
+    pub fn weighted_radius(&self) -> f64 {
+        self.bounding_eccentricities(Extremum::Radius, 0.0)
+    }
+
+    /// Distances from `source` with edge lengths `1/(w_u * w_v)`. For a
+    /// directed graph `in_neighbors` has to hold [`Graph::in_neighbors`], so
+    /// the edges are followed both ways.
+    fn weighted_distances(&self, source: NodeId, in_neighbors: Option<&[Vec<NodeId>]>) -> Vec<f64> {
+        let mut dist = vec![f64::INFINITY; self.nodes.len()];
+        let mut queue = BinaryHeap::new();
+        dist[source] = 0.0;
+        queue.push(Reverse(Distance(0.0, source)));
+        while let Some(Reverse(Distance(d, u))) = queue.pop() {
+            if d > dist[u] {
+                continue;
+            }
+            let w_u = self.nodes[u].weight;
+            for v in self.adjacent(u, in_neighbors) {
+                let next = d + 1.0 / (w_u * self.nodes[v].weight);
+                if next < dist[v] {
+                    dist[v] = next;
+                    queue.push(Reverse(Distance(next, v)));
+                }
+            }
+        }
+        dist
+    }
+
+    /// Bins the node weights into `num_classes` log-spaced classes between
+    /// the smallest and the largest positive weight and counts the edges
+    /// between every pair of classes. Nodes without a positive weight fall
+    /// into the first class.
+    pub fn weight_class_edge_counts(&self, num_classes: usize) -> WeightClassEdgeCounts {
+        assert!(num_classes > 0, "need at least one weight class");
+        let positive = self.nodes.iter().map(|n| n.weight).filter(|&w| w > 0.0);
+        let min = positive.clone().fold(f64::INFINITY, f64::min);
+        let max = positive.fold(0.0, f64::max);
+        if max == 0.0 {
+            return WeightClassEdgeCounts {
+                classes: vec![(0.0, 0.0)],
+                counts: vec![vec![self.edges.len()]],
+            };
+        }
+
+        let log_span = (max / min).ln();
+        let bound = |i: usize| min * (log_span * i as f64 / num_classes as f64).exp();
+        let classes = (0..num_classes).map(|i| (bound(i), bound(i + 1))).collect();
+        let class_of = |weight: f64| {
+            if weight <= min || log_span == 0.0 {
+                return 0;
+            }
+            let class = ((weight / min).ln() / log_span * num_classes as f64) as usize;
+            class.min(num_classes - 1)
+        };
+
+        let mut counts = vec![vec![0; num_classes]; num_classes];
+        for (u, node) in self.nodes.iter().enumerate() {
+            let class_u = class_of(node.weight);
+            for v in node.neighbor_ids().iter().filter(|&v| u < v) {
+                let class_v = class_of(self.nodes[v].weight);
+                counts[class_u][class_v] += 1;
+                if class_u != class_v {
+                    counts[class_v][class_u] += 1;
+                }
+            }
+        }
+        WeightClassEdgeCounts { classes, counts }
+    }
+
+    /// PageRank where a teleporting walker jumps to node `v` with probability
+    /// proportional to `degree(v)^weight_bias` instead of uniformly, which
+    /// favours hubs like the weights of the GIRG model do. `alpha` is the
+    /// probability of following an edge rather than teleporting. Iterates
+    /// until the ranks change by less than [`PAGE_RANK_TOLERANCE`] or for
+    /// `max_iter` iterations. The ranks sum to 1.
+    ///
+    /// In a directed graph the walker follows the edges forward, and the
+    /// teleport weights use the total degree, like [`Graph::recompute_weight`].
+    pub fn page_rank_weighted(&self, alpha: f64, weight_bias: f64, max_iter: usize) -> Vec<f64> {
+        assert!(
+            (0.0..=1.0).contains(&alpha),
+            "alpha must be a probability, got {alpha}"
+        );
+        let n = self.nodes.len();
+        if n == 0 {
+            return Vec::new();
+        }
+        let in_neighbors = self.in_neighbors();
+        let degree = |v: NodeId| self.nodes[v].neighbors.len() as f64;
+
+        let mut teleport: Vec<f64> = (0..n)
+            .map(|v| (self.degree(v) as f64).powf(weight_bias))
+            .collect();
+        let total: f64 = teleport.iter().sum();
+        if total > 0.0 && total.is_finite() {
+            teleport.iter_mut().for_each(|t| *t /= total);
+        } else {
+            teleport.fill(1.0 / n as f64);
+        }
+
+        let mut rank = teleport.clone();
+        let mut next = vec![0.0; n];
+        for _ in 0..max_iter {
+            // Walkers on isolated nodes have no edge to follow and teleport
+            let dangling: f64 = (0..n).filter(|&v| degree(v) == 0.0).map(|v| rank[v]).sum();
+            for (v, next) in next.iter_mut().enumerate() {
+                let incoming: f64 = in_neighbors[v].iter().map(|&u| rank[u] / degree(u)).sum();
+                *next = (1.0 - alpha + alpha * dangling) * teleport[v] + alpha * incoming;
+            }
+            let change: f64 = rank.iter().zip(&next).map(|(a, b)| (a - b).abs()).sum();
+            std::mem::swap(&mut rank, &mut next);
+            if change < PAGE_RANK_TOLERANCE {
+                break;
+            }
+        }
+        rank
+    }
+
+    /// Bounds every node's eccentricity from a few single-source searches
+    /// (Takes & Kosters, "Determining the diameter of small world networks")
+    /// until the requested extremum is pinned down.
+    fn bounding_eccentricities(&self, extremum: Extremum, epsilon: f64) -> f64 {
+        let n = self.nodes.len();
+        if n == 0 {
+            return 0.0;
+        }
+        let in_neighbors = self.directed.then(|| self.in_neighbors());
+        let mut lower = vec![0.0f64; n];
+        let mut upper = vec![f64::INFINITY; n];
+        let mut candidates: Vec<NodeId> = (0..n).collect();
+        loop {
+            // Start at the best bounded candidate, preferring hubs on ties
+            let key = |&v: &NodeId| match extremum {
+                Extremum::Diameter => upper[v],
+                Extremum::Radius => -lower[v],
+            };
+            let source = *candidates
+                .iter()
+                .max_by(|a, b| {
+                    key(a).total_cmp(&key(b)).then(
+                        self.nodes[**a]
+                            .neighbors
+                            .len()
+                            .cmp(&self.nodes[**b].neighbors.len()),
+                    )
+                })
+                .unwrap();
+
+            let dist = self.weighted_distances(source, in_neighbors.as_deref());
+            let ecc = dist.iter().copied().fold(0.0, f64::max);
+            if ecc.is_infinite() {
+                return f64::INFINITY;
+            }
+            for v in 0..n {
+                lower[v] = lower[v].max(dist[v]).max(ecc - dist[v]);
+                upper[v] = upper[v].min(ecc + dist[v]);
+            }
+            lower[source] = ecc;
+            upper[source] = ecc;
+
+            let (found, bound) = match extremum {
+                Extremum::Diameter => (
+                    lower.iter().copied().fold(0.0, f64::max),
+                    upper.iter().copied().fold(0.0, f64::max),
+                ),
+                Extremum::Radius => (
+                    upper.iter().copied().fold(f64::INFINITY, f64::min),
+                    lower.iter().copied().fold(f64::INFINITY, f64::min),
+                ),
+            };
+            let converged = match extremum {
+                Extremum::Diameter => bound <= found * (1.0 + epsilon),
+                Extremum::Radius => bound >= found,
+            };
+            // Nodes whose bounds cannot improve the result need no search
+            candidates.retain(|&v| {
+                lower[v] < upper[v]
+                    && match extremum {
+                        Extremum::Diameter => upper[v] > found,
+                        Extremum::Radius => lower[v] < found,
+                    }
+            });
+            if converged || candidates.is_empty() {
+                return found;
+            }
+        }
+    }
+}
+
+/// L1 change of the ranks below which [`Graph::page_rank_weighted`] stops
+pub const PAGE_RANK_TOLERANCE: f64 = 1e-12;
+
+/// Edge counts between node weight classes, see
+/// [`Graph::weight_class_edge_counts`]. Displays as an ASCII heatmap.
+#[derive(Clone, Debug)]
+pub struct WeightClassEdgeCounts {
+    /// Weight range `[lower, upper)` of every class. The last class also
+    /// contains its upper bound.
+    pub classes: Vec<(f64, f64)>,
+    /// `counts[i][j]` edges between class `i` and class `j`. Symmetric, edges
+    /// within a class are counted once on the diagonal.
+    pub counts: Vec<Vec<usize>>,
+}
+
+impl std::fmt::Display for WeightClassEdgeCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Log-scaled, so sparse classes stay visible next to dense ones
+        const RAMP: &[u8] = b" .:-=+*#%@";
+        let max = self.counts.iter().flatten().copied().max().unwrap_or(0);
+        let shade = |count: usize| {
+            if count == 0 {
+                return ' ';
+            }
+            let level = ((count as f64).ln_1p() / (max as f64).ln_1p() * (RAMP.len() - 1) as f64)
+                .ceil() as usize;
+            RAMP[level.min(RAMP.len() - 1)] as char
+        };
+
+        writeln!(
+            f,
+            "{:>22}  {:>9}  heatmap (max {max})",
+            "weight class", "edges"
+        )?;
+        for ((lower, upper), row) in self.classes.iter().zip(&self.counts) {
+            let edges: usize = row.iter().sum();
+            let cells: String = row.iter().map(|&count| shade(count)).collect();
+            writeln!(f, "[{lower:>9.3}, {upper:>9.3})  {edges:>9}  |{cells}|")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Extremum {
+    Diameter,
+    Radius,
+}
+
+/// Dijkstra queue entry ordered by distance
+#[derive(PartialEq)]
+struct Distance(f64, NodeId);
+
+impl Eq for Distance {}
+
+impl PartialOrd for Distance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Distance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
 }
 
 impl crate::query::Graph for Graph {
     fn is_connected(&self, first: NodeId, second: NodeId) -> bool {
-        self.edge_set.contains(&EdgeKey::new(first, second))
+        self.has_edge_between(first, second)
     }
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
-        &self.nodes[index].neighbors
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
+        self.nodes[index].neighbor_ids()
     }
     fn weight(&self, index: NodeId) -> f64 {
         self.nodes[index].weight
     }
 }
+
+/// `side` x `side` grid graph, node `u` in row `u / side` and column
+/// `u % side`
+#[cfg(test)]
+pub(crate) fn grid(side: usize) -> Graph {
+    let mut edges = Vec::new();
+    for u in 0..side * side {
+        if u % side + 1 < side {
+            edges.push((u, u + 1));
+        }
+        if u + side < side * side {
+            edges.push((u, u + side));
+        }
+    }
+    Graph::from_edge_list(edges, 2, 2).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Graph, ParseWarning};
+    use crate::query::Graph as _;
+
+    #[test]
+    fn igraph_round_trip() {
+        let graph =
+            Graph::from_edge_list(vec![(0, 1), (1, 2), (2, 3), (3, 0), (0, 2)], 2, 2).unwrap();
+        let text = graph.to_igraph_string();
+        assert!(text.starts_with("# Nodes: 4 Edges: 5\n"));
+
+        let parsed = Graph::from_igraph_string(&text, 2, 2).unwrap();
+        assert_eq!(parsed.edges, graph.edges);
+        assert_eq!(parsed.nodes.len(), graph.nodes.len());
+        for (a, b) in parsed.nodes.iter().zip(&graph.nodes) {
+            assert_eq!(a.neighbors, b.neighbors);
+            assert_eq!(a.weight, b.weight);
+        }
+        assert!(parsed.is_connected(2, 0));
+        assert_eq!(parsed.to_igraph_string(), text);
+    }
+
+    #[test]
+    fn directed_igraph_round_trip() {
+        let graph =
+            Graph::from_directed_edge_list(vec![(0, 1), (1, 2), (2, 0), (3, 0)], 2, 2).unwrap();
+        let text = graph.to_igraph_string();
+        let parsed = Graph::from_igraph_string(&text, 2, 2).unwrap();
+        assert!(parsed.is_directed());
+        assert_eq!(parsed.edges, graph.edges);
+        for (a, b) in parsed.nodes.iter().zip(&graph.nodes) {
+            assert_eq!(a.neighbors, b.neighbors);
+            assert_eq!(a.weight, b.weight);
+        }
+        assert_eq!(parsed.to_igraph_string(), text);
+    }
+
+    #[test]
+    fn reweighted_graph_matches_a_fresh_one() {
+        let edges = vec![(0, 1), (1, 2), (2, 3), (3, 0), (0, 2), (0, 1)];
+        let graph = Graph::from_edge_list(edges.clone(), 2, 2).unwrap();
+        let fresh = Graph::from_edge_list(edges, 8, 2).unwrap();
+
+        let reweighted = graph.reweighted(8, 2);
+        assert_eq!(reweighted.edges, fresh.edges);
+        for (a, b) in reweighted.nodes.iter().zip(&fresh.nodes) {
+            assert_eq!(a.neighbors, b.neighbors);
+            assert_eq!(a.weight, b.weight);
+        }
+        assert_ne!(reweighted.nodes[0].weight, graph.nodes[0].weight);
+    }
+
+    #[test]
+    fn weighted_page_rank_favours_high_degree_nodes() {
+        // Hub 0 with six leaves, two of which have two leaves of their own
+        let edges = vec![
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (0, 5),
+            (0, 6),
+            (1, 7),
+            (1, 8),
+            (2, 9),
+            (2, 10),
+        ];
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+
+        for weight_bias in [0.0, 1.0, 2.0] {
+            let rank = graph.page_rank_weighted(0.85, weight_bias, 100);
+            assert!((rank.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+            // Degrees 6 > 3 > 1
+            assert!(rank[0] > rank[1] && rank[1] > rank[3], "{rank:?}");
+            assert!(rank[1] > rank[7], "{rank:?}");
+        }
+        let uniform = graph.page_rank_weighted(0.85, 0.0, 100);
+        let biased = graph.page_rank_weighted(0.85, 2.0, 100);
+        assert!(biased[0] > uniform[0]);
+    }
+
+    #[test]
+    fn weighted_page_rank_follows_directed_edges() {
+        // Three leaves pointing at 0, which has no edge to follow
+        let graph = Graph::from_directed_edge_list(vec![(1, 0), (2, 0), (3, 0)], 2, 2).unwrap();
+        let alpha = 0.85;
+        let rank = graph.page_rank_weighted(alpha, 0.0, 100);
+        // Every leaf only gets teleported walkers, 0 also those of the leaves
+        let leaf = 1.0 / (4.0 + 3.0 * alpha);
+        assert!(
+            (rank[0] - (1.0 + 3.0 * alpha) * leaf).abs() < 1e-9,
+            "{rank:?}"
+        );
+        for &r in &rank[1..] {
+            assert!((r - leaf).abs() < 1e-9, "{rank:?}");
+        }
+    }
+
+    #[test]
+    fn weighted_diameter_and_radius_match_all_pairs() {
+        // Two triangles joined by a path, plus a pendant hub
+        let edges = vec![
+            (0, 1),
+            (1, 2),
+            (2, 0),
+            (2, 3),
+            (3, 4),
+            (4, 5),
+            (5, 6),
+            (6, 7),
+            (7, 5),
+            (8, 3),
+            (8, 4),
+            (8, 9),
+            (8, 10),
+        ];
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let eccentricities: Vec<f64> = (0..graph.nodes.len())
+            .map(|v| {
+                graph
+                    .weighted_distances(v, None)
+                    .into_iter()
+                    .fold(0.0, f64::max)
+            })
+            .collect();
+        let diameter = eccentricities.iter().copied().fold(0.0, f64::max);
+        let radius = eccentricities.iter().copied().fold(f64::INFINITY, f64::min);
+
+        assert!((graph.weighted_diameter(0.0) - diameter).abs() < 1e-9);
+        assert!((graph.weighted_radius() - radius).abs() < 1e-9);
+        let approx = graph.weighted_diameter(0.5);
+        assert!(approx <= diameter + 1e-9 && approx * 1.5 >= diameter - 1e-9);
+
+        let disconnected = Graph::from_edge_list(vec![(0, 1), (2, 3)], 2, 2).unwrap();
+        assert!(disconnected.weighted_diameter(0.0).is_infinite());
+    }
+
+    #[test]
+    fn weight_class_edge_counts_are_symmetric() {
+        // A star with 4 leaves and a path of 3 nodes attached to one leaf
+        let edges = vec![(0, 1), (0, 2), (0, 3), (0, 4), (4, 5), (5, 6)];
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let summary = graph.weight_class_edge_counts(3);
+
+        // Degrees 1, 2 and 4 fall into one class each
+        assert_eq!(summary.classes.len(), 3);
+        assert_eq!(
+            summary.counts,
+            vec![vec![0, 1, 3], vec![1, 1, 1], vec![3, 1, 0]]
+        );
+        let (lower, _) = summary.classes[0];
+        let (_, upper) = summary.classes[2];
+        assert!((lower - graph.nodes[1].weight).abs() < 1e-12);
+        assert!((upper - graph.nodes[0].weight).abs() < 1e-12);
+        assert_eq!(summary.to_string().lines().count(), 4);
+    }
+
+    #[test]
+    fn original_ids_survive_reduction_and_relabeling() {
+        // A 5-cycle on odd ids plus a separate edge
+        let edges = vec![(1, 3), (3, 5), (5, 7), (7, 9), (9, 1), (0, 2)];
+        let input = Graph::from_edge_list(edges.clone(), 2, 2).unwrap();
+        let (component, reduce) = input.reduce_to_largest_component();
+        assert_eq!(reduce, vec![1, 3, 5, 7, 9]);
+
+        let order = vec![4, 2, 0, 3, 1];
+        let relabeled = component.relabel(&order);
+        let mapping = Graph::compose_mapping(&reduce, &order);
+        for (v, &old) in mapping.iter().enumerate() {
+            assert_eq!(relabeled.original_id(v), old as u64);
+            assert_eq!(relabeled.nodes[v].weight, input.nodes[old].weight);
+        }
+        for &(u, v) in &relabeled.edges {
+            let (u, v) = (relabeled.original_id(u), relabeled.original_id(v));
+            assert!(edges.contains(&(u as usize, v as usize)));
+        }
+
+        let parsed = Graph::from_igraph_string(&relabeled.to_igraph_string(), 2, 2).unwrap();
+        assert_eq!(parsed.original_ids, relabeled.original_ids);
+    }
+
+    #[test]
+    fn directed_graphs_are_reduced_and_measured_without_directions() {
+        // A cycle that node 3 points into, and a separate edge
+        let edges = vec![(0, 1), (1, 2), (2, 0), (3, 0), (4, 5)];
+        let graph = Graph::from_directed_edge_list(edges.clone(), 2, 2).unwrap();
+        let undirected = Graph::from_edge_list(edges, 2, 2).unwrap();
+
+        let (component, mapping) = graph.reduce_to_largest_component();
+        assert_eq!(mapping, vec![0, 1, 2, 3]);
+        assert!(component.is_directed());
+        assert_eq!(component.edges, [(0, 1), (1, 2), (2, 0), (3, 0)]);
+
+        // Node 3 only has an outgoing edge, but is still reached from the cycle
+        let (reduced, _) = undirected.reduce_to_largest_component();
+        assert!(component.weighted_diameter(0.0).is_finite());
+        assert!((component.weighted_diameter(0.0) - reduced.weighted_diameter(0.0)).abs() < 1e-9);
+        assert!((component.weighted_radius() - reduced.weighted_radius()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn contract_edge_merges_neighbourhoods() {
+        // Triangle 0-1-2 with pendants 3 at node 1 and 4 at node 2
+        let graph =
+            Graph::from_edge_list(vec![(0, 1), (1, 2), (2, 0), (1, 3), (2, 4)], 2, 2).unwrap();
+        let contracted = graph.contract_edge(2, 1);
+
+        assert_eq!(contracted.nodes.len(), 4);
+        assert_eq!(contracted.nodes[1].neighbors, vec![0, 2, 3]);
+        assert_eq!(contracted.nodes[0].neighbors, vec![1]);
+        assert_eq!(
+            contracted.nodes[1].weight,
+            graph.nodes[1].weight + graph.nodes[2].weight
+        );
+        assert_eq!(contracted.edges, vec![(0, 1), (1, 2), (1, 3)]);
+        assert!(contracted.is_connected(3, 1));
+        assert_eq!(contracted.original_id(3), 4);
+    }
+
+    #[test]
+    fn contract_matching_equals_contracting_one_edge_at_a_time() {
+        let edges = vec![
+            (0, 1),
+            (1, 2),
+            (2, 3),
+            (3, 0),
+            (2, 4),
+            (4, 5),
+            (5, 6),
+            (6, 2),
+            (1, 5),
+        ];
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let matching = graph.coarsening_matching();
+        assert!(matching.len() >= 3, "{matching:?}");
+
+        let (contracted, coarse) = graph.contract_matching(&matching);
+        // Contracting the pair with the largest removed id first keeps the
+        // ids of the remaining pairs valid
+        let mut pairs = matching.clone();
+        pairs.sort_by_key(|&(u, v)| std::cmp::Reverse(u.max(v)));
+        let expected = pairs
+            .iter()
+            .fold(graph.clone(), |g, &(u, v)| g.contract_edge(u, v));
+
+        assert_eq!(contracted.edges, expected.edges);
+        assert_eq!(contracted.original_ids, expected.original_ids);
+        for (a, b) in contracted.nodes.iter().zip(&expected.nodes) {
+            assert_eq!(a.neighbors, b.neighbors);
+            assert_eq!(a.weight, b.weight);
+        }
+        for &(u, v) in &matching {
+            assert_eq!(coarse[u], coarse[v]);
+        }
+    }
+
+    #[test]
+    fn edge_updates_recompute_weights() {
+        let mut graph = Graph::from_edge_list(vec![(0, 1), (1, 2), (2, 3), (3, 4)], 2, 2).unwrap();
+        let before: Vec<f64> = graph.nodes.iter().map(|node| node.weight).collect();
+
+        assert!(graph.add_edge(0, 2));
+        assert!(!graph.add_edge(2, 0));
+        assert!(graph.add_edge(0, 3));
+        assert!(graph.nodes[0].weight > before[0]);
+        assert!(graph.nodes[2].weight > before[2]);
+        // Equal degrees give equal weights, no matter how they were reached
+        assert_eq!(graph.nodes[0].weight, graph.nodes[2].weight);
+        assert_eq!(graph.nodes[1].weight, before[1]);
+        assert_eq!(graph.nodes[0].neighbors, vec![1, 2, 3]);
+
+        assert!(graph.remove_edge(3, 0));
+        assert!(graph.remove_edge(0, 2));
+        assert!(!graph.remove_edge(0, 2));
+        assert!(!graph.is_connected(0, 2));
+        let after: Vec<f64> = graph.nodes.iter().map(|node| node.weight).collect();
+        assert_eq!(after, before);
+        assert_eq!(graph.edges.len(), 4);
+    }
+
+    #[test]
+    fn duplicate_edges_do_not_count_towards_the_degree() {
+        let graph = Graph::from_edge_list(vec![(0, 1), (1, 0), (1, 2), (1, 2)], 2, 2).unwrap();
+        let simple = Graph::from_edge_list(vec![(0, 1), (1, 2)], 2, 2).unwrap();
+        assert_eq!(graph.edges, [(0, 1), (1, 2)]);
+        for (a, b) in graph.nodes.iter().zip(&simple.nodes) {
+            assert_eq!(a.weight, b.weight);
+        }
+        // Recomputing a weight whose degree didn't change keeps it
+        let mut recomputed = graph.clone();
+        for v in 0..3 {
+            recomputed.recompute_weight(v);
+            assert_eq!(recomputed.nodes[v].weight, graph.nodes[v].weight);
+        }
+        assert!(recomputed.remove_edge(1, 0));
+        assert!(!recomputed.is_connected(0, 1));
+        assert!(recomputed.add_edge(1, 0));
+        for (a, b) in recomputed.nodes.iter().zip(&graph.nodes) {
+            assert_eq!(a.weight, b.weight);
+        }
+
+        let directed =
+            Graph::from_directed_edge_list(vec![(0, 1), (0, 1), (1, 0), (1, 2)], 2, 2).unwrap();
+        assert_eq!(directed.edges, [(0, 1), (1, 0), (1, 2)]);
+        let mut recomputed = directed.clone();
+        for v in 0..3 {
+            recomputed.recompute_weight(v);
+            assert_eq!(recomputed.nodes[v].weight, directed.nodes[v].weight);
+        }
+    }
+
+    #[test]
+    fn bipartition_of_complete_graphs() {
+        let k33 = (0..3).flat_map(|u| (3..6).map(move |v| (u, v))).collect();
+        let k33 = Graph::from_edge_list(k33, 2, 2).unwrap();
+        assert!(k33.is_bipartite());
+        assert_eq!(k33.bipartition(), Some((vec![0, 1, 2], vec![3, 4, 5])));
+
+        let k4 = (0..4)
+            .flat_map(|u| (u + 1..4).map(move |v| (u, v)))
+            .collect();
+        let k4 = Graph::from_edge_list(k4, 2, 2).unwrap();
+        assert!(!k4.is_bipartite());
+        assert_eq!(k4.bipartition(), None);
+
+        // An even cycle next to an isolated edge
+        let edges = vec![(0, 1), (1, 2), (2, 3), (3, 0), (4, 5)];
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        assert_eq!(graph.bipartition(), Some((vec![0, 2, 4], vec![1, 3, 5])));
+    }
+
+    #[test]
+    fn bipartition_of_directed_graphs_ignores_directions() {
+        // A 4-cycle whose edges all leave 1 and 3, so 0 has no outgoing edge
+        let edges = vec![(1, 0), (1, 2), (3, 2), (3, 0)];
+        let graph = Graph::from_directed_edge_list(edges, 2, 2).unwrap();
+        assert_eq!(graph.bipartition(), Some((vec![0, 2], vec![1, 3])));
+
+        let triangle = Graph::from_directed_edge_list(vec![(1, 0), (2, 0), (2, 1)], 2, 2).unwrap();
+        assert_eq!(triangle.bipartition(), None);
+    }
+
+    #[test]
+    fn directed_edges_only_point_one_way() {
+        let mut graph = Graph::from_directed_edge_list(vec![(0, 1), (1, 2), (2, 1)], 2, 2).unwrap();
+        let undirected = Graph::from_edge_list(vec![(0, 1), (1, 2)], 2, 2).unwrap();
+        assert!(graph.is_directed() && !undirected.is_directed());
+        assert_eq!(graph.neighbors(0).to_vec(), vec![1]);
+        assert!(graph.neighbors(1).into_iter().eq([2]));
+        assert!(graph.neighbors(2).into_iter().eq([1]));
+        // Both orders count as connected, so the ends don't repel each other
+        assert!(graph.is_connected(1, 0));
+        // (1, 2) and (2, 1) both count towards the degrees
+        let one_way = Graph::from_directed_edge_list(vec![(0, 1), (1, 2)], 2, 2).unwrap();
+        for (a, b) in one_way.nodes.iter().zip(&undirected.nodes) {
+            assert_eq!(a.weight, b.weight);
+        }
+        assert!(graph.nodes[1].weight > graph.nodes[2].weight);
+        assert!(graph.nodes[2].weight > graph.nodes[0].weight);
+
+        assert!(graph.add_edge(1, 0));
+        assert!(!graph.add_edge(1, 0));
+        assert!(graph.neighbors(0).into_iter().eq([1]));
+        assert!(graph.remove_edge(0, 1));
+        assert!(!graph.remove_edge(0, 1));
+        assert!(graph.is_connected(0, 1));
+        assert!(graph.remove_edge(1, 0));
+        assert!(!graph.is_connected(0, 1));
+        assert!(graph.neighbors(1).into_iter().eq([2]));
+        let mut edges = graph.edges.clone();
+        edges.sort_unstable();
+        assert_eq!(edges, [(1, 2), (2, 1)]);
+        assert!(graph.induced_subgraph(&[1, 2]).is_directed());
+    }
+
+    #[test]
+    fn strongly_connected_components_of_a_cycle_with_tails() {
+        // 1 -> 2 -> 3 -> 1, entered from 0 and left towards 4 -> 5
+        let edges = vec![(0, 1), (1, 2), (2, 3), (3, 1), (3, 4), (4, 5)];
+        let graph = Graph::from_directed_edge_list(edges.clone(), 2, 2).unwrap();
+        assert_eq!(
+            graph.strongly_connected_components(),
+            vec![vec![0], vec![1, 2, 3], vec![4], vec![5]]
+        );
+        assert!(!graph.is_strongly_connected());
+
+        let mut graph = graph;
+        assert!(graph.add_edge(5, 0));
+        assert!(graph.is_strongly_connected());
+        // Without directions the components are the connected ones
+        let undirected = Graph::from_edge_list(edges, 2, 2).unwrap();
+        assert!(undirected.is_strongly_connected());
+        // A long path doesn't overflow the stack
+        let path = (0..100_000).map(|u| (u, u + 1)).collect();
+        let path = Graph::from_directed_edge_list(path, 2, 2).unwrap();
+        assert_eq!(path.strongly_connected_components().len(), 100_001);
+    }
+
+    #[test]
+    fn neighbors_are_returned_as_node_ids() {
+        let graph = Graph::from_edge_list(vec![(0, 3), (3, 1), (2, 3)], 2, 2).unwrap();
+        let neighbors = graph.neighbors(3);
+        assert_eq!(neighbors.len(), 3);
+        assert_eq!(neighbors.to_vec(), vec![0, 1, 2]);
+        assert!(neighbors.contains(2) && !neighbors.contains(3));
+        assert!(!neighbors.contains(1 << 40));
+        assert!(graph.neighbors(0).into_iter().eq([3]));
+
+        let without_hub = graph.induced_subgraph(&[0, 1, 2]);
+        assert!(without_hub.neighbors(0).is_empty());
+        assert!(without_hub.memory_bytes() < graph.memory_bytes());
+    }
+
+    #[test]
+    fn igraph_invalid_input() {
+        assert!(Graph::from_igraph_string("", 2, 2).is_err());
+        assert!(Graph::from_igraph_string("0 1\n", 2, 2).is_err());
+        assert!(Graph::from_igraph_string("# Nodes: 2 Edges: 2\n0 1\n", 2, 2).is_err());
+        assert!(Graph::from_igraph_string("# Nodes: 2 Edges: 1\n0 2\n", 2, 2).is_err());
+        assert!(Graph::from_igraph_string("# Nodes: 3 Edges: 1\n0 1\n", 2, 2).is_err());
+        assert!(Graph::from_igraph_string("# Nodes: 2 Edges: 1\n# comment\n0 1\n", 2, 2).is_ok());
+    }
+
+    #[test]
+    fn edge_list_ids_with_gaps_are_remapped() {
+        let path = std::env::temp_dir().join(format!("gapped-ids-{}.txt", std::process::id()));
+        let parse = |contents: &str, remap_ids: bool| {
+            std::fs::write(&path, contents).unwrap();
+            Graph::parse_edge_list_file_with_warnings(path.to_str().unwrap(), 2, 2, remap_ids)
+                .unwrap()
+        };
+
+        // 1-indexed, and id 7 is unused
+        let (graph, warnings) = parse("1 2\n2 8\n8 1\n", true);
+        assert_eq!(
+            warnings,
+            [ParseWarning::NodeIdsRemapped {
+                nodes: 3,
+                max_id: 8
+            }]
+        );
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.edges, [(0, 1), (1, 2), (2, 0)]);
+        assert_eq!(graph.original_node_ids(), Some(vec![1, 2, 8]));
+        assert_eq!(graph.original_id(2), 8);
+
+        let (graph, warnings) = parse("1 2\n2 8\n8 1\n", false);
+        assert_eq!(
+            warnings,
+            [ParseWarning::NonConsecutiveNodeIds {
+                nodes: 3,
+                max_id: 8
+            }]
+        );
+        assert_eq!(graph.nodes.len(), 9);
+        assert_eq!(graph.edges, [(1, 2), (2, 8), (8, 1)]);
+        assert_eq!(graph.original_node_ids(), None);
+
+        // Remapping is the default
+        std::fs::write(&path, "1 2\n2 8\n8 1\n").unwrap();
+        let graph = Graph::parse_from_edge_list_file(path.to_str().unwrap(), 2, 2).unwrap();
+        assert_eq!(graph.original_node_ids(), Some(vec![1, 2, 8]));
+
+        let (graph, warnings) = parse("0 1\n1 2\n", true);
+        std::fs::remove_file(&path).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(graph.original_node_ids(), None);
+    }
+}
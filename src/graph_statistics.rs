@@ -0,0 +1,158 @@
+use crate::NodeId;
+
+/// Weight class of a node: class `k` holds the weights in
+/// `min_weight * 2^k..min_weight * 2^(k + 1)`, where `min_weight` is the
+/// smallest positive weight in the graph. Weights up to `min_weight`,
+/// including the zero weight of isolated nodes, are in class 0.
+pub fn weight_class(weight: f64, min_weight: f64) -> usize {
+    if weight <= min_weight {
+        return 0;
+    }
+    (weight / min_weight).log2().floor() as usize
+}
+
+/// The `min_weight` of [`weight_class`]: the smallest positive weight, as
+/// isolated nodes have weight zero
+pub fn min_positive_weight(weights: impl Iterator<Item = f64>) -> f64 {
+    weights
+        .filter(|&weight| weight > 0.0)
+        .fold(f64::INFINITY, f64::min)
+}
+
+/// Groups the nodes by [`weight_class`]. Classes without nodes stay empty so
+/// the position in the result is the class.
+pub fn partition_by_weight_class(weights: impl Iterator<Item = f64> + Clone) -> Vec<Vec<NodeId>> {
+    let min_weight = min_positive_weight(weights.clone());
+    let mut classes: Vec<Vec<NodeId>> = Vec::new();
+    for (node, weight) in weights.enumerate() {
+        let class = weight_class(weight, min_weight);
+        if classes.len() <= class {
+            classes.resize(class + 1, Vec::new());
+        }
+        classes[class].push(node);
+    }
+    classes
+}
+
+/// Precision and recall of the nodes in one weight class. The counts are
+/// taken per sampled node, so a pair between two sampled nodes is counted
+/// from both ends.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WeightClassStatistics {
+    /// The class holds the weights in `min_weight..max_weight`
+    pub min_weight: f64,
+    pub max_weight: f64,
+    pub nodes: usize,
+    /// Nodes the counts were taken over
+    pub sampled_nodes: usize,
+    /// Graph neighbours found within the weighted distance
+    pub true_positives: usize,
+    /// Other nodes found within the weighted distance
+    pub false_positives: usize,
+    /// Graph neighbours of the sampled nodes
+    pub edges: usize,
+}
+
+impl WeightClassStatistics {
+    pub fn precision(&self) -> f64 {
+        self.true_positives as f64 / (self.true_positives + self.false_positives).max(1) as f64
+    }
+
+    pub fn recall(&self) -> f64 {
+        self.true_positives as f64 / self.edges as f64
+    }
+
+    pub fn f1(&self) -> f64 {
+        f1(self.precision(), self.recall())
+    }
+}
+
+/// Precision and recall of an embedding, overall and per weight class, see
+/// [`crate::query::Embedder::graph_statistics_report`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GraphStatisticsReport {
+    pub precision: f64,
+    pub recall: f64,
+    /// Indexed by [`weight_class`]
+    pub classes: Vec<WeightClassStatistics>,
+}
+
+impl GraphStatisticsReport {
+    /// Sums up the classes into the overall precision and recall
+    pub fn from_classes(classes: Vec<WeightClassStatistics>) -> Self {
+        let total = classes.iter().fold((0, 0, 0), |(tp, fp, edges), class| {
+            (
+                tp + class.true_positives,
+                fp + class.false_positives,
+                edges + class.edges,
+            )
+        });
+        let total = WeightClassStatistics {
+            true_positives: total.0,
+            false_positives: total.1,
+            edges: total.2,
+            ..Default::default()
+        };
+        GraphStatisticsReport {
+            precision: total.precision(),
+            recall: total.recall(),
+            classes,
+        }
+    }
+
+    pub fn f1(&self) -> f64 {
+        f1(self.precision, self.recall)
+    }
+
+    /// One row per non-empty class, for plotting f1 over the weight:
+    /// `class,min_weight,max_weight,nodes,sampled_nodes,precision,recall,f1`
+    pub fn csv(&self) -> String {
+        let mut csv =
+            String::from("class,min_weight,max_weight,nodes,sampled_nodes,precision,recall,f1\n");
+        for (class, stats) in self.classes.iter().enumerate() {
+            if stats.nodes == 0 {
+                continue;
+            }
+            csv.push_str(&format!(
+                "{class},{},{},{},{},{},{},{}\n",
+                stats.min_weight,
+                stats.max_weight,
+                stats.nodes,
+                stats.sampled_nodes,
+                stats.precision(),
+                stats.recall(),
+                stats.f1()
+            ));
+        }
+        csv
+    }
+}
+
+fn f1(precision: f64, recall: f64) -> f64 {
+    if precision + recall == 0.0 {
+        return 0.0;
+    }
+    2.0 * precision * recall / (precision + recall)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{partition_by_weight_class, weight_class};
+
+    #[test]
+    fn weight_classes_are_log_spaced() {
+        assert_eq!(weight_class(1.0, 1.0), 0);
+        assert_eq!(weight_class(1.99, 1.0), 0);
+        assert_eq!(weight_class(2.0, 1.0), 1);
+        assert_eq!(weight_class(7.9, 1.0), 2);
+        assert_eq!(weight_class(8.0, 1.0), 3);
+        assert_eq!(weight_class(3.0, 1.5), 1);
+
+        let classes = partition_by_weight_class([0.5, 4.0, 0.9, 1.0, 1.1].into_iter());
+        assert_eq!(classes, vec![vec![0, 2], vec![3, 4], vec![], vec![1]]);
+
+        // Isolated nodes have weight zero
+        let classes = partition_by_weight_class([0.0, 1.0, 2.0].into_iter());
+        assert_eq!(classes, vec![vec![0, 1], vec![2]]);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 
@@ -10,6 +11,10 @@ pub struct Grid<'a, const D: usize> {
     cells: Vec<GridCellInner>,
     cell_positions: Vec<Vec<DVec<D>>>,
     grid_size: f64,
+    /// Number of cells per dimension when the cell size follows the bounding
+    /// box instead of `grid_size`
+    target_bucket_count: Option<usize>,
+    cell_size: [f32; D],
     min: [f32; D],
     extents: [usize; D],
 }
@@ -20,17 +25,37 @@ struct GridCellInner {
 }
 
 impl<'a, const D: usize> Grid<'a, D> {
-    pub fn new(embedding: Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let mut tree = Self {
             positions: Vec::new(),
             graph: embedding.graph,
             cells: Vec::new(),
             cell_positions: Vec::new(),
             grid_size: 1.0,
+            target_bucket_count: None,
+            cell_size: [1.0; D],
             min: [0.0; D],
             extents: [1; D],
         };
-        tree.update_positions(&embedding.positions, None);
+        tree.update_positions(embedding.positions, None);
+        tree
+    }
+
+    /// Grid with `target_bucket_count` cells along every dimension, so each
+    /// dimension gets the resolution `target_bucket_count / (max - min)` of
+    /// its own extent. Embeddings stretched along one axis then keep the
+    /// same number of cells in their short dimensions instead of collapsing
+    /// them into a single cell. Radius hints are ignored.
+    pub fn with_adaptive_resolution(
+        embedding: EmbeddingRef<'_, 'a, D>,
+        target_bucket_count: usize,
+    ) -> Self {
+        let mut tree = Self::new(EmbeddingRef {
+            positions: &[],
+            graph: embedding.graph,
+        });
+        tree.target_bucket_count = Some(target_bucket_count.max(1));
+        tree.update_positions(embedding.positions, None);
         tree
     }
 
@@ -49,7 +74,7 @@ impl<'a, const D: usize> Grid<'a, D> {
     /// Compute the grid coordinate for a position along dimension `d`.
     #[inline(always)]
     fn grid_coord(&self, pos: f32, d: usize) -> usize {
-        let c = ((pos - self.min[d]) / self.grid_size as f32).floor() as usize;
+        let c = ((pos - self.min[d]) / self.cell_size[d]).floor() as usize;
         c.min(self.extents[d] - 1)
     }
 
@@ -65,8 +90,8 @@ impl<'a, const D: usize> Grid<'a, D> {
         // For each dimension, pick whichever corner edge is farther from center.
         let mut max_dist_sq = 0.0f32;
         for d in 0..D {
-            let cell_min = self.min[d] + (coords[d] as f32) * self.grid_size as f32;
-            let cell_max = cell_min + self.grid_size as f32;
+            let cell_min = self.min[d] + (coords[d] as f32) * self.cell_size[d];
+            let cell_max = cell_min + self.cell_size[d];
             let d_min = center[d] - cell_min;
             let d_max = center[d] - cell_max;
             let far = if d_min.abs() > d_max.abs() {
@@ -88,6 +113,8 @@ impl<'a, const D: usize> Clone for Grid<'a, D> {
             cells: self.cells.clone(),
             cell_positions: self.cell_positions.clone(),
             grid_size: self.grid_size,
+            target_bucket_count: self.target_bucket_count,
+            cell_size: self.cell_size,
             min: self.min,
             extents: self.extents,
         };
@@ -101,7 +128,7 @@ impl<'a, const D: usize> Graph for Grid<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -133,7 +160,12 @@ impl<'a, const D: usize> Update<D> for Grid<'a, D> {
         }
         self.min = min;
         for d in 0..D {
-            self.extents[d] = ((max[d] - min[d]) / self.grid_size as f32).ceil().max(1.0) as usize;
+            let extent = max[d] - min[d];
+            self.cell_size[d] = match self.target_bucket_count {
+                Some(count) if extent > 0.0 => extent / count as f32,
+                _ => self.grid_size as f32,
+            };
+            self.extents[d] = (extent / self.cell_size[d]).ceil().max(1.0) as usize;
         }
 
         let total_cells: usize = self.extents.iter().product();
@@ -165,19 +197,19 @@ impl<'a, const D: usize> Update<D> for Grid<'a, D> {
 impl<'a, const D: usize> Query<D> for Grid<'a, D> {
     fn query_radius(&self, pos: DVec<D>, radius: f64, results: &mut Vec<NodeId>) {
         let radius_sq = (radius * radius) as f32;
-        let grid_size = self.grid_size as f32;
 
         // Compute the range of grid cells to check per dimension
         let mut min_grid = [0usize; D];
         let mut max_grid = [0usize; D];
         for d in 0..D {
-            let lo = ((pos[d] - self.min[d] - radius as f32) / grid_size).floor() as i32;
-            let hi = ((pos[d] - self.min[d] + radius as f32) / grid_size).floor() as i32;
+            let lo = ((pos[d] - self.min[d] - radius as f32) / self.cell_size[d]).floor() as i32;
+            let hi = ((pos[d] - self.min[d] + radius as f32) / self.cell_size[d]).floor() as i32;
             min_grid[d] = lo.max(0) as usize;
             max_grid[d] = (hi as usize).min(self.extents[d] - 1);
         }
 
-        let check_containment = radius > 2.0 * self.grid_size;
+        let largest_cell = self.cell_size.iter().copied().fold(0.0, f32::max);
+        let check_containment = radius as f32 > 2.0 * largest_cell;
 
         // Enumerate all cells in the D-dimensional box [min_grid, max_grid]
         // using an iterative counter.
@@ -197,7 +229,8 @@ impl<'a, const D: usize> Query<D> for Grid<'a, D> {
                 for i in 0..cell_ids.len() {
                     unsafe {
                         let p = cell_pos.get_unchecked(i);
-                        *results.get_unchecked_mut(len) = cell_ids[i];
+                        // Writes into the reserved capacity past the length
+                        results.as_mut_ptr().add(len).write(cell_ids[i]);
                         len += (pos.distance_squared(p) <= radius_sq) as usize;
                     }
                 }
@@ -233,6 +266,9 @@ impl<'a, const D: usize> SpatialIndex<D> for Grid<'a, D> {
 
     fn set_radius_hint(&mut self, radius: f64) {
         self.grid_size = radius;
+        if self.target_bucket_count.is_some() {
+            return;
+        }
         let positions = std::mem::take(&mut self.positions);
         self.update_positions(&positions, None);
     }
@@ -243,7 +279,42 @@ impl<'a, const D: usize> SpatialIndex<D> for Grid<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for Grid<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
-        Self::new(embedding.clone())
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::new(embedding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Grid;
+    use crate::{Embedding, Query, dvec::DVec, graph::Graph};
+    use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn adaptive_resolution_splits_the_short_dimension() {
+        let graph = Graph::from_edge_list((0..299).map(|i| (i, i + 1)).collect(), 2, 2).unwrap();
+        let mut rng = SmallRng::seed_from_u64(3);
+        let positions: Vec<_> = (0..300)
+            .map(|_| DVec::new([rng.random_range(0.0..100.0), rng.random_range(0.0..10.0)]))
+            .collect();
+        let embedding = Embedding {
+            positions: positions.clone(),
+            graph: &graph,
+        };
+        let grid = Grid::with_adaptive_resolution(embedding.view(), 8);
+        assert!(grid.extents.iter().all(|&e| (8..=9).contains(&e)));
+        assert!(grid.cell_size[0] > 9.0 * grid.cell_size[1]);
+
+        for radius in [0.5, 3.0, 40.0] {
+            for (i, pos) in positions.iter().enumerate().step_by(7) {
+                let mut found = Vec::new();
+                grid.query_radius(*pos, radius, &mut found);
+                found.sort_unstable();
+                let expected: Vec<_> = (0..positions.len())
+                    .filter(|&j| pos.distance(&positions[j]) as f64 <= radius)
+                    .collect();
+                assert_eq!(found, expected, "node {i} at radius {radius}");
+            }
+        }
     }
 }
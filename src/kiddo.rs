@@ -1,8 +1,9 @@
 use kiddo::{ImmutableKdTree, SquaredEuclidean};
 
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 
@@ -15,14 +16,14 @@ pub struct Kiddo<'a, const D: usize> {
 }
 
 impl<'a, const D: usize> Kiddo<'a, D> {
-    pub fn new(embedding: Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let mut tree = Self {
             positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             kdtree: ImmutableKdTree::new_from_slice(&[]),
             max_weights: Vec::new(),
         };
-        tree.update_positions(&embedding.positions, None);
+        tree.update_positions(embedding.positions, None);
         tree
     }
 }
@@ -32,7 +33,7 @@ impl<'a, const D: usize> Graph for Kiddo<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -85,7 +86,7 @@ impl<'a, const D: usize> SpatialIndex<D> for Kiddo<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for Kiddo<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
-        Self::new(embedding.clone())
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::new(embedding)
     }
 }
@@ -1,18 +1,24 @@
-pub use embedding::Embedding;
-use query::IndexClone;
+pub use embedding::{Embedding, EmbeddingRef, OwnedEmbedding};
 pub use query::Query;
+use query::{IndexClone, SpatialIndexFactory};
 pub use std::io;
 use std::ops::Deref;
 
 pub mod agrid;
+pub mod aligned;
+#[cfg(feature = "animation")]
+pub mod animation;
 #[cfg(feature = "boost-rtree")]
 pub mod boost_rtree;
+pub mod bundle;
 #[cfg(feature = "cgal")]
 pub mod cgal_kdtree;
+pub mod dim_transfer;
 pub mod dvec;
 pub mod dyn_sprk;
 pub mod dynamic_queries;
 pub mod embedding;
+pub mod girg;
 pub mod graph;
 pub mod grid;
 pub mod kiddo;
@@ -26,16 +32,21 @@ pub mod nanoflann;
 pub mod neighbourhood;
 pub mod orthtree;
 pub mod parsing;
+pub mod pca;
 #[cfg(feature = "py-snn")]
 pub mod py_snn;
 pub mod quadtree;
 pub mod query;
 pub mod random_projection_lsh;
+pub mod rng;
+pub mod select_index;
 pub mod sif;
 #[cfg(feature = "sklearn")]
 pub mod sklearn;
 pub mod snn;
 pub mod sprk;
+pub mod static_index;
+pub mod target_trace;
 pub mod vptree;
 #[cfg(feature = "wembed-snn")]
 pub mod wembed_snn;
@@ -47,12 +58,17 @@ pub use lossy_queries::LossyQuery;
 pub use measured_lsh::MeasuredLSH;
 pub use random_projection_lsh::RandomProjectionLsh;
 pub use sprk::Sprk;
+pub use static_index::StaticIndex;
 
 pub mod dyn_embed;
 pub mod intrinsic_dimension;
 
 pub mod embedder;
 
+pub mod graph_statistics;
+
+pub mod multi_scale;
+
 pub type NodeId = usize;
 
 pub fn convert_to_embeddings<'a, const D: usize>(
@@ -68,63 +84,186 @@ pub fn convert_to_embeddings<'a, const D: usize>(
         })
 }
 
-pub fn data_structures<'a, const D: usize>(
-    embedding: &Embedding<'a, D>,
-) -> impl ExactSizeIterator<Item = Box<dyn IndexClone<D> + 'a>> {
-    let iter = [
-        Box::new(sprk::Sprk::<D>::new(embedding)) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(naive_sprk::NaiveSprk::<D, true>::new(embedding)) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(naive_sprk::NaiveSprk::<D, false>::new(embedding)) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(dyn_sprk::DynSprk::<D>::new(embedding)) as Box<dyn IndexClone<D> + 'a>,
-        // Box::new(agrid::AGrid::<D>::new(embedding)) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(kiddo::Kiddo::<D>::new(embedding.clone())) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(nabo::Nabo::<D>::new(embedding.clone())) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(embedding.clone()) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(neighbourhood::Neihbourhood::<D>::new(embedding.clone()))
-            as Box<dyn IndexClone<D> + 'a>,
-        Box::new(sif::SIF::<D>::new(embedding.clone())) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(vptree::VPTree::<D>::new(embedding.clone())) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(quadtree::Quadtree::<D>::new(embedding.clone())) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(orthtree::Orthtree::<D>::new(embedding.clone())) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(grid::Grid::<D>::new(embedding.clone())) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(snn::Snn::<D>::new(embedding)) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(naive_snn::NaiveSnn::<D>::new(embedding)) as Box<dyn IndexClone<D> + 'a>,
-    ]
-    .into_iter();
+/// Factory for an index whose name is known up front, so it can be
+/// filtered out before it is built
+struct NamedFactory<'a, const D: usize> {
+    name: &'static str,
+    build: fn(EmbeddingRef<'_, 'a, D>) -> Box<dyn IndexClone<D> + 'a>,
+}
+
+impl<'a, const D: usize> SpatialIndexFactory<'a, D> for NamedFactory<'a, D> {
+    fn build(&self, embedding: EmbeddingRef<'_, 'a, D>) -> Box<dyn IndexClone<D> + 'a> {
+        (self.build)(embedding)
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(self.name)
+    }
+}
+
+/// Factories for every spatial index compiled into this build
+pub fn factories<'a, const D: usize>() -> Vec<Box<dyn SpatialIndexFactory<'a, D> + 'a>> {
+    fn factory<'a, const D: usize>(
+        name: &'static str,
+        build: fn(EmbeddingRef<'_, 'a, D>) -> Box<dyn IndexClone<D> + 'a>,
+    ) -> Box<dyn SpatialIndexFactory<'a, D> + 'a> {
+        Box::new(NamedFactory { name, build })
+    }
+
+    #[allow(unused_mut)]
+    let mut factories = vec![
+        factory("atree", |e| Box::new(sprk::Sprk::<D>::new(e))),
+        factory("naive_atree", |e| {
+            Box::new(naive_sprk::NaiveSprk::<D, true>::new(e))
+        }),
+        factory("naive_atree_non_progressive", |e| {
+            Box::new(naive_sprk::NaiveSprk::<D, false>::new(e))
+        }),
+        factory("dyn_atree", |e| Box::new(dyn_sprk::DynSprk::<D>::new(e))),
+        // factory("agrid", |e| Box::new(agrid::AGrid::<D>::new(e))),
+        factory("kiddo", |e| Box::new(kiddo::Kiddo::<D>::new(e))),
+        factory("nabo", |e| Box::new(nabo::Nabo::<D>::new(e))),
+        factory("brute-force", |e| Box::new(e.to_embedding())),
+        factory("neighbourhood", |e| {
+            Box::new(neighbourhood::Neihbourhood::<D>::new(e))
+        }),
+        factory("sif", |e| Box::new(sif::SIF::<D>::new(e))),
+        factory("vptree", |e| Box::new(vptree::VPTree::<D>::new(e))),
+        factory("quadtree", |e| Box::new(quadtree::Quadtree::<D>::new(e))),
+        factory("orthtree", |e| Box::new(orthtree::Orthtree::<D>::new(e))),
+        factory("grid", |e| Box::new(grid::Grid::<D>::new(e))),
+        factory("snn", |e| Box::new(snn::Snn::<D>::new(e))),
+        factory("snn_cosine", |e| {
+            Box::new(snn::Snn::<D>::with_cosine_sort(e))
+        }),
+        factory("naive_snn", |e| Box::new(naive_snn::NaiveSnn::<D>::new(e))),
+    ];
 
     #[cfg(feature = "nanoflann")]
-    let iter = iter.chain(Some(
-        Box::new(nanoflann::NanoflannIndexWrapper::<D>::new(embedding))
-            as Box<dyn IndexClone<D> + 'a>,
-    ));
+    factories.push(factory("nanoflann", |e| {
+        Box::new(nanoflann::NanoflannIndexWrapper::<D>::new(e))
+    }));
 
     #[cfg(feature = "boost-rtree")]
-    let iter = iter.chain(Some(
-        Box::new(boost_rtree::BoostRTreeWrapper::<D>::new(embedding))
-            as Box<dyn IndexClone<D> + 'a>,
-    ));
+    factories.push(factory("boost_rtree", |e| {
+        Box::new(boost_rtree::BoostRTreeWrapper::<D>::new(e))
+    }));
 
     #[cfg(feature = "cgal")]
-    let iter = iter.chain(Some(
-        Box::new(cgal_kdtree::CgalKdTreeWrapper::<D>::new(embedding))
-            as Box<dyn IndexClone<D> + 'a>,
-    ));
+    factories.push(factory("cgal_kdtree", |e| {
+        Box::new(cgal_kdtree::CgalKdTreeWrapper::<D>::new(e))
+    }));
 
     #[cfg(feature = "wembed-snn")]
-    let iter = iter.chain(Some(
-        Box::new(wembed_snn::WembedSnnWrapper::<D>::new(embedding)) as Box<dyn IndexClone<D> + 'a>,
-    ));
+    factories.push(factory("wembed_snn", |e| {
+        Box::new(wembed_snn::WembedSnnWrapper::<D>::new(e))
+    }));
 
     #[cfg(feature = "sklearn")]
-    let iter = iter.chain([
-        Box::new(sklearn::SklearnKDTree::<D>::new(embedding)) as Box<dyn IndexClone<D> + 'a>,
-        Box::new(sklearn::SklearnBallTree::<D>::new(embedding)) as Box<dyn IndexClone<D> + 'a>,
+    factories.extend([
+        factory("sklearn_kdtree", |e| {
+            Box::new(sklearn::SklearnKDTree::<D>::new(e))
+        }),
+        factory("sklearn_balltree", |e| {
+            Box::new(sklearn::SklearnBallTree::<D>::new(e))
+        }),
     ]);
 
     #[cfg(feature = "py-snn")]
-    let iter = iter.chain(Some(
-        Box::new(py_snn::PySnn::<D>::new(embedding)) as Box<dyn IndexClone<D> + 'a>
-    ));
+    factories.push(factory("py_snn", |e| Box::new(py_snn::PySnn::<D>::new(e))));
+
+    factories
+}
+
+/// Whether `names` selects the index `name`. `None` and an empty list
+/// select every index.
+fn selected(names: Option<&[String]>, name: &str) -> bool {
+    names.is_none_or(|names| names.is_empty() || names.iter().any(|n| n == name))
+}
+
+/// Builds the indices of `factories` that `names` selects, see
+/// [`data_structures`]
+fn build_selected<'a, const D: usize>(
+    factories: Vec<Box<dyn SpatialIndexFactory<'a, D> + 'a>>,
+    embedding: &Embedding<'a, D>,
+    names: Option<&[String]>,
+) -> Vec<Box<dyn IndexClone<D> + 'a>> {
+    factories
+        .into_iter()
+        .filter(|factory| factory.name().is_none_or(|name| selected(names, name)))
+        .map(|factory| factory.build(embedding.view()))
+        // Factories that do not know their name are filtered after building
+        .filter(|structure| selected(names, &structure.name()))
+        .collect()
+}
+
+/// Builds every index compiled into this build, or only the ones named in
+/// `names`. An empty list selects every index. Unselected indices are never
+/// constructed.
+pub fn data_structures<'a, const D: usize>(
+    embedding: &Embedding<'a, D>,
+    names: Option<&[String]>,
+) -> impl ExactSizeIterator<Item = Box<dyn IndexClone<D> + 'a>> {
+    build_selected(factories(), embedding, names).into_iter()
+}
+
+/// Like [`data_structures`], plus the indices that only pay off when the
+/// embedding does not move between queries, like [`StaticIndex`]
+pub fn query_data_structures<'a, const D: usize>(
+    embedding: &Embedding<'a, D>,
+    names: Option<&[String]>,
+) -> impl Iterator<Item = Box<dyn IndexClone<D> + 'a>> {
+    let static_index = selected(names, "static")
+        .then(|| -> Box<dyn IndexClone<D> + 'a> { Box::new(StaticIndex::new(embedding.view())) });
+    data_structures(embedding, names).chain(static_index)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{Embedding, EmbeddingRef, dvec::DVec, graph::Graph, query::SpatialIndexFactory};
+
+    use super::{NamedFactory, build_selected, data_structures, factories};
+
+    #[test]
+    fn filtering_builds_only_the_selected_index() {
+        static BUILT: AtomicUsize = AtomicUsize::new(0);
+        let graph = Graph::from_edge_list((0..9).map(|i| (i, i + 1)).collect(), 2, 2).unwrap();
+        let embedding = Embedding {
+            positions: (0..10).map(|i| DVec::new([i as f32, 0.0])).collect(),
+            graph: &graph,
+        };
+        let counted: Vec<Box<dyn SpatialIndexFactory<'_, 2>>> = vec![
+            Box::new(NamedFactory {
+                name: "grid",
+                build: |e| {
+                    BUILT.fetch_add(1, Ordering::Relaxed);
+                    Box::new(crate::grid::Grid::new(e))
+                },
+            }),
+            Box::new(NamedFactory {
+                name: "kiddo",
+                build: |e| {
+                    BUILT.fetch_add(1, Ordering::Relaxed);
+                    Box::new(crate::kiddo::Kiddo::new(e))
+                },
+            }),
+        ];
+
+        let built = build_selected(counted, &embedding, Some(&[String::from("grid")]));
+        assert_eq!(built.len(), 1);
+        assert_eq!(built[0].name(), "grid");
+        assert_eq!(BUILT.load(Ordering::Relaxed), 1);
 
-    iter.collect::<Vec<_>>().into_iter()
+        let all = data_structures(&embedding, None).len();
+        assert_eq!(data_structures(&embedding, Some(&[])).len(), all);
+        for factory in factories::<2>() {
+            let view = EmbeddingRef {
+                positions: &embedding.positions,
+                graph: &graph,
+            };
+            assert_eq!(factory.name(), Some(factory.build(view).name().as_str()));
+        }
+    }
 }
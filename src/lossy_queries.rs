@@ -1,9 +1,11 @@
-use rand::{rngs::SmallRng, seq::SliceRandom};
+use rand::seq::SliceRandom;
 
 use crate::{
     NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Embedder, Graph, Position, SpatialIndex, Update},
+    rng::{Purpose, RngSource},
 };
 
 #[derive(Clone, Copy, Debug)]
@@ -23,18 +25,38 @@ pub struct LossyQuery<'a, const D: usize, ID: Embedder<'a, D>> {
     recall: f64,
     loss_strategy: LossyStrategy,
     structure: ID,
+    rng: RngSource,
+    /// Number of position updates, [`LossyStrategy::Random`] drops
+    /// different results after each
+    updates: u64,
     _phantom: std::marker::PhantomData<&'a ()>,
 }
 
 impl<'a, const D: usize, ID: Embedder<'a, D>> LossyQuery<'a, D, ID> {
-    pub fn new(embedding: &crate::Embedding<'a, D>, recall: f64, strategy: LossyStrategy) -> Self {
+    pub fn new(
+        embedding: crate::EmbeddingRef<'_, 'a, D>,
+        recall: f64,
+        strategy: LossyStrategy,
+    ) -> Self {
+        Self::with_rng(embedding, recall, strategy, RngSource::default())
+    }
+
+    /// Like [`Self::new`], with the dropped results drawn from `rng`
+    pub fn with_rng(
+        embedding: crate::EmbeddingRef<'_, 'a, D>,
+        recall: f64,
+        strategy: LossyStrategy,
+        rng: RngSource,
+    ) -> Self {
         let mut query = LossyQuery {
             recall,
             loss_strategy: strategy,
             structure: ID::new(embedding),
+            rng,
+            updates: 0,
             _phantom: std::marker::PhantomData,
         };
-        query.update_positions(&embedding.positions, None);
+        query.update_positions(embedding.positions, None);
         query
     }
 }
@@ -44,7 +66,7 @@ impl<'a, const D: usize, ID: Embedder<'a, D>> crate::query::Graph for LossyQuery
         self.structure.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.structure.neighbors(index)
     }
 
@@ -65,6 +87,15 @@ impl<'a, const D: usize, ID: Embedder<'a, D>> query::Position<D> for LossyQuery<
 impl<'a, const D: usize, ID: Embedder<'a, D>> query::Update<D> for LossyQuery<'a, D, ID> {
     fn update_positions(&mut self, positions: &[DVec<D>], last_delta: Option<f64>) {
         self.structure.update_positions(positions, last_delta);
+        self.updates += 1;
+    }
+
+    fn moved_in_last_update(&self) -> Option<usize> {
+        self.structure.moved_in_last_update()
+    }
+
+    fn discard_warm_start(&mut self) {
+        self.structure.discard_warm_start();
     }
 }
 
@@ -73,24 +104,34 @@ impl<'a, const D: usize, ID: Embedder<'a, D>> Query<D> for LossyQuery<'a, D, ID>
         self.structure.nearest_neighbors(index, radius, results)
     }
 }
-impl<'a, const D: usize, ID: Embedder<'a, D> + Sync> SpatialIndex<D> for LossyQuery<'a, D, ID> {
+impl<'a, const D: usize, ID: Embedder<'a, D> + SpatialIndex<D>> SpatialIndex<D>
+    for LossyQuery<'a, D, ID>
+{
     fn name(&self) -> String {
         String::from("lossy queries")
     }
     fn implementation_string(&self) -> &'static str {
         include_str!("lossy_queries.rs")
     }
+    fn is_exact(&self) -> bool {
+        false
+    }
+    fn memory_usage_bytes(&self) -> usize {
+        self.structure.memory_usage_bytes()
+    }
 }
 
 impl<'a, const D: usize, ID: Embedder<'a, D>> query::Embedder<'a, D> for LossyQuery<'a, D, ID> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         let mut query = LossyQuery {
             recall: 1.0,
             loss_strategy: LossyStrategy::Random,
             structure: ID::new(embedding),
+            rng: RngSource::default(),
+            updates: 0,
             _phantom: std::marker::PhantomData,
         };
-        query.update_positions(&embedding.positions, None);
+        query.update_positions(embedding.positions, None);
         query
     }
     fn repelling_nodes(&self, index: usize, result: &mut Vec<NodeId>) {
@@ -120,11 +161,14 @@ impl<'a, const D: usize, ID: Embedder<'a, D>> query::Embedder<'a, D> for LossyQu
                 result.sort_by(|a, b| self.weight(*a).total_cmp(&self.weight(*b)));
             }
             LossyStrategy::Random => {
-                result.shuffle(&mut rand::rng());
+                let mut rng = self
+                    .rng
+                    .stream(Purpose::LossyQuery, &[self.updates, index as u64]);
+                result.shuffle(&mut rng);
             }
             LossyStrategy::Droplist => {
                 let mut droplist: Vec<_> = (0..self.num_nodes()).collect();
-                let mut rng: SmallRng = rand::SeedableRng::seed_from_u64(index as u64);
+                let mut rng = self.rng.stream(Purpose::LossyQuery, &[index as u64]);
                 droplist.shuffle(&mut rng);
                 result.sort_by_key(|id| droplist.iter().position(|x| x == id));
             }
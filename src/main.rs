@@ -40,12 +40,12 @@ fn main() -> io::Result<()> {
         graph: &graph,
     };
 
-    // let lossy_queries = Sprk::new(&embedding);
-    // let lossy_queries = Kiddo::new(embedding.clone());
+    // let lossy_queries = Sprk::new(embedding.view());
+    // let lossy_queries = Kiddo::new(embedding.view());
     // let lossy_queries = embedding.clone();
     // let lossy_queries =
-    //     RandomProjectionLsh::<_>::new_with_params(embedding.clone(), Some(1), Some(16));
-    let lossy_queries = DynamicQuery::<_, Sprk<_>>::new(&embedding);
+    //     RandomProjectionLsh::<_>::new_with_params(embedding.view(), Some(1), Some(16));
+    let lossy_queries = DynamicQuery::<_, Sprk<_>>::new(embedding.view());
     let mut embedder = embedder::WEmbedder::new(lossy_queries, options);
 
     let start = Instant::now();
@@ -65,8 +65,8 @@ fn main() -> io::Result<()> {
     eprintln!("Embedding took {:.2}s", start.elapsed().as_secs_f32());
 
     let embedder = WEmbedder::new(
-        Sprk::new(&Embedding {
-            positions: embedder.positions().to_vec(),
+        Sprk::new(EmbeddingRef {
+            positions: embedder.positions(),
             graph: &graph,
         }),
         Default::default(),
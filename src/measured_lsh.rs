@@ -1,6 +1,7 @@
 use crate::{
     Sprk, NodeId,
     dvec::DVec,
+    graph::Neighbors,
     query::{Embedder, Graph, Position, Query, SpatialIndex, Update},
     random_projection_lsh::RandomProjectionLsh,
 };
@@ -51,7 +52,7 @@ impl<'a, const D: usize> Graph for MeasuredLSH<'a, D> {
     fn is_connected(&self, first: NodeId, second: NodeId) -> bool {
         self.lsh.is_connected(first, second)
     }
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.lsh.neighbors(index)
     }
     fn weight(&self, index: NodeId) -> f64 {
@@ -88,10 +89,13 @@ impl<'a, const D: usize> SpatialIndex<D> for MeasuredLSH<'a, D> {
     fn implementation_string(&self) -> &'static str {
         include_str!("measured_lsh.rs")
     }
+    fn is_exact(&self) -> bool {
+        false
+    }
 }
 
 impl<'a, const D: usize> Embedder<'a, D> for MeasuredLSH<'a, D> {
-    fn new(_embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(_embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         panic!("MeasuredLSH requires explicit construction with both LSH and ground truth")
     }
 
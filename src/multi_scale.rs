@@ -0,0 +1,138 @@
+//! Multi-scale layout: embed a coarsened version of the graph and refine the
+//! layout level by level back to the input graph.
+
+use rand::Rng;
+
+use crate::{
+    DynamicQuery, NodeId, Sprk,
+    dvec::DVec,
+    embedder::{EmbedderOptions, WEmbedder},
+    graph::Graph,
+    query::Embedder,
+    rng::{Purpose, RngSource},
+};
+
+/// Finer levels start close to their final layout, so they only get
+/// `max_iterations / FINE_TUNING_SHARE` iterations
+const FINE_TUNING_SHARE: usize = 5;
+
+impl Graph {
+    /// 2D layout through a coarsening hierarchy. The graph is contracted up
+    /// to `levels` times along a [`Graph::coarsening_matching`], which
+    /// roughly halves the node count each time, so the coarsest graph has
+    /// about `n / 2^levels` nodes. It is embedded from random positions drawn
+    /// with `seed`, then every finer level starts at the position of its
+    /// coarse node and is fine-tuned with a fifth of
+    /// `options.max_iterations`. Coarsening stops early once no edge is left
+    /// to contract. With `levels = 0` this is a plain embedding.
+    pub fn multi_scale_layout(
+        &self,
+        seed: u64,
+        levels: usize,
+        options: EmbedderOptions,
+    ) -> Vec<DVec<2>> {
+        let mut graphs: Vec<Graph> = Vec::new();
+        // coarse[l][x]: node of level l + 1 that node x of level l was merged into
+        let mut coarse: Vec<Vec<NodeId>> = Vec::new();
+        for _ in 0..levels {
+            let finer = graphs.last().unwrap_or(self);
+            let matching = finer.coarsening_matching();
+            if matching.is_empty() {
+                break;
+            }
+            let (graph, mapping) = finer.contract_matching(&matching);
+            graphs.push(graph);
+            coarse.push(mapping);
+        }
+
+        let rng = RngSource::new(seed);
+        let coarsest = graphs.last().unwrap_or(self);
+        let mut embedder: WEmbedder<DynamicQuery<2, Sprk<2>>> =
+            WEmbedder::random(rng.seed(), coarsest, options.clone());
+        let mut positions = embedder.embed();
+
+        for level in (0..coarse.len()).rev() {
+            let finer = if level == 0 { self } else { &graphs[level - 1] };
+            let initial = prolongate(
+                finer,
+                &graphs[level],
+                &coarse[level],
+                &positions,
+                rng,
+                level,
+            );
+            let spatial_index = DynamicQuery::new(crate::EmbeddingRef {
+                positions: &initial,
+                graph: finer,
+            });
+            let options = EmbedderOptions {
+                max_iterations: options.max_iterations / FINE_TUNING_SHARE,
+                ..options.clone()
+            };
+            let mut embedder = WEmbedder::new(spatial_index, options).with_rng(rng);
+            positions = embedder.embed();
+        }
+        positions
+    }
+}
+
+/// Initial positions of `finer` from the layout of `coarser`: every node
+/// starts at its coarse node, and the two nodes of a merged pair are spread
+/// randomly around it so they do not start on top of each other.
+fn prolongate(
+    finer: &Graph,
+    coarser: &Graph,
+    coarse: &[NodeId],
+    positions: &[DVec<2>],
+    rng: RngSource,
+    level: usize,
+) -> Vec<DVec<2>> {
+    (0..finer.nodes.len())
+        .map(|x| {
+            let parent = coarse[x];
+            let position = positions[parent];
+            // Half the distance at which the merged pair would start to repel
+            let spread =
+                finer.nodes[x].weight * (coarser.nodes[parent].weight - finer.nodes[x].weight);
+            if spread <= 0.0 {
+                return position;
+            }
+            let mut rng = rng.stream(Purpose::Prolongation, &[level as u64, x as u64]);
+            let spread = 0.5 * spread as f32;
+            position + DVec::from_fn(|_| rng.random_range(-spread..spread))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Embedding, Sprk,
+        embedder::{EmbedderOptions, WEmbedder},
+        graph::grid,
+        query::Embedder,
+    };
+
+    #[test]
+    fn multi_scale_layout_beats_direct_embedding() {
+        let graph = grid(30);
+        let f1 = |positions| {
+            Embedding {
+                positions,
+                graph: &graph,
+            }
+            .f1()
+        };
+
+        let mut direct: WEmbedder<Sprk<2>> =
+            WEmbedder::random(0, &graph, EmbedderOptions::default());
+        let direct_f1 = f1(direct.embed());
+        let positions = graph.multi_scale_layout(0, 2, EmbedderOptions::default());
+        assert_eq!(positions.len(), graph.nodes.len());
+        let multi_scale_f1 = f1(positions);
+        assert!(
+            multi_scale_f1 > direct_f1,
+            "multi-scale: {multi_scale_f1}, direct: {direct_f1}"
+        );
+    }
+}
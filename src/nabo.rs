@@ -1,8 +1,9 @@
 use nabo::KDTree;
 
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 
@@ -28,14 +29,14 @@ impl<const D: usize> nabo::Point<f32> for DVec<D> {
 }
 
 impl<'a, const D: usize> Nabo<'a, D> {
-    pub fn new(embedding: Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let mut tree = Self {
             positions: embedding.positions.to_vec(),
             graph: embedding.graph,
-            kdtree: KDTree::new(&embedding.positions),
+            kdtree: KDTree::new(embedding.positions),
             max_weights: Vec::new(),
         };
-        tree.update_positions(&embedding.positions, None);
+        tree.update_positions(embedding.positions, None);
         tree
     }
 }
@@ -45,7 +46,7 @@ impl<'a, const D: usize> Graph for Nabo<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -102,7 +103,7 @@ impl<'a, const D: usize> SpatialIndex<D> for Nabo<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for Nabo<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
-        Self::new(embedding.clone())
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::new(embedding)
     }
 }
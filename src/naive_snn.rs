@@ -1,9 +1,10 @@
 use crate::{
-    Embedding, NodeId,
+    EmbeddingRef, NodeId,
     dvec::DVec,
+    graph::Neighbors,
+    pca::Pca,
     query::{self, SpatialIndex, Update},
 };
-use sprk::svd::Svd;
 
 #[derive(Clone)]
 pub struct NaiveSnn<'a, const D: usize> {
@@ -15,8 +16,8 @@ pub struct NaiveSnn<'a, const D: usize> {
     sorted_ids: Vec<u32>,
     /// Per-point principal-axis projection (sorted)
     sort_vals: Vec<f32>,
-    /// SVD for extracting principal axis
-    svd: Svd<D, f32>,
+    /// PCA for extracting principal axis
+    pca: Pca<D>,
     /// Principal axis (first singular vector)
     principal_axis: [f32; D],
     /// Mean of all positions
@@ -24,18 +25,18 @@ pub struct NaiveSnn<'a, const D: usize> {
 }
 
 impl<'a, const D: usize> NaiveSnn<'a, D> {
-    pub fn new(embedding: &Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let mut snn = Self {
-            positions: embedding.positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             sorted_positions: Vec::new(),
             sorted_ids: Vec::new(),
             sort_vals: Vec::new(),
-            svd: Svd::new(),
+            pca: Pca::new(),
             principal_axis: [0.0; D],
             mean: [0.0; D],
         };
-        snn.update_positions(&embedding.positions, None);
+        snn.update_positions(embedding.positions, None);
         snn
     }
 
@@ -47,39 +48,10 @@ impl<'a, const D: usize> NaiveSnn<'a, D> {
 
         let raw_positions: Vec<[f32; D]> = self.positions.iter().map(|p| p.components).collect();
 
-        // Compute mean
-        self.mean = [0.0; D];
-        let inv_n = 1.0 / n as f32;
-        for pos in &raw_positions {
-            for j in 0..D {
-                self.mean[j] += pos[j];
-            }
-        }
-        for m in &mut self.mean {
-            *m *= inv_n;
-        }
-
         // Compute SVD to find principal axis
-        self.svd.compute_svd(&raw_positions);
-
-        // Extract principal axis by probing SVD projection
-        self.principal_axis = {
-            let origin_proj = self.svd.project(&self.mean);
-            let mut axis = [0.0f32; D];
-            for j in 0..D {
-                let mut shifted = self.mean;
-                shifted[j] += 1.0;
-                let proj = self.svd.project(&shifted);
-                axis[j] = proj[0] - origin_proj[0];
-            }
-            let norm: f32 = axis.iter().map(|x| x * x).sum::<f32>().sqrt();
-            if norm > 0.0 {
-                for a in &mut axis {
-                    *a /= norm;
-                }
-            }
-            axis
-        };
+        self.pca.fit(&raw_positions);
+        self.mean = self.pca.mean;
+        self.principal_axis = self.pca.axis(0);
 
         // Project all points onto principal axis
         let mut projections: Vec<(f32, usize)> = raw_positions
@@ -109,7 +81,7 @@ impl<const D: usize> crate::query::Graph for NaiveSnn<'_, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -172,7 +144,7 @@ impl<const D: usize> SpatialIndex<D> for NaiveSnn<'_, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for NaiveSnn<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         Self::new(embedding)
     }
 }
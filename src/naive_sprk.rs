@@ -1,10 +1,20 @@
+use std::fmt;
+
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
+    target_trace::{TargetTrace, TraceStep},
 };
 
 const LEAFSIZE: usize = 150;
+/// Lookup table buckets of a leaf without weight-aware resolution
+const LUT_BUCKETS: f32 = 50.;
+/// Upper bound on the lookup table buckets per node of a leaf
+const MAX_LUT_BUCKETS_PER_NODE: usize = 8;
+/// Default for [`NaiveSprk::with_lut_radius_scale`]
+pub const DEFAULT_LUT_RADIUS_SCALE: f32 = 4.;
 
 #[derive(Clone)]
 pub struct NaiveSprk<'a, const D: usize, const P: bool> {
@@ -14,6 +24,7 @@ pub struct NaiveSprk<'a, const D: usize, const P: bool> {
     pub d_pos: Vec<f32>,
     pub graph: &'a crate::graph::Graph,
     layers: Vec<Layer>,
+    lut_radius_scale: f32,
 }
 
 impl<const D: usize, const P: bool> crate::query::Graph for NaiveSprk<'_, D, P> {
@@ -21,7 +32,7 @@ impl<const D: usize, const P: bool> crate::query::Graph for NaiveSprk<'_, D, P>
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -83,6 +94,22 @@ struct Snn {
     lut: Vec<usize>,
     min: f32,
     resolution: f32,
+    /// Bounding box of the leaf in all dimensions. The tree only splits on
+    /// one coordinate per level, so in high dimensions many leaves are far
+    /// from the query in coordinates that were never split on.
+    lower: Vec<f32>,
+    upper: Vec<f32>,
+}
+
+impl Snn {
+    fn box_distance_squared<const D: usize>(&self, pos: &DVec<D>) -> f32 {
+        let mut distance = 0.;
+        for d in 0..self.lower.len() {
+            let outside = (self.lower[d] - pos[d]).max(pos[d] - self.upper[d]).max(0.);
+            distance += outside * outside;
+        }
+        distance
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -116,24 +143,36 @@ impl Layer {
             if d_pos.is_empty() {
                 return;
             }
-            let mut lut = vec![];
             let min = d_pos[0].floor();
             let max = d_pos.last().unwrap().ceil();
-            let resolution = 50. / (max - min);
+            let resolution = sprk.lut_resolution(nodes, max - min);
+            let mut lut = vec![];
+            let mut pos_idx = 0;
             for i in 0..(((max - min) * resolution) as i32) {
-                let pos_idx = d_pos
-                    .iter()
-                    .take_while(|&&x| x < ((i as f32 / resolution) + min))
-                    .count();
+                let bucket_start = (i as f32 / resolution) + min;
+                while pos_idx < d_pos.len() && d_pos[pos_idx] < bucket_start {
+                    pos_idx += 1;
+                }
                 lut.push(pos_idx);
             }
 
+            let mut lower = vec![f32::INFINITY; D];
+            let mut upper = vec![f32::NEG_INFINITY; D];
+            for pos in nodes.iter().map(|id| sprk.position(*id)) {
+                for d in 0..D {
+                    lower[d] = lower[d].min(pos[d]);
+                    upper[d] = upper[d].max(pos[d]);
+                }
+            }
+
             layers[layer_id] = Self::Leaf(Snn {
                 offset,
                 len: nodes.len(),
                 lut,
                 min: d_pos[0].floor(),
                 resolution,
+                lower,
+                upper,
             });
             return;
         }
@@ -186,20 +225,45 @@ fn children(index: usize) -> (usize, usize) {
 }
 
 impl<'a, const D: usize, const P: bool> NaiveSprk<'a, D, P> {
-    pub fn new(embedding: &Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::with_lut_radius_scale(embedding, DEFAULT_LUT_RADIUS_SCALE)
+    }
+
+    /// Builds the tree with leaf lookup tables of at least `scale` buckets
+    /// per query radius of the lightest node in the leaf, i.e. `weight²`.
+    ///
+    /// Queries start scanning a leaf at the lookup table bucket containing
+    /// the lower end of their window, so coarse buckets mostly cost the
+    /// small windows of light nodes. Leaves never get fewer than 50 buckets
+    /// and at most 8 per node; a scale of 0 always uses 50.
+    pub fn with_lut_radius_scale(embedding: EmbeddingRef<'_, 'a, D>, scale: f32) -> Self {
         let mut line_lsh = NaiveSprk {
-            positions: embedding.positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             positions_sorted: Vec::new(),
             node_ids: Vec::new(),
             d_pos: Vec::new(),
             layers: vec![Layer::Node(Node { split: 0. }); embedding.positions.len()],
+            lut_radius_scale: scale,
         };
         if !line_lsh.positions.is_empty() {
-            line_lsh.update_positions(&embedding.positions, None);
+            line_lsh.update_positions(embedding.positions, None);
         }
         line_lsh
     }
+    /// Lookup table buckets per unit length for a leaf of `nodes` spanning
+    /// `span` in the sorted dimension
+    fn lut_resolution(&self, nodes: &[NodeId], span: f32) -> f32 {
+        let resolution = LUT_BUCKETS / span;
+        let lightest = nodes
+            .iter()
+            .map(|&id| self.weight(id))
+            .fold(f64::INFINITY, f64::min);
+        let weighted = self.lut_radius_scale / lightest.powi(2) as f32;
+        let max = (nodes.len() * MAX_LUT_BUCKETS_PER_NODE) as f32 / span;
+        resolution.max(weighted.min(max))
+    }
+
     fn light_nn(&self, index: usize, radius: f64, results: &mut Vec<NodeId>) {
         self.query_recursive(
             *self.position(index),
@@ -252,16 +316,13 @@ impl<'a, const D: usize, const P: bool> NaiveSprk<'a, D, P> {
                     distances,
                     results,
                 );
-                let mut reduced_radius = dim_radius_squared;
-                let dist = own_pos - node.split;
-                if P {
-                    // let d_2 = dist - distances[depth];
-                    // let x = 2. * distances[depth] * d_2 + d_2.powi(2);
-                    reduced_radius -= dist.powi(2) + distances[depth].powi(2);
-                } else {
-                    reduced_radius = original_radius_squared as f32 - distances.magnitude_squared();
-                }
-                distances[depth] = dist;
+                let reduced_radius = Self::reduce_radius(
+                    own_pos - node.split,
+                    depth,
+                    dim_radius_squared,
+                    original_radius_squared,
+                    &mut distances,
+                );
                 if reduced_radius <= 0. {
                     return;
                 }
@@ -277,28 +338,455 @@ impl<'a, const D: usize, const P: bool> NaiveSprk<'a, D, P> {
                 );
             }
             Layer::Leaf(snn) => {
-                let dim_diff_squared = distances[depth].powi(2);
-                let radius_sqrt = (dim_radius_squared + dim_diff_squared).sqrt();
-                let min = own_pos - radius_sqrt;
-                let max = own_pos + radius_sqrt;
-                let idx = (((min - snn.min) * snn.resolution) as usize).min(snn.lut.len() - 1);
-                if snn.lut.is_empty() {
+                if snn.box_distance_squared(&pos) > original_radius_squared as f32 {
                     return;
                 }
-                let min_i = snn.lut[idx];
+                self.scan_leaf(
+                    snn,
+                    pos,
+                    depth,
+                    dim_radius_squared,
+                    original_radius_squared,
+                    &distances,
+                    results,
+                );
+            }
+        }
+    }
+
+    /// Radius left for the far side of a split at distance `dist` from the
+    /// query in dimension `depth`. Records `dist` in `distances`.
+    fn reduce_radius(
+        dist: f32,
+        depth: usize,
+        dim_radius_squared: f32,
+        original_radius_squared: f64,
+        distances: &mut DVec<D>,
+    ) -> f32 {
+        let mut reduced_radius = dim_radius_squared;
+        if P {
+            // let d_2 = dist - distances[depth];
+            // let x = 2. * distances[depth] * d_2 + d_2.powi(2);
+            reduced_radius -= dist.powi(2) + distances[depth].powi(2);
+        } else {
+            reduced_radius = original_radius_squared as f32 - distances.magnitude_squared();
+        }
+        distances[depth] = dist;
+        reduced_radius
+    }
+
+    /// Window of the query in the sorted dimension of `snn` and the lookup
+    /// table bucket of its lower end, as `(bucket, scan start, (min, max))`
+    fn scan_window(
+        snn: &Snn,
+        pos: DVec<D>,
+        depth: usize,
+        dim_radius_squared: f32,
+        distances: &DVec<D>,
+    ) -> (Option<usize>, usize, (f32, f32)) {
+        let own_pos = pos[depth];
+        let dim_diff_squared = distances[depth].powi(2);
+        let radius_sqrt = (dim_radius_squared + dim_diff_squared).sqrt();
+        let min = own_pos - radius_sqrt;
+        let max = own_pos + radius_sqrt;
+        // All points of the leaf share the same integral coordinate if the
+        // lookup table is empty, so the whole leaf has to be scanned
+        let bucket = match snn.lut.len() {
+            0 => None,
+            len => Some((((min - snn.min) * snn.resolution) as usize).min(len - 1)),
+        };
+        let scan_start = bucket.map_or(0, |bucket| snn.lut[bucket]);
+        (bucket, scan_start, (min, max))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn scan_leaf(
+        &self,
+        snn: &Snn,
+        pos: DVec<D>,
+        depth: usize,
+        dim_radius_squared: f32,
+        original_radius_squared: f64,
+        distances: &DVec<D>,
+        results: &mut Vec<NodeId>,
+    ) -> usize {
+        let (_, min_i, (_, max)) =
+            Self::scan_window(snn, pos, depth, dim_radius_squared, distances);
+
+        let mut distance_checks = 0;
+        for i in (min_i + snn.offset)..(snn.offset + snn.len) {
+            let p = self.d_pos[i];
+            if p > max {
+                break;
+            }
+            distance_checks += 1;
+            let other_pos = self.positions_sorted[i];
+            if pos.distance_squared(&other_pos) <= original_radius_squared as f32 {
+                results.push(self.node_ids[i]);
+            }
+        }
+        distance_checks
+    }
+
+    /// Runs a query for the ball of radius `radius` around node `index` and
+    /// records every pruning decision on the way, like [`Self::query_radius`]
+    /// does without the trace.
+    pub fn explain_query(&self, index: NodeId, radius: f64) -> QueryTrace {
+        let radius_squared = radius.powi(2);
+        self.explain_recursive(
+            *self.position(index),
+            0,
+            0,
+            radius_squared as f32,
+            radius_squared,
+            DVec::zero(),
+        )
+    }
+
+    fn explain_recursive(
+        &self,
+        pos: DVec<D>,
+        depth: usize,
+        layer_id: usize,
+        dim_radius_squared: f32,
+        original_radius_squared: f64,
+        mut distances: DVec<D>,
+    ) -> QueryTrace {
+        match &self.layers[layer_id] {
+            Layer::Node(node) => {
+                let (left, right) = children(layer_id);
+                let (own, other) = if pos[depth] < node.split {
+                    (left, right)
+                } else {
+                    (right, left)
+                };
+                let near = self.explain_recursive(
+                    pos,
+                    (depth + 1) % D,
+                    own,
+                    dim_radius_squared,
+                    original_radius_squared,
+                    distances,
+                );
+                let reduced_radius_squared = Self::reduce_radius(
+                    pos[depth] - node.split,
+                    depth,
+                    dim_radius_squared,
+                    original_radius_squared,
+                    &mut distances,
+                );
+                let far_distances = distances.components.to_vec();
+                let far = (reduced_radius_squared > 0.).then(|| {
+                    Box::new(self.explain_recursive(
+                        pos,
+                        (depth + 1) % D,
+                        other,
+                        reduced_radius_squared,
+                        original_radius_squared,
+                        distances,
+                    ))
+                });
+                QueryTrace::Node {
+                    layer: layer_id,
+                    dim: depth,
+                    split: node.split,
+                    coordinate: pos[depth],
+                    radius_squared: dim_radius_squared,
+                    reduced_radius_squared,
+                    far_distances,
+                    near: Box::new(near),
+                    far,
+                }
+            }
+            Layer::Leaf(snn) => {
+                let box_distance_squared = snn.box_distance_squared(&pos);
+                let box_pruned = box_distance_squared > original_radius_squared as f32;
+                let mut found = Vec::new();
+                let mut distance_checks = 0;
+                if !box_pruned {
+                    distance_checks = self.scan_leaf(
+                        snn,
+                        pos,
+                        depth,
+                        dim_radius_squared,
+                        original_radius_squared,
+                        &distances,
+                        &mut found,
+                    );
+                }
+                let (bucket, scan_start, window) =
+                    Self::scan_window(snn, pos, depth, dim_radius_squared, &distances);
+                QueryTrace::Leaf {
+                    layer: layer_id,
+                    len: snn.len,
+                    box_distance_squared,
+                    box_pruned,
+                    bucket,
+                    scan_start,
+                    window,
+                    distance_checks,
+                    found,
+                }
+            }
+        }
+    }
 
-                for i in (min_i + snn.offset)..(snn.offset + snn.len) {
-                    let p = self.d_pos[i];
-                    if p > max {
+    /// Reruns the query of [`Query::nearest_neighbors`] for `index` with
+    /// `radius` and follows its [`QueryTrace`] towards the leaf holding
+    /// `target`, recording each decision that could have excluded it.
+    pub fn trace_query(&self, index: NodeId, radius: f64, target: NodeId) -> TargetTrace {
+        let query_radius = radius * self.weight(index).powi(2);
+        let mut trace = TargetTrace::new(self, index, query_radius, target);
+        let offset = self
+            .node_ids
+            .iter()
+            .position(|&id| id == target)
+            .expect("target is not in the tree");
+        let (pos, target_pos) = (*self.position(index), *self.position(target));
+        let radius_squared = query_radius.powi(2) as f32;
+        let explained = self.explain_query(index, query_radius);
+
+        let mut distances = vec![0.; D];
+        let mut current = &explained;
+        loop {
+            match current {
+                QueryTrace::Node {
+                    layer,
+                    dim,
+                    split,
+                    coordinate,
+                    radius_squared,
+                    reduced_radius_squared,
+                    far_distances,
+                    near,
+                    far,
+                } => {
+                    // The tree puts coordinates equal to the split right
+                    let target_far = (target_pos[*dim] < *split) != (*coordinate < *split);
+                    if target_far {
+                        distances.clone_from(far_distances);
+                    }
+                    trace.steps.push(TraceStep::Split {
+                        layer: *layer,
+                        dim: *dim,
+                        split: *split,
+                        coordinate: *coordinate,
+                        far: target_far,
+                        radius_squared: *radius_squared,
+                        reduced_radius_squared: *reduced_radius_squared,
+                        distances: distances.clone(),
+                    });
+                    match (target_far, far) {
+                        (false, _) => current = near,
+                        (true, Some(far)) => current = far,
+                        // Pruned by the split
+                        (true, None) => break,
+                    }
+                }
+                QueryTrace::Leaf {
+                    layer,
+                    box_distance_squared,
+                    box_pruned,
+                    bucket,
+                    scan_start,
+                    window,
+                    ..
+                } => {
+                    trace.steps.push(TraceStep::LeafBox {
+                        layer: *layer,
+                        box_distance_squared: *box_distance_squared,
+                        radius_squared,
+                    });
+                    if *box_pruned {
                         break;
                     }
-                    let other_pos = self.positions_sorted[i];
-                    if pos.distance_squared(&other_pos) <= original_radius_squared as f32 {
-                        results.push(self.node_ids[i]);
+                    let Layer::Leaf(snn) = &self.layers[*layer] else {
+                        unreachable!("traced leaves are leaves of the tree");
+                    };
+                    assert!(
+                        (snn.offset..snn.offset + snn.len).contains(&offset),
+                        "target is not in the leaf its coordinates lead to"
+                    );
+                    let target_offset = offset - snn.offset;
+                    let stopped_at = (*scan_start..=target_offset)
+                        .find(|&i| self.d_pos[snn.offset + i] > window.1);
+                    let step = TraceStep::LeafScan {
+                        layer: *layer,
+                        bucket: *bucket,
+                        scan_start: *scan_start,
+                        target_offset,
+                        window: *window,
+                        target_coordinate: self.d_pos[offset],
+                        stopped_at,
+                    };
+                    let pruned = step.prunes_target();
+                    trace.steps.push(step);
+                    if !pruned {
+                        trace.steps.push(TraceStep::Distance {
+                            distance_squared: pos.distance_squared(&self.positions_sorted[offset]),
+                            radius_squared,
+                        });
                     }
+                    break;
                 }
             }
         }
+        trace.found = !trace.steps.iter().any(TraceStep::prunes_target);
+        trace
+    }
+}
+
+/// Path of one query through a [`NaiveSprk`], see [`NaiveSprk::explain_query`].
+/// All radii and distances are squared, as in the tree itself.
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryTrace {
+    Node {
+        layer: usize,
+        /// Dimension the layer splits on
+        dim: usize,
+        split: f32,
+        /// Coordinate of the query in `dim`
+        coordinate: f32,
+        /// Radius the layer was entered with
+        radius_squared: f32,
+        /// Radius left for the far side, which is pruned if it is `<= 0`
+        reduced_radius_squared: f32,
+        /// Distances to the splits passed so far, including this one, that
+        /// the far side is entered with
+        far_distances: Vec<f32>,
+        /// Side of the split containing the query
+        near: Box<QueryTrace>,
+        /// Other side, `None` if it was pruned
+        far: Option<Box<QueryTrace>>,
+    },
+    Leaf {
+        layer: usize,
+        len: usize,
+        box_distance_squared: f32,
+        /// The leaf was skipped because its bounding box misses the ball
+        box_pruned: bool,
+        /// Lookup table bucket of the lower end of `window`, `None` if the
+        /// leaf has no lookup table
+        bucket: Option<usize>,
+        /// Offset into the leaf the scan starts at
+        scan_start: usize,
+        /// Coordinates in the sorted dimension of the leaf the scan covers
+        window: (f32, f32),
+        /// Points of the leaf the query was compared against
+        distance_checks: usize,
+        found: Vec<NodeId>,
+    },
+}
+
+/// Work done by one query, see [`QueryTrace::stats`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueryStats {
+    /// Leaves whose points were scanned
+    pub leaves_scanned: usize,
+    pub distance_checks: usize,
+    pub found: usize,
+}
+
+impl QueryTrace {
+    /// All nodes found by the query
+    pub fn found(&self) -> Vec<NodeId> {
+        match self {
+            QueryTrace::Node { near, far, .. } => {
+                let mut found = near.found();
+                if let Some(far) = far {
+                    found.extend(far.found());
+                }
+                found
+            }
+            QueryTrace::Leaf { found, .. } => found.clone(),
+        }
+    }
+
+    /// Totals of the work done over all layers of the query
+    pub fn stats(&self) -> QueryStats {
+        match self {
+            QueryTrace::Node { near, far, .. } => {
+                let mut stats = near.stats();
+                if let Some(far) = far {
+                    let far = far.stats();
+                    stats.leaves_scanned += far.leaves_scanned;
+                    stats.distance_checks += far.distance_checks;
+                    stats.found += far.found;
+                }
+                stats
+            }
+            QueryTrace::Leaf {
+                box_pruned,
+                distance_checks,
+                found,
+                ..
+            } => QueryStats {
+                leaves_scanned: usize::from(!box_pruned),
+                distance_checks: *distance_checks,
+                found: found.len(),
+            },
+        }
+    }
+
+    fn write_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        let pad = "  ".repeat(indent);
+        match self {
+            QueryTrace::Node {
+                layer,
+                dim,
+                split,
+                coordinate,
+                radius_squared,
+                reduced_radius_squared,
+                near,
+                far,
+                ..
+            } => {
+                writeln!(
+                    f,
+                    "{pad}node {layer}: x[{dim}] = {coordinate} vs split {split}, r² = {radius_squared}"
+                )?;
+                near.write_indented(f, indent + 1)?;
+                match far {
+                    Some(far) => {
+                        writeln!(f, "{pad}  far side with r² = {reduced_radius_squared}")?;
+                        far.write_indented(f, indent + 1)
+                    }
+                    None => writeln!(
+                        f,
+                        "{pad}  far side pruned, r² = {reduced_radius_squared} <= 0"
+                    ),
+                }
+            }
+            QueryTrace::Leaf {
+                layer,
+                len,
+                box_distance_squared,
+                box_pruned,
+                distance_checks,
+                found,
+                ..
+            } => {
+                if *box_pruned {
+                    writeln!(
+                        f,
+                        "{pad}leaf {layer} ({len} nodes): pruned, box distance² = {box_distance_squared}"
+                    )
+                } else {
+                    writeln!(
+                        f,
+                        "{pad}leaf {layer} ({len} nodes): found {} in {distance_checks} distance checks",
+                        found.len()
+                    )
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for QueryTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_indented(f, 0)
     }
 }
 
@@ -326,10 +814,295 @@ impl<const D: usize, const P: bool> SpatialIndex<D> for NaiveSprk<'_, D, P> {
     fn implementation_string(&self) -> &'static str {
         include_str!("naive_sprk.rs")
     }
+    fn trace_query(&self, index: NodeId, radius: f64, target: NodeId) -> Option<TargetTrace> {
+        Some(self.trace_query(index, radius, target))
+    }
 }
 
 impl<'a, const D: usize, const P: bool> query::Embedder<'a, D> for NaiveSprk<'a, D, P> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         Self::new(embedding)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+    use super::{NaiveSprk, QueryStats, QueryTrace};
+    use crate::{
+        Embedding, Query, dvec::DVec, graph::Graph, query::Graph as _, target_trace::TraceStep,
+    };
+
+    fn matches_brute_force<const P: bool>(embedding: &Embedding<16>, rng: &mut SmallRng) {
+        let tree = NaiveSprk::<16, P>::new(embedding.view());
+        let mut found = 0;
+        for index in 0..embedding.positions.len() {
+            // The tree queries a ball scaled by the squared own weight
+            let radius = embedding.weight(index).powi(2);
+            let mut expected = Vec::new();
+            embedding.query_radius(embedding.positions[index], radius, &mut expected);
+            let mut actual = tree.nearest_neighbors_owned(index, 1.);
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(actual, expected, "node {index}");
+            found += expected.len();
+        }
+        assert!(
+            found > embedding.positions.len(),
+            "too few neighbours to be meaningful"
+        );
+        for _ in 0..50 {
+            let pos = DVec::from_fn(|_| rng.random_range(0.0..0.7));
+            let radius = rng.random_range(0.3..1.2);
+            let mut expected = Vec::new();
+            let mut actual = Vec::new();
+            embedding.query_radius(pos, radius, &mut expected);
+            tree.query_radius(pos, radius, &mut actual);
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    /// Distance checks of `trace` if leaves were not pruned by their
+    /// bounding box, i.e. the scans the box-pruned leaves would have done
+    fn checks_without_leaf_boxes<const D: usize>(
+        tree: &NaiveSprk<'_, D, true>,
+        trace: &QueryTrace,
+    ) -> usize {
+        match trace {
+            QueryTrace::Node { near, far, .. } => {
+                checks_without_leaf_boxes(tree, near)
+                    + far
+                        .as_ref()
+                        .map_or(0, |far| checks_without_leaf_boxes(tree, far))
+            }
+            QueryTrace::Leaf {
+                box_pruned: false,
+                distance_checks,
+                ..
+            } => *distance_checks,
+            QueryTrace::Leaf {
+                layer,
+                scan_start,
+                window,
+                ..
+            } => {
+                let super::Layer::Leaf(snn) = &tree.layers[*layer] else {
+                    unreachable!("traced leaves are leaves of the tree");
+                };
+                tree.d_pos[snn.offset + scan_start..snn.offset + snn.len]
+                    .iter()
+                    .take_while(|&&coordinate| coordinate <= window.1)
+                    .count()
+            }
+        }
+    }
+
+    #[test]
+    fn leaf_boxes_reduce_distance_checks_in_high_dimensions() {
+        // Clusters around random centres, like the neighbourhoods of an embedding
+        let edges = (0..1999).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 16, 16).unwrap();
+        let mut rng = SmallRng::seed_from_u64(16);
+        let centres: Vec<DVec<16>> = (0..40)
+            .map(|_| DVec::from_fn(|_| rng.random_range(0.0..4.0)))
+            .collect();
+        let embedding = Embedding {
+            positions: (0..graph.nodes.len())
+                .map(|i| centres[i % centres.len()] + DVec::from_fn(|_| rng.random_range(0.0..0.3)))
+                .collect(),
+            graph: &graph,
+        };
+        let tree = NaiveSprk::<16, true>::new(embedding.view());
+
+        let (mut with_boxes, mut without_boxes) = (QueryStats::default(), QueryStats::default());
+        for index in 0..embedding.positions.len() {
+            let trace = tree.explain_query(index, embedding.weight(index).powi(2));
+            let stats = trace.stats();
+            with_boxes.distance_checks += stats.distance_checks;
+            with_boxes.found += stats.found;
+            without_boxes.distance_checks += checks_without_leaf_boxes(&tree, &trace);
+        }
+        assert!(with_boxes.found > embedding.positions.len());
+        // 272k instead of 509k checks with this seed
+        assert!(
+            with_boxes.distance_checks * 3 < without_boxes.distance_checks * 2,
+            "{with_boxes:?} vs {without_boxes:?}"
+        );
+    }
+
+    #[test]
+    fn leaf_boxes_keep_high_dimensional_queries_exact() {
+        let edges = (0..1999).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 16, 16).unwrap();
+        let mut rng = SmallRng::seed_from_u64(16);
+        let embedding = Embedding {
+            positions: (0..graph.nodes.len())
+                .map(|_| DVec::from_fn(|_| rng.random_range(0.0..0.7)))
+                .collect(),
+            graph: &graph,
+        };
+        matches_brute_force::<true>(&embedding, &mut rng);
+        matches_brute_force::<false>(&embedding, &mut rng);
+    }
+
+    #[test]
+    fn explain_query_matches_hand_computed_pruning() {
+        // 300 nodes on the x axis, so the root splits x at node 150 into two
+        // leaves of 150 nodes each
+        let edges = (0..299).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let embedding = Embedding {
+            positions: (0..300).map(|i| DVec::new([i as f32 / 100., 0.])).collect(),
+            graph: &graph,
+        };
+        let tree = NaiveSprk::<2, true>::new(embedding.view());
+
+        // From x = 0 with r = 1 the far leaf is 1.5 away: 1 - 1.5² < 0
+        let QueryTrace::Node {
+            split,
+            reduced_radius_squared,
+            near,
+            far,
+            ..
+        } = tree.explain_query(0, 1.)
+        else {
+            panic!("root should be an inner node");
+        };
+        assert_eq!(split, 1.5);
+        assert_eq!(reduced_radius_squared, 1. - 1.5 * 1.5);
+        assert!(far.is_none());
+        let mut found = near.found();
+        found.sort_unstable();
+        assert_eq!(found, (0..=100).collect::<Vec<_>>());
+
+        // With r = 2 the far leaf is entered with 4 - 1.5² and contributes
+        // the nodes up to x = 2
+        let trace = tree.explain_query(0, 2.);
+        let QueryTrace::Node {
+            reduced_radius_squared,
+            far: Some(far),
+            ..
+        } = &trace
+        else {
+            panic!("far side should be visited");
+        };
+        assert_eq!(*reduced_radius_squared, 4. - 1.5 * 1.5);
+        let mut far_found = far.found();
+        far_found.sort_unstable();
+        assert_eq!(far_found, (150..=200).collect::<Vec<_>>());
+
+        let mut expected = Vec::new();
+        tree.query_radius(embedding.positions[0], 2., &mut expected);
+        let mut found = trace.found();
+        expected.sort_unstable();
+        found.sort_unstable();
+        assert_eq!(found, expected);
+        assert!(trace.to_string().contains("far side with r² = 1.75"));
+    }
+
+    #[test]
+    fn trace_query_names_the_step_that_pruned_the_target() {
+        // Same tree as above, with unit weights so the factor is the radius
+        let edges = (0..299).map(|i| (i, i + 1)).collect();
+        let mut graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        graph.nodes.iter_mut().for_each(|node| node.weight = 1.);
+        let embedding = Embedding {
+            positions: (0..300).map(|i| DVec::new([i as f32 / 100., 0.])).collect(),
+            graph: &graph,
+        };
+        let tree = NaiveSprk::<2, true>::new(embedding.view());
+
+        // x = 2 is behind the split at 1.5, which leaves 1 - 1.5² for the far side
+        let trace = tree.trace_query(0, 1., 200);
+        assert!(!trace.found);
+        assert_eq!(
+            trace.pruned_by(),
+            Some(&TraceStep::Split {
+                layer: 0,
+                dim: 0,
+                split: 1.5,
+                coordinate: 0.,
+                far: true,
+                radius_squared: 1.,
+                reduced_radius_squared: 1. - 1.5 * 1.5,
+                distances: vec![-1.5, 0.],
+            })
+        );
+
+        // x = 1.2 shares the leaf, which sorts by y, so the scan reaches it
+        // and only the distance check rejects it
+        let trace = tree.trace_query(0, 1., 120);
+        let Some(&TraceStep::Distance {
+            distance_squared, ..
+        }) = trace.pruned_by()
+        else {
+            panic!("the distance check should prune 120:\n{trace}");
+        };
+        assert_eq!(distance_squared, 1.2f32 * 1.2);
+        assert!(trace.to_string().contains("✗ distance² 1.44 vs r² 1"));
+
+        let trace = tree.trace_query(0, 1., 80);
+        assert!(trace.found && trace.pruned_by().is_none());
+        assert!(matches!(
+            trace.steps.last(),
+            Some(TraceStep::Distance { .. })
+        ));
+        assert!(trace.to_string().contains("target found"));
+
+        let mut found = Vec::new();
+        tree.nearest_neighbors(7, 2., &mut found);
+        for target in 0..300 {
+            let trace = tree.trace_query(7, 2., target);
+            assert_eq!(trace.found, found.contains(&target), "{trace}");
+        }
+    }
+
+    #[test]
+    fn weighted_lut_resolution_reduces_light_scans() {
+        // A single leaf of 150 nodes on the x axis in [0, 3), alternating
+        // between light and heavy nodes
+        let edges = (0..149).map(|i| (i, i + 1)).collect();
+        let mut graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        for (i, node) in graph.nodes.iter_mut().enumerate() {
+            node.weight = if i % 2 == 0 { 0.1 } else { 0.9 };
+        }
+        let embedding = Embedding {
+            positions: (0..150).map(|i| DVec::new([i as f32 / 50., 0.])).collect(),
+            graph: &graph,
+        };
+        let fixed = NaiveSprk::<2, true>::with_lut_radius_scale(embedding.view(), 0.);
+        let weighted = NaiveSprk::<2, true>::new(embedding.view());
+
+        let mut light = (QueryStats::default(), QueryStats::default());
+        for index in 0..150 {
+            let radius = embedding.weight(index).powi(2);
+            let (fixed_stats, weighted_stats) = (
+                fixed.explain_query(index, radius).stats(),
+                weighted.explain_query(index, radius).stats(),
+            );
+            assert!(weighted_stats.distance_checks <= fixed_stats.distance_checks);
+
+            let mut expected = Vec::new();
+            embedding.query_radius(embedding.positions[index], radius, &mut expected);
+            let mut actual = weighted.nearest_neighbors_owned(index, 1.);
+            expected.sort_unstable();
+            actual.sort_unstable();
+            assert_eq!(actual, expected, "node {index}");
+            assert_eq!(weighted_stats.found, expected.len());
+
+            if index % 2 == 0 {
+                light.0.distance_checks += fixed_stats.distance_checks;
+                light.1.distance_checks += weighted_stats.distance_checks;
+            }
+        }
+        // Every light query only finds itself, but the fixed resolution
+        // scans up to 3 nodes before it
+        assert!(
+            light.1.distance_checks * 2 < light.0.distance_checks,
+            "{light:?}"
+        );
+    }
+}
@@ -2,8 +2,9 @@ use std::marker::PhantomData;
 use std::ptr;
 
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 use nanoflann::*;
@@ -15,24 +16,26 @@ pub struct NanoflannIndexWrapper<'a, const D: usize> {
     _phantom: PhantomData<&'a ()>,
 }
 
+/// Rebuilds the index, the C++ side offers no way to copy it, see
+/// [`crate::query::IndexClone`]
 impl<'a, const D: usize> Clone for NanoflannIndexWrapper<'a, D> {
     fn clone(&self) -> Self {
-        Self::new(&Embedding {
-            positions: self.positions.clone(),
+        Self::new(EmbeddingRef {
+            positions: &self.positions,
             graph: self.graph,
         })
     }
 }
 
 impl<'a, const D: usize> NanoflannIndexWrapper<'a, D> {
-    pub fn new(embedding: &Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let mut wrapper = Self {
-            positions: embedding.positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             index: ptr::null_mut(),
             _phantom: PhantomData,
         };
-        wrapper.update_positions(&embedding.positions, None);
+        wrapper.update_positions(embedding.positions, None);
         wrapper
     }
 
@@ -79,20 +82,23 @@ impl<'a, const D: usize> NanoflannIndexWrapper<'a, D> {
     }
 
     /// Create index with custom leaf size
-    pub fn with_leaf_size(embedding: Embedding<'a, D>, leaf_max_size: usize) -> Self {
-        let positions = embedding.positions.clone();
+    pub fn with_leaf_size(embedding: EmbeddingRef<'_, 'a, D>, leaf_max_size: usize) -> Self {
         let mut wrapper = Self {
-            positions: positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             index: ptr::null_mut(),
             _phantom: PhantomData,
         };
 
-        if !positions.is_empty() {
+        if !wrapper.positions.is_empty() {
             let flat_points = wrapper.positions_to_flat_array();
             unsafe {
-                wrapper.index =
-                    nanoflann_create_index(flat_points.as_ptr(), positions.len(), D, leaf_max_size);
+                wrapper.index = nanoflann_create_index(
+                    flat_points.as_ptr(),
+                    wrapper.positions.len(),
+                    D,
+                    leaf_max_size,
+                );
             }
         }
 
@@ -120,7 +126,7 @@ impl<'a, const D: usize> Graph for NanoflannIndexWrapper<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -220,7 +226,7 @@ impl<'a, const D: usize> SpatialIndex<D> for NanoflannIndexWrapper<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for NanoflannIndexWrapper<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         Self::new(embedding)
     }
 }
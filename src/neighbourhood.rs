@@ -1,8 +1,9 @@
 use std::collections::HashMap;
 
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 use neighbourhood::KdTree;
@@ -32,14 +33,14 @@ impl<'a, const D: usize> Clone for Neihbourhood<'a, D> {
 }
 
 impl<'a, const D: usize> Neihbourhood<'a, D> {
-    pub fn new(embedding: Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let mut tree = Self {
             positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             tree: KdTree::new(vec![[0.; D]; 2]),
             map: HashMap::new(),
         };
-        tree.update_positions(&embedding.positions, None);
+        tree.update_positions(embedding.positions, None);
         tree
     }
 }
@@ -49,7 +50,7 @@ impl<'a, const D: usize> Graph for Neihbourhood<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -109,7 +110,7 @@ impl<'a, const D: usize> SpatialIndex<D> for Neihbourhood<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for Neihbourhood<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
-        Self::new(embedding.clone())
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::new(embedding)
     }
 }
@@ -1,6 +1,7 @@
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 
@@ -261,13 +262,13 @@ pub struct Orthtree<'a, const D: usize> {
 }
 
 impl<'a, const D: usize> Orthtree<'a, D> {
-    pub fn new(embedding: Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let mut tree = Self {
             positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             arena: None,
         };
-        tree.update_positions(&embedding.positions, None);
+        tree.update_positions(embedding.positions, None);
         tree
     }
 }
@@ -288,7 +289,7 @@ impl<'a, const D: usize> Graph for Orthtree<'a, D> {
     fn is_connected(&self, first: NodeId, second: NodeId) -> bool {
         self.graph.is_connected(first, second)
     }
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
     fn weight(&self, index: NodeId) -> f64 {
@@ -361,7 +362,7 @@ impl<'a, const D: usize> SpatialIndex<D> for Orthtree<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for Orthtree<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
-        Self::new(embedding.clone())
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::new(embedding)
     }
 }
@@ -1,7 +1,9 @@
 use memmap::{Mmap, MmapOptions};
 
 use crate::dvec::DVec;
+use crate::graph::Graph;
 use core::panic;
+use std::fmt;
 use std::fs::File;
 use std::io::{self};
 use std::mem::ManuallyDrop;
@@ -12,28 +14,113 @@ pub struct Iteration<const D: usize> {
     pub number: usize,
     pub positions: ManuallyDrop<Vec<DVec<D>>>,
 }
-pub struct Iterations<const D: usize>(Vec<Iteration<D>>, Option<ManuallyDrop<Mmap>>);
+pub struct Iterations<const D: usize>(
+    Vec<Iteration<D>>,
+    Option<ManuallyDrop<Mmap>>,
+    Option<Vec<u64>>,
+);
 
+/// Set in the dimension word of a binary header when the header is followed
+/// by the original node ids
+const ORIGINAL_IDS_FLAG: u64 = 1 << 63;
+
+/// On-disk formats for embedding positions. [`parse_positions_file`] detects
+/// the format from the file contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionFormat {
+    /// Binary `.log` format written by [`write_test_file`]. All integers are
+    /// little endian `u64`, coordinates are little endian `f32`:
+    ///
+    /// ```text
+    /// n  D
+    /// iteration  x_0_0 .. x_0_{D-1}  ..  x_{n-1}_0 .. x_{n-1}_{D-1}
+    /// iteration  ...
+    /// ```
+    ///
+    /// If the highest bit of `D` is set, the header is followed by the `n`
+    /// original node ids of the graph, see
+    /// [`crate::graph::Graph::original_ids`].
+    Binary,
+    /// Text output of the reference wembed tool. Every line holds a node id
+    /// followed by its `D` coordinates, separated by whitespace or commas:
+    ///
+    /// ```text
+    /// # iteration 0
+    /// 0 0.5 1.25
+    /// 1 -3.0 2.0
+    /// ```
+    ///
+    /// `# iteration <number>` lines start a new iteration; without them the
+    /// whole file is a single iteration with number 0. Every node of an
+    /// iteration has to appear exactly once, in any order. Other lines
+    /// starting with `#` are ignored.
+    Text,
+}
+
+impl PositionFormat {
+    /// Detects the format from the start of a file. The binary header starts
+    /// with the node count as `u64`, which always contains non-text bytes.
+    pub fn detect(bytes: &[u8]) -> Self {
+        let head = &bytes[..bytes.len().min(64)];
+        let is_text = head
+            .iter()
+            .all(|&b| b.is_ascii_graphic() || b.is_ascii_whitespace());
+        if is_text && !head.is_empty() {
+            PositionFormat::Text
+        } else {
+            PositionFormat::Binary
+        }
+    }
+}
+
+/// Parses a positions file in any of the supported [`PositionFormat`]s.
 pub fn parse_positions_file<P: AsRef<Path>, const D: usize>(path: P) -> io::Result<Iterations<D>> {
     let file = File::open(path)?;
+    let mmap = ManuallyDrop::new(unsafe { MmapOptions::new().map(&file)? });
+    match PositionFormat::detect(&mmap) {
+        PositionFormat::Binary => parse_binary_positions(mmap),
+        PositionFormat::Text => {
+            let mmap = ManuallyDrop::into_inner(mmap);
+            let text = std::str::from_utf8(&mmap)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            parse_text_positions(text)
+        }
+    }
+}
+
+fn parse_binary_positions<const D: usize>(
+    original_mmap: ManuallyDrop<Mmap>,
+) -> io::Result<Iterations<D>> {
+    if let Err(e) = check_binary_layout::<D>(&original_mmap) {
+        drop(ManuallyDrop::into_inner(original_mmap));
+        return Err(e);
+    }
     let mut iterations: Vec<Iteration<D>> = Vec::new();
 
-    let original_mmap = ManuallyDrop::new(unsafe { MmapOptions::new().map(&file)? });
     // Read header: n (nodes) and dim (dimensions)
     let (buffer, mmap) = original_mmap.split_first_chunk().unwrap();
     let n = u64::from_le_bytes(*buffer) as usize;
 
     let (buffer, mmap) = mmap.split_first_chunk().unwrap();
-    let dim = u64::from_le_bytes(*buffer) as usize;
+    let dim = u64::from_le_bytes(*buffer);
 
-    if dim != D {
+    if (dim & !ORIGINAL_IDS_FLAG) as usize != D {
         panic!(
             "Graph dimension from data file ({}) did not match the compiled graph dimension ({})",
-            dim, D
+            dim & !ORIGINAL_IDS_FLAG,
+            D
         );
     }
     let mut mmap = mmap;
 
+    let original_ids = (dim & ORIGINAL_IDS_FLAG != 0).then(|| {
+        let (ids, rest) = mmap.split_at(n * size_of::<u64>());
+        mmap = rest;
+        ids.chunks_exact(size_of::<u64>())
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect()
+    });
+
     // Read iterations until EOF
     while !mmap.is_empty() {
         let (buffer, new_mmap) = mmap.split_first_chunk().unwrap();
@@ -46,7 +133,18 @@ pub fn parse_positions_file<P: AsRef<Path>, const D: usize>(path: P) -> io::Resu
         mmap = new_mmap;
 
         // Read position data
-        let buffer = unsafe { Vec::from_raw_parts(iteration.as_ptr() as *mut DVec<D>, n, n) };
+        let buffer = if packed::<D>() {
+            unsafe { Vec::from_raw_parts(iteration.as_ptr() as *mut DVec<D>, n, n) }
+        } else {
+            iteration
+                .chunks_exact(D * size_of::<f32>())
+                .map(|bytes| {
+                    DVec::from_fn(|i| {
+                        f32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap())
+                    })
+                })
+                .collect()
+        };
 
         iterations.push(Iteration {
             number: iteration_number,
@@ -54,12 +152,149 @@ pub fn parse_positions_file<P: AsRef<Path>, const D: usize>(path: P) -> io::Resu
         });
     }
 
-    Ok(Iterations(iterations, Some(original_mmap)))
+    if packed::<D>() {
+        Ok(Iterations(iterations, Some(original_mmap), original_ids))
+    } else {
+        // The positions were copied out, the mapping is no longer needed
+        drop(ManuallyDrop::into_inner(original_mmap));
+        Ok(Iterations(iterations, None, original_ids))
+    }
+}
+
+/// Checks the header and the length of every iteration of a binary positions
+/// file, so a file of another dimension or a truncated one is reported as an
+/// error instead of a panic
+fn check_binary_layout<const D: usize>(bytes: &[u8]) -> io::Result<()> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let word = |offset: usize| {
+        bytes
+            .get(offset..offset + 8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| invalid("positions file is truncated".to_string()))
+    };
+    let (n, dim) = (word(0)?, word(8)? as u64);
+    let has_ids = dim & ORIGINAL_IDS_FLAG != 0;
+    let dim = dim & !ORIGINAL_IDS_FLAG;
+    if dim != D as u64 {
+        return Err(invalid(format!(
+            "positions file has dimension {dim}, expected {D}"
+        )));
+    }
+    let iteration_size = n
+        .checked_mul(D * size_of::<f32>())
+        .and_then(|size| size.checked_add(8))
+        .ok_or_else(|| invalid(format!("invalid node count {n}")))?;
+    let header_size = if has_ids {
+        n.checked_mul(size_of::<u64>())
+            .and_then(|size| size.checked_add(16))
+            .ok_or_else(|| invalid(format!("invalid node count {n}")))?
+    } else {
+        16
+    };
+    if bytes.len() < header_size || !(bytes.len() - header_size).is_multiple_of(iteration_size) {
+        return Err(invalid("positions file is truncated".to_string()));
+    }
+    Ok(())
+}
+
+/// Whether `DVec<D>` has the layout of `[f32; D]`, so binary positions can be
+/// used in place. Not the case with the `simd-align` feature.
+const fn packed<const D: usize>() -> bool {
+    size_of::<DVec<D>>() == D * size_of::<f32>() && align_of::<DVec<D>>() == align_of::<f32>()
+}
+
+/// Parses positions in the [`PositionFormat::Text`] format.
+pub fn parse_text_positions<const D: usize>(text: &str) -> io::Result<Iterations<D>> {
+    let invalid = |line: usize, msg: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {}: {msg}", line + 1),
+        )
+    };
+
+    // Collect (node, position) rows per iteration first, the node order is arbitrary
+    let mut raw: Vec<(usize, Vec<(usize, DVec<D>)>)> = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(number) = comment.trim().strip_prefix("iteration") {
+                let number = number
+                    .trim()
+                    .parse()
+                    .map_err(|e| invalid(line_number, format!("invalid iteration: {e}")))?;
+                raw.push((number, Vec::new()));
+            }
+            continue;
+        }
+
+        let mut columns = line
+            .split(|c: char| c == ',' || c.is_ascii_whitespace())
+            .filter(|c| !c.is_empty());
+        let node: usize = columns
+            .next()
+            .ok_or_else(|| invalid(line_number, "missing node id".to_string()))?
+            .parse()
+            .map_err(|e| invalid(line_number, format!("invalid node id: {e}")))?;
+        let coordinates = columns
+            .map(|c| c.parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| invalid(line_number, format!("invalid coordinate: {e}")))?;
+        if coordinates.len() != D {
+            return Err(invalid(
+                line_number,
+                format!("expected {D} coordinates, found {}", coordinates.len()),
+            ));
+        }
+        if raw.is_empty() {
+            raw.push((0, Vec::new()));
+        }
+        let position = DVec::from_fn(|i| coordinates[i]);
+        raw.last_mut().unwrap().1.push((node, position));
+    }
+
+    let mut iterations = Vec::with_capacity(raw.len());
+    for (number, mut rows) in raw {
+        rows.sort_unstable_by_key(|&(node, _)| node);
+        if rows.iter().enumerate().any(|(i, &(node, _))| i != node) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("iteration {number} does not contain every node exactly once"),
+            ));
+        }
+        if let Some(first) = iterations.first().map(|i: &Iteration<D>| i.positions.len())
+            && first != rows.len()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "iteration {number} has {} nodes, expected {first}",
+                    rows.len()
+                ),
+            ));
+        }
+        iterations.push(Iteration {
+            number,
+            positions: ManuallyDrop::new(rows.into_iter().map(|(_, pos)| pos).collect()),
+        });
+    }
+
+    Ok(Iterations(iterations, None, None))
 }
 
 impl<const D: usize> Drop for Iterations<D> {
     fn drop(&mut self) {
-        ManuallyDrop::into_inner(self.1.take().unwrap());
+        match self.1.take() {
+            // Binary positions point into the mapping and must not be freed themselves
+            Some(mmap) => drop(ManuallyDrop::into_inner(mmap)),
+            None => {
+                for iteration in &mut self.0 {
+                    unsafe { ManuallyDrop::drop(&mut iteration.positions) };
+                }
+            }
+        }
     }
 }
 
@@ -67,11 +302,57 @@ impl<const D: usize> Iterations<D> {
     pub fn iterations(&self) -> &[Iteration<D>] {
         self.0.as_slice()
     }
+
+    /// Original node ids the positions were written with, see
+    /// [`crate::graph::Graph::original_ids`]
+    pub fn original_ids(&self) -> Option<&[u64]> {
+        self.2.as_deref()
+    }
+
+    /// Number of nodes per iteration, the largest if they differ and 0 for
+    /// an empty file
+    pub fn num_nodes(&self) -> usize {
+        self.0.iter().map(|i| i.positions.len()).max().unwrap_or(0)
+    }
+
+    /// Checks that the positions were written for `graph`
+    pub fn validate_graph_compatibility(&self, graph: &Graph) -> Result<(), ValidationError> {
+        let positions = self.num_nodes();
+        let graph = graph.nodes.len();
+        if positions != graph {
+            return Err(ValidationError { positions, graph });
+        }
+        Ok(())
+    }
+}
+
+/// The positions file and the graph have different numbers of nodes, see
+/// [`Iterations::validate_graph_compatibility`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationError {
+    pub positions: usize,
+    pub graph: usize,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "positions file has {} nodes, but the graph has {}; was it generated for a different graph?",
+            self.positions, self.graph
+        )
+    }
 }
 
+impl std::error::Error for ValidationError {}
+
+/// Writes positions in the [`PositionFormat::Binary`] format. `original_ids`
+/// are stored along with them so the positions can be joined back to the
+/// input graph, pass [`Graph::original_ids`] here.
 pub fn write_test_file<const D: usize>(
     file_path: &str,
     iterations: &[(u64, Vec<DVec<D>>)],
+    original_ids: Option<&[u64]>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     use std::io::{BufWriter, Write};
     let file = File::create(file_path)?;
@@ -81,7 +362,18 @@ pub fn write_test_file<const D: usize>(
     if let Some(first_iteration) = iterations.first() {
         let num_nodes = first_iteration.1.len() as u64;
         writer.write_all(&num_nodes.to_le_bytes())?;
-        writer.write_all(&(D as u64).to_le_bytes())?;
+        match original_ids {
+            Some(ids) => {
+                if ids.len() as u64 != num_nodes {
+                    return Err(format!("{} original ids for {num_nodes} nodes", ids.len()).into());
+                }
+                writer.write_all(&(D as u64 | ORIGINAL_IDS_FLAG).to_le_bytes())?;
+                for id in ids {
+                    writer.write_all(&id.to_le_bytes())?;
+                }
+            }
+            None => writer.write_all(&(D as u64).to_le_bytes())?,
+        }
     } else {
         return Err("No iterations found".into());
     }
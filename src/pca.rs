@@ -0,0 +1,70 @@
+//! Principal component analysis on top of the SVD from sprk.
+
+use sprk::svd::Svd;
+
+/// Mean and principal axes of a point set.
+#[derive(Clone, Debug)]
+pub struct Pca<const D: usize> {
+    svd: Svd<D, f32>,
+    /// Mean of all points passed to [`Pca::fit`]
+    pub mean: [f32; D],
+}
+
+impl<const D: usize> Default for Pca<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const D: usize> Pca<D> {
+    pub fn new() -> Self {
+        Self {
+            svd: Svd::new(),
+            mean: [0.0; D],
+        }
+    }
+
+    pub fn fit(&mut self, points: &[[f32; D]]) {
+        self.mean = [0.0; D];
+        if points.is_empty() {
+            return;
+        }
+        let inv_n = 1.0 / points.len() as f32;
+        for pos in points {
+            for (m, x) in self.mean.iter_mut().zip(pos) {
+                *m += x;
+            }
+        }
+        for m in &mut self.mean {
+            *m *= inv_n;
+        }
+        self.svd.compute_svd(points);
+    }
+
+    /// Coordinates of `point` along the principal axes, ordered by decreasing variance.
+    pub fn project(&self, point: &[f32; D]) -> [f32; D] {
+        self.svd.project(point)
+    }
+
+    pub fn project_all(&self, points: &[[f32; D]]) -> Vec<[f32; D]> {
+        self.svd.project_all(points)
+    }
+
+    /// The `k`-th principal axis as unit vector, extracted by probing the projection.
+    pub fn axis(&self, k: usize) -> [f32; D] {
+        let origin_proj = self.project(&self.mean);
+        let mut axis = [0.0f32; D];
+        for (j, a) in axis.iter_mut().enumerate() {
+            let mut shifted = self.mean;
+            shifted[j] += 1.0;
+            *a = self.project(&shifted)[k] - origin_proj[k];
+        }
+        let norm: f32 = axis.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            for a in &mut axis {
+                *a /= norm;
+            }
+        }
+        axis
+    }
+}
@@ -3,8 +3,9 @@ use std::marker::PhantomData;
 use py_snn::SnnIndex;
 
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 
@@ -17,22 +18,22 @@ pub struct PySnn<'a, const D: usize> {
 
 impl<'a, const D: usize> Clone for PySnn<'a, D> {
     fn clone(&self) -> Self {
-        Self::new(&Embedding {
-            positions: self.positions.clone(),
+        Self::new(EmbeddingRef {
+            positions: &self.positions,
             graph: self.graph,
         })
     }
 }
 
 impl<'a, const D: usize> PySnn<'a, D> {
-    pub fn new(embedding: &Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let mut wrapper = Self {
-            positions: embedding.positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             index: None,
             _phantom: PhantomData,
         };
-        wrapper.update_positions(&embedding.positions, None);
+        wrapper.update_positions(embedding.positions, None);
         wrapper
     }
 
@@ -64,7 +65,7 @@ impl<'a, const D: usize> Graph for PySnn<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -134,7 +135,7 @@ impl<'a, const D: usize> SpatialIndex<D> for PySnn<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for PySnn<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         Self::new(embedding)
     }
 }
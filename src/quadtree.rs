@@ -1,6 +1,7 @@
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 
@@ -280,7 +281,7 @@ pub struct Quadtree<'a, const D: usize> {
 }
 
 impl<'a, const D: usize> Quadtree<'a, D> {
-    pub fn new(embedding: Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         if D != 2 {
             return Self {
                 positions: embedding.positions.to_vec(),
@@ -307,7 +308,7 @@ impl<'a, const D: usize> Quadtree<'a, D> {
                 DEPTH,
             ),
         };
-        tree.update_positions(&embedding.positions, None);
+        tree.update_positions(embedding.positions, None);
         tree
     }
 }
@@ -350,7 +351,7 @@ impl<'a, const D: usize> Graph for Quadtree<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -443,7 +444,7 @@ impl<'a, const D: usize> SpatialIndex<D> for Quadtree<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for Quadtree<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
-        Self::new(embedding.clone())
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::new(embedding)
     }
 }
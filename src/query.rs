@@ -1,9 +1,19 @@
-use crate::{Embedding, NodeId, dvec::DVec};
+use crate::{
+    EmbeddingRef, NodeId,
+    dvec::DVec,
+    graph::Neighbors,
+    graph_statistics::{
+        GraphStatisticsReport, WeightClassStatistics, min_positive_weight,
+        partition_by_weight_class,
+    },
+    rng::{Purpose, RngSource},
+    target_trace::TargetTrace,
+};
 use rayon::prelude::*;
 
 pub trait Graph {
     fn is_connected(&self, first: NodeId, second: NodeId) -> bool;
-    fn neighbors(&self, index: NodeId) -> &[NodeId];
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_>;
     fn weight(&self, index: NodeId) -> f64;
 }
 
@@ -14,6 +24,12 @@ pub trait Position<const D: usize> {
         D
     }
 }
+/// Cloning of boxed spatial indices. A clone has to answer queries like the
+/// original right away, so `Clone` implementations copy the built state,
+/// e.g. trees and query caches, instead of rebuilding it from the positions.
+/// Indices that cannot copy their state, like
+/// [`crate::nanoflann::NanoflannIndexWrapper`] behind its FFI handle,
+/// rebuild and document it, as that makes clones expensive.
 pub trait IndexClone<const D: usize>: SpatialIndex<D> {
     fn clone_box<'a>(&'a self) -> Box<dyn SpatialIndex<D> + 'a>;
 }
@@ -24,6 +40,28 @@ impl<const D: usize, T: Clone + Sized + SpatialIndex<D> + Sync> IndexClone<D> fo
     }
 }
 
+/// Builds a spatial index from an embedding. Construction is kept out of
+/// [`SpatialIndex`] so that indices can be created through trait objects,
+/// see [`crate::factories`].
+pub trait SpatialIndexFactory<'a, const D: usize>: Sync {
+    fn build(&self, embedding: EmbeddingRef<'_, 'a, D>) -> Box<dyn IndexClone<D> + 'a>;
+
+    /// [`SpatialIndex::name`] of the built index, if it is known without
+    /// building it
+    fn name(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl<'a, const D: usize, F> SpatialIndexFactory<'a, D> for F
+where
+    F: Fn(EmbeddingRef<'_, 'a, D>) -> Box<dyn IndexClone<D> + 'a> + Sync,
+{
+    fn build(&self, embedding: EmbeddingRef<'_, 'a, D>) -> Box<dyn IndexClone<D> + 'a> {
+        self(embedding)
+    }
+}
+
 pub trait SpatialIndex<const D: usize>: Query<D> + Update<D> + Graph + Position<D> + Sync {
     fn name(&self) -> String;
 
@@ -31,6 +69,27 @@ pub trait SpatialIndex<const D: usize>: Query<D> + Update<D> + Graph + Position<
     /// The default implementation is a no-op.
     fn set_radius_hint(&mut self, _radius: f64) {}
 
+    /// Whether queries return exactly the ground truth neighbourhood.
+    /// Approximate indices (e.g. LSH) are held to a recall threshold by the
+    /// correctness tests instead of having to match exactly.
+    fn is_exact(&self) -> bool {
+        true
+    }
+
+    /// Reruns the query of `index` with `radius` like
+    /// [`Query::nearest_neighbors`] and records every decision that could
+    /// have excluded `target`. `None` if the index does not support tracing.
+    fn trace_query(&self, _index: NodeId, _radius: f64, _target: NodeId) -> Option<TargetTrace> {
+        None
+    }
+
+    /// Estimated heap usage in bytes. The graph is borrowed and not counted,
+    /// see [`crate::graph::Graph::memory_bytes`]. The default counts one copy
+    /// of the positions, indices that keep more override it.
+    fn memory_usage_bytes(&self) -> usize {
+        self.num_nodes() * size_of::<DVec<D>>()
+    }
+
     /// Returns the source code implementation as a string for checksum calculation.
     /// This should include all files that affect the performance of this data structure.
     fn implementation_string(&self) -> &'static str;
@@ -66,16 +125,25 @@ pub trait Query<const D: usize>: Position<D> + Graph {
         results
     }
 
-    /// Runs a batch of nn queries and makes the result symmetric
+    /// Runs a batch of nn queries with radius 1 and makes the result symmetric
     fn nearest_neighbors_batched(&self, indices: &[usize]) -> Vec<Vec<usize>>
     where
         Self: Sync,
     {
-        let n = indices.len();
+        let queries: Vec<_> = indices.iter().map(|&index| (index, 1.)).collect();
+        self.nearest_neighbors_batched_with(&queries)
+    }
+
+    /// Runs a batch of `(index, radius)` nn queries and makes the result symmetric
+    fn nearest_neighbors_batched_with(&self, queries: &[(usize, f64)]) -> Vec<Vec<usize>>
+    where
+        Self: Sync,
+    {
+        let n = queries.len();
         // Run all NN queries in parallel
-        let per_node: Vec<Vec<usize>> = indices
+        let per_node: Vec<Vec<usize>> = queries
             .par_iter()
-            .map(|&index| self.nearest_neighbors_owned(index, 1.))
+            .map(|&(index, radius)| self.nearest_neighbors_owned(index, radius))
             .collect();
 
         // Symmetrize: merge forward edges and reverse edges
@@ -92,10 +160,175 @@ pub trait Query<const D: usize>: Position<D> + Graph {
         });
         results
     }
+
+    /// Every pair of nodes that the nn queries with `radius` of all nodes
+    /// find, from either end, exactly once as `(u, v)` with `u < v` and in
+    /// sorted order. Unlike [`Query::nearest_neighbors_batched_with`] it does
+    /// not store each pair in the lists of both nodes; [`pairs_to_lists`]
+    /// expands the pairs to those lists.
+    fn nearest_neighbor_pairs(&self, radius: f64) -> Vec<(NodeId, NodeId)>
+    where
+        Self: Sync,
+    {
+        let mut pairs: Vec<_> = (0..self.num_nodes())
+            .into_par_iter()
+            .flat_map_iter(|v| {
+                self.nearest_neighbors_owned(v, radius)
+                    .into_iter()
+                    .filter(move |&u| u != v)
+                    .map(move |u| (u.min(v), u.max(v)))
+            })
+            .collect();
+        pairs.par_sort_unstable();
+        pairs.dedup();
+        pairs
+    }
+}
+
+/// The symmetric neighbor lists of `num_nodes` nodes with the `pairs` of
+/// [`Query::nearest_neighbor_pairs`], sorted like the lists of
+/// [`Query::nearest_neighbors_batched`] but without the node itself
+pub fn pairs_to_lists(pairs: &[(NodeId, NodeId)], num_nodes: usize) -> Vec<Vec<NodeId>> {
+    let mut lists = vec![Vec::new(); num_nodes];
+    for &(u, v) in pairs {
+        lists[u].push(v);
+        lists[v].push(u);
+    }
+    lists.par_iter_mut().for_each(|list| list.sort_unstable());
+    lists
+}
+
+/// The pairs in symmetric or one-sided neighbor lists, in the form of
+/// [`Query::nearest_neighbor_pairs`]. Nodes in their own list are dropped.
+pub fn lists_to_pairs(lists: &[Vec<NodeId>]) -> Vec<(NodeId, NodeId)> {
+    let mut pairs: Vec<_> = lists
+        .iter()
+        .enumerate()
+        .flat_map(|(v, list)| {
+            list.iter()
+                .filter(move |&&u| u != v)
+                .map(move |&u| (u.min(v), u.max(v)))
+        })
+        .collect();
+    pairs.par_sort_unstable();
+    pairs.dedup();
+    pairs
 }
 
 pub trait Update<const D: usize> {
     fn update_positions(&mut self, postions: &[DVec<D>], last_delta: Option<f64>);
+
+    /// First half of [`Update::update_positions`]: builds the index state for
+    /// `positions` without modifying `self`, so it can run on another thread
+    /// while the current state is still queried. The default only copies the
+    /// positions and leaves all work to [`Update::commit_update`].
+    fn prepare_update(
+        &self,
+        positions: &[DVec<D>],
+        last_delta: Option<f64>,
+    ) -> PreparedUpdate<DVec<D>> {
+        PreparedUpdate::deferred(positions, last_delta)
+    }
+
+    /// Second half of [`Update::update_positions`]: installs a state from
+    /// [`Update::prepare_update`] of this index. Afterwards, queries return
+    /// the same results as after `update_positions` with the same arguments.
+    fn commit_update(&mut self, prepared: PreparedUpdate<DVec<D>>) {
+        let (positions, last_delta) = prepared.into_deferred();
+        self.update_positions(&positions, last_delta);
+    }
+
+    /// Nodes that changed places in the order of the index during the last
+    /// update, see [`moved_nodes`]. `None` for indices that do not keep an
+    /// order from one update to the next.
+    fn moved_in_last_update(&self) -> Option<usize> {
+        None
+    }
+
+    /// Forgets the order kept from the last update, so that the next update
+    /// starts from scratch like the first build. Construction benchmarks use
+    /// it to measure cold builds.
+    fn discard_warm_start(&mut self) {}
+}
+
+/// Fewest nodes that have to move to turn the order `previous` into
+/// `current`, i.e. the nodes outside the longest subsequence that kept its
+/// order. All nodes if the two orders differ in length. `O(n log n)`.
+pub fn moved_nodes(previous: &[NodeId], current: &[NodeId]) -> usize {
+    let n = current.len();
+    if previous.len() != n {
+        return n;
+    }
+    let mut old_rank = vec![0; n];
+    for (rank, &node) in previous.iter().enumerate() {
+        old_rank[node] = rank;
+    }
+    // Patience sorting: tails[k] is the smallest old rank that ends an
+    // increasing subsequence of length k + 1
+    let mut tails: Vec<usize> = Vec::new();
+    for &node in current {
+        let rank = old_rank[node];
+        match tails.partition_point(|&tail| tail < rank) {
+            k if k == tails.len() => tails.push(rank),
+            k => tails[k] = rank,
+        }
+    }
+    n - tails.len()
+}
+
+// `Update` has to stay object safe, `dyn SpatialIndex` and the embedder's
+// uniform `last_delta` rely on it
+const _: Option<&dyn Update<2>> = None;
+
+/// Index state built by [`Update::prepare_update`], waiting for
+/// [`Update::commit_update`]. Generic over the vector type, so that
+/// [`crate::dyn_embed::EmbedIndex`] can share it.
+pub enum PreparedUpdate<V> {
+    /// Nothing was built yet, the commit runs the whole update
+    Deferred {
+        positions: Vec<V>,
+        last_delta: Option<f64>,
+    },
+    /// State built by the index, only its own commit knows the type
+    Built(Box<dyn std::any::Any + Send>),
+}
+
+impl<V: Clone> PreparedUpdate<V> {
+    pub fn deferred(positions: &[V], last_delta: Option<f64>) -> Self {
+        PreparedUpdate::Deferred {
+            positions: positions.to_vec(),
+            last_delta,
+        }
+    }
+}
+
+impl<V> PreparedUpdate<V> {
+    pub fn built<T: Send + 'static>(state: T) -> Self {
+        PreparedUpdate::Built(Box::new(state))
+    }
+
+    /// Positions and delta of a deferred update. Panics if the index built a
+    /// state, i.e. the update was prepared by a different index type.
+    pub fn into_deferred(self) -> (Vec<V>, Option<f64>) {
+        match self {
+            PreparedUpdate::Deferred {
+                positions,
+                last_delta,
+            } => (positions, last_delta),
+            PreparedUpdate::Built(_) => panic!("update was prepared by a different index"),
+        }
+    }
+
+    /// State built by the index. Panics if it is not a `T`, i.e. the update
+    /// was prepared by a different index type.
+    pub fn into_built<T: 'static>(self) -> T {
+        match self {
+            PreparedUpdate::Built(state) => *state
+                .downcast()
+                .unwrap_or_else(|_| panic!("update was prepared by a different index")),
+            PreparedUpdate::Deferred { .. } => panic!("update was prepared by a different index"),
+        }
+    }
 }
 
 pub trait Embedder<'a, const D: usize>: Query<D> + Update<D> + Graph + Position<D> {
@@ -116,13 +349,13 @@ pub trait Embedder<'a, const D: usize>: Query<D> + Update<D> + Graph + Position<
         self.neighbors(index).to_vec()
     }
 
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self;
+    fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self;
     fn from_graph(graph: &'a crate::graph::Graph) -> Self
     where
         Self: Sized,
     {
-        Self::new(&Embedding {
-            positions: Vec::new(),
+        Self::new(EmbeddingRef {
+            positions: &[],
             graph,
         })
     }
@@ -131,48 +364,101 @@ pub trait Embedder<'a, const D: usize>: Query<D> + Update<D> + Graph + Position<
     where
         Self: Sync,
     {
-        let ids: Vec<_> = (0..(self.num_nodes())).collect();
-        let results = self.nearest_neighbors_batched(&ids);
+        let report = self.graph_statistics_report(None, 0);
+        (report.precision, report.recall)
+    }
 
-        // Count total edges in graph
-        let total_edges: usize =
-            (0..self.num_nodes()).map(|i| self.neighbors(i).len()).sum::<usize>() / 2;
+    /// Precision and recall overall and per
+    /// [`crate::graph_statistics::weight_class`]. With
+    /// `samples_per_class` only that many nodes of each class, drawn with
+    /// `seed`, are queried; without it every node is and the overall numbers
+    /// are exact.
+    fn graph_statistics_report(
+        &self,
+        samples_per_class: Option<usize>,
+        seed: u64,
+    ) -> GraphStatisticsReport
+    where
+        Self: Sync,
+    {
+        let classes = partition_by_weight_class((0..self.num_nodes()).map(|i| self.weight(i)));
+        let min_weight = min_positive_weight((0..self.num_nodes()).map(|i| self.weight(i)));
+        let (sampled, results): (Vec<Vec<NodeId>>, Vec<Vec<NodeId>>) = match samples_per_class {
+            None => {
+                let indices: Vec<_> = (0..self.num_nodes()).collect();
+                (classes.clone(), self.nearest_neighbors_batched(&indices))
+            }
+            Some(samples) => {
+                let mut rng = RngSource::new(seed).stream(Purpose::StatisticsSample, &[]);
+                let sampled: Vec<Vec<NodeId>> = classes
+                    .iter()
+                    .map(|nodes| {
+                        let mut picked: Vec<_> = rand::seq::index::sample(
+                            &mut rng,
+                            nodes.len(),
+                            samples.min(nodes.len()),
+                        )
+                        .into_iter()
+                        .map(|i| nodes[i])
+                        .collect();
+                        picked.sort_unstable();
+                        picked
+                    })
+                    .collect();
+                // Only the sampled nodes' own lists are read
+                let mut results = vec![Vec::new(); self.num_nodes()];
+                let found: Vec<_> = sampled
+                    .concat()
+                    .into_par_iter()
+                    .map(|i| (i, self.nearest_neighbors_owned(i, 1.)))
+                    .collect();
+                for (i, neighbors) in found {
+                    results[i] = neighbors;
+                }
+                (sampled, results)
+            }
+        };
 
-        // precision, recall
-        let (found_edges, found_non_edges) = results
-            .par_iter()
+        let class_statistics = classes
+            .iter()
+            .zip(&sampled)
             .enumerate()
-            .map(|(i, close_nodes)| {
-                let mut edges = 0usize;
-                let mut non_edges = 0usize;
-                for &close_node in close_nodes {
-                    if i == close_node {
-                        continue;
-                    }
-                    let within_dist = (self
-                        .position(i)
-                        .distance_squared(self.position(close_node))
-                        as f64)
-                        < (self.weight(close_node) * self.weight(i)).powi(2);
-
-                    if self.is_connected(i, close_node) && within_dist {
-                        edges += 1;
-                    } else if within_dist {
-                        non_edges += 1;
-                    }
+            .map(|(class, (nodes, sampled))| {
+                let (true_positives, false_positives, edges) = sampled
+                    .par_iter()
+                    .map(|&i| {
+                        let mut true_positives = 0usize;
+                        let mut false_positives = 0usize;
+                        for &close_node in &results[i] {
+                            if i == close_node {
+                                continue;
+                            }
+                            let within_dist =
+                                (self.position(i).distance_squared(self.position(close_node))
+                                    as f64)
+                                    < (self.weight(close_node) * self.weight(i)).powi(2);
+
+                            if self.is_connected(i, close_node) && within_dist {
+                                true_positives += 1;
+                            } else if within_dist {
+                                false_positives += 1;
+                            }
+                        }
+                        (true_positives, false_positives, self.neighbors(i).len())
+                    })
+                    .reduce(|| (0, 0, 0), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2));
+                WeightClassStatistics {
+                    min_weight: min_weight * 2f64.powi(class as i32),
+                    max_weight: min_weight * 2f64.powi(class as i32 + 1),
+                    nodes: nodes.len(),
+                    sampled_nodes: sampled.len(),
+                    true_positives,
+                    false_positives,
+                    edges,
                 }
-                (edges, non_edges)
             })
-            .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
-
-        // Adjust for double-counting (since results are symmetric)
-        let found_edges = found_edges / 2;
-        let found_non_edges = found_non_edges / 2;
-
-        (
-            found_edges as f64 / (found_edges + found_non_edges).max(1) as f64,
-            found_edges as f64 / total_edges as f64,
-        )
+            .collect();
+        GraphStatisticsReport::from_classes(class_statistics)
     }
     fn f1(&self) -> f64
     where
@@ -182,3 +468,203 @@ pub trait Embedder<'a, const D: usize>: Query<D> + Update<D> + Graph + Position<
         2. / (recall.recip() + precision.recip())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+    use super::{Embedder, Graph as _, IndexClone, Position, lists_to_pairs, pairs_to_lists};
+    use crate::{
+        Embedding, data_structures, dvec::DVec, graph::Graph,
+        random_projection_lsh::RandomProjectionLsh,
+    };
+
+    #[test]
+    fn batched_queries_respect_per_node_radius() {
+        let edges = (0..299).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let mut rng = SmallRng::seed_from_u64(7);
+        let embedding: Embedding<2> = Embedding {
+            positions: (0..graph.nodes.len())
+                .map(|_| DVec::from_fn(|_| rng.random_range(0.0..10.0)))
+                .collect(),
+            graph: &graph,
+        };
+        let queries: Vec<(usize, f64)> = (0..graph.nodes.len())
+            .map(|i| (i, rng.random_range(0.5..2.0)))
+            .collect();
+
+        for structure in data_structures(&embedding, None) {
+            let mut expected = vec![Vec::new(); queries.len()];
+            for &(index, radius) in &queries {
+                for other in structure.nearest_neighbors_owned(index, radius) {
+                    expected[index].push(other);
+                    expected[other].push(index);
+                }
+            }
+            for list in &mut expected {
+                list.sort_unstable();
+                list.dedup();
+            }
+            assert_eq!(
+                structure.nearest_neighbors_batched_with(&queries),
+                expected,
+                "{}",
+                structure.name()
+            );
+        }
+    }
+
+    #[test]
+    fn neighbor_pairs_are_the_batched_lists_once() {
+        // Hubs every 25 nodes, so the two ends of a pair differ in weight
+        let edges = (0..299)
+            .map(|i| (i, i + 1))
+            .chain((0..300).filter(|i| i % 25 > 1).map(|i| (i, i - i % 25)))
+            .collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let mut rng = SmallRng::seed_from_u64(5);
+        let embedding: Embedding<2> = Embedding {
+            positions: (0..graph.nodes.len())
+                .map(|_| DVec::from_fn(|_| rng.random_range(0.0..10.0)))
+                .collect(),
+            graph: &graph,
+        };
+        let indices: Vec<_> = (0..graph.nodes.len()).collect();
+
+        for structure in data_structures(&embedding, None) {
+            let pairs = structure.nearest_neighbor_pairs(1.);
+            assert!(!pairs.is_empty());
+            // Strictly increasing, so no pair comes twice
+            assert!(pairs.iter().all(|&(u, v)| u < v), "{}", structure.name());
+            assert!(pairs.is_sorted_by(|a, b| a < b), "{}", structure.name());
+
+            let mut lists = structure.nearest_neighbors_batched(&indices);
+            for (node, list) in lists.iter_mut().enumerate() {
+                list.retain(|&other| other != node);
+            }
+            assert_eq!(
+                pairs_to_lists(&pairs, indices.len()),
+                lists,
+                "{}",
+                structure.name()
+            );
+            assert_eq!(lists_to_pairs(&lists), pairs, "{}", structure.name());
+        }
+    }
+
+    #[test]
+    fn two_phase_update_matches_update_positions() {
+        let edges = (0..299).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let mut rng = SmallRng::seed_from_u64(11);
+        let mut random_positions = || -> Vec<DVec<2>> {
+            (0..graph.nodes.len())
+                .map(|_| DVec::from_fn(|_| rng.random_range(0.0..10.0)))
+                .collect()
+        };
+        let embedding: Embedding<2> = Embedding {
+            positions: random_positions(),
+            graph: &graph,
+        };
+        let moved = random_positions();
+        let lsh = |e| -> Box<dyn IndexClone<2> + '_> { Box::new(RandomProjectionLsh::new(e)) };
+        let structures = |embedding| {
+            data_structures(embedding, None).chain(std::iter::once(lsh(embedding.view())))
+        };
+
+        for (mut direct, mut two_phase) in structures(&embedding).zip(structures(&embedding)) {
+            direct.update_positions(&moved, Some(0.5));
+            let prepared = two_phase.prepare_update(&moved, Some(0.5));
+            two_phase.commit_update(prepared);
+
+            let indices: Vec<_> = (0..moved.len()).collect();
+            assert_eq!(
+                direct.nearest_neighbors_batched(&indices),
+                two_phase.nearest_neighbors_batched(&indices),
+                "{}",
+                direct.name()
+            );
+        }
+    }
+
+    #[test]
+    fn weight_classes_add_up_to_the_overall_statistics() {
+        // Hubs every 25 nodes spread the weights over several classes
+        let edges = (0..399)
+            .map(|i| (i, i + 1))
+            .chain((0..400).filter(|i| i % 25 != 0).map(|i| (i, i - i % 25)))
+            .collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let mut rng = SmallRng::seed_from_u64(5);
+        let embedding: Embedding<2> = Embedding {
+            positions: (0..graph.nodes.len())
+                .map(|_| DVec::from_fn(|_| rng.random_range(0.0..15.0)))
+                .collect(),
+            graph: &graph,
+        };
+
+        let (mut true_positives, mut false_positives) = (0, 0);
+        for i in 0..embedding.num_nodes() {
+            for j in 0..i {
+                let distance_squared =
+                    embedding.positions[i].distance_squared(&embedding.positions[j]);
+                if (distance_squared as f64) < (graph.weight(i) * graph.weight(j)).powi(2) {
+                    if graph.is_connected(i, j) {
+                        true_positives += 1;
+                    } else {
+                        false_positives += 1;
+                    }
+                }
+            }
+        }
+        let total_edges = (0..graph.nodes.len())
+            .map(|i| graph.neighbors(i).len())
+            .sum::<usize>()
+            / 2;
+
+        let report = embedding.graph_statistics_report(None, 0);
+        assert!(report.classes.iter().filter(|c| c.nodes > 0).count() > 1);
+        let sum = |f: fn(&crate::graph_statistics::WeightClassStatistics) -> usize| {
+            report.classes.iter().map(f).sum::<usize>()
+        };
+        assert_eq!(sum(|c| c.true_positives), 2 * true_positives);
+        assert_eq!(sum(|c| c.false_positives), 2 * false_positives);
+        assert_eq!(sum(|c| c.edges), 2 * total_edges);
+        assert_eq!(sum(|c| c.nodes), graph.nodes.len());
+        assert_eq!(
+            report.precision,
+            true_positives as f64 / (true_positives + false_positives) as f64
+        );
+        assert_eq!(report.recall, true_positives as f64 / total_edges as f64);
+        assert_eq!(
+            embedding.graph_statistics(),
+            (report.precision, report.recall)
+        );
+
+        let sampled = embedding.graph_statistics_report(Some(10), 3);
+        assert_eq!(sampled, embedding.graph_statistics_report(Some(10), 3));
+        for (class, full) in sampled.classes.iter().zip(&report.classes) {
+            assert_eq!(class.sampled_nodes, full.nodes.min(10));
+        }
+    }
+
+    #[test]
+    fn weight_class_bounds_ignore_isolated_nodes() {
+        let graph = Graph::from_adjacency(
+            vec![0.0, 1.0, 1.5, 4.0],
+            vec![vec![], vec![2], vec![1, 3], vec![2]],
+        );
+        let embedding = Embedding {
+            positions: (0..4).map(|i| DVec::new([i as f32, 0.0])).collect(),
+            graph: &graph,
+        };
+        let report = embedding.graph_statistics_report(None, 0);
+        let bounds: Vec<_> = report
+            .classes
+            .iter()
+            .map(|class| (class.min_weight, class.max_weight, class.nodes))
+            .collect();
+        assert_eq!(bounds, [(1.0, 2.0, 3), (2.0, 4.0, 0), (4.0, 8.0, 1)]);
+    }
+}
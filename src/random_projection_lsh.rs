@@ -1,9 +1,12 @@
 use rustc_hash::{FxHashMap, FxHashSet};
 
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
+    rng::{Purpose, RngSource},
+    target_trace::{TargetTrace, TraceStep},
 };
 
 #[derive(Clone)]
@@ -16,31 +19,46 @@ pub struct RandomProjectionLsh<'a, const D: usize> {
     random_hyperplanes: Vec<Vec<DVec<D>>>,
     num_tables: usize,
     num_projections: usize,
+    rng: RngSource,
+    /// Number of position updates, new hyperplanes are drawn on each
+    updates: u64,
 }
 
 impl<'a, const D: usize> RandomProjectionLsh<'a, D> {
-    pub fn new(embedding: Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         Self::new_with_params(embedding, None, None)
     }
 
     pub fn new_with_params(
-        embedding: Embedding<'a, D>,
+        embedding: EmbeddingRef<'_, 'a, D>,
         num_tables: Option<usize>,
         num_projections: Option<usize>,
+    ) -> Self {
+        Self::new_with_rng(embedding, num_tables, num_projections, RngSource::default())
+    }
+
+    /// Like [`Self::new_with_params`], with hyperplanes drawn from `rng`
+    pub fn new_with_rng(
+        embedding: EmbeddingRef<'_, 'a, D>,
+        num_tables: Option<usize>,
+        num_projections: Option<usize>,
+        rng: RngSource,
     ) -> Self {
         let num_tables = num_tables.unwrap_or_else(|| Self::default_num_tables(D));
         let num_projections = num_projections.unwrap_or_else(|| Self::default_num_projections(D));
 
         let mut lsh = Self {
-            positions: embedding.positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             hash_tables: vec![FxHashMap::default(); num_tables],
             random_hyperplanes: Vec::new(),
             num_tables,
             num_projections,
+            rng,
+            updates: 0,
         };
 
-        lsh.update_positions(&embedding.positions, None);
+        lsh.update_positions(embedding.positions, None);
         lsh
     }
 
@@ -64,7 +82,7 @@ impl<'a, const D: usize> RandomProjectionLsh<'a, D> {
 
     fn generate_hyperplanes(&self) -> Vec<Vec<DVec<D>>> {
         use rand::Rng;
-        let mut rng = rand::rng();
+        let mut rng = self.rng.stream(Purpose::Projection, &[self.updates]);
 
         (0..self.num_tables)
             .map(|_| {
@@ -85,30 +103,98 @@ impl<'a, const D: usize> RandomProjectionLsh<'a, D> {
             .collect()
     }
 
-    fn compute_hash(&self, position: &DVec<D>, table_idx: usize) -> u64 {
-        let hyperplanes = &self.random_hyperplanes[table_idx];
-        let mut hash: u64 = 0;
+    /// Hashes `positions` into fresh tables under new hyperplanes, without
+    /// touching the tables queries currently run against
+    fn build_tables(&self, positions: &[DVec<D>]) -> LshTables<D> {
+        // Generate new random hyperplanes
+        let random_hyperplanes = self.generate_hyperplanes();
 
-        for (bit_idx, hyperplane) in hyperplanes.iter().enumerate() {
-            if bit_idx >= 64 {
-                break; // u64 has only 64 bits
-            }
-            // If dot product is positive, set bit to 1
-            if position.dot(hyperplane) >= 0.0 {
-                hash |= 1u64 << bit_idx;
+        // Hash all points into all L tables
+        let mut hash_tables: Vec<FxHashMap<u64, Vec<NodeId>>> =
+            vec![FxHashMap::default(); self.num_tables];
+        for (node_id, position) in positions.iter().enumerate() {
+            for (table, hyperplanes) in hash_tables.iter_mut().zip(&random_hyperplanes) {
+                table
+                    .entry(hash(position, hyperplanes))
+                    .or_default()
+                    .push(node_id);
             }
         }
 
-        hash
+        LshTables {
+            positions: positions.to_vec(),
+            hash_tables,
+            random_hyperplanes,
+        }
+    }
+
+    fn install(&mut self, tables: LshTables<D>) {
+        self.positions = tables.positions;
+        self.hash_tables = tables.hash_tables;
+        self.random_hyperplanes = tables.random_hyperplanes;
+        self.updates += 1;
+    }
+
+    fn compute_hash(&self, position: &DVec<D>, table_idx: usize) -> u64 {
+        hash(position, &self.random_hyperplanes[table_idx])
+    }
+
+    /// Hashes of the query of `index` and of `target` in every table, with
+    /// the hyperplanes that put them into different buckets. `radius` only
+    /// scales the reported query radius, the buckets do not depend on it.
+    pub fn trace_query(&self, index: NodeId, radius: f64, target: NodeId) -> TargetTrace {
+        let mut trace = TargetTrace::new(self, index, radius * self.weight(index).powi(2), target);
+        let (pos, target_pos) = (&self.positions[index], &self.positions[target]);
+        for (table, hyperplanes) in self.random_hyperplanes.iter().enumerate() {
+            // Only the first 64 hyperplanes make it into the hash
+            let differing = hyperplanes
+                .iter()
+                .take(64)
+                .enumerate()
+                .map(|(bit, hyperplane)| (bit, pos.dot(hyperplane), target_pos.dot(hyperplane)))
+                .filter(|&(_, query, target)| (query >= 0.0) != (target >= 0.0))
+                .collect();
+            trace.steps.push(TraceStep::HashTable {
+                table,
+                query_hash: hash(pos, hyperplanes),
+                target_hash: hash(target_pos, hyperplanes),
+                differing,
+            });
+        }
+        trace.found = trace.steps.iter().any(|step| !step.prunes_target());
+        trace
     }
 }
 
+/// State of a [`RandomProjectionLsh`] for one set of positions
+struct LshTables<const D: usize> {
+    positions: Vec<DVec<D>>,
+    hash_tables: Vec<FxHashMap<u64, Vec<NodeId>>>,
+    random_hyperplanes: Vec<Vec<DVec<D>>>,
+}
+
+fn hash<const D: usize>(position: &DVec<D>, hyperplanes: &[DVec<D>]) -> u64 {
+    let mut hash: u64 = 0;
+
+    for (bit_idx, hyperplane) in hyperplanes.iter().enumerate() {
+        if bit_idx >= 64 {
+            break; // u64 has only 64 bits
+        }
+        // If dot product is positive, set bit to 1
+        if position.dot(hyperplane) >= 0.0 {
+            hash |= 1u64 << bit_idx;
+        }
+    }
+
+    hash
+}
+
 impl<'a, const D: usize> Graph for RandomProjectionLsh<'a, D> {
     fn is_connected(&self, first: NodeId, second: NodeId) -> bool {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -129,25 +215,20 @@ impl<'a, const D: usize> Position<D> for RandomProjectionLsh<'a, D> {
 
 impl<'a, const D: usize> Update<D> for RandomProjectionLsh<'a, D> {
     fn update_positions(&mut self, positions: &[DVec<D>], _: Option<f64>) {
-        self.positions = positions.to_vec();
-
-        // Generate new random hyperplanes
-        self.random_hyperplanes = self.generate_hyperplanes();
-
-        // Clear and rebuild all hash tables
-        self.hash_tables = vec![FxHashMap::default(); self.num_tables];
+        let tables = self.build_tables(positions);
+        self.install(tables);
+    }
 
-        // Hash all points into all L tables
-        for (node_id, position) in self.positions.iter().enumerate() {
-            for table_idx in 0..self.num_tables {
-                let hash_code = self.compute_hash(position, table_idx);
+    fn prepare_update(
+        &self,
+        positions: &[DVec<D>],
+        _: Option<f64>,
+    ) -> query::PreparedUpdate<DVec<D>> {
+        query::PreparedUpdate::built(self.build_tables(positions))
+    }
 
-                self.hash_tables[table_idx]
-                    .entry(hash_code)
-                    .or_default()
-                    .push(node_id);
-            }
-        }
+    fn commit_update(&mut self, prepared: query::PreparedUpdate<DVec<D>>) {
+        self.install(prepared.into_built());
     }
 }
 
@@ -188,10 +269,58 @@ impl<'a, const D: usize> SpatialIndex<D> for RandomProjectionLsh<'a, D> {
     fn implementation_string(&self) -> &'static str {
         include_str!("random_projection_lsh.rs")
     }
+
+    fn is_exact(&self) -> bool {
+        false
+    }
+
+    fn trace_query(&self, index: NodeId, radius: f64, target: NodeId) -> Option<TargetTrace> {
+        Some(self.trace_query(index, radius, target))
+    }
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for RandomProjectionLsh<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
-        Self::new(embedding.clone())
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::new(embedding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RandomProjectionLsh;
+    use crate::{Embedding, Query, dvec::DVec, graph::Graph, target_trace::TraceStep};
+
+    #[test]
+    fn trace_query_shows_the_separating_hyperplanes() {
+        let graph = Graph::from_edge_list(vec![(0, 1), (1, 2)], 4, 4).unwrap();
+        let embedding = Embedding {
+            positions: vec![
+                DVec::new([1., 2., -0.5, 0.3]),
+                DVec::new([1.1, 2., -0.5, 0.3]),
+                DVec::new([-1., -2., 0.5, -0.3]),
+            ],
+            graph: &graph,
+        };
+        let lsh = RandomProjectionLsh::new_with_params(embedding.view(), Some(3), Some(8));
+
+        // The opposite point lies on the other side of every hyperplane
+        let trace = lsh.trace_query(0, 1., 2);
+        assert!(!trace.found);
+        assert_eq!(trace.steps.len(), 3);
+        for step in &trace.steps {
+            let TraceStep::HashTable { differing, .. } = step else {
+                panic!("unexpected step {step}");
+            };
+            assert_eq!(differing.len(), 8);
+        }
+        assert_eq!(trace.pruned_by(), trace.steps.first());
+
+        let found = lsh.nearest_neighbors_owned(0, 1.);
+        for target in 0..3 {
+            assert_eq!(
+                lsh.trace_query(0, 1., target).found,
+                found.contains(&target)
+            );
+        }
     }
 }
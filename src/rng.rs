@@ -0,0 +1,148 @@
+//! The single source of randomness of the crate.
+//!
+//! Every random draw is taken from a stream derived from the user seed, the
+//! [`Purpose`] of the draw and keys such as the node id and the iteration.
+//! Streams never depend on how many draws other streams made or on the order
+//! in which threads run, so with the same seed, graph, options and spatial
+//! index, an embedding is bitwise identical for any number of threads. The
+//! guarantee holds for one build: the generator behind the streams is
+//! `SmallRng`, whose output may change between `rand` releases.
+//!
+//! Do not use the thread-local or OS-seeded generators of `rand` in this
+//! crate; take a stream from a [`RngSource`] instead. A test below enforces
+//! this.
+
+use rand::{SeedableRng, rngs::SmallRng};
+
+/// What a stream of random numbers is used for. Each purpose has its own
+/// streams, so new draws for one purpose never shift the numbers of another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Purpose {
+    /// Displacement of a node that attracts a node at the same position
+    AttractionKick = 1,
+    /// Displacement of a node that repels a node at the same position
+    RepulsionKick = 2,
+    /// Placement of stuck nodes next to their neighbours
+    Reseed = 3,
+    /// Hyperplanes of [`crate::RandomProjectionLsh`]
+    Projection = 4,
+    /// Dropped results of [`crate::LossyQuery`]
+    LossyQuery = 5,
+    /// Spread of merged nodes around their coarse node in
+    /// [`crate::graph::Graph::multi_scale_layout`]
+    Prolongation = 6,
+    /// Node pairs sampled by [`crate::girg::estimate`]
+    GirgEstimate = 7,
+    /// Sampled nodes and probe moves of [`crate::select_index`]
+    IndexSelection = 8,
+    /// Nodes queried by [`crate::query::Embedder::graph_statistics_report`]
+    StatisticsSample = 9,
+    /// Offsets of projected initial positions, see
+    /// [`crate::dim_transfer::jitter`]
+    ProjectionJitter = 10,
+}
+
+/// Deterministic source of random streams, derived from a user seed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RngSource {
+    seed: u64,
+}
+
+impl RngSource {
+    pub fn new(seed: u64) -> Self {
+        RngSource { seed }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Stream of the initial positions. It is seeded with the user seed
+    /// itself, so seeds keep producing the layouts they always did.
+    pub fn initial_positions(&self) -> SmallRng {
+        SmallRng::seed_from_u64(self.seed)
+    }
+
+    /// Independent stream for `purpose` and `keys`, e.g. `[iteration, node]`.
+    pub fn stream(&self, purpose: Purpose, keys: &[u64]) -> SmallRng {
+        let mut state = splitmix64(self.seed ^ splitmix64(purpose as u64));
+        for &key in keys {
+            state = splitmix64(state ^ key);
+        }
+        SmallRng::seed_from_u64(state)
+    }
+}
+
+/// Finaliser of the SplitMix64 generator, a bijective mix of all input bits
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::{Purpose, RngSource};
+
+    #[test]
+    fn streams_are_stable_and_independent() {
+        let source = RngSource::new(42);
+        let draw = |purpose, keys: &[u64]| source.stream(purpose, keys).random::<u64>();
+
+        assert_eq!(
+            draw(Purpose::Reseed, &[3, 7]),
+            draw(Purpose::Reseed, &[3, 7])
+        );
+        assert_ne!(
+            draw(Purpose::Reseed, &[3, 7]),
+            draw(Purpose::Reseed, &[7, 3])
+        );
+        assert_ne!(
+            draw(Purpose::Reseed, &[3, 7]),
+            draw(Purpose::Projection, &[3, 7])
+        );
+        assert_ne!(
+            draw(Purpose::Reseed, &[3, 7]),
+            RngSource::new(43)
+                .stream(Purpose::Reseed, &[3, 7])
+                .random::<u64>()
+        );
+    }
+
+    /// Unseeded generators would break the determinism guarantees of the
+    /// crate, see the module documentation.
+    #[test]
+    fn no_unseeded_randomness_in_src() {
+        let forbidden = [
+            concat!("rand", "::rng()"),
+            concat!("thread", "_rng"),
+            concat!("rand", "::random"),
+            concat!("from", "_entropy"),
+            concat!("from", "_os_rng"),
+        ];
+        let mut dirs = vec![std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src")];
+        let mut offenders = Vec::new();
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                if path.extension().is_none_or(|e| e != "rs") {
+                    continue;
+                }
+                let source = std::fs::read_to_string(&path).unwrap();
+                for pattern in forbidden {
+                    if source.contains(pattern) {
+                        offenders.push(format!("{}: {pattern}", path.display()));
+                    }
+                }
+            }
+        }
+        assert!(offenders.is_empty(), "{offenders:#?}");
+    }
+}
@@ -0,0 +1,523 @@
+//! Picks the spatial index to embed a graph with by timing the candidates on
+//! a sample of the graph, see [`select_index`] and
+//! [`WEmbedder::random_auto`].
+//!
+//! The probe replays what the embedder does in a step: an update to moved
+//! positions, then the repulsion query of every sampled node. The workload
+//! is drawn from a seed before any candidate runs, so all candidates see the
+//! same moves and the selection only varies with the measured times.
+
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::{
+    EmbeddingRef, NodeId,
+    dvec::DVec,
+    dyn_embed::EmbedIndex,
+    embedder::{EmbedderOptions, WEmbedder, initial_box, random_positions},
+    graph::{Graph, Neighbors},
+    query::{self, PreparedUpdate},
+    rng::{Purpose, RngSource},
+};
+
+/// Time [`WEmbedder::random_auto`] spends on the selection
+pub const AUTO_INDEX_BUDGET: Duration = Duration::from_millis(500);
+/// Largest move of a coordinate per probe step
+const PROBE_MOVE: f32 = 0.05;
+
+/// Workload of [`select_index_with`]
+#[derive(Clone, Debug)]
+pub struct IndexSelection {
+    /// Nodes the candidates are built on. Larger graphs are subsampled, with
+    /// the positions shrunk so that the sample is as dense as the graph.
+    pub sample_nodes: usize,
+    /// Updates, each followed by a query of every sampled node
+    pub probe_steps: usize,
+    /// Seed of the sample and the moves
+    pub seed: u64,
+    /// Names of the candidates, `None` for all of [`AUTO_INDEX_NAMES`]
+    pub candidates: Option<Vec<String>>,
+}
+
+impl Default for IndexSelection {
+    fn default() -> Self {
+        IndexSelection {
+            sample_nodes: 2000,
+            probe_steps: 10,
+            seed: 0,
+            candidates: None,
+        }
+    }
+}
+
+/// Indices of [`crate::factories`] that [`AutoIndex`] can drive the embedder
+/// with
+pub const AUTO_INDEX_NAMES: &[&str] = &[
+    "atree",
+    "dyn_atree",
+    "kiddo",
+    "nabo",
+    "vptree",
+    "grid",
+    "brute-force",
+];
+
+/// The index among [`AUTO_INDEX_NAMES`] that runs the probe workload of
+/// [`IndexSelection::default`] fastest on `graph` with the nodes at
+/// `sample_positions`, see [`select_index_with`]
+pub fn select_index<const D: usize>(
+    graph: &Graph,
+    sample_positions: &[DVec<D>],
+    budget: Duration,
+) -> String {
+    select_index_with(graph, sample_positions, budget, &IndexSelection::default())
+}
+
+/// Builds every candidate of `selection` on the sampled graph and times the
+/// probe steps. Each candidate gets an equal share of `budget` and stops
+/// after the step that exceeds it; they are ranked by their mean time per
+/// step, ties going to the earlier candidate. The builds count against the
+/// budget but are not ranked, the embedder builds its index only once.
+pub fn select_index_with<const D: usize>(
+    graph: &Graph,
+    sample_positions: &[DVec<D>],
+    budget: Duration,
+    selection: &IndexSelection,
+) -> String {
+    select_index_timed(
+        graph,
+        sample_positions,
+        budget,
+        selection,
+        &mut WallClock::default(),
+    )
+}
+
+/// Clock the candidates of [`select_index_timed`] are measured with
+pub trait ProbeClock {
+    /// Starts measuring `candidate`, before it is built
+    fn start(&mut self, candidate: &str);
+    /// Time since the last [`ProbeClock::start`], read once after the build
+    /// and once after every probe step
+    fn elapsed(&mut self) -> Duration;
+}
+
+/// [`ProbeClock`] of the wall-clock time
+#[derive(Clone, Copy, Debug)]
+pub struct WallClock(Instant);
+
+impl Default for WallClock {
+    fn default() -> Self {
+        WallClock(Instant::now())
+    }
+}
+
+impl ProbeClock for WallClock {
+    fn start(&mut self, _candidate: &str) {
+        self.0 = Instant::now();
+    }
+
+    fn elapsed(&mut self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// [`select_index_with`] with the times read from `clock`
+pub fn select_index_timed<const D: usize>(
+    graph: &Graph,
+    sample_positions: &[DVec<D>],
+    budget: Duration,
+    selection: &IndexSelection,
+    clock: &mut impl ProbeClock,
+) -> String {
+    assert_eq!(
+        sample_positions.len(),
+        graph.nodes.len(),
+        "positions do not match the graph"
+    );
+    let candidates: Vec<_> = crate::factories::<D>()
+        .into_iter()
+        .filter(|factory| {
+            factory.name().is_some_and(|name| {
+                AUTO_INDEX_NAMES.contains(&name)
+                    && crate::selected(selection.candidates.as_deref(), name)
+            })
+        })
+        .collect();
+    assert!(!candidates.is_empty(), "no candidate index selected");
+
+    let (sample, steps) = probe_workload(graph, sample_positions, selection);
+    let share = budget / candidates.len() as u32;
+    let mut best: Option<(Duration, String)> = None;
+    for factory in candidates {
+        clock.start(factory.name().unwrap());
+        let view = EmbeddingRef {
+            positions: &steps[0],
+            graph: &sample,
+        };
+        let mut index = factory.build(view);
+        let built = clock.elapsed();
+        let mut elapsed = built;
+        let mut done = 0;
+        let mut results = Vec::new();
+        for positions in &steps[1..] {
+            index.update_positions(positions, Some(PROBE_MOVE as f64));
+            for v in 0..sample.nodes.len() {
+                results.clear();
+                index.nearest_neighbors(v, 1.0, &mut results);
+            }
+            done += 1;
+            elapsed = clock.elapsed();
+            if elapsed > share {
+                break;
+            }
+        }
+        let per_step = match done {
+            0 => Duration::MAX,
+            done => elapsed.saturating_sub(built) / done,
+        };
+        if best.as_ref().is_none_or(|(time, _)| per_step < *time) {
+            best = Some((per_step, index.name()));
+        }
+    }
+    best.unwrap().1
+}
+
+/// The sampled graph and its positions before and after every probe step.
+/// Depends only on the inputs, never on the time taken.
+fn probe_workload<const D: usize>(
+    graph: &Graph,
+    positions: &[DVec<D>],
+    selection: &IndexSelection,
+) -> (Graph, Vec<Vec<DVec<D>>>) {
+    let rng = RngSource::new(selection.seed);
+    let n = graph.nodes.len();
+    let (sample, mut current) = if n > selection.sample_nodes {
+        let mut nodes: Vec<NodeId> = rand::seq::index::sample(
+            &mut rng.stream(Purpose::IndexSelection, &[0]),
+            n,
+            selection.sample_nodes,
+        )
+        .into_vec();
+        nodes.sort_unstable();
+        let shrink = (selection.sample_nodes as f32 / n as f32).powf(1.0 / D as f32);
+        let current = nodes.iter().map(|&v| positions[v] * shrink).collect();
+        (graph.induced_subgraph(&nodes), current)
+    } else {
+        (graph.clone(), positions.to_vec())
+    };
+
+    let mut steps = vec![current.clone()];
+    for step in 0..selection.probe_steps {
+        let mut moves = rng.stream(Purpose::IndexSelection, &[1, step as u64]);
+        for position in &mut current {
+            for d in 0..D {
+                position[d] += moves.random_range(-PROBE_MOVE..PROBE_MOVE);
+            }
+        }
+        steps.push(current.clone());
+    }
+    (sample, steps)
+}
+
+/// Spatial index picked at runtime, see [`WEmbedder::random_auto`]
+#[derive(Clone)]
+pub enum AutoIndex<'a, const D: usize> {
+    Atree(crate::sprk::Sprk<'a, D>),
+    DynAtree(crate::dyn_sprk::DynSprk<'a, D>),
+    Kiddo(crate::kiddo::Kiddo<'a, D>),
+    Nabo(crate::nabo::Nabo<'a, D>),
+    VpTree(crate::vptree::VPTree<'a, D>),
+    Grid(crate::grid::Grid<'a, D>),
+    BruteForce(crate::embedding::Embedding<'a, D>),
+}
+
+/// Runs `$body` with `$index` bound to the index inside `$auto`
+macro_rules! dispatch {
+    ($auto:expr, $index:ident => $body:expr) => {
+        match $auto {
+            AutoIndex::Atree($index) => $body,
+            AutoIndex::DynAtree($index) => $body,
+            AutoIndex::Kiddo($index) => $body,
+            AutoIndex::Nabo($index) => $body,
+            AutoIndex::VpTree($index) => $body,
+            AutoIndex::Grid($index) => $body,
+            AutoIndex::BruteForce($index) => $body,
+        }
+    };
+}
+
+impl<'a, const D: usize> AutoIndex<'a, D> {
+    /// Builds the index called `name`, `None` if it is not one of
+    /// [`AUTO_INDEX_NAMES`]
+    pub fn build(name: &str, embedding: EmbeddingRef<'_, 'a, D>) -> Option<Self> {
+        use query::Embedder;
+        Some(match name {
+            "atree" => AutoIndex::Atree(Embedder::new(embedding)),
+            "dyn_atree" => AutoIndex::DynAtree(Embedder::new(embedding)),
+            "kiddo" => AutoIndex::Kiddo(Embedder::new(embedding)),
+            "nabo" => AutoIndex::Nabo(Embedder::new(embedding)),
+            "vptree" => AutoIndex::VpTree(Embedder::new(embedding)),
+            "grid" => AutoIndex::Grid(Embedder::new(embedding)),
+            "brute-force" => AutoIndex::BruteForce(Embedder::new(embedding)),
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> String {
+        dispatch!(self, index => query::SpatialIndex::name(index))
+    }
+}
+
+impl<const D: usize> EmbedIndex for AutoIndex<'_, D> {
+    type Vec = DVec<D>;
+
+    fn position(&self, index: NodeId) -> &DVec<D> {
+        dispatch!(self, inner => query::Position::position(inner, index))
+    }
+
+    fn num_nodes(&self) -> usize {
+        dispatch!(self, inner => query::Position::num_nodes(inner))
+    }
+
+    fn weight(&self, index: NodeId) -> f64 {
+        dispatch!(self, inner => query::Graph::weight(inner, index))
+    }
+
+    fn is_connected(&self, first: NodeId, second: NodeId) -> bool {
+        dispatch!(self, inner => query::Graph::is_connected(inner, first, second))
+    }
+
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
+        dispatch!(self, inner => query::Graph::neighbors(inner, index))
+    }
+
+    fn update_positions(&mut self, positions: &[DVec<D>], last_delta: Option<f64>) {
+        dispatch!(self, inner => query::Update::update_positions(inner, positions, last_delta))
+    }
+
+    fn prepare_update(
+        &self,
+        positions: &[DVec<D>],
+        last_delta: Option<f64>,
+    ) -> PreparedUpdate<DVec<D>> {
+        dispatch!(self, inner => query::Update::prepare_update(inner, positions, last_delta))
+    }
+
+    fn commit_update(&mut self, prepared: PreparedUpdate<DVec<D>>) {
+        dispatch!(self, inner => query::Update::commit_update(inner, prepared))
+    }
+
+    fn repelling_nodes(&self, index: usize, result: &mut Vec<NodeId>) {
+        dispatch!(self, inner => query::Embedder::repelling_nodes(inner, index, result))
+    }
+
+    fn graph_statistics(&self) -> (f64, f64) {
+        dispatch!(self, inner => query::Embedder::graph_statistics(inner))
+    }
+
+    fn memory_usage_bytes(&self) -> usize {
+        dispatch!(self, inner => query::SpatialIndex::memory_usage_bytes(inner))
+    }
+}
+
+impl<'a, const D: usize> WEmbedder<AutoIndex<'a, D>> {
+    /// Like [`WEmbedder::random`], but with the spatial index that
+    /// [`select_index`] finds fastest for the initial positions, spending
+    /// [`AUTO_INDEX_BUDGET`] on the choice. The choice is logged to stderr.
+    pub fn random_auto(seed: u64, graph: &'a Graph, options: EmbedderOptions) -> Self {
+        let rng = RngSource::new(seed);
+        let (min, max) = initial_box::<D>(graph.nodes.len(), &options);
+        let positions = random_positions(rng, graph.nodes.len(), min, max);
+        let selection = IndexSelection {
+            seed,
+            ..IndexSelection::default()
+        };
+        let name = select_index_with(graph, &positions, AUTO_INDEX_BUDGET, &selection);
+        eprintln!("selected spatial index {name}");
+        let embedding = EmbeddingRef {
+            positions: &positions,
+            graph,
+        };
+        let index = AutoIndex::build(&name, embedding).expect("selected an unknown index");
+        WEmbedder::new(index, options).with_rng(rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        dvec::DVec,
+        dyn_embed::EmbedIndex,
+        embedder::{EmbedderOptions, WEmbedder},
+        graph::Graph,
+    };
+
+    use super::{
+        AUTO_INDEX_NAMES, AutoIndex, IndexSelection, ProbeClock, probe_workload, select_index,
+        select_index_timed,
+    };
+
+    /// A `side` x `side` grid graph with the nodes at their grid points
+    fn grid(side: usize) -> (Graph, Vec<DVec<2>>) {
+        let graph = crate::graph::grid(side);
+        let positions = (0..side * side)
+            .map(|u| DVec::new([(u % side) as f32, (u / side) as f32]))
+            .collect();
+        (graph, positions)
+    }
+
+    /// Clock on which building a candidate and every probe step take the
+    /// times given for it, and that records the steps each candidate ran
+    #[derive(Default)]
+    struct StepClock {
+        costs: Vec<(&'static str, Duration, Duration)>,
+        current: (Duration, Duration),
+        reads: u32,
+        steps: Vec<(String, u32)>,
+    }
+
+    impl StepClock {
+        /// `costs` holds the build and step time of every candidate
+        fn new(costs: &[(&'static str, u64, u64)]) -> Self {
+            let costs = costs
+                .iter()
+                .map(|&(name, build, step)| {
+                    let millis = Duration::from_millis;
+                    (name, millis(build), millis(step))
+                })
+                .collect();
+            StepClock {
+                costs,
+                ..StepClock::default()
+            }
+        }
+    }
+
+    impl ProbeClock for StepClock {
+        fn start(&mut self, candidate: &str) {
+            let &(_, build, step) = self
+                .costs
+                .iter()
+                .find(|(name, _, _)| *name == candidate)
+                .expect("candidate without costs");
+            self.current = (build, step);
+            self.reads = 0;
+            self.steps.push((candidate.to_string(), 0));
+        }
+
+        fn elapsed(&mut self) -> Duration {
+            let (build, step) = self.current;
+            let elapsed = build + step * self.reads;
+            self.steps.last_mut().unwrap().1 = self.reads;
+            self.reads += 1;
+            elapsed
+        }
+    }
+
+    /// Selection among atree, brute-force and vptree on a small grid
+    fn select(budget_millis: u64, clock: &mut StepClock) -> String {
+        let (graph, positions) = grid(3);
+        let selection = IndexSelection {
+            probe_steps: 50,
+            candidates: Some(
+                ["atree", "vptree", "brute-force"]
+                    .map(String::from)
+                    .to_vec(),
+            ),
+            ..IndexSelection::default()
+        };
+        let budget = Duration::from_millis(budget_millis);
+        select_index_timed(&graph, &positions, budget, &selection, clock)
+    }
+
+    #[test]
+    fn candidates_are_ranked_by_time_per_step() {
+        let mut clock = StepClock::new(&[("atree", 0, 3), ("brute-force", 0, 2), ("vptree", 0, 1)]);
+        assert_eq!(select(300, &mut clock), "vptree");
+
+        let mut clock = StepClock::new(&[("atree", 0, 2), ("brute-force", 0, 1), ("vptree", 0, 3)]);
+        assert_eq!(select(300, &mut clock), "brute-force");
+    }
+
+    #[test]
+    fn builds_are_not_ranked_and_ties_go_to_the_earlier_candidate() {
+        let mut clock =
+            StepClock::new(&[("atree", 50, 2), ("brute-force", 0, 3), ("vptree", 0, 2)]);
+        assert_eq!(select(300, &mut clock), "atree");
+        let order: Vec<_> = clock.steps.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(order, ["atree", "brute-force", "vptree"]);
+    }
+
+    #[test]
+    fn candidates_stop_after_the_step_exceeding_their_share() {
+        // Every candidate gets 10 ms
+        let mut clock =
+            StepClock::new(&[("atree", 0, 4), ("brute-force", 20, 1), ("vptree", 0, 1)]);
+        assert_eq!(select(30, &mut clock), "brute-force");
+        let steps: Vec<_> = clock.steps.iter().map(|&(_, steps)| steps).collect();
+        // A build that exceeds the share still gets a step to be ranked by
+        assert_eq!(steps, [3, 1, 11]);
+
+        let mut clock = StepClock::new(&[("atree", 0, 1), ("brute-force", 0, 1), ("vptree", 0, 1)]);
+        select(3000, &mut clock);
+        assert!(clock.steps.iter().all(|&(_, steps)| steps == 50));
+    }
+
+    /// Timing smoke test: the only test on the wall clock, with a bound loose
+    /// enough for loaded machines
+    #[test]
+    fn selection_roughly_stays_within_budget() {
+        let (graph, positions) = grid(40);
+        let budget = Duration::from_millis(200);
+        let start = Instant::now();
+        let name = select_index(&graph, &positions, budget);
+        let elapsed = start.elapsed();
+
+        assert!(AUTO_INDEX_NAMES.contains(&name.as_str()));
+        let registered = crate::factories::<2>();
+        assert!(registered.iter().any(|f| f.name() == Some(name.as_str())));
+        // Every candidate finishes the step it is in when its share ends
+        assert!(elapsed < 5 * budget, "selection took {elapsed:?}");
+    }
+
+    #[test]
+    fn probe_workload_depends_only_on_the_seed() {
+        let (graph, positions) = grid(20);
+        let selection = IndexSelection {
+            sample_nodes: 100,
+            probe_steps: 3,
+            ..IndexSelection::default()
+        };
+        let (sample, steps) = probe_workload(&graph, &positions, &selection);
+        let (again, same_steps) = probe_workload(&graph, &positions, &selection);
+        assert_eq!(sample.nodes.len(), 100);
+        assert_eq!(sample.original_ids, again.original_ids);
+        assert_eq!(steps, same_steps);
+        assert_eq!(steps.len(), 4);
+
+        let other_seed = IndexSelection {
+            seed: 1,
+            ..selection
+        };
+        assert_ne!(probe_workload(&graph, &positions, &other_seed).1, steps);
+    }
+
+    #[test]
+    fn random_auto_starts_like_random_and_converges() {
+        let edges = (1..31).map(|i| ((i - 1) / 2, i)).collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let mut auto: WEmbedder<AutoIndex<2>> =
+            WEmbedder::random_auto(3, &graph, EmbedderOptions::default());
+        assert!(AUTO_INDEX_NAMES.contains(&auto.spatial_index.name().as_str()));
+        let plain: WEmbedder<crate::Embedding<2>> =
+            WEmbedder::random(3, &graph, EmbedderOptions::default());
+        assert_eq!(auto.positions(), plain.positions());
+
+        auto.embed();
+        assert_eq!(auto.spatial_index.graph_statistics(), (1.0, 1.0));
+    }
+}
@@ -2,8 +2,9 @@ use sif_kdtree::KdTree;
 use sif_kdtree::Object;
 
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 
@@ -25,7 +26,7 @@ pub struct SIF<'a, const D: usize> {
 }
 
 impl<'a, const D: usize> SIF<'a, D> {
-    pub fn new(embedding: Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let index = KdTree::new(
             embedding
                 .positions
@@ -40,7 +41,7 @@ impl<'a, const D: usize> SIF<'a, D> {
             kdtree: index,
             max_weights: Vec::new(),
         };
-        tree.update_positions(&embedding.positions, None);
+        tree.update_positions(embedding.positions, None);
         tree
     }
 }
@@ -67,7 +68,7 @@ impl<'a, const D: usize> Graph for SIF<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -131,7 +132,7 @@ impl<'a, const D: usize> SpatialIndex<D> for SIF<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for SIF<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
-        Self::new(embedding.clone())
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::new(embedding)
     }
 }
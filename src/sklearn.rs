@@ -3,8 +3,9 @@ use std::marker::PhantomData;
 use sklearn::{SklearnBallTreeIndex, SklearnKDTreeIndex};
 
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 
@@ -18,22 +19,22 @@ pub struct SklearnKDTree<'a, const D: usize> {
 
 impl<'a, const D: usize> Clone for SklearnKDTree<'a, D> {
     fn clone(&self) -> Self {
-        Self::new(&Embedding {
-            positions: self.positions.clone(),
+        Self::new(EmbeddingRef {
+            positions: &self.positions,
             graph: self.graph,
         })
     }
 }
 
 impl<'a, const D: usize> SklearnKDTree<'a, D> {
-    pub fn new(embedding: &Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let mut wrapper = Self {
-            positions: embedding.positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             index: None,
             _phantom: PhantomData,
         };
-        wrapper.update_positions(&embedding.positions, None);
+        wrapper.update_positions(embedding.positions, None);
         wrapper
     }
 
@@ -65,7 +66,7 @@ impl<'a, const D: usize> Graph for SklearnKDTree<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -135,7 +136,7 @@ impl<'a, const D: usize> SpatialIndex<D> for SklearnKDTree<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for SklearnKDTree<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         Self::new(embedding)
     }
 }
@@ -154,22 +155,22 @@ pub struct SklearnBallTree<'a, const D: usize> {
 
 impl<'a, const D: usize> Clone for SklearnBallTree<'a, D> {
     fn clone(&self) -> Self {
-        Self::new(&Embedding {
-            positions: self.positions.clone(),
+        Self::new(EmbeddingRef {
+            positions: &self.positions,
             graph: self.graph,
         })
     }
 }
 
 impl<'a, const D: usize> SklearnBallTree<'a, D> {
-    pub fn new(embedding: &Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let mut wrapper = Self {
-            positions: embedding.positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             index: None,
             _phantom: PhantomData,
         };
-        wrapper.update_positions(&embedding.positions, None);
+        wrapper.update_positions(embedding.positions, None);
         wrapper
     }
 
@@ -201,7 +202,7 @@ impl<'a, const D: usize> Graph for SklearnBallTree<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -271,7 +272,7 @@ impl<'a, const D: usize> SpatialIndex<D> for SklearnBallTree<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for SklearnBallTree<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         Self::new(embedding)
     }
 }
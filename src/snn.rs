@@ -1,12 +1,26 @@
 use crate::{
-    Embedding, NodeId,
+    EmbeddingRef, NodeId,
     dvec::DVec,
-    query::{self, SpatialIndex, Update},
+    graph::Neighbors,
+    pca::Pca,
+    query::{self, Graph as _, SpatialIndex, Update},
 };
 use sprk::simd::PDVec;
-use sprk::svd::Svd;
 
 const W: usize = 8;
+/// Slack in radians on the angle window of cosine sorted queries, covering the
+/// rounding of `acos` near ±1
+const ANGLE_TOLERANCE: f32 = 1e-3;
+
+/// Order of the points in an [`Snn`] index
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SortKey {
+    /// Projection onto the principal axis
+    Projection,
+    /// Angle between the principal axis and the direction from the mean to
+    /// the point, i.e. the cosine distance to the axis
+    Cosine,
+}
 
 #[derive(Clone)]
 pub struct Snn<'a, const D: usize> {
@@ -14,31 +28,96 @@ pub struct Snn<'a, const D: usize> {
     pub graph: &'a crate::graph::Graph,
     /// PDVecs sorted by principal-axis projection, storing original positions and IDs
     pdvecs: Vec<PDVec<D, W, f32, u32>>,
-    /// Min principal-axis projection per PDVec group
+    /// Min sort key per PDVec group
     group_min: Vec<f32>,
-    /// SVD for extracting principal axis
-    svd: Svd<D, f32>,
+    sort_key: SortKey,
+    /// PCA for extracting principal axis
+    pca: Pca<D>,
     /// Principal axis (first singular vector)
     principal_axis: [f32; D],
     /// Mean of all positions
     mean: [f32; D],
+    /// Node ids in sort key order, the starting point of the next sort
+    order: Vec<NodeId>,
+    /// `order` before the last update
+    previous_order: Vec<NodeId>,
+    /// Largest node weight, bounding the ball of the neighbors of a node
+    max_weight: f64,
 }
 
 impl<'a, const D: usize> Snn<'a, D> {
-    pub fn new(embedding: &Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::with_sort_key(embedding, SortKey::Projection)
+    }
+
+    /// Sorts the points by the cosine distance between the principal axis and
+    /// their direction from the mean instead of by their projection onto it.
+    ///
+    /// For D > 8 the first principal component carries little of the
+    /// distance, so the projection windows of queries span most points. A
+    /// query of radius `r` at distance `ρ > r` from the mean only has to scan
+    /// the points whose angle lies within `asin(r / ρ)` of its own.
+    pub fn with_cosine_sort(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::with_sort_key(embedding, SortKey::Cosine)
+    }
+
+    fn with_sort_key(embedding: EmbeddingRef<'_, 'a, D>, sort_key: SortKey) -> Self {
         let mut snn = Self {
-            positions: embedding.positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
             pdvecs: Vec::new(),
             group_min: Vec::new(),
-            svd: Svd::new(),
+            sort_key,
+            pca: Pca::new(),
             principal_axis: [0.0; D],
             mean: [0.0; D],
+            order: Vec::new(),
+            previous_order: Vec::new(),
+            max_weight: 0.0,
         };
-        snn.update_positions(&embedding.positions, None);
+        snn.update_positions(embedding.positions, None);
         snn
     }
 
+    /// Projection of `pos - mean` onto the principal axis and its length
+    fn project(&self, pos: &[f32; D]) -> (f32, f32) {
+        let (mut proj, mut norm_squared) = (0.0f32, 0.0f32);
+        for ((x, mean), axis) in pos.iter().zip(&self.mean).zip(&self.principal_axis) {
+            let centered = x - mean;
+            proj += centered * axis;
+            norm_squared += centered * centered;
+        }
+        (proj, norm_squared.sqrt())
+    }
+
+    fn key(&self, pos: &[f32; D]) -> f32 {
+        let (proj, norm) = self.project(pos);
+        match self.sort_key {
+            SortKey::Projection => proj,
+            SortKey::Cosine if norm > 0.0 => (proj / norm).clamp(-1.0, 1.0).acos(),
+            SortKey::Cosine => 0.0,
+        }
+    }
+
+    /// Range of sort keys of the points within `radius` of `pos`
+    fn key_window(&self, pos: &[f32; D], radius: f32) -> (f32, f32) {
+        match self.sort_key {
+            SortKey::Projection => {
+                let proj = self.key(pos);
+                (proj - radius, proj + radius)
+            }
+            SortKey::Cosine => {
+                let (_, norm) = self.project(pos);
+                if norm <= radius {
+                    return (f32::NEG_INFINITY, f32::INFINITY);
+                }
+                let angle = self.key(pos);
+                let spread = (radius / norm).asin() + ANGLE_TOLERANCE;
+                (angle - spread, angle + spread)
+            }
+        }
+    }
+
     fn build_index(&mut self) {
         let n = self.positions.len();
         if n == 0 {
@@ -47,55 +126,34 @@ impl<'a, const D: usize> Snn<'a, D> {
 
         let raw_positions: Vec<[f32; D]> = self.positions.iter().map(|p| p.components).collect();
 
-        // Compute mean
-        self.mean = [0.0; D];
-        let inv_n = 1.0 / n as f32;
-        for pos in &raw_positions {
-            for j in 0..D {
-                self.mean[j] += pos[j];
-            }
-        }
-        for m in &mut self.mean {
-            *m *= inv_n;
-        }
-
         // Compute SVD to find principal axis
-        self.svd.compute_svd(&raw_positions);
-
-        // Extract principal axis by probing SVD projection
-        self.principal_axis = {
-            let origin_proj = self.svd.project(&self.mean);
-            let mut axis = [0.0f32; D];
-            for j in 0..D {
-                let mut shifted = self.mean;
-                shifted[j] += 1.0;
-                let proj = self.svd.project(&shifted);
-                axis[j] = proj[0] - origin_proj[0];
-            }
-            let norm: f32 = axis.iter().map(|x| x * x).sum::<f32>().sqrt();
-            if norm > 0.0 {
-                for a in &mut axis {
-                    *a /= norm;
-                }
-            }
-            axis
-        };
+        self.pca.fit(&raw_positions);
+        self.mean = self.pca.mean;
+        self.principal_axis = self.pca.axis(0);
 
-        // Project all points onto principal axis
-        let mut projections: Vec<(f32, usize)> = raw_positions
-            .iter()
-            .enumerate()
-            .map(|(i, pos)| {
-                let proj: f32 = (0..D)
-                    .map(|j| (pos[j] - self.mean[j]) * self.principal_axis[j])
-                    .sum();
-                (proj, i)
-            })
-            .collect();
+        // Start from the previous order. Between iterations it is nearly
+        // sorted, and the stable sort, which merges presorted runs, then
+        // takes close to linear time.
+        let mut projections: Vec<(f32, usize)> = if self.order.len() == n {
+            self.order
+                .iter()
+                .map(|&i| (self.key(&raw_positions[i]), i))
+                .collect()
+        } else {
+            raw_positions
+                .iter()
+                .enumerate()
+                .map(|(i, pos)| (self.key(pos), i))
+                .collect()
+        };
 
         projections.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
 
-        // Build PDVecs grouped by W, with per-group min projection
+        std::mem::swap(&mut self.order, &mut self.previous_order);
+        self.order.clear();
+        self.order.extend(projections.iter().map(|&(_, i)| i));
+
+        // Build PDVecs grouped by W, with per-group min sort key
         let num_groups = n.div_ceil(W);
         self.pdvecs = Vec::with_capacity(num_groups);
         self.group_min = Vec::with_capacity(num_groups);
@@ -118,7 +176,7 @@ impl<const D: usize> crate::query::Graph for Snn<'_, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -140,8 +198,21 @@ impl<const D: usize> query::Position<D> for Snn<'_, D> {
 impl<const D: usize> query::Update<D> for Snn<'_, D> {
     fn update_positions(&mut self, positions: &[DVec<D>], _: Option<f64>) {
         self.positions = positions.to_vec();
+        self.max_weight = (0..positions.len())
+            .map(|i| self.weight(i))
+            .fold(0.0, f64::max);
         self.build_index();
     }
+
+    /// Nodes that moved in the sort order, all of them after the first build
+    fn moved_in_last_update(&self) -> Option<usize> {
+        Some(query::moved_nodes(&self.previous_order, &self.order))
+    }
+
+    fn discard_warm_start(&mut self) {
+        self.order.clear();
+        self.previous_order.clear();
+    }
 }
 
 impl<const D: usize> crate::Query<D> for Snn<'_, D> {
@@ -153,22 +224,19 @@ impl<const D: usize> crate::Query<D> for Snn<'_, D> {
         let radius_f32 = radius as f32;
         let radius_sq_half = radius_f32 * radius_f32 * 0.5 + 1e-2;
 
-        // Project query onto principal axis
-        let sv_q: f32 = (0..D)
-            .map(|j| (pos.components[j] - self.mean[j]) * self.principal_axis[j])
-            .sum();
+        let (min_key, max_key) = self.key_window(&pos.components, radius_f32);
 
         // Binary search on group_min:
-        // - Start one group before the first whose min > sv_q - radius
+        // - Start one group before the first whose min > min_key
         //   (that prior group could still contain points within range)
-        // - End at the first group whose min > sv_q + radius
+        // - End at the first group whose min > max_key
         let left = self
             .group_min
-            .partition_point(|&min_p| min_p <= sv_q - radius_f32)
+            .partition_point(|&min_p| min_p <= min_key)
             .saturating_sub(1);
         let right = self
             .group_min
-            .partition_point(|&min_p| min_p <= sv_q + radius_f32)
+            .partition_point(|&min_p| min_p <= max_key)
             .min(self.pdvecs.len());
 
         // Precompute ||q||²/2 for dist_half_squared
@@ -186,11 +254,33 @@ impl<const D: usize> crate::Query<D> for Snn<'_, D> {
             }
         }
     }
+
+    /// Scans the window of the ball of radius `radius * w * max_weight`,
+    /// which holds every node within `radius * w * w_v`, and keeps those.
+    fn nearest_neighbors(&self, index: usize, radius: f64, results: &mut Vec<NodeId>) {
+        let start = results.len();
+        let weight = self.weight(index);
+        let pos = self.positions[index];
+        self.query_radius(pos, radius * weight * self.max_weight, results);
+        let mut kept = start;
+        for i in start..results.len() {
+            let j = results[i];
+            let bound = radius * weight * self.weight(j);
+            if pos.distance_squared(&self.positions[j]) as f64 <= bound * bound {
+                results[kept] = j;
+                kept += 1;
+            }
+        }
+        results.truncate(kept);
+    }
 }
 
 impl<const D: usize> SpatialIndex<D> for Snn<'_, D> {
     fn name(&self) -> String {
-        String::from("snn")
+        match self.sort_key {
+            SortKey::Projection => String::from("snn"),
+            SortKey::Cosine => String::from("snn_cosine"),
+        }
     }
     fn implementation_string(&self) -> &'static str {
         include_str!("snn.rs")
@@ -198,7 +288,131 @@ impl<const D: usize> SpatialIndex<D> for Snn<'_, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for Snn<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         Self::new(embedding)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Snn;
+    use crate::{Embedding, Query, dvec::DVec, graph::Graph, query::Update};
+    use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn cosine_sort_finds_all_neighbors() {
+        const D: usize = 12;
+        let edges = (0..399).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, D, D).unwrap();
+        let mut rng = SmallRng::seed_from_u64(3);
+        let positions: Vec<DVec<D>> = (0..400)
+            .map(|_| DVec::new(std::array::from_fn(|_| rng.random_range(-4.0..4.0))))
+            .collect();
+        let embedding = Embedding {
+            positions,
+            graph: &graph,
+        };
+        let cosine = Snn::with_cosine_sort(embedding.view());
+
+        for radius in [0.5, 2.0, 5.0, 20.0] {
+            for (i, &pos) in embedding.positions.iter().enumerate() {
+                let mut found = Vec::new();
+                cosine.query_radius(pos, radius, &mut found);
+                for (j, other) in embedding.positions.iter().enumerate() {
+                    if pos.distance(other) as f64 <= radius {
+                        assert!(found.contains(&j), "node {j} near {i}, radius {radius}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn neighbors_match_brute_force_with_spread_weights() {
+        const D: usize = 2;
+        let mut rng = SmallRng::seed_from_u64(5);
+        // Weights from 0.1 to 10
+        let weights = (0..500)
+            .map(|_| 10f64.powf(rng.random_range(-1.0..1.0)))
+            .collect();
+        let neighbors = (0..500)
+            .map(|i: usize| {
+                [i.checked_sub(1), (i < 499).then_some(i + 1)]
+                    .into_iter()
+                    .flatten()
+                    .collect()
+            })
+            .collect();
+        let graph = Graph::from_adjacency(weights, neighbors);
+        let positions: Vec<DVec<D>> = (0..500)
+            .map(|_| DVec::new(std::array::from_fn(|_| rng.random_range(0.0..20.0))))
+            .collect();
+        let embedding = Embedding {
+            positions,
+            graph: &graph,
+        };
+        let snn = Snn::new(embedding.view());
+
+        for radius in [0.5, 1.0] {
+            for i in 0..500 {
+                let mut found = snn.nearest_neighbors_owned(i, radius);
+                found.sort_unstable();
+                let expected: Vec<_> = (0..500)
+                    .filter(|&j| {
+                        let distance = embedding.positions[i].distance(&embedding.positions[j]);
+                        distance as f64 / (graph.nodes[i].weight * graph.nodes[j].weight) <= radius
+                    })
+                    .collect();
+                assert_eq!(found, expected, "node {i} at radius {radius}");
+            }
+        }
+    }
+
+    #[test]
+    fn warm_started_updates_match_a_fresh_build() {
+        const D: usize = 4;
+        let edges = (0..499).map(|i| (i, i + 1)).collect();
+        let graph = Graph::from_edge_list(edges, D, D).unwrap();
+        let mut rng = SmallRng::seed_from_u64(8);
+        let mut positions: Vec<DVec<D>> = (0..500)
+            .map(|_| DVec::new(std::array::from_fn(|_| rng.random_range(-4.0..4.0))))
+            .collect();
+        let mut snn = Snn::new(
+            Embedding {
+                positions: positions.clone(),
+                graph: &graph,
+            }
+            .view(),
+        );
+        assert_eq!(snn.moved_in_last_update(), Some(500));
+        snn.update_positions(&positions, None);
+        assert_eq!(snn.moved_in_last_update(), Some(0));
+
+        for step in [0.01, 0.01, 0.5, 0.01] {
+            for pos in &mut positions {
+                *pos += DVec::new(std::array::from_fn(|_| rng.random_range(-step..step)));
+            }
+            snn.update_positions(&positions, None);
+            let embedding = Embedding {
+                positions: positions.clone(),
+                graph: &graph,
+            };
+            let fresh = Snn::new(embedding.view());
+            assert_eq!(snn.order, fresh.order);
+            assert_eq!(snn.group_min, fresh.group_min);
+            assert!(snn.moved_in_last_update() < Some(500), "step {step}");
+            for &pos in positions.iter().step_by(25) {
+                let (mut warm, mut scratch) = (Vec::new(), Vec::new());
+                snn.query_radius(pos, 1.0, &mut warm);
+                fresh.query_radius(pos, 1.0, &mut scratch);
+                warm.sort_unstable();
+                scratch.sort_unstable();
+                assert_eq!(warm, scratch);
+            }
+        }
+
+        snn.discard_warm_start();
+        snn.update_positions(&positions, None);
+        assert_eq!(snn.moved_in_last_update(), Some(500));
+    }
+}
@@ -1,6 +1,9 @@
+use rayon::prelude::*;
+
 use crate::{
-    Embedding, NodeId,
+    EmbeddingRef, NodeId,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, SpatialIndex},
 };
 
@@ -11,6 +14,7 @@ pub struct Sprk<'a, const D: usize> {
     pub tree: sprk::Sprk<D>,
     pub positions: Vec<DVec<D>>,
     pub graph: &'a crate::graph::Graph,
+    builds: usize,
 }
 
 impl<const D: usize> crate::query::Graph for Sprk<'_, D> {
@@ -18,7 +22,7 @@ impl<const D: usize> crate::query::Graph for Sprk<'_, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -49,6 +53,22 @@ impl<const D: usize> query::Update<D> for Sprk<'_, D> {
 
         let raw_positions: Vec<[f32; D]> = positions.iter().map(|p| p.components).collect();
         self.tree.update(&raw_positions);
+        self.builds += 1;
+    }
+
+    /// Builds a fresh tree, the current one stays queryable until the commit
+    fn prepare_update(
+        &self,
+        positions: &[DVec<D>],
+        _: Option<f64>,
+    ) -> query::PreparedUpdate<DVec<D>> {
+        let raw_positions: Vec<[f32; D]> = positions.iter().map(|p| p.components).collect();
+        query::PreparedUpdate::built((positions.to_vec(), sprk::Sprk::<D>::new(&raw_positions)))
+    }
+
+    fn commit_update(&mut self, prepared: query::PreparedUpdate<DVec<D>>) {
+        (self.positions, self.tree) = prepared.into_built();
+        self.builds += 1;
     }
 }
 
@@ -58,6 +78,28 @@ impl<const D: usize> crate::Query<D> for Sprk<'_, D> {
         self.tree
             .query_radius(&pos.components, radius as f32, results);
     }
+
+    /// A node queries the radius scaled by its own weight squared, so the
+    /// heavier end of a pair finds it whenever the lighter end does. Keeping
+    /// only the pairs found from the heavier end, ties broken by id, yields
+    /// each pair once without deduplicating.
+    fn nearest_neighbor_pairs(&self, radius: f64) -> Vec<(NodeId, NodeId)> {
+        let lighter = |u: NodeId, v: NodeId| {
+            let (w_u, w_v) = (self.graph.nodes[u].weight, self.graph.nodes[v].weight);
+            w_u < w_v || (w_u == w_v && u < v)
+        };
+        let mut pairs: Vec<_> = (0..self.positions.len())
+            .into_par_iter()
+            .flat_map_iter(|v| {
+                self.nearest_neighbors_owned(v, radius)
+                    .into_iter()
+                    .filter(move |&u| lighter(u, v))
+                    .map(move |u| (u.min(v), u.max(v)))
+            })
+            .collect();
+        pairs.par_sort_unstable();
+        pairs
+    }
 }
 
 impl<const D: usize> SpatialIndex<D> for Sprk<'_, D> {
@@ -67,22 +109,81 @@ impl<const D: usize> SpatialIndex<D> for Sprk<'_, D> {
     fn implementation_string(&self) -> &'static str {
         concat!(include_str!("sprk.rs"))
     }
+    fn memory_usage_bytes(&self) -> usize {
+        self.positions.capacity() * size_of::<DVec<D>>() + tree_bytes(self.tree.len(), D)
+    }
+}
+
+/// Estimated heap usage of a tree over `len` positions in `dim`
+/// dimensions: its copy of the positions, the sorted positions with their
+/// ids and the ids of the input order. The inner nodes and leaf tables are
+/// a small fraction of that and not counted.
+pub(crate) fn tree_bytes(len: usize, dim: usize) -> usize {
+    len * ((2 * dim + 3) * size_of::<f32>() + size_of::<usize>())
 }
 
 impl<'a, const D: usize> Sprk<'a, D> {
-    pub fn new(embedding: &Embedding<'a, D>) -> Self {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
         let raw_positions: Vec<[f32; D]> =
             embedding.positions.iter().map(|p| p.components).collect();
         Sprk {
             tree: sprk::Sprk::new(&raw_positions),
-            positions: embedding.positions.clone(),
+            positions: embedding.positions.to_vec(),
             graph: embedding.graph,
+            builds: 1,
         }
     }
+
+    /// Number of times the tree was built or updated. Clones keep the count
+    /// of the original, as they copy its tree instead of building one.
+    pub fn builds(&self) -> usize {
+        self.builds
+    }
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for Sprk<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
         Self::new(embedding)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+    use super::Sprk;
+    use crate::{
+        Query,
+        dvec::DVec,
+        graph::Graph,
+        query::{IndexClone, Update},
+    };
+
+    #[test]
+    fn clone_keeps_the_built_tree() {
+        let graph = Graph::from_edge_list((0..999).map(|i| (i, i + 1)).collect(), 2, 2).unwrap();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let positions: Vec<DVec<2>> = (0..graph.nodes.len())
+            .map(|_| DVec::from_fn(|_| rng.random_range(0.0..30.0)))
+            .collect();
+        let mut tree = Sprk::new(crate::EmbeddingRef {
+            positions: &positions,
+            graph: &graph,
+        });
+        tree.update_positions(&positions, None);
+        assert_eq!(tree.builds(), 2);
+
+        let clone = tree.clone();
+        assert_eq!(clone.builds(), 2);
+        let boxed = tree.clone_box();
+        let (mut expected, mut cloned, mut from_box) = (Vec::new(), Vec::new(), Vec::new());
+        for node in 0..positions.len() {
+            tree.nearest_neighbors(node, 1., &mut expected);
+            clone.nearest_neighbors(node, 1., &mut cloned);
+            boxed.nearest_neighbors(node, 1., &mut from_box);
+        }
+        assert!(!expected.is_empty());
+        assert_eq!(cloned, expected);
+        assert_eq!(from_box, expected);
+    }
+}
@@ -0,0 +1,204 @@
+use rayon::prelude::*;
+
+use crate::{
+    EmbeddingRef, NodeId,
+    dvec::DVec,
+    graph::Neighbors,
+    query::{self, Graph, Position, SpatialIndex},
+};
+
+/// Largest radius answered from the precomputed lists by default, the
+/// largest radius of the radius sweep benchmark
+pub const DEFAULT_MAX_RADIUS: f64 = 4.0;
+
+/// Index for embeddings that no longer move, like the final embedding in the
+/// query benchmarks. Construction sorts the nodes around each node by their
+/// distance relative to the weights, so a neighbour query up to
+/// `max_radius` is a prefix of that list. Every position update rebuilds
+/// the lists from scratch.
+#[derive(Clone)]
+pub struct StaticIndex<'a, const D: usize> {
+    pub positions: Vec<DVec<D>>,
+    pub graph: &'a crate::graph::Graph,
+    max_radius: f64,
+    max_weight: f64,
+    /// Answers radius queries and neighbour queries beyond `max_radius`
+    tree: sprk::Sprk<D>,
+    /// Per node the nodes `j` with `distance / (w_i * w_j) <= max_radius`,
+    /// including the node itself, ordered by that relative distance
+    candidates: Vec<Vec<(f32, NodeId)>>,
+}
+
+impl<'a, const D: usize> StaticIndex<'a, D> {
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::with_max_radius(embedding, DEFAULT_MAX_RADIUS)
+    }
+
+    pub fn with_max_radius(embedding: EmbeddingRef<'_, 'a, D>, max_radius: f64) -> Self {
+        let mut index = StaticIndex {
+            positions: Vec::new(),
+            graph: embedding.graph,
+            max_radius,
+            max_weight: 0.0,
+            tree: sprk::Sprk::new(&[]),
+            candidates: Vec::new(),
+        };
+        query::Update::update_positions(&mut index, embedding.positions, None);
+        index
+    }
+
+    fn relative_distance(&self, first: NodeId, second: NodeId) -> f32 {
+        let distance = self.positions[first].distance(&self.positions[second]) as f64;
+        (distance / (self.weight(first) * self.weight(second))) as f32
+    }
+
+    /// Sorts the candidates of every node. Each pair is found from its
+    /// heavier node, whose ball of radius `max_radius * w^2` contains all
+    /// lighter nodes in range.
+    fn build_candidates(&mut self) {
+        let heavier = |i: NodeId, j: NodeId| (self.weight(i), i) >= (self.weight(j), j);
+        let pairs: Vec<Vec<(f32, NodeId)>> = (0..self.positions.len())
+            .into_par_iter()
+            .map(|i| {
+                let radius = self.max_radius * self.weight(i).powi(2);
+                let mut results = Vec::new();
+                self.tree
+                    .query_radius(&self.positions[i].components, radius as f32, &mut results);
+                results
+                    .into_iter()
+                    .filter(|&j| heavier(i, j))
+                    .map(|j| (self.relative_distance(i, j), j))
+                    .filter(|&(distance, _)| distance as f64 <= self.max_radius)
+                    .collect()
+            })
+            .collect();
+
+        let mut candidates = vec![Vec::new(); self.positions.len()];
+        for (i, found) in pairs.into_iter().enumerate() {
+            for (distance, j) in found {
+                candidates[i].push((distance, j));
+                if i != j {
+                    candidates[j].push((distance, i));
+                }
+            }
+        }
+        candidates
+            .par_iter_mut()
+            .for_each(|list| list.sort_unstable_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1))));
+        self.candidates = candidates;
+    }
+}
+
+impl<const D: usize> Graph for StaticIndex<'_, D> {
+    fn is_connected(&self, first: NodeId, second: NodeId) -> bool {
+        self.graph.is_connected(first, second)
+    }
+
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
+        self.graph.neighbors(index)
+    }
+
+    fn weight(&self, index: NodeId) -> f64 {
+        self.graph.weight(index)
+    }
+}
+
+impl<const D: usize> Position<D> for StaticIndex<'_, D> {
+    fn position(&self, index: NodeId) -> &DVec<D> {
+        &self.positions[index]
+    }
+
+    fn num_nodes(&self) -> usize {
+        self.positions.len()
+    }
+}
+
+impl<const D: usize> query::Update<D> for StaticIndex<'_, D> {
+    fn update_positions(&mut self, positions: &[DVec<D>], _: Option<f64>) {
+        self.positions = positions.to_vec();
+        self.max_weight = (0..positions.len())
+            .map(|i| self.weight(i))
+            .fold(0.0, f64::max);
+        let raw_positions: Vec<[f32; D]> = positions.iter().map(|p| p.components).collect();
+        self.tree = sprk::Sprk::new(&raw_positions);
+        self.build_candidates();
+    }
+}
+
+impl<const D: usize> crate::Query<D> for StaticIndex<'_, D> {
+    fn query_radius(&self, pos: DVec<D>, radius: f64, results: &mut Vec<NodeId>) {
+        self.tree
+            .query_radius(&pos.components, radius as f32, results);
+    }
+
+    fn nearest_neighbors(&self, index: usize, radius: f64, results: &mut Vec<NodeId>) {
+        if radius <= self.max_radius {
+            let candidates = &self.candidates[index];
+            let end = candidates.partition_point(|&(distance, _)| distance as f64 <= radius);
+            results.extend(candidates[..end].iter().map(|&(_, j)| j));
+            return;
+        }
+        let start = results.len();
+        let ball = radius * self.weight(index) * self.max_weight;
+        self.query_radius(self.positions[index], ball, results);
+        let mut kept = start;
+        for i in start..results.len() {
+            let j = results[i];
+            if self.relative_distance(index, j) as f64 <= radius {
+                results[kept] = j;
+                kept += 1;
+            }
+        }
+        results.truncate(kept);
+    }
+}
+
+impl<const D: usize> SpatialIndex<D> for StaticIndex<'_, D> {
+    fn name(&self) -> String {
+        String::from("static")
+    }
+    fn implementation_string(&self) -> &'static str {
+        include_str!("static_index.rs")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StaticIndex;
+    use crate::{Embedding, Query, graph::Graph, query::Graph as _};
+    use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+    #[test]
+    fn neighbors_match_brute_force_below_and_above_max_radius() {
+        let edges = (0..499)
+            .map(|i| (i, i + 1))
+            .chain([(0, 250), (0, 400)])
+            .collect();
+        let graph = Graph::from_edge_list(edges, 2, 2).unwrap();
+        let mut rng = SmallRng::seed_from_u64(1);
+        let positions = (0..500)
+            .map(|_| {
+                crate::dvec::DVec::new([rng.random_range(0.0..20.0), rng.random_range(0.0..20.0)])
+            })
+            .collect();
+        let embedding = Embedding {
+            positions,
+            graph: &graph,
+        };
+        let index = StaticIndex::with_max_radius(embedding.view(), 1.5);
+
+        for radius in [0.5, 1.0, 1.5, 3.0] {
+            for i in 0..500 {
+                let mut found = index.nearest_neighbors_owned(i, radius);
+                found.sort_unstable();
+                let expected: Vec<_> = (0..500)
+                    .filter(|&j| {
+                        let distance = embedding.positions[i].distance(&embedding.positions[j]);
+                        distance as f64 / (graph.weight(i) * graph.weight(j)) <= radius
+                    })
+                    .collect();
+                assert_eq!(found, expected, "node {i} at radius {radius}");
+            }
+        }
+    }
+}
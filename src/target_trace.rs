@@ -0,0 +1,270 @@
+//! Why a query did or did not return one particular node, see
+//! [`crate::query::SpatialIndex::trace_query`].
+
+use std::fmt;
+
+use crate::{
+    NodeId,
+    query::{Graph, Position},
+};
+
+/// Decisions a query made on the way to the node it is traced for, with the
+/// values they were based on. All radii and distances are squared.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TraceStep {
+    /// Inner tree node above the target
+    Split {
+        layer: usize,
+        /// Dimension the node splits on
+        dim: usize,
+        split: f32,
+        /// Coordinate of the query in `dim`
+        coordinate: f32,
+        /// Whether the target lies on the side of the split without the query
+        far: bool,
+        /// Radius the node was entered with
+        radius_squared: f32,
+        /// Radius left for the far side, which is pruned if it is `<= 0`
+        reduced_radius_squared: f32,
+        /// Distances to the splits passed so far, after this one
+        distances: Vec<f32>,
+    },
+    /// Bounding box test of the leaf holding the target
+    LeafBox {
+        layer: usize,
+        box_distance_squared: f32,
+        radius_squared: f32,
+    },
+    /// Scan of the leaf holding the target, which starts at the lookup table
+    /// bucket of the lower end of `window` and stops behind its upper end
+    LeafScan {
+        layer: usize,
+        /// Lookup table bucket, `None` if the leaf has no lookup table
+        bucket: Option<usize>,
+        /// Offsets into the leaf
+        scan_start: usize,
+        target_offset: usize,
+        window: (f32, f32),
+        target_coordinate: f32,
+        /// Offset of the point the scan stopped at before the target
+        stopped_at: Option<usize>,
+    },
+    /// Exact distance check of the target
+    Distance {
+        distance_squared: f32,
+        radius_squared: f32,
+    },
+    /// Lookup in one hash table of an LSH index
+    HashTable {
+        table: usize,
+        query_hash: u64,
+        target_hash: u64,
+        /// Hyperplanes the query and the target lie on different sides of,
+        /// as `(bit, query projection, target projection)`
+        differing: Vec<(usize, f32, f32)>,
+    },
+}
+
+impl TraceStep {
+    /// Whether this decision excluded the target
+    pub fn prunes_target(&self) -> bool {
+        match self {
+            TraceStep::Split {
+                far,
+                reduced_radius_squared,
+                ..
+            } => *far && *reduced_radius_squared <= 0.,
+            TraceStep::LeafBox {
+                box_distance_squared,
+                radius_squared,
+                ..
+            } => box_distance_squared > radius_squared,
+            TraceStep::LeafScan {
+                scan_start,
+                target_offset,
+                stopped_at,
+                ..
+            } => target_offset < scan_start || stopped_at.is_some(),
+            TraceStep::Distance {
+                distance_squared,
+                radius_squared,
+            } => distance_squared > radius_squared,
+            TraceStep::HashTable {
+                query_hash,
+                target_hash,
+                ..
+            } => query_hash != target_hash,
+        }
+    }
+}
+
+impl fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceStep::Split {
+                layer,
+                dim,
+                split,
+                coordinate,
+                far,
+                radius_squared,
+                reduced_radius_squared,
+                distances,
+            } => {
+                let side = if *far { "far" } else { "near" };
+                write!(
+                    f,
+                    "node {layer}: x[{dim}] = {coordinate} vs split {split}, target on {side} side, \
+                     r² = {radius_squared}"
+                )?;
+                if *far {
+                    write!(f, " reduced to {reduced_radius_squared}")?;
+                }
+                write!(f, ", split distances {distances:?}")
+            }
+            TraceStep::LeafBox {
+                layer,
+                box_distance_squared,
+                radius_squared,
+            } => write!(
+                f,
+                "leaf {layer}: box distance² {box_distance_squared} vs r² {radius_squared}"
+            ),
+            TraceStep::LeafScan {
+                layer,
+                bucket,
+                scan_start,
+                target_offset,
+                window: (min, max),
+                target_coordinate,
+                stopped_at,
+            } => {
+                match bucket {
+                    Some(bucket) => write!(f, "leaf {layer}: lookup bucket {bucket}")?,
+                    None => write!(f, "leaf {layer}: no lookup table")?,
+                }
+                write!(
+                    f,
+                    ", scan from {scan_start} in window [{min}, {max}], target at {target_offset} \
+                     with coordinate {target_coordinate}"
+                )?;
+                if let Some(stop) = stopped_at {
+                    write!(f, ", stopped at {stop}")?;
+                }
+                Ok(())
+            }
+            TraceStep::Distance {
+                distance_squared,
+                radius_squared,
+            } => write!(f, "distance² {distance_squared} vs r² {radius_squared}"),
+            TraceStep::HashTable {
+                table,
+                query_hash,
+                target_hash,
+                differing,
+            } => {
+                write!(
+                    f,
+                    "table {table}: query hash {query_hash:#x}, target hash {target_hash:#x}"
+                )?;
+                if !differing.is_empty() {
+                    write!(f, ", split by hyperplanes")?;
+                    for (bit, query, target) in differing {
+                        write!(f, " {bit} ({query} vs {target})")?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Trace of a query for the node `target`, see
+/// [`crate::query::SpatialIndex::trace_query`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TargetTrace {
+    pub index: NodeId,
+    pub target: NodeId,
+    pub index_position: Vec<f32>,
+    pub target_position: Vec<f32>,
+    pub index_weight: f64,
+    pub target_weight: f64,
+    /// Radius of the ball the index was queried with
+    pub query_radius: f64,
+    pub distance: f64,
+    /// Whether the query returns the target
+    pub found: bool,
+    pub steps: Vec<TraceStep>,
+}
+
+impl TargetTrace {
+    /// Trace without any steps yet for a query of radius `query_radius`
+    /// around `index`
+    pub fn new<const D: usize>(
+        structure: &(impl Position<D> + Graph + ?Sized),
+        index: NodeId,
+        query_radius: f64,
+        target: NodeId,
+    ) -> Self {
+        let (from, to) = (structure.position(index), structure.position(target));
+        TargetTrace {
+            index,
+            target,
+            index_position: from.components.to_vec(),
+            target_position: to.components.to_vec(),
+            index_weight: structure.weight(index),
+            target_weight: structure.weight(target),
+            query_radius,
+            distance: from.distance(to) as f64,
+            found: false,
+            steps: Vec::new(),
+        }
+    }
+
+    /// The first step that excluded the target, `None` if it was found
+    pub fn pruned_by(&self) -> Option<&TraceStep> {
+        if self.found {
+            return None;
+        }
+        self.steps.iter().find(|step| step.prunes_target())
+    }
+}
+
+impl fmt::Display for TargetTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "query {} (weight {:.4}) at {:?}",
+            self.index, self.index_weight, self.index_position
+        )?;
+        writeln!(
+            f,
+            "target {} (weight {:.4}) at {:?}",
+            self.target, self.target_weight, self.target_position
+        )?;
+        let inside = if self.distance <= self.query_radius {
+            "inside"
+        } else {
+            "outside"
+        };
+        writeln!(
+            f,
+            "distance {:.6}, {inside} the query radius {:.6}",
+            self.distance, self.query_radius
+        )?;
+        let pruned_by = self.pruned_by();
+        for step in &self.steps {
+            let marker = if pruned_by.is_some_and(|p| std::ptr::eq(p, step)) {
+                "✗"
+            } else {
+                " "
+            };
+            writeln!(f, "  {marker} {step}")?;
+        }
+        if self.found {
+            writeln!(f, "target found")
+        } else {
+            writeln!(f, "target not found")
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use acap::{NearestNeighbors, vp::FlatVpTree};
 
 use crate::{
-    Embedding, NodeId, Query,
+    EmbeddingRef, NodeId, Query,
     dvec::DVec,
+    graph::Neighbors,
     query::{self, Graph, Position, SpatialIndex, Update},
 };
 
@@ -47,8 +48,8 @@ impl<'a, const D: usize> Clone for VPTree<'a, D> {
 }
 
 impl<'a, const D: usize> VPTree<'a, D> {
-    pub fn new(embedding: Embedding<'a, D>) -> Self {
-        let positions = embedding.positions.clone();
+    pub fn new(embedding: EmbeddingRef<'_, 'a, D>) -> Self {
+        let positions = embedding.positions.to_vec();
         let data_points = positions
             .iter()
             .enumerate()
@@ -71,7 +72,7 @@ impl<'a, const D: usize> Graph for VPTree<'a, D> {
         self.graph.is_connected(first, second)
     }
 
-    fn neighbors(&self, index: NodeId) -> &[NodeId] {
+    fn neighbors(&self, index: NodeId) -> Neighbors<'_> {
         self.graph.neighbors(index)
     }
 
@@ -133,7 +134,7 @@ impl<'a, const D: usize> SpatialIndex<D> for VPTree<'a, D> {
 }
 
 impl<'a, const D: usize> query::Embedder<'a, D> for VPTree<'a, D> {
-    fn new(embedding: &crate::Embedding<'a, D>) -> Self {
-        Self::new(embedding.clone())
+    fn new(embedding: crate::EmbeddingRef<'_, 'a, D>) -> Self {
+        Self::new(embedding)
     }
 }
@@ -0,0 +1,99 @@
+use rembed::parsing::{PositionFormat, parse_positions_file};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+#[test]
+fn binary_and_text_positions_are_equivalent() {
+    let binary_path = format!("{FIXTURES}/positions.log");
+    let text_path = format!("{FIXTURES}/positions.txt");
+    assert_eq!(
+        PositionFormat::detect(&std::fs::read(&binary_path).unwrap()),
+        PositionFormat::Binary
+    );
+    assert_eq!(
+        PositionFormat::detect(&std::fs::read(&text_path).unwrap()),
+        PositionFormat::Text
+    );
+
+    let binary = parse_positions_file::<_, 2>(&binary_path).unwrap();
+    let text = parse_positions_file::<_, 2>(&text_path).unwrap();
+
+    assert_eq!(binary.iterations().len(), 2);
+    assert_eq!(binary.iterations().len(), text.iterations().len());
+    for (b, t) in binary.iterations().iter().zip(text.iterations()) {
+        assert_eq!(b.number, t.number);
+        assert_eq!(b.positions.len(), 3);
+        for (pb, pt) in b.positions.iter().zip(t.positions.iter()) {
+            assert_eq!(pb.components, pt.components);
+        }
+    }
+}
+
+#[test]
+fn text_positions_reject_missing_nodes() {
+    let text = "# iteration 0\n0 1.0 2.0\n2 3.0 4.0\n";
+    assert!(rembed::parsing::parse_text_positions::<2>(text).is_err());
+    let text = "0 1.0 2.0 3.0\n";
+    assert!(rembed::parsing::parse_text_positions::<2>(text).is_err());
+    let text = "0 1.0 2.0\n,,\n";
+    let Err(error) = rembed::parsing::parse_text_positions::<2>(text) else {
+        panic!("a line without a node id should be rejected");
+    };
+    assert!(error.to_string().contains("line 2"), "{error}");
+    let text = "1 1.0 2.0\n0 3.0 4.0\n";
+    let iterations = rembed::parsing::parse_text_positions::<2>(text).unwrap();
+    assert_eq!(iterations.iterations()[0].number, 0);
+    assert_eq!(
+        iterations.iterations()[0].positions[1].components,
+        [1.0, 2.0]
+    );
+}
+
+#[test]
+fn positions_must_match_the_graph() {
+    let iterations = parse_positions_file::<_, 2>(format!("{FIXTURES}/positions.log")).unwrap();
+    assert_eq!(iterations.num_nodes(), 3);
+
+    let graph = rembed::graph::Graph::from_edge_list(vec![(0, 1), (1, 2)], 2, 2).unwrap();
+    assert!(iterations.validate_graph_compatibility(&graph).is_ok());
+
+    let graph = rembed::graph::Graph::from_edge_list(vec![(0, 1), (1, 2), (2, 3)], 2, 2).unwrap();
+    let error = iterations.validate_graph_compatibility(&graph).unwrap_err();
+    assert_eq!((error.positions, error.graph), (3, 4));
+    assert!(error.to_string().contains("graph has 4"));
+}
+
+#[test]
+fn positions_of_a_reduced_relabeled_graph_join_back_to_the_input() {
+    // Two components, the smaller one (nodes 0 and 1) is dropped
+    let edges = vec![(0, 1), (2, 3), (3, 4), (4, 5), (5, 2)];
+    let graph = rembed::graph::Graph::from_edge_list(edges.clone(), 2, 2).unwrap();
+    let (reduced, _) = graph.reduce_to_largest_component();
+    let relabeled = reduced.relabel(&[3, 1, 0, 2]);
+    let ids = relabeled.original_ids.clone().unwrap();
+    assert_eq!(ids, [5, 3, 2, 4]);
+
+    // Every node is placed at its input id, so the join can be checked exactly
+    let positions: Vec<_> = ids
+        .iter()
+        .map(|&id| rembed::dvec::DVec::<2>::new([id as f32, 0.0]))
+        .collect();
+    let path = std::env::temp_dir().join(format!("original-ids-{}.log", std::process::id()));
+    let path = path.to_string_lossy();
+    rembed::parsing::write_test_file(&path, &[(0, positions)], Some(&ids)).unwrap();
+
+    let iterations = parse_positions_file::<_, 2>(path.as_ref()).unwrap();
+    std::fs::remove_file(path.as_ref()).unwrap();
+    assert!(iterations.validate_graph_compatibility(&relabeled).is_ok());
+    let read_ids = iterations.original_ids().unwrap();
+    assert_eq!(read_ids, ids);
+    let positions = &iterations.iterations()[0].positions;
+    for (node, &id) in read_ids.iter().enumerate() {
+        assert_eq!(positions[node].components[0], id as f32);
+    }
+    // The kept edges still connect the same input nodes
+    for &(u, v) in &relabeled.edges {
+        let edge = (read_ids[u] as usize, read_ids[v] as usize);
+        assert!(edges.contains(&edge) || edges.contains(&(edge.1, edge.0)));
+    }
+}